@@ -25,7 +25,8 @@
 //! must return `Err(EngineAbortedError::new_abort_indicator().into())`.
 //! Unfortunately, this is the cleanest and most reliable API that we can
 //! provide because our abort handling uses `setjmp`/`longjmp` and those can't
-//! cross FFI boundaries.
+//! cross FFI boundaries. Engines implemented purely in Rust, which never touch
+//! the global state, can use the `with_unlocked_state` method instead.
 //!
 //! In order to use a C/C++ engine, you need to provide something that
 //! implements the [`DriverHooks`] trait. The [`MinimalDriver`] struct provides
@@ -40,17 +41,21 @@ use std::{
     ffi::CStr,
     fmt::{Display, Error as FmtError, Formatter},
     io::{self, Read, SeekFrom, Write},
-    path::PathBuf,
+    path::{Path, PathBuf},
     result::Result as StdResult,
     slice,
-    sync::Mutex,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
 };
 use tectonic_errors::prelude::*;
 use tectonic_io_base::{
-    digest::DigestData, normalize_tex_path, InputFeatures, InputHandle, IoProvider, OpenResult,
-    OutputHandle,
+    digest::DigestData, normalize_tex_path, InputFeatures, InputHandle, InputOrigin, IoProvider,
+    OpenResult, OutputHandle,
 };
-use tectonic_status_base::{tt_error, tt_warning, MessageKind, StatusBackend};
+use tectonic_status_base::{tt_error, tt_warning, MessageKind, Progress, StatusBackend};
 
 /// The ID of an InputHandle, used for Rust core state
 #[derive(Copy, Clone, PartialEq)]
@@ -242,12 +247,53 @@ impl Display for EngineAbortedError {
 
 impl std::error::Error for EngineAbortedError {}
 
+/// An error type indicating that an engine stopped because its processing was
+/// cancelled through a [`CancelFlag`].
+#[derive(Debug)]
+pub struct EngineCancelledError;
+
+impl Display for EngineCancelledError {
+    fn fmt(&self, f: &mut Formatter) -> StdResult<(), FmtError> {
+        write!(f, "the engine was cancelled")
+    }
+}
+
+impl std::error::Error for EngineCancelledError {}
+
+/// A flag that can be raised to cancel running engines.
+///
+/// The flag is shared between its clones, so an embedder can keep one clone,
+/// hand another to [`CoreBridgeLauncher::with_cancel_flag`], and raise it from
+/// any thread, such as in a Ctrl-C handler. Engines notice it the next time
+/// they check their resource limits and abort, upon which the launcher returns
+/// an [`EngineCancelledError`].
+#[derive(Clone, Debug, Default)]
+pub struct CancelFlag(Arc<AtomicBool>);
+
+impl CancelFlag {
+    /// Create a new flag that hasn't been raised.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Raise the flag, cancelling any engines that are watching it.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    /// Check whether the flag has been raised.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
 /// A mechanism for launching bridged FFI code.
 pub struct CoreBridgeLauncher<'a> {
     hooks: &'a mut dyn DriverHooks,
     status: &'a mut dyn StatusBackend,
     security: SecuritySettings,
     filesystem_emulation_settings: FsEmulationSettings,
+    resource_limits: ResourceLimits,
 }
 
 impl<'a> CoreBridgeLauncher<'a> {
@@ -272,6 +318,7 @@ impl<'a> CoreBridgeLauncher<'a> {
             status,
             security,
             filesystem_emulation_settings: FsEmulationSettings::default(),
+            resource_limits: ResourceLimits::default(),
         }
     }
 
@@ -290,6 +337,36 @@ impl<'a> CoreBridgeLauncher<'a> {
         self
     }
 
+    /// Limit how long the engine may run. If it runs for longer, it reports an
+    /// error and aborts.
+    pub fn with_time_limit(&mut self, limit: Option<Duration>) -> &mut Self {
+        self.resource_limits.time = limit;
+        self
+    }
+
+    /// Limit the resident memory of the process, in bytes, while the engine
+    /// runs. If it grows beyond the limit, the engine reports an error and
+    /// aborts. The limit isn't enforced on platforms where the memory usage
+    /// can't be determined, such as Windows.
+    pub fn with_memory_limit(&mut self, limit: Option<u64>) -> &mut Self {
+        self.resource_limits.memory = limit;
+        self
+    }
+
+    /// Watch a flag that cancels the engine when it's raised. An engine isn't
+    /// started at all if the flag has already been raised.
+    pub fn with_cancel_flag(&mut self, flag: Option<CancelFlag>) -> &mut Self {
+        self.resource_limits.cancel = flag;
+        self
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.resource_limits
+            .cancel
+            .as_ref()
+            .is_some_and(|f| f.is_cancelled())
+    }
+
     /// Invoke a function to launch a bridged FFI engine with a global mutex
     /// held.
     ///
@@ -303,20 +380,34 @@ impl<'a> CoreBridgeLauncher<'a> {
     /// and `ttbc_global_engine_exit` according to the pattern described in
     /// `tectonic_bridge_core.h`. If an abort is detected, the callback function
     /// should return `Err(EngineAbortedError::new_abort_indicator())`.
+    ///
+    /// If the engine fails after the flag set up with
+    /// [`Self::with_cancel_flag`] has been raised, an [`EngineCancelledError`]
+    /// is returned instead of the engine's own error.
     pub fn with_global_lock<F, T>(&mut self, callback: F) -> Result<T>
     where
         F: FnOnce(&mut CoreBridgeState<'_>) -> Result<T>,
     {
+        if self.is_cancelled() {
+            return Err(EngineCancelledError.into());
+        }
+
         let _guard = ENGINE_LOCK.lock().unwrap();
         let mut state = CoreBridgeState::new(
             self.security.clone(),
             self.hooks,
             self.status,
             self.filesystem_emulation_settings.clone(),
+            self.resource_limits.clone(),
         );
         let result = callback(&mut state);
+        state.finish_progress();
 
         if let Err(ref e) = result {
+            if self.is_cancelled() {
+                return Err(EngineCancelledError.into());
+            }
+
             if e.downcast_ref::<EngineAbortedError>().is_some() {
                 return Err(EngineAbortedError::new_with_details().into());
             }
@@ -324,6 +415,37 @@ impl<'a> CoreBridgeLauncher<'a> {
 
         result
     }
+
+    /// Invoke a function to launch an engine *without* the global mutex held.
+    ///
+    /// This is only appropriate for engines implemented entirely in Rust that
+    /// never touch the global core bridge state functions used by the C/C++
+    /// engines. Such engines can run at the same time as other engines, as long
+    /// as each invocation has its own launcher.
+    pub fn with_unlocked_state<F, T>(&mut self, callback: F) -> Result<T>
+    where
+        F: FnOnce(&mut CoreBridgeState<'_>) -> Result<T>,
+    {
+        if self.is_cancelled() {
+            return Err(EngineCancelledError.into());
+        }
+
+        let mut state = CoreBridgeState::new(
+            self.security.clone(),
+            self.hooks,
+            self.status,
+            self.filesystem_emulation_settings.clone(),
+            self.resource_limits.clone(),
+        );
+        let result = callback(&mut state);
+        state.finish_progress();
+
+        if result.is_err() && self.is_cancelled() {
+            return Err(EngineCancelledError.into());
+        }
+
+        result
+    }
 }
 
 /// The CoreBridgeState structure is a handle to Rust state that can be used by
@@ -339,6 +461,16 @@ pub struct CoreBridgeState<'a> {
     /// The filesystem emulation settings for this invocation.
     fs_emulation_settings: FsEmulationSettings,
 
+    /// The resource limits for this invocation.
+    resource_limits: ResourceLimits,
+
+    /// When this invocation started.
+    started: Instant,
+
+    /// When the memory usage was last checked against its limit, if it has
+    /// been.
+    last_memory_check: Option<Instant>,
+
     /// The driver hooks associated with this engine invocation.
     hooks: &'a mut dyn DriverHooks,
 
@@ -359,6 +491,9 @@ pub struct CoreBridgeState<'a> {
     /// recent input didn't have a filesystem path (it came from a bundle or
     /// memory or something else).
     latest_input_path: Option<PathBuf>,
+
+    /// The progress of the engine, if it's reporting it.
+    progress: Option<Progress>,
 }
 
 impl<'a> CoreBridgeState<'a> {
@@ -367,6 +502,7 @@ impl<'a> CoreBridgeState<'a> {
         hooks: &'a mut dyn DriverHooks,
         status: &'a mut dyn StatusBackend,
         fs_emulation_settings: FsEmulationSettings,
+        resource_limits: ResourceLimits,
     ) -> CoreBridgeState<'a> {
         CoreBridgeState {
             security,
@@ -376,9 +512,105 @@ impl<'a> CoreBridgeState<'a> {
             input_handles: Vec::new(),
             latest_input_path: None,
             fs_emulation_settings,
+            resource_limits,
+            started: Instant::now(),
+            last_memory_check: None,
+            progress: None,
+        }
+    }
+
+    /// Start reporting the progress of the engine to the status backend, under
+    /// the given description of what it's doing, such as "running TeX".
+    ///
+    /// After this is called, the backend is told about each file that the
+    /// engine opens and each page reported with [`Self::report_page`]. The
+    /// report is finished when the engine returns.
+    pub fn begin_progress(&mut self, phase: &str) {
+        let progress = Progress::new(phase);
+        self.status.report_progress(&progress);
+        self.progress = Some(progress);
+    }
+
+    /// Report that the engine is working on the page with the given number,
+    /// counting from 1. If the total number of pages to process is known, it
+    /// is used to report a percentage.
+    pub fn report_page(&mut self, page: u32, total: Option<u32>) {
+        if let Some(progress) = self.progress.as_mut() {
+            progress.page = Some(page);
+            progress.percent = total
+                .filter(|t| *t > 0)
+                .map(|t| (100. * page as f32 / t as f32).min(100.));
+            self.status.report_progress(progress);
+        }
+    }
+
+    fn report_file(&mut self, name: &str) {
+        if let Some(progress) = self.progress.as_mut() {
+            progress.file = Some(name.to_owned());
+            self.status.report_progress(progress);
+        }
+    }
+
+    fn finish_progress(&mut self) {
+        if self.progress.take().is_some() {
+            self.status.finish_progress();
         }
     }
 
+    /// Check whether the engine has exceeded the resource limits set up with
+    /// [`CoreBridgeLauncher::with_time_limit`] and
+    /// [`CoreBridgeLauncher::with_memory_limit`]. If it has, an error is
+    /// reported and `false` is returned, upon which the engine should abort.
+    /// `false` is also returned, without an error, if the engine has been
+    /// cancelled with the flag set up with [`CoreBridgeLauncher::with_cancel_flag`].
+    ///
+    /// The memory usage is only sampled every so often, so that this is cheap
+    /// enough to call frequently.
+    pub fn check_resource_limits(&mut self) -> bool {
+        if let Some(flag) = self.resource_limits.cancel.as_ref() {
+            if flag.is_cancelled() {
+                return false;
+            }
+        }
+
+        let now = Instant::now();
+
+        if let Some(limit) = self.resource_limits.time {
+            if now.duration_since(self.started) > limit {
+                tt_error!(
+                    self.status,
+                    "the engine exceeded its time limit of {:?}",
+                    limit
+                );
+                return false;
+            }
+        }
+
+        if let Some(limit) = self.resource_limits.memory {
+            let due = self
+                .last_memory_check
+                .is_none_or(|t| now.duration_since(t) >= MEMORY_CHECK_INTERVAL);
+
+            if due {
+                self.last_memory_check = Some(now);
+
+                if let Some(used) = resident_memory() {
+                    if used > limit {
+                        tt_error!(
+                            self.status,
+                            "the engine exceeded its memory limit of {} MiB, using {} MiB",
+                            limit / MIB,
+                            used / MIB
+                        );
+                        return false;
+                    }
+                }
+            }
+        }
+
+        true
+    }
+
     /// Get the current global bridge state. Uses a mutex to ensure unique access, and panics
     /// if no global state is set.
     pub fn with_global_state<T, F: for<'b> FnOnce(&mut CoreBridgeState<'b>) -> T>(f: F) -> T {
@@ -391,10 +623,51 @@ impl<'a> CoreBridgeState<'a> {
         f(state)
     }
 
+    /// Make sure that the security settings allow the engine to read an input
+    /// that's been opened.
+    fn check_input_access(
+        &mut self,
+        result: OpenResult<(InputHandle, Option<PathBuf>)>,
+    ) -> OpenResult<(InputHandle, Option<PathBuf>)> {
+        match result {
+            OpenResult::Ok((ih, path)) if ih.origin() == InputOrigin::Filesystem => {
+                let allowed = match &path {
+                    Some(p) => self.security.allow_file_access(FileAccess::Read, p),
+                    None => !self.security.restricts_file_access(FileAccess::Read),
+                };
+
+                if allowed {
+                    OpenResult::Ok((ih, path))
+                } else {
+                    let shown = match &path {
+                        Some(p) => p.display().to_string(),
+                        None => ih.name().to_owned(),
+                    };
+                    tt_warning!(
+                        self.status,
+                        "forbidden to read \"{}\" by the security settings",
+                        shown
+                    );
+                    OpenResult::NotAvailable
+                }
+            }
+            r => r,
+        }
+    }
+
     fn input_open_name_format(
         &mut self,
         name: &str,
         format: FileFormat,
+    ) -> OpenResult<(InputHandle, Option<PathBuf>)> {
+        let r = self.input_open_name_format_unchecked(name, format);
+        self.check_input_access(r)
+    }
+
+    fn input_open_name_format_unchecked(
+        &mut self,
+        name: &str,
+        format: FileFormat,
     ) -> OpenResult<(InputHandle, Option<PathBuf>)> {
         let io = self.hooks.io();
 
@@ -626,6 +899,7 @@ impl<'a> CoreBridgeState<'a> {
             }
         };
 
+        self.report_file(&name);
         self.input_handles.push(Some(ih));
         self.latest_input_path = path;
         InputId::new(self.input_handles.len())
@@ -634,7 +908,9 @@ impl<'a> CoreBridgeState<'a> {
     fn input_open_primary(&mut self) -> Option<InputId> {
         let io = self.hooks.io();
 
-        let (ih, path) = match io.input_open_primary_with_abspath(self.status) {
+        let r = io.input_open_primary_with_abspath(self.status);
+
+        let (ih, path) = match self.check_input_access(r) {
             OpenResult::Ok(tup) => tup,
             OpenResult::NotAvailable => {
                 tt_error!(self.status, "primary input not available (?!)");
@@ -646,6 +922,7 @@ impl<'a> CoreBridgeState<'a> {
             }
         };
 
+        self.report_file(ih.name());
         self.input_handles.push(Some(ih));
         self.latest_input_path = path;
         InputId::new(self.input_handles.len())
@@ -772,6 +1049,33 @@ pub struct SecuritySettings {
     /// there should always be a hard "disable everything known to be risky"
     /// option that supersedes everything else.
     disable_insecures: bool,
+
+    /// Rules about where engines may read and write files. See
+    /// [`SecuritySettings::allow_directory`].
+    file_rules: Vec<FileRule>,
+}
+
+/// A way in which an engine might access files, for the rules set up with
+/// [`SecuritySettings::allow_directory`] and
+/// [`SecuritySettings::deny_directory`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FileAccess {
+    /// Reading files from the filesystem, such as TeX inputs and images. This
+    /// doesn't cover files that come from a bundle, or from memory.
+    Read,
+
+    /// Writing files to the filesystem, such as the output PDF, the
+    /// intermediate files and logs that are kept, Makefile rules, and cached
+    /// format files. Processing fails if one of these files can't be written.
+    Write,
+}
+
+/// A rule about the files in a directory.
+#[derive(Clone, Debug)]
+struct FileRule {
+    access: FileAccess,
+    dir: PathBuf,
+    allowed: bool,
 }
 
 /// Different high-level security stances that can be adopted when creating
@@ -815,7 +1119,10 @@ impl SecuritySettings {
             }
         };
 
-        SecuritySettings { disable_insecures }
+        SecuritySettings {
+            disable_insecures,
+            file_rules: Vec::new(),
+        }
     }
 
     /// Query whether the shell-escape TeX engine feature is allowed to be used.
@@ -827,6 +1134,104 @@ impl SecuritySettings {
     pub fn allow_extra_search_paths(&self) -> bool {
         !self.disable_insecures
     }
+
+    /// Allow engines to access the files in the directory *dir*, and its
+    /// subdirectories, in the way given by *access*.
+    ///
+    /// By default, engines can access files anywhere. Once a directory has
+    /// been allowed or denied for some kind of access, though, that kind of
+    /// access is only allowed for the files in directories that have been
+    /// allowed, such as the project directory and the cache. Rules for
+    /// deeper directories take precedence, so a subdirectory of an allowed
+    /// directory can be denied, and the other way around. Symbolic links are
+    /// resolved, so they can't be used to get around the rules.
+    pub fn allow_directory<P: Into<PathBuf>>(&mut self, access: FileAccess, dir: P) -> &mut Self {
+        self.file_rules.push(FileRule {
+            access,
+            dir: dir.into(),
+            allowed: true,
+        });
+        self
+    }
+
+    /// Forbid engines to access the files in the directory *dir*, and its
+    /// subdirectories, in the way given by *access*.
+    ///
+    /// See [`Self::allow_directory`] for how the rules work together.
+    pub fn deny_directory<P: Into<PathBuf>>(&mut self, access: FileAccess, dir: P) -> &mut Self {
+        self.file_rules.push(FileRule {
+            access,
+            dir: dir.into(),
+            allowed: false,
+        });
+        self
+    }
+
+    /// Query whether any rules restrict the kind of file access *access*.
+    pub fn restricts_file_access(&self, access: FileAccess) -> bool {
+        self.file_rules.iter().any(|r| r.access == access)
+    }
+
+    /// Query whether engines are allowed to access the file at *path* in the
+    /// way given by *access*. The file need not exist yet.
+    pub fn allow_file_access(&self, access: FileAccess, path: &Path) -> bool {
+        if !self.restricts_file_access(access) {
+            return true;
+        }
+
+        let Some(path) = resolve_path(path) else {
+            return false;
+        };
+
+        // The rule for the deepest directory containing the file wins. If
+        // rules for the same directory disagree, denial wins.
+
+        let mut best: Option<(usize, bool)> = None;
+
+        for rule in self.file_rules.iter().filter(|r| r.access == access) {
+            let Some(dir) = resolve_path(&rule.dir) else {
+                continue;
+            };
+
+            if !path.starts_with(&dir) {
+                continue;
+            }
+
+            let depth = dir.components().count();
+
+            best = match best {
+                Some((d, allowed)) if d > depth || (d == depth && !allowed) => Some((d, allowed)),
+                _ => Some((depth, rule.allowed)),
+            };
+        }
+
+        best.is_some_and(|(_, allowed)| allowed)
+    }
+}
+
+/// Make *path* absolute and resolve any symbolic links in it. The file itself
+/// and its parent directories need not exist: the longest part of the path
+/// that does is resolved, and the rest is appended to it. Returns None if
+/// that's not possible, such as if the rest includes `..`.
+fn resolve_path(path: &Path) -> Option<PathBuf> {
+    let mut existing = path;
+    let mut rest = Vec::new();
+
+    loop {
+        let base = if existing.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            existing
+        };
+
+        if let Ok(mut resolved) = base.canonicalize() {
+            resolved.extend(rest.iter().rev());
+            return Some(resolved);
+        }
+
+        rest.push(existing.file_name()?);
+        existing = existing.parent()?;
+    }
 }
 
 impl Default for SecuritySettings {
@@ -852,6 +1257,67 @@ struct FsEmulationSettings {
     mtime_override: Option<i64>,
 }
 
+/// Limits on the resources that an engine invocation may use.
+#[derive(Clone, Debug, Default)]
+struct ResourceLimits {
+    /// How long the engine may run.
+    time: Option<Duration>,
+
+    /// How much resident memory the process may use while the engine runs, in
+    /// bytes.
+    memory: Option<u64>,
+
+    /// A flag that cancels the engine when it's raised.
+    cancel: Option<CancelFlag>,
+}
+
+/// How often the memory usage is checked against its limit.
+const MEMORY_CHECK_INTERVAL: Duration = Duration::from_millis(10);
+
+const MIB: u64 = 1024 * 1024;
+
+/// Get the resident memory of this process, in bytes.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn resident_memory() -> Option<u64> {
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    let pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+    // SAFETY: This is always safe to call
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    Some(pages * u64::try_from(page_size).ok()?)
+}
+
+/// Get the resident memory of this process, in bytes. On these platforms, the
+/// peak value is used, since the current one isn't readily available.
+#[cfg(all(unix, not(any(target_os = "linux", target_os = "android"))))]
+fn resident_memory() -> Option<u64> {
+    let mut usage = std::mem::MaybeUninit::<libc::rusage>::uninit();
+
+    // SAFETY: On success, getrusage initializes the structure
+    let usage = unsafe {
+        if libc::getrusage(libc::RUSAGE_SELF, usage.as_mut_ptr()) != 0 {
+            return None;
+        }
+
+        usage.assume_init()
+    };
+
+    let maxrss = u64::try_from(usage.ru_maxrss).ok()?;
+
+    // macOS reports bytes, while the BSDs report kilobytes.
+    if cfg!(target_vendor = "apple") {
+        Some(maxrss)
+    } else {
+        Some(maxrss * 1024)
+    }
+}
+
+/// Get the resident memory of this process, in bytes. This isn't supported on
+/// this platform.
+#[cfg(not(unix))]
+fn resident_memory() -> Option<u64> {
+    None
+}
+
 impl Default for FsEmulationSettings {
     fn default() -> Self {
         Self {
@@ -885,6 +1351,26 @@ pub unsafe extern "C" fn ttbc_issue_error(es: &mut CoreBridgeState, text: *const
     tt_error!(es.status, "{}", rtext.to_string_lossy());
 }
 
+/// Check whether the engine has exceeded its resource limits. If it has, an
+/// error is reported and false is returned, upon which the engine should abort.
+#[no_mangle]
+pub extern "C" fn ttbc_check_resource_limits(es: &mut CoreBridgeState) -> bool {
+    es.check_resource_limits()
+}
+
+/// Report that the engine is working on the page with the given number,
+/// counting from 1, out of `total` pages, or 0 if that isn't known.
+#[no_mangle]
+pub extern "C" fn ttbc_report_page(
+    es: &mut CoreBridgeState,
+    page: libc::c_int,
+    total: libc::c_int,
+) {
+    if page > 0 {
+        es.report_page(page as u32, u32::try_from(total).ok().filter(|t| *t > 0));
+    }
+}
+
 /// Calculate the MD5 digest of a Tectonic file.
 ///
 /// # Safety
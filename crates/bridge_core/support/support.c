@@ -159,6 +159,21 @@ ttstub_issue_error(const char *format, ...)
 }
 
 
+void
+ttstub_check_resource_limits(void)
+{
+    if (!ttbc_check_resource_limits(tectonic_global_bridge_core))
+        _tt_abort("the engine exceeded its resource limits");
+}
+
+
+void
+ttstub_report_page(int page, int total)
+{
+    ttbc_report_page(tectonic_global_bridge_core, page, total);
+}
+
+
 void
 ttstub_diag_finish(ttbc_diagnostic_t *diag)
 {
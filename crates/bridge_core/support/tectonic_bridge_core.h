@@ -225,6 +225,8 @@ NORETURN PRINTF_FUNC(1,2) int _tt_abort(const char *format, ...);
 
 PRINTF_FUNC(1,2) void ttstub_issue_warning(const char *format, ...);
 PRINTF_FUNC(1,2) void ttstub_issue_error(const char *format, ...);
+void ttstub_check_resource_limits(void);
+void ttstub_report_page(int page, int total);
 
 void ttstub_diag_finish(ttbc_diagnostic_t *diag);
 
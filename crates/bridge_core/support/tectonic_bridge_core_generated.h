@@ -156,6 +156,18 @@ void ttbc_issue_warning(ttbc_state_t *es, const char *text);
  */
 void ttbc_issue_error(ttbc_state_t *es, const char *text);
 
+/**
+ * Check whether the engine has exceeded its resource limits. If it has, an
+ * error is reported and false is returned, upon which the engine should abort.
+ */
+bool ttbc_check_resource_limits(ttbc_state_t *es);
+
+/**
+ * Report that the engine is working on the page with the given number,
+ * counting from 1, out of `total` pages, or 0 if that isn't known.
+ */
+void ttbc_report_page(ttbc_state_t *es, int page, int total);
+
 /**
  * Calculate the MD5 digest of a Tectonic file.
  *
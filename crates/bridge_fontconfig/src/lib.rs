@@ -21,6 +21,14 @@ pub fn init() -> bool {
     (unsafe { sys::FcInit() }) == sys::FcTrue
 }
 
+/// Reload the fontconfig configuration and rescan the font directories,
+/// bringing fontconfig's caches up to date. Fonts loaded from earlier font
+/// sets remain valid.
+pub fn reinitialize() -> bool {
+    // SAFETY: This is always safe to call
+    (unsafe { sys::FcInitReinitialize() }) == sys::FcTrue
+}
+
 /// Error returned by a fallible operation
 #[derive(Debug, PartialEq)]
 pub enum FcErr {
@@ -66,6 +66,7 @@ extern "C" {
         i: *mut libc::c_int,
     ) -> FcResult;
     pub fn FcInit() -> FcBool;
+    pub fn FcInitReinitialize() -> FcBool;
     pub fn FcNameParse(name: *const libc::c_char) -> *mut FcPattern;
     pub fn FcObjectSetBuild(first: *const libc::c_char, ...) -> *mut FcObjectSet;
     pub fn FcFontList(
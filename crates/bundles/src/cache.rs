@@ -6,13 +6,17 @@
 //! This module implements Tectonic’s local filesystem caching mechanism for TeX
 //! support files. To make a cachable bundle, wrap any [`CachableBundle`] with a
 //! [`BundleCache`].
+//!
+//! Several runs of Tectonic may use the same cache at once, as in parallel CI
+//! jobs. Files are added to it atomically, so that no run ever sees a partial
+//! file, and while holding a lock, so that a file that several runs need at
+//! once is only fetched by one of them.
 
-use crate::{Bundle, CachableBundle, FileIndex, FileInfo};
+use crate::{verify, Bundle, CachableBundle, FileIndex, FileInfo};
 use std::{
     fs::{self, File},
-    io::{self, BufReader, Read, Write},
+    io::{self, BufReader, Cursor, Read},
     path::{Path, PathBuf},
-    process,
     str::FromStr,
 };
 use tectonic_errors::{anyhow::Context, prelude::*};
@@ -21,27 +25,59 @@ use tectonic_io_base::{
     digest::{self, DigestData},
     InputHandle, InputOrigin, IoProvider, OpenResult,
 };
-use tectonic_status_base::StatusBackend;
-
-/// A convenience method to provide a better error message when writing to a created file.
-fn file_create_write<P, F, E>(path: P, write_fn: F) -> Result<()>
-where
-    P: AsRef<Path>,
-    F: FnOnce(&mut File) -> std::result::Result<(), E>,
-    E: std::error::Error + 'static + Sync + Send,
-{
-    let path = path.as_ref();
-    let mut f = atry!(
-        File::create(path);
-        ["couldn't open {} for writing", path.display()]
+use tectonic_status_base::{tt_note, tt_warning, Progress, StatusBackend};
+
+/// Write a file into the cache atomically, with data copied from `source`.
+///
+/// We first write to a uniquely-named temporary file in the same directory,
+/// then rename it to the target. This makes sure that parallel runs of
+/// tectonic don't see partial files, and that a failed write doesn't leave one
+/// behind.
+fn write_atomically(target: &Path, source: &mut dyn Read) -> Result<()> {
+    let dir = target.parent().unwrap_or(Path::new("."));
+    let mut temp = atry!(
+        tempfile::Builder::new().prefix(".tmp-").tempfile_in(dir);
+        ["couldn't create a temporary file in {}", dir.display()]
+    );
+    atry!(
+        io::copy(source, &mut temp);
+        ["couldn't write to {}", temp.path().display()]
     );
     atry!(
-        write_fn(&mut f);
-        ["couldn't write to {}", path.display()]
+        temp.persist(target);
+        ["couldn't move a temporary file to {}", target.display()]
     );
     Ok(())
 }
 
+/// Take the lock on the cached data of the bundle with digest `digest`, in
+/// the bundle cache rooted at `cache_root`.
+///
+/// Runs of tectonic hold this while adding files for that version of the
+/// bundle to the cache, so anything else that changes those files should hold
+/// it too. The lock is released when the returned file is dropped.
+pub fn lock_bundle_data(cache_root: &Path, digest: &str) -> Result<File> {
+    let path = cache_root.join(format!("data/{digest}.lock"));
+    let f = atry!(
+        File::options().create(true).truncate(false).write(true).open(&path);
+        ["couldn't open the lock file {}", path.display()]
+    );
+
+    match f.lock() {
+        Ok(()) => {}
+
+        // Without a lock, parallel runs may fetch the same files, which is
+        // wasteful but safe.
+        Err(e) if e.kind() == io::ErrorKind::Unsupported => {}
+
+        Err(e) => {
+            return Err(e).with_context(|| format!("while locking {path:?} in cache"));
+        }
+    }
+
+    Ok(f)
+}
+
 // Make sure a directory exists.
 // "inline" version is for convenience.
 macro_rules! ensure_dir {
@@ -82,6 +118,11 @@ pub struct BundleCache<'this, T> {
     /// bundle is not going to contain these files.
     only_cached: bool,
 
+    /// If true, network access has been disabled, so that files that aren't
+    /// cached are errors rather than being fetched. Unlike with `only_cached`,
+    /// the build then fails straight away, saying which file was missing.
+    offline: bool,
+
     /// The bundle we're wrapping. When files don't exist in the cache,
     /// we'll get them from here.
     bundle: Box<dyn CachableBundle<'this, T>>,
@@ -99,7 +140,10 @@ impl<'this, T: FileIndex<'this>> BundleCache<'this, T> {
     ///
     /// This method will fail if we can't connect to the bundle AND
     /// we don't already have it in our cache.
-    /// Other than that, this method does not require network access.
+    /// Other than that, this method does not require network access. If
+    /// network access has been disabled with
+    /// [`tectonic_geturl::set_network_disabled`], the bundle isn't contacted
+    /// at all.
     pub fn new(
         mut bundle: Box<dyn CachableBundle<'this, T>>,
         only_cached: bool,
@@ -136,7 +180,22 @@ impl<'this, T: FileIndex<'this>> BundleCache<'this, T> {
             }
         };
 
-        let live_hash = bundle.get_digest();
+        let offline = tectonic_geturl::network_disabled();
+
+        if offline && saved_hash.is_none() {
+            bail!(
+                "the bundle `{}` isn't cached, and network access is disabled; \
+                 build a document with it once while online to cache it",
+                bundle.get_location()
+            );
+        }
+
+        // Offline, the saved digest is used without contacting the bundle.
+        let live_hash = if offline {
+            Err(anyhow!("network access is disabled"))
+        } else {
+            bundle.get_digest()
+        };
 
         // Check remote bundle digest
         let bundle_hash: DigestData = match (saved_hash, live_hash) {
@@ -148,17 +207,16 @@ impl<'this, T: FileIndex<'this>> BundleCache<'this, T> {
                     // Silently update hash in cache.
                     // We don't need to delete anything, since data is indexed by hash.
                     // TODO: show a warning
-                    file_create_write(&hash_file, |f| writeln!(f, "{}", &l.to_string()))
-                        .with_context(|| {
-                            format!("while updating bundle hash in {hash_file:?} in cache")
-                        })?;
+                    write_atomically(&hash_file, &mut format!("{l}\n").as_bytes()).with_context(
+                        || format!("while updating bundle hash in {hash_file:?} in cache"),
+                    )?;
                     l
                 } else {
                     l
                 }
             }
             (None, Ok(l)) => {
-                file_create_write(&hash_file, |f| writeln!(f, "{}", &l.to_string())).with_context(
+                write_atomically(&hash_file, &mut format!("{l}\n").as_bytes()).with_context(
                     || format!("while writing bundle hash to {hash_file:?} in cache"),
                 )?;
                 l
@@ -168,6 +226,7 @@ impl<'this, T: FileIndex<'this>> BundleCache<'this, T> {
 
         let bundle = BundleCache {
             only_cached,
+            offline,
             bundle,
             cache_root,
             bundle_hash,
@@ -190,26 +249,50 @@ impl<'this, T: FileIndex<'this>> BundleCache<'this, T> {
 
     /// Build a cache path for the given bundle file
     fn get_file_path(&self, info: &T::InfoType) -> PathBuf {
-        let mut out = self.cache_root.clone();
-        out.push(format!("data/{}", self.bundle_hash));
+        let mut out = self.data_dir();
         out.push(info.path());
         out
     }
 
-    /// Build a temporary path for the given bundle file
-    /// To ensure safety with multiple instances of tectonic,
-    /// files are first downloaded to a known-unique location, then renamed.
-    fn get_file_path_tmp(&self, info: &T::InfoType) -> PathBuf {
-        let mut out = self.cache_root.clone();
-        out.push(format!("data/{}", self.bundle_hash));
-        out.push(format!("{}-tmp-pid{}", info.path(), process::id()));
-        out
+    /// The directory that holds the cached files of this bundle.
+    fn data_dir(&self) -> PathBuf {
+        self.cache_root.join(format!("data/{}", self.bundle_hash))
+    }
+
+    /// Get the infos of the named files that are in the bundle, but not in
+    /// the cache.
+    fn uncached_fileinfos(&mut self, names: &[String]) -> Vec<T::InfoType> {
+        let mut infos = Vec::new();
+
+        for name in names {
+            if let Some(info) = self.bundle.search(name) {
+                if !self.get_file_path(&info).exists() {
+                    infos.push(info);
+                }
+            }
+        }
+
+        infos
+    }
+
+    /// Take an exclusive lock on the cached data of this bundle, which is
+    /// released when the returned file is dropped.
+    ///
+    /// This is held while adding files to the cache, so that other runs of
+    /// tectonic that need the same files wait for them rather than fetching
+    /// them too.
+    fn lock_data(&self) -> Result<File> {
+        lock_bundle_data(&self.cache_root, &self.bundle_hash.to_string())
     }
 
     fn ensure_index(&mut self) -> Result<()> {
         let target = self
             .cache_root
             .join(format!("data/{}.index", self.bundle_hash));
+        let sig_target = self
+            .cache_root
+            .join(format!("data/{}.index.sig", self.bundle_hash));
+        let key = self.bundle.index_key();
 
         // We check for two things here:
         // - that the bundle index is initialized
@@ -218,56 +301,91 @@ impl<'this, T: FileIndex<'this>> BundleCache<'this, T> {
         // It would be nice to assume that the bundle index is never initialized
         // before this function is called, but we can't do that. Unlike ttb,
         // itar bundles cannot retrieve the bundle hash without loading the index.
-        if target.exists() {
+        //
+        // If the index must be signed, its signature is cached next to it, and
+        // checked every time that the cached index is used, so that a tampered
+        // cache is caught too.
+        let is_cached = || target.exists() && (key.is_none() || sig_target.exists());
+
+        // If another run is caching the index, wait for it to finish.
+        let _lock = if is_cached() {
+            None
+        } else {
+            Some(self.lock_data()?)
+        };
+
+        if is_cached() {
             if self.bundle.index().is_initialized() {
                 return Ok(());
             }
 
             // Initialize bundle index using cached file
-            let mut file = File::open(&target)
-                .with_context(|| format!("while opening index {target:?} in cache"))?;
+            let index = fs::read(&target)
+                .with_context(|| format!("while reading index {target:?} in cache"))?;
+
+            if let Some(key) = key {
+                let signature = fs::read_to_string(&sig_target).with_context(|| {
+                    format!("while reading index signature {sig_target:?} in cache")
+                })?;
+                key.verify(&index, &signature)
+                    .with_context(|| format!("while verifying cached index {target:?}"))?;
+            }
+
             self.bundle
-                .initialize_index(&mut file)
+                .initialize_index(&mut Cursor::new(index))
                 .with_context(|| format!("while inititalizing index using cached {target:?}"))?;
         } else {
-            // Download index
-
-            // We first download to a temporary file, rename to target
-            // Makes sure that parallel runs of tectonic don't break the index
-            let tmp_target = self.cache_root.join(format!(
-                "data/{}.index-tmp-pid{}",
-                self.bundle_hash,
-                process::id()
-            ));
+            if self.offline {
+                bail!(
+                    "the index of the bundle `{}` isn't cached, and network access is disabled; \
+                     build a document with it once while online to cache it",
+                    self.bundle.get_location()
+                );
+            }
 
-            let mut reader = self
-                .bundle
+            // Download index
+            let mut index = Vec::new();
+            self.bundle
                 .get_index_reader()
-                .context("while getting index reader")?;
-            let mut file = File::create(&tmp_target)
-                .with_context(|| format!("while creating index {tmp_target:?} in cache"))?;
-            io::copy(&mut reader, &mut file)
-                .with_context(|| format!("while writing index {tmp_target:?} in cache"))?;
-            drop(file);
+                .context("while getting index reader")?
+                .read_to_end(&mut index)
+                .context("while downloading index")?;
+
+            if let Some(key) = key {
+                let signature = self
+                    .bundle
+                    .get_index_signature()
+                    .context("while getting index signature")?;
+                key.verify(&index, &signature)?;
+                write_atomically(&sig_target, &mut signature.as_bytes())?;
+            }
 
-            fs::rename(&tmp_target, &target).with_context(|| {
-                format!("while renaming index {tmp_target:?} to {target:?} in cache")
-            })?;
+            write_atomically(&target, &mut &index[..])?;
 
             if self.bundle.index().is_initialized() {
                 return Ok(());
             }
 
-            let mut file = File::open(&target)
-                .with_context(|| format!("while opening index from {target:?} in cache"))?;
             self.bundle
-                .initialize_index(&mut file)
+                .initialize_index(&mut Cursor::new(index))
                 .with_context(|| format!("while initializing index {target:?} in cache"))?;
         }
 
         Ok(())
     }
 
+    /// Read a file from the cache, checking it against the digest in the
+    /// bundle index if there is one.
+    fn read_cached_file(&self, path: &Path, info: &T::InfoType) -> Result<Vec<u8>> {
+        let data = fs::read(path).with_context(|| format!("while reading {path:?} in cache"))?;
+
+        if let Some(digest) = info.digest() {
+            verify::check_file_digest(info.path(), digest, &data)?;
+        }
+
+        Ok(data)
+    }
+
     /// Get a FileInfo from a name.
     /// This returns (in_cache, info), where in_cache is true
     /// if this file is already in our cache and can be retrieved
@@ -304,27 +422,53 @@ impl<'this, T: FileIndex<'this>> BundleCache<'this, T> {
             return OpenResult::Ok(target);
         }
 
-        // No, it's not. Are we in cache-only mode?
+        // No, it's not. Are we offline, or in cache-only mode?
+        if self.offline {
+            return OpenResult::Err(anyhow!(
+                "`{}` isn't in the cache of the bundle, and network access is disabled; \
+                 build the document once while online to cache it",
+                info.name()
+            ));
+        }
+
         if self.only_cached {
             return OpenResult::NotAvailable;
         }
 
+        // Another run may be fetching the file; if so, wait for it to finish
+        // and use its copy.
+        let _lock = match self.lock_data() {
+            Ok(f) => f,
+            Err(e) => return OpenResult::Err(e),
+        };
+
+        if target.exists() {
+            return OpenResult::Ok(target);
+        }
+
         // Get the file.
+        let mut progress = Progress::new("downloading");
+        progress.file = Some(info.name().to_owned());
+        status.report_progress(&progress);
+
         let mut handle = match self.bundle.open_fileinfo(&info, status) {
             OpenResult::Ok(c) => c,
-            OpenResult::Err(e) => return OpenResult::Err(e),
-            OpenResult::NotAvailable => return OpenResult::NotAvailable,
+            OpenResult::Err(e) => {
+                status.finish_progress();
+                return OpenResult::Err(e);
+            }
+            OpenResult::NotAvailable => {
+                status.finish_progress();
+                return OpenResult::NotAvailable;
+            }
         };
 
-        // Download to a known-unique temporary location, then move.
-        // This prevents issues when running multiple processes.
-        let tmp_path = self.get_file_path_tmp(&info);
-        if let Err(e) = file_create_write(&tmp_path, |f| io::copy(&mut handle, f).map(|_| ())) {
+        let written = write_atomically(&target, &mut handle);
+        status.finish_progress();
+
+        if let Err(e) = written {
             return OpenResult::Err(e);
         }
-        if let Err(e) = fs::rename(&tmp_path, &target) {
-            return OpenResult::Err(e.into());
-        };
 
         OpenResult::Ok(target)
     }
@@ -336,25 +480,65 @@ impl<'this, T: FileIndex<'this>> IoProvider for BundleCache<'this, T> {
         name: &str,
         status: &mut dyn StatusBackend,
     ) -> OpenResult<InputHandle> {
-        let path = match self.get_fileinfo(name) {
+        let (in_cache, info) = match self.get_fileinfo(name) {
             OpenResult::NotAvailable => return OpenResult::NotAvailable,
             OpenResult::Err(e) => return OpenResult::Err(e),
-            OpenResult::Ok((true, f)) => self.get_file_path(&f),
-            OpenResult::Ok((false, f)) => match self.fetch_file(f, status) {
+            OpenResult::Ok(r) => r,
+        };
+
+        let path = if in_cache {
+            self.get_file_path(&info)
+        } else {
+            match self.fetch_file(info.clone(), status) {
                 OpenResult::Ok(p) => p,
                 OpenResult::NotAvailable => return OpenResult::NotAvailable,
                 OpenResult::Err(e) => return OpenResult::Err(e),
-            },
+            }
         };
 
-        let f = match File::open(path) {
-            Ok(f) => f,
-            Err(e) => return OpenResult::Err(e.into()),
+        // Files without digests are streamed straight from the cache.
+        if info.digest().is_none() {
+            let f = match File::open(path) {
+                Ok(f) => f,
+                Err(e) => return OpenResult::Err(e.into()),
+            };
+
+            return OpenResult::Ok(InputHandle::new_read_only(
+                name,
+                BufReader::new(f),
+                InputOrigin::Other,
+            ));
+        }
+
+        let data = match self.read_cached_file(&path, &info) {
+            Ok(d) => d,
+
+            // If a cached copy has gone bad, drop it and fetch the file again.
+            Err(e) if in_cache && !self.only_cached => {
+                tt_warning!(status, "the cached copy of \"{}\" is corrupt; fetching it again", name; e);
+
+                if let Err(e) = fs::remove_file(&path) {
+                    return OpenResult::Err(e.into());
+                }
+
+                let path = match self.fetch_file(info.clone(), status) {
+                    OpenResult::Ok(p) => p,
+                    OpenResult::NotAvailable => return OpenResult::NotAvailable,
+                    OpenResult::Err(e) => return OpenResult::Err(e),
+                };
+
+                match self.read_cached_file(&path, &info) {
+                    Ok(d) => d,
+                    Err(e) => return OpenResult::Err(e),
+                }
+            }
+
+            Err(e) => return OpenResult::Err(e),
         };
 
         OpenResult::Ok(InputHandle::new_read_only(
             name,
-            BufReader::new(f),
+            Cursor::new(data),
             InputOrigin::Other,
         ))
     }
@@ -368,4 +552,260 @@ impl<'this, T: FileIndex<'this>> Bundle for BundleCache<'this, T> {
     fn all_files(&self) -> Vec<String> {
         self.bundle.all_files()
     }
+
+    /// The files that aren't cached yet are fetched with
+    /// [`CachableBundle::fetch_fileinfos`], and added to the cache.
+    fn prefetch(&mut self, names: &[String], status: &mut dyn StatusBackend) -> Result<()> {
+        if self.offline || self.only_cached {
+            return Ok(());
+        }
+
+        self.ensure_index()?;
+
+        if self.uncached_fileinfos(names).is_empty() {
+            return Ok(());
+        }
+
+        // As in `fetch_file`, other runs that need the same files wait for
+        // them, rather than fetching them too.
+        let _lock = self.lock_data()?;
+        let infos = self.uncached_fileinfos(names);
+        let data_dir = self.data_dir();
+        let mut n_fetched = 0;
+
+        status.report_progress(&Progress::new("prefetching"));
+
+        let mut save = |info: &T::InfoType, source: &mut dyn Read| -> Result<()> {
+            let target = data_dir.join(info.path());
+            ensure_dir!(target.parent().unwrap());
+            write_atomically(&target, source)?;
+            n_fetched += 1;
+            Ok(())
+        };
+
+        let result = self.bundle.fetch_fileinfos(&infos, &mut save, status);
+        status.finish_progress();
+        result?;
+
+        if n_fetched < infos.len() {
+            tt_note!(
+                status,
+                "prefetched {} of {} files from the bundle; the others will be fetched as they're needed",
+                n_fetched,
+                infos.len()
+            );
+        } else {
+            tt_note!(status, "prefetched {} files from the bundle", n_fetched);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ttb::{TTBFileIndex, TTBFileInfo},
+        verify::IndexKey,
+    };
+    use ed25519_dalek::{Signer, SigningKey};
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+    use tectonic_io_base::digest::Digest;
+    use tectonic_status_base::NoopStatusBackend;
+
+    const CONTENTS: &[u8] = b"hello";
+
+    /// A bundle with one file, `a.sty`, and a signed index.
+    struct SignedBundle {
+        index: TTBFileIndex,
+        index_text: String,
+        key: IndexKey,
+        signature: String,
+        fetches: Arc<AtomicUsize>,
+    }
+
+    impl SignedBundle {
+        fn new(fetches: Arc<AtomicUsize>) -> Self {
+            let mut dc = digest::create();
+            dc.update(CONTENTS);
+            let index_text = format!(
+                "[DEFAULTSEARCH]\nMAIN\n[SEARCH:MAIN]\n/\n[FILELIST]\n0 5 5 {} a.sty\n",
+                DigestData::from(dc)
+            );
+
+            let signer = SigningKey::from_bytes(&[7; 32]);
+            let key = digest::bytes_to_hex(signer.verifying_key().as_bytes())
+                .parse()
+                .unwrap();
+            let signature = digest::bytes_to_hex(&signer.sign(index_text.as_bytes()).to_bytes());
+
+            SignedBundle {
+                index: TTBFileIndex::default(),
+                index_text,
+                key,
+                signature,
+                fetches,
+            }
+        }
+    }
+
+    impl IoProvider for SignedBundle {}
+
+    impl Bundle for SignedBundle {
+        fn all_files(&self) -> Vec<String> {
+            vec!["a.sty".to_owned()]
+        }
+
+        fn get_digest(&mut self) -> Result<DigestData> {
+            Ok(DigestData::zeros())
+        }
+    }
+
+    impl CachableBundle<'_, TTBFileIndex> for SignedBundle {
+        fn initialize_index(&mut self, source: &mut dyn Read) -> Result<()> {
+            self.index.initialize(source)
+        }
+
+        fn get_index_reader(&mut self) -> Result<Box<dyn Read>> {
+            Ok(Box::new(Cursor::new(self.index_text.clone())))
+        }
+
+        fn index(&mut self) -> &mut TTBFileIndex {
+            &mut self.index
+        }
+
+        fn open_fileinfo(
+            &mut self,
+            _info: &TTBFileInfo,
+            _status: &mut dyn StatusBackend,
+        ) -> OpenResult<InputHandle> {
+            self.fetches.fetch_add(1, Ordering::SeqCst);
+
+            // Take a while, as a download would, so that concurrent runs
+            // overlap.
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            OpenResult::Ok(InputHandle::new_read_only(
+                "a.sty",
+                Cursor::new(CONTENTS.to_owned()),
+                InputOrigin::Other,
+            ))
+        }
+
+        fn search(&mut self, name: &str) -> Option<TTBFileInfo> {
+            self.index.search(name)
+        }
+
+        fn get_location(&mut self) -> String {
+            "https://example.com/signed.ttb".to_owned()
+        }
+
+        fn index_key(&self) -> Option<IndexKey> {
+            Some(self.key)
+        }
+
+        fn get_index_signature(&mut self) -> Result<String> {
+            Ok(self.signature.clone())
+        }
+    }
+
+    fn read(cache: &mut BundleCache<'_, TTBFileIndex>) -> Result<Vec<u8>> {
+        match cache.input_open_name("a.sty", &mut NoopStatusBackend {}) {
+            OpenResult::Ok(mut h) => {
+                let mut data = Vec::new();
+                h.read_to_end(&mut data)?;
+                Ok(data)
+            }
+            OpenResult::NotAvailable => bail!("not available"),
+            OpenResult::Err(e) => Err(e),
+        }
+    }
+
+    fn open(root: &Path, fetches: &Arc<AtomicUsize>) -> BundleCache<'static, TTBFileIndex> {
+        let bundle = SignedBundle::new(fetches.clone());
+        BundleCache::new(Box::new(bundle), false, Some(root.to_owned())).unwrap()
+    }
+
+    #[test]
+    fn concurrent_runs() {
+        let root = tempfile::tempdir().unwrap();
+        let data_dir = root.path().join(format!("data/{}", DigestData::zeros()));
+        let fetches = Arc::new(AtomicUsize::new(0));
+
+        std::thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    assert_eq!(read(&mut open(root.path(), &fetches)).unwrap(), CONTENTS);
+                });
+            }
+        });
+
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+
+        // No temporary files are left behind.
+        let names: Vec<_> = fs::read_dir(data_dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        assert_eq!(names, ["a.sty"]);
+    }
+
+    #[test]
+    fn verified_cache() {
+        let root = tempfile::tempdir().unwrap();
+        let data_dir = root.path().join(format!("data/{}", DigestData::zeros()));
+        let fetches = Arc::new(AtomicUsize::new(0));
+
+        assert_eq!(read(&mut open(root.path(), &fetches)).unwrap(), CONTENTS);
+        assert_eq!(read(&mut open(root.path(), &fetches)).unwrap(), CONTENTS);
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+        assert!(root
+            .path()
+            .join(format!("data/{}.index.sig", DigestData::zeros()))
+            .exists());
+
+        // A damaged file in the cache is fetched again.
+        fs::write(data_dir.join("a.sty"), "hellp").unwrap();
+        assert_eq!(read(&mut open(root.path(), &fetches)).unwrap(), CONTENTS);
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+
+        // A tampered index in the cache is rejected.
+        let index_path = root
+            .path()
+            .join(format!("data/{}.index", DigestData::zeros()));
+        let mut index = fs::read_to_string(&index_path).unwrap();
+        index.push_str("0 5 5 nohash evil.sty\n");
+        fs::write(&index_path, index).unwrap();
+        assert!(read(&mut open(root.path(), &fetches)).is_err());
+    }
+
+    #[test]
+    fn offline() {
+        let root = tempfile::tempdir().unwrap();
+        let data_dir = root.path().join(format!("data/{}", DigestData::zeros()));
+        let fetches = Arc::new(AtomicUsize::new(0));
+
+        let read_offline = |fetches| {
+            let mut cache = open(root.path(), fetches);
+            cache.offline = true;
+            read(&mut cache)
+        };
+
+        // Anything that isn't cached is an error, rather than being fetched.
+        let err = read_offline(&fetches).unwrap_err().to_string();
+        assert!(err.contains("the index of the bundle"), "{err}");
+
+        // Once it's cached, it can be used.
+        assert_eq!(read(&mut open(root.path(), &fetches)).unwrap(), CONTENTS);
+        assert_eq!(read_offline(&fetches).unwrap(), CONTENTS);
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+
+        fs::remove_file(data_dir.join("a.sty")).unwrap();
+        let err = read_offline(&fetches).unwrap_err().to_string();
+        assert!(err.contains("`a.sty` isn't in the cache"), "{err}");
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+    }
 }
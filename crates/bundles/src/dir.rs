@@ -4,13 +4,19 @@
 //! A module for the directory bundle [`DirBundle`].
 
 use std::{
+    collections::{HashMap, HashSet},
     fs,
     io::Read,
     path::{Path, PathBuf},
     str::FromStr,
+    time::UNIX_EPOCH,
 };
 use tectonic_errors::prelude::*;
-use tectonic_io_base::{digest, filesystem::FilesystemIo, InputHandle, IoProvider, OpenResult};
+use tectonic_io_base::{
+    digest::{self, Digest, DigestData},
+    filesystem::FilesystemIo,
+    InputHandle, IoProvider, OpenResult,
+};
 use tectonic_status_base::{NoopStatusBackend, StatusBackend};
 
 use super::Bundle;
@@ -19,10 +25,26 @@ use super::Bundle;
 ///
 /// This implementation essentially just wraps
 /// [`tectonic_io_base::filesystem::FilesystemIo`], ensuring that it is
-/// read-only, self-contained, and implements the [`Bundle`] trait. The
-/// directory should contain a file named `SHA256SUM` if the bundle fingerprint
-/// will be needed.
-pub struct DirBundle(FilesystemIo);
+/// read-only, self-contained, and implements the [`Bundle`] trait.
+///
+/// The files may be spread across subdirectories, as in a `texmf` tree. Names
+/// are resolved like they are in the other bundle formats: a name that isn't a
+/// path relative to the top of the directory is looked up among all of the
+/// files in it. If several files have the same name, the one whose path comes
+/// first alphabetically is used, and a name with a directory part, like
+/// `pgf/utilities/pgfutil-common.tex`, only matches files whose paths end with
+/// it. Hidden files and directories are ignored.
+///
+/// If the directory contains a file named `SHA256SUM`, it is used as the
+/// bundle fingerprint. Otherwise, one is computed from the names, sizes, and
+/// modification times of the files, so that it changes whenever the files do.
+pub struct DirBundle {
+    fs: FilesystemIo,
+
+    /// The relative paths of all of the files in the bundle, sorted and
+    /// grouped by file name. This is built the first time that it's needed.
+    index: Option<HashMap<String, Vec<String>>>,
+}
 
 impl DirBundle {
     /// Create a new directory bundle.
@@ -30,12 +52,131 @@ impl DirBundle {
     /// No validation of the input path is performed, which is why this function
     /// is infallible.
     pub fn new<P: AsRef<Path>>(dir: P) -> DirBundle {
-        DirBundle(FilesystemIo::new(
-            dir.as_ref(),
-            false,              // no writes
-            false,              // no absolute paths
-            Default::default(), // no hidden files
-        ))
+        DirBundle {
+            fs: FilesystemIo::new(
+                dir.as_ref(),
+                false,              // no writes
+                false,              // no absolute paths
+                Default::default(), // no hidden files
+            ),
+            index: None,
+        }
+    }
+
+    fn index(&mut self) -> &HashMap<String, Vec<String>> {
+        let root = self.fs.root();
+
+        self.index.get_or_insert_with(|| {
+            let mut index: HashMap<String, Vec<String>> = HashMap::new();
+
+            for path in all_paths(root) {
+                let name = path.rsplit('/').next().unwrap_or_default().to_owned();
+                index.entry(name).or_default().push(path);
+            }
+
+            index
+        })
+    }
+
+    /// Find the relative path of the file that a name refers to, if it's not
+    /// one already.
+    fn resolve(&mut self, name: &str) -> Option<String> {
+        if name.starts_with('/') {
+            return None;
+        }
+
+        let basename = name.rsplit('/').next().unwrap_or_default();
+        let candidates = self.index().get(basename)?;
+
+        if basename.len() == name.len() {
+            candidates.first().cloned()
+        } else {
+            let suffix = format!("/{name}");
+            candidates.iter().find(|p| p.ends_with(&suffix)).cloned()
+        }
+    }
+
+    /// Compute a fingerprint for a bundle that doesn't provide one.
+    fn compute_digest(&mut self) -> DigestData {
+        let root = self.fs.root().to_owned();
+        let mut paths: Vec<&String> = self.index().values().flatten().collect();
+        paths.sort();
+
+        let mut dc = digest::create();
+
+        for path in paths {
+            let (size, mtime) = match fs::metadata(root.join(path)) {
+                Ok(m) => (
+                    m.len(),
+                    m.modified()
+                        .ok()
+                        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                        .map(|d| d.as_nanos())
+                        .unwrap_or_default(),
+                ),
+                Err(_) => (0, 0),
+            };
+
+            dc.update(path.as_bytes());
+            dc.update([0]);
+            dc.update(size.to_le_bytes());
+            dc.update(mtime.to_le_bytes());
+        }
+
+        DigestData::from(dc)
+    }
+}
+
+/// Get the sorted relative paths of all of the files under `root`.
+fn all_paths(root: &Path) -> Vec<String> {
+    let mut paths = Vec::new();
+    collect_paths(root, "", &mut HashSet::new(), &mut paths);
+    paths.sort();
+    paths
+}
+
+/// Recursively collect the paths of the files under `dir`, relative to the
+/// bundle root. `prefix` is the relative path of `dir` itself. Unreadable
+/// directories are skipped, as are directories that have already been visited
+/// through another path, so that symlink cycles don't recurse forever.
+fn collect_paths(
+    dir: &Path,
+    prefix: &str,
+    visited: &mut HashSet<PathBuf>,
+    paths: &mut Vec<String>,
+) {
+    match fs::canonicalize(dir) {
+        Ok(canonical) => {
+            if !visited.insert(canonical) {
+                return;
+            }
+        }
+        Err(_) => return,
+    }
+
+    let entries = match fs::read_dir(dir) {
+        Ok(e) => e,
+        Err(_) => return,
+    };
+
+    for entry in entries.filter_map(|e| e.ok()) {
+        let name = match entry.file_name().into_string() {
+            Ok(n) if !n.starts_with('.') => n,
+            _ => continue,
+        };
+
+        let path = if prefix.is_empty() {
+            name
+        } else {
+            format!("{prefix}/{name}")
+        };
+
+        // Follow symlinks, since trees of support files often use them.
+        match fs::metadata(entry.path()) {
+            Ok(m) if m.is_dir() => collect_paths(&entry.path(), &path, visited, paths),
+            Ok(m) if m.is_file() => paths.push(path),
+            _ => {}
+        }
     }
 }
 
@@ -45,7 +186,11 @@ impl IoProvider for DirBundle {
         name: &str,
         status: &mut dyn StatusBackend,
     ) -> OpenResult<InputHandle> {
-        self.0.input_open_name(name, status)
+        match self.input_open_name_with_abspath(name, status) {
+            OpenResult::Ok((h, _path)) => OpenResult::Ok(h),
+            OpenResult::Err(e) => OpenResult::Err(e),
+            OpenResult::NotAvailable => OpenResult::NotAvailable,
+        }
     }
 
     fn input_open_name_with_abspath(
@@ -53,23 +198,35 @@ impl IoProvider for DirBundle {
         name: &str,
         status: &mut dyn StatusBackend,
     ) -> OpenResult<(InputHandle, Option<PathBuf>)> {
-        self.0.input_open_name_with_abspath(name, status)
+        match self.fs.input_open_name_with_abspath(name, status) {
+            OpenResult::NotAvailable => {}
+            r => return r,
+        }
+
+        match self.resolve(name) {
+            Some(path) => self.fs.input_open_name_with_abspath(&path, status),
+            None => OpenResult::NotAvailable,
+        }
     }
 }
 
 impl Bundle for DirBundle {
     fn all_files(&self) -> Vec<String> {
-        fs::read_dir(self.0.root())
-            .unwrap()
-            .filter_map(|x| x.ok())
-            .filter(|x| !x.file_type().map(|x| x.is_dir()).unwrap_or(false))
-            .map(|x| x.file_name().to_str().unwrap_or("").to_owned())
-            .filter(|x| !x.is_empty())
-            .collect()
+        match &self.index {
+            Some(index) => {
+                let mut paths: Vec<String> = index.values().flatten().cloned().collect();
+                paths.sort();
+                paths
+            }
+
+            None => all_paths(self.fs.root()),
+        }
     }
 
-    fn get_digest(&mut self) -> Result<tectonic_io_base::digest::DigestData> {
-        let digest_text = match self.input_open_name(digest::DIGEST_NAME, &mut NoopStatusBackend {})
+    fn get_digest(&mut self) -> Result<DigestData> {
+        let digest_text = match self
+            .fs
+            .input_open_name(digest::DIGEST_NAME, &mut NoopStatusBackend {})
         {
             OpenResult::Ok(h) => {
                 let mut text = String::new();
@@ -78,7 +235,7 @@ impl Bundle for DirBundle {
             }
 
             OpenResult::NotAvailable => {
-                bail!("bundle does not provide needed SHA256SUM file");
+                return Ok(self.compute_digest());
             }
 
             OpenResult::Err(e) => {
@@ -86,6 +243,72 @@ impl Bundle for DirBundle {
             }
         };
 
-        Ok(atry!(digest::DigestData::from_str(&digest_text); ["corrupted SHA256 digest data"]))
+        Ok(atry!(DigestData::from_str(&digest_text); ["corrupted SHA256 digest data"]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(bundle: &mut DirBundle, name: &str) -> Option<String> {
+        match bundle.input_open_name(name, &mut NoopStatusBackend {}) {
+            OpenResult::Ok(mut h) => {
+                let mut text = String::new();
+                h.read_to_string(&mut text).unwrap();
+                Some(text)
+            }
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn nested_lookups() {
+        let dir = tempfile::tempdir().unwrap();
+
+        for (path, text) in [
+            ("plain.tex", "top"),
+            ("tex/b/article.cls", "b"),
+            ("tex/a/article.cls", "a"),
+            ("tex/pgf/utilities/pgfutil-common.tex", "pgf"),
+            (".git/article.cls", "hidden"),
+        ] {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, text).unwrap();
+        }
+
+        let mut bundle = DirBundle::new(dir.path());
+        assert_eq!(read(&mut bundle, "plain.tex").as_deref(), Some("top"));
+        assert_eq!(read(&mut bundle, "article.cls").as_deref(), Some("a"));
+        assert_eq!(read(&mut bundle, "tex/b/article.cls").as_deref(), Some("b"));
+        assert_eq!(
+            read(&mut bundle, "utilities/pgfutil-common.tex").as_deref(),
+            Some("pgf")
+        );
+        assert_eq!(read(&mut bundle, "other/pgfutil-common.tex"), None);
+        assert_eq!(read(&mut bundle, "missing.sty"), None);
+
+        assert_eq!(bundle.all_files().len(), 4);
+
+        let digest = bundle.get_digest().unwrap();
+        assert_eq!(digest, DirBundle::new(dir.path()).get_digest().unwrap());
+
+        fs::write(dir.path().join("plain.tex"), "changed").unwrap();
+        assert_ne!(digest, DirBundle::new(dir.path()).get_digest().unwrap());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_cycles() {
+        let dir = tempfile::tempdir().unwrap();
+        let tex = dir.path().join("tex");
+        fs::create_dir_all(&tex).unwrap();
+        fs::write(tex.join("plain.tex"), "top").unwrap();
+        std::os::unix::fs::symlink(dir.path(), tex.join("loop")).unwrap();
+
+        let mut bundle = DirBundle::new(dir.path());
+        assert_eq!(read(&mut bundle, "plain.tex").as_deref(), Some("top"));
+        assert_eq!(bundle.all_files(), vec!["tex/plain.tex".to_owned()]);
     }
 }
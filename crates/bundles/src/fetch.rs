@@ -0,0 +1,298 @@
+// Copyright 2024 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Downloading pieces of network bundles, with retries.
+//!
+//! Network bundles fetch their files with HTTP range requests. If a transfer
+//! breaks off partway, the next attempt only requests the part of the range
+//! that hasn't arrived yet, so that a large file doesn't have to be
+//! downloaded again from scratch on a flaky connection. Failed attempts are
+//! retried after a delay that doubles each time, as described by a
+//! [`RetryPolicy`].
+
+use std::{
+    env,
+    io::{ErrorKind, Read},
+    thread,
+    time::Duration,
+};
+use tectonic_errors::prelude::*;
+use tectonic_geturl::RangeReader;
+use tectonic_status_base::{tt_note, tt_warning, StatusBackend};
+
+/// The environment variable that sets the number of times that a failed
+/// download is retried.
+pub const RETRIES_ENV_VAR: &str = "TECTONIC_NET_RETRIES";
+
+/// Downloads at least this large report their progress.
+const PROGRESS_MIN_BYTES: usize = 4 * 1024 * 1024;
+
+/// How network bundles retry failed downloads.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RetryPolicy {
+    /// How many times a failed download is retried. Attempts that make some
+    /// progress before failing don't count against this limit, since the
+    /// next one picks up where they left off.
+    pub retries: usize,
+
+    /// How long to wait before the first retry. The wait doubles after each
+    /// further failure.
+    pub initial_delay: Duration,
+
+    /// The longest to wait between two attempts.
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            retries: 2,
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Get the default policy, with the number of retries taken from the
+    /// `TECTONIC_NET_RETRIES` environment variable if it's set to a number.
+    pub fn from_env() -> Self {
+        let mut policy = RetryPolicy::default();
+
+        if let Some(n) = env::var(RETRIES_ENV_VAR)
+            .ok()
+            .and_then(|v| v.trim().parse().ok())
+        {
+            policy.retries = n;
+        }
+
+        policy
+    }
+
+    /// How long to wait after the *failures*-th consecutive failure.
+    fn delay(&self, failures: usize) -> Duration {
+        let factor = 1u32
+            .checked_shl(failures.saturating_sub(1) as u32)
+            .unwrap_or(u32::MAX);
+
+        self.initial_delay
+            .checked_mul(factor)
+            .map_or(self.max_delay, |d| d.min(self.max_delay))
+    }
+}
+
+/// Download `length` bytes starting at `offset`, resuming and retrying as
+/// described by `policy`. The *name* is the name of the file being
+/// downloaded, used in messages.
+pub(crate) fn fetch_range<R: RangeReader>(
+    reader: &mut R,
+    offset: u64,
+    length: usize,
+    policy: &RetryPolicy,
+    name: &str,
+    status: &mut dyn StatusBackend,
+) -> Result<Vec<u8>> {
+    let mut data = Vec::with_capacity(length);
+    let mut failures = 0;
+    let mut next_report = PROGRESS_MIN_BYTES.max(length / 4);
+
+    while data.len() < length {
+        let start = data.len();
+
+        let error = match reader
+            .read_range(offset + start as u64, length - start)
+            .and_then(|stream| {
+                read_stream(stream, &mut data, length, |done| {
+                    if length >= PROGRESS_MIN_BYTES && done >= next_report {
+                        tt_note!(
+                            status,
+                            "downloading {}: {} of {} MiB",
+                            name,
+                            mib(done),
+                            mib(length)
+                        );
+                        next_report += length / 4;
+                    }
+                })
+            }) {
+            Ok(()) => break,
+            Err(e) => e,
+        };
+
+        // An attempt that made progress starts a new series of retries.
+        if data.len() > start {
+            failures = 0;
+        }
+
+        failures += 1;
+
+        if failures > policy.retries {
+            return Err(error.context(format!(
+                "failed to download \"{name}\"; please check your network connection"
+            )));
+        }
+
+        let delay = policy.delay(failures);
+
+        if data.is_empty() {
+            tt_warning!(status,
+                "failure downloading \"{}\"; retrying in {:.1} s ({}/{})",
+                name, delay.as_secs_f32(), failures, policy.retries; error
+            );
+        } else {
+            tt_warning!(status,
+                "download of \"{}\" broke off after {} of {} bytes; resuming in {:.1} s ({}/{})",
+                name, data.len(), length, delay.as_secs_f32(), failures, policy.retries; error
+            );
+        }
+
+        thread::sleep(delay);
+    }
+
+    Ok(data)
+}
+
+/// Read from `stream` into `data` until it holds `length` bytes, calling
+/// `progress` with the number of bytes held after each read.
+fn read_stream<S: Read>(
+    mut stream: S,
+    data: &mut Vec<u8>,
+    length: usize,
+    mut progress: impl FnMut(usize),
+) -> Result<()> {
+    let mut buf = vec![0; 64 * 1024];
+
+    while data.len() < length {
+        let n = match stream.read(&mut buf) {
+            Ok(0) => bail!("the connection was closed early"),
+            Ok(n) => n.min(length - data.len()),
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+
+        data.extend_from_slice(&buf[..n]);
+        progress(data.len());
+    }
+
+    Ok(())
+}
+
+fn mib(n: usize) -> String {
+    format!("{:.1}", n as f64 / (1024. * 1024.))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{self, Cursor};
+    use tectonic_status_base::NoopStatusBackend;
+
+    /// A stream that breaks off after a number of bytes.
+    struct Flaky(Cursor<Vec<u8>>, usize);
+
+    impl Read for Flaky {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.1 == 0 {
+                return Err(io::Error::new(io::ErrorKind::ConnectionReset, "reset"));
+            }
+
+            let n = buf.len().min(self.1);
+            let n = self.0.read(&mut buf[..n])?;
+            self.1 -= n;
+            Ok(n)
+        }
+    }
+
+    /// A resource whose responses break off after the given numbers of bytes,
+    /// in turn. A `None` fails the request itself.
+    struct FlakyResource {
+        data: Vec<u8>,
+        limits: Vec<Option<usize>>,
+        requests: Vec<(u64, usize)>,
+    }
+
+    impl RangeReader for FlakyResource {
+        type Response = Flaky;
+
+        fn read_range(&mut self, offset: u64, length: usize) -> Result<Flaky> {
+            self.requests.push((offset, length));
+            let range = self.data[offset as usize..offset as usize + length].to_vec();
+
+            match self.limits.get(self.requests.len() - 1).copied().flatten() {
+                Some(limit) => Ok(Flaky(Cursor::new(range), limit)),
+                None if self.requests.len() > self.limits.len() => {
+                    Ok(Flaky(Cursor::new(range), usize::MAX))
+                }
+                None => bail!("request failed"),
+            }
+        }
+    }
+
+    fn no_delay(retries: usize) -> RetryPolicy {
+        RetryPolicy {
+            retries,
+            initial_delay: Duration::ZERO,
+            max_delay: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn resume() {
+        let mut resource = FlakyResource {
+            data: (0..100).collect(),
+            limits: vec![None, Some(10), Some(20), Some(0)],
+            requests: Vec::new(),
+        };
+
+        let data = fetch_range(
+            &mut resource,
+            50,
+            40,
+            &no_delay(2),
+            "a.sty",
+            &mut NoopStatusBackend {},
+        )
+        .unwrap();
+
+        assert_eq!(data, (50..90).collect::<Vec<u8>>());
+        assert_eq!(
+            resource.requests,
+            [(50, 40), (50, 40), (60, 30), (80, 10), (80, 10)]
+        );
+    }
+
+    #[test]
+    fn give_up() {
+        let mut resource = FlakyResource {
+            data: (0..100).collect(),
+            limits: vec![Some(5), None, None, None],
+            requests: Vec::new(),
+        };
+
+        assert!(fetch_range(
+            &mut resource,
+            0,
+            40,
+            &no_delay(2),
+            "a.sty",
+            &mut NoopStatusBackend {}
+        )
+        .is_err());
+        assert_eq!(resource.requests.len(), 3);
+    }
+
+    #[test]
+    fn backoff() {
+        let policy = RetryPolicy {
+            retries: 10,
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(3),
+        };
+
+        assert_eq!(policy.delay(1), Duration::from_millis(500));
+        assert_eq!(policy.delay(2), Duration::from_secs(1));
+        assert_eq!(policy.delay(3), Duration::from_secs(2));
+        assert_eq!(policy.delay(4), Duration::from_secs(3));
+        assert_eq!(policy.delay(100), Duration::from_secs(3));
+    }
+}
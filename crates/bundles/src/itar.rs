@@ -14,20 +14,37 @@
 //! centered on HTTP byte-range requests. For each file contained in the backing
 //! resource, the index file merely contains a byte offset and length that are
 //! then used to construct an HTTP Range request to obtain the file as needed.
+//! When the files that will be needed are known in advance, several of these
+//! requests can be made at once; see [`ItarBundle::set_concurrent_prefetch`].
 
-use crate::{Bundle, CachableBundle, FileIndex, FileInfo, NET_RETRY_ATTEMPTS, NET_RETRY_SLEEP_MS};
+use crate::{
+    fetch::{self, RetryPolicy},
+    Bundle, CachableBundle, FileIndex, FileInfo, FileSink,
+};
 use flate2::read::GzDecoder;
 use std::{
     collections::HashMap,
     io::{BufRead, BufReader, Cursor, Read},
     str::FromStr,
+    sync::{mpsc, Mutex},
     thread,
-    time::Duration,
 };
 use tectonic_errors::prelude::*;
-use tectonic_geturl::{DefaultBackend, DefaultRangeReader, GetUrlBackend, RangeReader};
+use tectonic_geturl::{DefaultBackend, GetUrlBackend};
 use tectonic_io_base::{digest, InputHandle, InputOrigin, IoProvider, OpenResult};
-use tectonic_status_base::{tt_note, tt_warning, NoopStatusBackend, StatusBackend};
+use tectonic_status_base::{tt_note, NoopStatusBackend, StatusBackend};
+
+/// The most range requests that are made at once when prefetching files.
+const PREFETCH_CONNECTIONS: usize = 8;
+
+/// A way for an [`ItarBundle`] to fetch several files, as in
+/// [`CachableBundle::fetch_fileinfos`].
+type FetchMany<B> = fn(
+    &mut ItarBundle<B>,
+    &[ItarFileInfo],
+    &mut FileSink<'_, ItarFileInfo>,
+    &mut dyn StatusBackend,
+) -> Result<()>;
 
 /// The internal file-information struct used by the [`ItarBundle`].
 #[derive(Clone, Debug)]
@@ -96,8 +113,13 @@ impl<'this> FileIndex<'this> for ItarFileIndex {
 }
 
 /// The old-fashoned Tectonic web bundle format.
-pub struct ItarBundle {
+///
+/// Requests are made with the HTTP backend `B`, which is the default backend
+/// of the `tectonic_geturl` crate unless the bundle is created with
+/// [`ItarBundle::with_backend`].
+pub struct ItarBundle<B: GetUrlBackend = DefaultBackend> {
     url: String,
+    backend: B,
     /// Maps all available file names to [`FileInfo`]s.
     /// This is empty after we create this bundle, so we don't need network
     /// to make an object. It is automatically filled by get_index when we need it.
@@ -106,26 +128,52 @@ pub struct ItarBundle {
     /// RangeReader object, responsible for sending queries.
     /// Will be None when the object is created, automatically
     /// replaced with Some(...) once needed.
-    reader: Option<DefaultRangeReader>,
+    reader: Option<B::RangeReader>,
+
+    /// How failed downloads are retried.
+    retry_policy: RetryPolicy,
+
+    /// How prefetched files are fetched: one after another, unless
+    /// [`ItarBundle::set_concurrent_prefetch`] has been called.
+    fetch_many: FetchMany<B>,
 }
 
 impl ItarBundle {
     /// Make a new ItarBundle.
     /// This method does not require network access.
     /// It will succeed even in we can't connect to the bundle, or if we're given a bad url.
+    /// Files are prefetched several at a time.
     pub fn new(url: String) -> Result<ItarBundle> {
-        Ok(ItarBundle {
+        let mut bundle = ItarBundle::with_backend(url, DefaultBackend::default());
+        bundle.set_concurrent_prefetch();
+        Ok(bundle)
+    }
+}
+
+impl<B: GetUrlBackend + 'static> ItarBundle<B> {
+    /// Make a new ItarBundle that makes its requests with `backend`.
+    /// Like [`ItarBundle::new`], this does not require network access.
+    pub fn with_backend(url: String, backend: B) -> ItarBundle<B> {
+        ItarBundle {
             index: ItarFileIndex::default(),
+            backend,
             reader: None,
+            retry_policy: RetryPolicy::from_env(),
+            fetch_many: Self::fetch_sequentially,
             url,
-        })
+        }
+    }
+
+    /// Change how failed downloads are retried. By default, the policy comes
+    /// from [`RetryPolicy::from_env`].
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.retry_policy = policy;
     }
 
     fn connect_reader(&mut self) {
-        let geturl_backend = DefaultBackend::default();
         // Connect reader if it is not already connected
         if self.reader.is_none() {
-            self.reader = Some(geturl_backend.open_range_reader(&self.url));
+            self.reader = Some(self.backend.open_range_reader(&self.url));
         }
     }
 
@@ -142,9 +190,26 @@ impl ItarBundle {
 
         Ok(())
     }
+
+    /// Fetch the files one after another, as they would be if they were
+    /// opened.
+    fn fetch_sequentially(
+        &mut self,
+        infos: &[ItarFileInfo],
+        save: &mut FileSink<'_, ItarFileInfo>,
+        status: &mut dyn StatusBackend,
+    ) -> Result<()> {
+        for info in infos {
+            if let OpenResult::Ok(mut handle) = self.open_fileinfo(info, status) {
+                save(info, &mut handle)?;
+            }
+        }
+
+        Ok(())
+    }
 }
 
-impl IoProvider for ItarBundle {
+impl<B: GetUrlBackend + 'static> IoProvider for ItarBundle<B> {
     fn input_open_name(
         &mut self,
         name: &str,
@@ -165,7 +230,7 @@ impl IoProvider for ItarBundle {
     }
 }
 
-impl Bundle for ItarBundle {
+impl<B: GetUrlBackend + 'static> Bundle for ItarBundle<B> {
     fn all_files(&self) -> Vec<String> {
         self.index.iter().map(|x| x.path().to_owned()).collect()
     }
@@ -193,7 +258,7 @@ impl Bundle for ItarBundle {
     }
 }
 
-impl CachableBundle<'_, ItarFileIndex> for ItarBundle {
+impl<B: GetUrlBackend + 'static> CachableBundle<'_, ItarFileIndex> for ItarBundle<B> {
     fn get_location(&mut self) -> String {
         self.url.clone()
     }
@@ -212,9 +277,8 @@ impl CachableBundle<'_, ItarFileIndex> for ItarBundle {
     }
 
     fn get_index_reader(&mut self) -> Result<Box<dyn Read>> {
-        let mut geturl_backend = DefaultBackend::default();
         let index_url = format!("{}.index.gz", &self.url);
-        let reader = GzDecoder::new(geturl_backend.get_url(&index_url)?);
+        let reader = GzDecoder::new(self.backend.get_url(&index_url)?);
         Ok(Box::new(reader))
     }
 
@@ -228,7 +292,6 @@ impl CachableBundle<'_, ItarFileIndex> for ItarBundle {
             Err(e) => return OpenResult::Err(e),
         };
 
-        let mut v = Vec::with_capacity(info.length);
         tt_note!(status, "downloading {}", info.name);
 
         // Edge case for zero-sized reads
@@ -236,52 +299,273 @@ impl CachableBundle<'_, ItarFileIndex> for ItarBundle {
         if info.length == 0 {
             return OpenResult::Ok(InputHandle::new_read_only(
                 info.name.to_owned(),
-                Cursor::new(v),
+                Cursor::new(Vec::new()),
                 InputOrigin::Other,
             ));
         }
 
-        // Get file with retries
-        for i in 0..NET_RETRY_ATTEMPTS {
-            let mut stream = match self
-                .reader
-                .as_mut()
-                .unwrap()
-                .read_range(info.offset, info.length)
-            {
-                Ok(r) => r,
-                Err(e) => {
-                    tt_warning!(status,
-                        "failure fetching \"{}\" from network ({}/{NET_RETRY_ATTEMPTS})",
-                        info.name, i+1; e
-                    );
-                    thread::sleep(Duration::from_millis(NET_RETRY_SLEEP_MS));
-                    continue;
-                }
-            };
-
-            match stream.read_to_end(&mut v) {
-                Ok(_) => {}
-                Err(e) => {
-                    tt_warning!(status,
-                        "failure downloading \"{}\" from network ({}/{NET_RETRY_ATTEMPTS})",
-                        info.name, i+1; e.into()
-                    );
-                    thread::sleep(Duration::from_millis(NET_RETRY_SLEEP_MS));
-                    continue;
-                }
-            };
-
-            return OpenResult::Ok(InputHandle::new_read_only(
+        match fetch::fetch_range(
+            self.reader.as_mut().unwrap(),
+            info.offset,
+            info.length,
+            &self.retry_policy,
+            &info.name,
+            status,
+        ) {
+            Ok(v) => OpenResult::Ok(InputHandle::new_read_only(
                 info.name.to_owned(),
                 Cursor::new(v),
                 InputOrigin::Other,
-            ));
+            )),
+            Err(e) => OpenResult::Err(e),
         }
+    }
+
+    fn fetch_fileinfos(
+        &mut self,
+        infos: &[ItarFileInfo],
+        save: &mut FileSink<'_, ItarFileInfo>,
+        status: &mut dyn StatusBackend,
+    ) -> Result<()> {
+        (self.fetch_many)(self, infos, save, status)
+    }
+}
+
+impl<B: GetUrlBackend + 'static> ItarBundle<B>
+where
+    B::RangeReader: Send,
+{
+    /// Prefetch files with several range requests at once, each from its own
+    /// thread. This is what [`ItarBundle::new`] does, but bundles made with
+    /// [`ItarBundle::with_backend`] fetch them one after another unless this
+    /// is called, since not every backend's range readers can be sent to
+    /// other threads.
+    pub fn set_concurrent_prefetch(&mut self) {
+        self.fetch_many = Self::fetch_concurrently;
+    }
+
+    /// Fetch the files with several range requests at once. Each request is
+    /// retried as usual, but quietly, since a file that can't be prefetched is
+    /// fetched again when it's opened.
+    fn fetch_concurrently(
+        &mut self,
+        infos: &[ItarFileInfo],
+        save: &mut FileSink<'_, ItarFileInfo>,
+        _status: &mut dyn StatusBackend,
+    ) -> Result<()> {
+        let n_connections = PREFETCH_CONNECTIONS.min(infos.len());
+        let readers: Vec<_> = (0..n_connections)
+            .map(|_| self.backend.open_range_reader(&self.url))
+            .collect();
+        let queue = Mutex::new(infos.iter());
+        let policy = self.retry_policy;
+
+        thread::scope(|scope| {
+            let (tx, rx) = mpsc::channel();
+
+            for mut reader in readers {
+                let (tx, queue) = (tx.clone(), &queue);
+
+                scope.spawn(move || loop {
+                    let Some(info) = queue.lock().unwrap().next() else {
+                        break;
+                    };
+
+                    // Edge case for zero-sized reads, as in `open_fileinfo`.
+                    let data = if info.length == 0 {
+                        Ok(Vec::new())
+                    } else {
+                        fetch::fetch_range(
+                            &mut reader,
+                            info.offset,
+                            info.length,
+                            &policy,
+                            &info.name,
+                            &mut NoopStatusBackend {},
+                        )
+                    };
+
+                    if tx.send((info, data)).is_err() {
+                        break;
+                    }
+                });
+            }
+
+            drop(tx);
+
+            for (info, data) in rx {
+                if let Ok(data) = data {
+                    save(info, &mut &data[..])?;
+                }
+            }
+
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::BundleCache;
+    use flate2::{write::GzEncoder, Compression};
+    use std::{
+        io::Write,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+    use tectonic_geturl::RangeReader;
+    use tectonic_io_base::digest::DigestData;
+
+    const URL: &str = "https://example.com/bundle.tar";
+
+    /// An HTTP backend that serves files out of memory, as an embedder's own
+    /// client might. It counts the range readers that it opens, and the range
+    /// requests that they make.
+    #[derive(Default)]
+    struct MemoryBackend {
+        files: HashMap<String, Vec<u8>>,
+        readers: Arc<AtomicUsize>,
+        reads: Arc<AtomicUsize>,
+    }
+
+    struct MemoryRangeReader(Vec<u8>, Arc<AtomicUsize>);
+
+    impl RangeReader for MemoryRangeReader {
+        type Response = Cursor<Vec<u8>>;
+
+        fn read_range(&mut self, offset: u64, length: usize) -> Result<Self::Response> {
+            self.1.fetch_add(1, Ordering::SeqCst);
+            let start = offset as usize;
+            Ok(Cursor::new(self.0[start..start + length].to_vec()))
+        }
+    }
+
+    impl GetUrlBackend for MemoryBackend {
+        type Response = Cursor<Vec<u8>>;
+        type RangeReader = MemoryRangeReader;
+
+        fn resolve_url(&mut self, url: &str) -> Result<String> {
+            Ok(url.to_owned())
+        }
+
+        fn get_url(&mut self, url: &str) -> Result<Self::Response> {
+            match self.files.get(url) {
+                Some(data) => Ok(Cursor::new(data.clone())),
+                None => bail!("no such URL {}", url),
+            }
+        }
+
+        fn open_range_reader(&self, url: &str) -> Self::RangeReader {
+            self.readers.fetch_add(1, Ordering::SeqCst);
+            MemoryRangeReader(
+                self.files.get(url).cloned().unwrap_or_default(),
+                self.reads.clone(),
+            )
+        }
+    }
+
+    /// Serve a bundle with the given index, and the given contents.
+    fn serve(index_text: &str, contents: &[u8]) -> MemoryBackend {
+        let mut index = GzEncoder::new(Vec::new(), Compression::default());
+        index.write_all(index_text.as_bytes()).unwrap();
+
+        let mut backend = MemoryBackend::default();
+        backend.files.insert(URL.to_owned(), contents.to_vec());
+        backend
+            .files
+            .insert(format!("{URL}.index.gz"), index.finish().unwrap());
+        backend
+    }
+
+    #[test]
+    fn custom_backend() {
+        let backend = serve("a.sty 3 5\n", b"...hello...");
+        let mut bundle = ItarBundle::with_backend(URL.to_owned(), backend);
+        let mut contents = String::new();
+
+        bundle
+            .input_open_name("a.sty", &mut NoopStatusBackend {})
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "hello");
+        assert!(bundle
+            .input_open_name("b.sty", &mut NoopStatusBackend {})
+            .is_not_available());
+    }
+
+    #[test]
+    fn prefetch() {
+        let digest = DigestData::zeros().to_string();
+        let backend = serve(
+            "SHA256SUM 0 64\na.sty 64 5\nb.sty 69 3\n",
+            format!("{digest}hellobye").as_bytes(),
+        );
+        let (readers, reads) = (backend.readers.clone(), backend.reads.clone());
+        let root = tempfile::tempdir().unwrap();
+        let mut bundle = ItarBundle::with_backend(URL.to_owned(), backend);
+        bundle.set_concurrent_prefetch();
+        let mut cache =
+            BundleCache::new(Box::new(bundle), false, Some(root.path().to_owned())).unwrap();
+
+        // Getting the digest of the bundle takes one request.
+        assert_eq!(reads.load(Ordering::SeqCst), 1);
+
+        let names = ["a.sty", "b.sty", "c.sty"].map(String::from);
+        cache.prefetch(&names, &mut NoopStatusBackend {}).unwrap();
+
+        // The two files are fetched at once, with a reader each.
+        assert_eq!(readers.load(Ordering::SeqCst), 3);
+        assert_eq!(reads.load(Ordering::SeqCst), 3);
+
+        // From now on, they come out of the cache.
+        for (name, expected) in [("a.sty", "hello"), ("b.sty", "bye")] {
+            let mut contents = String::new();
+            cache
+                .input_open_name(name, &mut NoopStatusBackend {})
+                .unwrap()
+                .read_to_string(&mut contents)
+                .unwrap();
+            assert_eq!(contents, expected);
+        }
+
+        assert_eq!(reads.load(Ordering::SeqCst), 3);
+
+        // Nothing is fetched again.
+        cache.prefetch(&names, &mut NoopStatusBackend {}).unwrap();
+        assert_eq!(readers.load(Ordering::SeqCst), 3);
+    }
 
-        OpenResult::Err(anyhow!(
-            "failed to download \"{}\"; please check your network connection.",
-            info.name
-        ))
+    #[test]
+    fn prefetch_sequentially() {
+        let digest = DigestData::zeros().to_string();
+        let backend = serve(
+            "SHA256SUM 0 64\na.sty 64 5\nb.sty 69 3\n",
+            format!("{digest}hellobye").as_bytes(),
+        );
+        let (readers, reads) = (backend.readers.clone(), backend.reads.clone());
+        let root = tempfile::tempdir().unwrap();
+        let bundle = ItarBundle::with_backend(URL.to_owned(), backend);
+        let mut cache =
+            BundleCache::new(Box::new(bundle), false, Some(root.path().to_owned())).unwrap();
+
+        // Without concurrent prefetching, the files are fetched with the
+        // bundle's own reader.
+        let names = ["a.sty", "b.sty"].map(String::from);
+        cache.prefetch(&names, &mut NoopStatusBackend {}).unwrap();
+        assert_eq!(readers.load(Ordering::SeqCst), 1);
+        assert_eq!(reads.load(Ordering::SeqCst), 3);
+
+        let mut contents = String::new();
+        cache
+            .input_open_name("b.sty", &mut NoopStatusBackend {})
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "bye");
+        assert_eq!(reads.load(Ordering::SeqCst), 3);
     }
 }
@@ -11,9 +11,21 @@
 //!
 //! - [`cache::BundleCache`] provides filesystem-backed caching for any bundle
 //! - [`itar::ItarBundle`] provides filesystem-backed caching for any bundle
-//! - [`dir::DirBundle`] turns a directory full of files into a bundle; it is
-//!   useful for testing and lightweight usage.
+//! - [`dir::DirBundle`] turns a directory of support files into a bundle; it is
+//!   useful for testing, air-gapped setups, and experimenting with new files.
 //! - [`zip::ZipBundle`] for a ZIP-format bundle.
+//! - [`overlay::OverlayBundle`] stacks several bundles, letting earlier ones
+//!   shadow files in later ones.
+//! - [`tar::TarBundle`] for an uncompressed tarball, such as one exported for
+//!   a single document.
+//! - [`record::RecordingBundle`] keeps track of which files of any bundle get
+//!   used.
+//!
+//! The contents of ttb bundles are checked against the digests in their
+//! indexes as they are read, and their indexes may be signed; see [`verify`].
+//! Network bundles resume and retry interrupted downloads; see [`fetch`]. The
+//! files that a build is known to need can be fetched ahead of time, all at
+//! once, with [`Bundle::prefetch`].
 
 use std::{fmt::Debug, io::Read, path::PathBuf};
 use tectonic_errors::{prelude::bail, Result};
@@ -22,28 +34,29 @@ use tectonic_status_base::StatusBackend;
 
 pub mod cache;
 pub mod dir;
+pub mod fetch;
 pub mod itar;
+pub mod overlay;
+pub mod record;
+pub mod tar;
 mod ttb;
 pub mod ttb_fs;
 pub mod ttb_net;
+pub mod verify;
 pub mod zip;
 
 use cache::BundleCache;
 use dir::DirBundle;
 use itar::ItarBundle;
+use tar::TarBundle;
 use ttb_fs::TTBFsBundle;
 use ttb_net::TTBNetBundle;
+use verify::IndexKey;
 use zip::ZipBundle;
 
 /// The current hardcoded default prefix for tectonic's bundle.
 const TECTONIC_BUNDLE_PREFIX_DEFAULT: &str = "https://relay.fullyjustified.net";
 
-// How many times network bundles should retry
-// a download, and how long they should wait
-// between attempts.
-const NET_RETRY_ATTEMPTS: usize = 3;
-const NET_RETRY_SLEEP_MS: u64 = 500;
-
 /// Uniquely identifies a file in a bundle.
 pub trait FileInfo: Clone + Debug {
     /// Return a path to this file, relative to the bundle.
@@ -51,6 +64,12 @@ pub trait FileInfo: Clone + Debug {
 
     /// Return the name of this file
     fn name(&self) -> &str;
+
+    /// Return the SHA256 digest of this file's contents, as recorded in the
+    /// bundle index, if there is one.
+    fn digest(&self) -> Option<&str> {
+        None
+    }
 }
 
 /// Keeps track of
@@ -107,6 +126,16 @@ pub trait Bundle: IoProvider {
     /// Iterate over all file paths in this bundle.
     /// This is used for the `bundle search` command
     fn all_files(&self) -> Vec<String>;
+
+    /// Fetch the named files ahead of time, all at once, if this bundle would
+    /// otherwise fetch them one by one as they're opened. Names that aren't in
+    /// the bundle are ignored, and so are files that can't be fetched, which
+    /// are fetched again when they're opened.
+    ///
+    /// By default, this does nothing.
+    fn prefetch(&mut self, _names: &[String], _status: &mut dyn StatusBackend) -> Result<()> {
+        Ok(())
+    }
 }
 
 impl<B: Bundle + ?Sized> Bundle for Box<B> {
@@ -117,8 +146,16 @@ impl<B: Bundle + ?Sized> Bundle for Box<B> {
     fn all_files(&self) -> Vec<String> {
         (**self).all_files()
     }
+
+    fn prefetch(&mut self, names: &[String], status: &mut dyn StatusBackend) -> Result<()> {
+        (**self).prefetch(names, status)
+    }
 }
 
+/// A function that is handed the contents of files fetched by
+/// [`CachableBundle::fetch_fileinfos`], along with their infos.
+pub type FileSink<'a, I> = dyn FnMut(&I, &mut dyn Read) -> Result<()> + 'a;
+
 /// A bundle that may be cached.
 ///
 /// These methods do not implement any new features.
@@ -150,6 +187,27 @@ where
         status: &mut dyn StatusBackend,
     ) -> OpenResult<InputHandle>;
 
+    /// Fetch the files that `infos` point to, passing the contents of each one
+    /// to `save` as it arrives. Files that can't be fetched are skipped.
+    ///
+    /// By default, the files are opened one after another with
+    /// [`Self::open_fileinfo`]. Bundles that can fetch several files at once
+    /// should do so.
+    fn fetch_fileinfos(
+        &mut self,
+        infos: &[T::InfoType],
+        save: &mut FileSink<'_, T::InfoType>,
+        status: &mut dyn StatusBackend,
+    ) -> Result<()> {
+        for info in infos {
+            if let OpenResult::Ok(mut handle) = self.open_fileinfo(info, status) {
+                save(info, &mut handle)?;
+            }
+        }
+
+        Ok(())
+    }
+
     /// Search for a file in this bundle.
     /// This should foward the call to `self.index`
     fn search(&mut self, name: &str) -> Option<T::InfoType>;
@@ -157,6 +215,21 @@ where
     /// Return a string that corresponds to this bundle's location, probably a URL.
     /// We should NOT need to do any network IO to get this value.
     fn get_location(&mut self) -> String;
+
+    /// Return the key that this bundle's index must be signed with, if any.
+    ///
+    /// If there is one, [`crate::BundleCache`] checks the signature of the
+    /// index whenever it uses it, including copies of it that it has cached.
+    fn index_key(&self) -> Option<IndexKey> {
+        None
+    }
+
+    /// Get the detached signature of this bundle's index, reading directly
+    /// from the backend. This is only called if [`Self::index_key`] returns a
+    /// key.
+    fn get_index_signature(&mut self) -> Result<String> {
+        bail!("this bundle doesn't have a signed index")
+    }
 }
 
 impl<'this, T: FileIndex<'this>, B: CachableBundle<'this, T> + ?Sized> CachableBundle<'this, T>
@@ -186,19 +259,41 @@ impl<'this, T: FileIndex<'this>, B: CachableBundle<'this, T> + ?Sized> CachableB
         (**self).open_fileinfo(info, status)
     }
 
+    fn fetch_fileinfos(
+        &mut self,
+        infos: &[T::InfoType],
+        save: &mut FileSink<'_, T::InfoType>,
+        status: &mut dyn StatusBackend,
+    ) -> Result<()> {
+        (**self).fetch_fileinfos(infos, save, status)
+    }
+
     fn search(&mut self, name: &str) -> Option<T::InfoType> {
         (**self).search(name)
     }
+
+    fn index_key(&self) -> Option<IndexKey> {
+        (**self).index_key()
+    }
+
+    fn get_index_signature(&mut self) -> Result<String> {
+        (**self).get_index_signature()
+    }
 }
 
 /// Try to open a bundle from a string,
 /// detecting its type.
 ///
+/// If `index_key` is given, the bundle must be in the ttb format, and its
+/// index must have a valid detached signature for the key (see
+/// [`verify`]).
+///
 /// Returns None if auto-detection fails.
 pub fn detect_bundle(
     source: String,
     only_cached: bool,
     custom_cache_dir: Option<PathBuf>,
+    index_key: Option<IndexKey>,
 ) -> Result<Option<Box<dyn Bundle>>> {
     use url::Url;
 
@@ -206,13 +301,17 @@ pub fn detect_bundle(
     if let Ok(url) = Url::parse(&source) {
         if url.scheme() == "https" || url.scheme() == "http" {
             if source.ends_with("ttb") {
-                let bundle = BundleCache::new(
-                    Box::new(TTBNetBundle::new(source)?),
-                    only_cached,
-                    custom_cache_dir,
-                )?;
+                let bundle = match index_key {
+                    Some(key) => TTBNetBundle::new_signed(source, key)?,
+                    None => TTBNetBundle::new(source)?,
+                };
+                let bundle = BundleCache::new(Box::new(bundle), only_cached, custom_cache_dir)?;
                 return Ok(Some(Box::new(bundle)));
             } else {
+                if index_key.is_some() {
+                    bail!("only ttb bundles can have signed indexes, but `{source}` is not one");
+                }
+
                 let bundle = BundleCache::new(
                     Box::new(ItarBundle::new(source)?),
                     only_cached,
@@ -227,24 +326,41 @@ pub fn detect_bundle(
                     "failed to parse local path",
                 )
             })?;
-            return bundle_from_path(file_path);
+            return bundle_from_path(file_path, index_key);
         } else {
             return Ok(None);
         }
     } else {
         // If we couldn't parse the URL, this is probably a local path.
-        return bundle_from_path(PathBuf::from(source));
+        return bundle_from_path(PathBuf::from(source), index_key);
     }
 
-    fn bundle_from_path(p: PathBuf) -> Result<Option<Box<dyn Bundle>>> {
+    fn bundle_from_path(
+        p: PathBuf,
+        index_key: Option<IndexKey>,
+    ) -> Result<Option<Box<dyn Bundle>>> {
         let ext = p.extension().map_or("", |x| x.to_str().unwrap_or(""));
 
+        if ext == "ttb" && !p.is_dir() {
+            return match index_key {
+                Some(key) => Ok(Some(Box::new(TTBFsBundle::open_signed(p, key)?))),
+                None => Ok(Some(Box::new(TTBFsBundle::open(p)?))),
+            };
+        }
+
+        if index_key.is_some() {
+            bail!(
+                "only ttb bundles can have signed indexes, but `{}` is not one",
+                p.display()
+            );
+        }
+
         if p.is_dir() {
             Ok(Some(Box::new(DirBundle::new(p))))
         } else if ext == "zip" {
             Ok(Some(Box::new(ZipBundle::open(p)?)))
-        } else if ext == "ttb" {
-            Ok(Some(Box::new(TTBFsBundle::open(p)?)))
+        } else if ext == "tar" {
+            Ok(Some(Box::new(TarBundle::open(p)?)))
         } else {
             Ok(None)
         }
@@ -301,7 +417,7 @@ pub fn get_fallback_bundle_url(format_version: u32) -> String {
 /// position to use it.
 pub fn get_fallback_bundle(format_version: u32, only_cached: bool) -> Result<Box<dyn Bundle>> {
     let url = get_fallback_bundle_url(format_version);
-    let bundle = detect_bundle(url, only_cached, None)?;
+    let bundle = detect_bundle(url, only_cached, None, None)?;
     if bundle.is_none() {
         bail!("could not open default bundle")
     }
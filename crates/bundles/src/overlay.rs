@@ -0,0 +1,152 @@
+// Copyright 2024 the Tectonic Project
+// Licensed under the MIT License.
+
+//! A module for stacking bundles on top of each other, [`OverlayBundle`].
+
+use std::{collections::HashSet, path::PathBuf};
+use tectonic_errors::prelude::*;
+use tectonic_io_base::{
+    digest::{self, Digest, DigestData},
+    InputHandle, IoProvider, OpenResult,
+};
+use tectonic_status_base::StatusBackend;
+
+use super::Bundle;
+
+/// An ordered stack of bundles, where earlier bundles shadow later ones.
+///
+/// A file is opened from the first bundle that provides it. This makes it
+/// possible to layer a small set of patched support files, such as a directory
+/// of modified `.sty` or `.bst` files, over a complete bundle without having
+/// to rebuild the latter.
+pub struct OverlayBundle {
+    bundles: Vec<Box<dyn Bundle>>,
+}
+
+impl OverlayBundle {
+    /// Create a new overlay out of the given bundles, the first of which
+    /// has the highest priority.
+    pub fn new(bundles: Vec<Box<dyn Bundle>>) -> OverlayBundle {
+        OverlayBundle { bundles }
+    }
+}
+
+impl IoProvider for OverlayBundle {
+    fn input_open_name(
+        &mut self,
+        name: &str,
+        status: &mut dyn StatusBackend,
+    ) -> OpenResult<InputHandle> {
+        for bundle in &mut self.bundles {
+            match bundle.input_open_name(name, status) {
+                OpenResult::NotAvailable => {}
+                r => return r,
+            }
+        }
+
+        OpenResult::NotAvailable
+    }
+
+    fn input_open_name_with_abspath(
+        &mut self,
+        name: &str,
+        status: &mut dyn StatusBackend,
+    ) -> OpenResult<(InputHandle, Option<PathBuf>)> {
+        for bundle in &mut self.bundles {
+            match bundle.input_open_name_with_abspath(name, status) {
+                OpenResult::NotAvailable => {}
+                r => return r,
+            }
+        }
+
+        OpenResult::NotAvailable
+    }
+}
+
+impl Bundle for OverlayBundle {
+    fn all_files(&self) -> Vec<String> {
+        let mut seen = HashSet::new();
+        let mut files = Vec::new();
+
+        for bundle in &self.bundles {
+            for file in bundle.all_files() {
+                if seen.insert(file.clone()) {
+                    files.push(file);
+                }
+            }
+        }
+
+        files
+    }
+
+    /// The digest of an overlay combines the digests of all of its layers, in
+    /// order, so that changing any one of them invalidates cached formats.
+    fn get_digest(&mut self) -> Result<DigestData> {
+        let mut dc = digest::create();
+
+        for bundle in &mut self.bundles {
+            dc.update(bundle.get_digest()?.to_string().as_bytes());
+            dc.update([0]);
+        }
+
+        Ok(DigestData::from(dc))
+    }
+
+    /// Each layer is asked for the files that the layers above it don't
+    /// provide.
+    fn prefetch(&mut self, names: &[String], status: &mut dyn StatusBackend) -> Result<()> {
+        let mut names = names.to_vec();
+
+        for bundle in &mut self.bundles {
+            if names.is_empty() {
+                break;
+            }
+
+            bundle.prefetch(&names, status)?;
+            let provided: HashSet<String> = bundle.all_files().into_iter().collect();
+            names.retain(|name| !provided.contains(name));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dir::DirBundle;
+    use std::{fs, io::Read};
+    use tectonic_status_base::NoopStatusBackend;
+
+    #[test]
+    fn earlier_layers_shadow() {
+        let patches = tempfile::tempdir().unwrap();
+        let base = tempfile::tempdir().unwrap();
+
+        fs::write(patches.path().join("article.cls"), "patched").unwrap();
+        fs::write(base.path().join("article.cls"), "base").unwrap();
+        fs::write(base.path().join("plain.tex"), "plain").unwrap();
+
+        let mut overlay = OverlayBundle::new(vec![
+            Box::new(DirBundle::new(patches.path())),
+            Box::new(DirBundle::new(base.path())),
+        ]);
+
+        let mut read = |name: &str| match overlay.input_open_name(name, &mut NoopStatusBackend {}) {
+            OpenResult::Ok(mut h) => {
+                let mut text = String::new();
+                h.read_to_string(&mut text).unwrap();
+                Some(text)
+            }
+            _ => None,
+        };
+
+        assert_eq!(read("article.cls").as_deref(), Some("patched"));
+        assert_eq!(read("plain.tex").as_deref(), Some("plain"));
+        assert_eq!(read("missing.sty"), None);
+
+        let mut files = overlay.all_files();
+        files.sort();
+        assert_eq!(files, ["article.cls", "plain.tex"]);
+    }
+}
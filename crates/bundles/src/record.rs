@@ -0,0 +1,76 @@
+// Copyright 2024 the Tectonic Project
+// Licensed under the MIT License.
+
+//! A wrapper that keeps track of which files of a bundle get used,
+//! [`RecordingBundle`].
+
+use std::{cell::RefCell, collections::BTreeSet, path::PathBuf, rc::Rc};
+use tectonic_errors::prelude::*;
+use tectonic_io_base::{digest::DigestData, InputHandle, IoProvider, OpenResult};
+use tectonic_status_base::StatusBackend;
+
+use super::Bundle;
+
+/// The set of file names that a [`RecordingBundle`] has served.
+pub type UsedFiles = Rc<RefCell<BTreeSet<String>>>;
+
+/// A bundle that records the name of every file that is successfully opened
+/// from the bundle that it wraps.
+///
+/// Since the bundle itself is generally handed off to a processing session,
+/// the names are collected in a shared [`UsedFiles`] set.
+pub struct RecordingBundle {
+    inner: Box<dyn Bundle>,
+    used: UsedFiles,
+}
+
+impl RecordingBundle {
+    /// Wrap a bundle, adding the names of the files opened from it to `used`.
+    pub fn new(inner: Box<dyn Bundle>, used: UsedFiles) -> RecordingBundle {
+        RecordingBundle { inner, used }
+    }
+}
+
+impl IoProvider for RecordingBundle {
+    fn input_open_name(
+        &mut self,
+        name: &str,
+        status: &mut dyn StatusBackend,
+    ) -> OpenResult<InputHandle> {
+        let r = self.inner.input_open_name(name, status);
+
+        if let OpenResult::Ok(_) = r {
+            self.used.borrow_mut().insert(name.to_owned());
+        }
+
+        r
+    }
+
+    fn input_open_name_with_abspath(
+        &mut self,
+        name: &str,
+        status: &mut dyn StatusBackend,
+    ) -> OpenResult<(InputHandle, Option<PathBuf>)> {
+        let r = self.inner.input_open_name_with_abspath(name, status);
+
+        if let OpenResult::Ok(_) = r {
+            self.used.borrow_mut().insert(name.to_owned());
+        }
+
+        r
+    }
+}
+
+impl Bundle for RecordingBundle {
+    fn all_files(&self) -> Vec<String> {
+        self.inner.all_files()
+    }
+
+    fn get_digest(&mut self) -> Result<DigestData> {
+        self.inner.get_digest()
+    }
+
+    fn prefetch(&mut self, names: &[String], status: &mut dyn StatusBackend) -> Result<()> {
+        self.inner.prefetch(names, status)
+    }
+}
@@ -0,0 +1,234 @@
+// Copyright 2024 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Plain tar files as Tectonic bundles.
+//!
+//! These are mainly produced by `tectonic -X build --export-bundle`, which
+//! vendors exactly the support files that a document needs, but any tarball
+//! that includes a `SHA256SUM` file will work.
+
+use crate::Bundle;
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{Cursor, Read, Seek, SeekFrom, Write},
+    path::Path,
+    str::FromStr,
+};
+use tar::{Archive, Builder, Header};
+use tectonic_errors::prelude::*;
+use tectonic_io_base::{
+    digest::{self, Digest},
+    InputHandle, InputOrigin, IoProvider, OpenResult,
+};
+use tectonic_status_base::{NoopStatusBackend, StatusBackend};
+
+/// A bundle backed by an uncompressed tar file.
+pub struct TarBundle<R: Read + Seek> {
+    reader: R,
+
+    /// The offset and size of each file in the archive, keyed by path.
+    index: HashMap<String, (u64, u64)>,
+}
+
+impl<R: Read + Seek> TarBundle<R> {
+    /// Create a new tar bundle for a generic readable and seekable stream.
+    ///
+    /// This reads through the whole archive to index its contents.
+    pub fn new(reader: R) -> Result<TarBundle<R>> {
+        let mut archive = Archive::new(reader);
+        let mut index = HashMap::new();
+
+        for entry in archive.entries()? {
+            let entry = entry?;
+
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+
+            let path = entry.path()?;
+            let name = match path.to_str() {
+                Some(s) => s.strip_prefix("./").unwrap_or(s).to_owned(),
+                None => continue,
+            };
+
+            index.insert(name, (entry.raw_file_position(), entry.size()));
+        }
+
+        Ok(TarBundle {
+            reader: archive.into_inner(),
+            index,
+        })
+    }
+}
+
+impl TarBundle<File> {
+    /// Open a file on the filesystem as a tar bundle.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<TarBundle<File>> {
+        Self::new(File::open(path)?)
+    }
+}
+
+impl<R: Read + Seek> IoProvider for TarBundle<R> {
+    fn input_open_name(
+        &mut self,
+        name: &str,
+        _status: &mut dyn StatusBackend,
+    ) -> OpenResult<InputHandle> {
+        let (offset, size) = match self.index.get(name) {
+            Some(info) => *info,
+            None => return OpenResult::NotAvailable,
+        };
+
+        let mut buf = Vec::with_capacity(size as usize);
+
+        if let Err(e) = self.reader.seek(SeekFrom::Start(offset)) {
+            return OpenResult::Err(e.into());
+        }
+
+        if let Err(e) = (&mut self.reader).take(size).read_to_end(&mut buf) {
+            return OpenResult::Err(e.into());
+        }
+
+        OpenResult::Ok(InputHandle::new_read_only(
+            name,
+            Cursor::new(buf),
+            InputOrigin::Other,
+        ))
+    }
+}
+
+impl<R: Read + Seek> Bundle for TarBundle<R> {
+    fn all_files(&self) -> Vec<String> {
+        self.index.keys().cloned().collect()
+    }
+
+    fn get_digest(&mut self) -> Result<digest::DigestData> {
+        let digest_text = match self.input_open_name(digest::DIGEST_NAME, &mut NoopStatusBackend {})
+        {
+            OpenResult::Ok(h) => {
+                let mut text = String::new();
+                h.take(64).read_to_string(&mut text)?;
+                text
+            }
+
+            OpenResult::NotAvailable => {
+                bail!("bundle does not provide needed SHA256SUM file");
+            }
+
+            OpenResult::Err(e) => {
+                return Err(e);
+            }
+        };
+
+        Ok(atry!(digest::DigestData::from_str(&digest_text); ["corrupted SHA256 digest data"]))
+    }
+}
+
+/// Write the named files of a bundle into a new tar bundle.
+///
+/// The files are stored under the names by which they were requested, so
+/// that the resulting [`TarBundle`] will serve the same requests. A
+/// `SHA256SUM` file computed from the names and contents of the files is
+/// added as well. The archive is deterministic: its contents only depend on
+/// those of the files.
+pub fn write_bundle<'a, I, W>(
+    bundle: &mut dyn Bundle,
+    names: I,
+    dest: W,
+    status: &mut dyn StatusBackend,
+) -> Result<()>
+where
+    I: IntoIterator<Item = &'a str>,
+    W: Write,
+{
+    let mut names: Vec<&str> = names
+        .into_iter()
+        .filter(|n| *n != digest::DIGEST_NAME)
+        .collect();
+    names.sort_unstable();
+    names.dedup();
+
+    let mut builder = Builder::new(dest);
+    let mut dc = digest::create();
+
+    for name in names {
+        let mut data = Vec::new();
+
+        match bundle.input_open_name(name, status) {
+            OpenResult::Ok(mut h) => {
+                atry!(h.read_to_end(&mut data); ["failed to read bundle file `{}`", name]);
+            }
+
+            OpenResult::NotAvailable => {
+                bail!("bundle file `{}` is no longer available", name);
+            }
+
+            OpenResult::Err(e) => {
+                return Err(e.context(format!("failed to open bundle file `{name}`")));
+            }
+        }
+
+        dc.update(name.as_bytes());
+        dc.update([0]);
+        dc.update((data.len() as u64).to_le_bytes());
+        dc.update(&data);
+
+        append_file(&mut builder, name, &data)?;
+    }
+
+    let digest_text = format!("{}\n", digest::DigestData::from(dc));
+    append_file(&mut builder, digest::DIGEST_NAME, digest_text.as_bytes())?;
+
+    builder.into_inner()?.flush()?;
+    Ok(())
+}
+
+fn append_file<W: Write>(builder: &mut Builder<W>, name: &str, data: &[u8]) -> Result<()> {
+    let mut header = Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(0);
+    builder.append_data(&mut header, name, data)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dir::DirBundle;
+    use std::fs;
+
+    #[test]
+    fn write_and_read() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("tex/latex")).unwrap();
+        fs::write(dir.path().join("tex/latex/article.cls"), "article").unwrap();
+        fs::write(dir.path().join("plain.tex"), "plain").unwrap();
+        fs::write(dir.path().join("unused.sty"), "unused").unwrap();
+
+        let mut source = DirBundle::new(dir.path());
+        let mut buf = Vec::new();
+        write_bundle(
+            &mut source,
+            ["plain.tex", "article.cls"],
+            &mut buf,
+            &mut NoopStatusBackend {},
+        )
+        .unwrap();
+
+        let mut bundle = TarBundle::new(Cursor::new(buf)).unwrap();
+        let mut files = bundle.all_files();
+        files.sort();
+        assert_eq!(files, ["SHA256SUM", "article.cls", "plain.tex"]);
+
+        let mut text = String::new();
+        match bundle.input_open_name("article.cls", &mut NoopStatusBackend {}) {
+            OpenResult::Ok(mut h) => h.read_to_string(&mut text).unwrap(),
+            _ => panic!("expected to find article.cls"),
+        };
+        assert_eq!(text, "article");
+
+        assert!(bundle.get_digest().is_ok());
+    }
+}
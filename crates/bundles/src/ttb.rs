@@ -68,6 +68,10 @@ impl FileInfo for TTBFileInfo {
     fn path(&self) -> &str {
         &self.path
     }
+
+    fn digest(&self) -> Option<&str> {
+        self.hash.as_deref()
+    }
 }
 
 #[derive(Default, Debug)]
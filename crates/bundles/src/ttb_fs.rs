@@ -7,11 +7,13 @@
 
 use crate::{
     ttb::{TTBFileIndex, TTBFileInfo, TTBv1Header},
+    verify::{self, IndexKey},
     Bundle, FileIndex, FileInfo,
 };
 use flate2::read::GzDecoder;
 use std::{
     convert::TryFrom,
+    fs,
     fs::File,
     io::{Cursor, Read, Seek, SeekFrom},
     path::Path,
@@ -36,6 +38,9 @@ where
 {
     file: File,
     index: T,
+
+    /// If set, the key and detached signature that the index must match.
+    index_signature: Option<(IndexKey, String)>,
 }
 
 /// The internal file-information struct used by the [`TTBFsBundle`].
@@ -45,6 +50,7 @@ impl TTBFsBundle<TTBFileIndex> {
         Ok(TTBFsBundle {
             file,
             index: TTBFileIndex::default(),
+            index_signature: None,
         })
     }
 
@@ -70,7 +76,15 @@ impl TTBFsBundle<TTBFileIndex> {
         };
 
         let mut reader = read_fileinfo(&info, &mut self.file)?;
-        self.index.initialize(&mut reader)?;
+
+        if let Some((key, signature)) = &self.index_signature {
+            let mut index = Vec::new();
+            reader.read_to_end(&mut index)?;
+            key.verify(&index, signature)?;
+            self.index.initialize(&mut Cursor::new(index))?;
+        } else {
+            self.index.initialize(&mut reader)?;
+        }
 
         Ok(())
     }
@@ -79,6 +93,25 @@ impl TTBFsBundle<TTBFileIndex> {
     pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
         Self::new(File::open(path)?)
     }
+
+    /// Open a bundle on the filesystem whose index must be signed by `key`.
+    ///
+    /// The detached signature is read from a file next to the bundle, with
+    /// `.sig` appended to its name.
+    pub fn open_signed<P: AsRef<Path>>(path: P, key: IndexKey) -> Result<Self> {
+        let path = path.as_ref();
+        let mut sig_path = path.as_os_str().to_owned();
+        sig_path.push(".sig");
+
+        let signature = atry!(
+            fs::read_to_string(&sig_path);
+            ["couldn't read the index signature of bundle `{}`", path.display()]
+        );
+
+        let mut bundle = Self::open(path)?;
+        bundle.index_signature = Some((key, signature));
+        Ok(bundle)
+    }
 }
 
 impl IoProvider for TTBFsBundle<TTBFileIndex> {
@@ -110,6 +143,12 @@ impl IoProvider for TTBFsBundle<TTBFileIndex> {
             }
         };
 
+        if let Some(hash) = &info.hash {
+            if let Err(e) = verify::check_file_digest(&info.path, hash, &v) {
+                return OpenResult::Err(e);
+            }
+        }
+
         OpenResult::Ok(InputHandle::new_read_only(
             name,
             Cursor::new(v),
@@ -9,27 +9,45 @@
 //! Instead, wrap it in a [`crate::BundleCache`] for filesystem-backed caching.
 
 use crate::{
+    fetch::{self, RetryPolicy},
     ttb::{TTBFileIndex, TTBFileInfo, TTBv1Header},
-    Bundle, CachableBundle, FileIndex, FileInfo, NET_RETRY_ATTEMPTS, NET_RETRY_SLEEP_MS,
+    verify::{self, IndexKey},
+    Bundle, CachableBundle, FileIndex, FileInfo,
 };
 use flate2::read::GzDecoder;
 use std::{
     convert::TryFrom,
     io::{Cursor, Read},
-    thread,
-    time::Duration,
 };
 use tectonic_errors::prelude::*;
-use tectonic_geturl::{DefaultBackend, DefaultRangeReader, GetUrlBackend, RangeReader};
+use tectonic_geturl::{DefaultBackend, GetUrlBackend, RangeReader};
 use tectonic_io_base::{InputHandle, InputOrigin, IoProvider, OpenResult};
-use tectonic_status_base::{tt_note, tt_warning, StatusBackend};
+use tectonic_status_base::{tt_note, NoopStatusBackend, StatusBackend};
 
-/// Read a [`TTBFileInfo`] from this bundle.
-/// We assume that `fileinfo` points to a valid file in this bundle.
-fn read_fileinfo(fileinfo: &TTBFileInfo, reader: &mut DefaultRangeReader) -> Result<Box<dyn Read>> {
+/// Read a [`TTBFileInfo`] from this bundle, returning its decompressed
+/// contents. We assume that `fileinfo` points to a valid file in this bundle.
+fn read_fileinfo(
+    fileinfo: &TTBFileInfo,
+    reader: &mut impl RangeReader,
+    policy: &RetryPolicy,
+    status: &mut dyn StatusBackend,
+) -> Result<Vec<u8>> {
     // fileinfo.length is a u32, so it must fit inside a usize (assuming 32/64-bit machine).
-    let stream = reader.read_range(fileinfo.start, fileinfo.gzip_len as usize)?;
-    Ok(Box::new(GzDecoder::new(stream)))
+    let gzipped = fetch::fetch_range(
+        reader,
+        fileinfo.start,
+        fileinfo.gzip_len as usize,
+        policy,
+        &fileinfo.name,
+        status,
+    )?;
+
+    let mut data = Vec::with_capacity(fileinfo.real_len as usize);
+    atry!(
+        GzDecoder::new(&gzipped[..]).read_to_end(&mut data);
+        ["couldn't decompress bundle file `{}`", fileinfo.path]
+    );
+    Ok(data)
 }
 
 /// Access ttbv1 bundle hosted on the internet.
@@ -37,16 +55,28 @@ fn read_fileinfo(fileinfo: &TTBFileInfo, reader: &mut DefaultRangeReader) -> Res
 /// are downloaded.
 ///
 /// As such, this bundle should probably be wrapped in a [`crate::BundleCache`].
-pub struct TTBNetBundle<T>
+///
+/// Requests are made with the HTTP backend `B`, which is the default backend
+/// of the `tectonic_geturl` crate unless the bundle is created with
+/// [`TTBNetBundle::with_backend`].
+pub struct TTBNetBundle<T, B = DefaultBackend>
 where
     for<'a> T: FileIndex<'a>,
+    B: GetUrlBackend,
 {
     url: String,
     index: T,
+    backend: B,
+
+    /// If set, the index must have a valid detached signature for this key.
+    index_key: Option<IndexKey>,
 
     // We need the network to load these.
     // They're None until absolutely necessary.
-    reader: Option<DefaultRangeReader>,
+    reader: Option<B::RangeReader>,
+
+    /// How failed downloads are retried.
+    retry_policy: RetryPolicy,
 }
 
 /// The internal file-information struct used by the [`TTBNetBundle`].
@@ -55,19 +85,52 @@ impl TTBNetBundle<TTBFileIndex> {
     /// This method does not require network access.
     /// It will succeed even in we can't connect to the bundle, or if we're given a bad url.
     pub fn new(url: String) -> Result<Self> {
-        Ok(TTBNetBundle {
+        Ok(Self::with_backend(url, DefaultBackend::default()))
+    }
+
+    /// Create a new network bundle whose index must be signed by `key`.
+    ///
+    /// The detached signature is fetched from the bundle URL with `.sig`
+    /// appended. When the bundle is wrapped in a [`crate::BundleCache`], it
+    /// is cached along with the index, and checked whenever the index is used.
+    pub fn new_signed(url: String, key: IndexKey) -> Result<Self> {
+        let mut bundle = Self::new(url)?;
+        bundle.set_index_key(key);
+        Ok(bundle)
+    }
+}
+
+impl<B: GetUrlBackend + 'static> TTBNetBundle<TTBFileIndex, B> {
+    /// Create a new network bundle that makes its requests with `backend`.
+    /// Like [`TTBNetBundle::new`], this does not require network access.
+    pub fn with_backend(url: String, backend: B) -> Self {
+        TTBNetBundle {
             reader: None,
             index: TTBFileIndex::default(),
+            backend,
+            index_key: None,
+            retry_policy: RetryPolicy::from_env(),
             url,
-        })
+        }
+    }
+
+    /// Change how failed downloads are retried. By default, the policy comes
+    /// from [`RetryPolicy::from_env`].
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.retry_policy = policy;
+    }
+
+    /// Require this bundle's index to be signed by `key`, as with
+    /// [`TTBNetBundle::new_signed`].
+    pub fn set_index_key(&mut self, key: IndexKey) {
+        self.index_key = Some(key);
     }
 
     fn connect_reader(&mut self) -> Result<()> {
         if self.reader.is_some() {
             return Ok(());
         }
-        let geturl_backend = DefaultBackend::default();
-        self.reader = Some(geturl_backend.open_range_reader(&self.url));
+        self.reader = Some(self.backend.open_range_reader(&self.url));
         Ok(())
     }
 
@@ -90,12 +153,23 @@ impl TTBNetBundle<TTBFileIndex> {
         }
 
         let mut reader = self.get_index_reader()?;
-        self.index.initialize(&mut reader)?;
+
+        match self.index_key {
+            None => self.index.initialize(&mut reader)?,
+
+            Some(key) => {
+                let mut index = Vec::new();
+                reader.read_to_end(&mut index)?;
+                key.verify(&index, &self.get_index_signature()?)?;
+                self.index.initialize(&mut Cursor::new(index))?;
+            }
+        }
+
         Ok(())
     }
 }
 
-impl IoProvider for TTBNetBundle<TTBFileIndex> {
+impl<B: GetUrlBackend + 'static> IoProvider for TTBNetBundle<TTBFileIndex, B> {
     fn input_open_name(
         &mut self,
         name: &str,
@@ -116,7 +190,7 @@ impl IoProvider for TTBNetBundle<TTBFileIndex> {
     }
 }
 
-impl Bundle for TTBNetBundle<TTBFileIndex> {
+impl<B: GetUrlBackend + 'static> Bundle for TTBNetBundle<TTBFileIndex, B> {
     fn all_files(&self) -> Vec<String> {
         self.index.iter().map(|x| x.path().to_owned()).collect()
     }
@@ -127,7 +201,9 @@ impl Bundle for TTBNetBundle<TTBFileIndex> {
     }
 }
 
-impl CachableBundle<'_, TTBFileIndex> for TTBNetBundle<TTBFileIndex> {
+impl<B: GetUrlBackend + 'static> CachableBundle<'_, TTBFileIndex>
+    for TTBNetBundle<TTBFileIndex, B>
+{
     fn get_location(&mut self) -> String {
         self.url.clone()
     }
@@ -149,17 +225,39 @@ impl CachableBundle<'_, TTBFileIndex> for TTBNetBundle<TTBFileIndex> {
         self.connect_reader()?;
         let header = self.get_header()?;
 
-        read_fileinfo(
+        let index = read_fileinfo(
             &TTBFileInfo {
                 start: header.index_start,
                 gzip_len: header.index_gzip_len,
                 real_len: header.index_real_len,
                 path: "".to_owned(),
-                name: "".to_owned(),
+                name: "bundle index".to_owned(),
                 hash: None,
             },
             self.reader.as_mut().unwrap(),
-        )
+            &self.retry_policy,
+            &mut NoopStatusBackend {},
+        )?;
+
+        Ok(Box::new(Cursor::new(index)))
+    }
+
+    fn index_key(&self) -> Option<IndexKey> {
+        self.index_key
+    }
+
+    fn get_index_signature(&mut self) -> Result<String> {
+        let sig_url = format!("{}.sig", self.url);
+        let mut signature = String::new();
+
+        atry!(
+            self.backend
+                .get_url(&sig_url)?
+                .read_to_string(&mut signature);
+            ["couldn't download the index signature `{}`", sig_url]
+        );
+
+        Ok(signature)
     }
 
     fn open_fileinfo(
@@ -167,7 +265,6 @@ impl CachableBundle<'_, TTBFileIndex> for TTBNetBundle<TTBFileIndex> {
         info: &TTBFileInfo,
         status: &mut dyn StatusBackend,
     ) -> OpenResult<InputHandle> {
-        let mut v: Vec<u8> = Vec::with_capacity(info.real_len as usize);
         tt_note!(status, "downloading {}", info.name);
 
         // Edge case for zero-sized reads
@@ -175,47 +272,33 @@ impl CachableBundle<'_, TTBFileIndex> for TTBNetBundle<TTBFileIndex> {
         if info.gzip_len == 0 {
             return OpenResult::Ok(InputHandle::new_read_only(
                 info.name.to_owned(),
-                Cursor::new(v),
+                Cursor::new(Vec::new()),
                 InputOrigin::Other,
             ));
         }
 
-        // Get file with retries
-        for i in 0..NET_RETRY_ATTEMPTS {
-            let mut reader = match read_fileinfo(info, self.reader.as_mut().unwrap()) {
-                Ok(r) => r,
-                Err(e) => {
-                    tt_warning!(status,
-                        "failure fetching \"{}\" from network ({}/{NET_RETRY_ATTEMPTS})",
-                        info.name, i+1; e
-                    );
-                    thread::sleep(Duration::from_millis(NET_RETRY_SLEEP_MS));
-                    continue;
-                }
-            };
-
-            match reader.read_to_end(&mut v) {
-                Ok(_) => {}
-                Err(e) => {
-                    tt_warning!(status,
-                        "failure downloading \"{}\" from network ({}/{NET_RETRY_ATTEMPTS})",
-                        info.name, i+1; e.into()
-                    );
-                    thread::sleep(Duration::from_millis(NET_RETRY_SLEEP_MS));
-                    continue;
-                }
-            };
+        let v = match read_fileinfo(
+            info,
+            self.reader.as_mut().unwrap(),
+            &self.retry_policy,
+            status,
+        ) {
+            Ok(v) => v,
+            Err(e) => return OpenResult::Err(e),
+        };
 
-            return OpenResult::Ok(InputHandle::new_read_only(
-                info.name.to_owned(),
-                Cursor::new(v),
-                InputOrigin::Other,
-            ));
+        // A corrupted download is not retried, since a mirror serving
+        // bad data will most likely keep doing so.
+        if let Some(hash) = &info.hash {
+            if let Err(e) = verify::check_file_digest(&info.path, hash, &v) {
+                return OpenResult::Err(e);
+            }
         }
 
-        OpenResult::Err(anyhow!(
-            "failed to download \"{}\"; please check your network connection.",
-            info.name
+        OpenResult::Ok(InputHandle::new_read_only(
+            info.name.to_owned(),
+            Cursor::new(v),
+            InputOrigin::Other,
         ))
     }
 }
@@ -0,0 +1,134 @@
+// Copyright 2024 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Integrity checks for bundle contents.
+//!
+//! The index of a ttb bundle records the SHA256 digest of every file in it,
+//! which is checked whenever a file is read out of the bundle. The index
+//! itself may be signed with an Ed25519 key: if an [`IndexKey`] is provided
+//! when the bundle is opened, the index is only accepted if a detached
+//! signature, stored next to the bundle with a `.sig` extension appended to
+//! its name, is valid for it.
+
+use ed25519_dalek::{Signature, VerifyingKey};
+use std::{fmt, str::FromStr};
+use tectonic_errors::prelude::*;
+use tectonic_io_base::digest::{self, Digest, DigestData};
+
+/// Check that the contents of a bundle file match the digest recorded for it
+/// in the bundle index.
+pub fn check_file_digest(name: &str, expected: &str, data: &[u8]) -> Result<()> {
+    let expected = atry!(
+        DigestData::from_str(expected);
+        ["the bundle index has a malformed digest for file `{}`", name]
+    );
+
+    let mut dc = digest::create();
+    dc.update(data);
+    let actual = DigestData::from(dc);
+
+    if actual != expected {
+        bail!(
+            "bundle file `{}` is corrupt: its SHA256 digest is {}, but the bundle index says \
+             it should be {}; the bundle or the server hosting it may be damaged",
+            name,
+            actual,
+            expected
+        );
+    }
+
+    Ok(())
+}
+
+/// A public key used to check the signature of a bundle index.
+///
+/// Keys are written as 64 hexadecimal digits.
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub struct IndexKey(VerifyingKey);
+
+impl IndexKey {
+    /// Check a detached signature, written as 128 hexadecimal digits, of the
+    /// uncompressed bundle index `index`.
+    pub fn verify(&self, index: &[u8], signature_text: &str) -> Result<()> {
+        let mut bytes = [0u8; 64];
+        atry!(
+            digest::hex_to_bytes(signature_text.trim(), &mut bytes);
+            ["malformed bundle index signature"]
+        );
+
+        if self
+            .0
+            .verify_strict(index, &Signature::from_bytes(&bytes))
+            .is_err()
+        {
+            bail!(
+                "the signature of the bundle index is not valid for the key {}; \
+                 the bundle may have been tampered with",
+                self
+            );
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for IndexKey {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut bytes = [0u8; 32];
+        atry!(
+            digest::hex_to_bytes(s.trim(), &mut bytes);
+            ["malformed bundle index key `{}`", s]
+        );
+        Ok(IndexKey(atry!(
+            VerifyingKey::from_bytes(&bytes);
+            ["invalid bundle index key `{}`", s]
+        )))
+    }
+}
+
+impl fmt::Display for IndexKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&digest::bytes_to_hex(self.0.as_bytes()))
+    }
+}
+
+impl fmt::Debug for IndexKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "IndexKey({self})")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    #[test]
+    fn file_digests() {
+        let mut dc = digest::create();
+        dc.update(b"hello");
+        let good = DigestData::from(dc).to_string();
+
+        assert!(check_file_digest("a.sty", &good, b"hello").is_ok());
+        assert!(check_file_digest("a.sty", &good, b"hellp").is_err());
+        assert!(check_file_digest("a.sty", "nonsense", b"hello").is_err());
+    }
+
+    #[test]
+    fn index_signatures() {
+        let signer = SigningKey::from_bytes(&[7; 32]);
+        let key: IndexKey = digest::bytes_to_hex(signer.verifying_key().as_bytes())
+            .parse()
+            .unwrap();
+        let signature = digest::bytes_to_hex(&signer.sign(b"[FILELIST]\n").to_bytes());
+
+        assert!(key
+            .verify(b"[FILELIST]\n", &format!("{signature}\n"))
+            .is_ok());
+        assert!(key.verify(b"[FILELIST]\nevil\n", &signature).is_err());
+        assert!(key.verify(b"[FILELIST]\n", "00").is_err());
+        assert!("zz".parse::<IndexKey>().is_err());
+    }
+}
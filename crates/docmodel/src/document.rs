@@ -12,7 +12,7 @@
 //! processing, in the `tectonic::docmodel` module.
 
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap, HashSet},
     env, fs,
     io::{Read, Write},
     path::{Component, Path, PathBuf},
@@ -62,6 +62,10 @@ pub struct Document {
     /// which may be read by external tools.
     pub metadata: Option<toml::Value>,
 
+    /// Descriptive information about the document, such as its title, that
+    /// is recorded in its outputs.
+    pub info: DocumentInfo,
+
     /// The document name. This will be used to name build artifacts and the
     /// like, and so should be relatively filesystem-friendly. It does not
     /// need to be the same as the document title.
@@ -71,15 +75,38 @@ pub struct Document {
     /// Either a URL or a local path.
     pub bundle_loc: String,
 
+    /// Further bundles that are layered on top of [`Self::bundle_loc`], in
+    /// order of decreasing priority: files in earlier bundles shadow those
+    /// in later ones. Local paths may be relative to src_dir.
+    pub bundle_overlays: Vec<String>,
+
+    /// The hex-encoded Ed25519 public key that the index of the
+    /// [`Self::bundle_loc`] bundle must be signed with, if any.
+    pub bundle_key: Option<String>,
+
     /// Extra local search paths for this document.
     /// May be absolute or relative to src_dir.
     pub extra_paths: Vec<PathBuf>,
 
+    /// The programs that shell-escape commands are allowed to run, if they're
+    /// restricted. If this is set, outputs that activate shell-escape only run
+    /// commands whose program is named here, and run them without a shell.
+    /// Otherwise, shell-escape commands are unrestricted.
+    pub shell_escape_allow: Option<Vec<String>>,
+
     /// The different outputs that are created from the document source. These
     /// may have different formats (e.g., PDF and HTML) or the same format but
     /// different settings (e.g., PDF with A4 paper and PDF with US Letter
     /// paper).
     pub outputs: HashMap<String, OutputProfile>,
+
+    /// Named sets of overrides for the output settings, which can be selected
+    /// when building the document (e.g., a "draft" profile that turns on a
+    /// draft-mode variable).
+    pub profiles: HashMap<String, BuildProfile>,
+
+    /// External programs that are run as part of each build of the document.
+    pub hooks: Vec<ToolHook>,
 }
 
 impl Document {
@@ -102,6 +129,70 @@ impl Document {
 
         for toml_output in &doc.outputs {
             let output: OutputProfile = toml_output.into();
+            check_variable_names(&output.variables, "output", &output.name)?;
+
+            if let Some(f) = output
+                .font_features
+                .iter()
+                .find(|f| f.is_empty() || f.contains([':', ';', ',']))
+            {
+                bail!(
+                    "invalid font feature `{}` for output `{}`; give one feature per item",
+                    f,
+                    output.name
+                );
+            }
+
+            if let Some(level) = output.split_level {
+                if !(1..=6).contains(&level) {
+                    bail!(
+                        "the `split_level` of output `{}` must be between 1 and 6; got {}",
+                        output.name,
+                        level
+                    );
+                }
+            }
+
+            let mut attachment_names = HashSet::new();
+
+            for path in &output.attachments {
+                match path.file_name() {
+                    Some(name) if attachment_names.insert(name) => {}
+                    Some(name) => bail!(
+                        "output `{}` has more than one attachment named `{}`",
+                        output.name,
+                        name.to_string_lossy()
+                    ),
+                    None => bail!(
+                        "invalid attachment `{}` for output `{}`",
+                        path.display(),
+                        output.name
+                    ),
+                }
+            }
+
+            if output.pdfa && !output.attachments.is_empty() {
+                bail!(
+                    "output `{}` can't both have attachments and conform to PDF/A",
+                    output.name
+                );
+            }
+
+            if let Some(enc) = &output.encryption {
+                if enc.owner_password.is_empty() {
+                    bail!(
+                        "the `owner_password` of output `{}` must not be empty",
+                        output.name
+                    );
+                }
+
+                if output.pdfa {
+                    bail!(
+                        "output `{}` can't be both encrypted and conform to PDF/A",
+                        output.name
+                    );
+                }
+            }
 
             if outputs.insert(output.name.clone(), output).is_some() {
                 bail!(
@@ -115,17 +206,99 @@ impl Document {
             bail!("TOML specification must define at least one output");
         }
 
+        let mut profiles = HashMap::new();
+
+        for (name, toml_profile) in doc.profiles.iter().flatten() {
+            let profile: BuildProfile = toml_profile.into();
+            check_variable_names(&profile.variables, "profile", name)?;
+            profiles.insert(name.clone(), profile);
+        }
+
+        let mut hooks = Vec::new();
+
+        for toml_hook in doc.hooks.iter().flatten() {
+            let hook: ToolHook = toml_hook.into();
+            hook.check()?;
+            hooks.push(hook);
+        }
+
+        for program in doc.doc.shell_escape_allow.iter().flatten() {
+            if program.is_empty() || program.contains(['/', '\\']) {
+                bail!(
+                    "`shell_escape_allow` entries must be program names, not paths; got `{}`",
+                    program
+                );
+            }
+        }
+
+        let (bundle_overlays, bundle_loc) = match doc.doc.bundle {
+            syntax::TomlBundleLoc::One(loc) => (Vec::new(), loc),
+            syntax::TomlBundleLoc::Stack(mut locs) => match locs.pop() {
+                Some(loc) => (locs, loc),
+                None => bail!("the `bundle` list in `Tectonic.toml` must not be empty"),
+            },
+        };
+
         Ok(Document {
             src_dir: src_dir.into(),
             build_dir: build_dir.into(),
             name: doc.doc.name,
-            bundle_loc: doc.doc.bundle,
+            bundle_loc,
+            bundle_overlays,
+            bundle_key: doc.doc.bundle_key,
             extra_paths: doc.doc.extra_paths.unwrap_or_default(),
+            shell_escape_allow: doc.doc.shell_escape_allow,
             metadata: doc.doc.metadata,
+            info: doc
+                .doc
+                .info
+                .as_ref()
+                .map(DocumentInfo::from)
+                .unwrap_or_default(),
             outputs,
+            profiles,
+            hooks,
         })
     }
 
+    /// Apply one of the document's build profiles to all of its outputs.
+    ///
+    /// Settings given in the profile replace those of the outputs, except for
+    /// its variables, which are merged into each output's variables.
+    pub fn apply_profile(&mut self, name: &str) -> Result<()> {
+        let profile = match self.profiles.get(name) {
+            Some(p) => p,
+            None => bail!("no build profile named `{}` in `Tectonic.toml`", name),
+        };
+
+        for output in self.outputs.values_mut() {
+            if let Some(t) = profile.target_type {
+                output.target_type = t;
+            }
+
+            if let Some(f) = &profile.tex_format {
+                output.tex_format.clone_from(f);
+            }
+
+            if let Some(b) = profile.shell_escape {
+                output.shell_escape = b;
+            }
+
+            if let Some(b) = profile.synctex {
+                output.synctex = b;
+            }
+
+            output.variables.extend(
+                profile
+                    .variables
+                    .iter()
+                    .map(|(k, v)| (k.clone(), v.clone())),
+            );
+        }
+
+        Ok(())
+    }
+
     /// Write out this document's state as a fresh `Tectonic.toml` file in the
     /// document’s [`Self::src_dir`].
     ///
@@ -148,11 +321,26 @@ impl Document {
         let doc = syntax::TomlDocument {
             doc: syntax::TomlDocSection {
                 name: self.name.clone(),
-                bundle: self.bundle_loc.clone(),
+                bundle: if self.bundle_overlays.is_empty() {
+                    syntax::TomlBundleLoc::One(self.bundle_loc.clone())
+                } else {
+                    let mut locs = self.bundle_overlays.clone();
+                    locs.push(self.bundle_loc.clone());
+                    syntax::TomlBundleLoc::Stack(locs)
+                },
+                bundle_key: self.bundle_key.clone(),
                 extra_paths,
+                shell_escape_allow: self.shell_escape_allow.clone(),
                 metadata: None,
+                info: if self.info == DocumentInfo::default() {
+                    None
+                } else {
+                    Some(syntax::TomlDocInfo::from(&self.info))
+                },
             },
             outputs,
+            profiles: None,
+            hooks: None,
         };
 
         let toml_text = toml::to_string_pretty(&doc)?;
@@ -171,6 +359,53 @@ impl Document {
         Ok(())
     }
 
+    /// Change the bundle of this document to the one at `loc`, updating its
+    /// `Tectonic.toml` file.
+    ///
+    /// Only the `bundle` setting of the `[doc]` section is rewritten, so that
+    /// the rest of the user's file structure, comments, etc. are preserved. Any
+    /// [`Self::bundle_overlays`] and [`Self::bundle_key`] are dropped.
+    pub fn pin_bundle(&mut self, loc: String) -> Result<()> {
+        let toml_path = self.src_dir.join("Tectonic.toml");
+        let text = atry!(
+            fs::read_to_string(&toml_path);
+            ["couldn\'t read `{}`", toml_path.display()]
+        );
+
+        let mut toml_doc = atry!(
+            text.parse::<toml_edit::DocumentMut>();
+            ["couldn\'t parse `{}`", toml_path.display()]
+        );
+
+        let doc_table = toml_doc
+            .get_mut("doc")
+            .and_then(|item| item.as_table_like_mut());
+
+        let Some(doc_table) = doc_table.filter(|t| t.contains_key("bundle")) else {
+            bail!(
+                "couldn\'t find the `bundle` setting in `{}`",
+                toml_path.display()
+            );
+        };
+
+        // The key of the old bundle won't apply to the new one.
+        doc_table.remove("bundle_key");
+
+        if let Some(bundle) = doc_table.get_mut("bundle") {
+            *bundle = toml_edit::value(loc.as_str());
+        }
+
+        atry!(
+            fs::write(&toml_path, toml_doc.to_string());
+            ["couldn\'t write `{}`", toml_path.display()]
+        );
+
+        self.bundle_loc = loc;
+        self.bundle_overlays.clear();
+        self.bundle_key = None;
+        Ok(())
+    }
+
     /// Get this document's toplevel source directory.
     ///
     /// Note that this directory is the one containing the file `Tectonic.toml`.
@@ -219,6 +454,189 @@ impl Document {
 
         p
     }
+
+    /// Expand the patterns in a list of inputs.
+    ///
+    /// A file input containing `*` or `?` is a pattern. It is replaced with
+    /// the files in the document’s `src` directory that it matches, sorted by
+    /// name. `*` matches any run of characters other than `/`, and `?` matches
+    /// any one of them. Wildcards don't match names starting with a `.` unless
+    /// the pattern does.
+    ///
+    /// A file that an earlier input has already named is skipped when a pattern
+    /// matches it, so that an input list like `["intro.tex", "*.tex"]` works as
+    /// expected. It's an error for a pattern to match nothing.
+    pub fn expand_inputs(&self, inputs: &[InputFile]) -> Result<Vec<InputFile>> {
+        let tex_dir = self.src_dir.join("src");
+        let mut expanded = Vec::with_capacity(inputs.len());
+        let mut seen = HashSet::new();
+
+        for input in inputs {
+            let pattern = match input {
+                InputFile::File(f) if f.contains(['*', '?']) => f,
+                InputFile::File(f) => {
+                    seen.insert(f.clone());
+                    expanded.push(input.clone());
+                    continue;
+                }
+                InputFile::Inline(_) => {
+                    expanded.push(input.clone());
+                    continue;
+                }
+            };
+
+            let mut matches = Vec::new();
+            let components: Vec<&str> = pattern.split('/').collect();
+            expand_pattern(&tex_dir, "", &components, &mut matches)?;
+
+            if matches.is_empty() {
+                bail!(
+                    "input pattern `{}` does not match any files in `{}`",
+                    pattern,
+                    tex_dir.display()
+                );
+            }
+
+            matches.sort();
+
+            for m in matches {
+                if seen.insert(m.clone()) {
+                    expanded.push(InputFile::File(m));
+                }
+            }
+        }
+
+        Ok(expanded)
+    }
+}
+
+/// Find the files under `root` matching a pattern, given as its `/`-separated
+/// components. `prefix` is the part of the path that has been matched so far.
+fn expand_pattern(
+    root: &Path,
+    prefix: &str,
+    components: &[&str],
+    matches: &mut Vec<String>,
+) -> Result<()> {
+    let (component, rest) = match components.split_first() {
+        Some(c) => c,
+        None => return Ok(()),
+    };
+
+    let join = |name: &str| {
+        if prefix.is_empty() {
+            name.to_owned()
+        } else {
+            format!("{prefix}/{name}")
+        }
+    };
+
+    if !component.contains(['*', '?']) {
+        let path = join(component);
+        let full = root.join(&path);
+
+        if rest.is_empty() {
+            if full.is_file() {
+                matches.push(path);
+            }
+        } else if full.is_dir() {
+            expand_pattern(root, &path, rest, matches)?;
+        }
+
+        return Ok(());
+    }
+
+    let dir = root.join(prefix);
+
+    if !dir.is_dir() {
+        return Ok(());
+    }
+
+    let entries = atry!(
+        fs::read_dir(&dir);
+        ["couldn\'t read directory `{}`", dir.display()]
+    );
+
+    for entry in entries {
+        let entry = entry?;
+
+        let name = match entry.file_name().into_string() {
+            Ok(n) => n,
+            Err(_) => continue,
+        };
+
+        if name.starts_with('.') && !component.starts_with('.') {
+            continue;
+        }
+
+        if !wildcard_match(component, &name) {
+            continue;
+        }
+
+        let path = join(&name);
+
+        if rest.is_empty() {
+            if root.join(&path).is_file() {
+                matches.push(path);
+            }
+        } else if root.join(&path).is_dir() {
+            expand_pattern(root, &path, rest, matches)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Check whether a name matches a pattern in which `*` matches any run of
+/// characters and `?` matches any single character.
+pub fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+
+    // Where to resume after the most recent `*`, if matching fails.
+    let mut backtrack = None;
+    let (mut p, mut n) = (0, 0);
+
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = backtrack {
+            backtrack = Some((star_p, star_n + 1));
+            p = star_p + 1;
+            n = star_n + 1;
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Descriptive information about a document.
+///
+/// When building PDF outputs, this is recorded in the PDF’s document
+/// information dictionary, unless the document sets the same information
+/// itself.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct DocumentInfo {
+    /// The document title.
+    pub title: Option<String>,
+
+    /// The name of the person or people who wrote the document.
+    pub author: Option<String>,
+
+    /// The subject of the document.
+    pub subject: Option<String>,
+
+    /// Keywords associated with the document.
+    pub keywords: Vec<String>,
+
+    /// The natural language of the document, as a language tag like `en-US`.
+    pub language: Option<String>,
 }
 
 /// Persistent settings for a document build.
@@ -256,6 +674,228 @@ pub struct OutputProfile {
     ///
     /// Default is false.
     pub synctex: bool,
+
+    /// Whether the TeX engine may load fonts installed on the host system by
+    /// name. Turning this off limits documents to the fonts in the bundle and
+    /// the document's own files, so that the output doesn't depend on the
+    /// machine that builds it.
+    ///
+    /// Default is true.
+    pub system_fonts: bool,
+
+    /// OpenType features to apply to the fonts that the document loads, in
+    /// the syntax of XeTeX font names: `+smcp` turns on small capitals,
+    /// `-liga` turns off ligatures, and `script=latn` and `language=DEU`
+    /// choose the script and language whose rules are used. The features
+    /// that the document gives for a font take precedence.
+    pub font_features: Vec<String>,
+
+    /// Whether PDF outputs should conform to the PDF/A-2b archival standard.
+    ///
+    /// Default is false.
+    pub pdfa: bool,
+
+    /// Files to attach to PDF outputs, such as a source archive or the data
+    /// behind the document's figures. PDF viewers list them under their file
+    /// names.
+    ///
+    /// The paths may be absolute or relative to the document’s source
+    /// directory.
+    pub attachments: Vec<PathBuf>,
+
+    /// Whether HTML outputs should express math as MathML rather than as
+    /// absolutely positioned glyphs.
+    ///
+    /// Default is false.
+    pub mathml: bool,
+
+    /// Whether to optimize the assets of HTML outputs for publication:
+    /// subsetting fonts and converting them to WOFF2, and giving assets names
+    /// that include hashes of their contents.
+    ///
+    /// Default is false.
+    pub optimize_assets: bool,
+
+    /// The heading level at which HTML outputs are split into several linked
+    /// pages: 1 splits them at `<h1>` headings, 2 at `<h1>` and `<h2>`
+    /// headings, and so on, up to 6.
+    ///
+    /// Default is None, meaning that outputs aren't split.
+    pub split_level: Option<u8>,
+
+    /// Tera templates for HTML outputs that take the place of the document's
+    /// templates with the same names, mapping template names to files.
+    ///
+    /// The paths may be absolute or relative to the document’s source
+    /// directory.
+    pub html_templates: BTreeMap<String, PathBuf>,
+
+    /// TeX macros to define before the inputs are read, mapping macro names
+    /// to their replacement text.
+    ///
+    /// The names must consist only of ASCII letters, so that they're valid
+    /// TeX control sequence names.
+    pub variables: BTreeMap<String, String>,
+
+    /// Settings for running BibTeX.
+    pub bibtex: BibtexSettings,
+
+    /// Settings for the raster images embedded in PDF outputs.
+    pub images: ImageSettings,
+
+    /// How PDF outputs are encrypted.
+    ///
+    /// Default is None, meaning that they aren't encrypted.
+    pub encryption: Option<EncryptionSettings>,
+}
+
+/// Settings for running BibTeX when building an output.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct BibtexSettings {
+    /// The name of a bibliography style to use instead of the one chosen by
+    /// the document's `\bibliographystyle` command.
+    pub style: Option<String>,
+
+    /// The BibTeX `min_crossrefs` parameter: how many times an entry needs to
+    /// be cross-referenced before it is included in the bibliography on its
+    /// own. If unset, BibTeX's default of 2 is used.
+    pub min_crossrefs: Option<u32>,
+
+    /// Whether BibTeX warnings and errors should make the build fail.
+    pub warnings_as_errors: bool,
+
+    /// Extra directories to search for `.bib` files, before the usual
+    /// locations. These may be absolute or relative to the document’s source
+    /// directory.
+    pub search_paths: Vec<PathBuf>,
+}
+
+/// Settings for the raster images embedded in a PDF output.
+///
+/// These make it possible to build both a small copy of a document, for
+/// review, and a full-quality one, for print, from the same source.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ImageSettings {
+    /// The maximum resolution of embedded images, in dots per inch. Images
+    /// placed at a higher resolution are downsampled. If unset, images keep
+    /// their original resolution.
+    pub max_dpi: Option<u32>,
+
+    /// The quality, from 1 to 100, at which JPEG images are re-encoded. If
+    /// unset, JPEGs are only re-encoded when they must be downsampled or
+    /// converted to grayscale.
+    pub jpeg_quality: Option<u8>,
+
+    /// Whether color images should be converted to grayscale.
+    pub grayscale: bool,
+}
+
+/// Settings for encrypting a PDF output, as for review copies that shouldn't
+/// be printed or copied from.
+///
+/// The restrictions are honored by PDF viewers, but they don't stop anyone
+/// who has the owner password from lifting them.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EncryptionSettings {
+    /// The password that lifts the restrictions on the document. It may not
+    /// be empty.
+    pub owner_password: String,
+
+    /// The password needed to open the document. If it's empty, anyone can
+    /// open the document, subject to the restrictions.
+    pub user_password: String,
+
+    /// Whether the document may be printed without the owner password.
+    pub allow_printing: bool,
+
+    /// Whether text and graphics may be copied out of the document without
+    /// the owner password.
+    pub allow_copying: bool,
+}
+
+/// A named set of overrides for the settings of a document's outputs.
+///
+/// See [`Document::apply_profile`].
+#[derive(Clone, Debug, Default)]
+pub struct BuildProfile {
+    /// If set, the type of output to create instead.
+    pub target_type: Option<BuildTargetType>,
+
+    /// If set, the TeX format to use instead.
+    pub tex_format: Option<String>,
+
+    /// If set, whether shell-escape should be activated instead.
+    pub shell_escape: Option<bool>,
+
+    /// If set, whether synctex should be activated instead.
+    pub synctex: Option<bool>,
+
+    /// Variables to define in addition to, or instead of, the outputs' own.
+    pub variables: BTreeMap<String, String>,
+}
+
+/// An external program that is run as part of each build of a document.
+///
+/// The program runs in a temporary directory. The files that it needs are
+/// copied there first, and the files that it creates are treated like the
+/// files that the TeX engine creates: they can be read by later passes, and are
+/// saved in the build directory if they're final outputs.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ToolHook {
+    /// When the program is run.
+    pub stage: HookStage,
+
+    /// The program, followed by its arguments.
+    pub command: Vec<String>,
+
+    /// The files that the program needs, relative to the document’s `src`
+    /// directory or the build.
+    pub inputs: Vec<String>,
+
+    /// The files or directories that the program creates. If empty, all of the
+    /// files that it creates at the top level of its working directory are
+    /// used.
+    pub outputs: Vec<String>,
+
+    /// If set, the name of a file to save the program's standard output to.
+    pub stdout: Option<String>,
+}
+
+impl ToolHook {
+    /// Check that the hook names a program, and that its files are all inside
+    /// its working directory.
+    fn check(&self) -> Result<()> {
+        let program = match self.command.first() {
+            Some(p) => p,
+            None => bail!("the `command` of a hook must name a program to run"),
+        };
+
+        for name in self.inputs.iter().chain(&self.outputs).chain(&self.stdout) {
+            let path = Path::new(name);
+
+            if path.is_absolute() || path.components().any(|c| c == Component::ParentDir) {
+                bail!(
+                    "the files of the `{}` hook must be relative paths without `..`; got `{}`",
+                    program,
+                    name
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// When a [`ToolHook`] is run.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum HookStage {
+    /// Before the first TeX pass. This is for tools that prepare inputs for
+    /// the document, like `latexdiff`.
+    Pre,
+
+    /// After the first TeX pass, which is then followed by another. This is
+    /// for tools that process files written by TeX, like `pythontex`.
+    Post,
 }
 
 /// The output target type of a document build.
@@ -327,13 +967,40 @@ impl Document {
             build_dir,
             name,
             bundle_loc,
+            bundle_overlays: Vec::new(),
+            bundle_key: None,
             extra_paths,
+            shell_escape_allow: None,
             outputs: crate::document::default_outputs(),
+            profiles: HashMap::new(),
+            hooks: Vec::new(),
             metadata: None,
+            info: DocumentInfo::default(),
         })
     }
 }
 
+/// Check that the names of some variables are valid TeX macro names.
+fn check_variable_names(
+    variables: &BTreeMap<String, String>,
+    owner_kind: &str,
+    owner_name: &str,
+) -> Result<()> {
+    for name in variables.keys() {
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphabetic()) {
+            bail!(
+                "variable name `{}` in {} `{}` is not a valid TeX macro name; \
+                 it may only contain ASCII letters",
+                name,
+                owner_kind,
+                owner_name
+            );
+        }
+    }
+
+    Ok(())
+}
+
 pub(crate) fn default_outputs() -> HashMap<String, OutputProfile> {
     let mut outputs = HashMap::new();
     outputs.insert(
@@ -349,6 +1016,18 @@ pub(crate) fn default_outputs() -> HashMap<String, OutputProfile> {
             shell_escape: false,
             shell_escape_cwd: None,
             synctex: false,
+            system_fonts: true,
+            font_features: Vec::new(),
+            pdfa: false,
+            attachments: Vec::new(),
+            mathml: false,
+            optimize_assets: false,
+            split_level: None,
+            html_templates: BTreeMap::new(),
+            variables: BTreeMap::new(),
+            bibtex: BibtexSettings::default(),
+            images: ImageSettings::default(),
+            encryption: None,
         },
     );
     outputs
@@ -383,6 +1062,27 @@ mod tests {
         );
     }
 
+    #[test]
+    fn bundle_overlays() {
+        const TOML: &str = r#"
+        [doc]
+        name = "test"
+        bundle = ["patches", "more-patches", "na"]
+
+        [[output]]
+        name = "o"
+        type = "pdf"
+        "#;
+
+        let mut c = Cursor::new(TOML.as_bytes());
+        let doc = Document::new_from_toml(".", ".", &mut c).unwrap();
+        assert_eq!(doc.bundle_loc, "na");
+        assert_eq!(doc.bundle_overlays, ["patches", "more-patches"]);
+
+        let mut c = Cursor::new(TOML.replace(r#"["patches", "more-patches", "na"]"#, "[]"));
+        assert!(Document::new_from_toml(".", ".", &mut c).is_err());
+    }
+
     #[test]
     fn shell_escape_default_false() {
         const TOML: &str = r#"
@@ -418,6 +1118,31 @@ mod tests {
         assert!(doc.outputs.get("o").unwrap().shell_escape);
     }
 
+    #[test]
+    fn shell_escape_allow() {
+        const TOML: &str = r#"
+        [doc]
+        name = "test"
+        bundle = "na"
+        shell_escape_allow = ["pygmentize", "gnuplot"]
+
+        [[output]]
+        name = "o"
+        type = "pdf"
+        shell_escape = true
+        "#;
+
+        let mut c = Cursor::new(TOML.as_bytes());
+        let doc = Document::new_from_toml(".", ".", &mut c).unwrap();
+        assert_eq!(
+            doc.shell_escape_allow.as_deref(),
+            Some(&["pygmentize".to_owned(), "gnuplot".to_owned()][..])
+        );
+
+        let mut c = Cursor::new(TOML.replace("\"gnuplot\"", "\"/bin/sh\""));
+        assert!(Document::new_from_toml(".", ".", &mut c).is_err());
+    }
+
     #[test]
     fn synctex_default_false() {
         const TOML: &str = r#"
@@ -450,4 +1175,598 @@ mod tests {
         let doc = Document::new_from_toml(".", ".", &mut c).unwrap();
         assert!(doc.outputs.get("o").unwrap().synctex);
     }
+
+    #[test]
+    fn system_fonts() {
+        const TOML: &str = r#"
+        [doc]
+        name = "test"
+        bundle = "na"
+
+        [[output]]
+        name = "o"
+        type = "pdf"
+
+        [[output]]
+        name = "reproducible"
+        type = "pdf"
+        system_fonts = false
+        "#;
+        let mut c = Cursor::new(TOML.as_bytes());
+        let doc = Document::new_from_toml(".", ".", &mut c).unwrap();
+        assert!(doc.outputs.get("o").unwrap().system_fonts);
+        assert!(!doc.outputs.get("reproducible").unwrap().system_fonts);
+
+        // Only the non-default setting is written out.
+        let profile = |name| {
+            toml::to_string(&syntax::TomlOutputProfile::from(
+                doc.outputs.get(name).unwrap(),
+            ))
+            .unwrap()
+        };
+        assert!(!profile("o").contains("system_fonts"));
+        assert!(profile("reproducible").contains("system_fonts = false"));
+    }
+
+    #[test]
+    fn pdfa() {
+        const TOML: &str = r#"
+        [doc]
+        name = "test"
+        bundle = "na"
+
+        [[output]]
+        name = "o"
+        type = "pdf"
+
+        [[output]]
+        name = "archival"
+        type = "pdf"
+        pdfa = true
+        "#;
+        let mut c = Cursor::new(TOML.as_bytes());
+        let doc = Document::new_from_toml(".", ".", &mut c).unwrap();
+        assert!(!doc.outputs.get("o").unwrap().pdfa);
+        assert!(doc.outputs.get("archival").unwrap().pdfa);
+    }
+
+    #[test]
+    fn mathml() {
+        const TOML: &str = r#"
+        [doc]
+        name = "test"
+        bundle = "na"
+
+        [[output]]
+        name = "o"
+        type = "html"
+
+        [[output]]
+        name = "accessible"
+        type = "html"
+        mathml = true
+        "#;
+        let mut c = Cursor::new(TOML.as_bytes());
+        let doc = Document::new_from_toml(".", ".", &mut c).unwrap();
+        assert!(!doc.outputs.get("o").unwrap().mathml);
+        assert!(doc.outputs.get("accessible").unwrap().mathml);
+    }
+
+    #[test]
+    fn font_features() {
+        const TOML: &str = r#"
+        [doc]
+        name = "test"
+        bundle = "na"
+
+        [[output]]
+        name = "o"
+        type = "pdf"
+        font_features = ["+smcp", "+onum", "language=DEU"]
+        "#;
+        let mut c = Cursor::new(TOML.as_bytes());
+        let doc = Document::new_from_toml(".", ".", &mut c).unwrap();
+        assert_eq!(
+            doc.outputs.get("o").unwrap().font_features,
+            ["+smcp", "+onum", "language=DEU"]
+        );
+
+        let toml = TOML.replace(r#""+onum", "language=DEU""#, r#""+onum;language=DEU""#);
+        let mut c = Cursor::new(toml.as_bytes());
+        assert!(Document::new_from_toml(".", ".", &mut c).is_err());
+    }
+
+    #[test]
+    fn split_level() {
+        const TOML: &str = r#"
+        [doc]
+        name = "test"
+        bundle = "na"
+
+        [[output]]
+        name = "o"
+        type = "html"
+
+        [[output]]
+        name = "book"
+        type = "html"
+        split_level = 2
+        "#;
+        let mut c = Cursor::new(TOML.as_bytes());
+        let doc = Document::new_from_toml(".", ".", &mut c).unwrap();
+        assert_eq!(doc.outputs.get("o").unwrap().split_level, None);
+        assert_eq!(doc.outputs.get("book").unwrap().split_level, Some(2));
+
+        let toml = TOML.replace("split_level = 2", "split_level = 7");
+        let mut c = Cursor::new(toml.as_bytes());
+        assert!(Document::new_from_toml(".", ".", &mut c).is_err());
+    }
+
+    #[test]
+    fn optimize_assets() {
+        const TOML: &str = r#"
+        [doc]
+        name = "test"
+        bundle = "na"
+
+        [[output]]
+        name = "o"
+        type = "html"
+
+        [[output]]
+        name = "web"
+        type = "html"
+        optimize_assets = true
+        "#;
+        let mut c = Cursor::new(TOML.as_bytes());
+        let doc = Document::new_from_toml(".", ".", &mut c).unwrap();
+        assert!(!doc.outputs.get("o").unwrap().optimize_assets);
+        assert!(doc.outputs.get("web").unwrap().optimize_assets);
+    }
+
+    #[test]
+    fn html_templates() {
+        const TOML: &str = r#"
+        [doc]
+        name = "test"
+        bundle = "na"
+
+        [[output]]
+        name = "o"
+        type = "html"
+
+        [output.html_templates]
+        "head.html" = "templates/head.html"
+        "#;
+
+        let mut c = Cursor::new(TOML.as_bytes());
+        let doc = Document::new_from_toml(".", ".", &mut c).unwrap();
+        let templates = &doc.outputs.get("o").unwrap().html_templates;
+        assert_eq!(templates.len(), 1);
+        assert_eq!(templates["head.html"], PathBuf::from("templates/head.html"));
+    }
+
+    #[test]
+    fn document_info() {
+        const TOML: &str = r#"
+        [doc]
+        name = "test"
+        bundle = "na"
+
+        [doc.info]
+        title = "A Title"
+        keywords = ["one", "two"]
+        language = "en-GB"
+
+        [[output]]
+        name = "o"
+        type = "pdf"
+        "#;
+
+        let mut c = Cursor::new(TOML.as_bytes());
+        let doc = Document::new_from_toml(".", ".", &mut c).unwrap();
+        assert_eq!(
+            doc.info,
+            DocumentInfo {
+                title: Some("A Title".to_owned()),
+                author: None,
+                subject: None,
+                keywords: vec!["one".to_owned(), "two".to_owned()],
+                language: Some("en-GB".to_owned()),
+            }
+        );
+    }
+
+    #[test]
+    fn variables() {
+        const TOML: &str = r#"
+        [doc]
+        name = "test"
+        bundle = "na"
+
+        [[output]]
+        name = "o"
+        type = "pdf"
+
+        [output.variables]
+        draft = true
+        version = 3
+        title = "A \\emph{Title}"
+        "#;
+
+        let mut c = Cursor::new(TOML.as_bytes());
+        let doc = Document::new_from_toml(".", ".", &mut c).unwrap();
+        let variables = &doc.outputs.get("o").unwrap().variables;
+        assert_eq!(variables["draft"], "true");
+        assert_eq!(variables["version"], "3");
+        assert_eq!(variables["title"], "A \\emph{Title}");
+
+        const BAD_TOML: &str = r#"
+        [doc]
+        name = "test"
+        bundle = "na"
+
+        [[output]]
+        name = "o"
+        type = "pdf"
+
+        [output.variables]
+        draft_mode = true
+        "#;
+
+        let mut c = Cursor::new(BAD_TOML.as_bytes());
+        assert!(Document::new_from_toml(".", ".", &mut c).is_err());
+    }
+
+    #[test]
+    fn build_profiles() {
+        const TOML: &str = r#"
+        [doc]
+        name = "test"
+        bundle = "na"
+
+        [[output]]
+        name = "o"
+        type = "pdf"
+
+        [output.variables]
+        draft = false
+        title = "Title"
+
+        [profile.draft]
+        type = "html"
+        synctex = true
+
+        [profile.draft.variables]
+        draft = true
+        "#;
+
+        let mut c = Cursor::new(TOML.as_bytes());
+        let mut doc = Document::new_from_toml(".", ".", &mut c).unwrap();
+        doc.apply_profile("draft").unwrap();
+
+        let output = doc.outputs.get("o").unwrap();
+        assert_eq!(output.target_type, BuildTargetType::Html);
+        assert!(output.synctex);
+        assert!(!output.shell_escape);
+        assert_eq!(output.variables["draft"], "true");
+        assert_eq!(output.variables["title"], "Title");
+
+        assert!(doc.apply_profile("final").is_err());
+    }
+
+    #[test]
+    fn bibtex_settings() {
+        const TOML: &str = r#"
+        [doc]
+        name = "test"
+        bundle = "na"
+
+        [[output]]
+        name = "o"
+        type = "pdf"
+
+        [output.bibtex]
+        style = "alpha"
+        min_crossrefs = 1
+        warnings_as_errors = true
+        search_paths = ["../refs"]
+
+        [[output]]
+        name = "p"
+        type = "pdf"
+        "#;
+
+        let mut c = Cursor::new(TOML.as_bytes());
+        let doc = Document::new_from_toml(".", ".", &mut c).unwrap();
+        assert_eq!(
+            doc.outputs.get("o").unwrap().bibtex,
+            BibtexSettings {
+                style: Some("alpha".to_owned()),
+                min_crossrefs: Some(1),
+                warnings_as_errors: true,
+                search_paths: vec![PathBuf::from("../refs")],
+            }
+        );
+        assert_eq!(
+            doc.outputs.get("p").unwrap().bibtex,
+            BibtexSettings::default()
+        );
+    }
+
+    #[test]
+    fn image_settings() {
+        const TOML: &str = r#"
+        [doc]
+        name = "test"
+        bundle = "na"
+
+        [[output]]
+        name = "review"
+        type = "pdf"
+
+        [output.images]
+        max_dpi = 150
+        jpeg_quality = 60
+        grayscale = true
+
+        [[output]]
+        name = "print"
+        type = "pdf"
+        "#;
+
+        let mut c = Cursor::new(TOML.as_bytes());
+        let doc = Document::new_from_toml(".", ".", &mut c).unwrap();
+        assert_eq!(
+            doc.outputs.get("review").unwrap().images,
+            ImageSettings {
+                max_dpi: Some(150),
+                jpeg_quality: Some(60),
+                grayscale: true,
+            }
+        );
+        assert_eq!(
+            doc.outputs.get("print").unwrap().images,
+            ImageSettings::default()
+        );
+    }
+
+    #[test]
+    fn attachments() {
+        const TOML: &str = r#"
+        [doc]
+        name = "test"
+        bundle = "na"
+
+        [[output]]
+        name = "o"
+        type = "pdf"
+
+        [[output]]
+        name = "paper"
+        type = "pdf"
+        attachments = ["source.tar.gz", "data/results.csv"]
+        "#;
+        let mut c = Cursor::new(TOML.as_bytes());
+        let doc = Document::new_from_toml(".", ".", &mut c).unwrap();
+        assert!(doc.outputs.get("o").unwrap().attachments.is_empty());
+        assert_eq!(
+            doc.outputs.get("paper").unwrap().attachments,
+            vec![
+                PathBuf::from("source.tar.gz"),
+                PathBuf::from("data/results.csv")
+            ]
+        );
+
+        let toml = TOML.replace("source.tar.gz", "results.csv");
+        let mut c = Cursor::new(toml.as_bytes());
+        assert!(Document::new_from_toml(".", ".", &mut c).is_err());
+
+        let toml = TOML.replace("attachments =", "pdfa = true\n        attachments =");
+        let mut c = Cursor::new(toml.as_bytes());
+        assert!(Document::new_from_toml(".", ".", &mut c).is_err());
+    }
+
+    #[test]
+    fn encryption() {
+        const TOML: &str = r#"
+        [doc]
+        name = "test"
+        bundle = "na"
+
+        [[output]]
+        name = "review"
+        type = "pdf"
+
+        [output.encryption]
+        owner_password = "editor"
+        allow_printing = false
+
+        [[output]]
+        name = "print"
+        type = "pdf"
+        "#;
+
+        let mut c = Cursor::new(TOML.as_bytes());
+        let doc = Document::new_from_toml(".", ".", &mut c).unwrap();
+        assert_eq!(
+            doc.outputs.get("review").unwrap().encryption,
+            Some(EncryptionSettings {
+                owner_password: "editor".to_owned(),
+                user_password: String::new(),
+                allow_printing: false,
+                allow_copying: true,
+            })
+        );
+        assert_eq!(doc.outputs.get("print").unwrap().encryption, None);
+
+        let toml = TOML.replace("\"editor\"", "\"\"");
+        let mut c = Cursor::new(toml.as_bytes());
+        assert!(Document::new_from_toml(".", ".", &mut c).is_err());
+
+        let toml = TOML.replace("[output.encryption]", "pdfa = true\n[output.encryption]");
+        let mut c = Cursor::new(toml.as_bytes());
+        assert!(Document::new_from_toml(".", ".", &mut c).is_err());
+    }
+
+    #[test]
+    fn tool_hooks() {
+        const TOML: &str = r#"
+        [doc]
+        name = "test"
+        bundle = "na"
+
+        [[output]]
+        name = "o"
+        type = "pdf"
+
+        [[hook]]
+        stage = "pre"
+        command = ["latexdiff", "old.tex", "new.tex"]
+        inputs = ["old.tex", "new.tex"]
+        stdout = "diff.tex"
+
+        [[hook]]
+        stage = "post"
+        command = ["pythontex", "o"]
+        inputs = ["o.pytxcode"]
+        outputs = ["pythontex-files-o"]
+        "#;
+
+        let mut c = Cursor::new(TOML.as_bytes());
+        let doc = Document::new_from_toml(".", ".", &mut c).unwrap();
+        assert_eq!(doc.hooks.len(), 2);
+        assert_eq!(doc.hooks[0].stage, HookStage::Pre);
+        assert_eq!(doc.hooks[0].stdout.as_deref(), Some("diff.tex"));
+        assert!(doc.hooks[0].outputs.is_empty());
+        assert_eq!(doc.hooks[1].stage, HookStage::Post);
+        assert_eq!(doc.hooks[1].command, ["pythontex", "o"]);
+        assert_eq!(doc.hooks[1].outputs, ["pythontex-files-o"]);
+
+        const BAD_TOML: &str = r#"
+        [doc]
+        name = "test"
+        bundle = "na"
+
+        [[output]]
+        name = "o"
+        type = "pdf"
+
+        [[hook]]
+        stage = "post"
+        command = ["tool"]
+        outputs = ["../escape.tex"]
+        "#;
+
+        let mut c = Cursor::new(BAD_TOML.as_bytes());
+        assert!(Document::new_from_toml(".", ".", &mut c).is_err());
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(wildcard_match("ch?.tex", "ch1.tex"));
+        assert!(!wildcard_match("ch?.tex", "ch.tex"));
+        assert!(!wildcard_match("ch?.tex", "ch10.tex"));
+
+        assert!(wildcard_match("chap*", "chap"));
+        assert!(wildcard_match("chap*", "chapter.tex"));
+        assert!(wildcard_match("*", ""));
+        assert!(!wildcard_match("chap*", "cha"));
+
+        // The first `*` has to give back characters for the rest to match.
+        assert!(wildcard_match("*.tex", "a.tex.tex"));
+        assert!(wildcard_match("a*b*c", "aXbYbZc"));
+        assert!(wildcard_match("*ab", "aab"));
+        assert!(!wildcard_match("a*b*c", "aXbYc-"));
+        assert!(!wildcard_match("*.tex", "a.tex.bak"));
+    }
+
+    #[test]
+    fn expand_input_patterns() {
+        const TOML: &str = r#"
+        [doc]
+        name = "test"
+        bundle = "na"
+
+        [[output]]
+        name = "o"
+        type = "pdf"
+        inputs = [
+            "preamble.tex",
+            { inline = "\\begin{document}" },
+            "chapters/02-middle.tex",
+            "chapters/*.tex",
+            "*/appendix?.tex",
+        ]
+        "#;
+
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("src");
+
+        for name in [
+            "preamble.tex",
+            "chapters/01-start.tex",
+            "chapters/02-middle.tex",
+            "chapters/03-end.tex",
+            "chapters/.draft.tex",
+            "chapters/notes.txt",
+            "extra/appendixA.tex",
+            "extra/appendix10.tex",
+        ] {
+            let path = src.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+
+        let mut c = Cursor::new(TOML.as_bytes());
+        let doc = Document::new_from_toml(dir.path(), dir.path().join("build"), &mut c).unwrap();
+        let inputs = doc
+            .expand_inputs(&doc.outputs.get("o").unwrap().inputs)
+            .unwrap();
+
+        assert_eq!(
+            inputs,
+            vec![
+                InputFile::File("preamble.tex".to_owned()),
+                InputFile::Inline("\\begin{document}".to_owned()),
+                InputFile::File("chapters/02-middle.tex".to_owned()),
+                InputFile::File("chapters/01-start.tex".to_owned()),
+                InputFile::File("chapters/03-end.tex".to_owned()),
+                InputFile::File("extra/appendixA.tex".to_owned()),
+            ]
+        );
+
+        let missing = [InputFile::File("nothing/*.tex".to_owned())];
+        assert!(doc.expand_inputs(&missing).is_err());
+    }
+
+    #[test]
+    fn pin_bundle() {
+        const TOML: &str = r#"[doc]
+name = "test"
+# The patched files come first.
+bundle = ["patches", "na"]
+bundle_key = "00"
+
+[[output]]
+name = "o"
+type = "pdf"
+"#;
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("Tectonic.toml"), TOML).unwrap();
+
+        let mut c = Cursor::new(TOML.as_bytes());
+        let mut doc =
+            Document::new_from_toml(dir.path(), dir.path().join("build"), &mut c).unwrap();
+        doc.pin_bundle("vendor.tar".to_owned()).unwrap();
+        assert_eq!(doc.bundle_loc, "vendor.tar");
+        assert!(doc.bundle_overlays.is_empty());
+
+        assert_eq!(
+            fs::read_to_string(dir.path().join("Tectonic.toml")).unwrap(),
+            TOML.replace(r#"["patches", "na"]"#, r#""vendor.tar""#)
+                .replace("bundle_key = \"00\"\n", "")
+        );
+    }
 }
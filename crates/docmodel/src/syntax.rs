@@ -6,10 +6,11 @@
 //!
 //! This module is only used by [`crate::document::Document`]
 
-use std::path::PathBuf;
+use std::{collections::BTreeMap, path::PathBuf};
 
 use crate::document::{
-    BuildTargetType, InputFile, OutputProfile, DEFAULT_INDEX_FILE, DEFAULT_POSTAMBLE_FILE,
+    BibtexSettings, BuildProfile, BuildTargetType, DocumentInfo, EncryptionSettings, HookStage,
+    ImageSettings, InputFile, OutputProfile, ToolHook, DEFAULT_INDEX_FILE, DEFAULT_POSTAMBLE_FILE,
     DEFAULT_PREAMBLE_FILE,
 };
 use serde::{Deserialize, Serialize, Serializer};
@@ -27,15 +28,73 @@ pub struct TomlDocument {
 
     #[serde(rename = "output")]
     pub outputs: Vec<TomlOutputProfile>,
+
+    #[serde(rename = "profile")]
+    pub profiles: Option<BTreeMap<String, TomlBuildProfile>>,
+
+    #[serde(rename = "hook")]
+    pub hooks: Option<Vec<TomlToolHook>>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct TomlDocSection {
     pub name: String,
-    pub bundle: String,
+    pub bundle: TomlBundleLoc,
+    pub bundle_key: Option<String>,
     pub metadata: Option<toml::Value>,
     pub extra_paths: Option<Vec<PathBuf>>,
+    pub shell_escape_allow: Option<Vec<String>>,
+    pub info: Option<TomlDocInfo>,
+}
+
+/// The `bundle` setting: a single bundle, or a list of them where earlier
+/// entries shadow later ones.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum TomlBundleLoc {
+    One(String),
+    Stack(Vec<String>),
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TomlDocInfo {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub subject: Option<String>,
+    pub keywords: Option<Vec<String>>,
+    pub language: Option<String>,
+}
+
+impl From<&TomlDocInfo> for DocumentInfo {
+    fn from(val: &TomlDocInfo) -> DocumentInfo {
+        DocumentInfo {
+            title: val.title.clone(),
+            author: val.author.clone(),
+            subject: val.subject.clone(),
+            keywords: val.keywords.clone().unwrap_or_default(),
+            language: val.language.clone(),
+        }
+    }
+}
+
+impl From<&DocumentInfo> for TomlDocInfo {
+    fn from(rt: &DocumentInfo) -> Self {
+        let keywords = if rt.keywords.is_empty() {
+            None
+        } else {
+            Some(rt.keywords.clone())
+        };
+
+        TomlDocInfo {
+            title: rt.title.clone(),
+            author: rt.author.clone(),
+            subject: rt.subject.clone(),
+            keywords,
+            language: rt.language.clone(),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -92,6 +151,26 @@ impl From<TomlInputFile> for InputFile {
     }
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum TomlVariableValue {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+}
+
+impl From<&TomlVariableValue> for String {
+    fn from(val: &TomlVariableValue) -> String {
+        match val {
+            TomlVariableValue::String(s) => s.clone(),
+            TomlVariableValue::Integer(i) => i.to_string(),
+            TomlVariableValue::Float(f) => f.to_string(),
+            TomlVariableValue::Boolean(b) => b.to_string(),
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct TomlOutputProfile {
@@ -102,6 +181,18 @@ pub struct TomlOutputProfile {
     pub shell_escape: Option<bool>,
     pub shell_escape_cwd: Option<String>,
     pub synctex: Option<bool>,
+    pub system_fonts: Option<bool>,
+    pub font_features: Option<Vec<String>>,
+    pub pdfa: Option<bool>,
+    pub attachments: Option<Vec<PathBuf>>,
+    pub mathml: Option<bool>,
+    pub optimize_assets: Option<bool>,
+    pub split_level: Option<u8>,
+    pub html_templates: Option<BTreeMap<String, PathBuf>>,
+    pub variables: Option<BTreeMap<String, TomlVariableValue>>,
+    pub bibtex: Option<TomlBibtexSettings>,
+    pub images: Option<TomlImageSettings>,
+    pub encryption: Option<TomlEncryptionSettings>,
 
     // We cannot handle these two input variants with an enum.
     // The ideal solution requires #[serde(flatten)],
@@ -167,6 +258,31 @@ impl From<&TomlOutputProfile> for OutputProfile {
             shell_escape: val.shell_escape.unwrap_or(shell_escape_default),
             shell_escape_cwd: val.shell_escape_cwd.clone(),
             synctex: val.synctex.unwrap_or(synctex_default),
+            system_fonts: val.system_fonts.unwrap_or(true),
+            font_features: val.font_features.clone().unwrap_or_default(),
+            pdfa: val.pdfa.unwrap_or(false),
+            attachments: val.attachments.clone().unwrap_or_default(),
+            mathml: val.mathml.unwrap_or(false),
+            optimize_assets: val.optimize_assets.unwrap_or(false),
+            split_level: val.split_level,
+            html_templates: val.html_templates.clone().unwrap_or_default(),
+            variables: val
+                .variables
+                .iter()
+                .flatten()
+                .map(|(k, v)| (k.clone(), v.into()))
+                .collect(),
+            bibtex: val
+                .bibtex
+                .as_ref()
+                .map(BibtexSettings::from)
+                .unwrap_or_default(),
+            images: val
+                .images
+                .as_ref()
+                .map(ImageSettings::from)
+                .unwrap_or_default(),
+            encryption: val.encryption.as_ref().map(EncryptionSettings::from),
         }
     }
 }
@@ -185,6 +301,53 @@ impl From<&OutputProfile> for TomlOutputProfile {
         let shell_escape = if !rt.shell_escape { None } else { Some(true) };
         let shell_escape_cwd = rt.shell_escape_cwd.clone();
         let synctex = if !rt.synctex { None } else { Some(true) };
+        let system_fonts = if rt.system_fonts { None } else { Some(false) };
+        let font_features = if rt.font_features.is_empty() {
+            None
+        } else {
+            Some(rt.font_features.clone())
+        };
+        let pdfa = if !rt.pdfa { None } else { Some(true) };
+        let attachments = if rt.attachments.is_empty() {
+            None
+        } else {
+            Some(rt.attachments.clone())
+        };
+        let mathml = if !rt.mathml { None } else { Some(true) };
+        let html_templates = if rt.html_templates.is_empty() {
+            None
+        } else {
+            Some(rt.html_templates.clone())
+        };
+
+        let optimize_assets = if !rt.optimize_assets {
+            None
+        } else {
+            Some(true)
+        };
+
+        let variables = if rt.variables.is_empty() {
+            None
+        } else {
+            Some(
+                rt.variables
+                    .iter()
+                    .map(|(k, v)| (k.clone(), TomlVariableValue::String(v.clone())))
+                    .collect(),
+            )
+        };
+
+        let bibtex = if rt.bibtex == BibtexSettings::default() {
+            None
+        } else {
+            Some(TomlBibtexSettings::from(&rt.bibtex))
+        };
+
+        let images = if rt.images == ImageSettings::default() {
+            None
+        } else {
+            Some(TomlImageSettings::from(&rt.images))
+        };
 
         TomlOutputProfile {
             name: rt.name.clone(),
@@ -194,6 +357,18 @@ impl From<&OutputProfile> for TomlOutputProfile {
             shell_escape,
             shell_escape_cwd,
             synctex,
+            system_fonts,
+            font_features,
+            pdfa,
+            attachments,
+            mathml,
+            optimize_assets,
+            split_level: rt.split_level,
+            html_templates,
+            variables,
+            bibtex,
+            images,
+            encryption: rt.encryption.as_ref().map(TomlEncryptionSettings::from),
             preamble_file: None,
             index_file: None,
             postamble_file: None,
@@ -201,6 +376,178 @@ impl From<&OutputProfile> for TomlOutputProfile {
     }
 }
 
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TomlBibtexSettings {
+    pub style: Option<String>,
+    pub min_crossrefs: Option<u32>,
+    pub warnings_as_errors: Option<bool>,
+    pub search_paths: Option<Vec<PathBuf>>,
+}
+
+impl From<&TomlBibtexSettings> for BibtexSettings {
+    fn from(val: &TomlBibtexSettings) -> BibtexSettings {
+        BibtexSettings {
+            style: val.style.clone(),
+            min_crossrefs: val.min_crossrefs,
+            warnings_as_errors: val.warnings_as_errors.unwrap_or(false),
+            search_paths: val.search_paths.clone().unwrap_or_default(),
+        }
+    }
+}
+
+impl From<&BibtexSettings> for TomlBibtexSettings {
+    fn from(rt: &BibtexSettings) -> Self {
+        let search_paths = if rt.search_paths.is_empty() {
+            None
+        } else {
+            Some(rt.search_paths.clone())
+        };
+
+        TomlBibtexSettings {
+            style: rt.style.clone(),
+            min_crossrefs: rt.min_crossrefs,
+            warnings_as_errors: if rt.warnings_as_errors {
+                Some(true)
+            } else {
+                None
+            },
+            search_paths,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TomlImageSettings {
+    pub max_dpi: Option<u32>,
+    pub jpeg_quality: Option<u8>,
+    pub grayscale: Option<bool>,
+}
+
+impl From<&TomlImageSettings> for ImageSettings {
+    fn from(val: &TomlImageSettings) -> ImageSettings {
+        ImageSettings {
+            max_dpi: val.max_dpi,
+            jpeg_quality: val.jpeg_quality,
+            grayscale: val.grayscale.unwrap_or(false),
+        }
+    }
+}
+
+impl From<&ImageSettings> for TomlImageSettings {
+    fn from(rt: &ImageSettings) -> Self {
+        TomlImageSettings {
+            max_dpi: rt.max_dpi,
+            jpeg_quality: rt.jpeg_quality,
+            grayscale: if rt.grayscale { Some(true) } else { None },
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TomlEncryptionSettings {
+    pub owner_password: String,
+    pub user_password: Option<String>,
+    pub allow_printing: Option<bool>,
+    pub allow_copying: Option<bool>,
+}
+
+impl From<&TomlEncryptionSettings> for EncryptionSettings {
+    fn from(val: &TomlEncryptionSettings) -> EncryptionSettings {
+        EncryptionSettings {
+            owner_password: val.owner_password.clone(),
+            user_password: val.user_password.clone().unwrap_or_default(),
+            allow_printing: val.allow_printing.unwrap_or(true),
+            allow_copying: val.allow_copying.unwrap_or(true),
+        }
+    }
+}
+
+impl From<&EncryptionSettings> for TomlEncryptionSettings {
+    fn from(rt: &EncryptionSettings) -> Self {
+        TomlEncryptionSettings {
+            owner_password: rt.owner_password.clone(),
+            user_password: if rt.user_password.is_empty() {
+                None
+            } else {
+                Some(rt.user_password.clone())
+            },
+            allow_printing: if rt.allow_printing { None } else { Some(false) },
+            allow_copying: if rt.allow_copying { None } else { Some(false) },
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TomlBuildProfile {
+    #[serde(rename = "type")]
+    pub target_type: Option<TomlBuildTargetType>,
+    pub tex_format: Option<String>,
+    pub shell_escape: Option<bool>,
+    pub synctex: Option<bool>,
+    pub variables: Option<BTreeMap<String, TomlVariableValue>>,
+}
+
+impl From<&TomlBuildProfile> for BuildProfile {
+    fn from(val: &TomlBuildProfile) -> BuildProfile {
+        BuildProfile {
+            target_type: val.target_type.map(BuildTargetType::from),
+            tex_format: val.tex_format.clone(),
+            shell_escape: val.shell_escape,
+            synctex: val.synctex,
+            variables: val
+                .variables
+                .iter()
+                .flatten()
+                .map(|(k, v)| (k.clone(), v.into()))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TomlToolHook {
+    pub stage: TomlHookStage,
+    pub command: Vec<String>,
+    pub inputs: Option<Vec<String>>,
+    pub outputs: Option<Vec<String>>,
+    pub stdout: Option<String>,
+}
+
+impl From<&TomlToolHook> for ToolHook {
+    fn from(val: &TomlToolHook) -> ToolHook {
+        ToolHook {
+            stage: val.stage.into(),
+            command: val.command.clone(),
+            inputs: val.inputs.clone().unwrap_or_default(),
+            outputs: val.outputs.clone().unwrap_or_default(),
+            stdout: val.stdout.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum TomlHookStage {
+    #[serde(rename = "pre")]
+    Pre,
+
+    #[serde(rename = "post")]
+    Post,
+}
+
+impl From<TomlHookStage> for HookStage {
+    fn from(val: TomlHookStage) -> HookStage {
+        match val {
+            TomlHookStage::Pre => HookStage::Pre,
+            TomlHookStage::Post => HookStage::Post,
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub enum TomlBuildTargetType {
     #[serde(rename = "html")]
@@ -12,11 +12,11 @@ use std::{
     error::Error,
     fmt, fs,
     io::{self, Write},
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 use tectonic_errors::prelude::*;
 
-use crate::document::Document;
+use crate::document::{Document, InputFile};
 
 /// A Tectonic workspace.
 ///
@@ -90,6 +90,49 @@ impl Workspace {
     }
 }
 
+/// Find the directories at or below `root` that contain a `Tectonic.toml`
+/// file, in sorted order.
+///
+/// Hidden directories, the `build` directories of the documents that are
+/// found, and symbolic links are not searched.
+pub fn find_document_dirs(root: &Path) -> Result<Vec<PathBuf>> {
+    let mut dirs = Vec::new();
+    collect_document_dirs(root, &mut dirs)?;
+    dirs.sort();
+    Ok(dirs)
+}
+
+fn collect_document_dirs(dir: &Path, dirs: &mut Vec<PathBuf>) -> Result<()> {
+    let is_document = dir.join("Tectonic.toml").is_file();
+
+    if is_document {
+        dirs.push(dir.to_owned());
+    }
+
+    let entries = atry!(
+        fs::read_dir(dir);
+        ["couldn\'t read directory `{}`", dir.display()]
+    );
+
+    for entry in entries {
+        let entry = entry?;
+
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+
+        let name = entry.file_name();
+
+        if name.to_string_lossy().starts_with('.') || (is_document && name == "build") {
+            continue;
+        }
+
+        collect_document_dirs(&entry.path(), dirs)?;
+    }
+
+    Ok(())
+}
+
 /// An error for when the environment does not seem to contain a Tectonic
 /// workspace.
 #[derive(Debug)]
@@ -179,4 +222,193 @@ impl WorkspaceCreator {
             doc,
         })
     }
+
+    /// Consume this object and attempt to create a workspace around TeX
+    /// sources that already exist.
+    ///
+    /// Only the `Tectonic.toml` file is created. Its default output is built
+    /// from `inputs`, rather than the stub files created by [`Self::create`].
+    /// The existing sources don't live in the usual `src` directory, so the
+    /// caller will usually want `extra_paths` to include the directories that
+    /// contain them.
+    pub fn create_for_existing(
+        self,
+        bundle_loc: String,
+        extra_paths: Vec<PathBuf>,
+        inputs: Vec<InputFile>,
+    ) -> Result<Workspace> {
+        let mut doc = Document::create_for(&self, bundle_loc, extra_paths)?;
+
+        for profile in doc.outputs.values_mut() {
+            profile.inputs.clone_from(&inputs);
+        }
+
+        doc.create_toml()?;
+
+        Ok(Workspace {
+            root_dir: self.root_dir,
+            doc,
+        })
+    }
+
+    /// Consume this object and attempt to create the new workspace by copying
+    /// the contents of a template directory into it.
+    ///
+    /// If the template contains a `Tectonic.toml` file, it is used as-is, and
+    /// `bundle_loc` and `extra_paths` are ignored. Otherwise, one is created
+    /// just as in [`Self::create`], and the template should provide the source
+    /// files that it names. A `.git` directory at the top of the template is
+    /// skipped. This fails without changing anything if any of the template's
+    /// files already exist in the workspace.
+    pub fn create_from_template(
+        self,
+        template_dir: &Path,
+        bundle_loc: String,
+        extra_paths: Vec<PathBuf>,
+    ) -> Result<Workspace> {
+        let mut files = Vec::new();
+        collect_template_files(template_dir, Path::new(""), &mut files)?;
+
+        for rel_path in &files {
+            let dest = self.root_dir.join(rel_path);
+
+            if dest.exists() {
+                bail!(
+                    "refusing to overwrite `{}` with the template’s version",
+                    dest.display()
+                );
+            }
+        }
+
+        for rel_path in &files {
+            let dest = self.root_dir.join(rel_path);
+
+            if let Some(parent) = dest.parent() {
+                atry!(
+                    fs::create_dir_all(parent);
+                    ["couldn\'t create workspace directory `{}`", parent.display()]
+                );
+            }
+
+            atry!(
+                fs::copy(template_dir.join(rel_path), &dest);
+                ["couldn\'t create `{}`", dest.display()]
+            );
+        }
+
+        let toml_path = self.root_dir.join("Tectonic.toml");
+
+        if !toml_path.exists() {
+            Document::create_for(&self, bundle_loc, extra_paths)?.create_toml()?;
+        }
+
+        let mut doc_file = fs::File::open(&toml_path)?;
+        let doc = Document::new_from_toml(
+            self.root_dir.clone(),
+            self.root_dir.join("build"),
+            &mut doc_file,
+        )?;
+
+        Ok(Workspace {
+            root_dir: self.root_dir,
+            doc,
+        })
+    }
+}
+
+/// Collect the paths of the files in a template directory, relative to its
+/// root, skipping a toplevel `.git` directory.
+///
+/// Symbolic links are refused, since they could point anywhere on the system
+/// that fetched the template.
+fn collect_template_files(root: &Path, rel_dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    let dir = root.join(rel_dir);
+    let entries = atry!(
+        fs::read_dir(&dir);
+        ["couldn\'t read template directory `{}`", dir.display()]
+    );
+
+    for entry in entries {
+        let entry = entry?;
+        let rel_path = rel_dir.join(entry.file_name());
+        let file_type = entry.file_type()?;
+
+        if file_type.is_symlink() {
+            bail!(
+                "the template contains the symbolic link `{}`, which isn\'t supported",
+                rel_path.display()
+            );
+        }
+
+        if file_type.is_dir() {
+            if rel_path != Path::new(".git") {
+                collect_template_files(root, &rel_path, files)?;
+            }
+        } else {
+            files.push(rel_path);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn template_files() {
+        let template = tempfile::tempdir().unwrap();
+        fs::create_dir_all(template.path().join(".git")).unwrap();
+        fs::create_dir_all(template.path().join("src/parts")).unwrap();
+        fs::write(template.path().join(".git/HEAD"), "ref").unwrap();
+        fs::write(template.path().join("src/index.tex"), "index").unwrap();
+        fs::write(template.path().join("src/parts/a.tex"), "a").unwrap();
+
+        let mut files = Vec::new();
+        collect_template_files(template.path(), Path::new(""), &mut files).unwrap();
+        files.sort();
+        assert_eq!(
+            files,
+            [Path::new("src/index.tex"), Path::new("src/parts/a.tex")]
+        );
+    }
+
+    #[test]
+    fn document_dirs() {
+        let root = tempfile::tempdir().unwrap();
+
+        for dir in ["a/build/x", "b/c", ".hidden", "d"] {
+            fs::create_dir_all(root.path().join(dir)).unwrap();
+        }
+
+        for toml in ["a", "a/build/x", "b/c", ".hidden"] {
+            fs::write(root.path().join(toml).join("Tectonic.toml"), "").unwrap();
+        }
+
+        assert_eq!(
+            find_document_dirs(root.path()).unwrap(),
+            [root.path().join("a"), root.path().join("b/c")]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn template_symlinks() {
+        let secret = tempfile::tempdir().unwrap();
+        fs::write(secret.path().join("id_rsa"), "secret").unwrap();
+
+        for link in ["src/leak.tex", "src/leaks"] {
+            let template = tempfile::tempdir().unwrap();
+            fs::create_dir_all(template.path().join("src")).unwrap();
+            fs::write(template.path().join("src/index.tex"), "index").unwrap();
+            std::os::unix::fs::symlink(secret.path(), template.path().join(link)).unwrap();
+
+            let dest = tempfile::tempdir().unwrap();
+            assert!(WorkspaceCreator::new(dest.path())
+                .create_from_template(template.path(), "bundle".to_owned(), Vec::new())
+                .is_err());
+            assert!(!dest.path().join("src").exists());
+        }
+    }
 }
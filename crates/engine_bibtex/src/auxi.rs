@@ -1,4 +1,5 @@
 use crate::{
+    bib_input_key,
     bibs::BibData,
     buffer::{BufTy, GlobalBuffer},
     char_info::LexClass,
@@ -9,17 +10,21 @@ use crate::{
     log::{
         aux_end1_err_print, aux_end2_err_print, aux_err_illegal_another_print,
         aux_err_no_right_brace_print, aux_err_print, aux_err_stuff_after_right_brace_print,
-        aux_err_white_space_in_argument_print, hash_cite_confusion, log_pr_aux_name,
-        log_pr_bst_name, print_a_pool_str, print_a_token, print_aux_name, print_bib_name,
-        print_confusion, print_overflow, AuxTy,
+        aux_err_white_space_in_argument_print, aux_warn_print, hash_cite_confusion,
+        log_pr_aux_name, log_pr_bst_name, print_a_pool_str, print_a_token, print_aux_name,
+        print_bib_name, print_confusion, print_overflow, AuxTy,
     },
     peekable::PeekableInput,
     pool::{StrNumber, StringPool},
     scan::Scan,
-    Bibtex, BibtexError, File, GlobalItems,
+    Bibtex, BibtexError, EmptyCitationPolicy, File, GlobalItems,
+};
+use std::{
+    ffi::{CStr, CString},
+    io::Cursor,
 };
-use std::ffi::CString;
 use tectonic_bridge_core::FileFormat;
+use tectonic_io_base::{InputHandle, InputOrigin};
 
 const AUX_STACK_SIZE: usize = 20;
 
@@ -116,8 +121,7 @@ fn aux_bib_data_command(
 
         let name = pool.get_str(hash.get(res.loc).text());
         let fname = CString::new(name).unwrap();
-        let bib_in = PeekableInput::open(ctx, &fname, FileFormat::Bib);
-        match bib_in {
+        match open_bib_file(ctx, &fname) {
             Err(_) => {
                 ctx.write_logs("I couldn't open database file ");
                 print_bib_name(ctx, pool, hash.get(res.loc).text())?;
@@ -137,6 +141,32 @@ fn aux_bib_data_command(
     Ok(())
 }
 
+/// Open a database file, looking for it first among the ones provided by the
+/// caller.
+pub(crate) fn open_bib_file(
+    ctx: &mut Bibtex<'_, '_>,
+    fname: &CStr,
+) -> Result<PeekableInput, BibtexError> {
+    let key = bib_input_key(fname.to_str().unwrap_or_default());
+    if let Some(data) = ctx.bib_inputs.remove(key) {
+        Ok(PeekableInput::from_handle(InputHandle::new_read_only(
+            format!("{key}.bib"),
+            Cursor::new(data),
+            InputOrigin::Other,
+        )))
+    } else if let Some(data) = ctx.config.bib_data.get(key) {
+        Ok(PeekableInput::from_handle(InputHandle::new_read_only(
+            format!("{key}.bib"),
+            Cursor::new(data.clone()),
+            InputOrigin::Other,
+        )))
+    } else if let Some(handle) = ctx.bib_resolver.as_mut().and_then(|resolve| resolve(key)) {
+        Ok(PeekableInput::from_handle(handle))
+    } else {
+        PeekableInput::open(ctx, fname, FileFormat::Bib)
+    }
+}
+
 fn aux_bib_style_command(
     ctx: &mut Bibtex<'_, '_>,
     buffers: &mut GlobalBuffer,
@@ -186,7 +216,14 @@ fn aux_bib_style_command(
 
     let name = pool.get_str(hash.get(res.loc).text());
     let fname = CString::new(name).unwrap();
-    let bst_file = PeekableInput::open(ctx, &fname, FileFormat::Bst);
+    let bst_file = match &ctx.config.bst_data {
+        Some(data) => Ok(PeekableInput::from_handle(InputHandle::new_read_only(
+            fname.to_string_lossy(),
+            Cursor::new(data.clone()),
+            InputOrigin::Other,
+        ))),
+        None => PeekableInput::open(ctx, &fname, FileFormat::Bst),
+    };
     match bst_file {
         Err(_) => {
             ctx.write_logs("I couldn't open style file ");
@@ -251,6 +288,28 @@ fn aux_citation_command(
             return Ok(());
         }
 
+        if buffers.offset(BufTy::Base, 2) == buffers.offset(BufTy::Base, 1) {
+            match ctx.config.empty_citations {
+                EmptyCitationPolicy::Keep => (),
+                EmptyCitationPolicy::Ignore => {
+                    ctx.empty_citation_seen = true;
+                    continue;
+                }
+                EmptyCitationPolicy::Warn => {
+                    ctx.empty_citation_seen = true;
+                    ctx.write_logs("Warning--empty citation key");
+                    aux_warn_print(ctx, aux, pool)?;
+                    continue;
+                }
+                EmptyCitationPolicy::Error => {
+                    ctx.empty_citation_seen = true;
+                    ctx.write_logs("Empty citation key");
+                    aux_err_print(ctx, buffers, aux, pool)?;
+                    return Ok(());
+                }
+            }
+        }
+
         if buffers.offset(BufTy::Base, 2) - buffers.offset(BufTy::Base, 1) == 1
             && buffers.at_offset(BufTy::Base, 1) == b'*'
         {
@@ -479,7 +538,8 @@ pub(crate) fn last_check_for_aux_errors(
         aux_end1_err_print(ctx);
         ctx.write_logs("\\citation commands");
         aux_end2_err_print(ctx, pool, last_aux)?;
-    } else if cites.num_cites() == 0 && !ctx.all_entries {
+    } else if cites.num_cites() == 0 && !ctx.all_entries && !ctx.empty_citation_seen {
+        // If there were only empty keys, those have already been dealt with.
         aux_end1_err_print(ctx);
         ctx.write_logs("cite keys");
         aux_end2_err_print(ctx, pool, last_aux)?;
@@ -1,19 +1,22 @@
 use crate::{
     buffer::{BufTy, GlobalBuffer},
-    char_info::LexClass,
+    char_info::{IdClass, LexClass},
     cite::add_database_cite,
     hash,
     hash::{BstFn, HashData},
     log::{
-        bib_equals_sign_print, bib_err_print, bib_id_print, bib_one_of_two_print, bib_warn_print,
-        cite_key_disappeared_confusion, eat_bib_print, hash_cite_confusion, print_a_token,
-        print_confusion,
+        bib_equals_sign_print, bib_err_print, bib_field_equals_sign_print, bib_field_err_print,
+        bib_field_one_of_two_print, bib_id_print, bib_one_of_two_print, bib_warn_print,
+        cite_key_disappeared_confusion, eat_bib_print, hash_cite_confusion, print_a_pool_str,
+        print_a_token, print_confusion, print_entry_sites,
     },
     peekable::input_ln,
     pool::{StrNumber, StringPool},
     scan::{scan_and_store_the_field_value_and_eat_white, scan_identifier, Scan, ScanRes},
-    Bibtex, BibtexError, File, GlobalItems, HashPointer, LookupRes,
+    BibComment, Bibtex, BibtexError, BibtexProgress, CiteNumber, DuplicateEntryPolicy, EntryFields,
+    File, GlobalItems, HashPointer, LookupRes, PROGRESS_ENTRY_INTERVAL,
 };
+use std::collections::HashMap;
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub(crate) enum BibCommand {
@@ -22,9 +25,37 @@ pub(crate) enum BibCommand {
     String,
 }
 
+/// The entry field whose value is currently being scanned, kept around for
+/// diagnostics.
+#[derive(Clone)]
+pub(crate) struct FieldSite {
+    pub entry: Vec<u8>,
+    pub field: Vec<u8>,
+}
+
+/// A brace- or quote-delimited value that has been opened but not yet closed.
+struct OpenValue {
+    line: u32,
+    /// The first line inside the value that looks like the start of a new
+    /// entry, if any.
+    runaway_line: Option<u32>,
+}
+
 pub(crate) struct BibData {
     bibs: Vec<File>,
     preamble: Vec<StrNumber>,
+    cur_entry: Vec<u8>,
+    cur_field: Option<FieldSite>,
+    open_value: Option<OpenValue>,
+    /// The most recent value in the current entry that spanned multiple
+    /// lines, with the line it started on. A missing closing brace usually
+    /// shows up as a value like this swallowing the rest of the entry.
+    multiline_value: Option<(FieldSite, u32)>,
+    /// Whether the text up to the next command or entry follows an `@comment`
+    after_comment: bool,
+    /// The file name and line of each definition of an entry, keyed by its
+    /// position in the cite list
+    entry_sites: HashMap<CiteNumber, Vec<(StrNumber, u32)>>,
 }
 
 impl BibData {
@@ -32,6 +63,12 @@ impl BibData {
         BibData {
             bibs: Vec::new(),
             preamble: Vec::new(),
+            cur_entry: Vec::new(),
+            cur_field: None,
+            open_value: None,
+            multiline_value: None,
+            after_comment: false,
+            entry_sites: HashMap::new(),
         }
     }
 
@@ -47,6 +84,11 @@ impl BibData {
         self.bibs.push(file);
     }
 
+    /// Add a file to be read before any of the others.
+    pub fn push_file_first(&mut self, file: File) {
+        self.bibs.insert(0, file);
+    }
+
     pub fn pop_file(&mut self) -> File {
         self.bibs.remove(0)
     }
@@ -66,6 +108,200 @@ impl BibData {
     pub fn len(&self) -> usize {
         self.bibs.len()
     }
+
+    /// Record that the entry at `cite` is defined at the current line of the
+    /// current file.
+    pub fn add_entry_site(&mut self, cite: CiteNumber) {
+        let file = self.top_file();
+        let site = (file.name, file.line);
+        self.entry_sites.entry(cite).or_default().push(site);
+    }
+
+    pub fn entry_sites(&self, cite: CiteNumber) -> &[(StrNumber, u32)] {
+        self.entry_sites.get(&cite).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn after_comment(&self) -> bool {
+        self.after_comment
+    }
+
+    pub fn set_after_comment(&mut self, value: bool) {
+        self.after_comment = value;
+    }
+
+    pub fn cur_entry(&self) -> &[u8] {
+        &self.cur_entry
+    }
+
+    pub fn set_cur_entry(&mut self, key: &[u8]) {
+        self.cur_entry.clear();
+        self.cur_entry.extend_from_slice(key);
+    }
+
+    /// Note that the value of the named field of the current entry is about to
+    /// be scanned, or with `None`, that no entry field is being scanned.
+    pub fn set_cur_field(&mut self, field: Option<&[u8]>) {
+        self.cur_field = field.map(|field| FieldSite {
+            entry: self.cur_entry.clone(),
+            field: field.to_vec(),
+        });
+    }
+
+    /// Note that a delimited value starts at the current line.
+    pub fn open_value(&mut self) {
+        self.open_value = Some(OpenValue {
+            line: self.top_file().line,
+            runaway_line: None,
+        });
+    }
+
+    /// Note that the delimited value has been closed, returning the line on
+    /// which it started and the first line inside of it that looked like the
+    /// start of a new entry, if any.
+    pub fn close_value(&mut self) -> Option<(u32, u32)> {
+        let open = self.open_value.take()?;
+        if let Some(field) = &self.cur_field {
+            if open.line != self.top_file().line {
+                self.multiline_value = Some((field.clone(), open.line));
+            }
+        }
+        open.runaway_line.map(|runaway| (open.line, runaway))
+    }
+
+    /// Forget about the fields of the previous entry, if any.
+    pub fn reset_field_sites(&mut self) {
+        self.cur_field = None;
+        self.open_value = None;
+        self.multiline_value = None;
+    }
+
+    pub fn cur_field(&self) -> Option<&FieldSite> {
+        self.cur_field.as_ref()
+    }
+
+    /// If an entry field's delimited value is still open, get the field, the
+    /// line on which its value started, and the first line inside of it that
+    /// looked like the start of a new entry, if any.
+    pub fn unclosed_field(&self) -> Option<(&FieldSite, u32, Option<u32>)> {
+        match (&self.cur_field, &self.open_value) {
+            (Some(field), Some(open)) => Some((field, open.line, open.runaway_line)),
+            _ => None,
+        }
+    }
+
+    pub fn multiline_value(&self) -> Option<(&FieldSite, u32)> {
+        self.multiline_value
+            .as_ref()
+            .map(|(field, line)| (field, *line))
+    }
+
+    /// Called after reading a new line into the base buffer. If we're in the
+    /// middle of a delimited entry field value and the line looks like the
+    /// start of another entry, the value is probably missing a closing brace.
+    /// Returns whether this line is the first such line.
+    fn note_new_line(&mut self, buffers: &GlobalBuffer) -> bool {
+        let line = self.top_file().line;
+        let Some(open) = &mut self.open_value else {
+            return false;
+        };
+
+        if self.cur_field.is_none() || open.runaway_line.is_some() {
+            return false;
+        }
+
+        let text = &buffers.buffer(BufTy::Base)[..buffers.init(BufTy::Base)];
+        if is_entry_header(text) {
+            open.runaway_line = Some(line);
+            return true;
+        }
+        false
+    }
+}
+
+/// Whether a line starts the way a database entry or command does: an `@`,
+/// an entry type, and an opening delimiter, possibly separated by whitespace.
+/// Lines inside field values that merely start with an `@`, like an e-mail
+/// address or a social media handle, don't count.
+fn is_entry_header(text: &[u8]) -> bool {
+    let skip_white = |text: &[u8]| -> usize {
+        text.iter()
+            .take_while(|&&c| LexClass::of(c) == LexClass::Whitespace)
+            .count()
+    };
+
+    let mut pos = skip_white(text);
+    if text.get(pos) != Some(&b'@') {
+        return false;
+    }
+    pos += 1;
+    pos += skip_white(&text[pos..]);
+
+    // Like `scan_identifier`, an entry type can't start with a digit
+    let id_len = text[pos..]
+        .iter()
+        .take_while(|&&c| IdClass::of(c) == IdClass::LegalIdChar)
+        .count();
+    if id_len == 0 || LexClass::of(text[pos]) == LexClass::Numeric {
+        return false;
+    }
+    pos += id_len;
+    pos += skip_white(&text[pos..]);
+
+    matches!(text.get(pos), Some(b'{' | b'('))
+}
+
+/// Whether an error in the current entry field should only skip that field.
+/// If the error is the start of another entry, say after a value with
+/// unbalanced braces, the current entry is over in any case.
+fn recover_field(ctx: &Bibtex<'_, '_>, buffers: &GlobalBuffer) -> bool {
+    ctx.config.lenient && !at_entry_header(buffers)
+}
+
+/// In lenient mode, skip past a malformed entry field: move to the next comma
+/// or closing delimiter of the entry that isn't inside a braced or quoted
+/// value. Returns false if the database file ends first, or if a line that
+/// looks like the start of a new entry comes first, in which case the scan
+/// for the next entry picks up there.
+fn skip_rest_of_field(
+    ctx: &mut Bibtex<'_, '_>,
+    buffers: &mut GlobalBuffer,
+    bibs: &mut BibData,
+    right_outer_delim: u8,
+) -> bool {
+    let mut depth = 0;
+    let mut in_quotes = false;
+
+    loop {
+        while buffers.offset(BufTy::Base, 2) < buffers.init(BufTy::Base) {
+            let c = buffers.at_offset(BufTy::Base, 2);
+            let outside = depth == 0 && !in_quotes;
+            match c {
+                b',' if outside => return true,
+                _ if c == right_outer_delim && outside => return true,
+                b'{' => depth += 1,
+                b'}' if depth > 0 => depth -= 1,
+                b'"' if depth == 0 => in_quotes = !in_quotes,
+                _ => (),
+            }
+            buffers.set_offset(BufTy::Base, 2, buffers.offset(BufTy::Base, 2) + 1);
+        }
+
+        if !input_ln(ctx.engine, &mut bibs.top_file_mut().file, buffers) {
+            return false;
+        }
+
+        bibs.top_file_mut().line += 1;
+        buffers.set_offset(BufTy::Base, 2, 0);
+        if at_entry_header(buffers) {
+            return false;
+        }
+    }
+}
+
+/// Whether the rest of the current line looks like the start of a new entry.
+fn at_entry_header(buffers: &GlobalBuffer) -> bool {
+    let rest = buffers.offset(BufTy::Base, 2)..buffers.init(BufTy::Base);
+    is_entry_header(&buffers.buffer(BufTy::Base)[rest])
 }
 
 pub(crate) fn eat_bib_white_space(
@@ -84,6 +320,9 @@ pub(crate) fn eat_bib_white_space(
 
         bibs.top_file_mut().line += 1;
         buffers.set_offset(BufTy::Base, 2, 0);
+        if bibs.note_new_line(buffers) && ctx.config.lenient {
+            return false;
+        }
         init = buffers.init(BufTy::Base);
     }
     true
@@ -116,12 +355,63 @@ pub(crate) fn compress_bib_white(
 
         bibs.top_file_mut().line += 1;
         buffers.set_offset(BufTy::Base, 2, 0);
+        if bibs.note_new_line(buffers) && ctx.config.lenient {
+            return eat_bib_print(ctx, buffers, pool, bibs, bib_command).map(|_| false);
+        }
         last = buffers.init(BufTy::Base);
     }
 
     Ok(true)
 }
 
+/// If there's any text between database entries, keep it around in the run's
+/// report.
+fn preserve_comment(
+    ctx: &mut Bibtex<'_, '_>,
+    globals: &mut GlobalItems<'_>,
+    line: u32,
+    text: &[u8],
+) {
+    let comment_command = globals.bibs.after_comment();
+    globals.bibs.set_after_comment(false);
+
+    // The newlines between lines are ours, so they don't have a lexical class
+    let is_white = |c: &u8| *c == b'\n' || LexClass::of(*c) == LexClass::Whitespace;
+    if text.iter().all(is_white) {
+        return;
+    }
+
+    // Skip any blank lines at the start, so that the line number points at
+    // the first line with something on it.
+    let mut line = line;
+    let mut text = text;
+    while let Some(pos) = text.iter().position(|&c| c == b'\n') {
+        if !text[..pos].iter().all(is_white) {
+            break;
+        }
+        text = &text[pos + 1..];
+        line += 1;
+    }
+    let end = text
+        .iter()
+        .rposition(|c| !is_white(c))
+        .map_or(0, |pos| pos + 1);
+    let text = &text[..end];
+
+    let mut file =
+        String::from_utf8_lossy(globals.pool.get_str(globals.bibs.top_file().name)).into_owned();
+    if !file.ends_with(".bib") {
+        file.push_str(".bib");
+    }
+
+    ctx.comments.push(BibComment {
+        file,
+        line,
+        text: String::from_utf8_lossy(text).into_owned(),
+        comment_command,
+    });
+}
+
 pub(crate) fn get_bib_command_or_entry_and_process(
     ctx: &mut Bibtex<'_, '_>,
     globals: &mut GlobalItems<'_>,
@@ -129,20 +419,39 @@ pub(crate) fn get_bib_command_or_entry_and_process(
     field_name_loc: &mut HashPointer<BstFn>,
 ) -> Result<(), BibtexError> {
     let mut bib_command = None;
+    globals.bibs.reset_field_sites();
+
+    let preserve = ctx.config.preserve_comments;
+    let mut text = Vec::new();
+    let text_line = globals.bibs.top_file().line;
 
     let mut init = globals.buffers.init(BufTy::Base);
+    let mut start = globals.buffers.offset(BufTy::Base, 2);
     while !Scan::new().chars(b"@").scan_till(globals.buffers, init) {
+        if preserve {
+            text.extend_from_slice(&globals.buffers.buffer(BufTy::Base)[start..init]);
+            text.push(b'\n');
+        }
+
         if !input_ln(
             ctx.engine,
             &mut globals.bibs.top_file_mut().file,
             globals.buffers,
         ) {
+            preserve_comment(ctx, globals, text_line, &text);
             return Ok(());
         }
 
         globals.bibs.top_file_mut().line += 1;
         globals.buffers.set_offset(BufTy::Base, 2, 0);
         init = globals.buffers.init(BufTy::Base);
+        start = 0;
+    }
+
+    if preserve {
+        let end = globals.buffers.offset(BufTy::Base, 2);
+        text.extend_from_slice(&globals.buffers.buffer(BufTy::Base)[start..end]);
+        preserve_comment(ctx, globals, text_line, &text);
     }
 
     if globals.buffers.at_offset(BufTy::Base, 2) != b'@' {
@@ -195,7 +504,7 @@ pub(crate) fn get_bib_command_or_entry_and_process(
         let &cmd = globals.hash.get(loc).extra();
         bib_command = Some(cmd);
         match cmd {
-            BibCommand::Comment => (),
+            BibCommand::Comment => globals.bibs.set_after_comment(true),
             BibCommand::Preamble => {
                 if !eat_bib_white_space(ctx, globals.buffers, globals.bibs) {
                     eat_bib_print(
@@ -352,6 +661,9 @@ pub(crate) fn get_bib_command_or_entry_and_process(
                     .hash
                     .set_extra(res.loc, globals.hash.get(res.loc).text());
                 *cur_macro_loc = res.loc;
+                if let Some(stats) = ctx.cur_bib_stats() {
+                    stats.macros_defined += 1;
+                }
 
                 if !eat_bib_white_space(ctx, globals.buffers, globals.bibs) {
                     eat_bib_print(
@@ -429,6 +741,18 @@ pub(crate) fn get_bib_command_or_entry_and_process(
         return Ok(());
     }
 
+    if let Some(stats) = ctx.bib_stats.last_mut() {
+        stats.entries_scanned += 1;
+        if stats.entries_scanned % PROGRESS_ENTRY_INTERVAL == 0 {
+            if let Some(observer) = &mut ctx.progress {
+                observer(BibtexProgress::EntriesRead {
+                    name: &stats.name,
+                    count: stats.entries_scanned,
+                });
+            }
+        }
+    }
+
     let range = globals.buffers.offset(BufTy::Base, 1)..globals.buffers.offset(BufTy::Base, 2);
     let bst_fn = &mut globals.buffers.buffer_mut(BufTy::Base)[range];
     let bst_res = globals
@@ -491,6 +815,9 @@ pub(crate) fn get_bib_command_or_entry_and_process(
 
     let start = globals.buffers.offset(BufTy::Base, 1);
     let end = globals.buffers.offset(BufTy::Base, 2);
+    globals
+        .bibs
+        .set_cur_entry(&globals.buffers.buffer(BufTy::Base)[start..end]);
     globals
         .buffers
         .copy_within(BufTy::Base, BufTy::Ex, start, start, end - start);
@@ -499,7 +826,8 @@ pub(crate) fn get_bib_command_or_entry_and_process(
     let lc_cite = &mut globals.buffers.buffer_mut(BufTy::Ex)[range];
     lc_cite.make_ascii_lowercase();
 
-    let lc_res = if ctx.all_entries {
+    // Fallback databases only fill in for cited entries, even with `\nocite{*}`
+    let lc_res = if ctx.all_entries && !ctx.reading_fallback {
         globals
             .hash
             .lookup_str_insert(globals.pool, lc_cite, HashPointer::default())
@@ -517,6 +845,12 @@ pub(crate) fn get_bib_command_or_entry_and_process(
     };
 
     let mut cite_exists = lc_res.exists;
+    let mut replace_entry = false;
+    // Whether this is a repeated entry to be skipped. Its fields are still
+    // scanned, without being stored, so that the scan picks up again after
+    // its closing delimiter rather than at the next `@`, which might well be
+    // inside one of its field values.
+    let mut skip_entry = false;
 
     'a: {
         if lc_res.exists {
@@ -587,18 +921,55 @@ pub(crate) fn get_bib_command_or_entry_and_process(
                 return Err(BibtexError::Fatal);
             }
 
-            ctx.write_logs("Repeated entry");
-            return bib_err_print(
-                ctx,
-                globals.buffers,
-                globals.pool,
-                globals.bibs,
-                bib_command,
-            );
+            if ctx.reading_fallback {
+                // The main databases' definition takes precedence
+                skip_entry = true;
+                break 'a;
+            }
+
+            match ctx.config.duplicate_entries {
+                DuplicateEntryPolicy::Error => {
+                    ctx.write_logs("Repeated entry");
+                    return bib_err_print(
+                        ctx,
+                        globals.buffers,
+                        globals.pool,
+                        globals.bibs,
+                        bib_command,
+                    );
+                }
+                DuplicateEntryPolicy::FirstWins => {
+                    globals.bibs.add_entry_site(entry_ptr);
+                    ctx.write_logs("Warning--repeated entry \"");
+                    print_a_pool_str(ctx, globals.cites.get_cite(entry_ptr), globals.pool)?;
+                    ctx.write_logs("\", using the first definition\n");
+                    bib_warn_print(ctx, globals.pool, globals.bibs)?;
+                    print_entry_sites(ctx, globals.pool, globals.cites, globals.bibs, entry_ptr)?;
+                    skip_entry = true;
+                }
+                DuplicateEntryPolicy::LastWins => {
+                    globals.bibs.add_entry_site(entry_ptr);
+                    ctx.write_logs("Warning--repeated entry \"");
+                    print_a_pool_str(ctx, globals.cites.get_cite(entry_ptr), globals.pool)?;
+                    ctx.write_logs("\", using the last definition\n");
+                    bib_warn_print(ctx, globals.pool, globals.bibs)?;
+                    print_entry_sites(ctx, globals.pool, globals.cites, globals.bibs, entry_ptr)?;
+
+                    let start = entry_ptr * globals.other.num_fields();
+                    for idx in start..start + globals.other.num_fields() {
+                        globals.other.set_field(idx, StrNumber::invalid());
+                    }
+                    replace_entry = true;
+                }
+            }
         }
     }
 
-    let store_entry = if ctx.all_entries {
+    let store_entry = if skip_entry {
+        false
+    } else if replace_entry {
+        true
+    } else if ctx.all_entries && (cite_exists || !ctx.reading_fallback) {
         if cite_exists {
             if globals.cites.entry_ptr() >= globals.cites.all_marker() {
                 globals.cites.set_exists(globals.cites.entry_ptr(), true);
@@ -639,6 +1010,26 @@ pub(crate) fn get_bib_command_or_entry_and_process(
     };
 
     if store_entry {
+        // An entry that replaces an earlier definition has been counted
+        // already.
+        if !replace_entry {
+            if let Some(stats) = ctx.cur_bib_stats() {
+                stats.entries_used += 1;
+            }
+
+            globals.bibs.add_entry_site(globals.cites.entry_ptr());
+        }
+
+        let key = String::from_utf8_lossy(globals.bibs.cur_entry()).into_owned();
+        if replace_entry {
+            ctx.entry_fields
+                .retain(|entry| !entry.key.eq_ignore_ascii_case(&key));
+        }
+        ctx.entry_fields.push(EntryFields {
+            key,
+            fields: Vec::new(),
+        });
+
         if let Some(loc) = bst_res {
             globals.cites.set_type(globals.cites.entry_ptr(), loc);
         } else {
@@ -665,6 +1056,21 @@ pub(crate) fn get_bib_command_or_entry_and_process(
 
     while globals.buffers.at_offset(BufTy::Base, 2) != right_outer_delim {
         if globals.buffers.at_offset(BufTy::Base, 2) != b',' {
+            if recover_field(ctx, globals.buffers) {
+                bib_field_one_of_two_print(
+                    ctx,
+                    globals.buffers,
+                    globals.pool,
+                    globals.bibs,
+                    b',',
+                    right_outer_delim,
+                )?;
+                if skip_rest_of_field(ctx, globals.buffers, globals.bibs, right_outer_delim) {
+                    continue;
+                }
+                return Ok(());
+            }
+
             bib_one_of_two_print(
                 ctx,
                 globals.buffers,
@@ -702,6 +1108,13 @@ pub(crate) fn get_bib_command_or_entry_and_process(
             _ => {
                 bib_id_print(ctx, globals.buffers, scan_res)?;
                 ctx.write_logs("a field name");
+                if recover_field(ctx, globals.buffers) {
+                    bib_field_err_print(ctx, globals.buffers, globals.pool, globals.bibs)?;
+                    if skip_rest_of_field(ctx, globals.buffers, globals.bibs, right_outer_delim) {
+                        continue;
+                    }
+                    return Ok(());
+                }
                 bib_err_print(
                     ctx,
                     globals.buffers,
@@ -713,6 +1126,11 @@ pub(crate) fn get_bib_command_or_entry_and_process(
             }
         }
 
+        let range = globals.buffers.offset(BufTy::Base, 1)..globals.buffers.offset(BufTy::Base, 2);
+        globals
+            .bibs
+            .set_cur_field(Some(&globals.buffers.buffer(BufTy::Base)[range]));
+
         *field_name_loc = HashPointer::default();
         let mut store_field = false;
         if store_entry {
@@ -721,6 +1139,12 @@ pub(crate) fn get_bib_command_or_entry_and_process(
             let bst_fn = &mut globals.buffers.buffer_mut(BufTy::Base)[range];
             bst_fn.make_ascii_lowercase();
 
+            if let Some(entry) = ctx.entry_fields.last_mut() {
+                entry
+                    .fields
+                    .push(String::from_utf8_lossy(bst_fn).into_owned());
+            }
+
             let res = globals.hash.lookup_str::<BstFn>(globals.pool, bst_fn);
 
             match res {
@@ -744,6 +1168,20 @@ pub(crate) fn get_bib_command_or_entry_and_process(
         }
 
         if globals.buffers.at_offset(BufTy::Base, 2) != b'=' {
+            if recover_field(ctx, globals.buffers) {
+                bib_field_equals_sign_print(ctx, globals.buffers, globals.pool, globals.bibs)?;
+                if store_entry {
+                    if let Some(entry) = ctx.entry_fields.last_mut() {
+                        entry.fields.pop();
+                    }
+                }
+                globals.bibs.set_cur_field(None);
+                if skip_rest_of_field(ctx, globals.buffers, globals.bibs, right_outer_delim) {
+                    continue;
+                }
+                return Ok(());
+            }
+
             bib_equals_sign_print(
                 ctx,
                 globals.buffers,
@@ -781,6 +1219,7 @@ pub(crate) fn get_bib_command_or_entry_and_process(
         )? {
             return Ok(());
         }
+        globals.bibs.set_cur_field(None);
     }
 
     globals
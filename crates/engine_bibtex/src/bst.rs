@@ -1,4 +1,8 @@
+#[cfg(feature = "introspection")]
+use crate::inspect::inspect_execution;
 use crate::{
+    auxi::open_bib_file,
+    bib_input_key,
     bibs::get_bib_command_or_entry_and_process,
     buffer::{BufTy, GlobalBuffer},
     char_info::LexClass,
@@ -13,10 +17,14 @@ use crate::{
         hash_cite_confusion, log_pr_bib_name, nonexistent_cross_reference_error, print_a_token,
         print_bib_name, print_confusion, print_fn_class, print_missing_entry,
     },
+    peekable::PeekableInput,
     pool::{StrNumber, StringPool},
     scan::{eat_bst_white_space, scan_fn_def, scan_identifier, Scan, ScanRes},
-    Bibtex, BibtexError, GlobalItems, HashPointer,
+    BibFileStats, Bibtex, BibtexError, BibtexProgress, File, GlobalItems, HashPointer,
+    InjectedEntry,
 };
+use std::{ffi::CString, io::Cursor};
+use tectonic_io_base::{InputHandle, InputOrigin};
 
 macro_rules! eat_bst_white {
     ($ctx:ident, $globals:ident, $name:literal) => {
@@ -77,6 +85,23 @@ pub(crate) enum BstCommand {
     Strings,
 }
 
+impl BstCommand {
+    fn name(self) -> &'static str {
+        match self {
+            BstCommand::Entry => "entry",
+            BstCommand::Execute => "execute",
+            BstCommand::Function => "function",
+            BstCommand::Integers => "integers",
+            BstCommand::Iterate => "iterate",
+            BstCommand::Macro => "macro",
+            BstCommand::Read => "read",
+            BstCommand::Reverse => "reverse",
+            BstCommand::Sort => "sort",
+            BstCommand::Strings => "strings",
+        }
+    }
+}
+
 fn bst_entry_command(
     ctx: &mut ExecCtx<'_, '_, '_>,
     globals: &mut GlobalItems<'_>,
@@ -112,6 +137,10 @@ fn bst_entry_command(
         }
 
         globals.other.set_num_fields(globals.other.num_fields() + 1);
+        ctx.style_fields.push(
+            String::from_utf8_lossy(globals.pool.get_str(globals.hash.get(res.loc).text()))
+                .into_owned(),
+        );
 
         eat_bst_white!(ctx, globals, "entry");
     }
@@ -230,6 +259,8 @@ fn bst_execute_command(
     ctx.mess_with_entries = false;
 
     execute_fn(ctx, globals, fn_loc)?;
+    #[cfg(feature = "introspection")]
+    inspect_execution(ctx, globals, "execute", fn_loc, false);
     check_command_execution(ctx, globals.pool, globals.hash, globals.cites)?;
 
     Ok(())
@@ -273,6 +304,7 @@ fn bst_function_command(
     globals
         .buffers
         .set_offset(BufTy::Base, 2, globals.buffers.offset(BufTy::Base, 2) + 1);
+    ctx.impl_fn_num = 0;
     scan_fn_def(ctx, globals, res.loc, res.loc)?;
     Ok(())
 }
@@ -351,6 +383,8 @@ fn bst_iterate_command(
             .cites
             .set_ptr(globals.cites.info(sort_cite_ptr).to_raw_dangerous());
         execute_fn(ctx, globals, fn_loc)?;
+        #[cfg(feature = "introspection")]
+        inspect_execution(ctx, globals, "iterate", fn_loc, true);
         check_command_execution(ctx, globals.pool, globals.hash, globals.cites)?;
         sort_cite_ptr += 1;
     }
@@ -442,6 +476,90 @@ fn bst_macro_command(
     Ok(())
 }
 
+/// The name under which caller-supplied entries are read.
+const INJECTED_NAME: &str = "<injected entries>";
+
+/// Render caller-supplied entries in database syntax, so that they can be
+/// read just like entries in a file.
+fn render_injected_entries(entries: &[InjectedEntry]) -> String {
+    let mut text = String::new();
+    for entry in entries {
+        text.push_str(&format!("@{}{{{},\n", entry.entry_type, entry.key));
+        for (name, value) in &entry.fields {
+            text.push_str(&format!("  {name} = {{{value}}},\n"));
+        }
+        text.push_str("}\n");
+    }
+    text
+}
+
+/// Read all of the commands and entries of the database file at the top of
+/// the stack, then close it.
+fn read_bib_file(
+    ctx: &mut ExecCtx<'_, '_, '_>,
+    globals: &mut GlobalItems<'_>,
+) -> Result<(), BibtexError> {
+    globals
+        .buffers
+        .set_offset(BufTy::Base, 2, globals.buffers.init(BufTy::Base));
+
+    let mut cur_macro_loc = HashPointer::default();
+    let mut field_name_loc = HashPointer::default();
+    while !globals.bibs.top_file_mut().file.eof(ctx.engine) {
+        get_bib_command_or_entry_and_process(
+            ctx,
+            globals,
+            &mut cur_macro_loc,
+            &mut field_name_loc,
+        )?;
+    }
+    globals.bibs.pop_file().file.close(ctx)
+}
+
+/// Read the next database file, logging it as `{label} #{num}` and keeping
+/// statistics about it.
+fn read_counted_bib_file(
+    ctx: &mut ExecCtx<'_, '_, '_>,
+    globals: &mut GlobalItems<'_>,
+    label: &str,
+    num: usize,
+) -> Result<(), BibtexError> {
+    let file = globals.bibs.top_file();
+    if ctx.config.verbose {
+        ctx.write_logs(&format!("{label} #{num}: "));
+        print_bib_name(ctx, globals.pool, file.name)?;
+    } else {
+        ctx.write_log_file(&format!("{label} #{num}: "));
+        log_pr_bib_name(ctx, globals.pool, file.name)?;
+    }
+
+    let mut name = String::from_utf8_lossy(globals.pool.get_str(file.name)).into_owned();
+    if !name.ends_with(".bib") {
+        name.push_str(".bib");
+    }
+    ctx.report_progress(BibtexProgress::DatabaseOpened { name: &name });
+    ctx.bib_stats.push(BibFileStats {
+        name,
+        ..Default::default()
+    });
+    let errors_before = ctx.history.error_count();
+
+    read_bib_file(ctx, globals)?;
+
+    let errors = ctx.history.error_count().saturating_sub(errors_before) as usize;
+    let verbose = ctx.config.verbose;
+    let stats = ctx.cur_bib_stats().unwrap();
+    stats.parse_errors = errors;
+    if verbose {
+        let msg = format!(
+            "{label} #{num}: {} entries scanned, {} used, {} macros defined, {} parse errors\n",
+            stats.entries_scanned, stats.entries_used, stats.macros_defined, stats.parse_errors,
+        );
+        ctx.write_logs(&msg);
+    }
+    Ok(())
+}
+
 fn bst_read_command(
     ctx: &mut ExecCtx<'_, '_, '_>,
     globals: &mut GlobalItems<'_>,
@@ -496,32 +614,58 @@ fn bst_read_command(
     }
 
     ctx.read_performed = true;
+
+    if !ctx.config.injected_entries.is_empty() {
+        let data = render_injected_entries(&ctx.config.injected_entries);
+        let handle = InputHandle::new_read_only(
+            INJECTED_NAME,
+            Cursor::new(data.into_bytes()),
+            InputOrigin::Other,
+        );
+        globals.bibs.push_file_first(File {
+            name: globals.pool.add_string(INJECTED_NAME.as_bytes()),
+            file: PeekableInput::from_handle(handle),
+            line: 0,
+        });
+        read_bib_file(ctx, globals)?;
+    }
+
     for idx in 0..globals.bibs.len() {
-        let file = globals.bibs.top_file();
-        if ctx.config.verbose {
-            ctx.write_logs(&format!("Database file #{}: ", idx + 1));
-            print_bib_name(ctx, globals.pool, file.name)?;
-        } else {
-            ctx.write_log_file(&format!("Database file #{}: ", idx + 1));
-            log_pr_bib_name(ctx, globals.pool, file.name)?;
+        read_counted_bib_file(ctx, globals, "Database file", idx + 1)?;
+    }
+
+    ctx.reading_fallback = true;
+    let mut num_fallbacks = 0;
+    for name in ctx.config.fallback_databases.clone() {
+        let key = bib_input_key(&name);
+        let res = globals
+            .hash
+            .lookup_str_insert::<hash::BibFile>(globals.pool, key.as_bytes(), ());
+        if res.exists {
+            // Already read as one of the main databases
+            continue;
         }
 
-        globals
-            .buffers
-            .set_offset(BufTy::Base, 2, globals.buffers.init(BufTy::Base));
-
-        let mut cur_macro_loc = HashPointer::default();
-        let mut field_name_loc = HashPointer::default();
-        while !globals.bibs.top_file_mut().file.eof(ctx.engine) {
-            get_bib_command_or_entry_and_process(
-                ctx,
-                globals,
-                &mut cur_macro_loc,
-                &mut field_name_loc,
-            )?;
+        let text = globals.hash.get(res.loc).text();
+        let fname = CString::new(key).unwrap_or_default();
+        match open_bib_file(ctx, &fname) {
+            Err(_) => {
+                ctx.write_logs("Warning--I couldn't open fallback database file ");
+                print_bib_name(ctx, globals.pool, text)?;
+                ctx.mark_warning();
+            }
+            Ok(file) => {
+                globals.bibs.push_file(File {
+                    name: text,
+                    file,
+                    line: 0,
+                });
+                num_fallbacks += 1;
+                read_counted_bib_file(ctx, globals, "Fallback database file", num_fallbacks)?;
+            }
         }
-        globals.bibs.pop_file().file.close(ctx)?;
     }
+    ctx.reading_fallback = false;
 
     ctx.reading_completed = true;
     globals.cites.set_num_cites(globals.cites.ptr());
@@ -555,15 +699,15 @@ fn bst_read_command(
 
                 let field_start = cite_ptr * globals.other.num_fields();
                 let &cite = globals.hash.get(cite_loc).extra();
-                let mut parent =
+                let parent_start =
                     cite * globals.other.num_fields() + globals.other.pre_defined_fields();
-                for idx in (field_start + globals.other.pre_defined_fields())
-                    ..(field_start + globals.other.num_fields())
+                for (idx, parent) in ((field_start + globals.other.pre_defined_fields())
+                    ..(field_start + globals.other.num_fields()))
+                    .zip(parent_start..)
                 {
                     if globals.other.field(idx).is_invalid() {
                         globals.other.set_field(idx, globals.other.field(parent));
                     }
-                    parent += 1;
                 }
             }
         }
@@ -615,7 +759,7 @@ fn bst_read_command(
                     if !ctx.all_entries
                         && cite_parent_ptr >= globals.cites.old_num_cites()
                         && globals.cites.info(cite_parent_ptr).to_raw_dangerous()
-                            < ctx.config.min_crossrefs as usize
+                            < ctx.config.crossref_threshold()
                     {
                         globals.other.set_field(field_ptr, StrNumber::invalid());
                     }
@@ -643,7 +787,7 @@ fn bst_read_command(
             print_missing_entry(ctx, globals.pool, globals.cites.get_cite(cite_ptr))?;
         } else if ctx.all_entries
             || cite_ptr < globals.cites.old_num_cites()
-            || globals.cites.info(cite_ptr).to_raw_dangerous() >= ctx.config.min_crossrefs as usize
+            || globals.cites.info(cite_ptr).to_raw_dangerous() >= ctx.config.crossref_threshold()
         {
             if cite_ptr > ctx.cite_xptr {
                 if (ctx.cite_xptr + 1) * globals.other.num_fields() > globals.other.max_fields() {
@@ -765,6 +909,8 @@ fn bst_reverse_command(
             .cites
             .set_ptr(globals.cites.info(idx).to_raw_dangerous());
         execute_fn(ctx, globals, fn_loc)?;
+        #[cfg(feature = "introspection")]
+        inspect_execution(ctx, globals, "reverse", fn_loc, true);
         check_command_execution(ctx, globals.pool, globals.hash, globals.cites)?;
     }
 
@@ -903,6 +1049,9 @@ pub(crate) fn get_bst_command_and_process(
     };
 
     let cmd = *globals.hash.get(loc).extra();
+    ctx.report_progress(BibtexProgress::StyleCommand {
+        command: cmd.name(),
+    });
 
     match cmd {
         BstCommand::Entry => bst_entry_command(ctx, globals),
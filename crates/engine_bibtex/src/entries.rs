@@ -2,6 +2,15 @@ use crate::{cite::CiteInfo, ASCIICode};
 
 pub(crate) const ENT_STR_SIZE: usize = 250;
 
+/// The entry variable counts of an [`EntryData`], taken with
+/// [`EntryData::mark`].
+#[derive(Copy, Clone)]
+pub(crate) struct EntryMark {
+    num_entry_ints: usize,
+    num_entry_strs: usize,
+    sort_key_num: usize,
+}
+
 pub(crate) struct EntryData {
     num_entry_ints: usize,
     num_entry_strs: usize,
@@ -21,6 +30,22 @@ impl EntryData {
         }
     }
 
+    pub fn mark(&self) -> EntryMark {
+        EntryMark {
+            num_entry_ints: self.num_entry_ints,
+            num_entry_strs: self.num_entry_strs,
+            sort_key_num: self.sort_key_num,
+        }
+    }
+
+    /// Go back to the entry variables recorded by `mark`. The buffers holding
+    /// their values are kept, to be reused by [`EntryData::init_entries`].
+    pub fn reset(&mut self, mark: EntryMark) {
+        self.num_entry_ints = mark.num_entry_ints;
+        self.num_entry_strs = mark.num_entry_strs;
+        self.sort_key_num = mark.sort_key_num;
+    }
+
     pub fn ints(&self, pos: usize) -> i64 {
         self.entry_ints.as_ref().unwrap()[pos]
     }
@@ -74,13 +99,14 @@ impl EntryData {
 
     pub fn init_entries(&mut self, cites: &CiteInfo) {
         let num_cites = cites.num_cites();
-        self.entry_ints = Some(vec![0; (self.num_entry_ints + 1) * (num_cites + 1) + 1]);
-        self.entry_strs = Some(vec![
-            127;
-            (self.num_entry_strs + 1)
-                * (num_cites + 1)
-                * (ENT_STR_SIZE + 1)
-                + 1
-        ]);
+        let ints = self.entry_ints.get_or_insert_with(Vec::new);
+        ints.clear();
+        ints.resize((self.num_entry_ints + 1) * (num_cites + 1) + 1, 0);
+        let strs = self.entry_strs.get_or_insert_with(Vec::new);
+        strs.clear();
+        strs.resize(
+            (self.num_entry_strs + 1) * (num_cites + 1) * (ENT_STR_SIZE + 1) + 1,
+            127,
+        );
     }
 }
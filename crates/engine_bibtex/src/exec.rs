@@ -2422,6 +2422,10 @@ pub(crate) fn execute_fn(
                 match (pop1, pop2) {
                     (ExecVal::Function(f1), ExecVal::Function(f2)) => {
                         loop {
+                            if !ctx.engine.check_resource_limits() {
+                                return Err(BibtexError::Fatal);
+                            }
+
                             execute_fn(ctx, globals, f2)?;
                             let res = ctx.pop_stack(globals.pool, globals.cites)?;
                             if let ExecVal::Integer(i1) = res {
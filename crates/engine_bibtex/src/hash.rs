@@ -369,6 +369,7 @@ impl Ilk for ControlSeq {
     }
 }
 
+#[derive(Clone)]
 pub struct ExtraSlot {
     exists: u16,
     data: (
@@ -492,6 +493,11 @@ impl<T: Ilk> HashVal<'_, T> {
     }
 }
 
+/// A snapshot of a [`HashData`], taken with [`HashData::mark`].
+pub(crate) struct HashMark {
+    extras: Vec<ExtraSlot>,
+}
+
 pub(crate) struct HashData {
     data: IndexSet<Node>,
 }
@@ -503,6 +509,23 @@ impl HashData {
         }
     }
 
+    /// Record the current contents of the table, so that they can be
+    /// restored by [`HashData::truncate`].
+    pub fn mark(&self) -> HashMark {
+        HashMark {
+            extras: self.data.iter().map(|node| node.extra.clone()).collect(),
+        }
+    }
+
+    /// Go back to the contents recorded by `mark`, dropping the entries added
+    /// since then and any ilks given to the ones that were already there.
+    pub fn truncate(&mut self, mark: &HashMark) {
+        self.data.truncate(mark.extras.len());
+        for (idx, extra) in mark.extras.iter().enumerate() {
+            self.data.get_index_mut2(idx).unwrap().extra = extra.clone();
+        }
+    }
+
     pub fn undefined() -> HashPointer<BstFn> {
         HashPointer(usize::MAX, PhantomData)
     }
@@ -520,6 +543,19 @@ impl HashData {
         T::insert(&mut node.extra, val);
     }
 
+    /// Iterate over all the entries of the given ilk, in insertion order.
+    #[cfg(feature = "introspection")]
+    pub fn iter<T: Ilk>(&self) -> impl Iterator<Item = (HashPointer<T>, &T::Extra)>
+    where
+        T::Extra: 'static,
+    {
+        self.data
+            .iter()
+            .enumerate()
+            .filter(|(_, node)| node.extra.contains(T::ilk()))
+            .map(|(idx, node)| (HashPointer(idx, PhantomData), T::get(&node.extra)))
+    }
+
     pub fn lookup_str<T: Ilk>(&self, pool: &StringPool, str: &[u8]) -> Option<HashPointer<T>> {
         let hash_val = self.data.hasher().hash_one(str);
         self.data
@@ -629,4 +665,26 @@ mod tests {
         let res4 = hash.lookup_str::<Cite>(&pool, b"Hello World!");
         assert_eq!(res4, None);
     }
+
+    #[test]
+    fn test_truncate() {
+        let mut hash = HashData::new();
+        let mut pool = StringPool::new();
+
+        let res = hash.lookup_str_insert::<Text>(&mut pool, b"kept", ());
+        let mark = hash.mark();
+        let check = pool.checkpoint();
+        hash.lookup_str_insert::<Integer>(&mut pool, b"kept", 1);
+        hash.lookup_str_insert::<Text>(&mut pool, b"dropped", ());
+
+        hash.truncate(&mark);
+        pool.truncate(check);
+        assert_eq!(hash.lookup_str::<Text>(&pool, b"kept"), Some(res.loc));
+        assert_eq!(hash.lookup_str::<Integer>(&pool, b"kept"), None);
+        assert_eq!(hash.lookup_str::<Text>(&pool, b"dropped"), None);
+
+        let res2 = hash.lookup_str_insert::<Text>(&mut pool, b"added", ());
+        assert!(!res2.exists);
+        assert_eq!(pool.get_str(hash.get(res2.loc).text()), b"added");
+    }
 }
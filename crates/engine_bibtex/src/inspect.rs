@@ -0,0 +1,154 @@
+//! A view into the style interpreter's state, for testing styles.
+//!
+//! This is only available with the `introspection` Cargo feature.
+
+use crate::{
+    exec::{ExecCtx, ExecVal},
+    hash::{BstFn, HashPointer},
+    GlobalItems,
+};
+
+/// A value on the literal stack or in a style variable.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum StackValue {
+    /// An integer.
+    Integer(i64),
+
+    /// A string.
+    String(String),
+
+    /// A function, given by name.
+    Function(String),
+
+    /// The value of a field that an entry doesn't have, given by the field
+    /// name.
+    Missing(String),
+
+    /// The value used in place of a missing argument after an error.
+    Illegal,
+}
+
+/// The state of the style interpreter after it has run a function.
+///
+/// See [`BibtexEngine::inspect_execution`](crate::BibtexEngine::inspect_execution).
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ExecSnapshot {
+    /// The style command that ran the function: `"execute"`, `"iterate"`, or
+    /// `"reverse"`.
+    pub command: String,
+
+    /// The name of the function that ran.
+    pub function: String,
+
+    /// The citation key of the entry that the function ran for, if the
+    /// command runs functions once per entry.
+    pub entry: Option<String>,
+
+    /// The literal stack, from bottom to top. Anything left here is reported
+    /// as an error once the inspector returns.
+    pub stack: Vec<StackValue>,
+
+    /// The global variables declared by the style, by name, in the order that
+    /// they were declared. This includes the builtins `entry.max$` and
+    /// `global.max$`.
+    pub globals: Vec<(String, StackValue)>,
+
+    /// The entry variables of [`entry`](Self::entry), by name, in the order
+    /// that they were declared. This includes the builtin `sort.key$`, and is
+    /// empty if there is no current entry.
+    pub entry_vars: Vec<(String, StackValue)>,
+}
+
+/// A callback that receives the interpreter state after each function run by a
+/// style command.
+///
+/// See [`BibtexEngine::inspect_execution`](crate::BibtexEngine::inspect_execution).
+pub type ExecInspector = Box<dyn FnMut(&ExecSnapshot) + Send>;
+
+fn lossy(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+fn stack_value(globals: &GlobalItems<'_>, val: &ExecVal) -> StackValue {
+    let pool = &*globals.pool;
+    match val {
+        ExecVal::Integer(i) => StackValue::Integer(*i),
+        ExecVal::String(s) => StackValue::String(lossy(pool.get_str(*s))),
+        ExecVal::Function(f) => {
+            StackValue::Function(lossy(pool.get_str(globals.hash.get(*f).text())))
+        }
+        ExecVal::Missing(s) => StackValue::Missing(lossy(pool.get_str(*s))),
+        ExecVal::Illegal => StackValue::Illegal,
+    }
+}
+
+/// Report the interpreter state to the inspector, if there is one. This is
+/// called by the `execute`, `iterate`, and `reverse` commands after each
+/// function that they run, before the stack is checked.
+pub(crate) fn inspect_execution(
+    ctx: &mut ExecCtx<'_, '_, '_>,
+    globals: &GlobalItems<'_>,
+    command: &str,
+    fn_loc: HashPointer<BstFn>,
+    per_entry: bool,
+) {
+    if ctx.inspector.is_none() {
+        return;
+    }
+
+    let pool = &*globals.pool;
+    let name = |loc| lossy(pool.get_str(globals.hash.get(loc).text()));
+    let cite_ptr = globals.cites.ptr();
+
+    let mut snapshot = ExecSnapshot {
+        command: command.to_owned(),
+        function: name(fn_loc),
+        entry: per_entry.then(|| lossy(pool.get_str(globals.cites.get_cite(cite_ptr)))),
+        stack: ctx
+            .lit_stack
+            .iter()
+            .map(|val| stack_value(globals, val))
+            .collect(),
+        ..ExecSnapshot::default()
+    };
+
+    for (loc, extra) in globals.hash.iter::<BstFn>() {
+        match *extra {
+            BstFn::IntGlbl(value) => snapshot
+                .globals
+                .push((name(loc), StackValue::Integer(value))),
+            BstFn::StrGlbl(pos) => {
+                let str_ptr = globals.globals.str_ptr(pos);
+                let value = if str_ptr.is_invalid() {
+                    globals.globals.str(pos)
+                } else {
+                    pool.get_str(str_ptr)
+                };
+                snapshot
+                    .globals
+                    .push((name(loc), StackValue::String(lossy(value))));
+            }
+            BstFn::IntEntry(pos) if per_entry => {
+                let value = globals
+                    .entries
+                    .ints(cite_ptr * globals.entries.num_ent_ints() + pos);
+                snapshot
+                    .entry_vars
+                    .push((name(loc), StackValue::Integer(value)));
+            }
+            BstFn::StrEntry(pos) if per_entry => {
+                let value = globals
+                    .entries
+                    .strs(cite_ptr * globals.entries.num_ent_strs() + pos);
+                snapshot
+                    .entry_vars
+                    .push((name(loc), StackValue::String(lossy(value))));
+            }
+            _ => (),
+        }
+    }
+
+    if let Some(inspector) = &mut ctx.inspector {
+        inspector(&snapshot);
+    }
+}
@@ -25,26 +25,34 @@ use crate::{
     bibs::{BibCommand, BibData},
     bst::{get_bst_command_and_process, BstCommand},
     buffer::{BufTy, GlobalBuffer},
+    char_info::{IdClass, LexClass},
     cite::CiteInfo,
-    entries::{EntryData, ENT_STR_SIZE},
+    entries::{EntryData, EntryMark, ENT_STR_SIZE},
     exec::{ControlSeq, ExecCtx},
     global::{GlobalData, GLOB_STR_SIZE},
-    hash::{BstBuiltin, BstFn, HashData, HashPointer},
+    hash::{BstBuiltin, BstFn, HashData, HashMark, HashPointer},
     log::{
         bib_close_log, log_pr_aux_name, print_aux_name, print_confusion, sam_wrong_file_name_print,
         AsBytes,
     },
-    other::OtherData,
+    other::{OtherData, OtherMark},
     peekable::{input_ln, PeekableInput},
-    pool::{StrNumber, StringPool},
+    pool::{Checkpoint, StrNumber, StringPool},
     scan::eat_bst_white_space,
 };
 use std::{
+    collections::HashMap,
     ffi::{CStr, CString},
-    io::Write,
+    fmt,
+    io::{Read, Write},
+    mem,
 };
 use tectonic_bridge_core::{CoreBridgeLauncher, CoreBridgeState, FileFormat, OutputId};
 use tectonic_errors::prelude::*;
+use tectonic_io_base::InputHandle;
+
+#[cfg(feature = "introspection")]
+pub use crate::inspect::{ExecInspector, ExecSnapshot, StackValue};
 
 pub(crate) mod auxi;
 pub(crate) mod bibs;
@@ -56,6 +64,8 @@ pub(crate) mod entries;
 pub(crate) mod exec;
 pub(crate) mod global;
 pub(crate) mod hash;
+#[cfg(feature = "introspection")]
+pub(crate) mod inspect;
 pub(crate) mod log;
 pub(crate) mod other;
 pub(crate) mod peekable;
@@ -70,6 +80,15 @@ pub(crate) enum History {
     FatalError,
 }
 
+impl History {
+    fn error_count(self) -> u32 {
+        match self {
+            History::ErrorIssued(errs) => errs,
+            _ => 0,
+        }
+    }
+}
+
 #[doc(hidden)]
 #[derive(Debug)]
 pub(crate) enum BibtexError {
@@ -97,21 +116,320 @@ pub enum BibtexOutcome {
     Errors = 2,
 }
 
+/// How BibTeX handles an entry that is defined more than once across its
+/// databases.
+///
+/// Regardless of the policy, the log lists every location at which the
+/// entry was defined.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum DuplicateEntryPolicy {
+    /// Report an error and keep the first definition. This is the classic
+    /// BibTeX behavior.
+    #[default]
+    Error,
+
+    /// Keep the first definition, issuing a warning for each later one.
+    FirstWins,
+
+    /// Replace the earlier definition with each later one, issuing a warning.
+    LastWins,
+}
+
+/// Statistics gathered while reading one database file named in a `\bibdata`
+/// command.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct BibFileStats {
+    /// The name of the database file, as reported in the log.
+    pub name: String,
+
+    /// The number of entries found in the file, whether or not they were
+    /// cited.
+    pub entries_scanned: usize,
+
+    /// The number of entries that were stored for use by the style. An entry
+    /// that replaces an earlier definition is counted against the file that
+    /// defined it first.
+    pub entries_used: usize,
+
+    /// The number of `@string` macros defined by the file.
+    pub macros_defined: usize,
+
+    /// The number of errors issued while parsing the file.
+    pub parse_errors: usize,
+}
+
+/// How empty citation keys in the auxiliary file are handled.
+///
+/// These come from commands like `\citation{}`, or `\citation{a,,b}`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum EmptyCitationPolicy {
+    /// Treat empty keys like any other, as classic BibTeX does. This usually
+    /// ends up in a warning that there's no database entry for `""`. This is
+    /// the default.
+    #[default]
+    Keep,
+
+    /// Silently skip empty keys.
+    Ignore,
+
+    /// Skip empty keys, issuing a warning that points at the auxiliary file
+    /// line that contains them.
+    Warn,
+
+    /// Treat empty keys as an error, skipping the rest of the command.
+    Error,
+}
+
+/// Text found between the entries of a database file.
+///
+/// BibTeX ignores anything outside of its `@` commands, so this is where
+/// people put their annotations. See [`BibtexEngine::preserve_comments`].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct BibComment {
+    /// The name of the database file containing the text.
+    pub file: String,
+
+    /// The line on which the text starts.
+    pub line: u32,
+
+    /// The text itself, with lines separated by newlines. Blank lines at
+    /// either end and trailing whitespace on each line aren't preserved.
+    pub text: String,
+
+    /// Whether the text follows an `@comment` command. If so, it starts just
+    /// after the word `comment`.
+    pub comment_command: bool,
+}
+
+/// A database entry supplied by the caller rather than read from a file.
+///
+/// See [`BibtexEngine::inject_entry`].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct InjectedEntry {
+    /// The entry type, such as `"software"`.
+    pub entry_type: String,
+
+    /// The entry's citation key.
+    pub key: String,
+
+    /// The entry's fields, as pairs of names and values. Values are used
+    /// as-is, as if they were written within braces in a database file, so
+    /// any braces they contain must be balanced.
+    pub fields: Vec<(String, String)>,
+}
+
+impl InjectedEntry {
+    /// Check that the entry can be written in database syntax, so that it
+    /// will be read back as given.
+    fn validate(&self) -> Result<()> {
+        check_identifier("entry type", &self.entry_type)?;
+
+        // Keys are scanned up to the next comma, brace, or whitespace
+        if self.key.is_empty() {
+            bail!("an injected entry has an empty citation key");
+        }
+
+        if let Some(c) = self.key.bytes().find(|&c| {
+            matches!(c, b',' | b'{' | b'}')
+                || matches!(LexClass::of(c), LexClass::Whitespace | LexClass::Illegal)
+        }) {
+            bail!(
+                "the citation key `{}` of an injected entry contains the character {:?}, \
+                 which BibTeX doesn't allow there",
+                self.key,
+                c as char
+            );
+        }
+
+        for (name, value) in &self.fields {
+            check_identifier("field name", name)?;
+
+            let mut depth = 0usize;
+            for c in value.bytes() {
+                match c {
+                    b'{' => depth += 1,
+                    b'}' if depth == 0 => bail!(
+                        "the value of field `{}` of injected entry `{}` has an unmatched `}}`",
+                        name,
+                        self.key
+                    ),
+                    b'}' => depth -= 1,
+                    _ => {}
+                }
+            }
+
+            if depth != 0 {
+                bail!(
+                    "the value of field `{}` of injected entry `{}` has an unmatched `{{`",
+                    name,
+                    self.key
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Check that text can be read as a database identifier: it must be made of
+/// legal identifier characters, and not start with a digit.
+fn check_identifier(what: &str, text: &str) -> Result<()> {
+    let bytes = text.as_bytes();
+
+    if bytes.is_empty() {
+        bail!("an injected entry has an empty {}", what);
+    }
+
+    if LexClass::of(bytes[0]) == LexClass::Numeric {
+        bail!(
+            "the {} `{}` of an injected entry starts with a digit",
+            what,
+            text
+        );
+    }
+
+    if let Some(c) = bytes
+        .iter()
+        .find(|&&c| IdClass::of(c) == IdClass::IllegalIdChar)
+    {
+        bail!(
+            "the {} `{}` of an injected entry contains the character {:?}, which BibTeX \
+             doesn't allow there",
+            what,
+            text,
+            *c as char
+        );
+    }
+
+    Ok(())
+}
+
+/// The fields of a database entry that was used by the style.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct EntryFields {
+    /// The entry's citation key, as written in the database.
+    pub key: String,
+
+    /// The names of all of the fields given for the entry in the database,
+    /// lowercased, whether or not the style declares them.
+    pub fields: Vec<String>,
+}
+
+/// A structured report of a completed BibTeX engine invocation.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BibtexReport {
+    /// The overall outcome of the run.
+    pub outcome: BibtexOutcome,
+
+    /// Per-file statistics for each database that was read, in the order that
+    /// they were read.
+    pub bib_files: Vec<BibFileStats>,
+
+    /// The name of the `.bbl` output file, if it was opened.
+    pub bbl_file: Option<String>,
+
+    /// The name of the `.blg` log file, if it was opened.
+    pub blg_file: Option<String>,
+
+    /// The names of the entry fields declared by the style, in order. This
+    /// includes the `crossref` field, which BibTeX always declares. Database
+    /// fields that aren't listed here are never seen by the style.
+    pub style_fields: Vec<String>,
+
+    /// The fields given for each database entry used by the style, in the
+    /// order that the entries were read.
+    pub entry_fields: Vec<EntryFields>,
+
+    /// The text found between database entries, in the order that it was
+    /// read. This is only filled in if
+    /// [`BibtexEngine::preserve_comments`] is enabled.
+    pub comments: Vec<BibComment>,
+}
+
+/// How many entries are read from a database file between
+/// [`BibtexProgress::EntriesRead`] reports.
+pub const PROGRESS_ENTRY_INTERVAL: usize = 1000;
+
+/// The version of classic BibTeX that this engine implements.
+pub const BIBTEX_VERSION: &str = "0.99d";
+
+/// A progress update reported while the engine runs.
+///
+/// See [`BibtexEngine::progress`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum BibtexProgress<'a> {
+    /// A style file command is about to be executed. BibTeX does all of its
+    /// work in style commands, so this gives a coarse view of which phase of
+    /// processing is underway.
+    StyleCommand {
+        /// The name of the command, in lowercase, such as `"read"`.
+        command: &'a str,
+    },
+
+    /// A database file is about to be read.
+    DatabaseOpened {
+        /// The name of the file.
+        name: &'a str,
+    },
+
+    /// Another [`PROGRESS_ENTRY_INTERVAL`] entries have been read from the
+    /// current database file.
+    EntriesRead {
+        /// The name of the file.
+        name: &'a str,
+
+        /// The total number of entries read from the file so far.
+        count: usize,
+    },
+}
+
+/// A callback that receives progress updates.
+///
+/// See [`BibtexEngine::progress`].
+pub type ProgressObserver = Box<dyn FnMut(BibtexProgress<'_>) + Send>;
+
 /// A struct for invoking the BibTeX engine.
 ///
 /// This struct has a fairly straightforward "builder" interface: you create it,
 /// apply any settings that you wish, and eventually run the
 /// [`process()`](Self::process) method.
 ///
-/// Due to constraints of the gnarly C/C++ code underlying the engine
-/// implementation, only one engine may run at once in one process. The engine
-/// execution framework uses a global mutex to ensure that this is the case.
-/// This restriction applies not only to the [`BibtexEngine`] type but to *all*
-/// Tectonic engines. I.e., you can't run this engine and the XeTeX engine at
-/// the same time.
-#[derive(Debug, Default)]
+/// Unlike the other Tectonic engines, this engine is implemented entirely in
+/// Rust and keeps all of its state in the invocation, so it doesn't take the
+/// global mutex that serializes the C/C++ engines. You can run it at the same
+/// time as, say, the XeTeX engine, as long as each has its own
+/// [`CoreBridgeLauncher`]. Engines are [`Send`], so they can be set up on one
+/// thread and run on another; this is why the callbacks that they take must be
+/// `Send` too.
+#[derive(Default)]
 pub struct BibtexEngine {
     config: BibtexConfig,
+    bib_inputs: HashMap<String, Vec<u8>>,
+    bib_resolver: Option<BibResolver>,
+    progress: Option<ProgressObserver>,
+    #[cfg(feature = "introspection")]
+    inspector: Option<ExecInspector>,
+    predefined: Option<Predefined>,
+}
+
+/// A callback that maps a database name to a custom input source.
+///
+/// See [`BibtexEngine::bib_resolver`].
+pub type BibResolver = Box<dyn FnMut(&str) -> Option<InputHandle> + Send>;
+
+impl fmt::Debug for BibtexEngine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("BibtexEngine");
+        d.field("config", &self.config)
+            .field("bib_inputs", &self.bib_inputs.keys().collect::<Vec<_>>())
+            .field("bib_resolver", &self.bib_resolver.is_some())
+            .field("progress", &self.progress.is_some())
+            .field("predefined", &self.predefined.is_some());
+        #[cfg(feature = "introspection")]
+        d.field("inspector", &self.inspector.is_some());
+        d.finish()
+    }
 }
 
 impl BibtexEngine {
@@ -127,6 +445,207 @@ impl BibtexEngine {
         self
     }
 
+    /// Set whether every entry cross-referenced by a cited entry should be
+    /// included in the bibliography.
+    ///
+    /// This overrides [`min_crossrefs()`](Self::min_crossrefs), so that a
+    /// single cross-reference is enough. The default is false.
+    pub fn include_crossref_parents(&mut self, value: bool) -> &mut Self {
+        self.config.include_crossref_parents = value;
+        self
+    }
+
+    /// Set how entries defined more than once are handled.
+    ///
+    /// The default is [`DuplicateEntryPolicy::Error`].
+    pub fn duplicate_entries(&mut self, policy: DuplicateEntryPolicy) -> &mut Self {
+        self.config.duplicate_entries = policy;
+        self
+    }
+
+    /// Set how empty citation keys in the auxiliary file are handled.
+    ///
+    /// The default is [`EmptyCitationPolicy::Keep`].
+    pub fn empty_citations(&mut self, policy: EmptyCitationPolicy) -> &mut Self {
+        self.config.empty_citations = policy;
+        self
+    }
+
+    /// Set whether malformed database entries should be skipped leniently.
+    ///
+    /// Classic BibTeX lets a field value with a missing closing brace run on
+    /// until the braces happen to balance, swallowing any entries that follow
+    /// it. In lenient mode, such a value is cut off at the next line that
+    /// starts a new entry, like `@article{...`: the entry is reported as an
+    /// error and processing resumes with the entry on that line.
+    ///
+    /// Likewise, classic BibTeX gives up on the rest of an entry when one of
+    /// its fields has a malformed name, is missing its `=`, or is followed by
+    /// stray text. In lenient mode, only the rest of that field is skipped,
+    /// up to the next comma or the end of the entry, and the entry keeps its
+    /// other fields. The default is false.
+    pub fn lenient(&mut self, value: bool) -> &mut Self {
+        self.config.lenient = value;
+        self
+    }
+
+    /// Supply the contents of the style file directly.
+    ///
+    /// When set, these bytes are used as the style file instead of looking up
+    /// the file named by the `\bibstyle` command of the auxiliary file. That
+    /// command is still required, and its argument is still used to name the
+    /// style in the log.
+    pub fn bst_data(&mut self, data: impl Into<Vec<u8>>) -> &mut Self {
+        self.config.bst_data = Some(data.into());
+        self
+    }
+
+    /// Supply the contents of a database file directly.
+    ///
+    /// When the auxiliary file's `\bibdata` command names *name* (with or
+    /// without a `.bib` extension), these bytes are read instead of looking
+    /// the file up through the usual I/O stack. The data are kept around for
+    /// every subsequent call to [`process()`](Self::process).
+    pub fn bib_data(&mut self, name: impl AsRef<str>, data: impl Into<Vec<u8>>) -> &mut Self {
+        self.config
+            .bib_data
+            .insert(bib_input_key(name.as_ref()).to_owned(), data.into());
+        self
+    }
+
+    /// Supply a database file as an arbitrary input handle.
+    ///
+    /// This works like [`bib_data()`](Self::bib_data), and takes precedence
+    /// over it, but the database is only used by the next call to
+    /// [`process()`](Self::process) that reads it. The handle is read right
+    /// away, so that the engine can still be sent to another thread, and this
+    /// fails if it can't be.
+    pub fn bib_input(
+        &mut self,
+        name: impl AsRef<str>,
+        mut handle: InputHandle,
+    ) -> Result<&mut Self> {
+        let name = name.as_ref();
+        let mut data = Vec::new();
+        atry!(
+            handle.read_to_end(&mut data);
+            ["failed to read the database `{}`", name]
+        );
+        self.bib_inputs.insert(bib_input_key(name).to_owned(), data);
+        Ok(self)
+    }
+
+    /// Add an entry to the database without needing a file for it.
+    ///
+    /// Injected entries are read before any of the database files named by
+    /// the auxiliary file, and are otherwise treated just like entries that
+    /// come from them: they're only used if cited, and take part in sorting,
+    /// cross-referencing, and formatting as usual. Diagnostics about them
+    /// refer to a pseudo-file named `<injected entries>`, and they aren't
+    /// counted in the [`BibtexReport::bib_files`] statistics.
+    ///
+    /// This fails if the entry type, the key, or a field name contains
+    /// characters that BibTeX doesn't allow in identifiers, or if a field
+    /// value has unbalanced braces.
+    pub fn inject_entry(&mut self, entry: InjectedEntry) -> Result<&mut Self> {
+        entry.validate()?;
+        self.config.injected_entries.push(entry);
+        Ok(self)
+    }
+
+    /// Set a callback used to open database files from custom sources.
+    ///
+    /// When the auxiliary file's `\bibdata` command names a database that
+    /// wasn't supplied with [`bib_data()`](Self::bib_data) or
+    /// [`bib_input()`](Self::bib_input), the resolver is called with its name,
+    /// minus any `.bib` extension. If it returns a handle, the database is
+    /// read from it; otherwise the file is looked up through the usual I/O
+    /// stack.
+    pub fn bib_resolver<F>(&mut self, resolver: F) -> &mut Self
+    where
+        F: FnMut(&str) -> Option<InputHandle> + Send + 'static,
+    {
+        self.bib_resolver = Some(Box::new(resolver));
+        self
+    }
+
+    /// Set a callback that is notified as processing progresses.
+    ///
+    /// Large databases can take a while to process, so this lets callers give
+    /// some indication of what the engine is up to. See [`BibtexProgress`] for
+    /// the kinds of updates that are reported.
+    pub fn progress<F>(&mut self, observer: F) -> &mut Self
+    where
+        F: FnMut(BibtexProgress<'_>) + Send + 'static,
+    {
+        self.progress = Some(Box::new(observer));
+        self
+    }
+
+    /// Set a callback that is shown the style interpreter's state after each
+    /// function run by an `EXECUTE`, `ITERATE`, or `REVERSE` command.
+    ///
+    /// This lets style test harnesses check intermediate values, rather than
+    /// only the final `.bbl` output. See [`ExecSnapshot`] for what is shown.
+    /// This is only available with the `introspection` Cargo feature.
+    #[cfg(feature = "introspection")]
+    pub fn inspect_execution<F>(&mut self, inspector: F) -> &mut Self
+    where
+        F: FnMut(&ExecSnapshot) + Send + 'static,
+    {
+        self.inspector = Some(Box::new(inspector));
+        self
+    }
+
+    /// Set whether text between database entries should be kept.
+    ///
+    /// If enabled, the text is returned in [`BibtexReport::comments`], so that
+    /// tools that rewrite database files can carry it along. The default is
+    /// false.
+    pub fn preserve_comments(&mut self, value: bool) -> &mut Self {
+        self.config.preserve_comments = value;
+        self
+    }
+
+    /// Add a fallback database.
+    ///
+    /// Fallback databases are read after the ones named by the `\bibdata`
+    /// command, and only supply entries for citations and cross-references
+    /// that those lack. This lets a large shared bibliography back up each
+    /// document's own databases. Fallbacks are read in the order that they're
+    /// added and can be provided in the same ways as the main databases. One
+    /// that can't be found only causes a warning.
+    pub fn fallback_database(&mut self, name: impl Into<String>) -> &mut Self {
+        self.config.fallback_databases.push(name.into());
+        self
+    }
+
+    /// Set whether the engine should reuse its predefined strings across runs.
+    ///
+    /// Every run starts by filling the string pool and hash table with the
+    /// names of the commands and builtin functions that BibTeX knows about.
+    /// If this is enabled, that is done by the first call to
+    /// [`process()`](Self::process) and its results reused by later ones,
+    /// which cuts the startup cost for drivers that run BibTeX many times.
+    /// The default is false.
+    pub fn warm_start(&mut self, value: bool) -> &mut Self {
+        self.config.warm_start = value;
+        if !value {
+            self.predefined = None;
+        }
+        self
+    }
+
+    /// Set whether BibTeX should echo its full log to standard output.
+    ///
+    /// The default is false, in which case some informational messages, such
+    /// as per-database statistics, only appear in the `.blg` file (if at
+    /// all).
+    pub fn verbose(&mut self, value: bool) -> &mut Self {
+        self.config.verbose = value;
+        self
+    }
+
     /// Run BibTeX.
     ///
     /// The *launcher* parameter gives overarching environmental context in
@@ -134,23 +653,53 @@ impl BibtexEngine {
     ///
     /// The *aux* parameter gives the name of the "aux" file, created by the TeX
     /// engine, that BibTeX will process.
+    ///
+    /// On success, the returned [`BibtexReport`] describes the outcome of the
+    /// run along with statistics about the databases that were read.
     pub fn process(
         &mut self,
         launcher: &mut CoreBridgeLauncher<'_>,
         aux: &str,
-    ) -> Result<BibtexOutcome> {
+    ) -> Result<BibtexReport> {
         let caux = CString::new(aux)?;
 
-        launcher.with_global_lock(|state| {
+        launcher.with_unlocked_state(|state| {
+            state.begin_progress("running BibTeX");
             let mut ctx = Bibtex::new(state, self.config.clone());
+            ctx.bib_inputs = mem::take(&mut self.bib_inputs);
+            ctx.bib_resolver = self.bib_resolver.take();
+            ctx.progress = self.progress.take();
+            ctx.predefined = self.predefined.take();
+            #[cfg(feature = "introspection")]
+            {
+                ctx.inspector = self.inspector.take();
+            }
             let hist = bibtex_main(&mut ctx, &caux);
-
-            match hist {
-                History::Spotless => Ok(BibtexOutcome::Spotless),
-                History::WarningIssued(_) => Ok(BibtexOutcome::Warnings),
-                History::ErrorIssued(_) => Ok(BibtexOutcome::Errors),
-                History::FatalError => Err(anyhow!("unspecified fatal bibtex error")),
+            self.bib_inputs = mem::take(&mut ctx.bib_inputs);
+            self.bib_resolver = ctx.bib_resolver.take();
+            self.progress = ctx.progress.take();
+            self.predefined = ctx.predefined.take();
+            #[cfg(feature = "introspection")]
+            {
+                self.inspector = ctx.inspector.take();
             }
+
+            let outcome = match hist {
+                History::Spotless => BibtexOutcome::Spotless,
+                History::WarningIssued(_) => BibtexOutcome::Warnings,
+                History::ErrorIssued(_) => BibtexOutcome::Errors,
+                History::FatalError => bail!("unspecified fatal bibtex error"),
+            };
+
+            Ok(BibtexReport {
+                outcome,
+                bib_files: ctx.bib_stats,
+                bbl_file: ctx.bbl_name,
+                blg_file: ctx.blg_name,
+                style_fields: ctx.style_fields,
+                entry_fields: ctx.entry_fields,
+                comments: ctx.comments,
+            })
         })
     }
 }
@@ -164,6 +713,11 @@ const _: () = assert!(hash::HASH_PRIME <= hash::HASH_SIZE);
 const _: () = assert!(pool::MAX_STRINGS <= hash::HASH_SIZE);
 const _: () = assert!(cite::MAX_CITES <= pool::MAX_STRINGS);
 
+/// Get the name under which a caller-supplied database is looked up.
+pub(crate) fn bib_input_key(name: &str) -> &str {
+    name.strip_suffix(".bib").unwrap_or(name)
+}
+
 pub(crate) struct File {
     name: StrNumber,
     file: PeekableInput,
@@ -186,6 +740,16 @@ pub(crate) struct GlobalItems<'a> {
 pub(crate) struct BibtexConfig {
     pub min_crossrefs: u32,
     pub verbose: bool,
+    pub duplicate_entries: DuplicateEntryPolicy,
+    pub lenient: bool,
+    pub bst_data: Option<Vec<u8>>,
+    pub bib_data: HashMap<String, Vec<u8>>,
+    pub empty_citations: EmptyCitationPolicy,
+    pub include_crossref_parents: bool,
+    pub injected_entries: Vec<InjectedEntry>,
+    pub preserve_comments: bool,
+    pub warm_start: bool,
+    pub fallback_databases: Vec<String>,
 }
 
 impl Default for BibtexConfig {
@@ -193,6 +757,28 @@ impl Default for BibtexConfig {
         BibtexConfig {
             min_crossrefs: 2,
             verbose: false,
+            duplicate_entries: DuplicateEntryPolicy::default(),
+            lenient: false,
+            bst_data: None,
+            bib_data: HashMap::new(),
+            empty_citations: EmptyCitationPolicy::default(),
+            include_crossref_parents: false,
+            injected_entries: Vec::new(),
+            preserve_comments: false,
+            warm_start: false,
+            fallback_databases: Vec::new(),
+        }
+    }
+}
+
+impl BibtexConfig {
+    /// Get the number of cited entries that must cross-reference an entry for
+    /// it to be included in the bibliography on its own.
+    pub(crate) fn crossref_threshold(&self) -> usize {
+        if self.include_crossref_parents {
+            1
+        } else {
+            self.min_crossrefs as usize
         }
     }
 }
@@ -212,18 +798,33 @@ pub(crate) struct Bibtex<'a, 'cbs> {
     pub bst: Option<File>,
 
     pub bbl_file: Option<OutputId>,
+    pub bbl_name: Option<String>,
+    pub blg_name: Option<String>,
     pub bbl_line_num: usize,
 
+    pub bib_stats: Vec<BibFileStats>,
+    pub style_fields: Vec<String>,
+    pub entry_fields: Vec<EntryFields>,
+    pub comments: Vec<BibComment>,
+    pub bib_inputs: HashMap<String, Vec<u8>>,
+    pub bib_resolver: Option<BibResolver>,
+    pub progress: Option<ProgressObserver>,
+    #[cfg(feature = "introspection")]
+    pub inspector: Option<ExecInspector>,
+    pub predefined: Option<Predefined>,
+
     pub impl_fn_num: usize,
     pub cite_xptr: usize,
 
     pub bib_seen: bool,
     pub bst_seen: bool,
     pub citation_seen: bool,
+    pub empty_citation_seen: bool,
     pub entry_seen: bool,
     pub read_seen: bool,
     pub read_performed: bool,
     pub reading_completed: bool,
+    pub reading_fallback: bool,
     pub all_entries: bool,
 
     pub b_default: HashPointer<hash::BstFn>,
@@ -244,16 +845,30 @@ impl<'a, 'cbs> Bibtex<'a, 'cbs> {
             logs: Logs::default(),
             bst: None,
             bbl_file: None,
+            bbl_name: None,
+            blg_name: None,
             bbl_line_num: 1,
+            bib_stats: Vec::new(),
+            style_fields: Vec::new(),
+            entry_fields: Vec::new(),
+            comments: Vec::new(),
+            bib_inputs: HashMap::new(),
+            bib_resolver: None,
+            progress: None,
+            #[cfg(feature = "introspection")]
+            inspector: None,
+            predefined: None,
             impl_fn_num: 0,
             cite_xptr: 0,
             bib_seen: false,
             bst_seen: false,
             citation_seen: false,
+            empty_citation_seen: false,
             entry_seen: false,
             read_seen: false,
             read_performed: false,
             reading_completed: false,
+            reading_fallback: false,
             all_entries: false,
             b_default: HashPointer::default(),
             s_null: StrNumber::invalid(),
@@ -282,6 +897,17 @@ impl<'a, 'cbs> Bibtex<'a, 'cbs> {
         self.history = History::FatalError;
     }
 
+    pub(crate) fn report_progress(&mut self, progress: BibtexProgress<'_>) {
+        if let Some(observer) = &mut self.progress {
+            observer(progress);
+        }
+    }
+
+    /// Get the statistics for the database file currently being read.
+    pub(crate) fn cur_bib_stats(&mut self) -> Option<&mut BibFileStats> {
+        self.bib_stats.last_mut()
+    }
+
     pub(crate) fn write_logs<B: ?Sized + AsBytes>(&mut self, str: &B) {
         let _ = self
             .engine
@@ -318,7 +944,11 @@ impl<'a, 'cbs> Bibtex<'a, 'cbs> {
 
     pub(crate) fn init_log_file(&mut self, file: &CStr) -> bool {
         if self.logs.file.is_none() {
-            self.logs.file = self.engine.output_open(file.to_str().unwrap(), false);
+            let name = file.to_str().unwrap();
+            self.logs.file = self.engine.output_open(name, false);
+            if self.logs.file.is_some() {
+                self.blg_name = Some(name.to_owned());
+            }
             self.logs.file.is_some()
         } else {
             true
@@ -356,14 +986,20 @@ type FieldLoc = usize;
 
 pub(crate) fn bibtex_main(ctx: &mut Bibtex<'_, '_>, aux_file_name: &CStr) -> History {
     let mut buffers = GlobalBuffer::new();
-    let mut pool = StringPool::new();
-    let mut hash = HashData::new();
-    let mut entries = EntryData::new();
+    let (mut pool, mut hash, mut entries, mut other, mut marks) = match ctx.predefined.take() {
+        Some(pre) => pre.reset(),
+        None => (
+            StringPool::new(),
+            HashData::new(),
+            EntryData::new(),
+            OtherData::new(),
+            None,
+        ),
+    };
     let mut globals = GlobalData::new();
     let mut bibs = BibData::new();
     let mut aux = AuxData::new();
     let mut cites = CiteInfo::new();
-    let mut other = OtherData::new();
 
     let mut globals = GlobalItems {
         buffers: &mut buffers,
@@ -377,7 +1013,26 @@ pub(crate) fn bibtex_main(ctx: &mut Bibtex<'_, '_>, aux_file_name: &CStr) -> His
         other: &mut other,
     };
 
-    let res = inner_bibtex_main(ctx, &mut globals, aux_file_name);
+    let res = inner_bibtex_main(ctx, &mut globals, aux_file_name, &mut marks);
+    let hist = finish_bibtex(ctx, res);
+
+    if ctx.config.warm_start {
+        if let Some(marks) = marks {
+            ctx.predefined = Some(Predefined {
+                pool,
+                hash,
+                entries,
+                other,
+                marks,
+            });
+        }
+    }
+
+    hist
+}
+
+/// Close the output files and write the closing summary to the log.
+fn finish_bibtex(ctx: &mut Bibtex<'_, '_>, res: Result<History, BibtexError>) -> History {
     match res {
         Err(BibtexError::Recover) | Ok(History::Spotless) => {
             ctx.bst.take().map(|file| file.file.close(ctx));
@@ -517,6 +1172,7 @@ pub(crate) fn pre_def_certain_strings(
 
     let num_fields = other.num_fields();
     hash.lookup_str_insert::<BstFn>(pool, b"crossref", BstFn::Field(num_fields));
+    ctx.style_fields.push("crossref".to_owned());
     other.set_crossref_num(num_fields);
     other.set_num_fields(num_fields + 1);
     other.set_pre_defined_fields(num_fields + 1);
@@ -531,24 +1187,114 @@ pub(crate) fn pre_def_certain_strings(
     hash.lookup_str_insert::<BstFn>(pool, b"global.max$", BstFn::IntGlbl(GLOB_STR_SIZE as i64));
 }
 
+/// The state set up by [`pre_def_certain_strings`], which is the same for
+/// every run, kept around when warm starts are enabled.
+///
+/// Rather than copying the predefined strings into fresh structures, the next
+/// run takes over the ones used by the previous run, once they have been
+/// truncated back to the [`PredefinedMarks`]. This also keeps the memory that
+/// they grew into.
+pub(crate) struct Predefined {
+    pool: StringPool,
+    hash: HashData,
+    entries: EntryData,
+    other: OtherData,
+    marks: PredefinedMarks,
+}
+
+impl Predefined {
+    fn reset(
+        self,
+    ) -> (
+        StringPool,
+        HashData,
+        EntryData,
+        OtherData,
+        Option<PredefinedMarks>,
+    ) {
+        let Predefined {
+            mut pool,
+            mut hash,
+            mut entries,
+            mut other,
+            marks,
+        } = self;
+        pool.truncate(marks.pool);
+        hash.truncate(&marks.hash);
+        entries.reset(marks.entries);
+        other.reset(marks.other);
+        (pool, hash, entries, other, Some(marks))
+    }
+}
+
+/// How far the predefined strings go in each structure, along with the
+/// values that [`pre_def_certain_strings`] stores in the context.
+pub(crate) struct PredefinedMarks {
+    pool: Checkpoint,
+    hash: HashMark,
+    entries: EntryMark,
+    other: OtherMark,
+    style_fields: Vec<String>,
+    s_aux_extension: StrNumber,
+    s_null: StrNumber,
+    s_default: StrNumber,
+    b_default: HashPointer<BstFn>,
+}
+
+/// Set up the predefined strings, unless `marks` says that they are left over
+/// from an earlier run. If warm starts are enabled, record where they end.
+fn pre_define(
+    ctx: &mut Bibtex<'_, '_>,
+    globals: &mut GlobalItems<'_>,
+    marks: &mut Option<PredefinedMarks>,
+) {
+    if let Some(pre) = marks {
+        ctx.style_fields = pre.style_fields.clone();
+        ctx.s_aux_extension = pre.s_aux_extension;
+        ctx.s_null = pre.s_null;
+        ctx.s_default = pre.s_default;
+        ctx.b_default = pre.b_default;
+        return;
+    }
+
+    pre_def_certain_strings(ctx, globals);
+
+    if ctx.config.warm_start {
+        *marks = Some(PredefinedMarks {
+            pool: globals.pool.checkpoint(),
+            hash: globals.hash.mark(),
+            entries: globals.entries.mark(),
+            other: globals.other.mark(),
+            style_fields: ctx.style_fields.clone(),
+            s_aux_extension: ctx.s_aux_extension,
+            s_null: ctx.s_null,
+            s_default: ctx.s_default,
+            b_default: ctx.b_default,
+        });
+    }
+}
+
 pub(crate) fn inner_bibtex_main(
     ctx: &mut Bibtex<'_, '_>,
     globals: &mut GlobalItems<'_>,
     aux_file_name: &CStr,
+    marks: &mut Option<PredefinedMarks>,
 ) -> Result<History, BibtexError> {
     if !ctx.init_stdout() {
         return Ok(History::FatalError);
     }
 
-    pre_def_certain_strings(ctx, globals);
+    pre_define(ctx, globals, marks);
     if get_the_top_level_aux_file_name(ctx, globals, aux_file_name)? != 0 {
         return Ok(History::FatalError);
     }
 
+    let banner = format!("This is BibTeX, Version {BIBTEX_VERSION}\n");
+
     if ctx.config.verbose {
-        ctx.write_logs("This is BibTeX, Version 0.99d\n");
+        ctx.write_logs(&banner);
     } else {
-        ctx.write_log_file("This is BibTeX, Version 0.99d\n");
+        ctx.write_log_file(&banner);
     }
 
     ctx.write_log_file(&format!(
@@ -639,11 +1385,13 @@ pub(crate) fn get_the_top_level_aux_file_name(
 
     set_extension(&mut path, b".bbl");
     let bbl_file = CStr::from_bytes_with_nul(&path).unwrap();
-    ctx.bbl_file = ctx.engine.output_open(bbl_file.to_str().unwrap(), false);
+    let bbl_name = bbl_file.to_str().unwrap();
+    ctx.bbl_file = ctx.engine.output_open(bbl_name, false);
     if ctx.bbl_file.is_none() {
         sam_wrong_file_name_print(ctx, bbl_file);
         return Ok(1);
     }
+    ctx.bbl_name = Some(bbl_name.to_owned());
 
     set_extension(&mut path, b".aux");
     let lookup = hash.lookup_str_insert::<hash::AuxFile>(pool, &path[..path.len() - 1], ());
@@ -1,6 +1,6 @@
 use crate::{
     auxi::AuxData,
-    bibs::{BibCommand, BibData},
+    bibs::{BibCommand, BibData, FieldSite},
     buffer::{BufTy, GlobalBuffer},
     char_info::LexClass,
     cite::CiteInfo,
@@ -184,6 +184,17 @@ pub(crate) fn aux_err_print(
     Ok(())
 }
 
+pub(crate) fn aux_warn_print(
+    ctx: &mut Bibtex<'_, '_>,
+    aux: &AuxData,
+    pool: &StringPool,
+) -> Result<(), BibtexError> {
+    ctx.write_logs(&format!("--line {} of file ", aux.top_file().line));
+    print_aux_name(ctx, pool, aux.top_file().name)?;
+    ctx.mark_warning();
+    Ok(())
+}
+
 pub(crate) enum AuxTy {
     Data,
     Style,
@@ -343,15 +354,69 @@ pub(crate) fn bib_err_print(
     pool: &StringPool,
     bibs: &BibData,
     bib_command: Option<BibCommand>,
+) -> Result<(), BibtexError> {
+    bib_skip_print(
+        ctx,
+        buffers,
+        pool,
+        bibs,
+        if bib_command.is_some() {
+            "command"
+        } else {
+            "entry"
+        },
+    )
+}
+
+/// Like [`bib_err_print`], for errors in lenient mode after which only the
+/// rest of the current entry field is skipped.
+pub(crate) fn bib_field_err_print(
+    ctx: &mut Bibtex<'_, '_>,
+    buffers: &GlobalBuffer,
+    pool: &StringPool,
+    bibs: &BibData,
+) -> Result<(), BibtexError> {
+    bib_skip_print(ctx, buffers, pool, bibs, "field")
+}
+
+fn bib_skip_print(
+    ctx: &mut Bibtex<'_, '_>,
+    buffers: &GlobalBuffer,
+    pool: &StringPool,
+    bibs: &BibData,
+    skipped: &str,
 ) -> Result<(), BibtexError> {
     ctx.write_logs("-");
     bib_ln_num_print(ctx, pool, bibs)?;
     print_bad_input_line(ctx, buffers);
+    if bibs.unclosed_field().is_none() {
+        if let Some((site, start)) = bibs.multiline_value() {
+            ctx.write_logs("(The value of ");
+            field_site_print(ctx, site);
+            ctx.write_logs(&format!(
+                ", starting at line {start}, may have unbalanced braces)\n"
+            ));
+        }
+    }
     print_skipping_whatever_remains(ctx);
-    if bib_command.is_some() {
-        ctx.write_logs("command\n");
-    } else {
-        ctx.write_logs("entry\n");
+    ctx.write_logs(skipped);
+    ctx.write_logs("\n");
+    Ok(())
+}
+
+pub(crate) fn print_entry_sites(
+    ctx: &mut Bibtex<'_, '_>,
+    pool: &StringPool,
+    cites: &CiteInfo,
+    bibs: &BibData,
+    cite: CiteNumber,
+) -> Result<(), BibtexError> {
+    ctx.write_logs("Entry \"");
+    print_a_pool_str(ctx, cites.get_cite(cite), pool)?;
+    ctx.write_logs("\" is defined at:\n");
+    for &(name, line) in bibs.entry_sites(cite) {
+        ctx.write_logs(&format!("  line {line} of file "));
+        print_bib_name(ctx, pool, name)?;
     }
     Ok(())
 }
@@ -366,6 +431,14 @@ pub(crate) fn bib_warn_print(
     Ok(())
 }
 
+fn field_site_print(ctx: &mut Bibtex<'_, '_>, site: &FieldSite) {
+    ctx.write_logs("field \"");
+    ctx.write_logs(&site.field[..]);
+    ctx.write_logs("\" of entry \"");
+    ctx.write_logs(&site.entry[..]);
+    ctx.write_logs("\"");
+}
+
 pub(crate) fn eat_bib_print(
     ctx: &mut Bibtex<'_, '_>,
     buffers: &GlobalBuffer,
@@ -373,10 +446,39 @@ pub(crate) fn eat_bib_print(
     bibs: &BibData,
     bib_command: Option<BibCommand>,
 ) -> Result<(), BibtexError> {
-    ctx.write_logs("Illegal end of database file");
+    if let Some((site, start, runaway)) = bibs.unclosed_field() {
+        ctx.write_logs("Unbalanced braces in ");
+        field_site_print(ctx, site);
+        ctx.write_logs(&format!(" starting at line {start}"));
+        if let Some(runaway) = runaway {
+            ctx.write_logs(&format!(
+                " (its value runs into what looks like another entry on line {runaway})"
+            ));
+        }
+    } else {
+        ctx.write_logs("Illegal end of database file");
+    }
     bib_err_print(ctx, buffers, pool, bibs, bib_command)
 }
 
+pub(crate) fn runaway_field_warn_print(
+    ctx: &mut Bibtex<'_, '_>,
+    pool: &StringPool,
+    bibs: &BibData,
+    start: u32,
+    runaway: u32,
+) -> Result<(), BibtexError> {
+    let Some(site) = bibs.cur_field() else {
+        return Ok(());
+    };
+    ctx.write_logs("Warning--possibly unbalanced braces in ");
+    field_site_print(ctx, site);
+    ctx.write_logs(&format!(
+        " starting at line {start}: its value runs into what looks like another entry on line {runaway}\n"
+    ));
+    bib_warn_print(ctx, pool, bibs)
+}
+
 pub(crate) fn bib_one_of_two_print(
     ctx: &mut Bibtex<'_, '_>,
     buffers: &GlobalBuffer,
@@ -393,6 +495,21 @@ pub(crate) fn bib_one_of_two_print(
     bib_err_print(ctx, buffers, pool, bibs, bib_command)
 }
 
+pub(crate) fn bib_field_one_of_two_print(
+    ctx: &mut Bibtex<'_, '_>,
+    buffers: &GlobalBuffer,
+    pool: &StringPool,
+    bibs: &BibData,
+    char1: ASCIICode,
+    char2: ASCIICode,
+) -> Result<(), BibtexError> {
+    ctx.write_logs(&format!(
+        "I was expecting a `{}' or a `{}'",
+        char1 as char, char2 as char
+    ));
+    bib_field_err_print(ctx, buffers, pool, bibs)
+}
+
 pub(crate) fn bib_equals_sign_print(
     ctx: &mut Bibtex<'_, '_>,
     buffers: &GlobalBuffer,
@@ -404,6 +521,16 @@ pub(crate) fn bib_equals_sign_print(
     bib_err_print(ctx, buffers, pool, bibs, bib_command)
 }
 
+pub(crate) fn bib_field_equals_sign_print(
+    ctx: &mut Bibtex<'_, '_>,
+    buffers: &GlobalBuffer,
+    pool: &StringPool,
+    bibs: &BibData,
+) -> Result<(), BibtexError> {
+    ctx.write_logs("I was expecting an \"=\"");
+    bib_field_err_print(ctx, buffers, pool, bibs)
+}
+
 pub(crate) fn bib_unbalanced_braces_print(
     ctx: &mut Bibtex<'_, '_>,
     buffers: &GlobalBuffer,
@@ -11,6 +11,14 @@ pub enum WizOp {
     EndOfDef,
 }
 
+/// The field counts of an [`OtherData`], taken with [`OtherData::mark`].
+#[derive(Copy, Clone)]
+pub(crate) struct OtherMark {
+    num_fields: FieldLoc,
+    num_pre_defined_fields: FieldLoc,
+    crossref_num: FieldLoc,
+}
+
 pub(crate) struct OtherData {
     wiz_functions: Vec<WizOp>,
     field_info: Vec<StrNumber>,
@@ -30,6 +38,24 @@ impl OtherData {
         }
     }
 
+    pub fn mark(&self) -> OtherMark {
+        OtherMark {
+            num_fields: self.num_fields,
+            num_pre_defined_fields: self.num_pre_defined_fields,
+            crossref_num: self.crossref_num,
+        }
+    }
+
+    /// Go back to the fields recorded by `mark`, forgetting all field values
+    /// and wizard functions, but keeping the memory that held them.
+    pub fn reset(&mut self, mark: OtherMark) {
+        self.wiz_functions.clear();
+        self.field_info.fill(StrNumber::invalid());
+        self.num_fields = mark.num_fields;
+        self.num_pre_defined_fields = mark.num_pre_defined_fields;
+        self.crossref_num = mark.crossref_num;
+    }
+
     pub fn max_fields(&self) -> usize {
         self.field_info.len()
     }
@@ -6,12 +6,22 @@ use crate::{
 use libc::EOF;
 use std::{ffi::CStr, io};
 use tectonic_bridge_core::{CoreBridgeState, FileFormat, InputId};
+use tectonic_io_base::InputHandle;
+
+/// Where a [`PeekableInput`] gets its data from.
+enum InputSource {
+    /// An input opened through the bridge.
+    Bridge(InputId),
+    /// An input handle owned by the engine itself, such as one supplied by
+    /// the caller.
+    Owned(InputHandle),
+}
 
 /* Sigh, I'm worried about ungetc() and EOF semantics in Bibtex's I/O, so
  * here's a tiny wrapper that lets us fake it. */
 
 pub(crate) struct PeekableInput {
-    id: InputId,
+    source: InputSource,
     peek_char: libc::c_int,
     saw_eof: bool,
 }
@@ -26,7 +36,7 @@ impl PeekableInput {
 
         if let Some(id) = id {
             Ok(PeekableInput {
-                id,
+                source: InputSource::Bridge(id),
                 peek_char: EOF,
                 saw_eof: false,
             })
@@ -35,11 +45,25 @@ impl PeekableInput {
         }
     }
 
+    /// Wrap an input handle that doesn't go through the bridge.
+    pub(crate) fn from_handle(handle: InputHandle) -> PeekableInput {
+        PeekableInput {
+            source: InputSource::Owned(handle),
+            peek_char: EOF,
+            saw_eof: false,
+        }
+    }
+
     pub(crate) fn close(self, ctx: &mut Bibtex<'_, '_>) -> Result<(), BibtexError> {
-        if !ctx.engine.input_close(self.id) {
-            Ok(())
-        } else {
-            Err(BibtexError::Fatal)
+        match self.source {
+            InputSource::Bridge(id) => {
+                if !ctx.engine.input_close(id) {
+                    Ok(())
+                } else {
+                    Err(BibtexError::Fatal)
+                }
+            }
+            InputSource::Owned(_) => Ok(()),
         }
     }
 
@@ -52,7 +76,10 @@ impl PeekableInput {
 
         // SAFETY: Internal handle guaranteed valid, unique access to this input is unique access
         //         to the handle
-        let handle = engine.get_input(self.id);
+        let handle = match &mut self.source {
+            InputSource::Bridge(id) => engine.get_input(*id),
+            InputSource::Owned(handle) => handle,
+        };
         let rv = match handle.getc() {
             Ok(c) => libc::c_int::from(c),
             Err(e) => {
@@ -168,6 +168,13 @@ impl StringPool {
         self.cur_strs == check.0
     }
 
+    /// Forget every string made since the checkpoint was taken. The memory
+    /// backing them is kept, to be reused by later strings.
+    pub fn truncate(&mut self, check: Checkpoint) {
+        self.cur_strs = check.0;
+        self.pool_ptr = self.offsets[check.0 - 1];
+    }
+
     fn grow(&mut self) {
         self.strings.resize(self.strings.len() + POOL_SIZE, 0);
     }
@@ -247,4 +254,23 @@ mod tests {
         assert_eq!(new_str, new_str_2);
         assert_eq!(pool.get_str(str), pool.get_str(new_str_2));
     }
+
+    #[test]
+    fn test_truncate() {
+        let mut pool = StringPool::new();
+
+        let str1 = pool.add_string(b"String 1");
+        let check = pool.checkpoint();
+        pool.add_string(&[b'x'; POOL_SIZE]);
+        let capacity = pool.strings.capacity();
+
+        pool.truncate(check);
+        assert!(pool.is_at(check));
+        assert_eq!(pool.get_str(str1), b"String 1");
+        assert_eq!(pool.strings.capacity(), capacity);
+
+        let str2 = pool.add_string(b"String 2");
+        assert_eq!(str2, StrNumber(check.0));
+        assert_eq!(pool.get_str(str2), b"String 2");
+    }
 }
@@ -10,8 +10,8 @@ use crate::{
         bib_err_print, bib_id_print, bib_unbalanced_braces_print, bib_warn_print,
         braces_unbalanced_complaint, bst_err_print_and_look_for_blank_line, eat_bib_print,
         eat_bst_print, hash_cite_confusion, macro_warn_print, print_a_pool_str, print_confusion,
-        print_recursion_illegal, skip_illegal_stuff_after_token_print, skip_token_print,
-        skip_token_unknown_function_print,
+        print_recursion_illegal, runaway_field_warn_print, skip_illegal_stuff_after_token_print,
+        skip_token_print, skip_token_unknown_function_print,
     },
     other::WizOp,
     peekable::input_ln,
@@ -276,13 +276,17 @@ fn handle_char(
                 .buffers
                 .set_offset(BufTy::Base, 2, globals.buffers.offset(BufTy::Base, 2) + 1);
 
-            let str = format!("'{}", ctx.impl_fn_num);
+            // Implicit functions are named after the function whose definition
+            // they appear in, plus a counter local to that definition, so that
+            // editing one function doesn't rename the implicit functions of
+            // every function that follows it.
+            let mut str = vec![b'\''];
+            str.extend_from_slice(globals.pool.get_str(globals.hash.get(wiz_loc).text()));
+            str.extend_from_slice(format!("'{}", ctx.impl_fn_num).as_bytes());
 
-            let res = globals.hash.lookup_str_insert::<BstFn>(
-                globals.pool,
-                str.as_bytes(),
-                BstFn::Wizard(0),
-            );
+            let res = globals
+                .hash
+                .lookup_str_insert::<BstFn>(globals.pool, &str, BstFn::Wizard(0));
 
             if res.exists {
                 ctx.write_logs("Already encountered implicit function");
@@ -378,6 +382,7 @@ fn scan_balanced_braces(
     bib_command: Option<BibCommand>,
     right_str_delim: ASCIICode,
 ) -> Result<bool, BibtexError> {
+    bibs.open_value();
     buffers.set_offset(BufTy::Base, 2, buffers.offset(BufTy::Base, 2) + 1);
 
     if (LexClass::of(buffers.at_offset(BufTy::Base, 2)) == LexClass::Whitespace
@@ -539,6 +544,11 @@ fn scan_balanced_braces(
     }
 
     buffers.set_offset(BufTy::Base, 2, buffers.offset(BufTy::Base, 2) + 1);
+
+    if let Some((start, runaway)) = bibs.close_value() {
+        runaway_field_warn_print(ctx, pool, bibs, start, runaway)?;
+    }
+
     Ok(true)
 }
 
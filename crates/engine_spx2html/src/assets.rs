@@ -12,6 +12,7 @@ use std::{
     path::{Path, PathBuf},
 };
 use tectonic_errors::{anyhow::Context, prelude::*};
+use tectonic_io_base::digest::{self, Digest, DigestData};
 use tectonic_status_base::tt_warning;
 
 use crate::{fonts::FontEnsemble, specials::Special, Common};
@@ -63,7 +64,7 @@ impl Assets {
 
                 match kind {
                     "font-css" => {
-                        self.emit_font_css(dest_path);
+                        self.provide_font_css(dest_path);
                         true
                     }
                     _ => {
@@ -84,14 +85,14 @@ impl Assets {
         );
     }
 
-    fn emit_font_css<S: ToString>(&mut self, dest_path: S) {
+    pub(crate) fn provide_font_css<S: ToString>(&mut self, dest_path: S) {
         self.paths
             .insert(dest_path.to_string(), AssetOrigin::FontCss);
     }
 
     /// This functional must only be called if `common.out_path` is not None.
     pub(crate) fn emit(mut self, mut fonts: FontEnsemble, common: &mut Common) -> Result<()> {
-        let faces = fonts.emit(common.out_base)?;
+        let faces = fonts.emit(common.out_base, false)?;
 
         for (dest_path, origin) in self.paths.drain() {
             match origin {
@@ -103,6 +104,90 @@ impl Assets {
         Ok(())
     }
 
+    /// Emit the assets in optimized form, returning a map from the TeX paths
+    /// of the assets that have been renamed to their new paths.
+    ///
+    /// Fonts are subsetted and converted to WOFF2. Files that pages load as
+    /// subresources, like stylesheets, scripts, images, and fonts, get names
+    /// that include a hash of their contents, so that they can be cached
+    /// indefinitely, and stylesheets are rewritten to use the new names. If
+    /// `write` is false, the new names are worked out but nothing is written.
+    ///
+    /// This function must only be called if `common.out_path` is not None.
+    pub(crate) fn emit_optimized(
+        mut self,
+        mut fonts: FontEnsemble,
+        write: bool,
+        common: &mut Common,
+    ) -> Result<HashMap<String, String>> {
+        let out_base = if write { common.out_base } else { None };
+        let faces = fonts.emit(out_base, true)?;
+        let mut contents = HashMap::new();
+
+        for (dest_path, origin) in self.paths.drain() {
+            let data = match origin {
+                AssetOrigin::Copy(ref src_path) => read_copied_file(src_path, common)?,
+                AssetOrigin::FontCss => faces.clone().into_bytes(),
+            };
+
+            contents.insert(dest_path, data);
+        }
+
+        // Stylesheets can refer to other files, so their names can only be
+        // worked out once those of everything else are known.
+
+        let mut renames = HashMap::new();
+        let mut stylesheets = Vec::new();
+
+        for (path, data) in &contents {
+            if is_stylesheet(path) {
+                stylesheets.push(path);
+            } else if is_subresource(path) {
+                renames.insert(path.clone(), hashed_name(path, data));
+            }
+        }
+
+        // Stylesheets can import each other, too, so we go around until the
+        // names stop changing.
+
+        for _ in 0..=stylesheets.len() {
+            let mut changed = false;
+
+            for path in &stylesheets {
+                let data = rewrite_stylesheet(&contents[*path], path, &renames);
+                let name = hashed_name(path, &data);
+
+                if renames.insert((*path).clone(), name.clone()) != Some(name) {
+                    changed = true;
+                }
+            }
+
+            if !changed {
+                break;
+            }
+        }
+
+        if write {
+            for (path, data) in &contents {
+                let data = if is_stylesheet(path) {
+                    rewrite_stylesheet(data, path, &renames)
+                } else {
+                    Cow::Borrowed(&data[..])
+                };
+
+                let dest_path = renames.get(path).unwrap_or(path);
+                let (mut out_file, out_path) = create_asset_file(dest_path, common)?;
+
+                atry!(
+                    out_file.write_all(&data);
+                    ["cannot write output file `{}`", out_path.display()]
+                );
+            }
+        }
+
+        Ok(renames)
+    }
+
     pub(crate) fn into_serialize(mut self, fonts: FontEnsemble) -> impl Serialize {
         let (mut assets, css_data) = fonts.into_serialize();
 
@@ -141,6 +226,25 @@ fn emit_copied_file(src_tex_path: &str, dest_path: &str, common: &mut Common) ->
     Ok(())
 }
 
+fn read_copied_file(src_tex_path: &str, common: &mut Common) -> Result<Vec<u8>> {
+    let mut ih = atry!(
+        common.hooks.io().input_open_name(src_tex_path, common.status).must_exist();
+        ["unable to open provideFile source `{}`", &src_tex_path]
+    );
+
+    let mut data = Vec::new();
+    atry!(
+        ih.read_to_end(&mut data);
+        ["unable to read provideFile source `{}`", &src_tex_path]
+    );
+
+    let (name, digest_opt) = ih.into_name_digest();
+    common
+        .hooks
+        .event_input_closed(name, digest_opt, common.status);
+    Ok(data)
+}
+
 /// This functional must only be called if `common.out_path` is not None.
 fn emit_font_css(dest_path: &str, faces: &str, common: &mut Common) -> Result<()> {
     let (mut out_file, out_path) = create_asset_file(dest_path, common)?;
@@ -221,6 +325,144 @@ pub(crate) fn create_output_path(
     Ok((out_path, n_levels))
 }
 
+/// The extensions of the files that get hashed names when assets are
+/// optimized. Other files, like HTML pages, might be linked to from elsewhere,
+/// so they keep their names.
+const SUBRESOURCE_EXTENSIONS: &[&str] = &[
+    "css", "js", "mjs", "png", "jpg", "jpeg", "gif", "svg", "webp", "woff", "woff2", "ttf", "otf",
+];
+
+fn has_extension(path: &str, exts: &[&str]) -> bool {
+    let file = path.rsplit('/').next().unwrap_or(path);
+
+    file.rsplit_once('.')
+        .is_some_and(|(_, ext)| exts.iter().any(|e| ext.eq_ignore_ascii_case(e)))
+}
+
+fn is_subresource(path: &str) -> bool {
+    has_extension(path, SUBRESOURCE_EXTENSIONS)
+}
+
+fn is_stylesheet(path: &str) -> bool {
+    has_extension(path, &["css"])
+}
+
+/// Insert a hash of a file's contents into its name, before the extension, so
+/// that the name changes whenever the contents do.
+pub(crate) fn hashed_name(path: &str, data: &[u8]) -> String {
+    let mut dc = digest::create();
+    dc.update(data);
+    let hash = DigestData::from(dc).to_string();
+    let hash = &hash[..16];
+
+    let (dir, file) = match path.rfind('/') {
+        Some(i) => path.split_at(i + 1),
+        None => ("", path),
+    };
+
+    match file.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => format!("{dir}{stem}-{hash}.{ext}"),
+        _ => format!("{path}-{hash}"),
+    }
+}
+
+/// Rewrite the references in a stylesheet to use renamed assets. Stylesheets
+/// that aren't UTF-8 are left alone.
+fn rewrite_stylesheet<'a>(
+    data: &'a [u8],
+    path: &str,
+    renames: &HashMap<String, String>,
+) -> Cow<'a, [u8]> {
+    match std::str::from_utf8(data) {
+        Ok(text) => Cow::Owned(rewrite_references(text, path, renames).into_bytes()),
+        Err(_) => Cow::Borrowed(data),
+    }
+}
+
+/// Rewrite the references to renamed assets in an HTML or CSS file.
+///
+/// `file_path` is the TeX path of the file. References are recognized in two
+/// forms: the relative URL of the asset, and the path of the asset from the
+/// top of the output tree prefixed with enough `../` to get there, which is
+/// how Tectonic's templates use `tduxRelTop`. Text that merely contains a
+/// reference as part of a longer path isn't touched.
+pub(crate) fn rewrite_references(
+    text: &str,
+    file_path: &str,
+    renames: &HashMap<String, String>,
+) -> String {
+    let depth = file_path.split('/').filter(|p| !p.is_empty()).count();
+    let rel_top = "../".repeat(depth.saturating_sub(1));
+
+    let mut renames: Vec<_> = renames.iter().collect();
+    renames.sort();
+    let mut text = text.to_owned();
+
+    for (orig, new) in renames {
+        text = replace_references(
+            &text,
+            &format!("{rel_top}{orig}"),
+            &format!("{rel_top}{new}"),
+        );
+
+        let rel_orig = relative_url(file_path, orig);
+
+        if rel_orig != format!("{rel_top}{orig}") {
+            text = replace_references(&text, &rel_orig, &relative_url(file_path, new));
+        }
+    }
+
+    text
+}
+
+fn is_url_char(c: char) -> bool {
+    c.is_alphanumeric() || "-_./~%+".contains(c)
+}
+
+/// Replace the occurrences of a URL that aren't part of a longer one.
+fn replace_references(text: &str, from: &str, to: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut copied = 0;
+
+    for (start, _) in text.match_indices(from) {
+        // We allow an explicit `./` before the URL, but not `../`.
+        let before = &text[..start];
+        let before = match before.strip_suffix("./") {
+            Some(b) if !b.ends_with('.') => b,
+            _ => before,
+        };
+
+        let end = start + from.len();
+
+        if !before.ends_with(is_url_char) && !text[end..].starts_with(is_url_char) {
+            result.push_str(&text[copied..start]);
+            result.push_str(to);
+            copied = end;
+        }
+    }
+
+    result.push_str(&text[copied..]);
+    result
+}
+
+/// Get the relative URL of the output at TeX path `target` from the output at
+/// `from_file`.
+fn relative_url(from_file: &str, target: &str) -> String {
+    let from: Vec<_> = from_file.split('/').filter(|p| !p.is_empty()).collect();
+    let from_dir = &from[..from.len().saturating_sub(1)];
+    let target: Vec<_> = target.split('/').filter(|p| !p.is_empty()).collect();
+
+    let n_common = from_dir
+        .iter()
+        .zip(&target[..target.len().saturating_sub(1)])
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut url = "../".repeat(from_dir.len() - n_common);
+    url.push_str(&target[n_common..].join("/"));
+    url
+}
+
 /// Information about assets that have been defined in an SPX-to-HTML run.
 #[derive(Clone, Debug, Default)]
 pub struct AssetSpecification(syntax::Assets);
@@ -281,9 +523,10 @@ impl AssetSpecification {
                         }
 
                         // We have two font assets with the same source. We need
-                        // to merge the vglyph information, but otherwise we're
-                        // good!
+                        // to merge the vglyph and used-glyph information, but
+                        // otherwise we're good!
                         syntax::merge_vglyphs(&mut cur_ff.vglyphs, &new_ff.vglyphs);
+                        cur_ff.used_glyphs.extend(&new_ff.used_glyphs);
                     }
 
                     (AO::FontCss(new_fe), AO::FontCss(cur_fe)) => {
@@ -450,9 +693,11 @@ impl<'a> Iterator for AssetOutputsIterator<'a> {
 /// The top-level type is Assets.
 pub(crate) mod syntax {
     use serde::{Deserialize, Serialize, Serializer};
-    use std::collections::{BTreeMap, HashMap};
+    use std::collections::{BTreeMap, BTreeSet, HashMap};
     use tectonic_errors::prelude::*;
 
+    use crate::fontfile::GlyphId;
+
     /// Annoyingly we need to wrap this hashmap in a struct because we need to
     /// customize the serializer to sort the keys for reproducible outputs.
     /// Likewise for all other hashmaps in this module.
@@ -532,6 +777,10 @@ pub(crate) mod syntax {
         /// GlyphIds.
         #[serde(serialize_with = "ordered_map")]
         pub vglyphs: HashMap<String, GlyphVariantMapping>,
+
+        /// The glyphs that the document uses, which need to be kept if the
+        /// font is subsetted.
+        pub used_glyphs: BTreeSet<GlyphId>,
     }
 
     /// Merge one table of variant glyph USV mappings into another.
@@ -646,3 +895,70 @@ pub(crate) mod syntax {
         BoldItalic,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashed_names() {
+        let a = hashed_name("fonts/lmroman12.woff2", b"a");
+        assert!(a.starts_with("fonts/lmroman12-"));
+        assert!(a.ends_with(".woff2"));
+        assert_eq!(a.len(), "fonts/lmroman12-.woff2".len() + 16);
+        assert_eq!(a, hashed_name("fonts/lmroman12.woff2", b"a"));
+        assert_ne!(a, hashed_name("fonts/lmroman12.woff2", b"b"));
+
+        let b = hashed_name("dir.d/.hidden", b"a");
+        assert!(b.starts_with("dir.d/.hidden-"));
+    }
+
+    #[test]
+    fn relative_urls() {
+        assert_eq!(relative_url("index.html", "tdux.css"), "tdux.css");
+        assert_eq!(relative_url("a/b.html", "tdux.css"), "../tdux.css");
+        assert_eq!(relative_url("a/b.html", "a/c.png"), "c.png");
+        assert_eq!(relative_url("a/b/c.html", "a/d/e.png"), "../d/e.png");
+    }
+
+    #[test]
+    fn rewriting() {
+        let renames: HashMap<_, _> = [("tdux.css".to_owned(), "tdux-0123.css".to_owned())]
+            .into_iter()
+            .collect();
+
+        assert_eq!(
+            rewrite_references(r#"<link href="tdux.css">"#, "index.html", &renames),
+            r#"<link href="tdux-0123.css">"#
+        );
+        assert_eq!(
+            rewrite_references(r#"<link href="./tdux.css">"#, "index.html", &renames),
+            r#"<link href="./tdux-0123.css">"#
+        );
+
+        // Longer paths that end with the name aren't references to it.
+        for text in [
+            r#"<link href="mytdux.css">"#,
+            r#"<link href="other/tdux.css">"#,
+            r#"<link href="../tdux.css">"#,
+            r#"<link href="tdux.css.map">"#,
+        ] {
+            assert_eq!(rewrite_references(text, "index.html", &renames), text);
+        }
+
+        // In a nested page, both the `tduxRelTop` form and the relative URL are
+        // recognized.
+        let renames: HashMap<_, _> = [("sub/x.png".to_owned(), "sub/x-0123.png".to_owned())]
+            .into_iter()
+            .collect();
+
+        assert_eq!(
+            rewrite_references(
+                r#"<img src="../sub/x.png"><img src="x.png">"#,
+                "sub/page.html",
+                &renames
+            ),
+            r#"<img src="../sub/x-0123.png"><img src="x-0123.png">"#
+        );
+    }
+}
@@ -16,6 +16,7 @@ use crate::{
     finalization::FinalizingState,
     fonts::{FamilyRelativeFontId, FontEnsemble, FontFamilyAnalysis, PathToNewFont},
     html::Element,
+    mathml::{self, MathGlyph, MathRule},
     specials::Special,
     templating::Templating,
     Common, FixedPoint, TexFontNum,
@@ -569,6 +570,14 @@ impl EmittingState {
 
         let el = self.create_elem(tagname, true, common);
 
+        if self.is_split_point(&el, common) {
+            self.templating
+                .emit_split_page(self.content.take(), &mut self.assets, common)?;
+
+            let cur_space_width = self.fonts.maybe_get_font_space_width(None);
+            self.content.update_content_pos(0, cur_space_width);
+        }
+
         let mut elstate = {
             let cur = self.cur_elstate();
 
@@ -759,6 +768,21 @@ impl EmittingState {
         Ok(())
     }
 
+    /// Should a new page be started before this element? That's the case for
+    /// headings at or above the split level, if they're at the top level of
+    /// the document and there's already content for the current page.
+    fn is_split_point(&self, el: &Element, common: &Common) -> bool {
+        let (Some(split_level), Some(level)) = (common.split_level, el.heading_level()) else {
+            return false;
+        };
+
+        level <= split_level
+            && self.elem_stack.len() == 1
+            && self.current_canvas.is_none()
+            && !self.content.is_empty()
+            && self.templating.ready_to_output()
+    }
+
     pub(crate) fn handle_text_and_glyphs(
         &mut self,
         font_num: TexFontNum,
@@ -778,6 +802,11 @@ impl EmittingState {
                 });
             }
         } else if !glyphs.is_empty() {
+            atry!(
+                self.fonts.mark_text_used(font_num, text, glyphs);
+                ["undeclared font {} in text", font_num]
+            );
+
             self.set_up_for_font(xs[0], font_num, common);
             self.push_space_if_needed(xs[0], Some(font_num));
             self.content.push_with_html_escaping(text);
@@ -1070,6 +1099,67 @@ impl EmittingState {
             }
         }
 
+        // If MathML output is enabled, try to reconstruct the math from its
+        // layout. If that fails, we render the canvas as usual, but mark it up
+        // as an image with a plain-text description.
+
+        let mut alt_text = None;
+
+        if common.mathml {
+            let mut glyphs = Vec::with_capacity(canvas.glyphs.len());
+            let mut all_mapped = true;
+
+            for gi in &canvas.glyphs[..] {
+                let ch = atry!(
+                    self.fonts.get_glyph_char(gi.font_num, gi.glyph);
+                    ["undeclared font {} in canvas", gi.font_num]
+                );
+                let gm = atry!(
+                    self.fonts.get_glyph_metrics(gi.font_num, gi.glyph);
+                    ["undeclared font {} in canvas", gi.font_num]
+                );
+
+                if let Some(ch) = ch {
+                    glyphs.push(MathGlyph {
+                        ch,
+                        x: gi.dx,
+                        y: gi.dy,
+                        advance: gm.map(|gm| gm.advance).unwrap_or(0),
+                        size: self.fonts.get_font_size(gi.font_num),
+                    });
+                } else {
+                    all_mapped = false;
+                }
+            }
+
+            let rules: Vec<MathRule> = canvas
+                .rules
+                .iter()
+                .map(|ri| MathRule {
+                    x: ri.dx,
+                    y: ri.dy,
+                    width: ri.width,
+                    height: ri.height,
+                })
+                .collect();
+
+            let mathml = if all_mapped {
+                mathml::layout_to_mathml(&glyphs, &rules, !inline)
+            } else {
+                None
+            };
+
+            if let Some(mathml) = mathml {
+                self.content.push_str(&mathml);
+                let cur_space_width = self.fonts.maybe_get_font_space_width(None);
+                self.content
+                    .update_content_pos(x_max_tex + canvas.x0, cur_space_width);
+                return Ok(());
+            }
+
+            alt_text = Some(mathml::fallback_alt_text(&glyphs));
+        }
+
         // Now that we have that information, we can lay out the individual
         // glyphs.
         //
@@ -1180,7 +1270,7 @@ impl EmittingState {
 
         write!(
             self.content,
-            "<{} class=\"canvas {}\" style=\"width: {}rem; height: {}rem; padding-left: {}rem{}\">",
+            "<{} class=\"canvas {}\" style=\"width: {}rem; height: {}rem; padding-left: {}rem{}\"",
             element.name(),
             layout_class,
             (x_max_tex - x_min_tex) as f32 * self.rems_per_tex,
@@ -1189,6 +1279,15 @@ impl EmittingState {
             valign,
         )
         .unwrap();
+
+        if let Some(alt_text) = alt_text {
+            self.content.push_str(" role=\"img\" aria-label=\"");
+            self.content
+                .push_with_html_double_quoted_attribute_escaping(alt_text);
+            self.content.push_char('"');
+        }
+
+        self.content.push_char('>');
         self.content.push_str(&inner_content);
         write!(self.content, "</{}>", element.name()).unwrap();
         let cur_space_width = self.fonts.maybe_get_font_space_width(None);
@@ -1199,8 +1298,7 @@ impl EmittingState {
 
     fn finish_file(&mut self, common: &mut Common) -> Result<()> {
         self.templating
-            .set_variable("tduxContent", self.content.take());
-        self.templating.emit(common)?;
+            .emit_content(self.content.take(), &mut self.assets, common)?;
 
         let cur_space_width = self.fonts.maybe_get_font_space_width(None);
         self.content.update_content_pos(0, cur_space_width);
@@ -1208,7 +1306,12 @@ impl EmittingState {
     }
 
     pub(crate) fn emission_finished(mut self, common: &mut Common) -> Result<FinalizingState> {
-        if !self.content.is_empty() {
+        // Documents without Tectonic's HTML support files never emit
+        // explicitly, and their content all goes into the built-in template.
+
+        if self.templating.using_default_template() {
+            self.finish_file(common)?;
+        } else if !self.content.is_empty() {
             tt_warning!(
                 common.status,
                 "non-empty content left at the end without an explicit `emit` in HTML output"
@@ -7,7 +7,11 @@ use tectonic_errors::prelude::*;
 use tectonic_status_base::tt_warning;
 
 use crate::{
-    assets::Assets, fonts::FontEnsemble, specials::Special, templating::Templating, Common,
+    assets::Assets,
+    fonts::FontEnsemble,
+    specials::Special,
+    templating::{DeferredOutput, Templating},
+    Common,
 };
 
 #[derive(Debug)]
@@ -85,12 +89,12 @@ impl FinalizingState {
     }
 
     fn finish_file(&mut self, common: &mut Common) -> Result<()> {
-        self.templating.set_variable("tduxContent", "");
-        self.templating.emit(common)?;
+        self.templating
+            .emit_content(String::new(), &mut self.assets, common)?;
         Ok(())
     }
 
-    pub(crate) fn finished(self) -> (FontEnsemble, Assets) {
-        (self.fonts, self.assets)
+    pub(crate) fn finished(self) -> (FontEnsemble, Assets, Vec<DeferredOutput>) {
+        (self.fonts, self.assets, self.templating.into_deferred())
     }
 }
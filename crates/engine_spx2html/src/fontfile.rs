@@ -18,10 +18,18 @@ use pinot::{
     types::{FWord, Tag, UfWord},
     FontDataRef, TableProvider,
 };
-use std::{collections::HashMap, num::Wrapping, path::Path};
+use std::{
+    collections::{BTreeSet, HashMap},
+    num::Wrapping,
+    path::Path,
+};
 use tectonic_errors::prelude::*;
 
-use crate::FixedPoint;
+use crate::{
+    assets::hashed_name,
+    subset::{SfntTables, CMAP},
+    woff2, FixedPoint,
+};
 
 /// A numerical identifier of a glyph in a font.
 pub type GlyphId = u16;
@@ -45,6 +53,13 @@ pub struct FontFileData {
     /// Information about how glyphs can be reverse-mapped to Unicode input
     gmap: HashMap<GlyphId, MapEntry>,
 
+    /// The font's direct mapping from Unicode characters to glyphs.
+    cmap: HashMap<char, GlyphId>,
+
+    /// The glyphs that the document has used, which need to be kept when the
+    /// font is subsetted.
+    used_glyphs: BTreeSet<GlyphId>,
+
     /// The glyph for the basic space character, or zero (typically .notdef) if
     /// it can't be found.
     space_glyph: GlyphId,
@@ -109,7 +124,8 @@ pub enum MapEntry {
 }
 
 impl MapEntry {
-    fn get_char(&self) -> char {
+    /// Get the Unicode character that the glyph is derived from.
+    pub fn get_char(&self) -> char {
         match *self {
             MapEntry::Direct(c) => c,
             MapEntry::SubSuperScript(c, _) => c,
@@ -203,6 +219,7 @@ impl FontFileData {
         );
 
         let mut gmap = HashMap::new();
+        let mut fwd_cmap = HashMap::new();
         let mut space_glyph = 0;
 
         for usv in valid_usvs() {
@@ -220,6 +237,7 @@ impl FontFileData {
             }
 
             gmap.insert(gidx, MapEntry::Direct(c));
+            fwd_cmap.insert(c, gidx);
         }
 
         // Check for additional substitution-based mappings.
@@ -299,6 +317,8 @@ impl FontFileData {
         Ok(FontFileData {
             buffer,
             gmap,
+            cmap: fwd_cmap,
+            used_glyphs: BTreeSet::new(),
             space_glyph,
             units_per_em,
             hmetrics,
@@ -403,6 +423,29 @@ impl FontFileData {
         Some(*map)
     }
 
+    /// Record that a glyph has been used in the document.
+    pub fn mark_glyph_used(&mut self, glyph: GlyphId) {
+        self.used_glyphs.insert(glyph);
+    }
+
+    /// Record that a piece of text has been displayed in this font.
+    ///
+    /// Browsers do their own text shaping, so besides the glyphs that TeX
+    /// chose for the text, we keep the ones that the font maps its characters
+    /// to directly.
+    pub fn mark_text_used(&mut self, text: &str) {
+        for c in text.chars() {
+            if let Some(g) = self.cmap.get(&c) {
+                self.used_glyphs.insert(*g);
+            }
+        }
+    }
+
+    /// Get the glyphs that have been used in the document.
+    pub fn used_glyphs(&self) -> &BTreeSet<GlyphId> {
+        &self.used_glyphs
+    }
+
     /// Emit customized fonts to the filesystem and return information so that
     /// appropriate CSS can be generated. Consumes the object.
     ///
@@ -414,12 +457,24 @@ impl FontFileData {
     /// `out_base` is the output directory, or None if we shouldn't be writing
     /// anything to disk.
     ///
+    /// If `optimize` is true, the fonts are subsetted to the glyphs that have
+    /// been used, converted to WOFF2, and given names that include a hash of
+    /// their contents. Fonts that can't be handled this way, such as members
+    /// of font collections, are emitted as usual.
+    ///
     /// Return value is a vec of (variant-map-index, CSS-src-field).
     pub fn emit(
         self,
         out_base: Option<&Path>,
         rel_path: &str,
+        optimize: bool,
     ) -> Result<Vec<(Option<usize>, String)>> {
+        if optimize {
+            if let Some(tables) = SfntTables::parse(&self.buffer) {
+                return self.emit_optimized(tables, out_base, rel_path);
+            }
+        }
+
         // Write the main font file ... maybe.
 
         let mut out_path = out_base.map(|p| p.to_owned());
@@ -444,13 +499,7 @@ impl FontFileData {
         let orig_len = buffer.len();
 
         for cur_map_index in 0.. {
-            let mut mappings = Vec::new();
-
-            for (glyph, altmap) in &self.variant_map_allocations {
-                if altmap.variant_map_index == cur_map_index {
-                    mappings.push((altmap.usv, *glyph));
-                }
-            }
+            let mappings = variant_mappings(&self.variant_map_allocations, cur_map_index);
 
             if mappings.is_empty() {
                 break;
@@ -463,12 +512,8 @@ impl FontFileData {
 
             if let Some(out_path) = out_path.as_mut() {
                 // Step 1: create new CMAP, appending to buffer.
-                //
-                // Might be nice to sort mappings as we construct it, rather than
-                // after the fact?
 
                 buffer.truncate(orig_len);
-                mappings.sort_unstable();
                 append_simple_cmap(&mut buffer, &mappings[..]);
                 let cmap_size = buffer.len() - orig_len;
 
@@ -512,6 +557,54 @@ impl FontFileData {
         Ok(rv)
     }
 
+    /// The optimizing version of [`Self::emit`].
+    fn emit_optimized(
+        self,
+        mut tables: SfntTables,
+        out_base: Option<&Path>,
+        rel_path: &str,
+    ) -> Result<Vec<(Option<usize>, String)>> {
+        // We always need the space, which we insert into the HTML ourselves,
+        // and any glyphs that other sessions allocated variants for.
+
+        let mut keep = self.used_glyphs.clone();
+        keep.insert(self.space_glyph);
+        keep.extend(self.variant_map_allocations.keys().copied());
+        tables.subset(&keep);
+
+        let stem = rel_path
+            .rsplit_once('.')
+            .map(|(stem, _)| stem)
+            .unwrap_or(rel_path);
+        let mut rv = vec![(
+            None,
+            emit_woff2(&tables, out_base, &format!("{stem}.woff2"))?,
+        )];
+
+        // The variants just need different character maps.
+
+        for cur_map_index in 0.. {
+            let mappings = variant_mappings(&self.variant_map_allocations, cur_map_index);
+
+            if mappings.is_empty() {
+                break;
+            }
+
+            let mut cmap = Vec::new();
+            append_simple_cmap(&mut cmap, &mappings[..]);
+            let mut variant = tables.clone();
+            variant.set_table(CMAP, cmap);
+
+            let varname = format!("vg{cur_map_index}{stem}.woff2");
+            rv.push((
+                Some(cur_map_index),
+                emit_woff2(&variant, out_base, &varname)?,
+            ));
+        }
+
+        Ok(rv)
+    }
+
     /// Emit customized fonts to the filesystem and return information so that
     /// appropriate CSS can be generated. Consumes the object.
     ///
@@ -542,10 +635,49 @@ impl FontFileData {
             *c = std::cmp::max(mapping.index + 1, *c);
         }
 
+        self.used_glyphs.extend(&ffad.used_glyphs);
         self.no_new_variants = true;
     }
 }
 
+/// Get the glyphs that are mapped by one of the variant character maps,
+/// sorted by USV.
+fn variant_mappings(
+    allocations: &HashMap<GlyphId, GlyphVariantMapping>,
+    variant_map_index: usize,
+) -> Vec<(char, GlyphId)> {
+    let mut mappings: Vec<_> = allocations
+        .iter()
+        .filter(|(_, altmap)| altmap.variant_map_index == variant_map_index)
+        .map(|(glyph, altmap)| (altmap.usv, *glyph))
+        .collect();
+    mappings.sort_unstable();
+    mappings
+}
+
+/// Encode a font as WOFF2 and write it out, under a name derived from
+/// `rel_path` that includes a hash of its contents. Returns the value of the
+/// CSS `src` field that loads it.
+fn emit_woff2(tables: &SfntTables, out_base: Option<&Path>, rel_path: &str) -> Result<String> {
+    let data = a_ok_or!(
+        woff2::encode(&tables.to_sfnt());
+        ["failed to encode font `{}` as WOFF2", rel_path]
+    );
+
+    let name = hashed_name(rel_path, &data);
+
+    if let Some(out_base) = out_base {
+        let out_path = out_base.join(&name);
+        atry!(
+            std::fs::write(&out_path, &data);
+            ["cannot write output file `{}`", out_path.display()]
+        );
+    }
+
+    let rel_url = utf8_percent_encode(&name, CONTROLS).to_string();
+    Ok(format!(r#"url("{rel_url}") format("woff2")"#))
+}
+
 fn load_ssty_mappings(
     map: &mut HashMap<GlyphId, MapEntry>,
     feat: &Feature,
@@ -622,7 +754,7 @@ fn valid_usvs() -> impl Iterator<Item = Usv> {
     (0..0xD800).chain(0xE000..0x11_0000)
 }
 
-fn opentype_checksum(data: &[u8]) -> u32 {
+pub(crate) fn opentype_checksum(data: &[u8]) -> u32 {
     let mut iter = data.chunks_exact(4);
     let cs: Wrapping<u32> = iter
         .by_ref()
@@ -255,6 +255,39 @@ impl FontEnsemble {
             .lookup_metrics(glyph, tfi.size))
     }
 
+    /// Record that a run of text has been displayed in a font, so that the
+    /// glyphs it needs will be kept if the font is subsetted.
+    pub fn mark_text_used(
+        &mut self,
+        fnum: TexFontNum,
+        text: &str,
+        glyphs: &[GlyphId],
+    ) -> Result<()> {
+        let fid = self.lookup_tex(fnum)?.fid;
+        let details = &mut self.font_files[fid].details;
+
+        for glyph in glyphs {
+            details.mark_glyph_used(*glyph);
+        }
+
+        details.mark_text_used(text);
+        Ok(())
+    }
+
+    /// Get the Unicode character that a glyph in a font represents.
+    ///
+    /// Unlike [`Self::process_glyph_for_canvas`], this doesn't set up anything
+    /// needed to render the glyph: variant glyphs are mapped to the character
+    /// they're derived from. The return value is only `Err` if the font number
+    /// is undeclared.
+    pub fn get_glyph_char(&self, fnum: TexFontNum, glyph: GlyphId) -> Result<Option<char>> {
+        let tfi = self.lookup_tex(fnum)?;
+        Ok(self.font_files[tfi.fid]
+            .details
+            .lookup_mapping(glyph)
+            .map(|m| m.get_char()))
+    }
+
     /// Get information needed to render a glyph in a canvas context.
     ///
     /// The return value is a tuple `(text_info, size, baseline_factor)`. In
@@ -383,13 +416,16 @@ impl FontEnsemble {
 
     /// Emit the font files and return CSS code setting up the files.
     ///
+    /// If `optimize` is true, the fonts are subsetted and converted to WOFF2;
+    /// see [`FontFileData::emit`].
+    ///
     /// This function clears this object's internal data structures, making it
     /// effectively unusable for subsequent operations.
-    pub fn emit(&mut self, out_base: Option<&Path>) -> Result<String> {
+    pub fn emit(&mut self, out_base: Option<&Path>, optimize: bool) -> Result<String> {
         let mut faces = String::default();
 
         for font in self.font_files.drain(..) {
-            font.emit(out_base, &mut faces)?;
+            font.emit(out_base, optimize, &mut faces)?;
         }
 
         Ok(faces)
@@ -401,12 +437,14 @@ impl FontEnsemble {
         let mut fid_to_filename = Vec::new();
 
         for font in self.font_files.drain(..) {
+            let used_glyphs = font.details.used_glyphs().clone();
             let vglyphs = font.details.into_vglyphs();
 
             let ffad = syntax::FontFileAssetData {
                 source: font.src_tex_path,
                 face_index: font.face_index,
                 vglyphs,
+                used_glyphs,
             };
 
             let filename = ffad.source.clone();
@@ -614,6 +652,8 @@ fn get_text_info(
     glyph: GlyphId,
     status: &mut dyn StatusBackend,
 ) -> Option<(char, String)> {
+    font.details.mark_glyph_used(glyph);
+
     let text_info = font.details.lookup_mapping(glyph).map(|mc| {
         let (mut ch, need_alt) = match mc {
             MapEntry::Direct(c) => (c, false),
@@ -749,8 +789,8 @@ impl Font {
         }
     }
 
-    fn emit<W: Write>(self, out_base: Option<&Path>, mut dest: W) -> Result<()> {
-        for (var_index, css_src) in self.details.emit(out_base, &self.out_rel_path)? {
+    fn emit<W: Write>(self, out_base: Option<&Path>, optimize: bool, mut dest: W) -> Result<()> {
+        for (var_index, css_src) in self.details.emit(out_base, &self.out_rel_path, optimize)? {
             // This is almost identical to `selection_style_text`. A major
             // factor is that we're consuming `self`, with `self.details`
             // already consumed by the `emit()` call, so we can't borrow &self.
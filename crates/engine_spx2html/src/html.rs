@@ -238,3 +238,18 @@ emit_element_data! {
     [Wbr "wbr" deprecated(false) empty(true) autoclosed()],
     [Xmp "xmp" deprecated(true) empty(false) autoclosed()],
 }
+
+impl Element {
+    /// If this is a heading element, `<h1>` through `<h6>`, get its level.
+    pub fn heading_level(&self) -> Option<u8> {
+        match self {
+            Element::H1 => Some(1),
+            Element::H2 => Some(2),
+            Element::H3 => Some(3),
+            Element::H4 => Some(4),
+            Element::H5 => Some(5),
+            Element::H6 => Some(6),
+            _ => None,
+        }
+    }
+}
@@ -9,8 +9,11 @@ use tectonic_io_base::OpenResult;
 use tectonic_status_base::tt_warning;
 
 use crate::{
-    fonts::FontEnsemble, html::Element, specials::Special, templating::Templating, Common,
-    EmittingState, FixedPoint, TexFontNum,
+    fonts::FontEnsemble,
+    html::Element,
+    specials::Special,
+    templating::{Templating, DEFAULT_TEMPLATE_NAME},
+    Common, EmittingState, FixedPoint, TexFontNum,
 };
 
 #[derive(Debug)]
@@ -33,7 +36,7 @@ impl Default for InitializationState {
     fn default() -> Self {
         InitializationState {
             templates: Default::default(),
-            next_template_path: Default::default(),
+            next_template_path: DEFAULT_TEMPLATE_NAME.to_owned(),
             next_output_path: "index.html".to_owned(),
 
             fonts: Default::default(),
@@ -329,8 +332,11 @@ impl InitializationState {
             ["couldn't initialize Tera templating engine in temporary directory `{}`", p]
         );
 
+        // Templates provided to the engine are added last, so that they
+        // replace any of the document's templates with the same names.
+
         atry!(
-            tera.add_raw_templates(self.templates.iter());
+            tera.add_raw_templates(self.templates.iter().chain(common.templates.iter()));
             ["couldn't compile Tera templates"]
         );
 
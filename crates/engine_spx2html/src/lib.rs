@@ -6,10 +6,13 @@
 //! SPX is essentially the same thing as XDV, but we identify it differently to
 //! mark that the semantics of the content wil be set up for HTML output.
 
-use std::path::{Path, PathBuf};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
 use tectonic_bridge_core::DriverHooks;
 use tectonic_errors::prelude::*;
-use tectonic_status_base::StatusBackend;
+use tectonic_status_base::{Progress, StatusBackend};
 use tectonic_xdv::{FileType, XdvEvents, XdvParser};
 
 mod assets;
@@ -19,12 +22,15 @@ mod fontfile;
 mod fonts;
 mod html;
 mod initialization;
+mod mathml;
 mod specials;
+mod subset;
 mod templating;
+mod woff2;
 
 use self::{
     assets::Assets, emission::EmittingState, finalization::FinalizingState, fonts::FontEnsemble,
-    initialization::InitializationState, specials::Special,
+    initialization::InitializationState, specials::Special, templating::DeferredOutput,
 };
 
 /// An engine that converts SPX to HTML.
@@ -34,6 +40,10 @@ pub struct Spx2HtmlEngine {
     precomputed_assets: Option<AssetSpecification>,
     assets_spec_path: Option<String>,
     do_not_emit_assets: bool,
+    mathml: bool,
+    templates: HashMap<String, String>,
+    optimize_assets: bool,
+    split_level: Option<u8>,
 }
 
 #[derive(Debug, Default)]
@@ -123,6 +133,79 @@ impl Spx2HtmlEngine {
         self
     }
 
+    /// Specify whether math should be emitted as MathML.
+    ///
+    /// By default, the glyphs and rules of math "canvases" are positioned
+    /// absolutely with CSS, which looks right but is opaque to screen readers
+    /// and doesn't reflow. If this setting is enabled, the engine instead tries
+    /// to reconstruct the structure of each equation from its layout and emit
+    /// it as a MathML `<math>` element. Equations whose layout can't be
+    /// interpreted fall back to the CSS rendering, marked up as an image with a
+    /// plain-text description of the math.
+    pub fn mathml(&mut self, enabled: bool) -> &mut Self {
+        self.mathml = enabled;
+        self
+    }
+
+    /// Provide a Tera template that takes precedence over the document's.
+    ///
+    /// Documents set up their HTML output by registering templates and
+    /// choosing among them with SPX specials. A template given here replaces
+    /// any that the document registers under the same name, both when it's
+    /// used as the template for an output file and when another template
+    /// includes or extends it. Templates with new names are available for
+    /// inclusion, too. This makes it possible to restyle the shell of the
+    /// output — its head, navigation, footer, and so on — without changing the
+    /// document or its support files.
+    ///
+    /// Documents that don't choose a template are rendered with a plain
+    /// built-in one named `tdux-default.html`, which can be replaced in the
+    /// same way.
+    pub fn template(&mut self, name: impl Into<String>, contents: impl Into<String>) -> &mut Self {
+        self.templates.insert(name.into(), contents.into());
+        self
+    }
+
+    /// Specify whether the assets should be optimized for publication.
+    ///
+    /// If this setting is enabled, fonts are cut down to the glyphs that the
+    /// document uses and converted to the compact WOFF2 format. Fonts, and
+    /// other assets that pages load such as stylesheets, scripts, and images,
+    /// are given names that include a hash of their contents, so that web
+    /// servers can tell browsers to cache them indefinitely. Stylesheets and
+    /// HTML outputs are rewritten to refer to the new names.
+    ///
+    /// The fonts keep all of their layout tables, and the glyphs that browsers
+    /// need to render the document's text are kept along with those that TeX
+    /// chose, but text whose shaping depends on features that TeX didn't use
+    /// may find that glyphs are missing.
+    ///
+    /// This setting has no effect when an asset specification is being saved
+    /// with [`Self::assets_spec_path`], since the final form of the assets
+    /// isn't known until the specifications have been merged. The glyphs that
+    /// each session uses are recorded in the specification, though, so
+    /// sessions using the merged specification will all agree on the
+    /// optimized assets.
+    pub fn optimize_assets(&mut self, enabled: bool) -> &mut Self {
+        self.optimize_assets = enabled;
+        self
+    }
+
+    /// Specify whether the output should be split into several pages.
+    ///
+    /// If a level is given, a new page is started at each heading element of
+    /// that level or higher that's at the top level of the document, so that
+    /// a level of 2 splits the output at its `<h1>` and `<h2>` headings. The
+    /// first page keeps the output path that the document chose, such as
+    /// `index.html`, and the others are numbered after it: `index-2.html`,
+    /// `index-3.html`, and so on. Each page ends with links to the previous
+    /// and next ones. If no level is given, which is the default, the output
+    /// isn't split.
+    pub fn split_level(&mut self, level: Option<u8>) -> &mut Self {
+        self.split_level = level;
+        self
+    }
+
     /// Process SPX into HTML.
     ///
     /// Before calling this function, you must explicitly specify the output
@@ -143,9 +226,24 @@ impl Spx2HtmlEngine {
         };
 
         {
-            let state = EngineState::new(hooks, status, out_base, self.precomputed_assets.as_ref());
-            let state = XdvParser::process_with_seeks(&mut input, state)?;
-            let (fonts, assets, mut common) = state.finished()?;
+            let state = EngineState::new(
+                hooks,
+                status,
+                out_base,
+                self.precomputed_assets.as_ref(),
+                self.mathml,
+                &self.templates,
+                self.optimize_assets && self.assets_spec_path.is_none(),
+                self.split_level,
+            );
+            let state = match XdvParser::process_with_seeks(&mut input, state) {
+                Ok(s) => s,
+                Err(e) => {
+                    status.finish_progress();
+                    return Err(e);
+                }
+            };
+            let (fonts, assets, deferred, mut common) = state.finished()?;
 
             if let Some(asp) = self.assets_spec_path.as_ref() {
                 let ser = assets.into_serialize(fonts);
@@ -153,6 +251,17 @@ impl Spx2HtmlEngine {
                 serde_json::to_writer_pretty(&mut output, &ser)?;
                 let (name, digest) = output.into_name_digest();
                 hooks.event_output_closed(name, digest);
+            } else if common.optimize_assets {
+                // We need to work out the names of the assets even if we
+                // aren't writing them, so that we can update the outputs.
+                if common.out_base.is_some() {
+                    let renames =
+                        assets.emit_optimized(fonts, !self.do_not_emit_assets, &mut common)?;
+
+                    for output in deferred {
+                        output.write(&renames)?;
+                    }
+                }
             } else if !self.do_not_emit_assets {
                 assets.emit(fonts, &mut common)?;
             }
@@ -169,6 +278,7 @@ pub use assets::AssetSpecification;
 struct EngineState<'a> {
     common: Common<'a>,
     state: State,
+    progress: Progress,
 }
 
 struct Common<'a> {
@@ -176,23 +286,40 @@ struct Common<'a> {
     status: &'a mut dyn StatusBackend,
     out_base: Option<&'a Path>,
     precomputed_assets: Option<&'a AssetSpecification>,
+    mathml: bool,
+    templates: &'a HashMap<String, String>,
+    optimize_assets: bool,
+    split_level: Option<u8>,
 }
 
 impl<'a> EngineState<'a> {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         hooks: &'a mut dyn DriverHooks,
         status: &'a mut dyn StatusBackend,
         out_base: Option<&'a Path>,
         precomputed_assets: Option<&'a AssetSpecification>,
+        mathml: bool,
+        templates: &'a HashMap<String, String>,
+        optimize_assets: bool,
+        split_level: Option<u8>,
     ) -> Self {
+        let progress = Progress::new("writing HTML");
+        status.report_progress(&progress);
+
         Self {
             common: Common {
                 hooks,
                 status,
                 out_base,
                 precomputed_assets,
+                mathml,
+                templates,
+                optimize_assets,
+                split_level,
             },
             state: State::Initializing(InitializationState::default()),
+            progress,
         }
     }
 }
@@ -208,11 +335,13 @@ enum State {
 }
 
 impl<'a> EngineState<'a> {
-    pub fn finished(mut self) -> Result<(FontEnsemble, Assets, Common<'a>)> {
+    #[allow(clippy::type_complexity)]
+    pub fn finished(mut self) -> Result<(FontEnsemble, Assets, Vec<DeferredOutput>, Common<'a>)> {
+        self.common.status.finish_progress();
         self.state.ensure_finalizing(&mut self.common)?;
 
         if let State::Finalizing(s) = self.state {
-            let (fonts, mut assets) = s.finished();
+            let (fonts, mut assets, deferred) = s.finished();
 
             // If we have precomputed assets, make sure that this run didn't
             // define anything surprising, and sync up the runtime manifest with
@@ -221,7 +350,7 @@ impl<'a> EngineState<'a> {
                 precomputed.check_runtime_assets(&mut assets)?;
             }
 
-            Ok((fonts, assets, self.common))
+            Ok((fonts, assets, deferred, self.common))
         } else {
             panic!("invalid spx2html finalization state leaked");
         }
@@ -250,6 +379,12 @@ impl XdvEvents for EngineState<'_> {
         Ok(())
     }
 
+    fn handle_begin_page(&mut self, _counters: &[i32], _previous_bop: i32) -> Result<()> {
+        self.progress.page = Some(self.progress.page.unwrap_or(0) + 1);
+        self.common.status.report_progress(&self.progress);
+        Ok(())
+    }
+
     fn handle_special(&mut self, x: i32, y: i32, contents: &[u8]) -> Result<()> {
         let contents = atry!(std::str::from_utf8(contents); ["could not parse \\special as UTF-8"]);
 
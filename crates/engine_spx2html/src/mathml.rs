@@ -0,0 +1,1211 @@
+// Copyright 2025 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Reconstructing MathML from the layout of a math canvas.
+//!
+//! By the time TeX's math typesetting reaches us, all that is left of an
+//! equation is a set of positioned glyphs and rules. This module tries to
+//! recover the structure that produced them: fraction bars with material above
+//! and below, radical signs attached to their vinculums, large operators with
+//! limits, accents, and sub- and superscripts that are set in smaller fonts
+//! with shifted baselines. The heuristics are tuned for the layouts that TeX
+//! produces with OpenType math fonts. When a layout doesn't fit them, no
+//! MathML is produced and the caller should fall back to rendering the canvas
+//! directly.
+
+use std::fmt::Write as FmtWrite;
+
+use crate::FixedPoint;
+
+/// A glyph in a math canvas, with its position and extent in TeX units.
+///
+/// Vertical coordinates increase downward, as in the SPX file.
+#[derive(Clone, Debug)]
+pub(crate) struct MathGlyph {
+    /// The Unicode character that the glyph represents.
+    pub ch: char,
+
+    /// The horizontal position of the glyph origin.
+    pub x: i32,
+
+    /// The vertical position of the glyph baseline.
+    pub y: i32,
+
+    /// The glyph's advance width.
+    pub advance: FixedPoint,
+
+    /// The size of the font that the glyph is set in.
+    pub size: FixedPoint,
+}
+
+/// A rule in a math canvas, in TeX units.
+///
+/// As in DVI, `y` is the position of the bottom edge of the rule.
+#[derive(Clone, Debug)]
+pub(crate) struct MathRule {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl MathRule {
+    fn top(&self) -> i32 {
+        self.y - self.height
+    }
+
+    fn mid(&self) -> i32 {
+        self.y - self.height / 2
+    }
+
+    fn right(&self) -> i32 {
+        self.x + self.width
+    }
+}
+
+/// Try to reconstruct MathML from the glyphs and rules of a math canvas.
+///
+/// On success, the return value is a `<math>` element whose `alttext`
+/// attribute gives a plain-text rendition of the math. Returns `None` if the
+/// layout isn't one that we know how to interpret.
+pub(crate) fn layout_to_mathml(
+    glyphs: &[MathGlyph],
+    rules: &[MathRule],
+    display: bool,
+) -> Option<String> {
+    let node = analyze(glyphs, rules)?;
+
+    let mut alt_text = String::new();
+    node.write_alt_text(&mut alt_text);
+
+    let mut mathml = String::new();
+    write!(
+        mathml,
+        "<math xmlns=\"http://www.w3.org/1998/Math/MathML\" display=\"{}\" alttext=\"",
+        if display { "block" } else { "inline" }
+    )
+    .unwrap();
+    html_escape::encode_double_quoted_attribute_to_string(&alt_text, &mut mathml);
+    mathml.push_str("\">");
+    node.write_mathml(&mut mathml);
+    mathml.push_str("</math>");
+
+    Some(mathml)
+}
+
+/// Produce a plain-text rendition of a canvas that couldn't be converted to
+/// MathML: just its characters, in left-to-right order.
+pub(crate) fn fallback_alt_text(glyphs: &[MathGlyph]) -> String {
+    let mut sorted: Vec<&MathGlyph> = glyphs.iter().collect();
+    sorted.sort_by_key(|g| (g.x, g.y));
+    sorted.into_iter().map(|g| g.ch).collect()
+}
+
+// The structure we build up.
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum TokenKind {
+    Identifier,
+    Number,
+    Operator,
+}
+
+#[derive(Clone, Debug)]
+enum Node {
+    Token(TokenKind, String),
+    Row(Vec<Node>),
+    Fraction(Box<Node>, Box<Node>),
+    Sqrt(Box<Node>),
+    Root(Box<Node>, Box<Node>),
+    Sub(Box<Node>, Box<Node>),
+    Sup(Box<Node>, Box<Node>),
+    SubSup(Box<Node>, Box<Node>, Box<Node>),
+    Under(Box<Node>, Box<Node>, bool),
+    Over(Box<Node>, Box<Node>, bool),
+    UnderOver(Box<Node>, Box<Node>, Box<Node>),
+    Table(Vec<Node>),
+}
+
+impl Node {
+    fn operator(ch: char) -> Node {
+        Node::Token(TokenKind::Operator, ch.to_string())
+    }
+
+    fn row(mut nodes: Vec<Node>) -> Node {
+        if nodes.len() == 1 {
+            nodes.pop().unwrap()
+        } else {
+            Node::Row(nodes)
+        }
+    }
+
+    fn write_mathml(&self, out: &mut String) {
+        match self {
+            Node::Token(kind, text) => {
+                let tag = match kind {
+                    TokenKind::Identifier => "mi",
+                    TokenKind::Number => "mn",
+                    TokenKind::Operator => "mo",
+                };
+
+                // A single-character <mi> is rendered in italics by default,
+                // but Unicode math fonts mark italics with dedicated
+                // codepoints, so plain ASCII letters are upright.
+                let mut chars = text.chars();
+
+                if let (TokenKind::Identifier, Some(c), None) = (kind, chars.next(), chars.next()) {
+                    if c.is_ascii_alphabetic() {
+                        write!(out, "<{tag} mathvariant=\"normal\">").unwrap();
+                    } else {
+                        write!(out, "<{tag}>").unwrap();
+                    }
+                } else {
+                    write!(out, "<{tag}>").unwrap();
+                }
+
+                html_escape::encode_text_to_string(text, out);
+                write!(out, "</{tag}>").unwrap();
+            }
+
+            Node::Row(nodes) => {
+                out.push_str("<mrow>");
+                for n in nodes {
+                    n.write_mathml(out);
+                }
+                out.push_str("</mrow>");
+            }
+
+            Node::Fraction(num, den) => write_element(out, "mfrac", "", &[num, den]),
+            Node::Sqrt(base) => write_element(out, "msqrt", "", &[base]),
+            Node::Root(base, index) => write_element(out, "mroot", "", &[base, index]),
+            Node::Sub(base, sub) => write_element(out, "msub", "", &[base, sub]),
+            Node::Sup(base, sup) => write_element(out, "msup", "", &[base, sup]),
+            Node::SubSup(base, sub, sup) => write_element(out, "msubsup", "", &[base, sub, sup]),
+
+            Node::Under(base, under, accent) => write_element(
+                out,
+                "munder",
+                if *accent { " accentunder=\"true\"" } else { "" },
+                &[base, under],
+            ),
+
+            Node::Over(base, over, accent) => write_element(
+                out,
+                "mover",
+                if *accent { " accent=\"true\"" } else { "" },
+                &[base, over],
+            ),
+
+            Node::UnderOver(base, under, over) => {
+                write_element(out, "munderover", "", &[base, under, over])
+            }
+
+            Node::Table(rows) => {
+                out.push_str("<mtable>");
+                for r in rows {
+                    out.push_str("<mtr><mtd>");
+                    r.write_mathml(out);
+                    out.push_str("</mtd></mtr>");
+                }
+                out.push_str("</mtable>");
+            }
+        }
+    }
+
+    fn write_alt_text(&self, out: &mut String) {
+        match self {
+            Node::Token(_, text) => out.push_str(text),
+
+            Node::Row(nodes) => {
+                for n in nodes {
+                    n.write_alt_text(out);
+                }
+            }
+
+            Node::Fraction(num, den) => {
+                num.write_alt_text_grouped(out);
+                out.push('/');
+                den.write_alt_text_grouped(out);
+            }
+
+            Node::Sqrt(base) => {
+                out.push('√');
+                base.write_alt_text_grouped(out);
+            }
+
+            Node::Root(base, index) => {
+                out.push_str("root(");
+                index.write_alt_text(out);
+                out.push_str(", ");
+                base.write_alt_text(out);
+                out.push(')');
+            }
+
+            Node::Sub(base, sub) => {
+                base.write_alt_text(out);
+                out.push('_');
+                sub.write_alt_text_grouped(out);
+            }
+
+            Node::Sup(base, sup) => {
+                base.write_alt_text(out);
+                out.push('^');
+                sup.write_alt_text_grouped(out);
+            }
+
+            Node::SubSup(base, sub, sup) | Node::UnderOver(base, sub, sup) => {
+                base.write_alt_text(out);
+                out.push('_');
+                sub.write_alt_text_grouped(out);
+                out.push('^');
+                sup.write_alt_text_grouped(out);
+            }
+
+            Node::Under(base, under, _) => {
+                base.write_alt_text(out);
+                out.push('_');
+                under.write_alt_text_grouped(out);
+            }
+
+            Node::Over(base, over, _) => {
+                base.write_alt_text(out);
+                out.push('^');
+                over.write_alt_text_grouped(out);
+            }
+
+            Node::Table(rows) => {
+                for (i, r) in rows.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str("; ");
+                    }
+                    r.write_alt_text(out);
+                }
+            }
+        }
+    }
+
+    /// Write alt text, parenthesized unless this node is a single token.
+    fn write_alt_text_grouped(&self, out: &mut String) {
+        if let Node::Token(..) = self {
+            self.write_alt_text(out);
+        } else {
+            out.push('(');
+            self.write_alt_text(out);
+            out.push(')');
+        }
+    }
+}
+
+fn write_element(out: &mut String, tag: &str, attrs: &str, children: &[&Node]) {
+    write!(out, "<{tag}{attrs}>").unwrap();
+    for c in children {
+        c.write_mathml(out);
+    }
+    write!(out, "</{tag}>").unwrap();
+}
+
+// Layout analysis.
+
+/// Something in the canvas: either a glyph, or a structure that we've already
+/// assembled from glyphs and rules.
+#[derive(Clone, Debug)]
+struct Item {
+    id: usize,
+    content: ItemContent,
+    x0: i32,
+    x1: i32,
+    baseline: i32,
+    size: i32,
+}
+
+#[derive(Clone, Debug)]
+enum ItemContent {
+    Glyph(char),
+    Built(Node),
+}
+
+impl Item {
+    fn center(&self) -> i32 {
+        self.x0 + (self.x1 - self.x0) / 2
+    }
+
+    fn glyph_char(&self) -> Option<char> {
+        match self.content {
+            ItemContent::Glyph(c) => Some(c),
+            ItemContent::Built(_) => None,
+        }
+    }
+
+    fn into_node(self) -> Node {
+        match self.content {
+            ItemContent::Glyph(c) => Node::Token(token_kind(c), c.to_string()),
+            ItemContent::Built(n) => n,
+        }
+    }
+}
+
+/// The things around which structures get built. They're processed from
+/// narrowest to widest, so that inner structures are assembled before the
+/// structures that contain them.
+#[derive(Clone, Copy, Debug)]
+enum Anchor {
+    Rule(usize),
+    Glyph(usize),
+}
+
+fn analyze(glyphs: &[MathGlyph], rules: &[MathRule]) -> Option<Node> {
+    let mut items: Vec<Item> = glyphs
+        .iter()
+        .enumerate()
+        .map(|(id, g)| Item {
+            id,
+            content: ItemContent::Glyph(assembly_piece_char(g.ch).unwrap_or(g.ch)),
+            x0: g.x,
+            x1: g.x + g.advance.max(0),
+            baseline: g.y,
+            size: g.size,
+        })
+        .collect();
+
+    merge_assembly_pieces(&mut items);
+
+    // Glyphs go before rules of the same width, so that an accent over a
+    // fraction's numerator is attached before the fraction is built.
+    let mut anchors: Vec<(i32, bool, Anchor)> = rules
+        .iter()
+        .enumerate()
+        .map(|(i, r)| (r.width, true, Anchor::Rule(i)))
+        .collect();
+
+    for it in &items {
+        if let Some(c) = it.glyph_char() {
+            if is_large_operator(c) || is_accent(c) {
+                anchors.push((it.x1 - it.x0, false, Anchor::Glyph(it.id)));
+            }
+        }
+    }
+
+    anchors.sort_by_key(|(width, is_rule, _)| (*width, *is_rule));
+
+    let mut next_id = glyphs.len();
+    let mut pending_rules: Vec<&MathRule> = rules.iter().collect();
+
+    for (_, _, anchor) in anchors {
+        match anchor {
+            Anchor::Rule(i) => {
+                let rule = &rules[i];
+                pending_rules.retain(|r| !std::ptr::eq(*r, rule));
+                let built = build_around_rule(&mut items, rule, &pending_rules, next_id)?;
+                items.push(built);
+                next_id += 1;
+            }
+
+            Anchor::Glyph(id) => {
+                let Some(idx) = items.iter().position(|it| it.id == id) else {
+                    continue;
+                };
+
+                if let Some(built) = build_around_glyph(&mut items, idx, &pending_rules, next_id)? {
+                    items.push(built);
+                    next_id += 1;
+                }
+            }
+        }
+    }
+
+    build_row(items)
+}
+
+/// The pieces of assembled delimiters map to their own Unicode characters.
+/// Map them back to the delimiters that they make up.
+fn assembly_piece_char(c: char) -> Option<char> {
+    Some(match c {
+        '\u{239B}'..='\u{239D}' => '(',
+        '\u{239E}'..='\u{23A0}' => ')',
+        '\u{23A1}'..='\u{23A3}' => '[',
+        '\u{23A4}'..='\u{23A6}' => ']',
+        '\u{23A7}'..='\u{23A9}' | '\u{23AA}' => '{',
+        '\u{23AB}'..='\u{23AD}' => '}',
+        '\u{2320}' | '\u{2321}' | '\u{23AE}' => '∫',
+        '\u{23B7}' => '√',
+        _ => return None,
+    })
+}
+
+/// Merge vertically stacked copies of the same delimiter, which is what
+/// assembled delimiters look like after [`assembly_piece_char`].
+fn merge_assembly_pieces(items: &mut Vec<Item>) {
+    let mut i = 0;
+
+    while i < items.len() {
+        let Some(c) = items[i].glyph_char() else {
+            i += 1;
+            continue;
+        };
+
+        if !is_fence(c) && c != '∫' && c != '√' {
+            i += 1;
+            continue;
+        }
+
+        let mut j = i + 1;
+        let mut merged = false;
+
+        while j < items.len() {
+            let (a, b) = (&items[i], &items[j]);
+            let overlap = a.x1.min(b.x1) - a.x0.max(b.x0);
+            let narrower = (a.x1 - a.x0).min(b.x1 - b.x0);
+
+            if b.glyph_char() == Some(c)
+                && overlap * 2 > narrower
+                && (a.baseline - b.baseline).abs() <= 3 * a.size.max(b.size)
+                && a.baseline != b.baseline
+            {
+                let b = items.remove(j);
+                let a = &mut items[i];
+                a.x0 = a.x0.min(b.x0);
+                a.x1 = a.x1.max(b.x1);
+                a.baseline = a.baseline.max(b.baseline);
+                merged = true;
+            } else {
+                j += 1;
+            }
+        }
+
+        if !merged {
+            i += 1;
+        }
+    }
+}
+
+/// Is the material at the center of `it` separated vertically from position
+/// `y` by one of the rules that haven't been dealt with yet?
+fn is_blocked(it: &Item, y: i32, pending_rules: &[&MathRule]) -> bool {
+    let cx = it.center();
+    let (lo, hi) = if it.baseline < y {
+        (it.baseline, y)
+    } else {
+        (y, it.baseline)
+    };
+
+    pending_rules
+        .iter()
+        .any(|r| r.x <= cx && cx <= r.right() && lo < r.mid() && r.mid() < hi)
+}
+
+/// Remove the items whose indices are given, returning them.
+fn take_items(items: &mut Vec<Item>, mut indices: Vec<usize>) -> Vec<Item> {
+    indices.sort_unstable();
+    let mut taken: Vec<Item> = indices.into_iter().rev().map(|i| items.remove(i)).collect();
+    taken.reverse();
+    taken
+}
+
+/// Find the position and size of the main baseline of a set of items: the most
+/// common baseline among the items set in the largest font.
+fn main_baseline(items: &[Item]) -> (i32, i32) {
+    let size = items.iter().map(|it| it.size).max().unwrap_or(0);
+    let mut best = (0, 0);
+
+    for it in items.iter().filter(|it| it.size * 10 >= size * 9) {
+        let count = items
+            .iter()
+            .filter(|o| o.size * 10 >= size * 9 && (o.baseline - it.baseline).abs() <= size / 10)
+            .count();
+
+        if count > best.0 {
+            best = (count, it.baseline);
+        }
+    }
+
+    (best.1, size)
+}
+
+fn bounds(items: &[Item]) -> (i32, i32) {
+    let x0 = items.iter().map(|it| it.x0).min().unwrap_or(0);
+    let x1 = items.iter().map(|it| it.x1).max().unwrap_or(0);
+    (x0, x1)
+}
+
+fn build_around_rule(
+    items: &mut Vec<Item>,
+    rule: &MathRule,
+    pending_rules: &[&MathRule],
+    id: usize,
+) -> Option<Item> {
+    // Vertical rules don't correspond to anything that we can express.
+    if rule.width <= rule.height {
+        return None;
+    }
+
+    // Is this the vinculum of a radical?
+
+    let radical = items.iter().position(|it| {
+        it.glyph_char()
+            .is_some_and(|c| c == '√' || c == '∛' || c == '∜')
+            && (rule.x - it.x1).abs() <= it.size / 4
+            && rule.y <= it.baseline
+    });
+
+    let in_span = |it: &Item| rule.x <= it.center() && it.center() <= rule.right();
+
+    let mut above = Vec::new();
+    let mut below = Vec::new();
+
+    for (i, it) in items.iter().enumerate() {
+        if Some(i) == radical || !in_span(it) {
+            continue;
+        }
+
+        if it.baseline < rule.top() && !is_blocked(it, rule.mid(), pending_rules) {
+            above.push(i);
+        } else if it.baseline > rule.y && !is_blocked(it, rule.mid(), pending_rules) {
+            below.push(i);
+        }
+    }
+
+    if let Some(ridx) = radical {
+        if !above.is_empty() {
+            return None;
+        }
+
+        let sign = &items[ridx];
+        let index: Vec<usize> = items
+            .iter()
+            .enumerate()
+            .filter(|(i, it)| {
+                *i != ridx
+                    && !below.contains(i)
+                    && it.size * 10 < sign.size * 9
+                    && it.center() >= sign.x0 - sign.size / 2
+                    && it.center() <= sign.x1
+                    && it.baseline < sign.baseline - sign.size / 5
+            })
+            .map(|(i, _)| i)
+            .collect();
+
+        let mut all = below.clone();
+        all.extend(index.iter().copied());
+        all.push(ridx);
+        let mut taken = take_items(items, all);
+
+        let sign_pos = taken
+            .iter()
+            .position(|it| {
+                it.glyph_char()
+                    .is_some_and(|c| c == '√' || c == '∛' || c == '∜')
+            })
+            .unwrap();
+        let sign = taken.remove(sign_pos);
+        let (index_items, content): (Vec<Item>, Vec<Item>) = taken
+            .into_iter()
+            .partition(|it| it.x1 <= rule.x || it.center() < rule.x);
+
+        let (baseline, size) = if content.is_empty() {
+            (sign.baseline, sign.size)
+        } else {
+            main_baseline(&content)
+        };
+
+        let sign_char = sign.glyph_char().unwrap();
+        let base = build_row(content)?;
+
+        let node = match sign_char {
+            '∛' | '∜' if index_items.is_empty() => Node::Root(
+                Box::new(base),
+                Box::new(Node::Token(
+                    TokenKind::Number,
+                    if sign_char == '∛' { "3" } else { "4" }.to_owned(),
+                )),
+            ),
+            _ if index_items.is_empty() => Node::Sqrt(Box::new(base)),
+            _ => Node::Root(Box::new(base), Box::new(build_row(index_items)?)),
+        };
+
+        return Some(Item {
+            id,
+            content: ItemContent::Built(node),
+            x0: sign.x0,
+            x1: rule.right(),
+            baseline,
+            size: size.max(sign.size),
+        });
+    }
+
+    let mut all = above.clone();
+    all.extend(below.iter().copied());
+    let taken = take_items(items, all);
+    let (above, below): (Vec<Item>, Vec<Item>) =
+        taken.into_iter().partition(|it| it.baseline < rule.top());
+
+    let (x0, x1) = bounds(&[above.as_slice(), below.as_slice()].concat());
+    let x0 = x0.min(rule.x);
+    let x1 = x1.max(rule.right());
+
+    match (above.is_empty(), below.is_empty()) {
+        (false, false) => {
+            let size = above.iter().chain(below.iter()).map(|it| it.size).max()?;
+
+            Some(Item {
+                id,
+                content: ItemContent::Built(Node::Fraction(
+                    Box::new(build_row(above)?),
+                    Box::new(build_row(below)?),
+                )),
+                x0,
+                x1,
+                // The fraction bar is centered on the math axis, which is
+                // about a quarter of an em above the baseline.
+                baseline: rule.mid() + size / 4,
+                size,
+            })
+        }
+
+        (true, false) => {
+            let (baseline, size) = main_baseline(&below);
+
+            Some(Item {
+                id,
+                content: ItemContent::Built(Node::Over(
+                    Box::new(build_row(below)?),
+                    Box::new(Node::operator('‾')),
+                    true,
+                )),
+                x0,
+                x1,
+                baseline,
+                size,
+            })
+        }
+
+        (false, true) => {
+            let (baseline, size) = main_baseline(&above);
+
+            Some(Item {
+                id,
+                content: ItemContent::Built(Node::Under(
+                    Box::new(build_row(above)?),
+                    Box::new(Node::operator('_')),
+                    true,
+                )),
+                x0,
+                x1,
+                baseline,
+                size,
+            })
+        }
+
+        // A rule on its own, like a `\rule`.
+        (true, true) => None,
+    }
+}
+
+/// Try to build a structure around a large operator or an accent.
+///
+/// Returns `Ok(None)`, so to speak, if the glyph turns out to stand on its own.
+fn build_around_glyph(
+    items: &mut Vec<Item>,
+    idx: usize,
+    pending_rules: &[&MathRule],
+    id: usize,
+) -> Option<Option<Item>> {
+    let anchor = items[idx].clone();
+    let c = anchor.glyph_char()?;
+
+    if is_large_operator(c) {
+        // Limits are set in a smaller size, centered above and below.
+        let limits: Vec<usize> = items
+            .iter()
+            .enumerate()
+            .filter(|(i, it)| {
+                *i != idx
+                    && it.size * 10 < anchor.size * 9
+                    && anchor.x0 <= it.center()
+                    && it.center() <= anchor.x1
+                    && (it.baseline - anchor.baseline).abs() <= 2 * anchor.size
+                    && (it.baseline < anchor.baseline - anchor.size * 3 / 5
+                        || it.baseline > anchor.baseline + anchor.size * 2 / 5)
+                    && !is_blocked(it, anchor.baseline, pending_rules)
+            })
+            .map(|(i, _)| i)
+            .collect();
+
+        if limits.is_empty() {
+            return Some(None);
+        }
+
+        let mut all = limits;
+        all.push(idx);
+        let taken = take_items(items, all);
+        let (ops, limits): (Vec<Item>, Vec<Item>) =
+            taken.into_iter().partition(|it| it.id == anchor.id);
+        let (over, under): (Vec<Item>, Vec<Item>) = limits
+            .into_iter()
+            .partition(|it| it.baseline < anchor.baseline);
+        let (x0, x1) = bounds(&[ops.as_slice(), over.as_slice(), under.as_slice()].concat());
+        let base = Box::new(Node::operator(c));
+
+        let node = match (under.is_empty(), over.is_empty()) {
+            (false, true) => Node::Under(base, Box::new(build_row(under)?), false),
+            (true, false) => Node::Over(base, Box::new(build_row(over)?), false),
+            _ => Node::UnderOver(
+                base,
+                Box::new(build_row(under)?),
+                Box::new(build_row(over)?),
+            ),
+        };
+
+        return Some(Some(Item {
+            id,
+            content: ItemContent::Built(node),
+            x0,
+            x1,
+            baseline: anchor.baseline,
+            size: anchor.size,
+        }));
+    }
+
+    // Otherwise, it's an accent. TeX places accents over material of the
+    // same size; for narrow accents, that's whatever contains the accent's
+    // center, while wide ones span their bases.
+
+    let is_under = is_under_accent(c);
+    let cx = anchor.center();
+    let tol = anchor.size / 10;
+
+    let candidate = |it: &Item| {
+        it.size * 10 >= anchor.size * 9
+            && (it.baseline - anchor.baseline).abs() <= 3 * anchor.size / 2
+            && if is_under {
+                it.baseline <= anchor.baseline + tol
+            } else {
+                it.baseline >= anchor.baseline - tol
+            }
+            && !is_blocked(it, anchor.baseline, pending_rules)
+    };
+
+    let mut base: Vec<usize> = items
+        .iter()
+        .enumerate()
+        .filter(|(i, it)| {
+            *i != idx && candidate(it) && anchor.x0 <= it.center() && it.center() <= anchor.x1
+        })
+        .map(|(i, _)| i)
+        .collect();
+
+    if base.is_empty() {
+        base = items
+            .iter()
+            .enumerate()
+            .filter(|(i, it)| *i != idx && candidate(it) && it.x0 - tol <= cx && cx <= it.x1 + tol)
+            .min_by_key(|(_, it)| (it.center() - cx).abs())
+            .map(|(i, _)| vec![i])
+            .unwrap_or_default();
+    }
+
+    if base.is_empty() {
+        return Some(None);
+    }
+
+    let mut all = base;
+    all.push(idx);
+    let taken = take_items(items, all);
+    let (_, base): (Vec<Item>, Vec<Item>) = taken.into_iter().partition(|it| it.id == anchor.id);
+    let (x0, x1) = bounds(&base);
+    let (baseline, size) = main_baseline(&base);
+    let accent = Box::new(Node::operator(spacing_accent(c)));
+    let base = Box::new(build_row(base)?);
+
+    Some(Some(Item {
+        id,
+        content: ItemContent::Built(if is_under {
+            Node::Under(base, accent, true)
+        } else {
+            Node::Over(base, accent, true)
+        }),
+        x0: x0.min(anchor.x0),
+        x1: x1.max(anchor.x1),
+        baseline,
+        size,
+    }))
+}
+
+/// Build a row from items that don't form any larger structures. This is
+/// where sub- and superscripts are identified.
+fn build_row(items: Vec<Item>) -> Option<Node> {
+    if items.is_empty() {
+        return Some(Node::Row(Vec::new()));
+    }
+
+    let (main_y, main_size) = main_baseline(&items);
+
+    // Full-size material set on different baselines indicates multiple lines,
+    // as in an `align` environment.
+
+    let mut lines: Vec<i32> = Vec::new();
+
+    for it in items.iter().filter(|it| it.size * 10 >= main_size * 9) {
+        if !lines
+            .iter()
+            .any(|y| (y - it.baseline).abs() <= main_size * 6 / 5)
+        {
+            lines.push(it.baseline);
+        }
+    }
+
+    if lines.len() > 1 {
+        // Delimiters that span lines would need a real table analysis.
+        if items.iter().any(|it| it.glyph_char().is_some_and(is_fence)) {
+            return None;
+        }
+
+        lines.sort_unstable();
+        let mut grouped: Vec<Vec<Item>> = vec![Vec::new(); lines.len()];
+
+        for it in items {
+            let (i, _) = lines
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, y)| (**y - it.baseline).abs())
+                .unwrap();
+            grouped[i].push(it);
+        }
+
+        let rows = grouped
+            .into_iter()
+            .map(build_row)
+            .collect::<Option<Vec<_>>>()?;
+        return Some(Node::Table(rows));
+    }
+
+    let is_script = |it: &Item| {
+        it.size * 10 < main_size * 9 && (it.baseline - main_y).abs() > main_size * 3 / 20
+    };
+
+    let mut items = items;
+    items.sort_by_key(|it| (it.x0, it.baseline));
+
+    let mut nodes = Vec::new();
+    let mut run = Vec::new();
+    let mut iter = items.into_iter().peekable();
+
+    while let Some(it) = iter.next() {
+        if !is_script(&it) {
+            match it.content {
+                ItemContent::Glyph(_) => run.push(it),
+                ItemContent::Built(_) => {
+                    tokenize(std::mem::take(&mut run), &mut nodes);
+                    nodes.push(it.into_node());
+                }
+            }
+            continue;
+        }
+
+        let mut sups = Vec::new();
+        let mut subs = Vec::new();
+        let mut next = Some(it);
+
+        while let Some(s) = next {
+            if s.baseline < main_y {
+                sups.push(s);
+            } else {
+                subs.push(s);
+            }
+
+            next = iter.next_if(is_script);
+        }
+
+        tokenize(std::mem::take(&mut run), &mut nodes);
+        let base = Box::new(nodes.pop().unwrap_or(Node::Row(Vec::new())));
+
+        nodes.push(match (subs.is_empty(), sups.is_empty()) {
+            (true, _) => Node::Sup(base, Box::new(build_row(sups)?)),
+            (false, true) => Node::Sub(base, Box::new(build_row(subs)?)),
+            (false, false) => {
+                Node::SubSup(base, Box::new(build_row(subs)?), Box::new(build_row(sups)?))
+            }
+        });
+    }
+
+    tokenize(run, &mut nodes);
+    Some(Node::row(nodes))
+}
+
+/// Group a run of glyphs into MathML tokens: numbers, words set in upright
+/// letters, and individual identifiers and operators.
+fn tokenize(run: Vec<Item>, nodes: &mut Vec<Node>) {
+    let mut prev: Option<(TokenKind, bool, i32, i32)> = None;
+
+    for it in run {
+        let c = it.glyph_char().unwrap();
+        let mut kind = token_kind(c);
+        let word = kind == TokenKind::Identifier && c.is_ascii_alphabetic();
+
+        // Decimal points and digit separators continue numbers.
+        if matches!(c, '.' | ',') {
+            if let Some((TokenKind::Number, _, _, _)) = prev {
+                kind = TokenKind::Number;
+            }
+        }
+
+        let joins = match prev {
+            Some((pk, pword, px1, psize)) => {
+                let tight = it.x0 - px1 <= psize.max(it.size) / 10;
+                tight && ((kind == TokenKind::Number && pk == TokenKind::Number) || (word && pword))
+            }
+            None => false,
+        };
+
+        if joins {
+            if let Some(Node::Token(_, text)) = nodes.last_mut() {
+                text.push(c);
+            }
+        } else {
+            split_trailing_separator(nodes);
+            nodes.push(Node::Token(kind, c.to_string()));
+        }
+
+        prev = Some((kind, word, it.x1, it.size));
+    }
+
+    split_trailing_separator(nodes);
+}
+
+/// A separator at the end of a number isn't part of it.
+fn split_trailing_separator(nodes: &mut Vec<Node>) {
+    if let Some(Node::Token(TokenKind::Number, text)) = nodes.last_mut() {
+        if text.chars().count() > 1 && (text.ends_with('.') || text.ends_with(',')) {
+            let c = text.pop().unwrap();
+            nodes.push(Node::operator(c));
+        }
+    }
+}
+
+fn token_kind(c: char) -> TokenKind {
+    if c.is_numeric() {
+        TokenKind::Number
+    } else if c.is_alphabetic() || matches!(c, '∞' | '∅' | '∂' | '∇' | 'ℏ' | 'ℓ' | '℘')
+    {
+        TokenKind::Identifier
+    } else {
+        TokenKind::Operator
+    }
+}
+
+fn is_fence(c: char) -> bool {
+    matches!(
+        c,
+        '(' | ')' | '[' | ']' | '{' | '}' | '|' | '‖' | '⟨' | '⟩' | '⌊' | '⌋' | '⌈' | '⌉'
+    )
+}
+
+fn is_large_operator(c: char) -> bool {
+    matches!(
+        c,
+        '∑' | '∏'
+            | '∐'
+            | '⋀'
+            | '⋁'
+            | '⋂'
+            | '⋃'
+            | '⨀'
+            | '⨁'
+            | '⨂'
+            | '⨄'
+            | '⨆'
+            | '∫'
+            | '∮'
+    )
+}
+
+fn is_accent(c: char) -> bool {
+    matches!(
+        c,
+        '\u{0300}'..='\u{036F}'
+            | '\u{20D0}'..='\u{20EF}'
+            | '\u{02C6}'
+            | '\u{02C7}'
+            | '\u{02D8}'
+            | '\u{02D9}'
+            | '\u{02DC}'
+            | '\u{00A8}'
+            | '\u{00AF}'
+            | '\u{23B4}'
+            | '\u{23B5}'
+            | '\u{23DC}'
+            | '\u{23DD}'
+            | '\u{23DE}'
+            | '\u{23DF}'
+    )
+}
+
+fn is_under_accent(c: char) -> bool {
+    matches!(c, '\u{0332}' | '\u{23B5}' | '\u{23DD}' | '\u{23DF}')
+}
+
+/// Combining accents don't render well on their own, so MathML uses their
+/// spacing equivalents.
+fn spacing_accent(c: char) -> char {
+    match c {
+        '\u{0300}' => '`',
+        '\u{0301}' => '´',
+        '\u{0302}' => '^',
+        '\u{0303}' => '~',
+        '\u{0304}' => '¯',
+        '\u{0305}' => '‾',
+        '\u{0306}' => '˘',
+        '\u{0307}' => '˙',
+        '\u{0308}' => '¨',
+        '\u{030A}' => '˚',
+        '\u{030C}' => 'ˇ',
+        '\u{0332}' => '_',
+        '\u{20D6}' => '←',
+        '\u{20D7}' => '→',
+        '\u{20E1}' => '↔',
+        c => c,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PT: i32 = 65536;
+
+    fn glyph(ch: char, x: f32, y: f32, size: i32) -> MathGlyph {
+        MathGlyph {
+            ch,
+            x: (x * PT as f32) as i32,
+            y: (y * PT as f32) as i32,
+            advance: size * PT / 2,
+            size: size * PT,
+        }
+    }
+
+    fn rule(x: f32, y: f32, width: f32) -> MathRule {
+        MathRule {
+            x: (x * PT as f32) as i32,
+            y: (y * PT as f32) as i32,
+            width: (width * PT as f32) as i32,
+            height: PT / 2,
+        }
+    }
+
+    fn convert(glyphs: &[MathGlyph], rules: &[MathRule]) -> String {
+        let mathml = layout_to_mathml(glyphs, rules, false).unwrap();
+        let start = mathml.find("\">").unwrap() + 2;
+        mathml[start..mathml.len() - 7].to_owned()
+    }
+
+    #[test]
+    fn tokens() {
+        // x = 12.5, sin
+        let glyphs = [
+            glyph('𝑥', 0., 0., 10),
+            glyph('=', 8., 0., 10),
+            glyph('1', 16., 0., 10),
+            glyph('2', 21., 0., 10),
+            glyph('.', 26., 0., 10),
+            glyph('5', 31., 0., 10),
+            glyph(',', 36., 0., 10),
+            glyph('s', 44., 0., 10),
+            glyph('i', 49., 0., 10),
+            glyph('n', 54., 0., 10),
+        ];
+
+        assert_eq!(
+            convert(&glyphs, &[]),
+            "<mrow><mi>𝑥</mi><mo>=</mo><mn>12.5</mn><mo>,</mo><mi>sin</mi></mrow>"
+        );
+    }
+
+    #[test]
+    fn scripts() {
+        // x_i^2 + y^{n^2}
+        let glyphs = [
+            glyph('𝑥', 0., 0., 10),
+            glyph('2', 5., -4., 7),
+            glyph('𝑖', 5., 2., 7),
+            glyph('+', 12., 0., 10),
+            glyph('𝑦', 20., 0., 10),
+            glyph('𝑛', 25., -4., 7),
+            glyph('2', 28.5, -6.5, 5),
+        ];
+
+        assert_eq!(
+            convert(&glyphs, &[]),
+            "<mrow><msubsup><mi>𝑥</mi><mi>𝑖</mi><mn>2</mn></msubsup><mo>+</mo>\
+             <msup><mi>𝑦</mi><msup><mi>𝑛</mi><mn>2</mn></msup></msup></mrow>"
+        );
+    }
+
+    #[test]
+    fn fractions() {
+        // a + (1 + 1/b)/c, with the inner fraction in the numerator
+        let glyphs = [
+            glyph('𝑎', 0., 0., 10),
+            glyph('+', 6., 0., 10),
+            glyph('1', 12., -8., 10),
+            glyph('+', 17., -8., 10),
+            glyph('1', 22.5, -11., 7),
+            glyph('𝑏', 22.5, -6., 7),
+            glyph('𝑐', 19., 7., 10),
+        ];
+        let rules = [rule(12., -2., 15.), rule(22., -8.5, 4.)];
+
+        assert_eq!(
+            convert(&glyphs, &rules),
+            "<mrow><mi>𝑎</mi><mo>+</mo><mfrac><mrow><mn>1</mn><mo>+</mo>\
+             <mfrac><mn>1</mn><mi>𝑏</mi></mfrac></mrow><mi>𝑐</mi></mfrac></mrow>"
+        );
+    }
+
+    #[test]
+    fn radicals_and_limits() {
+        // sqrt(x) + sum_{i=1}^n
+        let glyphs = [
+            glyph('√', 0., 0., 10),
+            glyph('𝑥', 5., 0., 10),
+            glyph('+', 12., 0., 10),
+            glyph('∑', 20., 0., 10),
+            glyph('𝑛', 21., -12., 7),
+            glyph('𝑖', 18.8, 10., 7),
+            glyph('=', 20.6, 10., 7),
+            glyph('1', 22.4, 10., 7),
+        ];
+        let rules = [rule(5., -8., 5.)];
+
+        assert_eq!(
+            convert(&glyphs, &rules),
+            "<mrow><msqrt><mi>𝑥</mi></msqrt><mo>+</mo><munderover><mo>∑</mo>\
+             <mrow><mi>𝑖</mi><mo>=</mo><mn>1</mn></mrow><mi>𝑛</mi></munderover></mrow>"
+        );
+    }
+
+    #[test]
+    fn accents_and_alt_text() {
+        // hat x / 2
+        let glyphs = [
+            glyph('𝑥', 0., -6., 10),
+            glyph('\u{0302}', 0., -6., 10),
+            glyph('2', 0., 6., 10),
+        ];
+        let rules = [rule(0., -2., 5.)];
+        let mathml = layout_to_mathml(&glyphs, &rules, true).unwrap();
+
+        assert_eq!(
+            mathml,
+            "<math xmlns=\"http://www.w3.org/1998/Math/MathML\" display=\"block\" \
+             alttext=\"(𝑥^^)/2\"><mfrac><mover accent=\"true\"><mi>𝑥</mi><mo>^</mo></mover>\
+             <mn>2</mn></mfrac></math>"
+        );
+    }
+
+    #[test]
+    fn unsupported() {
+        // A rule with nothing above or below it.
+        let glyphs = [glyph('𝑥', 0., 0., 10)];
+        let rules = [rule(10., 0., 5.)];
+        assert!(layout_to_mathml(&glyphs, &rules, false).is_none());
+    }
+}
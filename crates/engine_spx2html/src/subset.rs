@@ -0,0 +1,1072 @@
+// Copyright 2025 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Subsetting OpenType fonts.
+//!
+//! When we optimize the assets of an HTML build, we cut each font down to the
+//! glyphs that the document actually uses. We don't renumber the glyphs, since
+//! the font's layout tables, our variant-glyph character maps, and the glyph
+//! IDs in the SPX file all refer to them by number. Instead, the outlines of
+//! unused glyphs are replaced with empty ones, which is where nearly all of the
+//! savings are to be had anyway. TrueType (`glyf`) and CFF outlines are
+//! supported; fonts with other kinds of outlines are left alone.
+
+use byteorder::{BigEndian, ByteOrder, WriteBytesExt};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    num::Wrapping,
+};
+
+use crate::fontfile::{opentype_checksum, GlyphId};
+
+const fn tag(b: &[u8; 4]) -> u32 {
+    u32::from_be_bytes(*b)
+}
+
+pub(crate) const CFF: u32 = tag(b"CFF ");
+pub(crate) const CMAP: u32 = tag(b"cmap");
+const DSIG: u32 = tag(b"DSIG");
+pub(crate) const GLYF: u32 = tag(b"glyf");
+const HEAD: u32 = tag(b"head");
+pub(crate) const LOCA: u32 = tag(b"loca");
+const MAXP: u32 = tag(b"maxp");
+const TTCF: u32 = tag(b"ttcf");
+
+/// The tables of an OpenType font, keyed by tag.
+#[derive(Clone, Debug)]
+pub(crate) struct SfntTables {
+    /// The sfnt version: 0x00010000 for TrueType outlines, `OTTO` for CFF.
+    flavor: u32,
+    tables: BTreeMap<u32, Vec<u8>>,
+}
+
+impl SfntTables {
+    /// Parse an OpenType font file.
+    ///
+    /// Font collections aren't supported. Digital signatures are dropped, since
+    /// they won't be valid once we've modified the font.
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let flavor = read_u32(data, 0)?;
+
+        if flavor == TTCF {
+            return None;
+        }
+
+        let num_tables = read_u16(data, 4)? as usize;
+        let mut tables = BTreeMap::new();
+
+        for i in 0..num_tables {
+            let rec = 12 + 16 * i;
+            let tag = read_u32(data, rec)?;
+            let offset = read_u32(data, rec + 8)? as usize;
+            let length = read_u32(data, rec + 12)? as usize;
+            let contents = data.get(offset..offset.checked_add(length)?)?;
+
+            if tag != DSIG {
+                tables.insert(tag, contents.to_vec());
+            }
+        }
+
+        Some(SfntTables { flavor, tables })
+    }
+
+    pub fn flavor(&self) -> u32 {
+        self.flavor
+    }
+
+    /// Iterate over the tables in order of their tags.
+    pub fn tables(&self) -> impl Iterator<Item = (u32, &[u8])> {
+        self.tables.iter().map(|(tag, data)| (*tag, &data[..]))
+    }
+
+    pub fn set_table(&mut self, tag: u32, data: Vec<u8>) {
+        self.tables.insert(tag, data);
+    }
+
+    /// Replace the outlines of the glyphs that aren't in *keep* with empty
+    /// ones. Glyph 0 (`.notdef`) and the components of kept composite glyphs
+    /// are always kept.
+    ///
+    /// Returns false, leaving the font unchanged, if its outlines aren't in a
+    /// form that we know how to subset.
+    pub fn subset(&mut self, keep: &BTreeSet<GlyphId>) -> bool {
+        let subsetted = if self.tables.contains_key(&GLYF) {
+            self.subset_glyf(keep)
+        } else if let Some(cff) = self.tables.get(&CFF) {
+            subset_cff(cff, keep).map(|cff| {
+                self.tables.insert(CFF, cff);
+            })
+        } else {
+            None
+        };
+
+        subsetted.is_some()
+    }
+
+    fn subset_glyf(&mut self, keep: &BTreeSet<GlyphId>) -> Option<()> {
+        let long_loca = read_u16(self.tables.get(&HEAD)?, 50)? != 0;
+        let num_glyphs = read_u16(self.tables.get(&MAXP)?, 4)? as usize;
+        let loca = self.tables.get(&LOCA)?;
+        let glyf = self.tables.get(&GLYF)?;
+
+        let mut offsets = Vec::with_capacity(num_glyphs + 1);
+
+        for i in 0..=num_glyphs {
+            offsets.push(if long_loca {
+                read_u32(loca, 4 * i)? as usize
+            } else {
+                read_u16(loca, 2 * i)? as usize * 2
+            });
+        }
+
+        let glyph_data = |g: usize| glyf.get(offsets[g]..offsets[g + 1]);
+
+        // Composite glyphs are built out of other glyphs, which we need to
+        // keep too.
+
+        let mut closure: BTreeSet<usize> = keep
+            .iter()
+            .map(|g| *g as usize)
+            .chain(std::iter::once(0))
+            .filter(|g| *g < num_glyphs)
+            .collect();
+        let mut pending: Vec<usize> = closure.iter().copied().collect();
+
+        while let Some(g) = pending.pop() {
+            for c in composite_components(glyph_data(g)?)? {
+                if c < num_glyphs && closure.insert(c) {
+                    pending.push(c);
+                }
+            }
+        }
+
+        // Rebuild the tables. We always write a long `loca`, which saves us
+        // from having to worry about whether the new offsets would fit in a
+        // short one.
+
+        let mut new_glyf = Vec::new();
+        let mut new_loca = Vec::with_capacity(4 * (num_glyphs + 1));
+
+        for g in 0..num_glyphs {
+            new_loca
+                .write_u32::<BigEndian>(new_glyf.len() as u32)
+                .unwrap();
+
+            if closure.contains(&g) {
+                new_glyf.extend_from_slice(glyph_data(g)?);
+
+                while new_glyf.len() % 4 != 0 {
+                    new_glyf.push(0);
+                }
+            }
+        }
+
+        new_loca
+            .write_u32::<BigEndian>(new_glyf.len() as u32)
+            .unwrap();
+
+        let head = self.tables.get_mut(&HEAD)?;
+        BigEndian::write_u16(&mut head[50..52], 1); // indexToLocFormat: long
+        self.tables.insert(GLYF, new_glyf);
+        self.tables.insert(LOCA, new_loca);
+        Some(())
+    }
+
+    /// Serialize the font as an OpenType file, with all of its checksums
+    /// filled in.
+    pub fn to_sfnt(&self) -> Vec<u8> {
+        let num_tables = self.tables.len() as u16;
+        let entry_selector = 15 - num_tables.max(1).leading_zeros() as u16;
+        let search_range = 16 << entry_selector;
+
+        let mut buf = Vec::new();
+        buf.write_u32::<BigEndian>(self.flavor).unwrap();
+        buf.write_u16::<BigEndian>(num_tables).unwrap();
+        buf.write_u16::<BigEndian>(search_range).unwrap();
+        buf.write_u16::<BigEndian>(entry_selector).unwrap();
+        buf.write_u16::<BigEndian>(num_tables * 16 - search_range)
+            .unwrap();
+
+        let dir_start = buf.len();
+        buf.resize(dir_start + 16 * self.tables.len(), 0);
+        let mut head_offset = None;
+
+        for (i, (tag, data)) in self.tables.iter().enumerate() {
+            let offset = buf.len();
+            buf.extend_from_slice(data);
+
+            // The checksum adjustment is zero for the purposes of computing
+            // the checksums.
+            if *tag == HEAD && data.len() >= 12 {
+                buf[offset + 8..offset + 12].fill(0);
+                head_offset = Some(offset);
+            }
+
+            let cs = opentype_checksum(&buf[offset..]);
+
+            while buf.len() % 4 != 0 {
+                buf.push(0);
+            }
+
+            let rec = dir_start + 16 * i;
+            BigEndian::write_u32(&mut buf[rec..rec + 4], *tag);
+            BigEndian::write_u32(&mut buf[rec + 4..rec + 8], cs);
+            BigEndian::write_u32(&mut buf[rec + 8..rec + 12], offset as u32);
+            BigEndian::write_u32(&mut buf[rec + 12..rec + 16], data.len() as u32);
+        }
+
+        if let Some(ofs) = head_offset {
+            let chkadj = Wrapping(0xB1B0AFBA) - Wrapping(opentype_checksum(&buf[..]));
+            BigEndian::write_u32(&mut buf[ofs + 8..ofs + 12], chkadj.0);
+        }
+
+        buf
+    }
+}
+
+fn read_u16(data: &[u8], ofs: usize) -> Option<u16> {
+    data.get(ofs..ofs + 2).map(BigEndian::read_u16)
+}
+
+fn read_u32(data: &[u8], ofs: usize) -> Option<u32> {
+    data.get(ofs..ofs + 4).map(BigEndian::read_u32)
+}
+
+/// Get the glyphs that a TrueType glyph description is built from. Simple
+/// glyphs have no components.
+fn composite_components(data: &[u8]) -> Option<Vec<usize>> {
+    const ARG_1_AND_2_ARE_WORDS: u16 = 0x0001;
+    const WE_HAVE_A_SCALE: u16 = 0x0008;
+    const MORE_COMPONENTS: u16 = 0x0020;
+    const WE_HAVE_AN_X_AND_Y_SCALE: u16 = 0x0040;
+    const WE_HAVE_A_TWO_BY_TWO: u16 = 0x0080;
+
+    let mut components = Vec::new();
+
+    // Empty glyphs and simple glyphs (with a nonnegative number of contours)
+    // have no components.
+    if data.is_empty() || read_u16(data, 0)? & 0x8000 == 0 {
+        return Some(components);
+    }
+
+    let mut ofs = 10;
+
+    loop {
+        let flags = read_u16(data, ofs)?;
+        components.push(read_u16(data, ofs + 2)? as usize);
+
+        ofs += if flags & ARG_1_AND_2_ARE_WORDS != 0 {
+            8
+        } else {
+            6
+        };
+
+        ofs += if flags & WE_HAVE_A_SCALE != 0 {
+            2
+        } else if flags & WE_HAVE_AN_X_AND_Y_SCALE != 0 {
+            4
+        } else if flags & WE_HAVE_A_TWO_BY_TWO != 0 {
+            8
+        } else {
+            0
+        };
+
+        if flags & MORE_COMPONENTS == 0 {
+            return Some(components);
+        }
+    }
+}
+
+// CFF operators that we care about. Two-byte operators are `12 xx`.
+const OP_CHARSET: u16 = 15;
+const OP_ENCODING: u16 = 16;
+const OP_CHARSTRINGS: u16 = 17;
+const OP_PRIVATE: u16 = 18;
+const OP_SUBRS: u16 = 19;
+const OP_ROS: u16 = 0x0c1e;
+
+/// The Type 2 charstring operator that returns from a subroutine.
+const RETURN: u8 = 11;
+
+/// The Type 2 charstring operator that ends a glyph.
+const ENDCHAR: u8 = 14;
+
+/// Subset a CFF table.
+///
+/// Unused charstrings are replaced with ones that just end the glyph, and
+/// subroutines that the remaining charstrings don't call are replaced with
+/// ones that just return. The new CharStrings INDEX, Private DICT, and local
+/// subroutines go at the end of the table, and the offsets in the Top DICT are
+/// updated to account for everything that has moved or changed size.
+/// CID-keyed fonts and font sets with more than one font aren't handled.
+fn subset_cff(data: &[u8], keep: &BTreeSet<GlyphId>) -> Option<Vec<u8>> {
+    if *data.first()? != 1 {
+        return None;
+    }
+
+    let hdr_size = *data.get(2)? as usize;
+    let name_index = CffIndex::parse(data, hdr_size)?;
+    let top_index = CffIndex::parse(data, name_index.end)?;
+
+    if name_index.count != 1 || top_index.count != 1 {
+        return None;
+    }
+
+    let top_dict = parse_dict(top_index.item(data, 0)?)?;
+
+    if top_dict.iter().any(|(op, _)| *op == OP_ROS) {
+        return None;
+    }
+
+    let string_index = CffIndex::parse(data, top_index.end)?;
+    let gsubr_index = CffIndex::parse(data, string_index.end)?;
+    let cs_start = dict_int(&top_dict, OP_CHARSTRINGS, 0)? as usize;
+    let charstrings = CffIndex::parse(data, cs_start)?;
+
+    // The structures that we're going to move to the end of the table.
+
+    let mut moved = vec![(cs_start, charstrings.end)];
+    let mut private = None;
+    let mut lsubr_items = Vec::new();
+
+    if let (Some(size), Some(offset)) = (
+        dict_int(&top_dict, OP_PRIVATE, 0),
+        dict_int(&top_dict, OP_PRIVATE, 1),
+    ) {
+        let (offset, size) = (offset as usize, size as usize);
+        let private_dict = parse_dict(data.get(offset..offset + size)?)?;
+        moved.push((offset, offset + size));
+
+        if let Some(subrs) = dict_int(&private_dict, OP_SUBRS, 0) {
+            let lsubr_index = CffIndex::parse(data, offset + subrs as usize)?;
+            lsubr_items = lsubr_index.items(data)?;
+            moved.push((offset + subrs as usize, lsubr_index.end));
+        }
+
+        private = Some(private_dict);
+    }
+
+    moved.sort_unstable();
+
+    if moved[0].0 < gsubr_index.end || moved.windows(2).any(|w| w[0].1 > w[1].0) {
+        return None;
+    }
+
+    // Subset the charstrings and subroutines. If we can't make sense of the
+    // charstrings, we keep all of the subroutines.
+
+    let mut new_charstrings = charstrings.items(data)?;
+
+    for (gid, cs) in new_charstrings.iter_mut().enumerate() {
+        if gid != 0 && !keep.contains(&(gid as GlyphId)) {
+            *cs = &[ENDCHAR];
+        }
+    }
+
+    let mut gsubr_items = gsubr_index.items(data)?;
+    let mut scanner = SubrScanner::new(&lsubr_items, &gsubr_items);
+
+    if new_charstrings.iter().all(|cs| scanner.scan_charstring(cs)) {
+        let (used_local, used_global) = (scanner.used_local, scanner.used_global);
+
+        for (i, subr) in lsubr_items.iter_mut().enumerate() {
+            if !used_local.contains(&i) {
+                *subr = &[RETURN];
+            }
+        }
+
+        for (i, subr) in gsubr_items.iter_mut().enumerate() {
+            if !used_global.contains(&i) {
+                *subr = &[RETURN];
+            }
+        }
+    }
+
+    let new_charstrings = write_index(&new_charstrings);
+    let new_gsubrs = write_index(&gsubr_items);
+    let new_lsubrs = write_index(&lsubr_items);
+
+    // Offsets in DICTs are always written in their five-byte form, so the
+    // sizes of the new DICTs don't depend on their values.
+
+    let new_private = private.map(|mut dict| {
+        for (op, operands) in &mut dict {
+            if let (OP_SUBRS, [CffOperand::Int(v)]) = (*op, &mut operands[..]) {
+                *v = 0;
+            }
+        }
+
+        let size = encode_dict(&dict).len() as i32;
+
+        for (op, operands) in &mut dict {
+            if let (OP_SUBRS, [CffOperand::Int(v)]) = (*op, &mut operands[..]) {
+                *v = size;
+            }
+        }
+
+        encode_dict(&dict)
+    });
+
+    let top_size = write_index(&[&encode_dict(&top_dict)]).len();
+    let shift = (top_size + new_gsubrs.len()) as isize
+        - (top_index.end - name_index.end + gsubr_index.end - string_index.end) as isize;
+
+    let relocate = |ofs: i32| -> Option<i32> {
+        let ofs = ofs as usize;
+        let mut new = ofs as isize + shift;
+
+        if ofs < gsubr_index.end {
+            return None;
+        }
+
+        for (start, end) in &moved {
+            if ofs >= *end {
+                new -= (end - start) as isize;
+            } else if ofs >= *start {
+                return None;
+            }
+        }
+
+        Some(new as i32)
+    };
+
+    let moved_len: usize = moved.iter().map(|(start, end)| end - start).sum();
+    let cs_offset = (data.len() - moved_len) as isize + shift;
+    let private_offset = cs_offset + new_charstrings.len() as isize;
+    let mut new_top_dict = top_dict.clone();
+
+    for (op, operands) in &mut new_top_dict {
+        let value = match (*op, &mut operands[..]) {
+            (OP_CHARSET, [CffOperand::Int(v)]) if *v > 2 => v,
+            (OP_ENCODING, [CffOperand::Int(v)]) if *v > 1 => v,
+            (OP_CHARSTRINGS, [CffOperand::Int(v)]) => {
+                *v = cs_offset as i32;
+                continue;
+            }
+            (OP_PRIVATE, [CffOperand::Int(size), CffOperand::Int(offset)]) => {
+                *size = new_private.as_ref()?.len() as i32;
+                *offset = private_offset as i32;
+                continue;
+            }
+            _ => continue,
+        };
+
+        *value = relocate(*value)?;
+    }
+
+    let mut cff = Vec::with_capacity(data.len());
+    cff.extend_from_slice(&data[..name_index.end]);
+    cff.extend_from_slice(&write_index(&[&encode_dict(&new_top_dict)]));
+    cff.extend_from_slice(&data[top_index.end..string_index.end]);
+    cff.extend_from_slice(&new_gsubrs);
+
+    let mut copied = gsubr_index.end;
+
+    for (start, end) in &moved {
+        cff.extend_from_slice(&data[copied..*start]);
+        copied = *end;
+    }
+
+    cff.extend_from_slice(&data[copied..]);
+    cff.extend_from_slice(&new_charstrings);
+
+    if let Some(new_private) = new_private {
+        cff.extend_from_slice(&new_private);
+
+        if !lsubr_items.is_empty() {
+            cff.extend_from_slice(&new_lsubrs);
+        }
+    }
+
+    Some(cff)
+}
+
+/// Figures out which subroutines a set of Type 2 charstrings call.
+struct SubrScanner<'a> {
+    local: &'a [&'a [u8]],
+    global: &'a [&'a [u8]],
+    used_local: BTreeSet<usize>,
+    used_global: BTreeSet<usize>,
+    stack: Vec<i32>,
+    n_stems: usize,
+}
+
+impl<'a> SubrScanner<'a> {
+    fn new(local: &'a [&'a [u8]], global: &'a [&'a [u8]]) -> Self {
+        SubrScanner {
+            local,
+            global,
+            used_local: BTreeSet::new(),
+            used_global: BTreeSet::new(),
+            stack: Vec::new(),
+            n_stems: 0,
+        }
+    }
+
+    /// Scan a charstring, returning false if it can't be interpreted.
+    fn scan_charstring(&mut self, cs: &[u8]) -> bool {
+        self.stack.clear();
+        self.n_stems = 0;
+        self.scan(cs, 0).is_some()
+    }
+
+    /// Scan a charstring or subroutine. Returns whether the glyph has ended.
+    ///
+    /// We only need to follow the operands far enough to know the arguments of
+    /// subroutine calls, and the number of stem hints, which determines the
+    /// size of hint masks.
+    fn scan(&mut self, cs: &[u8], depth: usize) -> Option<bool> {
+        // The specification limits subroutine nesting to ten levels.
+        if depth > 10 {
+            return None;
+        }
+
+        let mut i = 0;
+
+        while i < cs.len() {
+            let b0 = cs[i];
+            let byte = |n: usize| cs.get(i + n).map(|b| *b as i32);
+
+            match b0 {
+                28 => {
+                    self.stack
+                        .push(BigEndian::read_i16(cs.get(i + 1..i + 3)?) as i32);
+                    i += 3;
+                }
+
+                32..=246 => {
+                    self.stack.push(b0 as i32 - 139);
+                    i += 1;
+                }
+
+                247..=250 => {
+                    self.stack.push((b0 as i32 - 247) * 256 + byte(1)? + 108);
+                    i += 2;
+                }
+
+                251..=254 => {
+                    self.stack.push(-(b0 as i32 - 251) * 256 - byte(1)? - 108);
+                    i += 2;
+                }
+
+                255 => {
+                    // A 16.16 fixed-point number.
+                    self.stack
+                        .push(BigEndian::read_i32(cs.get(i + 1..i + 5)?) >> 16);
+                    i += 5;
+                }
+
+                // hstem, vstem, hstemhm, vstemhm
+                1 | 3 | 18 | 23 => {
+                    self.n_stems += self.stack.len() / 2;
+                    self.stack.clear();
+                    i += 1;
+                }
+
+                // hintmask, cntrmask: these may be preceded by the arguments
+                // of an implicit vstemhm.
+                19 | 20 => {
+                    self.n_stems += self.stack.len() / 2;
+                    self.stack.clear();
+                    i += 1 + self.n_stems.div_ceil(8);
+                }
+
+                // callsubr, callgsubr
+                10 | 29 => {
+                    let (subrs, used) = if b0 == 10 {
+                        (self.local, &mut self.used_local)
+                    } else {
+                        (self.global, &mut self.used_global)
+                    };
+
+                    let index = self.stack.pop()? + subr_bias(subrs.len());
+                    let subr = *subrs.get(usize::try_from(index).ok()?)?;
+                    used.insert(index as usize);
+
+                    if self.scan(subr, depth + 1)? {
+                        return Some(true);
+                    }
+
+                    i += 1;
+                }
+
+                RETURN => return Some(false),
+
+                ENDCHAR => return Some(true),
+
+                12 => {
+                    self.stack.clear();
+                    i += 2;
+                }
+
+                _ => {
+                    self.stack.clear();
+                    i += 1;
+                }
+            }
+        }
+
+        Some(false)
+    }
+}
+
+/// Subroutine numbers in charstrings are offset by an amount that depends on
+/// the number of subroutines.
+fn subr_bias(count: usize) -> i32 {
+    if count < 1240 {
+        107
+    } else if count < 33900 {
+        1131
+    } else {
+        32768
+    }
+}
+
+/// The location of a CFF INDEX structure.
+#[derive(Debug)]
+struct CffIndex {
+    /// The number of items in the INDEX.
+    count: usize,
+
+    /// The size of each offset.
+    off_size: usize,
+
+    /// The location of the offsets.
+    offsets_start: usize,
+
+    /// The location of the byte preceding the item data, which the offsets
+    /// are relative to.
+    data_base: usize,
+
+    /// The location of the end of the INDEX.
+    end: usize,
+}
+
+impl CffIndex {
+    fn parse(data: &[u8], start: usize) -> Option<Self> {
+        let count = read_u16(data, start)? as usize;
+
+        if count == 0 {
+            return Some(CffIndex {
+                count,
+                off_size: 0,
+                offsets_start: start + 2,
+                data_base: start + 2,
+                end: start + 2,
+            });
+        }
+
+        let off_size = *data.get(start + 2)? as usize;
+
+        if !(1..=4).contains(&off_size) {
+            return None;
+        }
+
+        let mut index = CffIndex {
+            count,
+            off_size,
+            offsets_start: start + 3,
+            data_base: start + 2 + (count + 1) * off_size,
+            end: 0,
+        };
+
+        index.end = index.data_base + index.offset(data, count)?;
+
+        if index.end > data.len() {
+            return None;
+        }
+
+        Some(index)
+    }
+
+    fn offset(&self, data: &[u8], i: usize) -> Option<usize> {
+        let start = self.offsets_start + i * self.off_size;
+        let bytes = data.get(start..start + self.off_size)?;
+        Some(BigEndian::read_uint(bytes, self.off_size) as usize)
+    }
+
+    fn item<'a>(&self, data: &'a [u8], i: usize) -> Option<&'a [u8]> {
+        let start = self.data_base + self.offset(data, i)?;
+        let end = self.data_base + self.offset(data, i + 1)?;
+        data.get(start..end)
+    }
+
+    fn items<'a>(&self, data: &'a [u8]) -> Option<Vec<&'a [u8]>> {
+        (0..self.count).map(|i| self.item(data, i)).collect()
+    }
+}
+
+fn write_index(items: &[&[u8]]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    buf.write_u16::<BigEndian>(items.len() as u16).unwrap();
+
+    if items.is_empty() {
+        return buf;
+    }
+
+    let data_len: usize = items.iter().map(|i| i.len()).sum();
+    let off_size = match data_len + 1 {
+        0..=0xFF => 1,
+        0x100..=0xFFFF => 2,
+        0x1_0000..=0xFF_FFFF => 3,
+        _ => 4,
+    };
+
+    buf.push(off_size as u8);
+    let mut offset = 1;
+
+    for item in items.iter().map(|i| i.len()).chain(std::iter::once(0)) {
+        buf.write_uint::<BigEndian>(offset as u64, off_size)
+            .unwrap();
+        offset += item;
+    }
+
+    for item in items {
+        buf.extend_from_slice(item);
+    }
+
+    buf
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum CffOperand {
+    Int(i32),
+
+    /// A real number, stored in its encoded form.
+    Real(Vec<u8>),
+}
+
+type CffDict = Vec<(u16, Vec<CffOperand>)>;
+
+fn parse_dict(data: &[u8]) -> Option<CffDict> {
+    let mut dict = Vec::new();
+    let mut operands = Vec::new();
+    let mut i = 0;
+
+    while i < data.len() {
+        let b0 = data[i];
+        let byte = |n: usize| data.get(i + n).map(|b| *b as i32);
+
+        match b0 {
+            0..=21 => {
+                let op = if b0 == 12 {
+                    i += 1;
+                    0x0c00 | *data.get(i)? as u16
+                } else {
+                    b0 as u16
+                };
+
+                dict.push((op, std::mem::take(&mut operands)));
+                i += 1;
+            }
+
+            28 => {
+                operands.push(CffOperand::Int(
+                    BigEndian::read_i16(data.get(i + 1..i + 3)?) as i32,
+                ));
+                i += 3;
+            }
+
+            29 => {
+                operands.push(CffOperand::Int(BigEndian::read_i32(
+                    data.get(i + 1..i + 5)?,
+                )));
+                i += 5;
+            }
+
+            30 => {
+                let start = i;
+                i += 1;
+
+                loop {
+                    let b = *data.get(i)?;
+                    i += 1;
+
+                    if b >> 4 == 0xf || b & 0xf == 0xf {
+                        break;
+                    }
+                }
+
+                operands.push(CffOperand::Real(data[start..i].to_vec()));
+            }
+
+            32..=246 => {
+                operands.push(CffOperand::Int(b0 as i32 - 139));
+                i += 1;
+            }
+
+            247..=250 => {
+                operands.push(CffOperand::Int((b0 as i32 - 247) * 256 + byte(1)? + 108));
+                i += 2;
+            }
+
+            251..=254 => {
+                operands.push(CffOperand::Int(-(b0 as i32 - 251) * 256 - byte(1)? - 108));
+                i += 2;
+            }
+
+            _ => return None,
+        }
+    }
+
+    Some(dict)
+}
+
+/// Get an integer operand of a DICT entry.
+fn dict_int(dict: &CffDict, op: u16, index: usize) -> Option<i32> {
+    dict.iter()
+        .find(|(o, _)| *o == op)
+        .and_then(|(_, operands)| match operands.get(index) {
+            Some(CffOperand::Int(v)) => Some(*v),
+            _ => None,
+        })
+}
+
+fn encode_dict(dict: &CffDict) -> Vec<u8> {
+    let mut buf = Vec::new();
+
+    for (op, operands) in dict {
+        let is_offset = matches!(
+            *op,
+            OP_CHARSET | OP_ENCODING | OP_CHARSTRINGS | OP_PRIVATE | OP_SUBRS
+        );
+
+        for operand in operands {
+            match operand {
+                CffOperand::Int(v) if is_offset => {
+                    buf.push(29);
+                    buf.write_i32::<BigEndian>(*v).unwrap();
+                }
+                CffOperand::Int(v) => encode_int(&mut buf, *v),
+                CffOperand::Real(bytes) => buf.extend_from_slice(bytes),
+            }
+        }
+
+        if *op > 0xff {
+            buf.push(12);
+        }
+
+        buf.push(*op as u8);
+    }
+
+    buf
+}
+
+fn encode_int(buf: &mut Vec<u8>, v: i32) {
+    match v {
+        -107..=107 => buf.push((v + 139) as u8),
+        108..=1131 => {
+            let w = v - 108;
+            buf.push((w / 256 + 247) as u8);
+            buf.push((w % 256) as u8);
+        }
+        -1131..=-108 => {
+            let w = -v - 108;
+            buf.push((w / 256 + 251) as u8);
+            buf.push((w % 256) as u8);
+        }
+        -32768..=32767 => {
+            buf.push(28);
+            buf.write_i16::<BigEndian>(v as i16).unwrap();
+        }
+        _ => {
+            buf.push(29);
+            buf.write_i32::<BigEndian>(v).unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::{Path, PathBuf};
+
+    fn assets_dir() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../../tests/assets")
+            .canonicalize()
+            .unwrap()
+    }
+
+    fn load(name: &str) -> SfntTables {
+        let data = std::fs::read(assets_dir().join(name)).unwrap();
+        SfntTables::parse(&data).unwrap()
+    }
+
+    fn top_dict(cff: &[u8]) -> CffDict {
+        let name_index = CffIndex::parse(cff, cff[2] as usize).unwrap();
+        let top_index = CffIndex::parse(cff, name_index.end).unwrap();
+        parse_dict(top_index.item(cff, 0).unwrap()).unwrap()
+    }
+
+    fn cff_charstrings(cff: &[u8]) -> Vec<Vec<u8>> {
+        let cs_start = dict_int(&top_dict(cff), OP_CHARSTRINGS, 0).unwrap() as usize;
+        let charstrings = CffIndex::parse(cff, cs_start).unwrap();
+        (0..charstrings.count)
+            .map(|i| charstrings.item(cff, i).unwrap().to_vec())
+            .collect()
+    }
+
+    /// The parts of a CFF table that the Top DICT points to, aside from the
+    /// CharStrings.
+    #[derive(Debug, PartialEq)]
+    struct CffParts {
+        /// The Private DICT, without the offset of the local subroutines.
+        private: CffDict,
+        local_subrs: Vec<Vec<u8>>,
+        global_subrs: Vec<Vec<u8>>,
+        charset_start: Vec<u8>,
+    }
+
+    fn cff_parts(cff: &[u8]) -> CffParts {
+        let top = top_dict(cff);
+        let size = dict_int(&top, OP_PRIVATE, 0).unwrap() as usize;
+        let offset = dict_int(&top, OP_PRIVATE, 1).unwrap() as usize;
+        let mut private = parse_dict(&cff[offset..offset + size]).unwrap();
+        let subrs_ofs = dict_int(&private, OP_SUBRS, 0).unwrap() as usize;
+        private.retain(|(op, _)| *op != OP_SUBRS);
+
+        let to_vecs = |items: Vec<&[u8]>| items.into_iter().map(|i| i.to_vec()).collect();
+        let local_subrs = CffIndex::parse(cff, offset + subrs_ofs).unwrap();
+        let name_index = CffIndex::parse(cff, cff[2] as usize).unwrap();
+        let top_index = CffIndex::parse(cff, name_index.end).unwrap();
+        let string_index = CffIndex::parse(cff, top_index.end).unwrap();
+        let global_subrs = CffIndex::parse(cff, string_index.end).unwrap();
+        let charset = dict_int(&top, OP_CHARSET, 0).unwrap() as usize;
+
+        CffParts {
+            private,
+            local_subrs: to_vecs(local_subrs.items(cff).unwrap()),
+            global_subrs: to_vecs(global_subrs.items(cff).unwrap()),
+            charset_start: cff[charset..charset + 16].to_vec(),
+        }
+    }
+
+    #[test]
+    fn dict_roundtrip() {
+        let mut buf = Vec::new();
+
+        for v in [0, 107, -107, 108, 1131, -108, -1131, 1132, -32768, 32768] {
+            encode_int(&mut buf, v);
+        }
+
+        buf.push(OP_SUBRS as u8);
+        assert_eq!(
+            parse_dict(&buf).unwrap(),
+            vec![(
+                OP_SUBRS,
+                [0, 107, -107, 108, 1131, -108, -1131, 1132, -32768, 32768]
+                    .into_iter()
+                    .map(CffOperand::Int)
+                    .collect()
+            )]
+        );
+    }
+
+    #[test]
+    fn subset_cff_font() {
+        let orig = load("lmroman12-regular.otf");
+        let mut font = orig.clone();
+        let keep = [36, 37, 38].into_iter().collect();
+        assert!(font.subset(&keep));
+        assert!(font.tables[&CFF].len() < orig.tables[&CFF].len() / 3);
+
+        let before = cff_charstrings(&orig.tables[&CFF]);
+        let after = cff_charstrings(&font.tables[&CFF]);
+        assert_eq!(before.len(), after.len());
+
+        for (gid, (b, a)) in before.iter().zip(&after).enumerate() {
+            if gid == 0 || keep.contains(&(gid as GlyphId)) {
+                assert_eq!(a, b);
+            } else {
+                assert_eq!(a, &[ENDCHAR]);
+            }
+        }
+
+        // The other structures that the Top DICT points to must have moved
+        // along with it.
+        let orig_parts = cff_parts(&orig.tables[&CFF]);
+        let parts = cff_parts(&font.tables[&CFF]);
+        assert_eq!(parts.private, orig_parts.private);
+        assert_eq!(parts.charset_start, orig_parts.charset_start);
+        assert_eq!(parts.local_subrs.len(), orig_parts.local_subrs.len());
+        assert_eq!(parts.global_subrs.len(), orig_parts.global_subrs.len());
+
+        // The subroutines that the kept glyphs use must be intact, and the
+        // others emptied.
+        let local: Vec<_> = parts.local_subrs.iter().map(|s| &s[..]).collect();
+        let global: Vec<_> = parts.global_subrs.iter().map(|s| &s[..]).collect();
+        let mut scanner = SubrScanner::new(&local, &global);
+
+        for gid in keep {
+            assert!(scanner.scan_charstring(&after[gid as usize]));
+        }
+
+        assert!(!scanner.used_local.is_empty());
+
+        for (i, (new, orig)) in parts
+            .local_subrs
+            .iter()
+            .zip(&orig_parts.local_subrs)
+            .enumerate()
+        {
+            if scanner.used_local.contains(&i) {
+                assert_eq!(new, orig);
+            } else {
+                assert_eq!(new, &[RETURN]);
+            }
+        }
+
+        for (i, (new, orig)) in parts
+            .global_subrs
+            .iter()
+            .zip(&orig_parts.global_subrs)
+            .enumerate()
+        {
+            if scanner.used_global.contains(&i) {
+                assert_eq!(new, orig);
+            } else {
+                assert_eq!(new, &[RETURN]);
+            }
+        }
+    }
+
+    #[test]
+    fn subset_glyf_font() {
+        let orig = load("LinLibertine_R_G.ttf");
+        let mut font = orig.clone();
+        let keep = [36, 37, 38].into_iter().collect();
+        assert!(font.subset(&keep));
+        assert!(font.tables[&GLYF].len() < orig.tables[&GLYF].len() / 100);
+
+        let num_glyphs = read_u16(&font.tables[&MAXP], 4).unwrap() as usize;
+        assert_eq!(font.tables[&LOCA].len(), 4 * (num_glyphs + 1));
+        assert_eq!(read_u16(&font.tables[&HEAD], 50), Some(1));
+
+        let loca = &font.tables[&LOCA];
+        let size = |g: usize| read_u32(loca, 4 * g + 4).unwrap() - read_u32(loca, 4 * g).unwrap();
+        assert!(size(36) > 0);
+        assert_eq!(size(39), 0);
+    }
+
+    #[test]
+    fn composite_closure() {
+        // A composite glyph with two components, the first with word-sized
+        // arguments and a scale.
+        let mut composite = vec![0xff, 0xff, 0, 0, 0, 0, 0, 0, 0, 0];
+        composite.extend_from_slice(&[0x00, 0x29, 0x00, 0x05, 0, 0, 0, 0, 0x40, 0x00]);
+        composite.extend_from_slice(&[0x00, 0x00, 0x00, 0x07, 0, 0]);
+        assert_eq!(composite_components(&composite), Some(vec![5, 7]));
+        assert_eq!(composite_components(&[0, 1, 0, 0]), Some(vec![]));
+        assert_eq!(composite_components(&[]), Some(vec![]));
+    }
+
+    #[test]
+    fn sfnt_checksum() {
+        let font = load("Inconsolatazi4-Regular.otf");
+        let sfnt = font.to_sfnt();
+        assert_eq!(opentype_checksum(&sfnt), 0xB1B0AFBA);
+        let reparsed = SfntTables::parse(&sfnt).unwrap();
+        assert_eq!(reparsed.tables.len(), font.tables.len());
+    }
+}
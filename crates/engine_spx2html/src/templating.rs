@@ -4,13 +4,49 @@
 //! State relating to handling the Tera templating and file emission.
 
 use std::{
+    collections::HashMap,
     fs::File,
     io::{Read, Write},
+    path::{Path, PathBuf},
 };
 use tectonic_errors::prelude::*;
 use tectonic_status_base::tt_warning;
 
-use crate::Common;
+use crate::{
+    assets::{rewrite_references, Assets},
+    Common,
+};
+
+/// The name of the built-in page template, which is used for documents that
+/// don't choose a template of their own. A template provided to the engine
+/// under this name replaces it.
+pub(crate) const DEFAULT_TEMPLATE_NAME: &str = "tdux-default.html";
+
+/// The output path of the font CSS that the built-in template links to.
+pub(crate) const DEFAULT_FONT_CSS_PATH: &str = "tdux-fonts.css";
+
+/// The built-in page template. It's deliberately plain: just the document's
+/// content in a readable column, with the fonts that it uses.
+const DEFAULT_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<link rel="stylesheet" href="{{ tduxRelTop }}tdux-fonts.css">
+<style>
+body {
+  max-width: 45rem;
+  margin: 0 auto;
+  padding: 1rem;
+  line-height: 1.4;
+}
+</style>
+</head>
+<body>
+{{ tduxContent }}
+</body>
+</html>
+"#;
 
 #[derive(Debug)]
 pub(crate) struct Templating {
@@ -18,6 +54,82 @@ pub(crate) struct Templating {
     context: tera::Context,
     next_template_path: String,
     next_output_path: String,
+    split: Option<SplitState>,
+    deferred: Vec<DeferredOutput>,
+}
+
+/// The state of an output that's being split into several linked pages.
+#[derive(Debug)]
+struct SplitState {
+    /// The output path of the first page. The paths of the others are derived
+    /// from it.
+    base_path: String,
+
+    /// The number of pages emitted so far.
+    n_pages: usize,
+
+    /// The output path of the previous page, if there is one.
+    prev_path: Option<String>,
+}
+
+impl SplitState {
+    fn new(base_path: &str) -> Self {
+        SplitState {
+            base_path: base_path.to_owned(),
+            n_pages: 0,
+            prev_path: None,
+        }
+    }
+
+    /// Get the output path of the *n*'th page, counting from 1. The first
+    /// page keeps the original path, and the others get a number before the
+    /// extension, as in `index-2.html`.
+    fn page_path(&self, n: usize) -> String {
+        if n < 2 {
+            return self.base_path.clone();
+        }
+
+        match self.base_path.rsplit_once('.') {
+            Some((stem, ext)) if !ext.contains('/') => format!("{stem}-{n}.{ext}"),
+            _ => format!("{}-{}", self.base_path, n),
+        }
+    }
+}
+
+/// Generate the navigation links that go at the end of a split page. The
+/// pages are all in the same directory, so the links only need their names.
+fn page_navigation(prev_path: Option<&str>, next_path: Option<&str>) -> String {
+    let mut nav = String::from("<nav class=\"tdux-pagenav\">");
+
+    for (rel, label, path) in [("prev", "Previous", prev_path), ("next", "Next", next_path)] {
+        if let Some(path) = path {
+            let name = path.rsplit('/').next().unwrap_or(path);
+            nav.push_str(&format!(
+                "<a rel=\"{rel}\" href=\"{}\">{label}</a>",
+                html_escape::encode_double_quoted_attribute(name)
+            ));
+        }
+    }
+
+    nav.push_str("</nav>");
+    nav
+}
+
+/// An HTML output whose writing has been put off until the names of the
+/// optimized assets are known.
+#[derive(Debug)]
+pub(crate) struct DeferredOutput {
+    tex_path: String,
+    out_path: PathBuf,
+    contents: String,
+}
+
+impl DeferredOutput {
+    /// Write out the file, updating its references to renamed assets.
+    pub(crate) fn write(self, renames: &HashMap<String, String>) -> Result<()> {
+        let contents = rewrite_references(&self.contents, &self.tex_path, renames);
+        write_output(&self.out_path, &contents)
+    }
 }
 
 impl Templating {
@@ -32,6 +144,8 @@ impl Templating {
             context,
             next_template_path,
             next_output_path,
+            split: None,
+            deferred: Vec::new(),
         }
     }
 
@@ -67,11 +181,61 @@ impl Templating {
         self.context.insert(name, value.as_ref());
     }
 
+    /// Whether the next output will use the built-in template.
+    pub(crate) fn using_default_template(&self) -> bool {
+        self.next_template_path == DEFAULT_TEMPLATE_NAME && !self.next_output_path.is_empty()
+    }
+
     pub(crate) fn ready_to_output(&self) -> bool {
         !self.next_template_path.is_empty() && !self.next_output_path.is_empty()
     }
 
-    pub(crate) fn emit(&mut self, common: &mut Common) -> Result<()> {
+    /// Emit `content` as the next output file. If the output was being split
+    /// into pages, this is its last page.
+    pub(crate) fn emit_content(
+        &mut self,
+        content: String,
+        assets: &mut Assets,
+        common: &mut Common,
+    ) -> Result<()> {
+        let content = match self.split.take() {
+            Some(split) => content + &page_navigation(split.prev_path.as_deref(), None),
+            None => content,
+        };
+
+        self.set_variable("tduxContent", content);
+        self.emit(assets, common)
+    }
+
+    /// Emit `content` as a page of an output that's being split into several
+    /// pages, and set up the output path of the page after it.
+    pub(crate) fn emit_split_page(
+        &mut self,
+        content: String,
+        assets: &mut Assets,
+        common: &mut Common,
+    ) -> Result<()> {
+        let split = self
+            .split
+            .get_or_insert_with(|| SplitState::new(&self.next_output_path));
+        split.n_pages += 1;
+
+        let cur_path = self.next_output_path.clone();
+        let next_path = split.page_path(split.n_pages + 1);
+        let nav = page_navigation(split.prev_path.as_deref(), Some(&next_path));
+
+        self.set_variable("tduxContent", content + &nav);
+        self.emit(assets, common)?;
+
+        if let Some(split) = self.split.as_mut() {
+            split.prev_path = Some(cur_path);
+        }
+
+        self.next_output_path = next_path;
+        Ok(())
+    }
+
+    fn emit(&mut self, assets: &mut Assets, common: &mut Common) -> Result<()> {
         if self.next_template_path.is_empty() {
             bail!("need to emit HTML content but no template has been specified; is your document HTML-compatible?");
         }
@@ -95,26 +259,34 @@ impl Templating {
             self.context.insert("tduxRelTop", &rel_top);
         }
 
-        // Read in the template. Let's not cache it, in case someone wants to do
-        // something fancy with rewriting it. If that setting is empty, probably
-        // the user is compiling the document in HTML mode without all of the
-        // TeX infrastructure that Tectonic needs to make it work.
+        // Read in the template, unless one was provided to the engine or it's
+        // the built-in one. Let's not cache it, in case someone wants to do
+        // something fancy with rewriting it. The built-in template needs the
+        // font CSS, which the document won't have asked for.
 
-        let mut ih = atry!(
-            common.hooks.io().input_open_name(&self.next_template_path, common.status).must_exist();
-            ["unable to open input HTML template `{}`", &self.next_template_path]
-        );
+        let template = if let Some(t) = common.templates.get(&self.next_template_path) {
+            t.clone()
+        } else if self.next_template_path == DEFAULT_TEMPLATE_NAME {
+            assets.provide_font_css(DEFAULT_FONT_CSS_PATH);
+            DEFAULT_TEMPLATE.to_owned()
+        } else {
+            let mut ih = atry!(
+                common.hooks.io().input_open_name(&self.next_template_path, common.status).must_exist();
+                ["unable to open input HTML template `{}`", &self.next_template_path]
+            );
 
-        let mut template = String::new();
-        atry!(
-            ih.read_to_string(&mut template);
-            ["unable to read input HTML template `{}`", &self.next_template_path]
-        );
+            let mut template = String::new();
+            atry!(
+                ih.read_to_string(&mut template);
+                ["unable to read input HTML template `{}`", &self.next_template_path]
+            );
 
-        let (name, digest_opt) = ih.into_name_digest();
-        common
-            .hooks
-            .event_input_closed(name, digest_opt, common.status);
+            let (name, digest_opt) = ih.into_name_digest();
+            common
+                .hooks
+                .event_input_closed(name, digest_opt, common.status);
+            template
+        };
 
         // Ready to render!
 
@@ -123,18 +295,19 @@ impl Templating {
             ["failed to render HTML template `{}` while creating `{}`", &self.next_template_path, &self.next_output_path]
         );
 
-        // Save it. Unless we shouldn't, actually.
+        // Save it. Unless we shouldn't, actually. If we're optimizing assets,
+        // we can't write it until we know what they're called.
 
         if let Some(out_path) = out_path {
-            let mut out_file = atry!(
-                File::create(&out_path);
-                ["cannot open output file `{}`", out_path.display()]
-            );
-
-            atry!(
-                out_file.write_all(rendered.as_bytes());
-                ["cannot write output file `{}`", out_path.display()]
-            );
+            if common.optimize_assets {
+                self.deferred.push(DeferredOutput {
+                    tex_path: self.next_output_path.clone(),
+                    out_path,
+                    contents: rendered,
+                });
+            } else {
+                write_output(&out_path, &rendered)?;
+            }
         }
 
         // Clear the output path, because we don't want people to be accidentally
@@ -144,4 +317,50 @@ impl Templating {
 
         Ok(())
     }
+
+    pub(crate) fn into_deferred(self) -> Vec<DeferredOutput> {
+        self.deferred
+    }
+}
+
+fn write_output(out_path: &Path, contents: &str) -> Result<()> {
+    let mut out_file = atry!(
+        File::create(out_path);
+        ["cannot open output file `{}`", out_path.display()]
+    );
+
+    atry!(
+        out_file.write_all(contents.as_bytes());
+        ["cannot write output file `{}`", out_path.display()]
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_page_paths() {
+        let split = SplitState::new("book/index.html");
+        assert_eq!(split.page_path(1), "book/index.html");
+        assert_eq!(split.page_path(2), "book/index-2.html");
+
+        let split = SplitState::new("v1.0/index");
+        assert_eq!(split.page_path(3), "v1.0/index-3");
+    }
+
+    #[test]
+    fn split_page_navigation() {
+        assert_eq!(
+            page_navigation(Some("book/index.html"), Some("book/index-3.html")),
+            "<nav class=\"tdux-pagenav\"><a rel=\"prev\" href=\"index.html\">Previous</a>\
+            <a rel=\"next\" href=\"index-3.html\">Next</a></nav>"
+        );
+        assert_eq!(
+            page_navigation(Some("a\"b.html"), None),
+            "<nav class=\"tdux-pagenav\"><a rel=\"prev\" href=\"a&quot;b.html\">Previous</a></nav>"
+        );
+    }
 }
@@ -0,0 +1,225 @@
+// Copyright 2025 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Encoding fonts in the WOFF2 format.
+//!
+//! WOFF2 is OpenType data compressed with Brotli, and is the most compact way
+//! to ship fonts to web browsers. The format also defines transforms of the
+//! `glyf`, `loca`, and `hmtx` tables that make them compress better, but they
+//! are optional and we don't apply them.
+
+use brotli::enc::{backward_references::BrotliEncoderMode, BrotliEncoderParams};
+use byteorder::{BigEndian, WriteBytesExt};
+
+use crate::subset::{SfntTables, GLYF, LOCA};
+
+const WOFF2_SIGNATURE: u32 = 0x774F_4632; // "wOF2"
+
+/// The tags that can be identified by an index in the WOFF2 table directory.
+/// Any other tag is given explicitly.
+const KNOWN_TAGS: [&[u8; 4]; 63] = [
+    b"cmap", b"head", b"hhea", b"hmtx", b"maxp", b"name", b"OS/2", b"post", b"cvt ", b"fpgm",
+    b"glyf", b"loca", b"prep", b"CFF ", b"VORG", b"EBDT", b"EBLC", b"gasp", b"hdmx", b"kern",
+    b"LTSH", b"PCLT", b"VDMX", b"vhea", b"vmtx", b"BASE", b"GDEF", b"GPOS", b"GSUB", b"EBSC",
+    b"JSTF", b"MATH", b"CBDT", b"CBLC", b"COLR", b"CPAL", b"SVG ", b"sbix", b"acnt", b"avar",
+    b"bdat", b"bloc", b"bsln", b"cvar", b"fdsc", b"feat", b"fmtx", b"fvar", b"gvar", b"hsty",
+    b"just", b"lcar", b"mort", b"morx", b"opbd", b"prop", b"trak", b"Zapf", b"Silf", b"Glat",
+    b"Gloc", b"Feat", b"Sill",
+];
+
+/// Encode an OpenType font as WOFF2.
+///
+/// The input should be a complete font file with its checksums filled in, as
+/// produced by [`SfntTables::to_sfnt`]. Returns None if it can't be parsed.
+pub(crate) fn encode(sfnt: &[u8]) -> Option<Vec<u8>> {
+    let font = SfntTables::parse(sfnt)?;
+
+    // The tables go in tag order, except that `loca` has to come right after
+    // `glyf`.
+
+    let mut tables: Vec<_> = font.tables().filter(|(tag, _)| *tag != LOCA).collect();
+
+    if let Some(loca) = font.tables().find(|(tag, _)| *tag == LOCA) {
+        let pos = tables
+            .iter()
+            .position(|(tag, _)| *tag == GLYF)
+            .map(|i| i + 1)
+            .unwrap_or(tables.len());
+        tables.insert(pos, loca);
+    }
+
+    let mut directory = Vec::new();
+    let mut data = Vec::new();
+    let mut sfnt_size = 12 + 16 * tables.len();
+
+    for (tag, contents) in &tables {
+        // For `glyf` and `loca`, transform version 3 is the null transform.
+        // For everything else, it's version 0.
+        let transform = if *tag == GLYF || *tag == LOCA {
+            3 << 6
+        } else {
+            0
+        };
+
+        match KNOWN_TAGS
+            .iter()
+            .position(|t| u32::from_be_bytes(**t) == *tag)
+        {
+            Some(i) => directory.push(i as u8 | transform),
+            None => {
+                directory.push(63 | transform);
+                directory.write_u32::<BigEndian>(*tag).unwrap();
+            }
+        }
+
+        write_uint_base128(&mut directory, contents.len() as u32);
+        data.extend_from_slice(contents);
+        sfnt_size += (contents.len() + 3) & !3;
+    }
+
+    let params = BrotliEncoderParams {
+        mode: BrotliEncoderMode::BROTLI_MODE_FONT,
+        quality: 11,
+        size_hint: data.len(),
+        ..Default::default()
+    };
+
+    let mut compressed = Vec::new();
+    brotli::BrotliCompress(&mut &data[..], &mut compressed, &params).ok()?;
+
+    // The compressed data is padded out to a four-byte boundary, which is
+    // where the file ends since we don't include any metadata.
+
+    let length = (48 + directory.len() + compressed.len() + 3) & !3;
+
+    let mut buf = Vec::with_capacity(length);
+    buf.write_u32::<BigEndian>(WOFF2_SIGNATURE).unwrap();
+    buf.write_u32::<BigEndian>(font.flavor()).unwrap();
+    buf.write_u32::<BigEndian>(length as u32).unwrap();
+    buf.write_u16::<BigEndian>(tables.len() as u16).unwrap();
+    buf.write_u16::<BigEndian>(0).unwrap(); // reserved
+    buf.write_u32::<BigEndian>(sfnt_size as u32).unwrap();
+    buf.write_u32::<BigEndian>(compressed.len() as u32).unwrap();
+    buf.write_u16::<BigEndian>(1).unwrap(); // majorVersion
+    buf.write_u16::<BigEndian>(0).unwrap(); // minorVersion
+    buf.write_u32::<BigEndian>(0).unwrap(); // metaOffset
+    buf.write_u32::<BigEndian>(0).unwrap(); // metaLength
+    buf.write_u32::<BigEndian>(0).unwrap(); // metaOrigLength
+    buf.write_u32::<BigEndian>(0).unwrap(); // privOffset
+    buf.write_u32::<BigEndian>(0).unwrap(); // privLength
+    buf.extend_from_slice(&directory);
+    buf.extend_from_slice(&compressed);
+    buf.resize(length, 0);
+    Some(buf)
+}
+
+/// Write a number in the WOFF2 variable-length `UIntBase128` encoding: seven
+/// bits per byte, most significant first, with the high bit set on all bytes
+/// but the last.
+fn write_uint_base128(buf: &mut Vec<u8>, mut value: u32) {
+    let mut bytes = [0u8; 5];
+    let mut n = 0;
+
+    loop {
+        bytes[n] = (value & 0x7F) as u8;
+        n += 1;
+        value >>= 7;
+
+        if value == 0 {
+            break;
+        }
+    }
+
+    for i in (0..n).rev() {
+        buf.push(bytes[i] | if i > 0 { 0x80 } else { 0 });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use byteorder::{ByteOrder, ReadBytesExt};
+    use std::io::Read;
+
+    fn read_uint_base128(r: &mut &[u8]) -> u32 {
+        let mut value = 0;
+
+        loop {
+            let b = r.read_u8().unwrap();
+            value = (value << 7) | (b & 0x7F) as u32;
+
+            if b & 0x80 == 0 {
+                return value;
+            }
+        }
+    }
+
+    #[test]
+    fn base128() {
+        for (value, expected) in [
+            (0, &[0x00][..]),
+            (127, &[0x7F]),
+            (128, &[0x81, 0x00]),
+            (63 * 128 + 5, &[0xBF, 0x05]),
+            (u32::MAX, &[0x8F, 0xFF, 0xFF, 0xFF, 0x7F]),
+        ] {
+            let mut buf = Vec::new();
+            write_uint_base128(&mut buf, value);
+            assert_eq!(buf, expected);
+            assert_eq!(read_uint_base128(&mut &buf[..]), value);
+        }
+    }
+
+    /// Decode our WOFF2 output and check that it reproduces the input tables.
+    #[test]
+    fn roundtrip() {
+        let mut font = SfntTables::parse(&[0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]).unwrap();
+        font.set_table(u32::from_be_bytes(*b"head"), vec![1; 54]);
+        font.set_table(LOCA, vec![0, 0, 0, 0, 0, 0, 0, 8]);
+        font.set_table(GLYF, vec![7; 8]);
+        font.set_table(u32::from_be_bytes(*b"zzzz"), vec![3; 5]);
+        let sfnt = font.to_sfnt();
+
+        let woff2 = encode(&sfnt).unwrap();
+        assert_eq!(woff2.len() % 4, 0);
+        assert_eq!(BigEndian::read_u32(&woff2[0..4]), WOFF2_SIGNATURE);
+        assert_eq!(BigEndian::read_u32(&woff2[8..12]) as usize, woff2.len());
+        assert_eq!(BigEndian::read_u16(&woff2[12..14]), 4);
+        assert_eq!(BigEndian::read_u32(&woff2[16..20]) as usize, sfnt.len());
+
+        let mut dir = &woff2[48..];
+        let mut entries = Vec::new();
+
+        for _ in 0..4 {
+            let flags = dir.read_u8().unwrap();
+            let tag = match flags & 63 {
+                63 => dir.read_u32::<BigEndian>().unwrap(),
+                i => u32::from_be_bytes(*KNOWN_TAGS[i as usize]),
+            };
+            entries.push((tag, flags >> 6, read_uint_base128(&mut dir)));
+        }
+
+        let tags: Vec<_> = entries.iter().map(|e| e.0.to_be_bytes()).collect();
+        assert_eq!(tags, [*b"glyf", *b"loca", *b"head", *b"zzzz"]);
+        assert_eq!(entries[0].1, 3);
+        assert_eq!(entries[1].1, 3);
+        assert_eq!(entries[2].1, 0);
+
+        let compressed_len = BigEndian::read_u32(&woff2[20..24]) as usize;
+        let mut data = Vec::new();
+        brotli::Decompressor::new(&dir[..compressed_len], 4096)
+            .read_to_end(&mut data)
+            .unwrap();
+
+        let reparsed = SfntTables::parse(&sfnt).unwrap();
+        let mut expected = Vec::new();
+
+        for (tag, _, len) in &entries {
+            let contents = reparsed.tables().find(|(t, _)| t == tag).unwrap().1;
+            assert_eq!(contents.len(), *len as usize);
+            expected.extend_from_slice(contents);
+        }
+
+        assert_eq!(data, expected);
+    }
+}
@@ -37,8 +37,22 @@ use tectonic_errors::prelude::*;
 pub struct XdvipdfmxEngine {
     paper_spec: String,
     enable_compression: bool,
+    compression_level: u8,
+    enable_object_streams: bool,
+    pdf_version: (u8, u8),
+    image_max_dpi: u32,
+    jpeg_quality: u8,
+    grayscale_images: bool,
+    outline_open_depth: u8,
     deterministic_tags: bool,
     build_date: SystemTime,
+    document_info: Vec<(String, String)>,
+    language: Option<String>,
+    pdfa: bool,
+    encryption: Option<(String, String)>,
+    allow_printing: bool,
+    allow_copying: bool,
+    attachments: Vec<(String, Vec<u8>)>,
 }
 
 impl Default for XdvipdfmxEngine {
@@ -46,8 +60,22 @@ impl Default for XdvipdfmxEngine {
         XdvipdfmxEngine {
             paper_spec: "letter".to_owned(),
             enable_compression: true,
+            compression_level: 9,
+            enable_object_streams: true,
+            pdf_version: (1, 5),
+            image_max_dpi: 0,
+            jpeg_quality: 0,
+            grayscale_images: false,
+            outline_open_depth: 0,
             deterministic_tags: false,
             build_date: SystemTime::UNIX_EPOCH,
+            document_info: Vec::new(),
+            language: None,
+            pdfa: false,
+            encryption: None,
+            allow_printing: true,
+            allow_copying: true,
+            attachments: Vec::new(),
         }
     }
 }
@@ -65,6 +93,82 @@ impl XdvipdfmxEngine {
         self
     }
 
+    /// Set the level of compression applied to the output PDF's streams, from
+    /// 0 (none) to 9 (most).
+    ///
+    /// The default is 9. This has no effect if compression is disabled with
+    /// [`enable_compression`](Self::enable_compression). Documents can still
+    /// change it with a `pdf:config` special.
+    pub fn compression_level(&mut self, level: u8) -> &mut Self {
+        self.compression_level = level;
+        self
+    }
+
+    /// Set whether objects may be collected into compressed object streams.
+    ///
+    /// The default is true. Object streams make the output smaller, but some
+    /// older PDF consumers can't read them. They're never used for PDF
+    /// versions before 1.5.
+    pub fn enable_object_streams(&mut self, enable_object_streams: bool) -> &mut Self {
+        self.enable_object_streams = enable_object_streams;
+        self
+    }
+
+    /// Set the version of the PDF format that the output targets, such as
+    /// `(1, 4)` for PDF 1.4.
+    ///
+    /// The default is 1.5. Versions from 1.3 to 1.7 and 2.0 are supported.
+    /// Documents can still change it with the `pdf:majorversion` and
+    /// `pdf:minorversion` specials.
+    pub fn pdf_version(&mut self, major: u8, minor: u8) -> &mut Self {
+        self.pdf_version = (major, minor);
+        self
+    }
+
+    /// Set the maximum resolution of raster images in the output, in dots per
+    /// inch.
+    ///
+    /// The default is 0, meaning that images are embedded at their original
+    /// resolution. Otherwise, 8-bit grayscale and RGB images (including JPEGs)
+    /// that are placed at a higher resolution are downsampled. The resolution
+    /// is computed from the largest size at which each image is placed.
+    /// Transparency masks keep their original resolution.
+    pub fn image_max_dpi(&mut self, dpi: u32) -> &mut Self {
+        self.image_max_dpi = dpi;
+        self
+    }
+
+    /// Set the quality, from 1 to 100, at which JPEG images are re-encoded.
+    ///
+    /// The default is 0, meaning that JPEGs are only re-encoded when they have
+    /// to be downsampled or converted to grayscale, at a quality of 90. A
+    /// re-encoded JPEG that would be larger than the original is discarded.
+    pub fn jpeg_quality(&mut self, quality: u8) -> &mut Self {
+        self.jpeg_quality = quality;
+        self
+    }
+
+    /// Set whether color raster images will be converted to grayscale.
+    ///
+    /// The default is false. Only images that are eligible for downsampling
+    /// (see [`image_max_dpi`](Self::image_max_dpi)) are converted; vector
+    /// graphics and text colors are unaffected.
+    pub fn grayscale_images(&mut self, grayscale: bool) -> &mut Self {
+        self.grayscale_images = grayscale;
+        self
+    }
+
+    /// Set the number of levels of the document outline (bookmarks) that are
+    /// expanded when the PDF is opened.
+    ///
+    /// The default is 0, meaning that only the top-level entries are visible.
+    /// This applies to outline entries whose open state isn't given
+    /// explicitly by the `pdf:outline` special that creates them.
+    pub fn outline_open_depth(&mut self, depth: u8) -> &mut Self {
+        self.outline_open_depth = depth;
+        self
+    }
+
     /// Set whether font tags will be generated deterministically.
     ///
     /// The default is false: the engine includes some random characters when
@@ -93,6 +197,86 @@ impl XdvipdfmxEngine {
         self
     }
 
+    /// Add an entry to the document information dictionary of the output PDF.
+    ///
+    /// The *key* is the name of the entry, such as `"Title"` or `"Author"`.
+    /// Entries added here are only defaults: if the document sets the same
+    /// entry with a `pdf:docinfo` special, as `hyperref` does, that value wins.
+    pub fn document_info(&mut self, key: &str, value: &str) -> &mut Self {
+        self.document_info.push((key.to_owned(), value.to_owned()));
+        self
+    }
+
+    /// Set the natural language of the document, as a language tag like
+    /// `"en-US"`.
+    ///
+    /// This is recorded as the `/Lang` entry of the PDF document catalog,
+    /// unless the document sets one itself.
+    pub fn language(&mut self, language: &str) -> &mut Self {
+        self.language = Some(language.to_owned());
+        self
+    }
+
+    /// Set whether the output should conform to PDF/A-2b.
+    ///
+    /// The default is false. When enabled, the output gets an sRGB output
+    /// intent with an embedded ICC profile and an XMP metadata stream matching
+    /// the document information dictionary, and any encryption requested by
+    /// the document is ignored. Fonts that can't be embedded are reported as
+    /// errors through the status backend, and processing then fails.
+    pub fn pdfa(&mut self, pdfa: bool) -> &mut Self {
+        self.pdfa = pdfa;
+        self
+    }
+
+    /// Encrypt the output PDF with the given passwords.
+    ///
+    /// By default, the output isn't encrypted. The *user_password* is needed
+    /// to open the document, unless it's empty; the *owner_password*, which
+    /// may not be empty, lifts the restrictions set with
+    /// [`allow_printing`](Self::allow_printing) and
+    /// [`allow_copying`](Self::allow_copying). Passwords may be at most 127
+    /// bytes long. The output is encrypted with 256-bit AES when the PDF
+    /// version is 1.7 or later, and 128-bit AES otherwise. A `pdf:encrypt`
+    /// special in the document replaces all of these settings.
+    pub fn encryption(&mut self, owner_password: &str, user_password: &str) -> &mut Self {
+        self.encryption = Some((owner_password.to_owned(), user_password.to_owned()));
+        self
+    }
+
+    /// Set whether an encrypted output may be printed without the owner
+    /// password.
+    ///
+    /// The default is true. This has no effect unless the output is encrypted
+    /// with [`encryption`](Self::encryption).
+    pub fn allow_printing(&mut self, allow: bool) -> &mut Self {
+        self.allow_printing = allow;
+        self
+    }
+
+    /// Set whether text and graphics may be copied out of an encrypted output
+    /// without the owner password.
+    ///
+    /// The default is true. Text can still be extracted for accessibility,
+    /// as by screen readers. This has no effect unless the output is encrypted
+    /// with [`encryption`](Self::encryption).
+    pub fn allow_copying(&mut self, allow: bool) -> &mut Self {
+        self.allow_copying = allow;
+        self
+    }
+
+    /// Attach a file to the output PDF.
+    ///
+    /// The *name* is the name under which PDF viewers list the file, and
+    /// *data* is its contents. If the document attaches a file with the same
+    /// name itself, its attachment is kept. Attachments can't be combined
+    /// with [`pdfa`](Self::pdfa), since PDF/A-2b only allows attaching other
+    /// PDF/A documents.
+    pub fn attachment(&mut self, name: &str, data: Vec<u8>) -> &mut Self {
+        self.attachments.push((name.to_owned(), data));
+        self
+    }
+
     /// Run xdvipdfmx.
     ///
     /// The *launcher* parameter gives overarching environmental context in
@@ -109,55 +293,360 @@ impl XdvipdfmxEngine {
         dvi: &str,
         pdf: &str,
     ) -> Result<()> {
+        match self.pdf_version {
+            (1, 3..=7) | (2, 0) => {}
+            (major, minor) => bail!("unsupported PDF version {major}.{minor}"),
+        }
+
+        if self.pdfa && self.pdf_version.0 > 1 {
+            bail!("PDF/A-2b output requires a PDF version of 1.7 or below");
+        }
+
+        if self.pdfa && !self.attachments.is_empty() {
+            bail!("PDF/A-2b output may not have attachments");
+        }
+
+        if let Some((owner_password, user_password)) = &self.encryption {
+            if self.pdfa {
+                bail!("PDF/A output may not be encrypted");
+            }
+
+            if owner_password.is_empty() {
+                bail!("an owner password is needed to encrypt the PDF");
+            }
+
+            for password in [owner_password, user_password] {
+                if password.len() > MAX_PASSWORD_LEN {
+                    bail!("PDF passwords may be at most {MAX_PASSWORD_LEN} bytes long");
+                }
+            }
+        }
+
+        if self.compression_level > 9 {
+            bail!(
+                "the PDF compression level must be between 0 and 9, not {}",
+                self.compression_level
+            );
+        }
+
+        if self.jpeg_quality > 100 {
+            bail!(
+                "the JPEG quality must be between 1 and 100, not {}",
+                self.jpeg_quality
+            );
+        }
+
         let paperspec_str = atry!(
             CString::new(self.paper_spec.as_str());
             ["paper_spec may not contain internal NULs"]
         );
 
+        let mut docinfo_keys = Vec::with_capacity(self.document_info.len());
+        let mut docinfo_values = Vec::with_capacity(self.document_info.len());
+
+        for (key, value) in &self.document_info {
+            docinfo_keys.push(atry!(
+                CString::new(key.as_str());
+                ["document info keys may not contain internal NULs"]
+            ));
+            docinfo_values.push(pdf_text_string(value));
+        }
+
+        let docinfo: Vec<_> = docinfo_keys
+            .iter()
+            .zip(&docinfo_values)
+            .map(|(key, value)| c_api::XdvipdfmxDocInfoEntry {
+                key: key.as_ptr(),
+                value: value.as_ptr(),
+                value_len: value.len(),
+            })
+            .collect();
+
+        let language = match self.language.as_deref() {
+            Some(l) => Some(atry!(
+                CString::new(l);
+                ["the language may not contain internal NULs"]
+            )),
+            None => None,
+        };
+
+        let attachment_names: Vec<_> = self
+            .attachments
+            .iter()
+            .map(|(name, _)| pdf_text_string(name))
+            .collect();
+
+        let attachments: Vec<_> = attachment_names
+            .iter()
+            .zip(&self.attachments)
+            .map(|(name, (_, data))| c_api::XdvipdfmxAttachment {
+                name: name.as_ptr(),
+                name_len: name.len(),
+                data: data.as_ptr(),
+                data_len: data.len(),
+            })
+            .collect();
+
+        let (owner, user) = self
+            .encryption
+            .as_ref()
+            .map_or(("", ""), |(o, u)| (o.as_str(), u.as_str()));
+
+        let owner_password = atry!(
+            CString::new(owner);
+            ["PDF passwords may not contain internal NULs"]
+        );
+
+        let user_password = atry!(
+            CString::new(user);
+            ["PDF passwords may not contain internal NULs"]
+        );
+
+        // The permission flags of the PDF standard security handler. Everything
+        // is allowed unless it's turned off here; in particular, extracting
+        // text for accessibility (bit 10) always is.
+        let mut permission = 0x0f3c;
+
+        if !self.allow_printing {
+            // Printing at all (bit 3), and at full quality (bit 12).
+            permission &= !0x0804;
+        }
+
+        if !self.allow_copying {
+            // Copying text and graphics (bit 5).
+            permission &= !0x0010;
+        }
+
+        let icc_profile = if self.pdfa {
+            srgb_icc_profile()
+        } else {
+            Vec::new()
+        };
+
         let config = c_api::XdvipdfmxConfig {
             paperspec: paperspec_str.as_c_str().as_ptr(),
             enable_compression: u8::from(self.enable_compression),
+            compression_level: self.compression_level,
+            enable_object_streams: u8::from(self.enable_object_streams),
+            pdf_version_major: self.pdf_version.0,
+            pdf_version_minor: self.pdf_version.1,
+            image_max_dpi: self.image_max_dpi,
+            jpeg_quality: self.jpeg_quality,
+            grayscale_images: u8::from(self.grayscale_images),
+            outline_open_depth: self.outline_open_depth,
             deterministic_tags: u8::from(self.deterministic_tags),
             build_date: self
                 .build_date
                 .duration_since(SystemTime::UNIX_EPOCH)
                 .expect("invalid build date")
                 .as_secs(),
+            docinfo: docinfo.as_ptr(),
+            docinfo_len: docinfo.len(),
+            language: language
+                .as_ref()
+                .map_or(std::ptr::null(), |l| l.as_c_str().as_ptr()),
+            pdfa: u8::from(self.pdfa),
+            icc_profile: icc_profile.as_ptr(),
+            icc_profile_len: icc_profile.len(),
+            encrypt: u8::from(self.encryption.is_some()),
+            encryption_key_bits: if self.pdf_version >= (1, 7) { 256 } else { 128 },
+            encryption_permission: permission,
+            owner_password: owner_password.as_ptr(),
+            user_password: user_password.as_ptr(),
+            attachments: attachments.as_ptr(),
+            attachments_len: attachments.len(),
         };
 
         let cdvi = CString::new(dvi)?;
         let cpdf = CString::new(pdf)?;
 
         launcher.with_global_lock(|state| {
+            state.begin_progress("writing PDF");
+
             // SAFETY: This is called while the global lock is held, and with valid C-strings for
             //         dvi and pdf.
             let r = unsafe {
                 c_api::tt_engine_xdvipdfmx_main(state, &config, cdvi.as_ptr(), cpdf.as_ptr())
             };
 
-            // At the moment, the only possible return codes are 0 and 99 (= abort).
-            if r == 99 {
-                Err(EngineAbortedError::new_abort_indicator().into())
-            } else {
-                Ok(())
+            // The possible return codes are 0, 1 (= PDF/A check failed) and 99
+            // (= abort).
+            match r {
+                99 => Err(EngineAbortedError::new_abort_indicator().into()),
+                1 => Err(anyhow!("the output does not conform to PDF/A-2b")),
+                _ => Ok(()),
             }
         })
     }
 }
 
+/// The longest password that xdvipdfmx accepts, in bytes.
+const MAX_PASSWORD_LEN: usize = 127;
+
+/// Encode text as a PDF text string: ASCII text as-is, and anything else as
+/// UTF-16BE with a byte order mark.
+fn pdf_text_string(text: &str) -> Vec<u8> {
+    if text.is_ascii() {
+        return text.as_bytes().to_owned();
+    }
+
+    let mut encoded = vec![0xfe, 0xff];
+
+    for unit in text.encode_utf16() {
+        encoded.extend_from_slice(&unit.to_be_bytes());
+    }
+
+    encoded
+}
+
+/// Build a minimal ICC version 2 profile for the sRGB color space, for use
+/// as the output intent of PDF/A documents.
+fn srgb_icc_profile() -> Vec<u8> {
+    fn s15f16(v: f64) -> [u8; 4] {
+        ((v * 65536.0).round() as i32).to_be_bytes()
+    }
+
+    fn xyz(x: f64, y: f64, z: f64) -> Vec<u8> {
+        let mut tag = b"XYZ \0\0\0\0".to_vec();
+        tag.extend_from_slice(&s15f16(x));
+        tag.extend_from_slice(&s15f16(y));
+        tag.extend_from_slice(&s15f16(z));
+        tag
+    }
+
+    const DESCRIPTION: &[u8] = b"sRGB IEC61966-2.1\0";
+
+    let mut desc = b"desc\0\0\0\0".to_vec();
+    desc.extend_from_slice(&(DESCRIPTION.len() as u32).to_be_bytes());
+    desc.extend_from_slice(DESCRIPTION);
+    // Empty Unicode and ScriptCode descriptions.
+    desc.extend_from_slice(&[0; 4 + 4 + 2 + 1 + 67]);
+
+    let mut cprt = b"text\0\0\0\0".to_vec();
+    cprt.extend_from_slice(b"No copyright, use freely\0");
+
+    // The sRGB transfer function, sampled.
+    const TRC_POINTS: u32 = 1024;
+    let mut trc = b"curv\0\0\0\0".to_vec();
+    trc.extend_from_slice(&TRC_POINTS.to_be_bytes());
+
+    for i in 0..TRC_POINTS {
+        let v = f64::from(i) / f64::from(TRC_POINTS - 1);
+        let linear = if v <= 0.04045 {
+            v / 12.92
+        } else {
+            ((v + 0.055) / 1.055).powf(2.4)
+        };
+        trc.extend_from_slice(&((linear * 65535.0).round() as u16).to_be_bytes());
+    }
+
+    // Colorants are the sRGB primaries adapted to the D50 connection space.
+    // The three TRC tags share one curve.
+    let tags: [(&[u8; 4], Vec<u8>); 9] = [
+        (b"desc", desc),
+        (b"cprt", cprt),
+        (b"wtpt", xyz(0.9505, 1.0, 1.0891)),
+        (b"rXYZ", xyz(0.4361, 0.2225, 0.0139)),
+        (b"gXYZ", xyz(0.3851, 0.7169, 0.0971)),
+        (b"bXYZ", xyz(0.1431, 0.0606, 0.7141)),
+        (b"rTRC", trc),
+        (b"gTRC", Vec::new()),
+        (b"bTRC", Vec::new()),
+    ];
+
+    let header_len = 128 + 4 + 12 * tags.len();
+    let mut table = Vec::new();
+    let mut data = Vec::new();
+    let mut trc_entry = (0, 0);
+
+    for (sig, tag) in &tags {
+        let entry = if tag.is_empty() {
+            trc_entry
+        } else {
+            let entry = ((header_len + data.len()) as u32, tag.len() as u32);
+            data.extend_from_slice(tag);
+            data.resize(data.len().next_multiple_of(4), 0);
+            if *sig == b"rTRC" {
+                trc_entry = entry;
+            }
+            entry
+        };
+
+        table.extend_from_slice(*sig);
+        table.extend_from_slice(&entry.0.to_be_bytes());
+        table.extend_from_slice(&entry.1.to_be_bytes());
+    }
+
+    let mut profile = Vec::with_capacity(header_len + data.len());
+    profile.extend_from_slice(&((header_len + data.len()) as u32).to_be_bytes());
+    profile.extend_from_slice(&[0; 4]); // preferred CMM
+    profile.extend_from_slice(&[2, 0x10, 0, 0]); // version 2.1
+    profile.extend_from_slice(b"mntrRGB XYZ ");
+    profile.extend_from_slice(&[0x07, 0xe5, 0, 1, 0, 1, 0, 0, 0, 0, 0, 0]); // 2021-01-01
+    profile.extend_from_slice(b"acsp");
+    profile.extend_from_slice(&[0; 24]); // platform, flags, device, attributes
+    profile.extend_from_slice(&[0; 4]); // perceptual rendering intent
+    profile.extend_from_slice(&s15f16(0.9642));
+    profile.extend_from_slice(&s15f16(1.0));
+    profile.extend_from_slice(&s15f16(0.8249));
+    profile.extend_from_slice(&[0; 48]); // creator, profile ID, reserved
+    profile.extend_from_slice(&(tags.len() as u32).to_be_bytes());
+    profile.extend_from_slice(&table);
+    profile.extend_from_slice(&data);
+    profile
+}
+
 #[doc(hidden)]
 pub mod c_api {
     // If you change the interfaces here, rerun cbindgen as described in the README!
 
     use tectonic_bridge_core::CoreBridgeState;
 
+    #[derive(Debug)]
+    #[repr(C)]
+    pub struct XdvipdfmxDocInfoEntry {
+        pub key: *const libc::c_char,
+        pub value: *const libc::c_uchar,
+        pub value_len: libc::size_t,
+    }
+
+    #[derive(Debug)]
+    #[repr(C)]
+    pub struct XdvipdfmxAttachment {
+        pub name: *const libc::c_uchar,
+        pub name_len: libc::size_t,
+        pub data: *const libc::c_uchar,
+        pub data_len: libc::size_t,
+    }
+
     #[derive(Debug)]
     #[repr(C)]
     pub struct XdvipdfmxConfig {
         pub paperspec: *const libc::c_char,
         pub enable_compression: libc::c_uchar,
+        pub compression_level: libc::c_uchar,
+        pub enable_object_streams: libc::c_uchar,
+        pub pdf_version_major: libc::c_uchar,
+        pub pdf_version_minor: libc::c_uchar,
+        pub image_max_dpi: u32,
+        pub jpeg_quality: libc::c_uchar,
+        pub grayscale_images: libc::c_uchar,
+        pub outline_open_depth: libc::c_uchar,
         pub deterministic_tags: libc::c_uchar,
         pub build_date: u64,
+        pub docinfo: *const XdvipdfmxDocInfoEntry,
+        pub docinfo_len: libc::size_t,
+        pub language: *const libc::c_char,
+        pub pdfa: libc::c_uchar,
+        pub icc_profile: *const libc::c_uchar,
+        pub icc_profile_len: libc::size_t,
+        pub encrypt: libc::c_uchar,
+        pub encryption_key_bits: u16,
+        pub encryption_permission: i32,
+        pub owner_password: *const libc::c_char,
+        pub user_password: *const libc::c_char,
+        pub attachments: *const XdvipdfmxAttachment,
+        pub attachments_len: libc::size_t,
     }
 
     #[allow(improper_ctypes)] // for CoreBridgeState
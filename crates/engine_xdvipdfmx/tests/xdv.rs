@@ -0,0 +1,106 @@
+// Copyright 2025 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Run synthesized XDV files through the engine.
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    io::{Cursor, Result as IoResult, Write},
+    rc::Rc,
+};
+use tectonic_bridge_core::{CoreBridgeLauncher, MinimalDriver};
+use tectonic_engine_xdvipdfmx::XdvipdfmxEngine;
+use tectonic_io_base::{InputHandle, InputOrigin, IoProvider, OpenResult, OutputHandle};
+use tectonic_status_base::{NoopStatusBackend, StatusBackend};
+use tectonic_xdv::{FileType, XdvWriter};
+
+type Files = Rc<RefCell<HashMap<String, Vec<u8>>>>;
+
+/// An I/O provider that keeps all files in memory.
+struct MemoryFiles(Files);
+
+struct MemoryOutput(Files, String);
+
+impl Write for MemoryOutput {
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        self.0
+            .borrow_mut()
+            .entry(self.1.clone())
+            .or_default()
+            .extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> IoResult<()> {
+        Ok(())
+    }
+}
+
+impl IoProvider for MemoryFiles {
+    fn output_open_name(&mut self, name: &str) -> OpenResult<OutputHandle> {
+        self.0.borrow_mut().insert(name.to_owned(), Vec::new());
+        OpenResult::Ok(OutputHandle::new(
+            name,
+            MemoryOutput(self.0.clone(), name.to_owned()),
+        ))
+    }
+
+    fn output_open_stdout(&mut self) -> OpenResult<OutputHandle> {
+        self.output_open_name("")
+    }
+
+    fn input_open_name(
+        &mut self,
+        name: &str,
+        _status: &mut dyn StatusBackend,
+    ) -> OpenResult<InputHandle> {
+        match self.0.borrow().get(name) {
+            Some(data) => OpenResult::Ok(InputHandle::new(
+                name,
+                Cursor::new(data.clone()),
+                InputOrigin::Other,
+            )),
+            None => OpenResult::NotAvailable,
+        }
+    }
+}
+
+#[test]
+fn synthesized_document() {
+    let mut w = XdvWriter::new(Vec::new(), FileType::Xdv, b"tectonic").unwrap();
+
+    for page in 1..=2 {
+        w.begin_page(&[page, 0, 0, 0, 0, 0, 0, 0, 0, 0]).unwrap();
+        w.down(3_000_000).unwrap();
+        w.right(2_000_000).unwrap();
+        w.special(format!("pdf:docinfo << /Title (Page {page}) >>").as_bytes())
+            .unwrap();
+        w.push().unwrap();
+        w.special(b"pdf:code 0.5 g").unwrap();
+        w.put_rule(65536 * 20, 65536 * 40).unwrap();
+        w.pop().unwrap();
+        w.end_page().unwrap();
+    }
+
+    let files = Files::default();
+    files
+        .borrow_mut()
+        .insert("doc.xdv".to_owned(), w.finish().unwrap());
+
+    let mut hooks = MinimalDriver::new(MemoryFiles(files.clone()));
+    let mut status = NoopStatusBackend::default();
+    let mut launcher = CoreBridgeLauncher::new(&mut hooks, &mut status);
+    XdvipdfmxEngine::default()
+        .enable_compression(false)
+        .enable_object_streams(false)
+        .process(&mut launcher, "doc.xdv", "doc.pdf")
+        .unwrap();
+
+    let files = files.borrow();
+    let pdf = String::from_utf8_lossy(&files["doc.pdf"]);
+    assert!(pdf.contains("/Type/Pages/Count 2"));
+    assert!(pdf.contains("/Title(Page 2)"));
+    assert!(pdf.contains("0.5 g q"));
+    assert_eq!(pdf.matches(" re f").count(), 2);
+}
@@ -198,12 +198,16 @@ do_dvi_pages (void)
 {
   int      page_no, step;
   unsigned int page_count, i;
+  int      npages_expected;
   double   page_width, page_height;
   double   init_paper_width, init_paper_height;
   pdf_rect mediabox;
 
   spc_exec_at_begin_document();
 
+  /* We only know how many pages we'll process if we process all of them. */
+  npages_expected = num_page_ranges == 0 ? (int) dvi_npages() : 0;
+
   if (num_page_ranges == 0) {
     if (!page_ranges) {
       page_ranges = NEW(1, struct page_range);
@@ -237,6 +241,7 @@ do_dvi_pages (void)
         int    lm;
 
         dpx_message("[%d", page_no+1);
+        ttstub_report_page(page_count + 1, npages_expected);
         /* Users want to change page size even after page is started! */
         page_width = paper_width; page_height = paper_height;
         w = page_width; h = page_height; lm = landscape_mode;
@@ -278,6 +283,7 @@ do_dvi_pages (void)
         dvi_do_page(page_height, x_offset, y_offset);
         page_count++;
         dpx_message("]");
+        ttstub_check_resource_limits();
       }
 
       if (step > 0 &&
@@ -327,21 +333,39 @@ dvipdfmx_main (
   int opt_flags,
   bool translate,
   bool compress,
+  int compression_level,
+  int pdf_version_major,
+  int pdf_version_minor,
+  unsigned int image_max_dpi,
+  int jpeg_quality,
+  bool grayscale_images,
+  int outline_open_depth,
   bool deterministic_tags,
   bool quiet,
   unsigned int verbose,
   time_t build_date,
-  const char *paperspec)
+  const char *paperspec,
+  const XdvipdfmxDocInfoEntry *docinfo,
+  size_t docinfo_len,
+  const char *language,
+  bool pdfa,
+  const unsigned char *icc_profile,
+  size_t icc_profile_len,
+  bool encrypt,
+  int encryption_key_bits,
+  int32_t encryption_permission,
+  const char *owner_password,
+  const char *user_password,
+  const XdvipdfmxAttachment *attachments,
+  size_t attachments_len)
 {
   double dvi2pts;
   const char *creator = NULL;
   char oplain[128] = "", uplain[128] = "";
   int has_id = 0;
+  int pdfa_errors = 0;
   unsigned char id1[16], id2[16];
   struct pdf_setting settings;
-  int pdf_version_major = 1;
-  int pdf_version_minor = 5;
-  int compression_level = 9;
   double annot_grow_x = 0;
   double annot_grow_y = 0;
 
@@ -369,6 +393,7 @@ dvipdfmx_main (
   }
 
   pdf_font_set_deterministic_unique_tags(deterministic_tags ? 1 : 0);
+  pdf_ximage_set_raster_options(image_max_dpi, jpeg_quality, grayscale_images ? 1 : 0);
 
   pdf_init_fontmaps(); /* This must come before parsing options... */
 
@@ -378,9 +403,16 @@ dvipdfmx_main (
 
   select_paper(paperspec);
 
-  bookmark_open = 0;
-  key_bits = 40;
-  permission = 0x003C;
+  bookmark_open = outline_open_depth;
+  /* Tectonic: the encryption settings are defaults that a `pdf:encrypt`
+   * special in the document replaces. */
+  do_encryption = encrypt ? 1 : 0;
+  key_bits = encrypt ? encryption_key_bits : 40;
+  permission = encrypt ? encryption_permission : 0x003C;
+  if (encrypt) {
+    strncpy(oplain, owner_password, MAX_PWD_LEN);
+    strncpy(uplain, user_password, MAX_PWD_LEN);
+  }
   font_dpi = 600;
   pdfdecimaldigits = 5;
   image_cache_life = -2;
@@ -410,6 +442,12 @@ dvipdfmx_main (
                       &has_id, id1, id2, &opt_flags);
   }
 
+  /* Tectonic: PDF/A forbids encryption, so it overrides `pdf:encrypt`. */
+  if (pdfa && do_encryption) {
+    dpx_warning("PDF/A output may not be encrypted; ignoring the encryption request.");
+    do_encryption = 0;
+  }
+
   /*kpse_init_prog("", font_dpi, NULL, NULL);
     kpse_set_program_enabled(kpse_pk_format, true, kpse_src_texmf_cnf);*/
   pdf_font_set_dpi(font_dpi);
@@ -462,6 +500,9 @@ dvipdfmx_main (
   settings.outline_open_depth = bookmark_open;
   settings.check_gotos        = !(opt_flags & OPT_PDFDOC_NO_DEST_REMOVE);
   settings.enable_manual_thumb = enable_thumbnail;
+  settings.enable_pdfa        = pdfa;
+  settings.pdfa_icc_profile   = icc_profile;
+  settings.pdfa_icc_profile_len = icc_profile_len;
 
   settings.device.dvi2pts     = dvi2pts;
   settings.device.precision   = pdfdecimaldigits;
@@ -482,6 +523,37 @@ dvipdfmx_main (
 
   do_dvi_pages();
 
+  /* Tectonic: metadata provided by the caller. This only fills in entries that
+   * the document didn't set itself with `pdf:docinfo` specials. Packages like
+   * hyperref emit empty strings for unset entries, so those are replaced too.
+   */
+  for (size_t i = 0; i < docinfo_len; i++) {
+    pdf_obj *info = pdf_doc_docinfo();
+    pdf_obj *value = pdf_lookup_dict(info, docinfo[i].key);
+
+    if (!value || (PDF_OBJ_STRINGTYPE(value) && pdf_string_length(value) == 0)) {
+      pdf_add_dict(info,
+                   pdf_new_name(docinfo[i].key),
+                   pdf_new_string(docinfo[i].value, docinfo[i].value_len));
+    }
+  }
+
+  if (language && !pdf_lookup_dict(pdf_doc_catalog(), "Lang")) {
+    pdf_add_dict(pdf_doc_catalog(),
+                 pdf_new_name("Lang"),
+                 pdf_new_string(language, strlen(language)));
+  }
+
+  /* Tectonic: files attached by the caller. */
+  for (size_t i = 0; i < attachments_len; i++) {
+    if (pdf_doc_add_attachment(attachments[i].name, attachments[i].name_len,
+                               attachments[i].data, attachments[i].data_len) < 0)
+      dpx_warning("Failed to attach a file; the document already has an attachment with its name.");
+  }
+
+  if (pdfa)
+    pdfa_errors = pdf_font_check_embedding();
+
   pdf_close_document();
 
   pdf_close_fontmaps(); /* pdf_font may depend on fontmap. */
@@ -491,7 +563,7 @@ dvipdfmx_main (
   dpx_message("\n");
   free(page_ranges);
 
-  return 0;
+  return pdfa_errors ? 1 : 0;
 }
 
 int
@@ -515,14 +587,34 @@ tt_engine_xdvipdfmx_main(
     pdfname,
     dviname,
     NULL, /* pagespec */
-    0, /* opt_flags */
+    config->enable_object_streams ? 0 : OPT_PDFOBJ_NO_OBJSTM, /* opt_flags */
     false, /* translate */
     (bool) config->enable_compression,
+    config->compression_level,
+    config->pdf_version_major,
+    config->pdf_version_minor,
+    config->image_max_dpi,
+    config->jpeg_quality,
+    (bool) config->grayscale_images,
+    config->outline_open_depth,
     (bool) config->deterministic_tags,
     false, /* quiet */
     0, /* verbose */
     (time_t) config->build_date,
-    config->paperspec
+    config->paperspec,
+    config->docinfo,
+    config->docinfo_len,
+    config->language,
+    (bool) config->pdfa,
+    config->icc_profile,
+    config->icc_profile_len,
+    (bool) config->encrypt,
+    config->encryption_key_bits,
+    config->encryption_permission,
+    config->owner_password,
+    config->user_password,
+    config->attachments,
+    config->attachments_len
   );
 
   ttbc_global_engine_exit();
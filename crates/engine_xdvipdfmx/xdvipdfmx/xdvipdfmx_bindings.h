@@ -6,11 +6,45 @@
 #include <stdint.h>
 #include <stdlib.h>
 
+typedef struct {
+  const char *key;
+  const unsigned char *value;
+  size_t value_len;
+} XdvipdfmxDocInfoEntry;
+
+typedef struct {
+  const unsigned char *name;
+  size_t name_len;
+  const unsigned char *data;
+  size_t data_len;
+} XdvipdfmxAttachment;
+
 typedef struct {
   const char *paperspec;
   unsigned char enable_compression;
+  unsigned char compression_level;
+  unsigned char enable_object_streams;
+  unsigned char pdf_version_major;
+  unsigned char pdf_version_minor;
+  uint32_t image_max_dpi;
+  unsigned char jpeg_quality;
+  unsigned char grayscale_images;
+  unsigned char outline_open_depth;
   unsigned char deterministic_tags;
   uint64_t build_date;
+  const XdvipdfmxDocInfoEntry *docinfo;
+  size_t docinfo_len;
+  const char *language;
+  unsigned char pdfa;
+  const unsigned char *icc_profile;
+  size_t icc_profile_len;
+  unsigned char encrypt;
+  uint16_t encryption_key_bits;
+  int32_t encryption_permission;
+  const char *owner_password;
+  const char *user_password;
+  const XdvipdfmxAttachment *attachments;
+  size_t attachments_len;
 } XdvipdfmxConfig;
 
 #ifdef __cplusplus
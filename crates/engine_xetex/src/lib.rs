@@ -18,7 +18,13 @@
 // TODO: the internal interface we're using here is pretty janky. The bibtex
 // engine has a nicer approach that we should probably start using.
 
-use std::{ffi::CString, time::SystemTime};
+use std::{
+    collections::HashMap,
+    ffi::{CStr, CString},
+    fmt, ptr,
+    str::FromStr,
+    time::SystemTime,
+};
 use tectonic_bridge_core::{CoreBridgeLauncher, EngineAbortedError};
 use tectonic_errors::prelude::*;
 
@@ -35,6 +41,13 @@ use tectonic_errors::prelude::*;
 // into the C/C++ code as a #define.
 pub const FORMAT_SERIAL: u32 = 33;
 
+/// The version of XeTeX that this engine is derived from, as reported by the
+/// `\XeTeXversion` and `\XeTeXrevision` primitives.
+//
+// DEVELOPER NOTE: this is duplicated in the C code, in `xetex-xetex0.c` and
+// `xetex-constants.h`. Keep them in sync.
+pub const XETEX_VERSION: &str = "0.999994";
+
 /// A possible outcome from a (Xe)TeX engine invocation.
 ///
 /// The classic TeX implementation provides a fourth outcome: "fatal error". In
@@ -61,6 +74,182 @@ pub enum TexOutcome {
     Errors,
 }
 
+/// The interaction mode in which the TeX engine starts, as set by the
+/// `\batchmode`, `\nonstopmode`, `\scrollmode`, and `\errorstopmode`
+/// primitives.
+///
+/// Tectonic never asks the user for input, so the difference between the modes
+/// is that the engine halts at the first error in [`InteractionMode::ErrorStop`]
+/// mode, and keeps going in the others. In [`InteractionMode::Batch`] mode,
+/// nothing is printed to the terminal, only to the log. A document can still
+/// change the mode with the primitives, but that doesn’t affect whether the
+/// engine halts on errors.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum InteractionMode {
+    /// Keep going after errors, and print nothing to the terminal.
+    Batch,
+
+    /// Keep going after errors. Since the engine never asks for input, this
+    /// is the same as [`InteractionMode::Scroll`].
+    Nonstop,
+
+    /// Keep going after errors, printing them to the terminal.
+    Scroll,
+
+    /// Halt at the first error.
+    ErrorStop,
+}
+
+impl InteractionMode {
+    /// The value of TeX’s `\interactionmode` for this mode.
+    fn tex_value(self) -> libc::c_int {
+        match self {
+            InteractionMode::Batch => 0,
+            InteractionMode::Nonstop => 1,
+            InteractionMode::Scroll => 2,
+            InteractionMode::ErrorStop => 3,
+        }
+    }
+}
+
+impl FromStr for InteractionMode {
+    type Err = &'static str;
+
+    fn from_str(a_str: &str) -> std::result::Result<Self, Self::Err> {
+        match a_str {
+            "batchmode" => Ok(InteractionMode::Batch),
+            "nonstopmode" => Ok(InteractionMode::Nonstop),
+            "scrollmode" => Ok(InteractionMode::Scroll),
+            "errorstopmode" => Ok(InteractionMode::ErrorStop),
+            _ => Err("unsupported or unknown interaction mode"),
+        }
+    }
+}
+
+impl fmt::Display for InteractionMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            InteractionMode::Batch => "batchmode",
+            InteractionMode::Nonstop => "nonstopmode",
+            InteractionMode::Scroll => "scrollmode",
+            InteractionMode::ErrorStop => "errorstopmode",
+        })
+    }
+}
+
+/// A size parameter of one of the TeX engine’s internal tables, which limit how
+/// large a document it can process. If a document overflows one of them, TeX
+/// fails with a “TeX capacity exceeded” error naming it.
+///
+/// The parameters are named as in the `texmf.cnf` files of other TeX
+/// distributions, and can be raised with [`TexEngine::memory_parameter`]. The
+/// size of TeX’s main memory (`main_memory`) is fixed by the layout of the
+/// format files, so it can’t be configured.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum MemoryParameter {
+    /// The number of characters in the string pool (“pool size”).
+    PoolSize,
+
+    /// The number of strings (“number of strings”).
+    MaxStrings,
+
+    /// The number of extra control sequences that can be defined (“hash
+    /// size”).
+    HashExtra,
+
+    /// The number of words of font metric data (“font memory”).
+    FontMemSize,
+
+    /// The number of characters in the input line buffer (“buffer size”).
+    BufSize,
+
+    /// The depth of the input stack, as in nested macros (“input stack
+    /// size”).
+    StackSize,
+
+    /// The number of values saved by groups (“save size”).
+    SaveSize,
+
+    /// The depth of nested lists being built (“semantic nest size”).
+    NestSize,
+
+    /// The number of macro parameters in use at once (“parameter stack
+    /// size”).
+    ParamSize,
+
+    /// The depth of recursive expansion (“expansion depth”).
+    ExpandDepth,
+}
+
+impl MemoryParameter {
+    const ALL: [MemoryParameter; 10] = [
+        MemoryParameter::PoolSize,
+        MemoryParameter::MaxStrings,
+        MemoryParameter::HashExtra,
+        MemoryParameter::FontMemSize,
+        MemoryParameter::BufSize,
+        MemoryParameter::StackSize,
+        MemoryParameter::SaveSize,
+        MemoryParameter::NestSize,
+        MemoryParameter::ParamSize,
+        MemoryParameter::ExpandDepth,
+    ];
+
+    /// The name of the parameter, which is also the name of the variable that
+    /// holds it in the C code.
+    fn c_name(self) -> &'static CStr {
+        match self {
+            MemoryParameter::PoolSize => c"pool_size",
+            MemoryParameter::MaxStrings => c"max_strings",
+            MemoryParameter::HashExtra => c"hash_extra",
+            MemoryParameter::FontMemSize => c"font_mem_size",
+            MemoryParameter::BufSize => c"buf_size",
+            MemoryParameter::StackSize => c"stack_size",
+            MemoryParameter::SaveSize => c"save_size",
+            MemoryParameter::NestSize => c"nest_size",
+            MemoryParameter::ParamSize => c"param_size",
+            MemoryParameter::ExpandDepth => c"expand_depth",
+        }
+    }
+
+    /// The value used unless another is configured, which is also the smallest
+    /// allowed.
+    pub fn default_value(self) -> u32 {
+        match self {
+            MemoryParameter::PoolSize => 6_250_000,
+            MemoryParameter::MaxStrings => 565_536,
+            MemoryParameter::HashExtra => 600_000,
+            MemoryParameter::FontMemSize => 8_000_000,
+            MemoryParameter::BufSize => 200_000,
+            MemoryParameter::StackSize => 5_000,
+            MemoryParameter::SaveSize => 80_000,
+            MemoryParameter::NestSize => 500,
+            MemoryParameter::ParamSize => 10_000,
+            MemoryParameter::ExpandDepth => 10_000,
+        }
+    }
+
+    /// The largest value that the engine supports.
+    pub fn max_value(self) -> u32 {
+        // This is the engine's `MAX_HALFWORD`.
+        const MAX_HALFWORD: u32 = 0x3FFF_FFFF;
+
+        match self {
+            MemoryParameter::PoolSize => 40_000_000,
+            MemoryParameter::MaxStrings | MemoryParameter::HashExtra => 2_097_151,
+            MemoryParameter::FontMemSize => 147_483_647,
+            MemoryParameter::ExpandDepth => 10_000_000,
+            _ => MAX_HALFWORD,
+        }
+    }
+}
+
+impl fmt::Display for MemoryParameter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.c_name().to_string_lossy())
+    }
+}
+
 /// A struct for invoking the (Xe)TeX engine.
 ///
 /// This struct has a fairly straightforward "builder" interface: you create it,
@@ -78,22 +267,38 @@ pub struct TexEngine {
     // One day, the engine will hold its own state. For the time being,
     // though, it's just a proxy for the global constants in the C code.
     halt_on_error: bool,
+    interaction: InteractionMode,
     initex_mode: bool,
+    initex_loads_format: bool,
     synctex_enabled: bool,
     semantic_pagination_enabled: bool,
+    actual_text_enabled: bool,
     shell_escape_enabled: bool,
+    system_fonts_enabled: bool,
     build_date: SystemTime,
+    clock_pinned: bool,
+    job_name: Option<String>,
+    font_features: Vec<String>,
+    memory_parameters: HashMap<MemoryParameter, u32>,
 }
 
 impl Default for TexEngine {
     fn default() -> Self {
         TexEngine {
             halt_on_error: true,
+            interaction: InteractionMode::ErrorStop,
             initex_mode: false,
+            initex_loads_format: false,
             synctex_enabled: false,
             semantic_pagination_enabled: false,
+            actual_text_enabled: false,
             shell_escape_enabled: false,
+            system_fonts_enabled: true,
             build_date: SystemTime::UNIX_EPOCH,
+            clock_pinned: false,
+            job_name: None,
+            font_features: Vec::new(),
+            memory_parameters: HashMap::new(),
         }
     }
 }
@@ -109,6 +314,18 @@ impl TexEngine {
         self
     }
 
+    /// Configure the interaction mode in which the engine starts.
+    ///
+    /// The default is [`InteractionMode::ErrorStop`]. This also configures
+    /// whether the engine will halt on errors, overriding any earlier call to
+    /// [`halt_on_error_mode()`](Self::halt_on_error_mode): it will only halt in
+    /// the error-stop mode.
+    pub fn interaction_mode(&mut self, mode: InteractionMode) -> &mut Self {
+        self.interaction = mode;
+        self.halt_on_error = mode == InteractionMode::ErrorStop;
+        self
+    }
+
     /// Configure the engine to run in "initex" mode, in which it generates a
     /// "format" file that serializes the engine state rather than a PDF
     /// document. The default is false.
@@ -117,6 +334,17 @@ impl TexEngine {
         self
     }
 
+    /// Configure the engine to load the format file in "initex" mode, so that
+    /// the format that it generates extends that one rather than starting
+    /// from scratch. This is what `xetex -ini "&latex"` does in other TeX
+    /// distributions. It has no effect outside of initex mode.
+    ///
+    /// The default is false.
+    pub fn initex_loads_format(&mut self, enabled: bool) -> &mut Self {
+        self.initex_loads_format = enabled;
+        self
+    }
+
     /// Configure the engine to produce SyncTeX data.
     ///
     /// The default is false.
@@ -140,6 +368,19 @@ impl TexEngine {
         self
     }
 
+    /// Configure the engine to record the Unicode text of each word of
+    /// native-font output, as if `\XeTeXgenerateactualtext` were set to 1.
+    ///
+    /// This makes it possible to extract the text of a document from its XDV
+    /// output. Semantic pagination implies this setting. The document may
+    /// still change the parameter itself.
+    ///
+    /// The default is false.
+    pub fn actual_text(&mut self, enabled: bool) -> &mut Self {
+        self.actual_text_enabled = enabled;
+        self
+    }
+
     /// Configure whether the "shell escape" TeX feature is enabled.
     ///
     /// The default is false.
@@ -148,6 +389,40 @@ impl TexEngine {
         self
     }
 
+    /// Configure whether fonts installed on the host system can be loaded by
+    /// name, as with `\font\x="Linux Libertine O"`.
+    ///
+    /// Turning this off makes the output independent of the fonts that happen
+    /// to be installed, for reproducibility. Font files can still be loaded by
+    /// file name, as with `\font\x="[LinLibertine_R.otf]"`, from the bundle
+    /// or the other usual places.
+    ///
+    /// The default is true.
+    pub fn system_fonts(&mut self, enabled: bool) -> &mut Self {
+        self.system_fonts_enabled = enabled;
+        self
+    }
+
+    /// Set OpenType features to apply to every font that's loaded by name or
+    /// file name, as if they were given in the font's name, as with
+    /// `\font\x="[LinLibertine_R.otf]:+smcp"`.
+    ///
+    /// Each item is one feature setting in the syntax of XeTeX font names,
+    /// such as `+onum`, `-liga`, `script=latn`, or `language=DEU`. The
+    /// features that the document gives for a font take precedence over
+    /// these. They don't apply to TFM fonts, or to fonts that are rendered
+    /// with AAT on macOS.
+    ///
+    /// The default is to apply no features.
+    pub fn font_features<I, S>(&mut self, features: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.font_features = features.into_iter().map(Into::into).collect();
+        self
+    }
+
     /// Sets the date and time used by the TeX engine. This affects things like
     /// LaTeX's \today command.
     ///
@@ -159,6 +434,39 @@ impl TexEngine {
         self
     }
 
+    /// Pin the engine’s clock to *date*, so that the output is the same from one
+    /// run to the next.
+    ///
+    /// This sets the date like [`build_date()`](Self::build_date), except that
+    /// `\year`, `\month`, `\day`, and `\time` are computed in UTC rather than
+    /// in the local time zone. The primitives that read the wall clock see the
+    /// same time: `\elapsedtime` is always zero, and the initial
+    /// `\randomseed` is derived from the date.
+    pub fn pinned_build_date(&mut self, date: SystemTime) -> &mut Self {
+        self.build_date = date;
+        self.clock_pinned = true;
+        self
+    }
+
+    /// Set the job name, which is the value of `\jobname` and the base name of
+    /// the log and output files.
+    ///
+    /// By default, the job name is the name of the input file without its
+    /// extension.
+    pub fn job_name(&mut self, name: &str) -> &mut Self {
+        self.job_name = Some(name.to_owned());
+        self
+    }
+
+    /// Set the size of one of the engine’s internal tables, so that it can
+    /// process larger documents. Values below the default, or above the
+    /// largest that the engine supports, cause [`process()`](Self::process) to
+    /// fail.
+    pub fn memory_parameter(&mut self, param: MemoryParameter, value: u32) -> &mut Self {
+        self.memory_parameters.insert(param, value);
+        self
+    }
+
     /// Process a document using the current engine configuration.
     ///
     /// The *launcher* parameter gives overarching environmental context in
@@ -168,7 +476,8 @@ impl TexEngine {
     /// preloaded engine state. It must be findable in the I/O stack, using the
     /// special hooks that are provided for handing format files, which allow
     /// updates to the file format to be handed (see [`FORMAT_SERIAL`]). If in
-    /// "initex" mode, this parameter will be ignored.
+    /// "initex" mode, this parameter will be ignored, unless
+    /// [`initex_loads_format()`](Self::initex_loads_format) is set.
     ///
     /// The *input_file_name* is used to name the "primary input file". The I/O
     /// system has special hooks for opening this primary input, so be aware
@@ -184,8 +493,33 @@ impl TexEngine {
     ) -> Result<TexOutcome> {
         let cformat = CString::new(format_file_name)?;
         let cinput = CString::new(input_file_name)?;
+        let cjob = self.job_name.as_deref().map(CString::new).transpose()?;
+
+        if let Some(f) = self
+            .font_features
+            .iter()
+            .find(|f| f.is_empty() || f.contains([':', ';', ',']))
+        {
+            bail!("invalid font feature setting `{}`", f);
+        }
+
+        let cfeatures = CString::new(self.font_features.join(";"))?;
+
+        for (&param, &value) in &self.memory_parameters {
+            if value < param.default_value() || value > param.max_value() {
+                bail!(
+                    "the TeX memory parameter `{}` must be between {} and {}, not {}",
+                    param,
+                    param.default_value(),
+                    param.max_value(),
+                    value
+                );
+            }
+        }
 
         launcher.with_global_lock(|state| {
+            state.begin_progress("running TeX");
+
             // Note that we have to do all of this setup while holding the
             // lock, because we're modifying static state variables.
 
@@ -197,12 +531,44 @@ impl TexEngine {
                     self.shell_escape_enabled.into(),
                 );
                 tt_xetex_set_int_variable(c"halt_on_error_p".as_ptr(), self.halt_on_error.into());
+                tt_xetex_set_int_variable(
+                    c"initial_interaction".as_ptr(),
+                    self.interaction.tex_value(),
+                );
                 tt_xetex_set_int_variable(c"in_initex_mode".as_ptr(), self.initex_mode.into());
+                tt_xetex_set_int_variable(
+                    c"initex_loads_format".as_ptr(),
+                    self.initex_loads_format.into(),
+                );
                 tt_xetex_set_int_variable(c"synctex_enabled".as_ptr(), self.synctex_enabled.into());
+                tt_xetex_set_int_variable(c"clock_pinned".as_ptr(), self.clock_pinned.into());
+                tt_xetex_set_int_variable(
+                    c"system_fonts_enabled".as_ptr(),
+                    self.system_fonts_enabled.into(),
+                );
                 tt_xetex_set_int_variable(
                     c"semantic_pagination_enabled".as_ptr(),
                     self.semantic_pagination_enabled.into(),
                 );
+                tt_xetex_set_int_variable(
+                    c"actual_text_enabled".as_ptr(),
+                    self.actual_text_enabled.into(),
+                );
+
+                tt_xetex_set_string_variable(
+                    c"job_name_override".as_ptr(),
+                    cjob.as_ref().map_or(ptr::null(), |s| s.as_ptr()),
+                );
+                tt_xetex_set_string_variable(c"default_font_features".as_ptr(), cfeatures.as_ptr());
+
+                for param in MemoryParameter::ALL {
+                    let value = self
+                        .memory_parameters
+                        .get(&param)
+                        .copied()
+                        .unwrap_or_else(|| param.default_value());
+                    tt_xetex_set_int_variable(param.c_name().as_ptr(), value as libc::c_int);
+                }
 
                 tt_engine_xetex_main(
                     state,
@@ -239,6 +605,11 @@ pub mod c_api {
             value: libc::c_int,
         ) -> libc::c_int;
 
+        pub fn tt_xetex_set_string_variable(
+            var_name: *const libc::c_char,
+            value: *const libc::c_char,
+        ) -> libc::c_int;
+
         pub fn tt_engine_xetex_main(
             api: &mut CoreBridgeState,
             dump_name: *const libc::c_char,
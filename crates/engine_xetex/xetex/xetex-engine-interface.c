@@ -26,14 +26,44 @@ tt_xetex_set_int_variable (const char *var_name, int value)
 {
     if (streq_ptr(var_name, "halt_on_error_p"))
         halt_on_error_p = value;
+    else if (streq_ptr(var_name, "initial_interaction"))
+        initial_interaction = value;
     else if (streq_ptr(var_name, "in_initex_mode"))
         in_initex_mode = (value != 0);
+    else if (streq_ptr(var_name, "initex_loads_format"))
+        initex_loads_format = (value != 0);
     else if (streq_ptr(var_name, "synctex_enabled"))
         synctex_enabled = (value != 0);
     else if (streq_ptr(var_name, "semantic_pagination_enabled"))
         semantic_pagination_enabled = (value != 0);
+    else if (streq_ptr(var_name, "actual_text_enabled"))
+        actual_text_enabled = (value != 0);
     else if (streq_ptr(var_name, "shell_escape_enabled"))
         shell_escape_enabled = (value != 0);
+    else if (streq_ptr(var_name, "clock_pinned"))
+        clock_pinned = (value != 0);
+    else if (streq_ptr(var_name, "system_fonts_enabled"))
+        system_fonts_enabled = (value != 0);
+    else if (streq_ptr(var_name, "pool_size"))
+        pool_size = value;
+    else if (streq_ptr(var_name, "max_strings"))
+        max_strings = value;
+    else if (streq_ptr(var_name, "hash_extra"))
+        hash_extra = value;
+    else if (streq_ptr(var_name, "font_mem_size"))
+        font_mem_size = value;
+    else if (streq_ptr(var_name, "buf_size"))
+        buf_size = value;
+    else if (streq_ptr(var_name, "stack_size"))
+        stack_size = value;
+    else if (streq_ptr(var_name, "save_size"))
+        save_size = value;
+    else if (streq_ptr(var_name, "nest_size"))
+        nest_size = value;
+    else if (streq_ptr(var_name, "param_size"))
+        param_size = value;
+    else if (streq_ptr(var_name, "expand_depth"))
+        expand_depth = value;
     else
         return 1; /* Uh oh: unrecognized variable */
 
@@ -44,8 +74,17 @@ tt_xetex_set_int_variable (const char *var_name, int value)
 int
 tt_xetex_set_string_variable (const char *var_name, const char *value)
 {
-    /* Currently unused; see Git history for how we used to set output_comment */
-    return 1;
+    /* See Git history for how we used to set output_comment */
+    if (streq_ptr(var_name, "job_name_override")) {
+        free(job_name_override);
+        job_name_override = value ? xstrdup(value) : NULL;
+    } else if (streq_ptr(var_name, "default_font_features")) {
+        free(default_font_features);
+        default_font_features = value ? xstrdup(value) : NULL;
+    } else
+        return 1; /* Uh oh: unrecognized variable */
+
+    return 0; /* success */
 }
 
 int
@@ -486,6 +486,8 @@ loadOTfont(RawPlatformFontRef fontRef, XeTeXFont font, Fixed scaled_size, char*
 
     hb_tag_t tag;
 
+    char* withDefaults = NULL;
+
     uint32_t rgbValue = 0x000000FF;
 
     float extend = 1.0;
@@ -520,6 +522,21 @@ loadOTfont(RawPlatformFontRef fontRef, XeTeXFont font, Fixed scaled_size, char*
             return NULL;
     }
 
+    /* Tectonic: the default features, if any, come first, so that the
+     * font's own features override them. */
+    if (default_font_features != NULL && *default_font_features != 0) {
+        size_t len = strlen(default_font_features) + 1;
+        if (cp1 != NULL)
+            len += strlen(cp1) + 1;
+        withDefaults = xmalloc(len);
+        strcpy(withDefaults, default_font_features);
+        if (cp1 != NULL) {
+            strcat(withDefaults, ";");
+            strcat(withDefaults, cp1);
+        }
+        cp1 = withDefaults;
+    }
+
     /* scan the feature string (if any) */
     if (cp1 != NULL) {
         while (*cp1) {
@@ -548,6 +565,7 @@ loadOTfont(RawPlatformFontRef fontRef, XeTeXFont font, Fixed scaled_size, char*
                 if (*cp3 != '=')
                     goto bad_option;
                 ++cp3;
+                free(language);
                 language = xmalloc(cp2 - cp3 + 1);
                 language[cp2 - cp3] = '\0';
                 memcpy(language, cp3, cp2 - cp3);
@@ -637,6 +655,8 @@ loadOTfont(RawPlatformFontRef fontRef, XeTeXFont font, Fixed scaled_size, char*
         }
     }
 
+    free(withDefaults);
+
     if (shapers != NULL) {
         shapers = (char**) xrealloc(shapers, (nShapers + 1) * sizeof(char *));
         shapers[nShapers] = NULL;
@@ -798,6 +818,16 @@ find_native_font(char* uname, int32_t scaled_size)
                 end_diagnostic(0);
             }
         }
+    } else if (!system_fonts_enabled) {
+        /* Tectonic: fonts installed on the host system can be ruled out, so
+         * that the output doesn't depend on them. Font files can still be
+         * loaded with the "[filename]" form above. */
+        if (get_tracing_fonts_state() > 0) {
+            begin_diagnostic();
+            print_nl(' ');
+            print_c_string("-> system fonts are disabled");
+            end_diagnostic(0);
+        }
     } else {
         fontRef = findFontByName(nameString, varString, Fix2D(scaled_size));
 
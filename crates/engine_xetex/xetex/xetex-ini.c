@@ -29,6 +29,7 @@ int32_t first;
 int32_t last;
 int32_t max_buf_stack;
 bool in_initex_mode;
+bool initex_loads_format;
 int32_t error_line;
 int32_t half_error_line;
 int32_t max_print_line;
@@ -50,6 +51,9 @@ int32_t save_size;
 int32_t expand_depth;
 int file_line_error_style_p;
 int halt_on_error_p;
+int initial_interaction;
+char *job_name_override;
+char *default_font_features;
 bool quoted_filename;
 bool insert_src_special_auto;
 bool insert_src_special_every_par;
@@ -348,6 +352,9 @@ scaled_t delta;
 int synctex_enabled;
 bool used_tectonic_coda_tokens;
 bool semantic_pagination_enabled;
+bool actual_text_enabled;
+bool clock_pinned;
+bool system_fonts_enabled;
 bool gave_char_warning_help;
 
 /* These ought to live in xetex-pagebuilder.c but are shared a lot: */
@@ -3060,7 +3067,7 @@ initialize_more_variables(void)
     native_text_size = 128;
     native_text = xmalloc(native_text_size * sizeof(UTF16_code));
 
-    interaction = ERROR_STOP_MODE;
+    interaction = initial_interaction;
 
     deletions_allowed = true;
     set_box_allowed = true;
@@ -3579,28 +3586,22 @@ tt_run_engine(const char *dump_name, const char *input_file_name, time_t build_d
         file_line_error_style_p = 0;
 
     /* These various parameters were configurable in web2c TeX. We don't
-     * bother to allow that. */
+     * bother to allow that, except for the sizes of the tables that large
+     * documents tend to overflow: `pool_size`, `max_strings`, `hash_extra`,
+     * `font_mem_size`, `buf_size`, `stack_size`, `save_size`, `nest_size`,
+     * `param_size`, and `expand_depth`. The Rust code sets those before every
+     * run with tt_xetex_set_int_variable(). */
 
-    pool_size = 6250000L;
     string_vacancies = 90000L;
     pool_free = 47500L;
-    max_strings = 565536L;
     strings_free = 100;
-    font_mem_size = 8000000L;
     font_max = 9000;
     trie_size = 1000000L;
     hyph_size = 8191;
-    buf_size = 200000L;
-    nest_size = 500;
     max_in_open = 15;
-    param_size = 10000;
-    save_size = 80000L;
-    stack_size = 5000;
     error_line = 79;
     half_error_line = 50;
     max_print_line = 79;
-    hash_extra = 600000L;
-    expand_depth = 10000;
 
     /* Allocate many of our big arrays. */
 
@@ -3701,8 +3702,8 @@ tt_run_engine(const char *dump_name, const char *input_file_name, time_t build_d
     initialize_pagebuilder_variables();
     initialize_shipout_variables();
 
-    get_seconds_and_micros(&epochseconds, &microseconds);
     init_start_time(build_date);
+    get_seconds_and_micros(&epochseconds, &microseconds);
 
     selector = SELECTOR_TERM_ONLY;
     tally = 0;
@@ -3755,7 +3756,10 @@ tt_run_engine(const char *dump_name, const char *input_file_name, time_t build_d
 
     no_new_control_sequence = true;
 
-    if (!in_initex_mode) {
+    /* An initex run may start from an existing format, like `-ini "&latex"`
+     * did in web2c TeX, to dump a format that extends it. */
+
+    if (!in_initex_mode || initex_loads_format) {
         if (!load_fmt_file())
             return history;
     }
@@ -3774,6 +3778,7 @@ tt_run_engine(const char *dump_name, const char *input_file_name, time_t build_d
         INTPAR(year) = 0;
     } else {
         get_date_and_time(build_date,
+                          clock_pinned,
                           &(INTPAR(time)),
                           &(INTPAR(day)),
                           &(INTPAR(month)),
@@ -3864,7 +3869,7 @@ tt_run_engine(const char *dump_name, const char *input_file_name, time_t build_d
     else
         selector = SELECTOR_TERM_ONLY; /*:79*/
 
-    if (semantic_pagination_enabled)
+    if (semantic_pagination_enabled || actual_text_enabled)
         INTPAR(xetex_generate_actual_text) = 1;
 
     pdf_files_init();
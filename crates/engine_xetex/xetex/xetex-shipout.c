@@ -262,6 +262,7 @@ ship_out(int32_t p)
     dvi_out(EOP);
     total_pages++;
     cur_s = -1;
+    ttstub_report_page(total_pages, 0);
 
 done:
     /*1518: "Check for LR anomalies at the end of ship_out" */
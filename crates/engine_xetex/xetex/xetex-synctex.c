@@ -131,8 +131,15 @@ static char *
 get_current_name (void)
 {
     /* Tectonic: this used to make pathnames absolute, but in the virtualized
-    * I/O system that information has to be provided externally. */
-    return xstrdup(abspath_of_input_file);
+    * I/O system that information has to be provided externally. Files that
+    * have no absolute path -- bundle files, in-memory inputs, and everything
+    * in deterministic mode -- are recorded by the names that they were opened
+    * with, rather than with empty names that editors can't do anything
+    * with. */
+    if (abspath_of_input_file[0] != '\0' || name_of_input_file == NULL)
+        return xstrdup(abspath_of_input_file);
+
+    return xstrdup(name_of_input_file);
 }
 
 
@@ -27,6 +27,10 @@ static int last_lineno;
 #define TIME_STR_SIZE 30
 static char start_time_str[TIME_STR_SIZE];
 
+/* Tectonic: the build date, which is what the wall clock reads when it's
+ * pinned with `clock_pinned`. */
+static time_t start_time;
+
 static void
 makepdftime(time_t t, char *time_str, bool utc)
 {
@@ -84,6 +88,7 @@ makepdftime(time_t t, char *time_str, bool utc)
 void
 init_start_time(time_t source_date_epoch)
 {
+  start_time = source_date_epoch;
   makepdftime(source_date_epoch, start_time_str, /* utc= */true);
 }
 
@@ -113,11 +118,11 @@ getcreationdate(void)
 
 
 void
-get_date_and_time (time_t source_date_epoch,
+get_date_and_time (time_t source_date_epoch, bool utc,
                    int32_t *minutes, int32_t *day,
                    int32_t *month, int32_t *year)
 {
-  struct tm *tmptr = localtime (&source_date_epoch);
+  struct tm *tmptr = utc ? gmtime (&source_date_epoch) : localtime (&source_date_epoch);
   *minutes = tmptr->tm_hour * 60 + tmptr->tm_min;
   *day = tmptr->tm_mday;
   *month = tmptr->tm_mon + 1;
@@ -128,6 +133,12 @@ get_date_and_time (time_t source_date_epoch,
 void
 get_seconds_and_micros (int32_t *seconds,  int32_t *micros)
 {
+  if (clock_pinned) {
+    *seconds = start_time;
+    *micros = 0;
+    return;
+  }
+
 #ifdef HAVE_GETTIMEOFDAY
   struct timeval tv;
   gettimeofday(&tv, NULL);
@@ -5152,6 +5152,12 @@ check_outer_validity(void)
     case (s + SUB_MARK): case (s + LETTER): case (s + OTHER_CHAR)
 
 
+/* How many tokens to fetch between checks of the engine's resource limits.
+ * Every computation fetches tokens, so this catches runaway documents. */
+#define RESOURCE_CHECK_INTERVAL 100000
+
+static int32_t resource_check_countdown = RESOURCE_CHECK_INTERVAL;
+
 void
 get_next(void)
 {
@@ -5163,6 +5169,11 @@ get_next(void)
     small_number d;
     small_number sup_count;
 
+    if (--resource_check_countdown <= 0) {
+        resource_check_countdown = RESOURCE_CHECK_INTERVAL;
+        ttstub_check_resource_limits();
+    }
+
 restart:
     cur_cs = 0;
 
@@ -10872,7 +10883,7 @@ open_log_file(void)
 
     old_setting = selector;
     if (job_name == 0)
-        job_name = maketexstring("texput");
+        job_name = maketexstring(job_name_override ? job_name_override : "texput");
 
     pack_job_name(".log");
 
@@ -11012,7 +11023,7 @@ start_input(const char *primary_input_name)
     /* Finally we start really doing stuff with the newly-opened file. */
 
     if (job_name == 0) {
-        job_name = cur_name;
+        job_name = job_name_override ? maketexstring(job_name_override) : cur_name;
         open_log_file();
     }
 
@@ -363,7 +363,7 @@ typedef enum {
 void getmd5sum(int32_t s, bool file);
 
 void init_start_time(time_t source_date_epoch);
-void get_date_and_time (time_t source_date_epoch, int32_t *minutes, int32_t *day, int32_t *month, int32_t *year);
+void get_date_and_time (time_t source_date_epoch, bool utc, int32_t *minutes, int32_t *day, int32_t *month, int32_t *year);
 void get_seconds_and_micros (int32_t *seconds,  int32_t *micros);
 
 void getcreationdate(void);
@@ -394,6 +394,7 @@ extern int32_t first;
 extern int32_t last;
 extern int32_t max_buf_stack;
 extern bool in_initex_mode;
+extern bool initex_loads_format;
 extern int32_t error_line;
 extern int32_t half_error_line;
 extern int32_t max_print_line;
@@ -415,6 +416,9 @@ extern int32_t save_size;
 extern int32_t expand_depth;
 extern int file_line_error_style_p;
 extern int halt_on_error_p;
+extern int initial_interaction;
+extern char *job_name_override;
+extern char *default_font_features;
 extern bool quoted_filename;
 extern bool insert_src_special_auto;
 extern bool insert_src_special_every_par;
@@ -722,6 +726,9 @@ extern scaled_t delta;
 extern int synctex_enabled;
 extern bool used_tectonic_coda_tokens;
 extern bool semantic_pagination_enabled;
+extern bool actual_text_enabled;
+extern bool clock_pinned;
+extern bool system_fonts_enabled;
 extern bool gave_char_warning_help;
 
 /*:1683*/
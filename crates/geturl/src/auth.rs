@@ -0,0 +1,221 @@
+// Copyright 2025 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Credentials for fetching from servers that require authentication.
+//!
+//! Bundles can be hosted behind HTTP authentication. Credentials are tied to a
+//! host, and are only sent to that host over HTTPS, so that a redirection to
+//! a CDN or a plain-HTTP URL doesn't leak them. They can be registered by the
+//! program with [`add_credentials`], or given in the environment:
+//!
+//! - `TECTONIC_BUNDLE_AUTH_HOST` names the host that the credentials are for.
+//! - `TECTONIC_BUNDLE_TOKEN` gives a bearer token, or
+//! - `TECTONIC_BUNDLE_USERNAME` and `TECTONIC_BUNDLE_PASSWORD` give a username
+//!   and password for basic authentication.
+//!
+//! Registered credentials take precedence over the environment.
+
+use std::{env, fmt, sync::RwLock};
+
+/// The environment variable naming the host that the credentials in the
+/// environment are for.
+pub const AUTH_HOST_ENV_VAR: &str = "TECTONIC_BUNDLE_AUTH_HOST";
+
+/// The environment variable giving a bearer token.
+pub const TOKEN_ENV_VAR: &str = "TECTONIC_BUNDLE_TOKEN";
+
+/// The environment variable giving a username for basic authentication.
+pub const USERNAME_ENV_VAR: &str = "TECTONIC_BUNDLE_USERNAME";
+
+/// The environment variable giving a password for basic authentication.
+pub const PASSWORD_ENV_VAR: &str = "TECTONIC_BUNDLE_PASSWORD";
+
+/// Credentials to present to a server.
+#[derive(Clone, Eq, PartialEq)]
+pub enum Credentials {
+    /// A bearer token, as used by most token-based schemes.
+    Bearer(String),
+
+    /// A username and password for HTTP basic authentication.
+    Basic {
+        /// The username.
+        username: String,
+
+        /// The password, which may be empty.
+        password: String,
+    },
+}
+
+impl Credentials {
+    /// Get the value of the `Authorization` header for these credentials.
+    pub fn header_value(&self) -> String {
+        match self {
+            Credentials::Bearer(token) => format!("Bearer {token}"),
+            Credentials::Basic { username, password } => {
+                format!(
+                    "Basic {}",
+                    base64(format!("{username}:{password}").as_bytes())
+                )
+            }
+        }
+    }
+}
+
+// Don't print the secrets in debugging output.
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Credentials::Bearer(_) => f.write_str("Bearer(..)"),
+            Credentials::Basic { username, .. } => f
+                .debug_struct("Basic")
+                .field("username", username)
+                .finish_non_exhaustive(),
+        }
+    }
+}
+
+static REGISTERED: RwLock<Vec<(String, Credentials)>> = RwLock::new(Vec::new());
+
+/// Register credentials to use for requests to `host`.
+///
+/// The host may include a port, in which case only requests to that port get
+/// the credentials. Registering credentials for a host again replaces them.
+pub fn add_credentials(host: &str, credentials: Credentials) {
+    let host = host.to_ascii_lowercase();
+    let mut registered = REGISTERED.write().unwrap();
+    registered.retain(|(h, _)| *h != host);
+    registered.push((host, credentials));
+}
+
+/// Get the credentials to use for a request to `url`, if any.
+pub fn credentials_for(url: &str) -> Option<Credentials> {
+    let (host, port) = https_host(url)?;
+
+    let matches = |h: &str| {
+        let h = h.to_ascii_lowercase();
+        h == host || port.is_some_and(|p| h == format!("{host}:{p}"))
+    };
+
+    if let Some((_, c)) = REGISTERED.read().unwrap().iter().find(|(h, _)| matches(h)) {
+        return Some(c.clone());
+    }
+
+    let env_host = env::var(AUTH_HOST_ENV_VAR).ok()?;
+    if !matches(&env_host) {
+        return None;
+    }
+
+    credentials_from_env()
+}
+
+/// Get the credentials given in the environment, regardless of host.
+fn credentials_from_env() -> Option<Credentials> {
+    if let Ok(token) = env::var(TOKEN_ENV_VAR) {
+        if !token.is_empty() {
+            return Some(Credentials::Bearer(token));
+        }
+    }
+
+    let username = env::var(USERNAME_ENV_VAR).ok().filter(|u| !u.is_empty())?;
+    let password = env::var(PASSWORD_ENV_VAR).unwrap_or_default();
+    Some(Credentials::Basic { username, password })
+}
+
+/// Get the lower-cased host and the port of an HTTPS URL. Other URLs give
+/// `None`, since credentials aren't sent over plain HTTP.
+fn https_host(url: &str) -> Option<(String, Option<&str>)> {
+    let (scheme, rest) = url.split_once("://")?;
+    if !scheme.eq_ignore_ascii_case("https") {
+        return None;
+    }
+
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let authority = authority.rsplit('@').next()?;
+
+    // Leave the colons of IPv6 addresses alone.
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((h, p)) if !p.contains(']') => (h, Some(p)),
+        _ => (authority, None),
+    };
+
+    Some((host.to_ascii_lowercase(), port))
+}
+
+/// Encode bytes as standard, padded base64.
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_values() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(
+            Credentials::Basic {
+                username: "Aladdin".to_owned(),
+                password: "open sesame".to_owned(),
+            }
+            .header_value(),
+            "Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ=="
+        );
+        assert_eq!(
+            Credentials::Bearer("abc".to_owned()).header_value(),
+            "Bearer abc"
+        );
+    }
+
+    #[test]
+    fn hosts() {
+        add_credentials("Bundles.Example.com", Credentials::Bearer("t".to_owned()));
+        add_credentials(
+            "other.example.com:8443",
+            Credentials::Bearer("u".to_owned()),
+        );
+
+        let token = |url| credentials_for(url).map(|c| c.header_value());
+
+        assert_eq!(
+            token("https://bundles.example.com/b.ttb").as_deref(),
+            Some("Bearer t")
+        );
+        assert_eq!(
+            token("https://BUNDLES.example.com:443/b.ttb?x=1").as_deref(),
+            Some("Bearer t")
+        );
+        assert_eq!(
+            token("https://other.example.com:8443/b.ttb").as_deref(),
+            Some("Bearer u")
+        );
+        assert_eq!(token("https://other.example.com/b.ttb"), None);
+        assert_eq!(token("http://bundles.example.com/b.ttb"), None);
+        assert_eq!(token("https://cdn.example.net/bundles.example.com"), None);
+        assert_eq!(token("https://bundles.example.com.evil.net/b.ttb"), None);
+    }
+}
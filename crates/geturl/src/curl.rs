@@ -3,11 +3,11 @@
 
 //! A URL-get backend based on the `curl` crate.
 
-use curl::easy::Easy;
-use std::io::Cursor;
+use curl::easy::{Easy, List};
+use std::{env, io::Cursor};
 use tectonic_errors::{anyhow::bail, Result};
 
-use crate::{GetUrlBackend, RangeReader};
+use crate::{auth::credentials_for, ca_certs_path, check_network, GetUrlBackend, RangeReader};
 
 const MAX_HTTP_REDIRECTS_ALLOWED: u32 = 10;
 
@@ -16,7 +16,32 @@ fn get_url_generic(
     url: &str,
     range: Option<(u64, usize)>,
 ) -> Result<Cursor<Vec<u8>>> {
+    // Start from a clean slate, so that a proxy set for one URL doesn't carry
+    // over to the next. Open connections are kept.
+    handle.reset();
     handle.url(url)?;
+
+    // libcurl deliberately ignores the upper-case `HTTP_PROXY`, which can be
+    // set by untrusted CGI-style request headers. We don't run in that kind of
+    // context, and reqwest honors it, so we do too. The other variables are
+    // handled by libcurl itself.
+    if url.starts_with("http:") && env::var_os("http_proxy").is_none() {
+        if let Ok(proxy) = env::var("HTTP_PROXY") {
+            handle.proxy(&proxy)?;
+        }
+    }
+
+    if let Some(path) = ca_certs_path() {
+        handle.cainfo(path)?;
+    }
+
+    // libcurl doesn't send this header on if it's redirected to another host.
+    if let Some(creds) = credentials_for(url) {
+        let mut headers = List::new();
+        headers.append(&format!("Authorization: {}", creds.header_value()))?;
+        handle.http_headers(headers)?;
+    }
+
     handle.follow_location(true)?;
     handle.max_redirections(MAX_HTTP_REDIRECTS_ALLOWED)?;
 
@@ -67,6 +92,7 @@ impl GetUrlBackend for CurlBackend {
     type RangeReader = CurlRangeReader;
 
     fn get_url(&mut self, url: &str) -> Result<Self::Response> {
+        check_network(url)?;
         get_url_generic(&mut self.handle, url, None)
     }
 
@@ -99,6 +125,7 @@ impl RangeReader for CurlRangeReader {
     type Response = Cursor<Vec<u8>>;
 
     fn read_range(&mut self, offset: u64, length: usize) -> Result<Self::Response> {
+        check_network(&self.url)?;
         get_url_generic(&mut self.handle, &self.url, Some((offset, length)))
     }
 }
@@ -3,11 +3,13 @@
 
 //! A simple, pluggable interface for HTTP GETs and range requests.
 //!
-//! At the moment, there are three backends that might be available:
+//! At the moment, there are four backends that might be available:
 //! 1. A "null" backend that is always available, but does nothing and always
 //!    fails.
 //! 2. A backend based on [reqwest](https://docs.rs/reqwest/).
 //! 3. A backend based on [curl](https://docs.rs/curl/).
+//! 4. A backend based on [ureq](https://docs.rs/ureq/), which doesn't need an
+//!    async runtime.
 //!
 //! The actual backend availability is determined at build time using [Cargo
 //! features][features]. See the crate README for information about the features
@@ -18,11 +20,82 @@
 //! The default backend is intentionally exposed as a concrete type, so that
 //! crates relying on this one need not use a lot of dyns and impl Traits. It is
 //! intended that the choice of HTTP backend is a build-time one, not a runtime
-//! one.
+//! one. Programs that already have an HTTP client of their own can implement
+//! [`GetUrlBackend`] for it and hand it to the network bundles of the
+//! `tectonic_bundles` crate instead.
+//!
+//! All of the real backends send the credentials found by
+//! [`auth::credentials_for`] with their requests, so that bundles can be
+//! hosted behind authentication.
+//!
+//! Network access can be switched off for the whole process with
+//! [`set_network_disabled`], after which all of the real backends fail
+//! without connecting to anything.
 
 use cfg_if::cfg_if;
-use std::io::Read;
-use tectonic_errors::Result;
+use std::{
+    env,
+    io::Read,
+    path::PathBuf,
+    sync::atomic::{AtomicBool, Ordering},
+};
+use tectonic_errors::{prelude::bail, Result};
+
+/// The environment variable naming a file of PEM-encoded CA certificates to
+/// trust when making HTTPS requests.
+///
+/// This is needed behind proxies that intercept TLS connections with their own
+/// certificate authority. With the `reqwest` backend, the certificates are
+/// trusted in addition to the system's own. With the `curl` and `ureq`
+/// backends, they replace the default set, so the file should contain both.
+pub const CA_CERTS_ENV_VAR: &str = "TECTONIC_CA_CERTS";
+
+/// The standard environment variables that configure HTTP proxies. All of the
+/// backends except the null one honor them.
+pub const PROXY_ENV_VARS: &[&str] = &[
+    "HTTPS_PROXY",
+    "https_proxy",
+    "HTTP_PROXY",
+    "http_proxy",
+    "ALL_PROXY",
+    "all_proxy",
+    "NO_PROXY",
+    "no_proxy",
+];
+
+/// Get the path of the CA certificate file named by `TECTONIC_CA_CERTS`, if
+/// it's set.
+pub fn ca_certs_path() -> Option<PathBuf> {
+    env::var_os(CA_CERTS_ENV_VAR)
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+}
+
+static NETWORK_DISABLED: AtomicBool = AtomicBool::new(false);
+
+/// Disable (or re-enable) network access for the whole process.
+///
+/// This is meant for programs that must never touch the network, such as a
+/// service that compiles untrusted documents. Once it's disabled, the requests
+/// of all of the real backends fail with an error saying so.
+pub fn set_network_disabled(disabled: bool) {
+    NETWORK_DISABLED.store(disabled, Ordering::SeqCst);
+}
+
+/// Get whether network access has been disabled with [`set_network_disabled`].
+pub fn network_disabled() -> bool {
+    NETWORK_DISABLED.load(Ordering::SeqCst)
+}
+
+/// Fail if network access has been disabled. Backends call this before
+/// making any request to `url`, and custom backends should too.
+pub fn check_network(url: &str) -> Result<()> {
+    if network_disabled() {
+        bail!("can't connect to {url}, since network access is disabled");
+    }
+
+    Ok(())
+}
 
 /// A trait for reading byte ranges from an HTTP resource.
 pub trait RangeReader {
@@ -54,6 +127,7 @@ pub trait GetUrlBackend: Default {
     fn open_range_reader(&self, url: &str) -> Self::RangeReader;
 }
 
+pub mod auth;
 pub mod null;
 
 #[cfg(feature = "curl")]
@@ -62,11 +136,16 @@ pub mod curl;
 #[cfg(feature = "reqwest")]
 pub mod reqwest;
 
+#[cfg(feature = "ureq")]
+pub mod ureq;
+
 cfg_if! {
     if #[cfg(feature = "reqwest")] {
         pub use crate::reqwest::ReqwestBackend as DefaultBackend;
     } else if #[cfg(feature = "curl")] {
         pub use crate::curl::CurlBackend as DefaultBackend;
+    } else if #[cfg(feature = "ureq")] {
+        pub use crate::ureq::UreqBackend as DefaultBackend;
     } else {
         pub use null::NullBackend as DefaultBackend;
     }
@@ -4,17 +4,57 @@
 //! A URL-get backend based on the `reqwest` crate.
 
 use reqwest::{
-    blocking::{Client, Response},
-    header::{HeaderMap, RANGE},
+    blocking::{Client, ClientBuilder, Response},
+    header::{HeaderMap, AUTHORIZATION, RANGE},
     redirect::Policy,
-    StatusCode, Url,
+    Certificate, StatusCode, Url,
 };
-use tectonic_errors::{anyhow::bail, Result};
+use std::fs;
+use tectonic_errors::{anyhow::bail, atry, Result};
 
-use crate::{GetUrlBackend, RangeReader};
+use crate::{auth::credentials_for, ca_certs_path, check_network, GetUrlBackend, RangeReader};
 
 const MAX_HTTP_REDIRECTS_ALLOWED: usize = 10;
 
+/// Start building an HTTP client that trusts the certificates named by
+/// `TECTONIC_CA_CERTS`, if it's set. Proxies set in the environment are used
+/// by reqwest without further ado.
+fn client_builder() -> Result<ClientBuilder> {
+    let mut builder = Client::builder();
+
+    if let Some(path) = ca_certs_path() {
+        let pem = atry!(
+            fs::read(&path);
+            ["failed to read CA certificates from `{}`", path.display()]
+        );
+        let certs = atry!(
+            Certificate::from_pem_bundle(&pem);
+            ["failed to parse CA certificates in `{}`", path.display()]
+        );
+
+        for cert in certs {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+
+    Ok(builder)
+}
+
+/// Get the headers to send with every request to `url`: for now, just its
+/// credentials, if there are any. Reqwest drops them if it's redirected to
+/// another host.
+fn base_headers(url: &str) -> Result<HeaderMap> {
+    let mut headers = HeaderMap::new();
+
+    if let Some(creds) = credentials_for(url) {
+        let mut value: reqwest::header::HeaderValue = creds.header_value().parse()?;
+        value.set_sensitive(true);
+        headers.insert(AUTHORIZATION, value);
+    }
+
+    Ok(headers)
+}
+
 /// URL-get backend implemented using the `reqwest` crate.
 #[derive(Debug, Default)]
 pub struct ReqwestBackend {}
@@ -24,7 +64,13 @@ impl GetUrlBackend for ReqwestBackend {
     type RangeReader = ReqwestRangeReader;
 
     fn get_url(&mut self, url: &str) -> Result<Response> {
-        let res = Client::new().get(url).send()?;
+        check_network(url)?;
+
+        let res = client_builder()?
+            .build()?
+            .get(url)
+            .headers(base_headers(url)?)
+            .send()?;
         if !res.status().is_success() {
             bail!(
                 "unexpected HTTP response code {} for URL {}",
@@ -36,6 +82,8 @@ impl GetUrlBackend for ReqwestBackend {
     }
 
     fn resolve_url(&mut self, url: &str) -> Result<String> {
+        check_network(url)?;
+
         let parsed = Url::parse(url)?;
         let original_filename = parsed
             .path_segments()
@@ -75,10 +123,11 @@ impl GetUrlBackend for ReqwestBackend {
             }
         });
 
-        let res = Client::builder()
+        let res = client_builder()?
             .redirect(redirect_policy)
             .build()?
             .head(url)
+            .headers(base_headers(url)?)
             .send()?;
 
         if !(res.status().is_success()
@@ -107,14 +156,14 @@ impl GetUrlBackend for ReqwestBackend {
 #[derive(Debug)]
 pub struct ReqwestRangeReader {
     url: String,
-    client: Client,
+    client: Option<Client>,
 }
 
 impl ReqwestRangeReader {
     fn new(url: &str) -> ReqwestRangeReader {
         ReqwestRangeReader {
             url: url.to_owned(),
-            client: Client::new(),
+            client: None,
         }
     }
 }
@@ -123,13 +172,21 @@ impl RangeReader for ReqwestRangeReader {
     type Response = Response;
 
     fn read_range(&mut self, offset: u64, length: usize) -> Result<Response> {
+        check_network(&self.url)?;
+
         let end_inclusive = offset + length as u64 - 1;
         let header_val = format!("bytes={offset}-{end_inclusive}").parse()?;
 
-        let mut headers = HeaderMap::new();
+        let mut headers = base_headers(&self.url)?;
         headers.insert(RANGE, header_val);
 
-        let res = self.client.get(&self.url).headers(headers).send()?;
+        // The client is set up on first use, since setting it up can fail.
+        let client = match &self.client {
+            Some(c) => c,
+            None => self.client.insert(client_builder()?.build()?),
+        };
+
+        let res = client.get(&self.url).headers(headers).send()?;
 
         if res.status() != StatusCode::PARTIAL_CONTENT {
             bail!(
@@ -0,0 +1,238 @@
+// Copyright 2024 the Tectonic Project
+// Licensed under the MIT License.
+
+//! A URL-get backend based on the `ureq` crate.
+//!
+//! Unlike `reqwest`, `ureq` is a small, fully synchronous HTTP client, so this
+//! backend doesn't pull in an async runtime.
+
+use std::fs;
+use tectonic_errors::{anyhow::bail, atry, Result};
+use ureq::{
+    http::{header::AUTHORIZATION, header::LOCATION, header::RANGE, StatusCode, Uri},
+    tls::{parse_pem, PemItem, RootCerts, TlsConfig},
+    Agent, BodyReader, RequestBuilder,
+};
+
+use crate::{auth::credentials_for, ca_certs_path, check_network, GetUrlBackend, RangeReader};
+
+const MAX_HTTP_REDIRECTS_ALLOWED: u32 = 10;
+
+/// Make an agent that follows up to `max_redirects` redirections and trusts
+/// the certificates named by `TECTONIC_CA_CERTS`, if it's set. Proxies set in
+/// the environment are used by ureq without further ado.
+fn make_agent(max_redirects: u32) -> Result<Agent> {
+    let mut config = Agent::config_builder()
+        .max_redirects(max_redirects)
+        .http_status_as_error(false);
+
+    if let Some(path) = ca_certs_path() {
+        let pem = atry!(
+            fs::read(&path);
+            ["failed to read CA certificates from `{}`", path.display()]
+        );
+
+        let mut certs = Vec::new();
+
+        for item in parse_pem(&pem) {
+            if let PemItem::Certificate(cert) = atry!(
+                item;
+                ["failed to parse CA certificates in `{}`", path.display()]
+            ) {
+                certs.push(cert);
+            }
+        }
+
+        config = config.tls_config(
+            TlsConfig::builder()
+                .root_certs(RootCerts::new_with_certs(&certs))
+                .build(),
+        );
+    }
+
+    Ok(config.build().new_agent())
+}
+
+/// Add the credentials for `url` to a request, if there are any. Ureq never
+/// sends them on after a redirection.
+fn with_credentials<B>(req: RequestBuilder<B>, url: &str) -> RequestBuilder<B> {
+    match credentials_for(url) {
+        Some(creds) => req.header(AUTHORIZATION, creds.header_value()),
+        None => req,
+    }
+}
+
+/// URL-get backend implemented using the `ureq` crate.
+#[derive(Debug, Default)]
+pub struct UreqBackend {}
+
+impl GetUrlBackend for UreqBackend {
+    type Response = BodyReader<'static>;
+    type RangeReader = UreqRangeReader;
+
+    fn get_url(&mut self, url: &str) -> Result<Self::Response> {
+        check_network(url)?;
+
+        let agent = make_agent(MAX_HTTP_REDIRECTS_ALLOWED)?;
+        let res = with_credentials(agent.get(url), url).call()?;
+
+        if !res.status().is_success() {
+            bail!(
+                "unexpected HTTP response code {} for URL {}",
+                res.status(),
+                url
+            );
+        }
+
+        Ok(res.into_body().into_reader())
+    }
+
+    fn resolve_url(&mut self, url: &str) -> Result<String> {
+        check_network(url)?;
+
+        // We follow the redirections by hand, with the same rules as the
+        // reqwest backend: stop once the filename changes to something
+        // without a `.` in it, which is probably a CDN file hash.
+        let agent = make_agent(0)?;
+        let original_filename = filename(url).to_owned();
+        let mut current = url.to_owned();
+
+        for _ in 0..MAX_HTTP_REDIRECTS_ALLOWED {
+            let res = with_credentials(agent.head(&current), &current).call()?;
+            let status = res.status();
+
+            if status.is_success() {
+                return Ok(current);
+            }
+
+            if !status.is_redirection() {
+                bail!("unexpected HTTP response code {} for URL {}", status, url);
+            }
+
+            let Some(location) = res.headers().get(LOCATION).and_then(|v| v.to_str().ok()) else {
+                bail!("redirection without a location for URL {}", current);
+            };
+
+            let next = join_location(&current, location)?;
+            let file = filename(&next);
+
+            if !(file.contains('.') || file == original_filename) {
+                if status == StatusCode::FOUND || status == StatusCode::TEMPORARY_REDIRECT {
+                    return Ok(current);
+                }
+
+                bail!("unexpected HTTP response code {} for URL {}", status, url);
+            }
+
+            current = next;
+        }
+
+        bail!("too many redirections for URL {}", url);
+    }
+
+    fn open_range_reader(&self, url: &str) -> Self::RangeReader {
+        UreqRangeReader::new(url)
+    }
+}
+
+/// Get the last path segment of a URL.
+fn filename(url: &str) -> &str {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+/// Resolve the target of a redirection against the URL that it came from.
+fn join_location(base: &str, location: &str) -> Result<String> {
+    let loc: Uri = location.parse()?;
+
+    if loc.scheme().is_some() {
+        return Ok(location.to_owned());
+    }
+
+    let base: Uri = base.parse()?;
+
+    let (Some(scheme), Some(authority)) = (base.scheme_str(), base.authority()) else {
+        bail!(
+            "cannot resolve redirection `{}` against `{}`",
+            location,
+            base
+        );
+    };
+
+    if location.starts_with('/') {
+        Ok(format!("{scheme}://{authority}{location}"))
+    } else {
+        let dir = base.path().rsplit_once('/').map_or("", |(dir, _)| dir);
+        Ok(format!("{scheme}://{authority}{dir}/{location}"))
+    }
+}
+
+/// Ureq-based byte-range reader.
+#[derive(Debug)]
+pub struct UreqRangeReader {
+    url: String,
+    agent: Option<Agent>,
+}
+
+impl UreqRangeReader {
+    fn new(url: &str) -> UreqRangeReader {
+        UreqRangeReader {
+            url: url.to_owned(),
+            agent: None,
+        }
+    }
+}
+
+impl RangeReader for UreqRangeReader {
+    type Response = BodyReader<'static>;
+
+    fn read_range(&mut self, offset: u64, length: usize) -> Result<Self::Response> {
+        check_network(&self.url)?;
+
+        // The agent is set up on first use, since setting it up can fail.
+        let agent = match &self.agent {
+            Some(a) => a,
+            None => self.agent.insert(make_agent(MAX_HTTP_REDIRECTS_ALLOWED)?),
+        };
+
+        let end_inclusive = offset + length as u64 - 1;
+        let res = with_credentials(agent.get(&self.url), &self.url)
+            .header(RANGE, format!("bytes={offset}-{end_inclusive}"))
+            .call()?;
+
+        if res.status() != StatusCode::PARTIAL_CONTENT {
+            bail!(
+                "unexpected HTTP response code {} for URL {}",
+                res.status(),
+                self.url
+            );
+        }
+
+        Ok(res.into_body().into_reader())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locations() {
+        let base = "https://example.com/bundles/tlextras?x=1";
+
+        assert_eq!(
+            join_location(base, "https://cdn.example.net/a.tar").unwrap(),
+            "https://cdn.example.net/a.tar"
+        );
+        assert_eq!(
+            join_location(base, "/other/b.tar").unwrap(),
+            "https://example.com/other/b.tar"
+        );
+        assert_eq!(
+            join_location(base, "c.tar").unwrap(),
+            "https://example.com/bundles/c.tar"
+        );
+        assert_eq!(filename(base), "tlextras");
+        assert_eq!(filename("https://example.com/"), "");
+    }
+}
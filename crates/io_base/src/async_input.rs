@@ -0,0 +1,249 @@
+// Copyright 2024 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Input files fetched asynchronously.
+//!
+//! The TeX engines are synchronous: when they open a file, they wait for it.
+//! Services built on an async runtime, though, want to fetch remote resources
+//! with their own async clients rather than tie up a thread per request. This
+//! module bridges the two. An [`AsyncInputProvider`] fetches files with
+//! async code. [`bridge`] pairs it with an [`IoProvider`] that the engines
+//! use, [`AsyncInputIo`], and a future, [`AsyncInputServer::run`], that the
+//! service runs on its runtime. When the engines open a file, the request is
+//! handed to the server future, and the engine's thread waits for the answer.
+//! The build itself still needs a thread of its own, but the fetching is done
+//! by the runtime.
+
+use std::{
+    collections::VecDeque,
+    future::{poll_fn, Future},
+    io::Cursor,
+    sync::{mpsc, Arc, Mutex},
+    task::{Poll, Waker},
+};
+use tectonic_errors::anyhow::anyhow;
+use tectonic_status_base::StatusBackend;
+
+use crate::{InputHandle, InputOrigin, IoProvider, OpenResult};
+
+/// A source of input files that fetches them asynchronously.
+pub trait AsyncInputProvider {
+    /// Fetch the file *name*, returning its contents, or
+    /// [`OpenResult::NotAvailable`] if there's no such file.
+    fn fetch(&mut self, name: &str) -> impl Future<Output = OpenResult<Vec<u8>>> + Send;
+}
+
+/// A request for a file, waiting to be answered by the server.
+struct Request {
+    name: String,
+    reply: mpsc::Sender<OpenResult<Vec<u8>>>,
+}
+
+#[derive(Default)]
+struct Queue {
+    requests: VecDeque<Request>,
+    waker: Option<Waker>,
+    closed: bool,
+}
+
+/// Create the two halves of a bridge to the asynchronous *provider*.
+///
+/// The [`AsyncInputIo`] goes into the I/O stack of a build; the
+/// [`AsyncInputServer`] should be run on an async runtime for as long as the
+/// build lasts.
+pub fn bridge<P: AsyncInputProvider>(provider: P) -> (AsyncInputIo, AsyncInputServer<P>) {
+    let queue = Arc::new(Mutex::new(Queue::default()));
+
+    (
+        AsyncInputIo {
+            queue: queue.clone(),
+        },
+        AsyncInputServer { queue, provider },
+    )
+}
+
+/// An I/O provider whose input files are fetched by an [`AsyncInputServer`].
+///
+/// Opening a file blocks the calling thread until the server has fetched it.
+/// The provider never creates output files.
+pub struct AsyncInputIo {
+    queue: Arc<Mutex<Queue>>,
+}
+
+impl IoProvider for AsyncInputIo {
+    fn input_open_name(
+        &mut self,
+        name: &str,
+        _status: &mut dyn StatusBackend,
+    ) -> OpenResult<InputHandle> {
+        let (reply, answer) = mpsc::channel();
+
+        {
+            let mut queue = self.queue.lock().unwrap();
+
+            if queue.closed {
+                return OpenResult::Err(anyhow!("the async input server has stopped"));
+            }
+
+            queue.requests.push_back(Request {
+                name: name.to_owned(),
+                reply,
+            });
+
+            if let Some(waker) = queue.waker.take() {
+                waker.wake();
+            }
+        }
+
+        match answer.recv() {
+            Ok(OpenResult::Ok(data)) => OpenResult::Ok(InputHandle::new_read_only(
+                name,
+                Cursor::new(data),
+                InputOrigin::Other,
+            )),
+            Ok(OpenResult::NotAvailable) => OpenResult::NotAvailable,
+            Ok(OpenResult::Err(e)) => OpenResult::Err(e),
+            Err(_) => OpenResult::Err(anyhow!(
+                "the async input server stopped before fetching `{}`",
+                name
+            )),
+        }
+    }
+}
+
+impl Drop for AsyncInputIo {
+    fn drop(&mut self) {
+        let mut queue = self.queue.lock().unwrap();
+        queue.closed = true;
+
+        if let Some(waker) = queue.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// The half of a bridge that answers requests with an [`AsyncInputProvider`].
+pub struct AsyncInputServer<P> {
+    queue: Arc<Mutex<Queue>>,
+    provider: P,
+}
+
+impl<P: AsyncInputProvider> AsyncInputServer<P> {
+    /// Answer requests for files until the [`AsyncInputIo`] is dropped, which
+    /// happens when the build that uses it is done. Requests are answered
+    /// one at a time, since a build only asks for one file at a time.
+    pub async fn run(mut self) {
+        while let Some(request) = self.next_request().await {
+            let result = self.provider.fetch(&request.name).await;
+
+            // If the build has gone away, nobody needs the answer.
+            let _ = request.reply.send(result);
+        }
+
+        self.queue.lock().unwrap().closed = true;
+    }
+
+    /// Wait for the next request, or `None` once the other half is gone.
+    async fn next_request(&self) -> Option<Request> {
+        poll_fn(|cx| {
+            let mut queue = self.queue.lock().unwrap();
+
+            if let Some(request) = queue.requests.pop_front() {
+                Poll::Ready(Some(request))
+            } else if queue.closed {
+                Poll::Ready(None)
+            } else {
+                queue.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        collections::HashMap,
+        io::Read,
+        pin::pin,
+        sync::Arc,
+        task::{Context, Wake},
+        thread::{self, Thread},
+    };
+    use tectonic_status_base::NoopStatusBackend;
+
+    /// A provider that yields to the executor once before answering, as a
+    /// network fetch would.
+    struct Remote(HashMap<&'static str, &'static str>);
+
+    impl AsyncInputProvider for Remote {
+        fn fetch(&mut self, name: &str) -> impl Future<Output = OpenResult<Vec<u8>>> + Send {
+            let result = match self.0.get(name) {
+                Some(text) => OpenResult::Ok(text.as_bytes().to_vec()),
+                None => OpenResult::NotAvailable,
+            };
+
+            async move {
+                let mut yielded = false;
+
+                poll_fn(|cx| {
+                    if yielded {
+                        Poll::Ready(())
+                    } else {
+                        yielded = true;
+                        cx.waker().wake_by_ref();
+                        Poll::Pending
+                    }
+                })
+                .await;
+
+                result
+            }
+        }
+    }
+
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    /// A minimal executor, standing in for a service's async runtime.
+    fn block_on<F: Future>(fut: F) -> F::Output {
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut fut = pin!(fut);
+
+        loop {
+            match fut.as_mut().poll(&mut cx) {
+                Poll::Ready(v) => return v,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn fetch_through_bridge() {
+        let (mut io, server) = bridge(Remote(HashMap::from([("a.tex", "hello")])));
+        let runtime = thread::spawn(move || block_on(server.run()));
+
+        let mut text = String::new();
+        io.input_open_name("a.tex", &mut NoopStatusBackend {})
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        assert_eq!(text, "hello");
+
+        assert!(io
+            .input_open_name("b.tex", &mut NoopStatusBackend {})
+            .is_not_available());
+
+        // Dropping the I/O half lets the server finish.
+        drop(io);
+        runtime.join().unwrap();
+    }
+}
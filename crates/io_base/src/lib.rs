@@ -27,6 +27,7 @@ use thiserror::Error as ThisError;
 use crate::digest::DigestData;
 
 pub mod app_dirs;
+pub mod async_input;
 pub mod digest;
 pub mod filesystem;
 pub mod flate2;
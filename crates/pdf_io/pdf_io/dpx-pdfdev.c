@@ -1653,6 +1653,14 @@ pdf_dev_put_image (int             id,
   pdf_concatmatrix(&M, &M1);
   pdf_dev_concat(&M);
 
+  /* Tectonic: track placed sizes for image downsampling */
+  if (pdf_ximage_get_subtype(id) == PDF_XOBJECT_TYPE_IMAGE) {
+    pdf_tmatrix CTM;
+
+    pdf_dev_currentmatrix(&CTM);
+    pdf_ximage_note_placement(id, &CTM);
+  }
+
   /* Clip */
   if (ti->flags & INFO_DO_CLIP) {
     pdf_dev_rectclip(r.llx, r.lly, r.urx - r.llx, r.ury - r.lly);
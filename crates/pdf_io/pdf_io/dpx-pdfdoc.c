@@ -28,6 +28,7 @@
 #include "dpx-pdfdoc.h"
 
 #include <assert.h>
+#include <ctype.h>
 #include <math.h>
 #include <stdint.h>
 #include <stdio.h>
@@ -58,6 +59,7 @@
 #define PDFDOC_PAGES_ALLOC_SIZE   128u
 #define PDFDOC_ARTICLE_ALLOC_SIZE 16
 #define PDFDOC_BEAD_ALLOC_SIZE    16
+#define PDFDOC_STRUCT_ALLOC_SIZE  16
 
 static pdf_obj *
 read_thumbnail (const char *thumb_filename)
@@ -165,6 +167,12 @@ typedef struct pdf_article
   pdf_bead *beads;
 } pdf_article;
 
+typedef struct pdf_struct_elem
+{
+  pdf_obj *dict;
+  pdf_obj *ref;
+} pdf_struct_elem;
+
 struct name_dict
 {
   const char  *category;
@@ -207,6 +215,20 @@ typedef struct pdf_doc
     pdf_article *entries;
   } articles;
 
+  /* Logical structure for tagged PDF, created on first use. */
+  struct {
+    pdf_obj *root;        /* StructTreeRoot dictionary */
+    pdf_obj *root_ref;
+    pdf_obj *parent_tree; /* Nums array of the ParentTree */
+    int      next_key;
+    pdf_obj *page_mcids;  /* Elements owning each MCID of the current page */
+    int      in_content;  /* Marked content of the innermost element is open */
+
+    unsigned int     depth;
+    unsigned int     max_depth;
+    pdf_struct_elem *stack;
+  } structure;
+
   struct name_dict *names;
 
   int check_gotos;
@@ -218,6 +240,11 @@ typedef struct pdf_doc
       double x, y;
     } annot_grow;
     int enable_manual_thumb;
+    struct {
+      int enabled;
+      const unsigned char *icc_profile;
+      size_t icc_profile_len;
+    } pdfa;
   } options;
 
   struct form_list_node *pending_forms;
@@ -361,6 +388,10 @@ static void pdf_doc_init_articles    (pdf_doc *p);
 static void pdf_doc_close_articles   (pdf_doc *p);
 static void pdf_doc_init_bookmarks   (pdf_doc *p, int bm_open_depth);
 static void pdf_doc_close_bookmarks  (pdf_doc *p);
+static void pdf_doc_init_structure   (pdf_doc *p);
+static void pdf_doc_close_structure  (pdf_doc *p);
+static void pdf_doc_structure_bop    (pdf_doc *p);
+static void pdf_doc_structure_eop    (pdf_doc *p);
 
 void
 pdf_doc_set_bop_content (const char *content, unsigned int length)
@@ -413,6 +444,276 @@ pdf_doc_init_docinfo (pdf_doc *p)
   return;
 }
 
+/* Tectonic: PDF/A support. PDF/A requires an XMP metadata stream whose
+ * contents agree with the DocInfo dictionary, and an output intent with an
+ * embedded ICC profile. Documents that provide either of these themselves
+ * (e.g. with the pdfx package) keep their own.
+ */
+
+/* PDFDocEncoding differs from Latin-1 in 0x80-0xA0. */
+static const int32_t pdfdoc_encoding_high[] = {
+  0x2022, 0x2020, 0x2021, 0x2026, 0x2014, 0x2013, 0x0192, 0x2044,
+  0x2039, 0x203A, 0x2212, 0x2030, 0x201E, 0x201C, 0x201D, 0x2018,
+  0x2019, 0x201A, 0x2122, 0xFB01, 0xFB02, 0x0141, 0x0152, 0x0160,
+  0x0178, 0x017D, 0x0131, 0x0142, 0x0153, 0x0161, 0x017E, 0xFFFD,
+  0x20AC
+};
+
+static void
+xmp_add (pdf_obj *xmp, const char *str)
+{
+  pdf_add_stream(xmp, str, strlen(str));
+}
+
+static void
+xmp_add_char (pdf_obj *xmp, int32_t c)
+{
+  unsigned char buf[4];
+  int len;
+
+  switch (c) {
+  case '&': xmp_add(xmp, "&amp;"); return;
+  case '<': xmp_add(xmp, "&lt;"); return;
+  case '>': xmp_add(xmp, "&gt;"); return;
+  case '"': xmp_add(xmp, "&quot;"); return;
+  }
+
+  if (c < 0x20 && c != '\t' && c != '\n' && c != '\r')
+    return; /* Not allowed in XML */
+
+  if (c < 0x80) {
+    buf[0] = c;
+    len = 1;
+  } else if (c < 0x800) {
+    buf[0] = 0xC0 | (c >> 6);
+    buf[1] = 0x80 | (c & 0x3F);
+    len = 2;
+  } else if (c < 0x10000) {
+    buf[0] = 0xE0 | (c >> 12);
+    buf[1] = 0x80 | ((c >> 6) & 0x3F);
+    buf[2] = 0x80 | (c & 0x3F);
+    len = 3;
+  } else {
+    buf[0] = 0xF0 | (c >> 18);
+    buf[1] = 0x80 | ((c >> 12) & 0x3F);
+    buf[2] = 0x80 | ((c >> 6) & 0x3F);
+    buf[3] = 0x80 | (c & 0x3F);
+    len = 4;
+  }
+
+  pdf_add_stream(xmp, buf, len);
+}
+
+/* Add a PDF text string, which is either UTF-16BE with a byte order mark or
+ * PDFDocEncoding, as escaped UTF-8. */
+static void
+xmp_add_text (pdf_obj *xmp, pdf_obj *text)
+{
+  const unsigned char *s = pdf_string_value(text);
+  unsigned int len = pdf_string_length(text), i;
+
+  if (len >= 2 && s[0] == 0xFE && s[1] == 0xFF) {
+    for (i = 2; i + 1 < len; i += 2) {
+      int32_t c = (s[i] << 8) | s[i + 1];
+
+      if (c >= 0xD800 && c < 0xDC00 && i + 3 < len) {
+        int32_t lo = (s[i + 2] << 8) | s[i + 3];
+
+        if (lo >= 0xDC00 && lo < 0xE000) {
+          c = 0x10000 + ((c - 0xD800) << 10) + (lo - 0xDC00);
+          i += 2;
+        }
+      }
+
+      if (c >= 0xD800 && c < 0xE000)
+        c = 0xFFFD; /* Unpaired surrogate */
+
+      xmp_add_char(xmp, c);
+    }
+  } else {
+    for (i = 0; i < len; i++) {
+      int32_t c = s[i];
+
+      if (c >= 0x80 && c <= 0xA0)
+        c = pdfdoc_encoding_high[c - 0x80];
+
+      xmp_add_char(xmp, c);
+    }
+  }
+}
+
+/* Convert a PDF date, "D:YYYYMMDDHHmmSSOHH'mm'" with everything after the year
+ * optional, to the ISO 8601 form used by XMP. Returns 0 if it can't be parsed.
+ */
+static int
+xmp_format_date (char *buf, pdf_obj *date)
+{
+  const char *seps = "--T::";
+  const unsigned char *s = pdf_string_value(date);
+  unsigned int len = pdf_string_length(date), i = 0;
+  int n = 0, k;
+  char *p = buf;
+
+  if (len >= 2 && s[0] == 'D' && s[1] == ':')
+    i = 2;
+
+  while (i + n < len && n < 14 && s[i + n] >= '0' && s[i + n] <= '9')
+    n++;
+
+  if (n < 4 || n % 2)
+    return 0;
+
+  memcpy(p, s + i, 4);
+  p += 4;
+
+  for (k = 4; k < n; k += 2) {
+    *p++ = seps[(k - 4) / 2];
+    memcpy(p, s + i + k, 2);
+    p += 2;
+  }
+
+  if (n == 10) {
+    /* XMP has no hour-only times. */
+    memcpy(p, ":00", 3);
+    p += 3;
+  }
+
+  i += n;
+
+  if (n >= 10 && i < len) {
+    if (s[i] == 'Z') {
+      *p++ = 'Z';
+    } else if ((s[i] == '+' || s[i] == '-') && i + 2 < len) {
+      char hh[2] = { s[i + 1], s[i + 2] }, mm[2] = { '0', '0' };
+
+      if (i + 4 < len && s[i + 3] == '\'')
+        memcpy(mm, s + i + 4, 2);
+
+      if (!memcmp(hh, "00", 2) && !memcmp(mm, "00", 2)) {
+        *p++ = 'Z';
+      } else {
+        *p++ = s[i];
+        memcpy(p, hh, 2);
+        p += 2;
+        *p++ = ':';
+        memcpy(p, mm, 2);
+        p += 2;
+      }
+    }
+  }
+
+  *p = '\0';
+  return p - buf;
+}
+
+static void
+xmp_add_entry (pdf_obj *xmp, pdf_obj *info, const char *key,
+               const char *open, const char *close)
+{
+  pdf_obj *value = pdf_lookup_dict(info, key);
+  char date[32];
+
+  if (!value)
+    return;
+
+  if (!strcmp(key, "CreationDate") || !strcmp(key, "ModDate")) {
+    if (!xmp_format_date(date, value))
+      return;
+    xmp_add(xmp, open);
+    xmp_add(xmp, date);
+  } else {
+    xmp_add(xmp, open);
+    xmp_add_text(xmp, value);
+  }
+
+  xmp_add(xmp, close);
+}
+
+static void
+pdf_doc_add_pdfa_entries (pdf_doc *p)
+{
+  pdf_obj *info = p->info;
+
+  if (!pdf_lookup_dict(p->root.dict, "Metadata")) {
+    /* PDF/A forbids filters on the metadata stream. */
+    pdf_obj *xmp = pdf_new_stream(0);
+
+    pdf_add_dict(pdf_stream_dict(xmp),
+                 pdf_new_name("Type"), pdf_new_name("Metadata"));
+    pdf_add_dict(pdf_stream_dict(xmp),
+                 pdf_new_name("Subtype"), pdf_new_name("XML"));
+
+    xmp_add(xmp,
+            "<?xpacket begin=\"\xEF\xBB\xBF\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n"
+            "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n"
+            " <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n"
+            "  <rdf:Description rdf:about=\"\"\n"
+            "    xmlns:pdfaid=\"http://www.aiim.org/pdfa/ns/id/\"\n"
+            "    xmlns:dc=\"http://purl.org/dc/elements/1.1/\"\n"
+            "    xmlns:pdf=\"http://ns.adobe.com/pdf/1.3/\"\n"
+            "    xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\">\n"
+            "   <pdfaid:part>2</pdfaid:part>\n"
+            "   <pdfaid:conformance>B</pdfaid:conformance>\n"
+            "   <dc:format>application/pdf</dc:format>\n");
+    xmp_add_entry(xmp, info, "Title",
+                  "   <dc:title><rdf:Alt><rdf:li xml:lang=\"x-default\">",
+                  "</rdf:li></rdf:Alt></dc:title>\n");
+    xmp_add_entry(xmp, info, "Author",
+                  "   <dc:creator><rdf:Seq><rdf:li>",
+                  "</rdf:li></rdf:Seq></dc:creator>\n");
+    xmp_add_entry(xmp, info, "Subject",
+                  "   <dc:description><rdf:Alt><rdf:li xml:lang=\"x-default\">",
+                  "</rdf:li></rdf:Alt></dc:description>\n");
+    xmp_add_entry(xmp, info, "Keywords",
+                  "   <pdf:Keywords>", "</pdf:Keywords>\n");
+    xmp_add_entry(xmp, info, "Producer",
+                  "   <pdf:Producer>", "</pdf:Producer>\n");
+    xmp_add_entry(xmp, info, "Creator",
+                  "   <xmp:CreatorTool>", "</xmp:CreatorTool>\n");
+    xmp_add_entry(xmp, info, "CreationDate",
+                  "   <xmp:CreateDate>", "</xmp:CreateDate>\n");
+    xmp_add_entry(xmp, info, "ModDate",
+                  "   <xmp:ModifyDate>", "</xmp:ModifyDate>\n");
+    xmp_add(xmp,
+            "  </rdf:Description>\n"
+            " </rdf:RDF>\n"
+            "</x:xmpmeta>\n"
+            "<?xpacket end=\"w\"?>\n");
+
+    pdf_add_dict(p->root.dict, pdf_new_name("Metadata"), pdf_ref_obj(xmp));
+    pdf_release_obj(xmp);
+  }
+
+  if (!pdf_lookup_dict(p->root.dict, "OutputIntents")) {
+    const char *condition = "sRGB IEC61966-2.1";
+    pdf_obj *profile = pdf_new_stream(STREAM_COMPRESS);
+    pdf_obj *intent = pdf_new_dict();
+    pdf_obj *intents = pdf_new_array();
+
+    pdf_add_dict(pdf_stream_dict(profile),
+                 pdf_new_name("N"), pdf_new_number(3));
+    pdf_add_stream(profile,
+                   p->options.pdfa.icc_profile, p->options.pdfa.icc_profile_len);
+
+    pdf_add_dict(intent,
+                 pdf_new_name("Type"), pdf_new_name("OutputIntent"));
+    pdf_add_dict(intent,
+                 pdf_new_name("S"), pdf_new_name("GTS_PDFA1"));
+    pdf_add_dict(intent,
+                 pdf_new_name("OutputConditionIdentifier"),
+                 pdf_new_string(condition, strlen(condition)));
+    pdf_add_dict(intent,
+                 pdf_new_name("Info"),
+                 pdf_new_string(condition, strlen(condition)));
+    pdf_add_dict(intent,
+                 pdf_new_name("DestOutputProfile"), pdf_ref_obj(profile));
+    pdf_release_obj(profile);
+
+    pdf_add_array(intents, intent);
+    pdf_add_dict(p->root.dict, pdf_new_name("OutputIntents"), intents);
+  }
+}
+
 static void
 pdf_doc_close_docinfo (pdf_doc *p)
 {
@@ -470,6 +771,9 @@ pdf_doc_close_docinfo (pdf_doc *p)
                  pdf_new_string(now, strlen(now)));
   }
 
+  if (p->options.pdfa.enabled)
+    pdf_doc_add_pdfa_entries(p);
+
   pdf_release_obj(docinfo);
   p->info = NULL;
 
@@ -1549,6 +1853,34 @@ pdf_doc_add_names (const char *category,
   return pdf_names_add_object(p->names[i].data, key, keylen, value);
 }
 
+int
+pdf_doc_add_attachment (const unsigned char *name, size_t namelen,
+                        const unsigned char *data, size_t length)
+{
+  pdf_obj *stream, *params, *ef, *filespec;
+
+  stream = pdf_new_stream(STREAM_COMPRESS);
+  params = pdf_new_dict();
+  pdf_add_dict(params,
+               pdf_new_name("Size"), pdf_new_number((double) length));
+  pdf_add_dict(pdf_stream_dict(stream),
+               pdf_new_name("Type"), pdf_new_name("EmbeddedFile"));
+  pdf_add_dict(pdf_stream_dict(stream), pdf_new_name("Params"), params);
+  pdf_add_stream(stream, data, length);
+
+  ef = pdf_new_dict();
+  pdf_add_dict(ef, pdf_new_name("F"), pdf_ref_obj(stream));
+  pdf_release_obj(stream);
+
+  filespec = pdf_new_dict();
+  pdf_add_dict(filespec, pdf_new_name("Type"), pdf_new_name("Filespec"));
+  pdf_add_dict(filespec, pdf_new_name("F"), pdf_new_string(name, namelen));
+  pdf_add_dict(filespec, pdf_new_name("UF"), pdf_new_string(name, namelen));
+  pdf_add_dict(filespec, pdf_new_name("EF"), ef);
+
+  return pdf_doc_add_names("EmbeddedFiles", name, (int) namelen, filespec);
+}
+
 static void
 pdf_doc_add_goto (pdf_obj *annot_dict)
 {
@@ -2048,6 +2380,239 @@ pdf_doc_close_articles (pdf_doc *p)
   return;
 }
 
+static void
+pdf_doc_init_structure (pdf_doc *p)
+{
+  p->structure.root        = NULL;
+  p->structure.root_ref    = NULL;
+  p->structure.parent_tree = NULL;
+  p->structure.next_key    = 0;
+  p->structure.page_mcids  = NULL;
+  p->structure.in_content  = 0;
+  p->structure.depth       = 0;
+  p->structure.max_depth   = 0;
+  p->structure.stack       = NULL;
+
+  return;
+}
+
+/* Open a marked-content sequence on the current page for the innermost
+ * structure element and link it to the element with a marked-content
+ * reference.
+ */
+static void
+structure_begin_content (pdf_doc *p)
+{
+  pdf_obj    *elem, *mcr;
+  const char *type;
+  char       *buf;
+  int         mcid, len;
+
+  elem = p->structure.stack[p->structure.depth - 1].dict;
+
+  if (!p->structure.page_mcids)
+    p->structure.page_mcids = pdf_new_array();
+  mcid = pdf_array_length(p->structure.page_mcids);
+  pdf_add_array(p->structure.page_mcids,
+                pdf_link_obj(p->structure.stack[p->structure.depth - 1].ref));
+
+  mcr = pdf_new_dict();
+  pdf_add_dict(mcr, pdf_new_name("Type"), pdf_new_name("MCR"));
+  pdf_add_dict(mcr, pdf_new_name("Pg"), pdf_doc_get_reference("@THISPAGE"));
+  pdf_add_dict(mcr, pdf_new_name("MCID"), pdf_new_number(mcid));
+  pdf_add_array(pdf_lookup_dict(elem, "K"), mcr);
+
+  type = pdf_name_value(pdf_lookup_dict(elem, "S"));
+  buf  = NEW(strlen(type) + 32, char);
+  len  = sprintf(buf, " /%s <</MCID %d>> BDC", type, mcid);
+  graphics_mode();
+  pdf_doc_add_page_content(buf, len);
+  free(buf);
+
+  p->structure.in_content = 1;
+}
+
+static void
+structure_end_content (pdf_doc *p)
+{
+  graphics_mode();
+  pdf_doc_add_page_content(" EMC", 4);
+  p->structure.in_content = 0;
+}
+
+int
+pdf_doc_begin_struct (const char *type, pdf_obj *attrs)
+{
+  pdf_doc    *p = &pdoc;
+  pdf_obj    *elem, *parent, *parent_ref;
+  const char *c;
+
+  if (p->pending_forms) {
+    dpx_warning("Structure elements can't be started inside of a form XObject.");
+    return -1;
+  }
+
+  if (!type[0]) {
+    dpx_warning("Empty structure type.");
+    return -1;
+  }
+  for (c = type; *c; c++) {
+    if (!isalnum((unsigned char) *c)) {
+      dpx_warning("Invalid structure type: %s", type);
+      return -1;
+    }
+  }
+
+  if (!p->structure.root) {
+    p->structure.root = pdf_new_dict();
+    pdf_add_dict(p->structure.root,
+                 pdf_new_name("Type"), pdf_new_name("StructTreeRoot"));
+    pdf_add_dict(p->structure.root, pdf_new_name("K"), pdf_new_array());
+    p->structure.root_ref    = pdf_ref_obj(p->structure.root);
+    p->structure.parent_tree = pdf_new_array();
+  }
+
+  if (p->structure.depth > 0) {
+    if (p->structure.in_content)
+      structure_end_content(p);
+    parent     = p->structure.stack[p->structure.depth - 1].dict;
+    parent_ref = p->structure.stack[p->structure.depth - 1].ref;
+  } else {
+    parent     = p->structure.root;
+    parent_ref = p->structure.root_ref;
+  }
+
+  elem = pdf_new_dict();
+  if (attrs)
+    pdf_merge_dict(elem, attrs);
+  pdf_add_dict(elem, pdf_new_name("Type"), pdf_new_name("StructElem"));
+  pdf_add_dict(elem, pdf_new_name("S"),    pdf_new_name(type));
+  pdf_add_dict(elem, pdf_new_name("P"),    pdf_link_obj(parent_ref));
+  pdf_add_dict(elem, pdf_new_name("K"),    pdf_new_array());
+
+  if (p->structure.depth >= p->structure.max_depth) {
+    p->structure.max_depth += PDFDOC_STRUCT_ALLOC_SIZE;
+    p->structure.stack = RENEW(p->structure.stack,
+                               p->structure.max_depth,
+                               pdf_struct_elem);
+  }
+  p->structure.stack[p->structure.depth].dict = elem;
+  p->structure.stack[p->structure.depth].ref  = pdf_ref_obj(elem);
+  pdf_add_array(pdf_lookup_dict(parent, "K"),
+                pdf_link_obj(p->structure.stack[p->structure.depth].ref));
+  p->structure.depth++;
+
+  structure_begin_content(p);
+
+  return 0;
+}
+
+int
+pdf_doc_end_struct (void)
+{
+  pdf_doc *p = &pdoc;
+
+  if (p->pending_forms) {
+    dpx_warning("Structure elements can't be ended inside of a form XObject.");
+    return -1;
+  }
+
+  if (p->structure.depth == 0) {
+    dpx_warning("No structure element to end.");
+    return -1;
+  }
+
+  if (p->structure.in_content)
+    structure_end_content(p);
+
+  p->structure.depth--;
+  pdf_release_obj(p->structure.stack[p->structure.depth].ref);
+  pdf_release_obj(p->structure.stack[p->structure.depth].dict);
+
+  if (p->structure.depth > 0)
+    structure_begin_content(p);
+
+  return 0;
+}
+
+/* Structure elements may span pages: their marked content is closed at the
+ * end of each page and reopened at the beginning of the next one.
+ */
+static void
+pdf_doc_structure_bop (pdf_doc *p)
+{
+  if (p->structure.depth > 0)
+    structure_begin_content(p);
+}
+
+static void
+pdf_doc_structure_eop (pdf_doc *p)
+{
+  pdf_obj *page;
+
+  if (p->structure.in_content)
+    structure_end_content(p);
+
+  if (p->structure.page_mcids) {
+    page = pdf_doc_get_dictionary("@THISPAGE");
+    pdf_add_dict(page,
+                 pdf_new_name("StructParents"),
+                 pdf_new_number(p->structure.next_key));
+    pdf_add_dict(page, pdf_new_name("Tabs"), pdf_new_name("S"));
+
+    pdf_add_array(p->structure.parent_tree,
+                  pdf_new_number(p->structure.next_key));
+    pdf_add_array(p->structure.parent_tree,
+                  pdf_ref_obj(p->structure.page_mcids));
+    pdf_release_obj(p->structure.page_mcids);
+    p->structure.page_mcids = NULL;
+    p->structure.next_key++;
+  }
+}
+
+static void
+pdf_doc_close_structure (pdf_doc *p)
+{
+  pdf_obj *tree, *mark_info;
+
+  if (p->structure.depth > 0) {
+    dpx_warning("%u structure element(s) not ended.", p->structure.depth);
+    while (p->structure.depth > 0) {
+      p->structure.depth--;
+      pdf_release_obj(p->structure.stack[p->structure.depth].ref);
+      pdf_release_obj(p->structure.stack[p->structure.depth].dict);
+    }
+  }
+  p->structure.stack = mfree(p->structure.stack);
+  p->structure.max_depth = 0;
+
+  if (p->structure.root) {
+    tree = pdf_new_dict();
+    pdf_add_dict(tree, pdf_new_name("Nums"), p->structure.parent_tree);
+    pdf_add_dict(p->structure.root,
+                 pdf_new_name("ParentTree"), pdf_ref_obj(tree));
+    pdf_release_obj(tree);
+    pdf_add_dict(p->structure.root,
+                 pdf_new_name("ParentTreeNextKey"),
+                 pdf_new_number(p->structure.next_key));
+
+    pdf_add_dict(p->root.dict,
+                 pdf_new_name("StructTreeRoot"), p->structure.root_ref);
+    if (!pdf_lookup_dict(p->root.dict, "MarkInfo")) {
+      mark_info = pdf_new_dict();
+      pdf_add_dict(mark_info, pdf_new_name("Marked"), pdf_new_boolean(1));
+      pdf_add_dict(p->root.dict, pdf_new_name("MarkInfo"), mark_info);
+    }
+
+    pdf_release_obj(p->structure.root);
+    p->structure.root        = NULL;
+    p->structure.root_ref    = NULL;
+    p->structure.parent_tree = NULL;
+  }
+
+  return;
+}
+
 /* page_no = 0 for root page tree node. */
 void
 pdf_doc_set_mediabox (unsigned page_no, const pdf_rect *mediabox)
@@ -2407,6 +2972,7 @@ pdf_doc_begin_page (double scale, double x_origin, double y_origin)
   /* pdf_doc_new_page() allocates page content stream. */
   pdf_doc_new_page(p);
   pdf_dev_bop(&M);
+  pdf_doc_structure_bop(p);
 
   return;
 }
@@ -2416,6 +2982,7 @@ pdf_doc_end_page (void)
 {
   pdf_doc *p = &pdoc;
 
+  pdf_doc_structure_eop(p);
   pdf_dev_eop();
   doc_fill_page_background(p);
 
@@ -2467,6 +3034,9 @@ pdf_open_document (const char *filename,
   p->options.annot_grow.x = settings.annot_grow_amount.x;
   p->options.annot_grow.y = settings.annot_grow_amount.y;
   p->options.outline_open_depth = settings.outline_open_depth;
+  p->options.pdfa.enabled = settings.enable_pdfa;
+  p->options.pdfa.icc_profile = settings.pdfa_icc_profile;
+  p->options.pdfa.icc_profile_len = settings.pdfa_icc_profile_len;
 
   pdf_init_resources();
   pdf_init_colors();
@@ -2483,6 +3053,7 @@ pdf_open_document (const char *filename,
 
   pdf_doc_init_bookmarks(p, settings.outline_open_depth);
   pdf_doc_init_articles (p);
+  pdf_doc_init_structure(p);
   pdf_doc_init_names    (p, settings.check_gotos);
   pdf_doc_init_page_tree(p, settings.media_width, settings.media_height);
 
@@ -2527,6 +3098,7 @@ pdf_close_document (void)
    * Following things were kept around so user can add dictionary items.
    */
   pdf_doc_close_articles (p);
+  pdf_doc_close_structure(p);
   pdf_doc_close_names    (p);
   pdf_doc_close_bookmarks(p);
   pdf_doc_close_page_tree(p);
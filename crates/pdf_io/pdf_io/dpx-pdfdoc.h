@@ -75,6 +75,11 @@ struct pdf_setting
     int enable_manual_thumb;
     int    enable_encrypt;
     struct pdf_enc_setting encrypt;
+    /* Tectonic: PDF/A-2b output. The ICC profile becomes the document's
+     * sRGB output intent. */
+    int    enable_pdfa;
+    const unsigned char *pdfa_icc_profile;
+    size_t pdfa_icc_profile_len;
     struct pdf_dev_setting device;
     struct pdf_obj_setting object;
 };
@@ -120,6 +125,11 @@ pdf_obj *pdf_doc_ref_page(unsigned page_no);
 int      pdf_doc_add_names       (const char *category,
                                          const void *key, int keylen, pdf_obj *value);
 
+/* Tectonic: embed a file in the document, listed under the given name (a PDF
+ * text string) in the EmbeddedFiles name tree. */
+int      pdf_doc_add_attachment  (const unsigned char *name, size_t namelen,
+                                  const unsigned char *data, size_t length);
+
 void     pdf_doc_set_bop_content (const char *str, unsigned int length);
 void     pdf_doc_set_eop_content (const char *str, unsigned int length);
 
@@ -139,6 +149,10 @@ void     pdf_doc_add_bead      (const char *article_id,
                                        const char *bead_id,
                                        int page_no, const pdf_rect *rect);
 
+/* Logical structure (tagged PDF) */
+int      pdf_doc_begin_struct  (const char *type, pdf_obj *attrs);
+int      pdf_doc_end_struct    (void);
+
 /* Bookmarks */
 int      pdf_doc_bookmarks_up    (void);
 int      pdf_doc_bookmarks_down  (void);
@@ -512,6 +512,44 @@ try_load_ToUnicode_CMap (pdf_font *font)
   return  0;
 }
 
+int
+pdf_font_check_embedding (void)
+{
+  int font_id, count = 0;
+
+  for (font_id = 0; font_id < font_cache.count; font_id++) {
+    pdf_font *font = &font_cache.fonts[font_id];
+    int       embedded;
+
+    if ((font->flags & PDF_FONT_FLAG_IS_ALIAS) ||
+        (font->flags & PDF_FONT_FLAG_IS_REENCODE) ||
+        !font->reference) {
+      continue;
+    }
+
+    switch (font->subtype) {
+    case PDF_FONT_FONTTYPE_TYPE0:
+    case PDF_FONT_FONTTYPE_TYPE3:
+      /* Type0 fonts defer to their descendant; Type3 glyphs are content. */
+      continue;
+    case PDF_FONT_FONTTYPE_CIDTYPE0:
+    case PDF_FONT_FONTTYPE_CIDTYPE2:
+      embedded = font->cid.options.embed;
+      break;
+    default:
+      embedded = !(font->flags & PDF_FONT_FLAG_NOEMBED);
+    }
+
+    if (!embedded) {
+      ttstub_issue_error("PDF/A: font \"%s\" is not embedded",
+                         font->fontname ? font->fontname : font->filename);
+      count++;
+    }
+  }
+
+  return count;
+}
+
 void
 pdf_close_fonts (void)
 {
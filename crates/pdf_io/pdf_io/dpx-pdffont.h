@@ -132,6 +132,10 @@ typedef struct pdf_font pdf_font;
 void     pdf_init_fonts  (void);
 void     pdf_close_fonts (void);
 
+/* Tectonic: report fonts that will not be embedded, as PDF/A requires.
+ * Returns the number of such fonts. */
+int      pdf_font_check_embedding (void);
+
 /* tex_name is used when mrec is NULL.
  * font_scale (point size) used by PK font.
  * It might be necessary if dvipdfmx supports font format with
@@ -2221,6 +2221,19 @@ pdf_add_stream (pdf_obj *stream, const void *stream_data, int length)
     data->stream_length += length;
 }
 
+/* Tectonic: replace the contents of a stream, e.g. when an image is resampled */
+void
+pdf_stream_set_data (pdf_obj *stream, const void *stream_data, int length)
+{
+    pdf_stream *data;
+
+    TYPECHECK(stream, PDF_STREAM);
+
+    data = stream->data;
+    data->stream_length = 0;
+    pdf_add_stream(stream, stream_data, length);
+}
+
 static int
 filter_get_DecodeParms_FlateDecode (struct decode_parms *parms, pdf_obj *dict)
 {
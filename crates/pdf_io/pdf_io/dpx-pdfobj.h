@@ -153,6 +153,9 @@ pdf_obj    *pdf_new_stream        (int flags);
 void        pdf_add_stream        (pdf_obj *stream,
                                           const void *stream_data_ptr,
                                           int stream_data_len);
+void        pdf_stream_set_data   (pdf_obj *stream,
+                                          const void *stream_data_ptr,
+                                          int stream_data_len);
 int         pdf_concat_stream     (pdf_obj *dst, pdf_obj *src);
 pdf_obj    *pdf_stream_dict       (pdf_obj *stream);
 int         pdf_stream_length     (pdf_obj *stream);
@@ -23,6 +23,7 @@
 #include "dpx-pdfximage.h"
 
 #include <fcntl.h>
+#include <math.h>
 #include <stdbool.h>
 #include <stdio.h>
 #include <stdlib.h>
@@ -43,6 +44,7 @@
 #include "dpx-pdfnames.h"
 #include "dpx-pdfobj.h"
 #include "dpx-pngimage.h"
+#include "tectonic_pdf_io_bindings.h"
 
 static int check_for_ps (rust_input_handle_t handle);
 
@@ -84,6 +86,9 @@ struct pdf_ximage_
     pdf_obj     *reference;
     pdf_obj     *resource;
     int reserved;
+
+    /* Tectonic: the largest size at which the image is placed, in bp */
+    double placed_width, placed_height;
 };
 
 
@@ -91,12 +96,20 @@ struct pdf_ximage_
 struct opt_
 {
     char  *cmdtmpl;
+
+    /* Tectonic: raster image reduction */
+    double max_dpi;
+    int    jpeg_quality;
+    int    grayscale;
 };
 
 static struct opt_ _opts = {
-    NULL
+    NULL, 0.0, 0, 0
 };
 
+#define RASTER_OPTS_ACTIVE() \
+    (_opts.max_dpi > 0.0 || _opts.jpeg_quality > 0 || _opts.grayscale)
+
 struct ic_
 {
     int         count, capacity;
@@ -119,6 +132,7 @@ pdf_init_ximage_struct (pdf_ximage *I)
     I->reference = NULL;
     I->resource  = NULL;
     I->reserved = 0;
+    I->placed_width = I->placed_height = 0.0;
 
     I->attr.width = I->attr.height = 0;
     I->attr.xdensity = I->attr.ydensity = 1.0;
@@ -146,6 +160,92 @@ pdf_clean_ximage_struct (pdf_ximage *I)
 }
 
 
+/* Tectonic: reduce a raster image according to the options set with
+ * pdf_ximage_set_raster_options(). The image is downsampled to the maximum
+ * resolution at the largest size at which it's placed, converted to
+ * grayscale, and re-encoded if it's a JPEG. Images that we can't process
+ * faithfully -- masks, indexed or CMYK colors, unusual bit depths and filters
+ * -- are left alone. */
+static void
+reduce_raster_image (pdf_ximage *I)
+{
+    pdf_obj *stream = I->resource;
+    pdf_obj *dict = pdf_stream_dict(stream);
+    pdf_obj *tmp;
+    int      jpeg = 0;
+    uint32_t max_width, max_height;
+    TransformedImage result;
+
+    tmp = pdf_lookup_dict(dict, "BitsPerComponent");
+    if (!tmp || !PDF_OBJ_NUMBERTYPE(tmp) || pdf_number_value(tmp) != 8)
+        return;
+    if (pdf_lookup_dict(dict, "ImageMask") || pdf_lookup_dict(dict, "Mask") ||
+        pdf_lookup_dict(dict, "Decode") || pdf_lookup_dict(dict, "DecodeParms"))
+        return;
+
+    tmp = pdf_lookup_dict(dict, "Filter");
+    if (tmp) {
+        if (!PDF_OBJ_NAMETYPE(tmp) || strcmp(pdf_name_value(tmp), "DCTDecode"))
+            return;
+        jpeg = 1;
+    }
+
+    /* ICC-based color spaces are stored as references. */
+    tmp = pdf_lookup_dict(dict, "ColorSpace");
+    if (!tmp)
+        return;
+    if (PDF_OBJ_NAMETYPE(tmp)) {
+        if (strcmp(pdf_name_value(tmp), "DeviceGray") &&
+            strcmp(pdf_name_value(tmp), "DeviceRGB"))
+            return;
+    } else if (PDF_OBJ_ARRAYTYPE(tmp)) {
+        pdf_obj *family = pdf_get_array(tmp, 0);
+
+        if (!family || !PDF_OBJ_NAMETYPE(family) ||
+            (strcmp(pdf_name_value(family), "CalGray") &&
+             strcmp(pdf_name_value(family), "CalRGB") &&
+             strcmp(pdf_name_value(family), "ICCBased")))
+            return;
+    } else if (!PDF_OBJ_INDIRECTTYPE(tmp)) {
+        return;
+    }
+
+    max_width = I->attr.width;
+    max_height = I->attr.height;
+    if (_opts.max_dpi > 0.0 && I->placed_width > 0.0 && I->placed_height > 0.0) {
+        double w = ceil(I->placed_width * _opts.max_dpi / 72.0);
+        double h = ceil(I->placed_height * _opts.max_dpi / 72.0);
+
+        if (w < max_width)
+            max_width = w;
+        if (h < max_height)
+            max_height = h;
+    }
+
+    if (tectonic_pdf_io_transform_image(pdf_stream_dataptr(stream),
+                                        pdf_stream_length(stream), jpeg,
+                                        I->attr.width, I->attr.height,
+                                        max_width, max_height, _opts.grayscale,
+                                        _opts.jpeg_quality, &result))
+        return;
+
+    if (dpx_conf.verbose_level > 0)
+        dpx_message("pdf_image>> reduced image \"%s\" from %dx%d to %ux%u\n",
+                    I->filename ? I->filename : "", I->attr.width,
+                    I->attr.height, result.width, result.height);
+
+    pdf_stream_set_data(stream, result.data, result.len);
+    pdf_add_dict(dict, pdf_new_name("Width"), pdf_new_number(result.width));
+    pdf_add_dict(dict, pdf_new_name("Height"), pdf_new_number(result.height));
+    if (result.converted_to_gray)
+        pdf_add_dict(dict, pdf_new_name("ColorSpace"), pdf_new_name("DeviceGray"));
+    if (!jpeg && result.height > 64)
+        pdf_stream_set_predictor(stream, 15, result.width, 8, result.components);
+
+    tectonic_pdf_io_free_image(&result);
+}
+
+
 void
 pdf_init_images (void)
 {
@@ -163,6 +263,8 @@ pdf_close_images (void)
         int  i;
         for (i = 0; i < ic->count; i++) {
             pdf_ximage *I = ic->ximages+i;
+            if (I->resource && I->subtype == PDF_XOBJECT_TYPE_IMAGE)
+                reduce_raster_image(I);
             if (I->attr.tempfile) {
                 /*
                  * It is important to remove temporary files at the end because
@@ -186,6 +288,9 @@ pdf_close_images (void)
     }
 
     _opts.cmdtmpl = mfree(_opts.cmdtmpl);
+    _opts.max_dpi = 0.0;
+    _opts.jpeg_quality = 0;
+    _opts.grayscale = 0;
 }
 
 static int
@@ -554,8 +659,14 @@ pdf_ximage_set_image (pdf_ximage *I, void *image_info, pdf_obj *resource)
         I->reference = pdf_ref_obj(resource);
     }
 
-    pdf_release_obj(resource); /* Caller don't know we are using reference. */
-    I->resource  = NULL;
+    if (RASTER_OPTS_ACTIVE()) {
+        /* Tectonic: hold on to the image so that it can be reduced once we
+         * know how large it's placed; see pdf_close_images(). */
+        I->resource = resource;
+    } else {
+        pdf_release_obj(resource); /* Caller don't know we are using reference. */
+        I->resource  = NULL;
+    }
 }
 
 void
@@ -747,6 +858,38 @@ pdf_ximage_get_subtype (int id)
     return I->subtype;
 }
 
+/* Tectonic: record the transformation with which an image is placed, so that
+ * it can be downsampled to the resolution needed by its largest placement. */
+void
+pdf_ximage_note_placement (int id, const pdf_tmatrix *M)
+{
+    struct ic_ *ic = &_ic;
+    pdf_ximage *I;
+    double      width, height;
+
+    CHECK_ID(ic, id);
+
+    I = GET_IMAGE(ic, id);
+
+    /* Image XObjects occupy the unit square. */
+    width  = sqrt(M->a * M->a + M->b * M->b);
+    height = sqrt(M->c * M->c + M->d * M->d);
+    if (width > I->placed_width)
+        I->placed_width = width;
+    if (height > I->placed_height)
+        I->placed_height = height;
+}
+
+/* Tectonic: options for reducing raster images. A max_dpi or jpeg_quality of
+ * zero leaves the corresponding property alone. */
+void
+pdf_ximage_set_raster_options (double max_dpi, int jpeg_quality, int grayscale)
+{
+    _opts.max_dpi = max_dpi;
+    _opts.jpeg_quality = jpeg_quality;
+    _opts.grayscale = grayscale;
+}
+
 void
 pdf_ximage_set_attr (int id, int width, int height, double xdensity, double ydensity, double llx, double lly, double urx, double ury)
 {
@@ -91,6 +91,10 @@ pdf_ximage_scale_image (int            id,
                         transform_info *p  /* arg */
                        );
 
+/* Tectonic: raster image reduction */
+void pdf_ximage_set_raster_options (double max_dpi, int jpeg_quality, int grayscale);
+void pdf_ximage_note_placement (int xobj_id, const pdf_tmatrix *M);
+
 /* from spc_pdfm.c */
 int      pdf_ximage_get_subtype    (int xobj_id);
 void
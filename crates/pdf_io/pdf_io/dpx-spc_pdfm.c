@@ -102,7 +102,8 @@ spc_handler_pdfm__init (void *dp)
   static const char *default_taintkeys[] = {
     "Title",   "Author",   "Subject", "Keywords",
     "Creator", "Producer", "Contents", "Subj",
-    "TU",      "T",        "TM",        NULL /* EOD */
+    "TU",      "T",        "TM",        "Alt",
+    "ActualText", "E",     NULL /* EOD */
   };
   int  i;
 
@@ -2179,6 +2180,46 @@ spc_handler_pdfm_exgstate (struct spc_env *spe, struct spc_arg *args)
   return 0;
 }
 
+/* Tagged PDF: pdf:bstruct /Type <<attributes>> ... pdf:estruct */
+static int
+spc_handler_pdfm_bstruct (struct spc_env *spe, struct spc_arg *args)
+{
+  struct spc_pdf_ *sd = &_pdf_stat;
+  pdf_obj *type, *attrs = NULL;
+  int      error;
+
+  skip_white(&args->curptr, args->endptr);
+  type = parse_pdf_name(&args->curptr, args->endptr);
+  if (!type) {
+    spc_warn(spe, "Structure type expected but not found.");
+    return  -1;
+  }
+
+  skip_white(&args->curptr, args->endptr);
+  if (args->curptr < args->endptr) {
+    attrs = parse_pdf_dict_with_tounicode(&args->curptr, args->endptr, &sd->cd);
+    if (!attrs) {
+      spc_warn(spe, "Dictionary object expected but not found.");
+      pdf_release_obj(type);
+      return  -1;
+    }
+  }
+
+  error = pdf_doc_begin_struct(pdf_name_value(type), attrs);
+  pdf_release_obj(type);
+  if (attrs)
+    pdf_release_obj(attrs);
+
+  return error;
+}
+
+static int
+spc_handler_pdfm_estruct (struct spc_env *spe, struct spc_arg *args)
+{
+  skip_white(&args->curptr, args->endptr);
+  return pdf_doc_end_struct();
+}
+
 static int
 spc_handler_pdft_compat_page (struct spc_env *spe, struct spc_arg *args)
 {
@@ -2313,6 +2354,9 @@ static struct spc_handler pdfm_handlers[] = {
 
   {"bxgstate", spc_handler_pdfm_bxgstate},
   {"exgstate", spc_handler_pdfm_exgstate},
+
+  {"bstruct", spc_handler_pdfm_bstruct},
+  {"estruct", spc_handler_pdfm_estruct},
 };
 
 static struct spc_handler pdft_compat_handlers[] = {
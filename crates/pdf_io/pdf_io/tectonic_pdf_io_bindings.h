@@ -0,0 +1,87 @@
+#ifndef TECTONIC_PDF_IO_BINDINGS_H
+#define TECTONIC_PDF_IO_BINDINGS_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Image data returned by [`tectonic_pdf_io_transform_image`].
+ */
+typedef struct {
+  /**
+   * The new image data. If the input was a JPEG, this is a new JPEG stream;
+   * otherwise it is raw 8-bit samples.
+   */
+  uint8_t *data;
+  /**
+   * The length of the data, in bytes.
+   */
+  uintptr_t len;
+  /**
+   * The new width of the image, in pixels.
+   */
+  uint32_t width;
+  /**
+   * The new height of the image, in pixels.
+   */
+  uint32_t height;
+  /**
+   * The number of color components in the new image, either 1 or 3.
+   */
+  uint32_t components;
+  /**
+   * Whether the image was converted from color to grayscale, in which case
+   * its color space must be changed.
+   */
+  bool converted_to_gray;
+} TransformedImage;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Reduce an image to at most the given dimensions, optionally converting it
+ * to grayscale and, if it is a JPEG, re-encoding it at the given quality.
+ *
+ * If `jpeg` is true, `data` is a complete JPEG stream and `width` and
+ * `height` are ignored. Otherwise, `data` contains raw 8-bit samples with
+ * either one or three components. A `jpeg_quality` of zero means that JPEGs
+ * are only re-encoded if they must be resized or converted.
+ *
+ * Returns zero and fills in `result` if the image was transformed. Returns
+ * nonzero if the image is in an unsupported format or doesn't need to be
+ * changed, in which case `result` is untouched. A successful result must be
+ * freed with [`tectonic_pdf_io_free_image`].
+ *
+ * # Safety
+ *
+ * This is a C API function, so it is unsafe.
+ */
+int tectonic_pdf_io_transform_image(const uint8_t *data,
+                                    uintptr_t len,
+                                    bool jpeg,
+                                    uint32_t width,
+                                    uint32_t height,
+                                    uint32_t max_width,
+                                    uint32_t max_height,
+                                    bool grayscale,
+                                    uint8_t jpeg_quality,
+                                    TransformedImage *result);
+
+/**
+ * Free image data returned by [`tectonic_pdf_io_transform_image`].
+ *
+ * # Safety
+ *
+ * This is a C API function, so it is unsafe.
+ */
+void tectonic_pdf_io_free_image(TransformedImage *image);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* TECTONIC_PDF_IO_BINDINGS_H */
@@ -1,13 +1,16 @@
 // Copyright 2021 the Tectonic Project
 // Licensed under the MIT License.
 
-//! This crate contains no Rust code, for now. It exists to export a *C* API for
-//! I/O on PDF, XDV, and graphics files, within the Cargo build framework used
-//! by [Tectonic]. Ideally, it will migrate to become a cbindgen C API to a Rust
-//! implementation.
+//! This crate exists to export a *C* API for I/O on PDF, XDV, and graphics
+//! files, within the Cargo build framework used by [Tectonic]. Ideally, it will
+//! migrate to become a cbindgen C API to a Rust implementation. For now, the
+//! only Rust code is the raster image processing in the [`raster`] module,
+//! which the C code reaches through a cbindgen header.
 //!
 //! [Tectonic]: https://tectonic-typesetting.github.io/
 
+pub mod raster;
+
 /// Import things from our bridge crates to ensure that we actually link with
 /// them.
 mod linkage {
@@ -0,0 +1,397 @@
+// Copyright 2025 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Resampling and re-encoding of raster image data.
+//!
+//! These functions are used by the C code to reduce the resolution, color
+//! depth, and JPEG quality of embedded images when the output PDF is meant to
+//! be a lightweight copy.
+//!
+//! If you change the interfaces here, rerun cbindgen as described in the README!
+
+use jpeg_decoder::PixelFormat;
+use jpeg_encoder::{ColorType, Encoder};
+use std::{ffi::c_int, ptr, slice};
+
+/// The JPEG quality used when an image must be re-encoded but no quality was
+/// specified.
+const DEFAULT_JPEG_QUALITY: u8 = 90;
+
+/// Image data returned by [`tectonic_pdf_io_transform_image`].
+#[derive(Debug)]
+#[repr(C)]
+pub struct TransformedImage {
+    /// The new image data. If the input was a JPEG, this is a new JPEG stream;
+    /// otherwise it is raw 8-bit samples.
+    pub data: *mut u8,
+
+    /// The length of the data, in bytes.
+    pub len: usize,
+
+    /// The new width of the image, in pixels.
+    pub width: u32,
+
+    /// The new height of the image, in pixels.
+    pub height: u32,
+
+    /// The number of color components in the new image, either 1 or 3.
+    pub components: u32,
+
+    /// Whether the image was converted from color to grayscale, in which case
+    /// its color space must be changed.
+    pub converted_into_gray: bool,
+}
+
+/// Decoded image samples, eight bits per component.
+#[derive(Debug, PartialEq)]
+struct Samples {
+    data: Vec<u8>,
+    width: usize,
+    height: usize,
+    components: usize,
+}
+
+impl Samples {
+    /// Reduce the image to at most the given dimensions by averaging the
+    /// source pixels that fall within each destination pixel. Images are never
+    /// enlarged.
+    fn downsample(self, max_width: usize, max_height: usize) -> Samples {
+        let width = max_width.clamp(1, self.width);
+        let height = max_height.clamp(1, self.height);
+
+        if width == self.width && height == self.height {
+            return self;
+        }
+
+        let nc = self.components;
+        let mut data = Vec::with_capacity(width * height * nc);
+        let mut sums = vec![0u64; nc];
+
+        for y in 0..height {
+            let y0 = y * self.height / height;
+            let y1 = ((y + 1) * self.height / height).max(y0 + 1);
+
+            for x in 0..width {
+                let x0 = x * self.width / width;
+                let x1 = ((x + 1) * self.width / width).max(x0 + 1);
+
+                sums.iter_mut().for_each(|s| *s = 0);
+
+                for sy in y0..y1 {
+                    let row = &self.data[(sy * self.width + x0) * nc..(sy * self.width + x1) * nc];
+
+                    for pixel in row.chunks_exact(nc) {
+                        for (s, v) in sums.iter_mut().zip(pixel) {
+                            *s += u64::from(*v);
+                        }
+                    }
+                }
+
+                let n = ((y1 - y0) * (x1 - x0)) as u64;
+                data.extend(sums.iter().map(|s| ((s + n / 2) / n) as u8));
+            }
+        }
+
+        Samples {
+            data,
+            width,
+            height,
+            components: nc,
+        }
+    }
+
+    /// Convert an RGB image to grayscale using the Rec. 601 luma weights.
+    fn into_gray(self) -> Samples {
+        if self.components == 1 {
+            return self;
+        }
+
+        let data = self
+            .data
+            .chunks_exact(3)
+            .map(|p| {
+                ((299 * u32::from(p[0]) + 587 * u32::from(p[1]) + 114 * u32::from(p[2]) + 500)
+                    / 1000) as u8
+            })
+            .collect();
+
+        Samples {
+            data,
+            width: self.width,
+            height: self.height,
+            components: 1,
+        }
+    }
+}
+
+fn decode_jpeg(data: &[u8]) -> Option<Samples> {
+    let mut decoder = jpeg_decoder::Decoder::new(data);
+    let pixels = decoder.decode().ok()?;
+    let info = decoder.info()?;
+
+    let components = match info.pixel_format {
+        PixelFormat::L8 => 1,
+        PixelFormat::RGB24 => 3,
+        _ => return None,
+    };
+
+    Some(Samples {
+        data: pixels,
+        width: info.width.into(),
+        height: info.height.into(),
+        components,
+    })
+}
+
+fn encode_jpeg(samples: &Samples, quality: u8) -> Option<Vec<u8>> {
+    let color_type = if samples.components == 1 {
+        ColorType::Luma
+    } else {
+        ColorType::Rgb
+    };
+
+    let mut buf = Vec::new();
+    Encoder::new(&mut buf, quality)
+        .encode(
+            &samples.data,
+            samples.width.try_into().ok()?,
+            samples.height.try_into().ok()?,
+            color_type,
+        )
+        .ok()?;
+    Some(buf)
+}
+
+/// The core of [`tectonic_pdf_io_transform_image`], operating on safe types.
+/// Returns `None` if the image can't be handled or doesn't need to change.
+#[allow(clippy::too_many_arguments)]
+fn transform(
+    data: &[u8],
+    jpeg: bool,
+    width: usize,
+    height: usize,
+    max_width: usize,
+    max_height: usize,
+    grayscale: bool,
+    jpeg_quality: u8,
+) -> Option<(Vec<u8>, Samples, bool)> {
+    let samples = if jpeg {
+        decode_jpeg(data)?
+    } else {
+        let npix = width.checked_mul(height)?;
+
+        if npix == 0 || !data.len().is_multiple_of(npix) {
+            return None;
+        }
+
+        let components = data.len() / npix;
+
+        if components != 1 && components != 3 {
+            return None;
+        }
+
+        Samples {
+            data: data.to_owned(),
+            width,
+            height,
+            components,
+        }
+    };
+
+    let resize = max_width < samples.width || max_height < samples.height;
+    let convert = grayscale && samples.components == 3;
+    let reencode = jpeg && jpeg_quality > 0;
+
+    if !resize && !convert && !reencode {
+        return None;
+    }
+
+    let mut samples = samples.downsample(max_width, max_height);
+
+    if convert {
+        samples = samples.into_gray();
+    }
+
+    let out = if jpeg {
+        let quality = if jpeg_quality > 0 {
+            jpeg_quality.min(100)
+        } else {
+            DEFAULT_JPEG_QUALITY
+        };
+
+        let encoded = encode_jpeg(&samples, quality)?;
+
+        // Don't swap in a re-encoded JPEG that's bigger than the original
+        // unless we had to change the image itself.
+        if !resize && !convert && encoded.len() >= data.len() {
+            return None;
+        }
+
+        encoded
+    } else {
+        std::mem::take(&mut samples.data)
+    };
+
+    Some((out, samples, convert))
+}
+
+/// Reduce an image to at most the given dimensions, optionally converting it
+/// to grayscale and, if it is a JPEG, re-encoding it at the given quality.
+///
+/// If `jpeg` is true, `data` is a complete JPEG stream and `width` and
+/// `height` are ignored. Otherwise, `data` contains raw 8-bit samples with
+/// either one or three components. A `jpeg_quality` of zero means that JPEGs
+/// are only re-encoded if they must be resized or converted.
+///
+/// Returns zero and fills in `result` if the image was transformed. Returns
+/// nonzero if the image is in an unsupported format or doesn't need to be
+/// changed, in which case `result` is untouched. A successful result must be
+/// freed with [`tectonic_pdf_io_free_image`].
+///
+/// # Safety
+///
+/// This is a C API function, so it is unsafe.
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn tectonic_pdf_io_transform_image(
+    data: *const u8,
+    len: usize,
+    jpeg: bool,
+    width: u32,
+    height: u32,
+    max_width: u32,
+    max_height: u32,
+    grayscale: bool,
+    jpeg_quality: u8,
+    result: *mut TransformedImage,
+) -> c_int {
+    let data = slice::from_raw_parts(data, len);
+
+    let Some((out, samples, converted_into_gray)) = transform(
+        data,
+        jpeg,
+        width as usize,
+        height as usize,
+        max_width as usize,
+        max_height as usize,
+        grayscale,
+        jpeg_quality,
+    ) else {
+        return 1;
+    };
+
+    let len = out.len();
+    let out = Box::into_raw(out.into_boxed_slice());
+
+    *result = TransformedImage {
+        data: out.cast::<u8>(),
+        len,
+        width: samples.width as u32,
+        height: samples.height as u32,
+        components: samples.components as u32,
+        converted_into_gray,
+    };
+    0
+}
+
+/// Free image data returned by [`tectonic_pdf_io_transform_image`].
+///
+/// # Safety
+///
+/// This is a C API function, so it is unsafe.
+#[no_mangle]
+pub unsafe extern "C" fn tectonic_pdf_io_free_image(image: *mut TransformedImage) {
+    let image = &mut *image;
+
+    if !image.data.is_null() {
+        let _data = Box::from_raw(ptr::slice_from_raw_parts_mut(image.data, image.len));
+        // The box will be freed as we exit.
+    }
+
+    image.data = ptr::null_mut();
+    image.len = 0;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient(width: usize, height: usize, components: usize) -> Samples {
+        let data = (0..width * height * components)
+            .map(|i| (i % 251) as u8)
+            .collect();
+
+        Samples {
+            data,
+            width,
+            height,
+            components,
+        }
+    }
+
+    #[test]
+    fn downsample_averages() {
+        let samples = Samples {
+            data: vec![0, 100, 10, 20, 200, 50, 30, 40],
+            width: 4,
+            height: 2,
+            components: 1,
+        };
+
+        let reduced = samples.downsample(2, 1);
+        assert_eq!(reduced.data, vec![88, 25]);
+        assert_eq!((reduced.width, reduced.height), (2, 1));
+    }
+
+    #[test]
+    fn downsample_never_enlarges() {
+        let samples = gradient(5, 7, 3);
+        let reduced = gradient(5, 7, 3).downsample(50, 3);
+        assert_eq!((reduced.width, reduced.height), (5, 3));
+        assert_eq!(gradient(5, 7, 3).downsample(10, 10), samples);
+    }
+
+    #[test]
+    fn gray_conversion() {
+        let samples = Samples {
+            data: vec![255, 0, 0, 255, 255, 255],
+            width: 2,
+            height: 1,
+            components: 3,
+        };
+
+        let gray = samples.into_gray();
+        assert_eq!(gray.components, 1);
+        assert_eq!(gray.data, vec![76, 255]);
+    }
+
+    #[test]
+    fn raw_unchanged() {
+        let samples = gradient(8, 8, 3);
+        assert!(transform(&samples.data, false, 8, 8, 8, 8, false, 50).is_none());
+        assert!(transform(&samples.data, false, 8, 8, 4, 4, false, 0).is_some());
+
+        // Four components aren't handled.
+        let samples = gradient(8, 8, 4);
+        assert!(transform(&samples.data, false, 8, 8, 4, 4, false, 0).is_none());
+    }
+
+    #[test]
+    fn jpeg_round_trip() {
+        let samples = gradient(64, 48, 3);
+        let jpeg = encode_jpeg(&samples, 95).unwrap();
+
+        let (out, reduced, converted) = transform(&jpeg, true, 0, 0, 32, 24, true, 50).unwrap();
+        assert!(converted);
+        assert_eq!(
+            (reduced.width, reduced.height, reduced.components),
+            (32, 24, 1)
+        );
+
+        let decoded = decode_jpeg(&out).unwrap();
+        assert_eq!(
+            (decoded.width, decoded.height, decoded.components),
+            (32, 24, 1)
+        );
+    }
+}
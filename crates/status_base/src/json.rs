@@ -0,0 +1,164 @@
+// Copyright 2025 the Tectonic Project
+// Licensed under the MIT License.
+
+//! A status-reporting backend that prints messages as JSON.
+//!
+//! Each message is printed to the standard error stream as a JSON object on a
+//! line of its own, so that programs such as editors and CI systems can pick
+//! out problems in a document without parsing human-oriented text. The objects
+//! look like:
+//!
+//! ```json
+//! {"severity":"error","message":"Undefined control sequence.","file":"./main.tex","line":3,"causes":[]}
+//! ```
+//!
+//! The `severity` is one of `note`, `warning`, or `error`. The `file` and
+//! `line` are null if the message isn't about a particular place in an input
+//! file. The `causes` are the messages of the chain of errors that led to this
+//! one, if any.
+
+use std::{
+    fmt::{Arguments, Write as _},
+    io::{self, Write},
+};
+use tectonic_errors::Error;
+
+use super::{split_location, ChatterLevel, MessageKind, StatusBackend};
+
+/// A status-reporting backend that prints messages as newline-delimited JSON.
+#[derive(Clone, Debug, Default)]
+pub struct JsonStatusBackend {
+    chatter: ChatterLevel,
+}
+
+impl JsonStatusBackend {
+    /// Create a new backend with the specified chatter level.
+    ///
+    /// To use the default chatter level, you can also use [`Self::default`].
+    pub fn new(chatter: ChatterLevel) -> Self {
+        JsonStatusBackend { chatter }
+    }
+
+    fn emit(&mut self, kind: MessageKind, message: &str, causes: &[String]) {
+        let record = format_record(kind, message, causes);
+        let mut stderr = io::stderr().lock();
+        stderr
+            .write_all(record.as_bytes())
+            .and_then(|_| stderr.flush())
+            .expect("write to stderr failed");
+    }
+}
+
+impl StatusBackend for JsonStatusBackend {
+    fn report(&mut self, kind: MessageKind, args: Arguments, err: Option<&Error>) {
+        if self.chatter.suppress_message(kind) {
+            return;
+        }
+
+        let causes: Vec<String> = err
+            .map(|e| e.chain().map(|item| item.to_string()).collect())
+            .unwrap_or_default();
+        self.emit(kind, &args.to_string(), &causes);
+    }
+
+    fn report_error(&mut self, err: &Error) {
+        let mut chain = err.chain().map(|item| item.to_string());
+        let message = chain.next().unwrap_or_default();
+        let causes: Vec<String> = chain.collect();
+        self.emit(MessageKind::Error, &message, &causes);
+    }
+
+    // The engines report their errors and warnings with a location prefix
+    // that `format_record` picks out, so the diagnostics found in the TeX log
+    // afterwards would only repeat them. The log dumped after a failure
+    // likewise repeats the messages, so it's left out to keep the output
+    // parseable; use `--keep-logs` to get it.
+
+    fn dump_error_logs(&mut self, _output: &[u8]) {}
+}
+
+/// Format a message as a line of JSON.
+///
+/// Messages from the TeX engine begin with the place in the input that they
+/// concern, as `<file>:<line>: `. That's split out into the `file` and `line`
+/// fields.
+fn format_record(kind: MessageKind, message: &str, causes: &[String]) -> String {
+    let severity = match kind {
+        MessageKind::Note => "note",
+        MessageKind::Warning => "warning",
+        MessageKind::Error => "error",
+    };
+
+    let (location, message) = match split_location(message) {
+        Some((file, line, rest)) => (Some((file, line)), rest),
+        None => (None, message),
+    };
+
+    let mut record = format!("{{\"severity\":\"{severity}\",\"message\":");
+    write_string(&mut record, message.trim_end());
+
+    match location {
+        Some((file, line)) => {
+            record.push_str(",\"file\":");
+            write_string(&mut record, file);
+            write!(record, ",\"line\":{line}").unwrap();
+        }
+        None => record.push_str(",\"file\":null,\"line\":null"),
+    }
+
+    record.push_str(",\"causes\":[");
+
+    for (i, cause) in causes.iter().enumerate() {
+        if i > 0 {
+            record.push(',');
+        }
+
+        write_string(&mut record, cause);
+    }
+
+    record.push_str("]}\n");
+    record
+}
+
+/// Append a string to `buf` as a quoted JSON string.
+pub(crate) fn write_string(buf: &mut String, text: &str) {
+    buf.push('"');
+
+    for c in text.chars() {
+        match c {
+            '"' => buf.push_str("\\\""),
+            '\\' => buf.push_str("\\\\"),
+            '\n' => buf.push_str("\\n"),
+            '\r' => buf.push_str("\\r"),
+            '\t' => buf.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(buf, "\\u{:04x}", c as u32).unwrap(),
+            c => buf.push(c),
+        }
+    }
+
+    buf.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records() {
+        assert_eq!(
+            format_record(MessageKind::Note, "Running TeX ...", &[]),
+            "{\"severity\":\"note\",\"message\":\"Running TeX ...\",\
+             \"file\":null,\"line\":null,\"causes\":[]}\n"
+        );
+
+        assert_eq!(
+            format_record(
+                MessageKind::Error,
+                "./main.tex:3: Undefined control sequence.\nl.3 \\foo\n",
+                &["a \"b\"".to_owned(), "c\u{1}".to_owned()]
+            ),
+            "{\"severity\":\"error\",\"message\":\"Undefined control sequence.\\nl.3 \\\\foo\",\
+             \"file\":\"./main.tex\",\"line\":3,\"causes\":[\"a \\\"b\\\"\",\"c\\u0001\"]}\n"
+        );
+    }
+}
@@ -10,7 +10,11 @@
 use std::{cmp::Ordering, fmt::Arguments, result::Result as StdResult, str::FromStr};
 use tectonic_errors::Error;
 
+pub mod json;
+pub mod lsp;
 pub mod plain;
+pub mod snippet;
+pub mod tee;
 
 /// A kind of status message.
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
@@ -27,6 +31,149 @@ pub enum MessageKind {
     Error,
 }
 
+/// A message about a particular place in a source file, such as an error
+/// found by the TeX engine.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SourceDiagnostic {
+    /// The kind of message.
+    pub kind: MessageKind,
+
+    /// The name of the file in which the problem was found, if known.
+    pub file: Option<String>,
+
+    /// The line of the file on which the problem was found, counting from 1,
+    /// if known.
+    pub line: Option<u32>,
+
+    /// The text of the message.
+    pub message: String,
+
+    /// The lines of output that show where in the input the problem was
+    /// found, as printed by the engine.
+    pub context: Vec<String>,
+
+    /// The text of the line of the file on which the problem was found, if
+    /// known. The engines don't provide it, but the driver fills it in from
+    /// the file so that backends can show it.
+    pub source_line: Option<String>,
+}
+
+impl SourceDiagnostic {
+    /// Find the text of the problem in the line on which it was found, if
+    /// the context shows that. This returns the text of the line before the
+    /// problem, which starts with `...` if the start of the line was cut off,
+    /// and the text of the problem itself, which is empty if it's not known.
+    ///
+    /// The context splits the input line at the point that the engine had
+    /// read when it found the problem. TeX shows what it had read after
+    /// `l.<line>`, and the problem is the last token that it read. BibTeX
+    /// shows it after ` : `, and the problem is the character after it.
+    pub fn problem_text(&self) -> Option<(&str, &str)> {
+        let line = self.line?;
+        let mut context = self.context.iter();
+
+        while let Some(c) = context.next() {
+            if let Some(read) = c.strip_prefix(" : ") {
+                // The rest of the line is indented to follow what was read.
+                let unread = context
+                    .next()
+                    .and_then(|u| u.strip_prefix(" : "))
+                    .and_then(|u| u.get(read.len()..))
+                    .unwrap_or_default();
+                let end = unread
+                    .char_indices()
+                    .nth(1)
+                    .map_or(unread.len(), |(i, _)| i);
+                return Some((read, &unread[..end]));
+            }
+
+            if let Some(rest) = c.strip_prefix(&format!("l.{line}")) {
+                let read = match rest.strip_prefix(' ') {
+                    Some(read) => read,
+                    None if rest.is_empty() => rest,
+                    None => continue,
+                };
+
+                let token = last_token(read);
+                return Some((&read[..read.len() - token.len()], token));
+            }
+        }
+
+        None
+    }
+}
+
+/// A report on the progress of a long-running task, such as a run of an
+/// engine or the download of a file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Progress {
+    /// A short description of what's being done, such as "running TeX".
+    pub phase: String,
+
+    /// How much of the task is done, as a percentage from 0 to 100, if known.
+    pub percent: Option<f32>,
+
+    /// The file that's being worked on, if any.
+    pub file: Option<String>,
+
+    /// The page that's being worked on, counting from 1, if any.
+    pub page: Option<u32>,
+}
+
+impl Progress {
+    /// Create a report for the start of a task.
+    pub fn new(phase: impl Into<String>) -> Self {
+        Progress {
+            phase: phase.into(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Split the place in the input that a message concerns off of its start.
+///
+/// Messages from the TeX engine about a particular place in the input begin
+/// with it, as `<file>:<line>: `. If the message does, this returns the file,
+/// the line, and the rest of the message.
+pub fn split_location(message: &str) -> Option<(&str, u32, &str)> {
+    let first_line = message.lines().next()?;
+
+    for (i, _) in first_line.match_indices(": ") {
+        let Some((file, line)) = first_line[..i].rsplit_once(':') else {
+            continue;
+        };
+
+        if file.is_empty() || line.is_empty() || !line.bytes().all(|b| b.is_ascii_digit()) {
+            continue;
+        }
+
+        if let Ok(line) = line.parse() {
+            return Some((file, line, &message[i + 2..]));
+        }
+    }
+
+    None
+}
+
+/// Get the last token of some TeX input: a control sequence, or a character.
+fn last_token(text: &str) -> &str {
+    let trimmed = text.trim_end_matches(|c: char| c.is_ascii_alphabetic() || c == '@');
+
+    if trimmed.len() < text.len() && trimmed.ends_with('\\') {
+        return &text[trimmed.len() - 1..];
+    }
+
+    let mut chars = text.char_indices().rev();
+    let Some((last, _)) = chars.next() else {
+        return text;
+    };
+
+    match chars.next() {
+        Some((i, '\\')) => &text[i..],
+        _ => &text[last..],
+    }
+}
+
 /// A setting regarding which messages to display.
 #[repr(usize)]
 #[non_exhaustive]
@@ -119,6 +266,29 @@ pub trait StatusBackend {
         )
     }
 
+    /// Report a message about a particular place in a source file.
+    ///
+    /// This is used for the problems found by the TeX engine and BibTeX, which
+    /// are also reported as plain messages with `report()`, beforehand. It lets
+    /// backends that can show where a problem is do so, such as editor
+    /// integrations, or terminals with a [`snippet::SourceSnippet`]. The
+    /// default implementation does nothing.
+    fn report_diagnostic(&mut self, _diag: &SourceDiagnostic) {}
+
+    /// Report the progress of a long-running task.
+    ///
+    /// This may be called many times a second, with each report replacing the
+    /// one before, until [`Self::finish_progress`] is called. Backends that
+    /// show progress should take care not to slow things down. A task may
+    /// start another one, such as a download of a file that an engine needs,
+    /// in which case the reports about the inner task come in between those
+    /// about the outer one. The default implementation does nothing.
+    fn report_progress(&mut self, _progress: &Progress) {}
+
+    /// Report that the task whose progress was being reported is over. The
+    /// default implementation does nothing.
+    fn finish_progress(&mut self) {}
+
     /// This is used to print TeX engine logs after it encountered errors. This
     /// should print the provided output, which may span many lines, with some
     /// clear delineation.
@@ -177,3 +347,19 @@ impl StatusBackend for NoopStatusBackend {
     fn report(&mut self, _kind: MessageKind, _args: Arguments, _err: Option<&Error>) {}
     fn dump_error_logs(&mut self, _output: &[u8]) {}
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locations() {
+        assert_eq!(
+            split_location("C:/doc/main.tex:12: Overfull \\hbox"),
+            Some(("C:/doc/main.tex", 12, "Overfull \\hbox"))
+        );
+        assert_eq!(split_location("note: time: 12: x"), None);
+        assert_eq!(split_location("! Emergency stop."), None);
+        assert_eq!(split_location("a.tex:1:2: x"), Some(("a.tex:1", 2, "x")));
+    }
+}
@@ -0,0 +1,433 @@
+// Copyright 2025 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Converting diagnostics to the form used by the Language Server Protocol.
+//!
+//! Editors that speak the [Language Server Protocol][lsp] show problems in a
+//! document given as `Diagnostic` objects, grouped by the URI of the file that
+//! they concern in the parameters of `textDocument/publishDiagnostics`
+//! notifications. The types here mirror those structures, and
+//! [`LspStatusBackend`] collects them from the messages of a build, so that a
+//! language server such as [texlab] can run Tectonic and pass along what it
+//! finds.
+//!
+//! [lsp]: https://microsoft.github.io/language-server-protocol/
+//! [texlab]: https://github.com/latex-lsp/texlab
+
+use std::{
+    collections::BTreeMap,
+    fmt::{Arguments, Write as _},
+    path::{Component, Path, PathBuf},
+};
+use tectonic_errors::Error;
+
+use super::{
+    json::write_string, split_location, MessageKind, Progress, SourceDiagnostic, StatusBackend,
+};
+
+/// The value of the `source` field of the diagnostics that we create.
+const SOURCE: &str = "tectonic";
+
+/// A position in a text document: a line, counting from 0, and an offset
+/// within it, counted in UTF-16 code units as LSP does by default.
+#[derive(Clone, Copy, Debug, Default, Eq, Ord, PartialEq, PartialOrd)]
+pub struct Position {
+    /// The line, counting from 0.
+    pub line: u32,
+
+    /// The offset within the line, in UTF-16 code units.
+    pub character: u32,
+}
+
+/// A range in a text document. The end position is exclusive.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Range {
+    /// The start of the range.
+    pub start: Position,
+
+    /// The end of the range.
+    pub end: Position,
+}
+
+/// How serious a diagnostic is. The values are those used on the wire.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DiagnosticSeverity {
+    /// An error.
+    Error = 1,
+
+    /// A warning.
+    Warning = 2,
+
+    /// An informational message.
+    Information = 3,
+
+    /// A hint.
+    Hint = 4,
+}
+
+impl From<MessageKind> for DiagnosticSeverity {
+    fn from(kind: MessageKind) -> Self {
+        match kind {
+            MessageKind::Note => DiagnosticSeverity::Information,
+            MessageKind::Warning => DiagnosticSeverity::Warning,
+            MessageKind::Error => DiagnosticSeverity::Error,
+        }
+    }
+}
+
+/// A problem found in a document, as an LSP `Diagnostic`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Diagnostic {
+    /// The range of the text that the problem concerns.
+    pub range: Range,
+
+    /// How serious the problem is.
+    pub severity: DiagnosticSeverity,
+
+    /// The text of the message.
+    pub message: String,
+}
+
+impl Diagnostic {
+    /// Convert a diagnostic found by an engine.
+    ///
+    /// If the context of the diagnostic shows where in its line the problem
+    /// is, as described for [`SourceDiagnostic::problem_text`], the range
+    /// covers the text of the problem. Otherwise, it covers the whole line.
+    pub fn from_source(diag: &SourceDiagnostic) -> Self {
+        let line = diag.line.unwrap_or(1);
+
+        let range = match diag.problem_text() {
+            // A leading `...` means that the start of the line was cut off,
+            // so we don't know where the text is.
+            Some((before, problem)) if !before.starts_with("...") && !problem.is_empty() => {
+                let start = utf16_len(before);
+                Range {
+                    start: Position {
+                        line: line.saturating_sub(1),
+                        character: start,
+                    },
+                    end: Position {
+                        line: line.saturating_sub(1),
+                        character: start + utf16_len(problem),
+                    },
+                }
+            }
+            _ => whole_line(line),
+        };
+
+        Diagnostic {
+            range,
+            severity: diag.kind.into(),
+            message: diag.message.clone(),
+        }
+    }
+
+    /// Write this diagnostic as a JSON object.
+    fn write_json(&self, buf: &mut String) {
+        let Range { start, end } = self.range;
+        write!(
+            buf,
+            "{{\"range\":{{\"start\":{{\"line\":{},\"character\":{}}},\
+             \"end\":{{\"line\":{},\"character\":{}}}}},\"severity\":{},\"source\":",
+            start.line, start.character, end.line, end.character, self.severity as u8,
+        )
+        .unwrap();
+        write_string(buf, SOURCE);
+        buf.push_str(",\"message\":");
+        write_string(buf, &self.message);
+        buf.push('}');
+    }
+}
+
+/// The diagnostics for one file, as the parameters of an LSP
+/// `textDocument/publishDiagnostics` notification.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PublishDiagnosticsParams {
+    /// The `file:` URI of the file.
+    pub uri: String,
+
+    /// The diagnostics.
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl PublishDiagnosticsParams {
+    /// Express these parameters as JSON, on one line.
+    pub fn to_json(&self) -> String {
+        let mut buf = String::from("{\"uri\":");
+        write_string(&mut buf, &self.uri);
+        buf.push_str(",\"diagnostics\":[");
+
+        for (i, diag) in self.diagnostics.iter().enumerate() {
+            if i > 0 {
+                buf.push(',');
+            }
+
+            diag.write_json(&mut buf);
+        }
+
+        buf.push_str("]}");
+        buf
+    }
+}
+
+/// A status backend that collects the messages about places in the input as
+/// LSP diagnostics, passing all messages along to another backend.
+///
+/// The errors found by the TeX engine are taken from the diagnostics reported
+/// with [`StatusBackend::report_diagnostic`]. Warnings are taken from messages
+/// that begin with the place in the input that they concern, which the engine
+/// reports for things like overfull boxes.
+///
+/// The engine names files as it opened them, so relative names have to be
+/// resolved into paths. Each is looked for in the directories given to
+/// [`Self::new`], in order, and assumed to be in the first one if it isn't
+/// found in any of them. The directories should be the ones that the engine
+/// reads files from, such as the one containing the primary input.
+pub struct LspStatusBackend {
+    inner: Box<dyn StatusBackend>,
+    base_dirs: Vec<PathBuf>,
+    diagnostics: BTreeMap<String, Vec<Diagnostic>>,
+}
+
+impl LspStatusBackend {
+    /// Create a new backend that passes messages along to `inner`, and
+    /// resolves file names relative to `base_dirs`.
+    pub fn new(inner: Box<dyn StatusBackend>, base_dirs: Vec<PathBuf>) -> Self {
+        LspStatusBackend {
+            inner,
+            base_dirs,
+            diagnostics: BTreeMap::new(),
+        }
+    }
+
+    /// Get back the backend that messages are passed along to.
+    pub fn into_inner(self) -> Box<dyn StatusBackend> {
+        self.inner
+    }
+
+    /// Get the diagnostics collected so far, grouped by file, in order of
+    /// the files' URIs.
+    pub fn publish_params(&self) -> Vec<PublishDiagnosticsParams> {
+        self.diagnostics
+            .iter()
+            .map(|(uri, diagnostics)| PublishDiagnosticsParams {
+                uri: uri.clone(),
+                diagnostics: diagnostics.clone(),
+            })
+            .collect()
+    }
+
+    fn add(&mut self, file: &str, diag: Diagnostic) {
+        let uri = file_uri(&self.resolve(file));
+        let diagnostics = self.diagnostics.entry(uri).or_default();
+
+        if !diagnostics.contains(&diag) {
+            diagnostics.push(diag);
+        }
+    }
+
+    fn resolve(&self, file: &str) -> PathBuf {
+        let path = Path::new(file);
+
+        if path.is_absolute() {
+            return normalize(path);
+        }
+
+        self.base_dirs
+            .iter()
+            .map(|dir| normalize(&dir.join(path)))
+            .find(|p| p.is_file())
+            .or_else(|| self.base_dirs.first().map(|dir| normalize(&dir.join(path))))
+            .unwrap_or_else(|| normalize(path))
+    }
+}
+
+impl StatusBackend for LspStatusBackend {
+    fn report(&mut self, kind: MessageKind, args: Arguments, err: Option<&Error>) {
+        if kind == MessageKind::Warning {
+            let message = args.to_string();
+
+            if let Some((file, line, rest)) = split_location(&message) {
+                self.add(
+                    file,
+                    Diagnostic {
+                        range: whole_line(line),
+                        severity: kind.into(),
+                        message: rest.trim_end().to_owned(),
+                    },
+                );
+            }
+        }
+
+        self.inner.report(kind, args, err);
+    }
+
+    fn report_error(&mut self, err: &Error) {
+        self.inner.report_error(err);
+    }
+
+    fn note_highlighted(&mut self, before: &str, highlighted: &str, after: &str) {
+        self.inner.note_highlighted(before, highlighted, after);
+    }
+
+    fn report_diagnostic(&mut self, diag: &SourceDiagnostic) {
+        if let Some(file) = diag.file.as_ref() {
+            self.add(file, Diagnostic::from_source(diag));
+        }
+
+        self.inner.report_diagnostic(diag);
+    }
+
+    fn report_progress(&mut self, progress: &Progress) {
+        self.inner.report_progress(progress);
+    }
+
+    fn finish_progress(&mut self) {
+        self.inner.finish_progress();
+    }
+
+    fn dump_error_logs(&mut self, output: &[u8]) {
+        self.inner.dump_error_logs(output);
+    }
+}
+
+/// Get the `file:` URI of a path, which should be absolute.
+pub fn file_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    let text = path.to_string_lossy();
+
+    // Windows paths such as `C:\doc` become `file:///C:/doc`.
+    if !text.starts_with('/') {
+        uri.push('/');
+    }
+
+    for b in text.bytes() {
+        match b {
+            b'\\' if cfg!(windows) => uri.push('/'),
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' | b':' => {
+                uri.push(b as char)
+            }
+            _ => write!(uri, "%{b:02X}").unwrap(),
+        }
+    }
+
+    uri
+}
+
+/// Remove the `.` components of a path, which TeX puts in the names of
+/// files that it finds in the current directory.
+fn normalize(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|c| *c != Component::CurDir)
+        .collect()
+}
+
+fn whole_line(line: u32) -> Range {
+    let line = line.saturating_sub(1);
+    Range {
+        start: Position { line, character: 0 },
+        end: Position {
+            line: line + 1,
+            character: 0,
+        },
+    }
+}
+
+fn utf16_len(text: &str) -> u32 {
+    text.encode_utf16().count() as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NoopStatusBackend;
+
+    fn position(line: u32, character: u32) -> Position {
+        Position { line, character }
+    }
+
+    fn error(line: Option<u32>, context: &[&str]) -> SourceDiagnostic {
+        SourceDiagnostic {
+            kind: MessageKind::Error,
+            file: Some("./main.tex".to_owned()),
+            line,
+            message: "Undefined control sequence.".to_owned(),
+            context: context.iter().map(|s| s.to_string()).collect(),
+            source_line: None,
+        }
+    }
+
+    #[test]
+    fn ranges() {
+        let range = |context: &[&str]| Diagnostic::from_source(&error(Some(5), context)).range;
+
+        let r = range(&["l.5 Some \\foo", "             bar"]);
+        assert_eq!((r.start, r.end), (position(4, 5), position(4, 9)));
+
+        let r = range(&["<argument> \\foo", "l.5 \\emph{é\\$", "  x"]);
+        assert_eq!((r.start, r.end), (position(4, 7), position(4, 9)));
+
+        let r = range(&["l.5 $x^", ""]);
+        assert_eq!((r.start, r.end), (position(4, 2), position(4, 3)));
+
+        let r = range(&["l.5 ...long text \\foo", ""]);
+        assert_eq!((r.start, r.end), (position(4, 0), position(5, 0)));
+
+        let r = range(&["l.5", "\\foo"]);
+        assert_eq!((r.start, r.end), (position(4, 0), position(5, 0)));
+
+        let r = range(&["l.50 \\foo", ""]);
+        assert_eq!((r.start, r.end), (position(4, 0), position(5, 0)));
+    }
+
+    #[test]
+    fn uris() {
+        if cfg!(windows) {
+            assert_eq!(
+                file_uri(Path::new(r"C:\my doc\main.tex")),
+                "file:///C:/my%20doc/main.tex"
+            );
+        } else {
+            assert_eq!(
+                file_uri(Path::new("/my doc/naïve.tex")),
+                "file:///my%20doc/na%C3%AFve.tex"
+            );
+        }
+    }
+
+    #[test]
+    fn collection() {
+        let dir = std::env::temp_dir();
+        let mut status = LspStatusBackend::new(Box::new(NoopStatusBackend {}), vec![dir.clone()]);
+        let diag = error(Some(2), &["l.2 \\foo", ""]);
+
+        status.report(
+            MessageKind::Warning,
+            format_args!("./main.tex:7: Overfull \\hbox (3.0pt too wide) in paragraph\n"),
+            None,
+        );
+        status.report(MessageKind::Warning, format_args!("no location"), None);
+        status.report_diagnostic(&diag);
+        status.report_diagnostic(&diag);
+        status.report_diagnostic(&SourceDiagnostic { file: None, ..diag });
+
+        let params = status.publish_params();
+        assert_eq!(params.len(), 1);
+        assert_eq!(params[0].uri, file_uri(&dir.join("main.tex")));
+        assert_eq!(params[0].diagnostics.len(), 2);
+        assert_eq!(
+            params[0].to_json(),
+            format!(
+                "{{\"uri\":\"{}\",\"diagnostics\":[\
+                 {{\"range\":{{\"start\":{{\"line\":6,\"character\":0}},\
+                 \"end\":{{\"line\":7,\"character\":0}}}},\"severity\":2,\"source\":\"tectonic\",\
+                 \"message\":\"Overfull \\\\hbox (3.0pt too wide) in paragraph\"}},\
+                 {{\"range\":{{\"start\":{{\"line\":1,\"character\":0}},\
+                 \"end\":{{\"line\":1,\"character\":4}}}},\"severity\":1,\"source\":\"tectonic\",\
+                 \"message\":\"Undefined control sequence.\"}}]}}",
+                params[0].uri
+            )
+        );
+    }
+}
@@ -9,7 +9,7 @@ use std::{
 };
 use tectonic_errors::Error;
 
-use super::{ChatterLevel, MessageKind, StatusBackend};
+use super::{snippet::SourceSnippet, ChatterLevel, MessageKind, SourceDiagnostic, StatusBackend};
 
 /// A basic status-reporting backend that prints messages via stdio.
 #[derive(Clone, Debug, Default)]
@@ -83,6 +83,16 @@ impl StatusBackend for PlainStatusBackend {
         );
     }
 
+    fn report_diagnostic(&mut self, diag: &SourceDiagnostic) {
+        if self.chatter.suppress_message(diag.kind) {
+            return;
+        }
+
+        if let Some(snippet) = SourceSnippet::from_diagnostic(diag) {
+            eprint!("{snippet}");
+        }
+    }
+
     fn dump_error_logs(&mut self, output: &[u8]) {
         eprintln!(
             "==============================================================================="
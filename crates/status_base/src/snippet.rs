@@ -0,0 +1,215 @@
+// Copyright 2025 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Showing the source of a problem in a document.
+//!
+//! A [`SourceSnippet`] is an excerpt of the line of a file on which a problem
+//! was found, for printing under the message about it in the style of
+//! `rustc`:
+//!
+//! ```text
+//!  --> ./main.tex:3
+//!   |
+//! 3 | Some \foo bar
+//!   |      ^^^^ Undefined control sequence.
+//! ```
+//!
+//! The caret marks the text of the problem, as found by
+//! [`SourceDiagnostic::problem_text`].
+
+use std::fmt::{Display, Error as FmtError, Formatter};
+
+use super::{MessageKind, SourceDiagnostic};
+
+/// The parts of a snippet, which backends may style differently.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SnippetPart {
+    /// The line numbers, the margin beside them, and the arrow in front of
+    /// the location.
+    Gutter,
+
+    /// The location and the text of the source.
+    Text,
+
+    /// The caret under the problem and the message beside it.
+    Caret,
+}
+
+/// An excerpt of a source file showing where a problem was found.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SourceSnippet {
+    /// The kind of message about the problem.
+    pub kind: MessageKind,
+
+    /// The name of the file.
+    pub file: String,
+
+    /// The number of the line, counting from 1.
+    pub line: u32,
+
+    /// The text of the line.
+    pub text: String,
+
+    /// The range of characters in the line to mark with the caret, as a start
+    /// offset and a length, if it's known.
+    pub caret: Option<(usize, usize)>,
+
+    /// The message to print beside the caret.
+    pub message: String,
+}
+
+impl SourceSnippet {
+    /// Create a snippet for a diagnostic.
+    ///
+    /// Returns None if the diagnostic doesn't have a file, a line, and the
+    /// text of the line.
+    pub fn from_diagnostic(diag: &SourceDiagnostic) -> Option<Self> {
+        let file = diag.file.clone()?;
+        let line = diag.line?;
+        let text = diag.source_line.as_ref()?.trim_end().replace('\t', " ");
+
+        Some(SourceSnippet {
+            kind: diag.kind,
+            caret: diag
+                .problem_text()
+                .and_then(|(before, problem)| caret_range(&text, before, problem)),
+            file,
+            line,
+            text,
+            message: diag.message.lines().next().unwrap_or_default().to_owned(),
+        })
+    }
+
+    /// Produce the snippet as a series of pieces of text, each of which is
+    /// passed to `emit` with the part of the snippet that it belongs to.
+    ///
+    /// This is how backends can color the parts of the snippet. The
+    /// [`Display`] implementation gives the plain text.
+    pub fn render(&self, mut emit: impl FnMut(SnippetPart, &str)) {
+        let number = self.line.to_string();
+        let margin = " ".repeat(number.len());
+
+        emit(SnippetPart::Gutter, &format!("{margin}--> "));
+        emit(SnippetPart::Text, &format!("{}:{}\n", self.file, self.line));
+        emit(SnippetPart::Gutter, &format!("{margin} |\n{number} | "));
+        emit(SnippetPart::Text, &format!("{}\n", self.text));
+
+        if let Some((start, len)) = self.caret {
+            emit(SnippetPart::Gutter, &format!("{margin} | "));
+            emit(
+                SnippetPart::Caret,
+                &format!(
+                    "{}{} {}\n",
+                    " ".repeat(start),
+                    "^".repeat(len),
+                    self.message
+                ),
+            );
+        }
+    }
+}
+
+impl Display for SourceSnippet {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        let mut result = Ok(());
+        self.render(|_, text| result = result.and_then(|_| f.write_str(text)));
+        result
+    }
+}
+
+/// Find the range of characters of a line to mark, given the text of the
+/// problem and of the line before it, as found by
+/// [`SourceDiagnostic::problem_text`].
+fn caret_range(text: &str, before: &str, problem: &str) -> Option<(usize, usize)> {
+    if problem.is_empty() {
+        return None;
+    }
+
+    // If the start of the line was cut off, look for what's left of it.
+    let start = match before.strip_prefix("...") {
+        Some(tail) => {
+            let offset = text.find(&format!("{tail}{problem}"))?;
+            text[..offset + tail.len()].chars().count()
+        }
+        None => before.chars().count(),
+    };
+
+    let len = problem.chars().count();
+
+    if start + len > text.chars().count() {
+        return None;
+    }
+
+    Some((start, len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diagnostic(context: &[&str], source_line: &str) -> SourceDiagnostic {
+        SourceDiagnostic {
+            kind: MessageKind::Error,
+            file: Some("./main.tex".to_owned()),
+            line: Some(3),
+            message: "Undefined control sequence.".to_owned(),
+            context: context.iter().map(|s| s.to_string()).collect(),
+            source_line: Some(source_line.to_owned()),
+        }
+    }
+
+    #[test]
+    fn tex() {
+        let diag = diagnostic(&["l.3 Some \\foo", "              bar"], "Some \\foo bar\n");
+        assert_eq!(
+            SourceSnippet::from_diagnostic(&diag).unwrap().to_string(),
+            " --> ./main.tex:3\n  |\n3 | Some \\foo bar\n  |      ^^^^ Undefined control sequence.\n"
+        );
+
+        let diag = diagnostic(&["l.3 ...ne \\foo", " bar"], "A long line \\foo bar");
+        assert_eq!(
+            SourceSnippet::from_diagnostic(&diag).unwrap().caret,
+            Some((12, 4))
+        );
+
+        let diag = diagnostic(&["l.3 ", "\\foo"], "\\foo");
+        let snippet = SourceSnippet::from_diagnostic(&diag).unwrap();
+        assert_eq!(snippet.caret, None);
+        assert_eq!(snippet.to_string(), " --> ./main.tex:3\n  |\n3 | \\foo\n");
+    }
+
+    #[test]
+    fn bibtex() {
+        let diag = diagnostic(
+            &[
+                " :   title = {Brown's Book} ",
+                " :                          extra",
+            ],
+            "  title = {Brown's Book} extra {text, here},",
+        );
+        assert_eq!(
+            SourceSnippet::from_diagnostic(&diag).unwrap().caret,
+            Some((25, 1))
+        );
+
+        let diag = diagnostic(
+            &[" : \\citation{Book01,", " :                  }"],
+            "\\citation{Book01,}",
+        );
+        assert_eq!(
+            SourceSnippet::from_diagnostic(&diag).unwrap().caret,
+            Some((17, 1))
+        );
+    }
+
+    #[test]
+    fn incomplete() {
+        let diag = diagnostic(&["l.3 \\foo", ""], "\\foo");
+        assert!(SourceSnippet::from_diagnostic(&SourceDiagnostic {
+            source_line: None,
+            ..diag.clone()
+        })
+        .is_none());
+        assert!(SourceSnippet::from_diagnostic(&SourceDiagnostic { line: None, ..diag }).is_none());
+    }
+}
@@ -0,0 +1,179 @@
+// Copyright 2025 the Tectonic Project
+// Licensed under the MIT License.
+
+//! A status-reporting backend that also writes the messages to a log file.
+//!
+//! [`TeeStatusBackend`] passes everything on to another backend, and writes
+//! a plain-text copy of the messages to a file as well, in the same form as
+//! the [`crate::plain::PlainStatusBackend`] prints them. This gives one build
+//! log per run, separate from TeX's own `.log` files, that CI systems can keep
+//! as an artifact. Messages are written whatever the chatter level of the
+//! other backend, so that the log is complete even if the terminal is kept
+//! quiet.
+
+use std::{
+    fmt::Arguments,
+    io::{self, Write},
+};
+use tectonic_errors::Error;
+
+use super::{
+    snippet::SourceSnippet, tt_warning, MessageKind, Progress, SourceDiagnostic, StatusBackend,
+};
+
+/// A status backend that writes a copy of the messages of another one to a
+/// log.
+pub struct TeeStatusBackend<W: Write> {
+    inner: Box<dyn StatusBackend>,
+    log: W,
+
+    /// Whether writing to the log has failed, after which it's left alone.
+    failed: bool,
+}
+
+impl<W: Write> TeeStatusBackend<W> {
+    /// Create a backend that passes messages on to `inner`, and writes them to
+    /// `log` too.
+    pub fn new(inner: Box<dyn StatusBackend>, log: W) -> Self {
+        TeeStatusBackend {
+            inner,
+            log,
+            failed: false,
+        }
+    }
+
+    /// Give back the wrapped backend and the log.
+    pub fn into_inner(self) -> (Box<dyn StatusBackend>, W) {
+        (self.inner, self.log)
+    }
+
+    /// Write to the log, flushing it straight away so that nothing is lost if
+    /// the program exits abruptly. If that fails, the problem is reported
+    /// once through the wrapped backend.
+    fn write_log(&mut self, write: impl FnOnce(&mut W) -> io::Result<()>) {
+        if self.failed {
+            return;
+        }
+
+        if let Err(e) = write(&mut self.log).and_then(|_| self.log.flush()) {
+            self.failed = true;
+            tt_warning!(self.inner, "couldn't write to the build log"; e.into());
+        }
+    }
+}
+
+impl<W: Write> StatusBackend for TeeStatusBackend<W> {
+    fn report(&mut self, kind: MessageKind, args: Arguments, err: Option<&Error>) {
+        let prefix = match kind {
+            MessageKind::Note => "note:",
+            MessageKind::Warning => "warning:",
+            MessageKind::Error => "error:",
+        };
+
+        self.write_log(|log| {
+            writeln!(log, "{prefix} {args}")?;
+
+            if let Some(e) = err {
+                for item in e.chain() {
+                    writeln!(log, "caused by: {item}")?;
+                }
+            }
+
+            Ok(())
+        });
+
+        self.inner.report(kind, args, err);
+    }
+
+    fn report_error(&mut self, err: &Error) {
+        self.write_log(|log| {
+            let mut prefix = "error";
+
+            for item in err.chain() {
+                writeln!(log, "{prefix}: {item}")?;
+                prefix = "caused by";
+            }
+
+            Ok(())
+        });
+
+        self.inner.report_error(err);
+    }
+
+    fn note_highlighted(&mut self, before: &str, highlighted: &str, after: &str) {
+        self.write_log(|log| writeln!(log, "note: {before}{highlighted}{after}"));
+        self.inner.note_highlighted(before, highlighted, after);
+    }
+
+    fn report_diagnostic(&mut self, diag: &SourceDiagnostic) {
+        if let Some(snippet) = SourceSnippet::from_diagnostic(diag) {
+            self.write_log(|log| write!(log, "{snippet}"));
+        }
+
+        self.inner.report_diagnostic(diag);
+    }
+
+    fn report_progress(&mut self, progress: &Progress) {
+        self.inner.report_progress(progress);
+    }
+
+    fn finish_progress(&mut self) {
+        self.inner.finish_progress();
+    }
+
+    fn dump_error_logs(&mut self, output: &[u8]) {
+        self.write_log(|log| {
+            writeln!(
+                log,
+                "==============================================================================="
+            )?;
+            log.write_all(output)?;
+            writeln!(
+                log,
+                "==============================================================================="
+            )
+        });
+
+        self.inner.dump_error_logs(output);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{tt_error, tt_note, ChatterLevel, NoopStatusBackend};
+
+    #[test]
+    fn log() {
+        let mut tee = TeeStatusBackend::new(Box::new(NoopStatusBackend {}), Vec::new());
+
+        tt_note!(tee, "Running TeX ...");
+        tt_warning!(tee, "main.tex:3: Overfull \\hbox");
+        tee.note_highlighted("Writing ", "`main.pdf`", " (12 bytes)");
+        tt_error!(tee, "the build failed"; tectonic_errors::anyhow::anyhow!("out of fonts"));
+        tee.dump_error_logs(b"! Emergency stop.\n");
+
+        let (_, log) = tee.into_inner();
+        assert_eq!(
+            String::from_utf8(log).unwrap(),
+            "note: Running TeX ...\n\
+             warning: main.tex:3: Overfull \\hbox\n\
+             note: Writing `main.pdf` (12 bytes)\n\
+             error: the build failed\n\
+             caused by: out of fonts\n\
+             ===============================================================================\n\
+             ! Emergency stop.\n\
+             ===============================================================================\n"
+        );
+    }
+
+    #[test]
+    fn whatever_the_chatter() {
+        let inner = crate::plain::PlainStatusBackend::new(ChatterLevel::Minimal);
+        let mut tee = TeeStatusBackend::new(Box::new(inner), Vec::new());
+        tt_note!(tee, "quiet");
+
+        let (_, log) = tee.into_inner();
+        assert_eq!(log, b"note: quiet\n");
+    }
+}
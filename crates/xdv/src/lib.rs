@@ -1,7 +1,8 @@
 // Copyright 2018 the Tectonic Project
 // Licensed under the MIT License.
 
-//! A decoder for the XDV and SPX file formats used by Tectonic and XeTeX.
+//! A decoder and encoder for the XDV and SPX file formats used by Tectonic and
+//! XeTeX.
 //!
 //! Both of these file formats are derived from the venerable "device
 //! independent" (DVI) format used by TeX. The XDV format (name presumably
@@ -10,6 +11,12 @@
 //! ("semantically-paginated XDV") is essentially the same as XDV, but
 //! expresses output that is not paginated for print — this is what Tectonic
 //! uses to produce its HTML output.
+//!
+//! Files are decoded with an [`XdvParser`], which delivers events to an
+//! implementation of [`XdvEvents`], and can be synthesized with an
+//! [`XdvWriter`]. The [`TextExtractor`] event handler recovers the plain text
+//! of a document, and [`LinkSpecial`] and [`LinkCollector`] make sense of its
+//! hyperlinks and annotations.
 
 use byteorder::{BigEndian, ByteOrder};
 use std::{
@@ -19,6 +26,14 @@ use std::{
     marker::PhantomData,
 };
 
+mod links;
+mod text;
+mod writer;
+
+pub use links::{Anchor, Annotation, AnnotationBox, Link, LinkCollector, LinkSpecial, LinkTarget};
+pub use text::{extract_text, TextExtractor};
+pub use writer::XdvWriter;
+
 /// Errors that can occur when parsing XDV/SPX files.
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum XdvError {
@@ -1420,6 +1435,7 @@ mod tests {
     use super::*;
     use std::ffi::OsStr;
     use std::fs;
+    use std::io;
     use std::path::{Path, PathBuf};
 
     fn tex_outputs() -> PathBuf {
@@ -1463,4 +1479,208 @@ mod tests {
             assert_eq!(consumed, file.len());
         }
     }
+
+    #[test]
+    fn test_writer_round_trip() {
+        #[derive(Default)]
+        struct Recorder(Vec<String>);
+
+        impl XdvEvents for Recorder {
+            type Error = XdvError;
+
+            fn handle_header(
+                &mut self,
+                filetype: FileType,
+                comment: &[u8],
+            ) -> Result<(), Self::Error> {
+                self.0.push(format!("header {filetype:?} {comment:?}"));
+                Ok(())
+            }
+
+            fn handle_begin_page(
+                &mut self,
+                counters: &[i32],
+                previous_bop: i32,
+            ) -> Result<(), Self::Error> {
+                self.0.push(format!("page {} {previous_bop}", counters[0]));
+                Ok(())
+            }
+
+            fn handle_special(
+                &mut self,
+                x: i32,
+                y: i32,
+                contents: &[u8],
+            ) -> Result<(), Self::Error> {
+                let contents = String::from_utf8_lossy(contents);
+                self.0.push(format!("special {x} {y} {contents}"));
+                Ok(())
+            }
+
+            fn handle_char_run(&mut self, font_num: i32, chars: &[i32]) -> Result<(), Self::Error> {
+                self.0.push(format!("chars {font_num} {chars:?}"));
+                Ok(())
+            }
+
+            fn handle_glyph_run(
+                &mut self,
+                font_num: i32,
+                glyphs: &[u16],
+                x: &[i32],
+                y: &[i32],
+            ) -> Result<(), Self::Error> {
+                self.0
+                    .push(format!("glyphs {font_num} {glyphs:?} {x:?} {y:?}"));
+                Ok(())
+            }
+
+            fn handle_text_and_glyphs(
+                &mut self,
+                font_num: i32,
+                text: &str,
+                width: i32,
+                glyphs: &[u16],
+                x: &[i32],
+                y: &[i32],
+            ) -> Result<(), Self::Error> {
+                self.0.push(format!(
+                    "text {font_num} {text} {width} {glyphs:?} {x:?} {y:?}"
+                ));
+                Ok(())
+            }
+
+            fn handle_define_native_font(
+                &mut self,
+                name: &str,
+                font_num: i32,
+                size: i32,
+                face_index: u32,
+                color_rgba: Option<u32>,
+                extend: Option<u32>,
+                slant: Option<u32>,
+                embolden: Option<u32>,
+            ) -> Result<(), Self::Error> {
+                self.0.push(format!(
+                    "font {name} {font_num} {size} {face_index} {color_rgba:?} {extend:?} {slant:?} {embolden:?}"
+                ));
+                Ok(())
+            }
+
+            fn handle_rule(
+                &mut self,
+                x: i32,
+                y: i32,
+                height: i32,
+                width: i32,
+            ) -> Result<(), Self::Error> {
+                self.0.push(format!("rule {x} {y} {height} {width}"));
+                Ok(())
+            }
+        }
+
+        let mut w = XdvWriter::new(Vec::new(), FileType::Spx, b"test").unwrap();
+        w.begin_page(&[1, 0, 0, 0, 0, 0, 0, 0, 0, 0]).unwrap();
+        w.define_native_font("a.otf", 3, 10 << 16, 0, None, None, Some(0x3000), None)
+            .unwrap();
+        w.set_font(3).unwrap();
+        w.down(1_000_000).unwrap();
+        w.right(-5).unwrap();
+        w.set_glyphs(300, &[7, 8], &[0, 150], &[0, 2]).unwrap();
+        w.push().unwrap();
+        w.right(40_000).unwrap();
+        w.special(b"color push gray 0").unwrap();
+        w.pop().unwrap();
+        w.set_text_and_glyphs("fi\u{1F600}", 120, &[9], &[0], &[0])
+            .unwrap();
+        w.put_rule(10, 20).unwrap();
+        w.end_page().unwrap();
+
+        w.begin_page(&[2, 0, 0, 0, 0, 0, 0, 0, 0, 0]).unwrap();
+        w.define_font(70, 0, 1 << 20, 1 << 20, "cmr10").unwrap();
+        w.set_font(70).unwrap();
+        w.set_char(65).unwrap();
+        w.set_char(200).unwrap();
+        w.set_rule(1, 2).unwrap();
+        w.end_page().unwrap();
+        assert_eq!(w.pop().unwrap_err().kind(), io::ErrorKind::InvalidInput);
+
+        let data = w.finish().unwrap();
+        assert_eq!(data.len() % 4, 0);
+
+        let mut parser = XdvParser::new(Recorder::default());
+        let (consumed, keep_going) = parser.parse(&data).unwrap();
+        assert!(keep_going);
+        assert_eq!(consumed, data.len());
+        let events = parser.finish().unwrap().0;
+
+        let font = "font a.otf 3 655360 0 None None Some(12288) None";
+        assert_eq!(
+            events,
+            [
+                "header Spx [116, 101, 115, 116]",
+                "page 1 -1",
+                font,
+                "glyphs 3 [7, 8] [-5, 145] [1000000, 1000002]",
+                "special 40295 1000000 color push gray 0",
+                "text 3 fi\u{1F600} 120 [9] [295] [1000000]",
+                "rule 415 1000000 10 20",
+                "page 2 19",
+                "rule 0 0 1 2",
+                "chars 70 [65, 200]",
+                font,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_extract_text() {
+        const PT: i32 = 1 << 16;
+
+        fn word(w: &mut XdvWriter<Vec<u8>>, text: &str) {
+            let width = text.len() as i32 * 5 * PT;
+            w.set_text_and_glyphs(text, width, &[1], &[0], &[0])
+                .unwrap();
+            w.right(3 * PT).unwrap();
+        }
+
+        let mut w = XdvWriter::new(Vec::new(), FileType::Xdv, b"tectonic").unwrap();
+        w.begin_page(&[1, 0, 0, 0, 0, 0, 0, 0, 0, 0]).unwrap();
+        w.define_native_font("a.otf", 1, 10 * PT, 0, None, None, None, None)
+            .unwrap();
+        w.set_font(1).unwrap();
+        w.down(20 * PT).unwrap();
+        w.push().unwrap();
+        word(&mut w, "Hello");
+        word(&mut w, "world,");
+        w.right(-3 * PT).unwrap();
+        word(&mut w, "x");
+        w.right(-3 * PT).unwrap();
+        w.push().unwrap();
+        w.down(-3 * PT).unwrap();
+        word(&mut w, "2");
+        w.pop().unwrap();
+        w.pop().unwrap();
+        w.down(12 * PT).unwrap();
+        w.push().unwrap();
+        word(&mut w, "again.");
+        w.pop().unwrap();
+        w.down(30 * PT).unwrap();
+        word(&mut w, "Next");
+        w.end_page().unwrap();
+
+        w.begin_page(&[2, 0, 0, 0, 0, 0, 0, 0, 0, 0]).unwrap();
+        w.define_font(2, 0, 10 * PT as u32, 10 * PT as u32, "cmr10")
+            .unwrap();
+        w.set_font(2).unwrap();
+        w.set_char(65).unwrap();
+        w.end_page().unwrap();
+
+        w.begin_page(&[3, 0, 0, 0, 0, 0, 0, 0, 0, 0]).unwrap();
+        w.set_font(1).unwrap();
+        word(&mut w, "Last");
+        w.end_page().unwrap();
+
+        let pages = extract_text(&w.finish().unwrap()).unwrap();
+        assert_eq!(pages, ["Hello world,x2\nagain.\n\nNext\n", "", "Last\n"]);
+    }
 }
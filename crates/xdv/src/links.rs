@@ -0,0 +1,904 @@
+// Copyright 2025 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Hyperlinks, anchors, and annotations expressed with `\special`s.
+//!
+//! The specials understood here are the ones that `xdvipdfmx` uses to create
+//! PDF annotations and named destinations, which are what `hyperref` emits
+//! for Tectonic documents, and the HyperTeX `html:` specials.
+
+use std::collections::HashMap;
+
+use super::{XdvError, XdvEvents};
+
+/// The number of DVI units in a TeX point.
+const DVI_UNITS_PER_PT: f64 = 65536.;
+
+/// Where a link goes.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum LinkTarget {
+    /// An external URI.
+    Uri(String),
+
+    /// A named destination in the same document.
+    Destination(String),
+
+    /// A destination in another document, optionally with a named destination
+    /// within it.
+    RemoteDestination {
+        /// The file containing the destination.
+        file: String,
+
+        /// The named destination, if there is one.
+        dest: Option<String>,
+    },
+}
+
+/// The parts of an annotation dictionary that don't depend on the PDF being
+/// generated.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Annotation {
+    /// The annotation subtype, such as `Link` or `Text`, without its leading
+    /// slash.
+    pub subtype: Option<String>,
+
+    /// Where the annotation links to, for link annotations that use a URI,
+    /// go-to, or remote go-to action or destination.
+    pub target: Option<LinkTarget>,
+
+    /// The text of the annotation, for annotations that have some.
+    pub contents: Option<String>,
+}
+
+/// The extent of an annotation with an explicit size.
+///
+/// The coordinates are in DVI units, relative to the point where the special
+/// appears, with *y* increasing downward as in the DVI format. So an
+/// annotation that is given a height and a depth has a negative `y0`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct AnnotationBox {
+    /// The left edge.
+    pub x0: i32,
+
+    /// The top edge.
+    pub y0: i32,
+
+    /// The right edge.
+    pub x1: i32,
+
+    /// The bottom edge.
+    pub y1: i32,
+}
+
+/// A `\special` that creates a hyperlink, anchor, or annotation.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LinkSpecial {
+    /// The start of an annotation that covers the material up to the next
+    /// [`LinkSpecial::EndAnnotation`], from `pdf:bann` or the HyperTeX
+    /// `html:<a href="...">`.
+    BeginAnnotation(Annotation),
+
+    /// The end of an annotation, from `pdf:eann` or the HyperTeX `html:</a>`.
+    EndAnnotation,
+
+    /// An annotation with an explicit size, from `pdf:ann`.
+    SizedAnnotation(Annotation, AnnotationBox),
+
+    /// A named destination at the current point, from `pdf:dest` or the
+    /// HyperTeX `html:<a name="...">`.
+    Destination(String),
+}
+
+impl LinkSpecial {
+    /// Parse the contents of a `\special`, returning `None` if it isn't one
+    /// that this type represents or if it's malformed.
+    ///
+    /// Since the HyperTeX `html:<a name="...">` is followed by an `html:</a>`
+    /// that doesn't end an annotation, consumers should ignore unmatched
+    /// [`LinkSpecial::EndAnnotation`] values.
+    pub fn parse(contents: &[u8]) -> Option<Self> {
+        if let Some(rest) = contents.strip_prefix(b"pdf:") {
+            Self::parse_pdf(rest)
+        } else if let Some(rest) = contents.strip_prefix(b"html:") {
+            Self::parse_html(rest)
+        } else {
+            None
+        }
+    }
+
+    fn parse_pdf(text: &[u8]) -> Option<Self> {
+        let mut p = ObjectParser::new(text);
+        p.skip_white();
+        let command = p.take_token();
+
+        match command {
+            b"bann" | b"bannot" | b"beginann" => {
+                p.skip_label();
+                let dict = p.parse_dict()?;
+                Some(LinkSpecial::BeginAnnotation(Annotation::from_dict(&dict)))
+            }
+
+            b"eann" | b"eannot" | b"endann" => Some(LinkSpecial::EndAnnotation),
+
+            b"ann" | b"annot" | b"annotate" | b"annotation" => {
+                p.skip_label();
+                let bbox = p.parse_dimensions()?;
+                let dict = p.parse_dict()?;
+                Some(LinkSpecial::SizedAnnotation(
+                    Annotation::from_dict(&dict),
+                    bbox,
+                ))
+            }
+
+            b"dest" | b"destination" => match p.parse_object()? {
+                Object::String(name) => Some(LinkSpecial::Destination(decode_text(&name))),
+                _ => None,
+            },
+
+            _ => None,
+        }
+    }
+
+    fn parse_html(text: &[u8]) -> Option<Self> {
+        let text = std::str::from_utf8(text).ok()?.trim();
+
+        if text.eq_ignore_ascii_case("</a>") {
+            return Some(LinkSpecial::EndAnnotation);
+        }
+
+        let attr = text
+            .strip_prefix("<a ")
+            .or_else(|| text.strip_prefix("<A "))?
+            .strip_suffix('>')?
+            .trim();
+        let (name, value) = attr.split_once('=')?;
+        let value = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+
+        if name.trim().eq_ignore_ascii_case("href") {
+            let target = if let Some(dest) = value.strip_prefix('#') {
+                LinkTarget::Destination(dest.to_owned())
+            } else if let Some((file, dest)) = value.split_once('#') {
+                if value.contains("://") {
+                    LinkTarget::Uri(value.to_owned())
+                } else {
+                    LinkTarget::RemoteDestination {
+                        file: file.to_owned(),
+                        dest: Some(dest.to_owned()),
+                    }
+                }
+            } else {
+                LinkTarget::Uri(value.to_owned())
+            };
+
+            Some(LinkSpecial::BeginAnnotation(Annotation {
+                subtype: Some("Link".to_owned()),
+                target: Some(target),
+                contents: None,
+            }))
+        } else if name.trim().eq_ignore_ascii_case("name") {
+            Some(LinkSpecial::Destination(value.to_owned()))
+        } else {
+            None
+        }
+    }
+}
+
+impl Annotation {
+    fn from_dict(dict: &HashMap<Vec<u8>, Object>) -> Self {
+        let subtype = match dict.get(&b"Subtype"[..]) {
+            Some(Object::Name(n)) => Some(decode_text(n)),
+            _ => None,
+        };
+
+        let contents = match dict.get(&b"Contents"[..]) {
+            Some(Object::String(s)) => Some(decode_text(s)),
+            _ => None,
+        };
+
+        let target = match dict.get(&b"A"[..]) {
+            Some(Object::Dict(action)) => target_from_action(action),
+            _ => dict
+                .get(&b"Dest"[..])
+                .and_then(dest_name)
+                .map(LinkTarget::Destination),
+        };
+
+        Annotation {
+            subtype,
+            target,
+            contents,
+        }
+    }
+}
+
+fn target_from_action(action: &HashMap<Vec<u8>, Object>) -> Option<LinkTarget> {
+    let Some(Object::Name(kind)) = action.get(&b"S"[..]) else {
+        return None;
+    };
+
+    match &kind[..] {
+        b"URI" => match action.get(&b"URI"[..]) {
+            Some(Object::String(uri)) => Some(LinkTarget::Uri(decode_text(uri))),
+            _ => None,
+        },
+
+        b"GoTo" => action
+            .get(&b"D"[..])
+            .and_then(dest_name)
+            .map(LinkTarget::Destination),
+
+        b"GoToR" => {
+            let file = match action.get(&b"F"[..]) {
+                Some(Object::String(f)) => decode_text(f),
+                Some(Object::Dict(spec)) => {
+                    match spec.get(&b"UF"[..]).or_else(|| spec.get(&b"F"[..])) {
+                        Some(Object::String(f)) => decode_text(f),
+                        _ => return None,
+                    }
+                }
+                _ => return None,
+            };
+
+            Some(LinkTarget::RemoteDestination {
+                file,
+                dest: action.get(&b"D"[..]).and_then(dest_name),
+            })
+        }
+
+        _ => None,
+    }
+}
+
+/// Get the name of a destination. Explicit destinations, which are arrays,
+/// refer to the PDF's page objects and aren't supported.
+fn dest_name(dest: &Object) -> Option<String> {
+    match dest {
+        Object::String(s) | Object::Name(s) => Some(decode_text(s)),
+        _ => None,
+    }
+}
+
+/// Decode a PDF text string, which is UTF-16BE if it starts with a byte order
+/// mark. Otherwise, XeTeX writes UTF-8, but fall back to Latin-1 for anything
+/// else.
+fn decode_text(bytes: &[u8]) -> String {
+    if let Some(utf16) = bytes.strip_prefix(b"\xfe\xff") {
+        let units: Vec<u16> = utf16
+            .chunks_exact(2)
+            .map(|c| u16::from_be_bytes([c[0], c[1]]))
+            .collect();
+        String::from_utf16_lossy(&units)
+    } else {
+        match std::str::from_utf8(bytes) {
+            Ok(s) => s.to_owned(),
+            Err(_) => bytes.iter().map(|b| char::from(*b)).collect(),
+        }
+    }
+}
+
+/// A PDF object, as far as we care about them.
+#[derive(Debug)]
+enum Object {
+    Name(Vec<u8>),
+    String(Vec<u8>),
+    Dict(HashMap<Vec<u8>, Object>),
+    /// Numbers, booleans, `null`, arrays, and `xdvipdfmx` references like
+    /// `@thispage`.
+    Other,
+}
+
+/// A minimal parser for the PDF objects in `xdvipdfmx` specials.
+struct ObjectParser<'a> {
+    text: &'a [u8],
+}
+
+fn is_white(c: u8) -> bool {
+    matches!(c, b' ' | b'\t' | b'\r' | b'\n' | b'\x0c' | b'\0')
+}
+
+fn is_delimiter(c: u8) -> bool {
+    matches!(
+        c,
+        b'(' | b')' | b'<' | b'>' | b'[' | b']' | b'{' | b'}' | b'/' | b'%'
+    )
+}
+
+impl<'a> ObjectParser<'a> {
+    fn new(text: &'a [u8]) -> Self {
+        ObjectParser { text }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.first().copied()
+    }
+
+    fn advance(&mut self, n: usize) {
+        self.text = &self.text[n.min(self.text.len())..];
+    }
+
+    fn skip_white(&mut self) {
+        loop {
+            match self.peek() {
+                Some(c) if is_white(c) => self.advance(1),
+                Some(b'%') => {
+                    let n = self
+                        .text
+                        .iter()
+                        .position(|c| *c == b'\n' || *c == b'\r')
+                        .unwrap_or(self.text.len());
+                    self.advance(n);
+                }
+                _ => break,
+            }
+        }
+    }
+
+    /// Take a run of regular characters.
+    fn take_token(&mut self) -> &'a [u8] {
+        let n = self
+            .text
+            .iter()
+            .position(|c| is_white(*c) || is_delimiter(*c))
+            .unwrap_or(self.text.len());
+        let token = &self.text[..n];
+        self.advance(n);
+        token
+    }
+
+    /// Skip an optional `@label` that names the annotation for later reference.
+    fn skip_label(&mut self) {
+        self.skip_white();
+
+        if self.peek() == Some(b'@') {
+            self.take_token();
+            self.skip_white();
+        }
+    }
+
+    /// Parse the `width`, `height`, `depth`, or `bbox` keywords that may give
+    /// the size of an annotation. Other transformation keywords are skipped.
+    fn parse_dimensions(&mut self) -> Option<AnnotationBox> {
+        let (mut width, mut height, mut depth) = (0., 0., 0.);
+        let mut bbox = None;
+
+        loop {
+            self.skip_white();
+
+            if self.peek() == Some(b'<') || self.text.is_empty() {
+                break;
+            }
+
+            match self.take_token() {
+                b"width" => width = self.parse_length()?,
+                b"height" => height = self.parse_length()?,
+                b"depth" => depth = self.parse_length()?,
+                b"bbox" => {
+                    let mut values = [0.; 4];
+
+                    for v in &mut values {
+                        self.skip_white();
+                        *v = parse_number(self.take_token())? * 72.27 / 72.;
+                    }
+
+                    bbox = Some(values);
+                }
+                b"" => return None,
+                _ => {}
+            }
+        }
+
+        let to_dvi = |v: f64| (v * DVI_UNITS_PER_PT).round() as i32;
+
+        Some(match bbox {
+            Some([llx, lly, urx, ury]) => AnnotationBox {
+                x0: to_dvi(llx),
+                y0: to_dvi(-ury),
+                x1: to_dvi(urx),
+                y1: to_dvi(-lly),
+            },
+            None => AnnotationBox {
+                x0: 0,
+                y0: to_dvi(-height),
+                x1: to_dvi(width),
+                y1: to_dvi(depth),
+            },
+        })
+    }
+
+    /// Parse a length with a unit, returning it in TeX points.
+    fn parse_length(&mut self) -> Option<f64> {
+        self.skip_white();
+        let token = self.take_token();
+        let split = token
+            .iter()
+            .position(|c| c.is_ascii_alphabetic())
+            .unwrap_or(token.len());
+        let mut value = parse_number(&token[..split])?;
+        let mut unit = &token[split..];
+
+        if unit.is_empty() {
+            self.skip_white();
+            unit = self.take_token();
+        }
+
+        let unit = unit.strip_prefix(b"true").unwrap_or(unit);
+
+        value *= match unit {
+            b"pt" => 1.,
+            b"in" => 72.27,
+            b"cm" => 72.27 / 2.54,
+            b"mm" => 72.27 / 25.4,
+            b"bp" => 72.27 / 72.,
+            b"pc" => 12.,
+            b"dd" => 1238. / 1157.,
+            b"cc" => 12. * 1238. / 1157.,
+            b"sp" => 1. / DVI_UNITS_PER_PT,
+            _ => return None,
+        };
+
+        Some(value)
+    }
+
+    fn parse_dict(&mut self) -> Option<HashMap<Vec<u8>, Object>> {
+        self.skip_white();
+
+        match self.parse_object()? {
+            Object::Dict(d) => Some(d),
+            _ => None,
+        }
+    }
+
+    fn parse_object(&mut self) -> Option<Object> {
+        self.skip_white();
+
+        match self.peek()? {
+            b'/' => {
+                self.advance(1);
+                Some(Object::Name(decode_name(self.take_token())))
+            }
+
+            b'(' => {
+                self.advance(1);
+                self.parse_literal_string().map(Object::String)
+            }
+
+            b'<' if self.text.get(1) == Some(&b'<') => {
+                self.advance(2);
+                let mut dict = HashMap::new();
+
+                loop {
+                    self.skip_white();
+
+                    if self.text.starts_with(b">>") {
+                        self.advance(2);
+                        return Some(Object::Dict(dict));
+                    }
+
+                    let Object::Name(key) = self.parse_object()? else {
+                        return None;
+                    };
+
+                    let value = self.parse_object()?;
+                    dict.insert(key, value);
+                }
+            }
+
+            b'<' => {
+                self.advance(1);
+                self.parse_hex_string().map(Object::String)
+            }
+
+            b'[' => {
+                self.advance(1);
+
+                loop {
+                    self.skip_white();
+
+                    if self.peek() == Some(b']') {
+                        self.advance(1);
+                        return Some(Object::Other);
+                    }
+
+                    self.parse_object()?;
+                }
+            }
+
+            c if is_delimiter(c) => None,
+
+            _ => {
+                self.take_token();
+                Some(Object::Other)
+            }
+        }
+    }
+
+    fn parse_literal_string(&mut self) -> Option<Vec<u8>> {
+        let mut out = Vec::new();
+        let mut depth = 0;
+
+        loop {
+            let c = self.peek()?;
+            self.advance(1);
+
+            match c {
+                b'(' => {
+                    depth += 1;
+                    out.push(c);
+                }
+
+                b')' if depth == 0 => return Some(out),
+
+                b')' => {
+                    depth -= 1;
+                    out.push(c);
+                }
+
+                b'\\' => {
+                    let e = self.peek()?;
+                    self.advance(1);
+
+                    match e {
+                        b'n' => out.push(b'\n'),
+                        b'r' => out.push(b'\r'),
+                        b't' => out.push(b'\t'),
+                        b'b' => out.push(b'\x08'),
+                        b'f' => out.push(b'\x0c'),
+                        b'0'..=b'7' => {
+                            let mut value = u32::from(e - b'0');
+
+                            for _ in 0..2 {
+                                match self.peek() {
+                                    Some(d @ b'0'..=b'7') => {
+                                        value = value * 8 + u32::from(d - b'0');
+                                        self.advance(1);
+                                    }
+                                    _ => break,
+                                }
+                            }
+
+                            out.push(value as u8);
+                        }
+                        b'\r' => {
+                            if self.peek() == Some(b'\n') {
+                                self.advance(1);
+                            }
+                        }
+                        b'\n' => {}
+                        _ => out.push(e),
+                    }
+                }
+
+                _ => out.push(c),
+            }
+        }
+    }
+
+    fn parse_hex_string(&mut self) -> Option<Vec<u8>> {
+        let end = self.text.iter().position(|c| *c == b'>')?;
+        let digits: Vec<u8> = self.text[..end]
+            .iter()
+            .filter(|c| !is_white(**c))
+            .map(|c| (*c as char).to_digit(16).map(|d| d as u8))
+            .collect::<Option<_>>()?;
+        self.advance(end + 1);
+
+        Some(
+            digits
+                .chunks(2)
+                .map(|pair| pair[0] << 4 | pair.get(1).copied().unwrap_or(0))
+                .collect(),
+        )
+    }
+}
+
+/// Decode the `#xx` escapes in a PDF name.
+fn decode_name(token: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(token.len());
+    let mut i = 0;
+
+    while i < token.len() {
+        if token[i] == b'#' && i + 2 < token.len() {
+            let hex = std::str::from_utf8(&token[i + 1..i + 3]).ok();
+
+            if let Some(b) = hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                out.push(b);
+                i += 3;
+                continue;
+            }
+        }
+
+        out.push(token[i]);
+        i += 1;
+    }
+
+    out
+}
+
+fn parse_number(token: &[u8]) -> Option<f64> {
+    std::str::from_utf8(token).ok()?.parse().ok()
+}
+
+/// A hyperlink found in a document.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Link {
+    /// The page on which the link starts, counting from 1.
+    pub page: usize,
+
+    /// Where the link goes.
+    pub target: LinkTarget,
+
+    /// The top-left corner of a link with an explicit size, or the point
+    /// where a link that covers some material starts. In DVI units.
+    pub start: (i32, i32),
+
+    /// The bottom-right corner of a link with an explicit size, or the point
+    /// where a link that covers some material ends. In DVI units.
+    pub end: (i32, i32),
+}
+
+/// A named destination found in a document.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Anchor {
+    /// The page on which the destination appears, counting from 1.
+    pub page: usize,
+
+    /// The name of the destination.
+    pub name: String,
+
+    /// The position of the destination, in DVI units.
+    pub position: (i32, i32),
+}
+
+/// An event handler that collects the hyperlinks and named destinations of a
+/// document.
+///
+/// Only annotations that have a [`LinkTarget`] are reported as links. As with
+/// `xdvipdfmx`, an annotation can't begin while another one is pending. An
+/// annotation that is broken across pages is reported once, on the page where
+/// it begins.
+#[derive(Debug, Default)]
+pub struct LinkCollector {
+    page: usize,
+    pending: Option<(usize, Option<LinkTarget>, (i32, i32))>,
+    links: Vec<Link>,
+    anchors: Vec<Anchor>,
+}
+
+impl LinkCollector {
+    /// Create a new collector.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the links found so far, in the order in which they end.
+    pub fn links(&self) -> &[Link] {
+        &self.links
+    }
+
+    /// Get the named destinations found so far, in document order.
+    pub fn anchors(&self) -> &[Anchor] {
+        &self.anchors
+    }
+
+    /// Consume the collector, returning its links and named destinations.
+    pub fn into_parts(self) -> (Vec<Link>, Vec<Anchor>) {
+        (self.links, self.anchors)
+    }
+}
+
+impl XdvEvents for LinkCollector {
+    type Error = XdvError;
+
+    fn handle_begin_page(&mut self, _counters: &[i32], _previous_bop: i32) -> Result<(), XdvError> {
+        self.page += 1;
+        Ok(())
+    }
+
+    fn handle_special(&mut self, x: i32, y: i32, contents: &[u8]) -> Result<(), XdvError> {
+        match LinkSpecial::parse(contents) {
+            Some(LinkSpecial::BeginAnnotation(annot)) if self.pending.is_none() => {
+                self.pending = Some((self.page, annot.target, (x, y)));
+            }
+
+            Some(LinkSpecial::EndAnnotation) => {
+                if let Some((page, Some(target), start)) = self.pending.take() {
+                    self.links.push(Link {
+                        page,
+                        target,
+                        start,
+                        end: (x, y),
+                    });
+                }
+            }
+
+            Some(LinkSpecial::SizedAnnotation(annot, bbox)) => {
+                if let Some(target) = annot.target {
+                    self.links.push(Link {
+                        page: self.page,
+                        target,
+                        start: (x + bbox.x0, y + bbox.y0),
+                        end: (x + bbox.x1, y + bbox.y1),
+                    });
+                }
+            }
+
+            Some(LinkSpecial::Destination(name)) => self.anchors.push(Anchor {
+                page: self.page,
+                name,
+                position: (x, y),
+            }),
+
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FileType, XdvParser, XdvWriter};
+
+    fn link(target: LinkTarget) -> Option<LinkSpecial> {
+        Some(LinkSpecial::BeginAnnotation(Annotation {
+            subtype: Some("Link".to_owned()),
+            target: Some(target),
+            contents: None,
+        }))
+    }
+
+    #[test]
+    fn hyperref_specials() {
+        assert_eq!(
+            LinkSpecial::parse(
+                b"pdf:bann<</Type/Annot/Subtype/Link/Border[0 0 1]/H/I/C[0 1 1]\
+                  /A<</S/URI/URI(https://example.com/a\\(b\\)%20c)>>>>"
+            ),
+            link(LinkTarget::Uri("https://example.com/a(b)%20c".to_owned()))
+        );
+
+        assert_eq!(
+            LinkSpecial::parse(
+                b"pdf:bann @lnk <</Type/Annot/Subtype/Link /A<</S/GoTo/D<FEFF00E9>>> >>"
+            ),
+            link(LinkTarget::Destination("\u{e9}".to_owned()))
+        );
+
+        assert_eq!(
+            LinkSpecial::parse(
+                b"pdf:bann<</Subtype/Link/A<</S/GoToR/F(other.pdf)/D(sec.1)/NewWindow false>>>>"
+            ),
+            link(LinkTarget::RemoteDestination {
+                file: "other.pdf".to_owned(),
+                dest: Some("sec.1".to_owned()),
+            })
+        );
+
+        assert_eq!(
+            LinkSpecial::parse(b"pdf:dest (section.1) [@thispage /XYZ @xpos @ypos null]"),
+            Some(LinkSpecial::Destination("section.1".to_owned()))
+        );
+
+        assert_eq!(
+            LinkSpecial::parse(b"pdf:eann"),
+            Some(LinkSpecial::EndAnnotation)
+        );
+        assert_eq!(LinkSpecial::parse(b"pdf:bann <</Subtype/Link"), None);
+        assert_eq!(LinkSpecial::parse(b"pdf:outline 1 <</Title(x)>>"), None);
+        assert_eq!(LinkSpecial::parse(b"color push rgb 1 0 0"), None);
+    }
+
+    #[test]
+    fn sized_annotations() {
+        assert_eq!(
+            LinkSpecial::parse(
+                b"pdf:ann width 10pt height 2pt depth 1 pt \
+                  <</Subtype/Text/Contents(Note #1)/Dest/here>>"
+            ),
+            Some(LinkSpecial::SizedAnnotation(
+                Annotation {
+                    subtype: Some("Text".to_owned()),
+                    target: Some(LinkTarget::Destination("here".to_owned())),
+                    contents: Some("Note #1".to_owned()),
+                },
+                AnnotationBox {
+                    x0: 0,
+                    y0: -2 * 65536,
+                    x1: 10 * 65536,
+                    y1: 65536,
+                },
+            ))
+        );
+
+        assert_eq!(
+            LinkSpecial::parse(b"pdf:ann bbox 0 -72 72 0 <</Subtype/Link>>"),
+            Some(LinkSpecial::SizedAnnotation(
+                Annotation {
+                    subtype: Some("Link".to_owned()),
+                    ..Annotation::default()
+                },
+                AnnotationBox {
+                    x0: 0,
+                    y0: 0,
+                    x1: 4_736_287,
+                    y1: 4_736_287,
+                },
+            ))
+        );
+    }
+
+    #[test]
+    fn hypertex_specials() {
+        assert_eq!(
+            LinkSpecial::parse(b"html:<a href=\"#page.2\">"),
+            link(LinkTarget::Destination("page.2".to_owned()))
+        );
+        assert_eq!(
+            LinkSpecial::parse(b"html:<a href=\"https://example.com/#x\">"),
+            link(LinkTarget::Uri("https://example.com/#x".to_owned()))
+        );
+        assert_eq!(
+            LinkSpecial::parse(b"html:<a name=\"page.2\">"),
+            Some(LinkSpecial::Destination("page.2".to_owned()))
+        );
+        assert_eq!(
+            LinkSpecial::parse(b"html:</a>"),
+            Some(LinkSpecial::EndAnnotation)
+        );
+    }
+
+    #[test]
+    fn collect_links() {
+        let mut w = XdvWriter::new(Vec::new(), FileType::Xdv, b"tectonic").unwrap();
+        w.begin_page(&[1, 0, 0, 0, 0, 0, 0, 0, 0, 0]).unwrap();
+        w.down(1000).unwrap();
+        w.special(b"pdf:dest (top) [@thispage /XYZ @xpos @ypos null]")
+            .unwrap();
+        w.special(b"pdf:bann <</Subtype/Link/A<</S/URI/URI(https://example.com)>>>>")
+            .unwrap();
+        w.set_rule(10, 500).unwrap();
+        w.special(b"pdf:bann <</Subtype/Link/A<</S/GoTo/D(top)>>>>")
+            .unwrap();
+        w.special(b"pdf:eann").unwrap();
+        w.special(b"pdf:eann").unwrap();
+        w.end_page().unwrap();
+
+        w.begin_page(&[2, 0, 0, 0, 0, 0, 0, 0, 0, 0]).unwrap();
+        w.special(b"pdf:ann width 1sp height 2sp <</Subtype/Link/Dest(top)>>")
+            .unwrap();
+        w.special(b"pdf:ann width 1sp <</Subtype/Widget>>").unwrap();
+        w.end_page().unwrap();
+
+        let data = w.finish().unwrap();
+        let mut parser = XdvParser::new(LinkCollector::new());
+        parser.parse(&data).unwrap();
+        let (links, anchors) = parser.finish().unwrap().into_parts();
+
+        assert_eq!(
+            links,
+            [
+                Link {
+                    page: 1,
+                    target: LinkTarget::Uri("https://example.com".to_owned()),
+                    start: (0, 1000),
+                    end: (500, 1000),
+                },
+                Link {
+                    page: 2,
+                    target: LinkTarget::Destination("top".to_owned()),
+                    start: (0, -2),
+                    end: (1, 0),
+                },
+            ]
+        );
+        assert_eq!(
+            anchors,
+            [Anchor {
+                page: 1,
+                name: "top".to_owned(),
+                position: (0, 1000),
+            }]
+        );
+    }
+}
@@ -0,0 +1,152 @@
+// Copyright 2025 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Extracting plain text from XDV/SPX files.
+
+use std::collections::HashMap;
+
+use super::{XdvError, XdvEvents, XdvParser};
+
+/// The font size assumed for fonts whose definitions haven't been seen: 10pt,
+/// in DVI units.
+const DEFAULT_FONT_SIZE: i32 = 10 << 16;
+
+/// An event handler that reconstructs the plain text of a document.
+///
+/// Text is taken from the runs of native-font glyphs that XeTeX emits along
+/// with the Unicode text they represent, which it only does when
+/// `\XeTeXgenerateactualtext` is positive. The text is reassembled in the order in
+/// which it appears in the file, which for TeX output is almost always the
+/// reading order. A new line is started whenever the baseline moves by more
+/// than half the font size or the current point moves backward; a blank line
+/// is added when the baseline jumps by more than twice the font size; and a
+/// space is added between runs on the same line that are separated by more
+/// than a fifth of the font size.
+///
+/// Characters set in TFM fonts are ignored, since their encodings vary from
+/// font to font and the parser can't position them. So are bare glyph runs,
+/// which carry no text.
+#[derive(Debug, Default)]
+pub struct TextExtractor {
+    pages: Vec<String>,
+    font_sizes: HashMap<i32, i32>,
+    last: Option<RunEnd>,
+}
+
+/// Where the previous run of text ended.
+#[derive(Clone, Copy, Debug)]
+struct RunEnd {
+    x: i32,
+    y: i32,
+    size: i32,
+}
+
+impl TextExtractor {
+    /// Create a new extractor.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Consume the extractor, returning the text of each page. The text of a
+    /// page that isn't empty ends with a newline.
+    pub fn into_pages(self) -> Vec<String> {
+        self.pages
+            .into_iter()
+            .map(|mut page| {
+                if !page.is_empty() {
+                    page.push('\n');
+                }
+                page
+            })
+            .collect()
+    }
+
+    fn font_size(&self, font_num: i32) -> i32 {
+        self.font_sizes
+            .get(&font_num)
+            .copied()
+            .unwrap_or(DEFAULT_FONT_SIZE)
+    }
+}
+
+impl XdvEvents for TextExtractor {
+    type Error = XdvError;
+
+    fn handle_begin_page(&mut self, _counters: &[i32], _previous_bop: i32) -> Result<(), XdvError> {
+        self.pages.push(String::new());
+        self.last = None;
+        Ok(())
+    }
+
+    fn handle_define_native_font(
+        &mut self,
+        _name: &str,
+        font_num: i32,
+        size: i32,
+        _face_index: u32,
+        _color_rgba: Option<u32>,
+        _extend: Option<u32>,
+        _slant: Option<u32>,
+        _embolden: Option<u32>,
+    ) -> Result<(), XdvError> {
+        if size > 0 {
+            self.font_sizes.insert(font_num, size);
+        }
+
+        Ok(())
+    }
+
+    fn handle_text_and_glyphs(
+        &mut self,
+        font_num: i32,
+        text: &str,
+        width: i32,
+        _glyphs: &[u16],
+        x: &[i32],
+        y: &[i32],
+    ) -> Result<(), XdvError> {
+        let size = self.font_size(font_num);
+
+        let Some(page) = self.pages.last_mut() else {
+            return Ok(());
+        };
+
+        // The parser reports absolute glyph positions; the first glyph of a
+        // run sits at or very near the point where the run starts.
+        let (Some(&x0), Some(&y0)) = (x.first(), y.first()) else {
+            page.push_str(text);
+            return Ok(());
+        };
+
+        if let Some(last) = self.last {
+            let size = size.max(last.size);
+
+            if (y0 - last.y).abs() > size / 2 || x0 < last.x - size / 5 {
+                page.push('\n');
+
+                if (y0 - last.y).abs() > 2 * size {
+                    page.push('\n');
+                }
+            } else if x0 - last.x > size / 5 {
+                page.push(' ');
+            }
+        }
+
+        page.push_str(text);
+        self.last = Some(RunEnd {
+            x: x0 + width,
+            y: y0,
+            size,
+        });
+        Ok(())
+    }
+}
+
+/// Extract the text of each page of an XDV or SPX file held in memory.
+///
+/// See [`TextExtractor`] for how the text is reconstructed.
+pub fn extract_text(data: &[u8]) -> Result<Vec<String>, XdvError> {
+    let mut parser = XdvParser::new(TextExtractor::new());
+    parser.parse(data)?;
+    Ok(parser.finish()?.into_pages())
+}
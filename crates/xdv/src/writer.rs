@@ -0,0 +1,460 @@
+// Copyright 2025 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Writing XDV/SPX files.
+
+use byteorder::{BigEndian, WriteBytesExt};
+use std::io::{Error as IoError, ErrorKind, Result as IoResult, Write};
+
+use super::{FileType, IdByte, NativeFontFlags, Opcode};
+
+/// A writer that synthesizes an XDV/SPX stream.
+///
+/// The writer emits the commands that it is given more or less verbatim,
+/// picking the most compact encoding of each, and takes care of the
+/// bookkeeping that the format requires: the back-pointers between pages and
+/// the postamble, which repeats every font definition. It doesn't know about
+/// font metrics, so it doesn't track the current position.
+///
+/// Methods that place material on a page fail with an error of kind
+/// [`ErrorKind::InvalidInput`] if they're called outside of a page, as do
+/// unbalanced [`pop`](Self::pop) and [`end_page`](Self::end_page) calls.
+#[derive(Debug)]
+pub struct XdvWriter<W: Write> {
+    out: W,
+    filetype: FileType,
+    offset: u64,
+    in_page: bool,
+    last_bop: i32,
+    n_pages: u16,
+    stack_depth: u16,
+    max_stack_depth: u16,
+    font_defs: Vec<u8>,
+}
+
+impl<W: Write> XdvWriter<W> {
+    /// Create a new writer, emitting the preamble.
+    ///
+    /// The *comment* is recorded in the preamble. Tectonic uses `b"tectonic"`.
+    /// It may be at most 255 bytes long.
+    pub fn new(out: W, filetype: FileType, comment: &[u8]) -> IoResult<Self> {
+        let Ok(comment_len) = u8::try_from(comment.len()) else {
+            return Err(invalid("the XDV comment must be at most 255 bytes long"));
+        };
+
+        let mut w = XdvWriter {
+            out,
+            filetype,
+            offset: 0,
+            in_page: false,
+            last_bop: -1,
+            n_pages: 0,
+            stack_depth: 0,
+            max_stack_depth: 0,
+            font_defs: Vec::new(),
+        };
+
+        w.put_u8(Opcode::Preamble as u8)?;
+        w.put_u8(w.id_byte())?;
+        w.put_u32(25_400_000)?; // dimensions unit numerator
+        w.put_u32(473_628_672)?; // dimensions unit denominator
+        w.put_u32(1000)?; // 'mag' factor
+        w.put_u8(comment_len)?;
+        w.put_bytes(comment)?;
+        Ok(w)
+    }
+
+    /// Begin a new page, with the given values of TeX's `\count0` through
+    /// `\count9`.
+    pub fn begin_page(&mut self, counters: &[i32; 10]) -> IoResult<()> {
+        if self.in_page {
+            return Err(invalid("cannot begin an XDV page inside of another one"));
+        }
+
+        let Some(n_pages) = self.n_pages.checked_add(1) else {
+            return Err(invalid("too many pages for an XDV file"));
+        };
+
+        let bop = self.offset_i32()?;
+        self.put_u8(Opcode::BeginningOfPage as u8)?;
+
+        for counter in counters {
+            self.put_i32(*counter)?;
+        }
+
+        self.put_i32(self.last_bop)?;
+        self.last_bop = bop;
+        self.n_pages = n_pages;
+        self.in_page = true;
+        Ok(())
+    }
+
+    /// End the current page.
+    pub fn end_page(&mut self) -> IoResult<()> {
+        self.check_in_page()?;
+
+        if self.stack_depth != 0 {
+            return Err(invalid("XDV page ended with unbalanced pushes"));
+        }
+
+        self.put_u8(Opcode::EndOfPage as u8)?;
+        self.in_page = false;
+        Ok(())
+    }
+
+    /// Save the current position and spacing amounts.
+    pub fn push(&mut self) -> IoResult<()> {
+        self.check_in_page()?;
+        self.put_u8(Opcode::PushStack as u8)?;
+        self.stack_depth += 1;
+        self.max_stack_depth = self.max_stack_depth.max(self.stack_depth);
+        Ok(())
+    }
+
+    /// Restore the most recently saved position and spacing amounts.
+    pub fn pop(&mut self) -> IoResult<()> {
+        self.check_in_page()?;
+
+        if self.stack_depth == 0 {
+            return Err(invalid("XDV pop without a matching push"));
+        }
+
+        self.put_u8(Opcode::PopStack as u8)?;
+        self.stack_depth -= 1;
+        Ok(())
+    }
+
+    /// Move right by *dx*, which may be negative.
+    pub fn right(&mut self, dx: i32) -> IoResult<()> {
+        self.check_in_page()?;
+        self.put_compact_signed(Opcode::Right1 as u8, dx)
+    }
+
+    /// Move down by *dy*, which may be negative.
+    pub fn down(&mut self, dy: i32) -> IoResult<()> {
+        self.check_in_page()?;
+        self.put_compact_signed(Opcode::Down1 as u8, dy)
+    }
+
+    /// Select the font with the given number for subsequent characters and
+    /// glyphs.
+    pub fn set_font(&mut self, font_num: i32) -> IoResult<()> {
+        self.check_in_page()?;
+
+        if (0..64).contains(&font_num) {
+            self.put_u8(Opcode::SetFontNumber0 as u8 + font_num as u8)
+        } else {
+            self.put_compact_unsigned(Opcode::SetFont1 as u8, font_num)
+        }
+    }
+
+    /// Typeset a character from the current TFM font and move right by its
+    /// width.
+    pub fn set_char(&mut self, char_num: i32) -> IoResult<()> {
+        self.check_in_page()?;
+
+        if (0..128).contains(&char_num) {
+            self.put_u8(Opcode::SetCharNumber0 as u8 + char_num as u8)
+        } else {
+            self.put_compact_unsigned(Opcode::SetChar1 as u8, char_num)
+        }
+    }
+
+    /// Draw a rule with its bottom left corner at the current point, then
+    /// move right by its width.
+    pub fn set_rule(&mut self, height: i32, width: i32) -> IoResult<()> {
+        self.rule(Opcode::SetRule, height, width)
+    }
+
+    /// Draw a rule with its bottom left corner at the current point, without
+    /// moving.
+    pub fn put_rule(&mut self, height: i32, width: i32) -> IoResult<()> {
+        self.rule(Opcode::PutRule, height, width)
+    }
+
+    fn rule(&mut self, opcode: Opcode, height: i32, width: i32) -> IoResult<()> {
+        self.check_in_page()?;
+        self.put_u8(opcode as u8)?;
+        self.put_i32(height)?;
+        self.put_i32(width)
+    }
+
+    /// Emit a `\special` at the current point.
+    pub fn special(&mut self, contents: &[u8]) -> IoResult<()> {
+        self.check_in_page()?;
+
+        let Ok(len) = i32::try_from(contents.len()) else {
+            return Err(invalid("XDV special is too long"));
+        };
+
+        self.put_compact_unsigned(Opcode::Special1 as u8, len)?;
+        self.put_bytes(contents)
+    }
+
+    /// Typeset glyphs from the current native font, then move right by
+    /// *width*.
+    ///
+    /// The *x* and *y* slices give the position of each glyph relative to the
+    /// current point, and must be as long as *glyphs*. (The parser reports
+    /// absolute positions.)
+    pub fn set_glyphs(&mut self, width: i32, glyphs: &[u16], x: &[i32], y: &[i32]) -> IoResult<()> {
+        self.check_in_page()?;
+        let n_glyphs = glyph_count(glyphs, x, y)?;
+        self.put_u8(Opcode::SetGlyphs as u8)?;
+        self.put_i32(width)?;
+        self.put_glyphs(n_glyphs, glyphs, x, y)
+    }
+
+    /// Typeset glyphs from the current native font along with the text that
+    /// they represent, then move right by *width*.
+    ///
+    /// Glyph positions are given as in [`set_glyphs`](Self::set_glyphs).
+    pub fn set_text_and_glyphs(
+        &mut self,
+        text: &str,
+        width: i32,
+        glyphs: &[u16],
+        x: &[i32],
+        y: &[i32],
+    ) -> IoResult<()> {
+        self.check_in_page()?;
+        let n_glyphs = glyph_count(glyphs, x, y)?;
+        let chars: Vec<u16> = text.encode_utf16().collect();
+
+        let Ok(n_chars) = u16::try_from(chars.len()) else {
+            return Err(invalid("too much text in one XDV text run"));
+        };
+
+        self.put_u8(Opcode::SetTextAndGlyphs as u8)?;
+        self.put_u16(n_chars)?;
+
+        for c in chars {
+            self.put_u16(c)?;
+        }
+
+        self.put_i32(width)?;
+        self.put_glyphs(n_glyphs, glyphs, x, y)
+    }
+
+    fn put_glyphs(&mut self, n_glyphs: u16, glyphs: &[u16], x: &[i32], y: &[i32]) -> IoResult<()> {
+        self.put_u16(n_glyphs)?;
+
+        for (x, y) in x.iter().zip(y) {
+            self.put_i32(*x)?;
+            self.put_i32(*y)?;
+        }
+
+        for glyph in glyphs {
+            self.put_u16(*glyph)?;
+        }
+
+        Ok(())
+    }
+
+    /// Define a TFM font.
+    ///
+    /// The *scale* and *design_size* are in DVI units. The definition is
+    /// repeated in the postamble, where XDV processors such as `xdvipdfmx`
+    /// look for it.
+    pub fn define_font(
+        &mut self,
+        font_num: i32,
+        checksum: u32,
+        scale: u32,
+        design_size: u32,
+        name: &str,
+    ) -> IoResult<()> {
+        let Ok(name_len) = u8::try_from(name.len()) else {
+            return Err(invalid("XDV font names must be at most 255 bytes long"));
+        };
+
+        let mut def = Vec::new();
+        put_compact_unsigned(&mut def, Opcode::DefineFont1 as u8, font_num)?;
+        def.write_u32::<BigEndian>(checksum)?;
+        def.write_u32::<BigEndian>(scale)?;
+        def.write_u32::<BigEndian>(design_size)?;
+        def.write_u8(0)?; // area length
+        def.write_u8(name_len)?;
+        def.write_all(name.as_bytes())?;
+        self.put_font_def(def)
+    }
+
+    /// Define a native font, as loaded by XeTeX from an OpenType or TrueType
+    /// file.
+    ///
+    /// The *size* and the optional *extend*, *slant*, and *embolden* values
+    /// are 16.16 fixed-point numbers. As with [`define_font`](Self::define_font),
+    /// the definition is repeated in the postamble.
+    #[allow(clippy::too_many_arguments)]
+    pub fn define_native_font(
+        &mut self,
+        name: &str,
+        font_num: i32,
+        size: i32,
+        face_index: u32,
+        color_rgba: Option<u32>,
+        extend: Option<u32>,
+        slant: Option<u32>,
+        embolden: Option<u32>,
+    ) -> IoResult<()> {
+        let Ok(name_len) = u8::try_from(name.len()) else {
+            return Err(invalid("XDV font names must be at most 255 bytes long"));
+        };
+
+        let mut flags = 0;
+
+        for (value, flag) in [
+            (color_rgba, NativeFontFlags::Colored),
+            (extend, NativeFontFlags::Extend),
+            (slant, NativeFontFlags::Slant),
+            (embolden, NativeFontFlags::Embolden),
+        ] {
+            if value.is_some() {
+                flags |= flag as u16;
+            }
+        }
+
+        let mut def = vec![Opcode::DefineNativeFont as u8];
+        def.write_i32::<BigEndian>(font_num)?;
+        def.write_i32::<BigEndian>(size)?;
+        def.write_u16::<BigEndian>(flags)?;
+        def.write_u8(name_len)?;
+        def.write_all(name.as_bytes())?;
+        def.write_u32::<BigEndian>(face_index)?;
+
+        for value in [color_rgba, extend, slant, embolden].into_iter().flatten() {
+            def.write_u32::<BigEndian>(value)?;
+        }
+
+        self.put_font_def(def)
+    }
+
+    fn put_font_def(&mut self, def: Vec<u8>) -> IoResult<()> {
+        self.put_bytes(&def)?;
+        self.font_defs.extend(def);
+        Ok(())
+    }
+
+    /// Finish the file by writing the postamble, and return the underlying
+    /// writer.
+    pub fn finish(mut self) -> IoResult<W> {
+        if self.in_page {
+            return Err(invalid("XDV file finished inside of a page"));
+        }
+
+        let postamble = self.offset_i32()?;
+        self.put_u8(Opcode::Postamble as u8)?;
+        self.put_i32(self.last_bop)?;
+        self.put_u32(25_400_000)?; // dimensions unit numerator
+        self.put_u32(473_628_672)?; // dimensions unit denominator
+        self.put_u32(1000)?; // 'mag' factor
+        self.put_u32(0)?; // largest height+depth of tallest page (unknown)
+        self.put_u32(0)?; // largest width of widest page (unknown)
+        self.put_u16(self.max_stack_depth)?;
+        self.put_u16(self.n_pages)?;
+
+        let font_defs = std::mem::take(&mut self.font_defs);
+        self.put_bytes(&font_defs)?;
+
+        self.put_u8(Opcode::DoublePostamble as u8)?;
+        self.put_i32(postamble)?;
+        self.put_u8(self.id_byte())?;
+
+        // At least four 0xDF bytes, padding the file to a multiple of four
+        // bytes.
+        let n_pad = 4 + (4 - self.offset % 4) % 4;
+        self.put_bytes(&vec![0xDF; n_pad as usize])?;
+
+        self.out.flush()?;
+        Ok(self.out)
+    }
+
+    fn id_byte(&self) -> u8 {
+        let id = match self.filetype {
+            FileType::Xdv => IdByte::Xdv,
+            FileType::Spx => IdByte::Spx,
+        };
+        id as u8
+    }
+
+    fn check_in_page(&self) -> IoResult<()> {
+        if self.in_page {
+            Ok(())
+        } else {
+            Err(invalid("XDV page content must be written inside of a page"))
+        }
+    }
+
+    fn offset_i32(&self) -> IoResult<i32> {
+        i32::try_from(self.offset).map_err(|_| invalid("XDV file is too large"))
+    }
+
+    fn put_bytes(&mut self, data: &[u8]) -> IoResult<()> {
+        self.out.write_all(data)?;
+        self.offset += data.len() as u64;
+        Ok(())
+    }
+
+    fn put_u8(&mut self, value: u8) -> IoResult<()> {
+        self.put_bytes(&[value])
+    }
+
+    fn put_u16(&mut self, value: u16) -> IoResult<()> {
+        self.put_bytes(&value.to_be_bytes())
+    }
+
+    fn put_u32(&mut self, value: u32) -> IoResult<()> {
+        self.put_bytes(&value.to_be_bytes())
+    }
+
+    fn put_i32(&mut self, value: i32) -> IoResult<()> {
+        self.put_bytes(&value.to_be_bytes())
+    }
+
+    fn put_compact_signed(&mut self, opcode_base: u8, value: i32) -> IoResult<()> {
+        let n = if i8::try_from(value).is_ok() {
+            1
+        } else if i16::try_from(value).is_ok() {
+            2
+        } else if (-0x80_0000..0x80_0000).contains(&value) {
+            3
+        } else {
+            4
+        };
+
+        self.put_u8(opcode_base + n as u8 - 1)?;
+        self.put_bytes(&value.to_be_bytes()[4 - n..])
+    }
+
+    fn put_compact_unsigned(&mut self, opcode_base: u8, value: i32) -> IoResult<()> {
+        let mut buf = Vec::with_capacity(5);
+        put_compact_unsigned(&mut buf, opcode_base, value)?;
+        self.put_bytes(&buf)
+    }
+}
+
+/// Encode *value* with the smallest of the unsigned one-, two-, or
+/// three-byte forms of an opcode, or the signed four-byte form.
+fn put_compact_unsigned(buf: &mut Vec<u8>, opcode_base: u8, value: i32) -> IoResult<()> {
+    let n = match value {
+        0..=0xFF => 1,
+        0x100..=0xFFFF => 2,
+        0x1_0000..=0xFF_FFFF => 3,
+        _ => 4,
+    };
+
+    buf.write_u8(opcode_base + n as u8 - 1)?;
+    buf.write_all(&value.to_be_bytes()[4 - n..])
+}
+
+fn glyph_count(glyphs: &[u16], x: &[i32], y: &[i32]) -> IoResult<u16> {
+    if x.len() != glyphs.len() || y.len() != glyphs.len() {
+        return Err(invalid("XDV glyph positions must match the glyphs"));
+    }
+
+    u16::try_from(glyphs.len()).map_err(|_| invalid("too many glyphs in one XDV run"))
+}
+
+fn invalid(message: &str) -> IoError {
+    IoError::new(ErrorKind::InvalidInput, message)
+}
@@ -102,6 +102,7 @@ trait FontManagerBackend {
     fn get_platform_font_desc<'a>(&'a self, font: &'a PlatformFontRef) -> Cow<'a, CStr>;
     fn get_op_size_rec_and_style_flags(&self, font: &mut FontInfo);
     fn search_for_host_platform_fonts(&mut self, maps: &mut FontMaps, name: &CStr);
+    fn cache_all_host_platform_fonts(&mut self, maps: &mut FontMaps);
     fn read_names(&self, font: PlatformFontRef) -> NameCollection;
 }
 
@@ -300,6 +301,19 @@ impl TryFrom<u8> for Engine {
     }
 }
 
+/// A font installed on the host system that the engine can load by name.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SystemFont {
+    /// The PostScript name of the font.
+    pub ps_name: String,
+    /// The name of the font's family.
+    pub family_name: String,
+    /// The name of the font's style within its family.
+    pub style_name: String,
+    /// Where the font comes from, usually the path of its file.
+    pub desc: String,
+}
+
 /// Font manager - combines a font management backend such as FreeType or CoreText with a cache of
 /// loaded fonts and the engine to use.
 pub struct FontManager {
@@ -348,6 +362,53 @@ impl FontManager {
         })
     }
 
+    /// List all the fonts installed on the host system that can be loaded by
+    /// name, sorted by family and style.
+    pub fn system_fonts(&mut self) -> Vec<SystemFont> {
+        self.backend.cache_all_host_platform_fonts(&mut self.maps);
+
+        let mut fonts: Vec<_> = self
+            .maps
+            .fonts
+            .iter()
+            .map(|font| SystemFont {
+                ps_name: font.ps_name.to_string_lossy().into_owned(),
+                family_name: font.family_name.to_string_lossy().into_owned(),
+                style_name: font.style_name.to_string_lossy().into_owned(),
+                desc: self
+                    .backend
+                    .get_platform_font_desc(&font.font_ref)
+                    .to_string_lossy()
+                    .into_owned(),
+            })
+            .collect();
+
+        fonts.sort_by(|a, b| {
+            (&a.family_name, &a.style_name, &a.ps_name).cmp(&(
+                &b.family_name,
+                &b.style_name,
+                &b.ps_name,
+            ))
+        });
+        fonts
+    }
+
+    /// Bring the host system's record of its installed fonts up to date, so
+    /// that newly installed fonts can be found. With fontconfig, this rebuilds
+    /// any of its caches that are out of date. macOS keeps its own record up to
+    /// date, so there's nothing to do there.
+    ///
+    /// The global font manager is destroyed, so that it's recreated with the
+    /// new set of fonts. Returns false if the refresh failed.
+    pub fn refresh_system_fonts() -> bool {
+        Self::destroy();
+
+        #[cfg(target_os = "macos")]
+        return true;
+        #[cfg(not(target_os = "macos"))]
+        tectonic_bridge_fontconfig::reinitialize()
+    }
+
     /// Get the font for a given name, variant, and point size
     pub fn find_font(
         &mut self,
@@ -181,6 +181,20 @@ impl FontManagerBackend for FcBackend {
         }
     }
 
+    fn cache_all_host_platform_fonts(&mut self, maps: &mut FontMaps) {
+        for pos in 0..self.all_fonts.as_ref().fonts().len() {
+            let pat = self.all_fonts.as_ref().fonts()[pos].upgrade();
+            if maps.platform_ref_to_font.contains_key(&pat) {
+                continue;
+            }
+
+            let names = self.read_names(pat.clone());
+            maps.add_to_maps(self, pat, &names);
+        }
+
+        self.cached_all = true;
+    }
+
     fn read_names(&self, pat: PlatformFontRef) -> NameCollection {
         let mut names = NameCollection::default();
 
@@ -6,7 +6,7 @@ use crate::manager::{
 use std::borrow::Cow;
 use std::ffi::{CStr, CString};
 use tectonic_mac_core::{
-    CFArray, CFDictionary, CFSet, CFString, CFUrl, CTFont, CTFontDescriptor, CoreType,
+    CFArray, CFDictionary, CFSet, CFString, CFType, CFUrl, CTFont, CTFontDescriptor, CoreType,
     FontAttribute, FontNameKey,
 };
 
@@ -142,6 +142,14 @@ impl FontManagerBackend for MacBackend {
         }
     }
 
+    fn cache_all_host_platform_fonts(&mut self, maps: &mut FontMaps) {
+        // A descriptor with no attributes matches every font.
+        let attributes = CFDictionary::new(Vec::<(CFString, CFType)>::new());
+        let descriptor = CTFontDescriptor::new_with_attrs(&attributes);
+        let all = descriptor.matching_font_descriptors(&CFSet::new(&[]));
+        self.add_fonts_to_caches(maps, all);
+    }
+
     fn read_names(&self, font: PlatformFontRef) -> NameCollection {
         let mut names = NameCollection::default();
 
@@ -6,24 +6,33 @@
 //! `compile` subcommand of the "V2" / "cargo-like" interface.
 
 use clap::Parser;
-use std::path::{Path, PathBuf};
-use tectonic_bridge_core::{SecuritySettings, SecurityStance};
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::{Mutex, Once},
+    time::Duration,
+};
+use tectonic_bridge_core::{CancelFlag, SecuritySettings, SecurityStance};
 
 use tectonic::{
-    config::{maybe_return_test_bundle, PersistentConfig},
+    config::{is_config_test_mode_activated, maybe_return_test_bundle, PersistentConfig},
     driver::{OutputFormat, PassSetting, ProcessingSession, ProcessingSessionBuilder},
     errmsg,
     errors::{ErrorKind, Result},
     status::StatusBackend,
     tt_error, tt_note,
     unstable_opts::{UnstableArg, UnstableOptions},
+    InteractionMode,
 };
 
-use tectonic_bundles::detect_bundle;
+use tectonic_bundles::{detect_bundle, overlay::OverlayBundle};
 
 #[derive(Debug, Parser)]
 pub struct CompileOptions {
-    /// The file to process, or "-" to process the standard input stream
+    /// The file to process, or "-" to process the standard input stream. A ZIP
+    /// archive is processed by compiling the main TeX file inside it
     #[arg(value_hint = clap::ValueHint::FilePath)]
     input: String,
 
@@ -31,14 +40,23 @@ pub struct CompileOptions {
     #[arg(long, short, name = "path", default_value = "latex")]
     format: String,
 
-    /// Use this URL or path to find resource files instead of the default
+    /// Use this URL or path to find resource files instead of the default;
+    /// may be repeated, with earlier bundles shadowing later ones
     #[arg(long, short)]
-    bundle: Option<String>,
+    bundle: Vec<String>,
 
     /// Use only resource files cached locally
     #[arg(short = 'C', long)]
     only_cached: bool,
 
+    /// Never access the network, and fail if a resource file isn't cached locally
+    #[arg(long)]
+    offline: bool,
+
+    /// The TeX job name, which names the output files [default: the name of <input>]
+    #[arg(long, value_name = "name")]
+    jobname: Option<String>,
+
     /// The kind of output to generate
     #[arg(long, name = "format", default_value = "pdf")]
     outfmt: OutputFormat,
@@ -55,6 +73,21 @@ pub struct CompileOptions {
     #[arg(name = "count", long = "reruns", short = 'r')]
     reruns: Option<usize>,
 
+    /// Abort any engine pass that runs for longer than this many seconds
+    #[arg(long, value_name = "seconds")]
+    time_limit: Option<u64>,
+
+    /// Abort any engine pass during which the process uses more than this
+    /// many mebibytes of memory
+    #[arg(long, value_name = "MiB")]
+    memory_limit: Option<u64>,
+
+    /// The interaction mode in which to start the TeX engine: errorstopmode
+    /// halts at the first error, while batchmode, nonstopmode, and scrollmode
+    /// keep going
+    #[arg(long, value_name = "mode")]
+    interaction: Option<InteractionMode>,
+
     /// Keep the intermediate files generated during processing
     #[arg(short, long)]
     keep_intermediates: bool,
@@ -63,10 +96,18 @@ pub struct CompileOptions {
     #[arg(long)]
     keep_logs: bool,
 
+    /// Keep the intermediate and log files with these extensions, such as `aux,log,bbl`
+    #[arg(long, value_name = "extensions", value_delimiter = ',')]
+    keep: Vec<String>,
+
     /// Generate SyncTeX data
     #[arg(long)]
     synctex: bool,
 
+    /// Don't let the engine load fonts installed on this computer by name
+    #[arg(long)]
+    no_system_fonts: bool,
+
     /// Tell the engine that no file at <hide_path> exists, if it tries to read it
     #[arg(long, name = "hide_path")]
     hide: Option<Vec<PathBuf>>,
@@ -79,10 +120,23 @@ pub struct CompileOptions {
     #[arg(name = "outdir", short, long)]
     outdir: Option<PathBuf>,
 
+    /// Write the output document to <output_path>, or to the standard output
+    /// stream if it's "-", and no other files
+    #[arg(
+        long,
+        name = "output_path",
+        conflicts_with_all = ["outdir", "keep_intermediates", "keep_logs", "keep", "makefile_rules"]
+    )]
+    output: Option<PathBuf>,
+
     /// Input is untrusted -- disable all known-insecure features
     #[arg(long)]
     untrusted: bool,
 
+    /// Open the output document using the system handler
+    #[arg(long)]
+    open: bool,
+
     /// Unstable options. Pass -Zhelp to show a list
     #[arg(name = "option", short = 'Z')]
     unstable: Vec<UnstableArg>,
@@ -92,7 +146,44 @@ pub struct CompileOptions {
 
 //impl TectonicCommand for CompileOptions {
 impl CompileOptions {
+    /// The directories that relative file names in the engine's messages
+    /// should be resolved against: that of the primary input, if there is
+    /// one, and the current directory.
+    pub fn source_dirs(&self) -> Vec<PathBuf> {
+        let cwd = std::env::current_dir().unwrap_or_default();
+        let mut dirs = Vec::new();
+
+        if self.input != "-" {
+            if let Some(parent) = Path::new(&self.input).parent() {
+                dirs.push(cwd.join(parent));
+            }
+        }
+
+        dirs.push(cwd);
+        dirs
+    }
+
+    /// Whether the output document goes to the standard output stream, in
+    /// which case status messages must go to standard error.
+    pub fn writes_to_stdout(&self) -> bool {
+        self.output.as_deref() == Some(Path::new("-"))
+    }
+
     pub fn execute(self, config: PersistentConfig, status: &mut dyn StatusBackend) -> Result<i32> {
+        if self.output.is_some() && self.outfmt == OutputFormat::Html {
+            return Err(errmsg!(
+                "`--output` can't be used with HTML output, which has many files"
+            ));
+        }
+
+        let to_stdout = self.writes_to_stdout();
+
+        if to_stdout && self.print_stdout {
+            return Err(errmsg!(
+                "`--print` can't be used when the output goes to standard output"
+            ));
+        }
+
         let unstable = UnstableOptions::from_unstable_args(self.unstable.into_iter());
 
         // Default to allowing insecure since it would be super duper annoying
@@ -114,8 +205,10 @@ impl CompileOptions {
             .format_name(&format_path)
             .keep_logs(self.keep_logs)
             .keep_intermediates(self.keep_intermediates)
+            .keep_extensions(&self.keep)
             .format_cache_path(config.format_cache_path()?)
             .synctex(self.synctex)
+            .system_fonts(!self.no_system_fonts)
             .output_format(self.outfmt)
             .pass(self.pass);
 
@@ -123,6 +216,22 @@ impl CompileOptions {
             sess_builder.reruns(s);
         }
 
+        if let Some(secs) = self.time_limit {
+            sess_builder.time_limit(Duration::from_secs(secs));
+        }
+
+        if let Some(mib) = self.memory_limit {
+            sess_builder.memory_limit(mib.saturating_mul(1024 * 1024));
+        }
+
+        if let Some(mode) = self.interaction {
+            sess_builder.interaction_mode(mode);
+        }
+
+        if let Some(name) = &self.jobname {
+            sess_builder.job_name(name);
+        }
+
         if let Some(p) = self.makefile_rules {
             sess_builder.makefile_output_path(p);
         }
@@ -130,20 +239,44 @@ impl CompileOptions {
         // Input and path setup
 
         let input_path = self.input;
+        let tex_input_name;
+        let mut output_dir;
+
         if input_path == "-" {
             // Don't provide an input path to the ProcessingSession, so it will default to stdin.
-            sess_builder.tex_input_name("texput.tex");
-            sess_builder.output_dir(Path::new(""));
+            tex_input_name = "texput.tex".to_owned();
+            output_dir = PathBuf::new();
+            sess_builder.tex_input_name(&tex_input_name);
+            sess_builder.output_dir(&output_dir);
             tt_note!(
                 status,
-                "reading from standard input; outputs will appear under the base name \"texput\""
+                "reading from standard input; outputs will appear under the base name \"{}\"",
+                self.jobname.as_deref().unwrap_or("texput")
             );
         } else {
             let input_path = Path::new(&input_path);
-            sess_builder.primary_input_path(input_path);
+            let is_zip = input_path
+                .extension()
+                .is_some_and(|e| e.eq_ignore_ascii_case("zip"));
+
+            if is_zip {
+                sess_builder.primary_input_zip(input_path);
+            } else {
+                sess_builder.primary_input_path(input_path);
+            }
 
             if let Some(fname) = input_path.file_name() {
-                sess_builder.tex_input_name(&fname.to_string_lossy());
+                // Outputs from an archive are named after it, so that
+                // `paper.zip` produces `paper.pdf`.
+                tex_input_name = if is_zip {
+                    Path::new(fname)
+                        .with_extension("tex")
+                        .to_string_lossy()
+                        .into_owned()
+                } else {
+                    fname.to_string_lossy().into_owned()
+                };
+                sess_builder.tex_input_name(&tex_input_name);
             } else {
                 return Err(errmsg!(
                     "can't figure out a basename for input path \"{}\"",
@@ -152,7 +285,8 @@ impl CompileOptions {
             };
 
             if let Some(par) = input_path.parent() {
-                sess_builder.output_dir(par);
+                output_dir = par.to_owned();
+                sess_builder.output_dir(&output_dir);
             } else {
                 return Err(errmsg!(
                     "can't figure out a parent directory for input path \"{}\"",
@@ -161,14 +295,20 @@ impl CompileOptions {
             }
         }
 
-        if let Some(output_dir) = self.outdir {
-            if !output_dir.is_dir() {
+        if let Some(outdir) = self.outdir {
+            if !outdir.is_dir() {
                 return Err(errmsg!(
                     "output directory \"{}\" does not exist",
-                    output_dir.display()
+                    outdir.display()
                 ));
             }
-            sess_builder.output_dir(output_dir);
+            sess_builder.output_dir(&outdir);
+            output_dir = outdir;
+        }
+
+        // With `--output`, the output document is written below instead.
+        if self.output.is_some() {
+            sess_builder.do_not_write_output_files();
         }
 
         // Set up the rest of I/O.
@@ -185,16 +325,32 @@ impl CompileOptions {
             tt_note!(status, "using only cached resource files");
         }
 
-        if let Some(bundle) = self.bundle {
-            // TODO: this is ugly.
-            // It's probably a good idea to re-design our code so we
-            // don't need special cases for tests our source.
-            if let Ok(bundle) = maybe_return_test_bundle(Some(bundle.clone())) {
-                sess_builder.bundle(bundle);
-            } else if let Some(bundle) = detect_bundle(bundle.clone(), self.only_cached, None)? {
-                sess_builder.bundle(bundle);
+        if self.offline {
+            go_offline(status);
+        }
+
+        if !self.bundle.is_empty() {
+            let mut bundles = Vec::new();
+
+            for bundle in self.bundle {
+                // TODO: this is ugly.
+                // It's probably a good idea to re-design our code so we
+                // don't need special cases for tests our source.
+                if let Ok(bundle) = maybe_return_test_bundle(Some(bundle.clone())) {
+                    bundles.push(bundle);
+                } else if let Some(bundle) =
+                    detect_bundle(bundle.clone(), self.only_cached, None, None)?
+                {
+                    bundles.push(bundle);
+                } else {
+                    return Err(errmsg!("`{bundle}` doesn't specify a valid bundle."));
+                }
+            }
+
+            if bundles.len() == 1 {
+                sess_builder.bundle(bundles.pop().unwrap());
             } else {
-                return Err(errmsg!("`{bundle}` doesn't specify a valid bundle."));
+                sess_builder.bundle(Box::new(OverlayBundle::new(bundles)));
             }
         } else if let Ok(bundle) = maybe_return_test_bundle(None) {
             // TODO: this is ugly too.
@@ -204,11 +360,129 @@ impl CompileOptions {
         }
         sess_builder.build_date_from_env(deterministic_mode);
 
-        run_and_report(sess_builder, status).map(|_| 0)
+        let sess = run_and_report(sess_builder, status)?;
+
+        let out_name = Path::new(match &self.jobname {
+            Some(name) => name,
+            None => &tex_input_name,
+        })
+        .with_extension(self.outfmt.extension());
+
+        if let Some(output) = &self.output {
+            let Some(data) = sess.get_file_data(&out_name.to_string_lossy()) else {
+                return Err(errmsg!(
+                    "the output document `{}` wasn't created",
+                    out_name.display()
+                ));
+            };
+
+            if to_stdout {
+                let mut stdout = io::stdout().lock();
+                stdout.write_all(&data)?;
+                stdout.flush()?;
+            } else {
+                tt_note!(status, "writing `{}`", output.display());
+                fs::write(output, &data)?;
+            }
+        }
+
+        if self.open {
+            if to_stdout {
+                tt_note!(
+                    status,
+                    "not opening the output -- it went to standard output"
+                );
+            } else if self.outfmt == OutputFormat::Pdf {
+                let out_file = match &self.output {
+                    Some(path) => path.clone(),
+                    None => output_dir.join(&out_name),
+                };
+                open_output(&out_file, status);
+            } else {
+                tt_note!(
+                    status,
+                    "not opening the output -- only PDF output can be opened"
+                );
+            }
+        }
+
+        Ok(0)
+    }
+}
+
+/// Open a built document using the system handler, unless we're testing.
+pub(crate) fn open_output(out_file: &Path, status: &mut dyn StatusBackend) {
+    if is_config_test_mode_activated() {
+        tt_note!(status, "not opening `{}` -- test mode", out_file.display());
+    } else {
+        tt_note!(status, "opening `{}`", out_file.display());
+        if let Err(e) = open::that(out_file) {
+            tt_error!(
+                status,
+                "failed to open `{}` with system handler",
+                out_file.display();
+                e.into()
+            )
+        }
+    }
+}
+
+/// Disable all network access for the rest of the run, for `--offline`.
+/// Resource files that aren't cached are then errors, rather than being
+/// downloaded.
+pub(crate) fn go_offline(status: &mut dyn StatusBackend) {
+    tectonic_geturl::set_network_disabled(true);
+    tt_note!(
+        status,
+        "network access is disabled; using only cached resource files"
+    );
+}
+
+/// The flag that cancels the session that's running, if there is one.
+static RUNNING_SESSION: Mutex<Option<CancelFlag>> = Mutex::new(None);
+
+/// The exit code of a program that was interrupted with Ctrl-C, by Unix
+/// convention.
+pub(crate) const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+/// Make Ctrl-C cancel the running session, so that it stops without leaving
+/// partial output files behind. If there isn't a session running, or Ctrl-C
+/// is pressed again, the program exits right away as usual.
+fn install_interrupt_handler() {
+    static INSTALLED: Once = Once::new();
+
+    INSTALLED.call_once(|| {
+        // If the handler can't be installed, Ctrl-C keeps its default effect.
+        let _ = ctrlc::set_handler(|| match RUNNING_SESSION.lock().unwrap().as_ref() {
+            Some(flag) if !flag.is_cancelled() => flag.cancel(),
+            _ => process::exit(INTERRUPTED_EXIT_CODE),
+        });
+    });
+}
+
+/// Get the code with which to exit after a failure.
+pub(crate) fn failure_exit_code(e: &tectonic::errors::Error) -> i32 {
+    match e.kind() {
+        ErrorKind::Cancelled => INTERRUPTED_EXIT_CODE,
+        _ => 1,
     }
 }
 
 pub(crate) fn run_and_report(
+    mut sess_builder: ProcessingSessionBuilder,
+    status: &mut dyn StatusBackend,
+) -> Result<ProcessingSession> {
+    let flag = CancelFlag::new();
+    sess_builder.cancel_flag(flag.clone());
+    install_interrupt_handler();
+    *RUNNING_SESSION.lock().unwrap() = Some(flag);
+
+    let result = run_and_report_inner(sess_builder, status);
+    *RUNNING_SESSION.lock().unwrap() = None;
+    result
+}
+
+fn run_and_report_inner(
     sess_builder: ProcessingSessionBuilder,
     status: &mut dyn StatusBackend,
 ) -> Result<ProcessingSession> {
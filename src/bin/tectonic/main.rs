@@ -4,9 +4,18 @@
 
 //! `tectonic` binary - the main entry point for command-line users.
 
-use clap::{Parser, ValueEnum};
-use std::{env, io::IsTerminal, process};
-use tectonic_status_base::plain::PlainStatusBackend;
+use clap::{error::ErrorKind, CommandFactory, Parser, ValueEnum};
+use std::{
+    env,
+    fs::File,
+    io::IsTerminal,
+    path::{Path, PathBuf},
+    process,
+};
+use tectonic_status_base::{
+    json::JsonStatusBackend, lsp::LspStatusBackend, plain::PlainStatusBackend,
+    tee::TeeStatusBackend,
+};
 
 use tectonic::{
     config::PersistentConfig,
@@ -15,7 +24,7 @@ use tectonic::{
         termcolor::TermcolorStatusBackend,
         {ChatterLevel, StatusBackend},
     },
-    unstable_opts,
+    tt_error, unstable_opts,
 };
 
 mod compile;
@@ -52,6 +61,14 @@ struct CliOptions {
     #[arg(long = "color", default_value = "auto")]
     cli_color: CliColor,
 
+    /// How to format status messages
+    #[arg(long = "output-format", default_value = "human")]
+    output_format: OutputFormat,
+
+    /// Also write all status messages to this file, replacing it
+    #[arg(long, value_name = "path")]
+    build_log: Option<PathBuf>,
+
     #[command(flatten)]
     compile: compile::CompileOptions,
 }
@@ -78,6 +95,97 @@ impl CliColor {
     }
 }
 
+#[derive(ValueEnum, Clone, Debug, Eq, PartialEq)]
+enum OutputFormat {
+    /// Messages for people to read
+    #[value(name = "human")]
+    Human,
+
+    /// Newline-delimited JSON records on standard error
+    #[value(name = "json")]
+    Json,
+
+    /// Messages for people on standard error, then LSP diagnostics on
+    /// standard output
+    #[value(name = "lsp")]
+    Lsp,
+}
+
+impl OutputFormat {
+    /// Create the status backend for this format.
+    pub fn status_backend(
+        &self,
+        chatter: ChatterLevel,
+        color: &CliColor,
+        always_stderr: bool,
+    ) -> Box<dyn StatusBackend> {
+        // With LSP output, standard output is reserved for the diagnostics.
+        let always_stderr = always_stderr || *self == OutputFormat::Lsp;
+
+        if *self == OutputFormat::Json {
+            Box::new(JsonStatusBackend::new(chatter))
+        } else if color.should_enable() {
+            let mut sb = TermcolorStatusBackend::new(chatter);
+            sb.always_stderr(always_stderr);
+            Box::new(sb)
+        } else {
+            let mut sb = PlainStatusBackend::new(chatter);
+            sb.always_stderr(always_stderr);
+            Box::new(sb)
+        }
+    }
+}
+
+/// Write a copy of the messages of `status` to the file at `path`, if one was
+/// given with `--build-log`. The program exits if the file can't be created.
+fn tee_build_log(
+    mut status: Box<dyn StatusBackend>,
+    path: Option<&Path>,
+) -> Box<dyn StatusBackend> {
+    let Some(path) = path else {
+        return status;
+    };
+
+    match File::create(path) {
+        Ok(f) => Box::new(TeeStatusBackend::new(status, f)),
+        Err(e) => {
+            tt_error!(status, "couldn't create the build log `{}`", path.display(); e.into());
+            process::exit(1);
+        }
+    }
+}
+
+/// The error for when both the LSP diagnostics and the output document would
+/// go to standard output.
+const LSP_STDOUT_CONFLICT: &str =
+    "`--output-format lsp` can't be used when the output document goes to standard output";
+
+/// Run a command, collecting LSP diagnostics from its messages and printing
+/// them to standard output afterwards if `source_dirs` is given. These are
+/// the directories that relative file names are resolved against. The status
+/// backend is handed back for reporting the outcome.
+fn run_collecting_lsp<F, T>(
+    mut status: Box<dyn StatusBackend>,
+    source_dirs: Option<Vec<PathBuf>>,
+    run: F,
+) -> (T, Box<dyn StatusBackend>)
+where
+    F: FnOnce(&mut dyn StatusBackend) -> T,
+{
+    let Some(source_dirs) = source_dirs else {
+        return (run(&mut *status), status);
+    };
+
+    let mut lsp = LspStatusBackend::new(status, source_dirs);
+    let result = run(&mut lsp);
+
+    for params in lsp.publish_params() {
+        println!("{}", params.to_json());
+    }
+
+    (result, lsp.into_inner())
+}
+
 #[derive(Parser)]
 struct PeekUnstableOptions {
     #[arg(name = "option", short = 'Z')]
@@ -135,6 +243,12 @@ fn main() {
 
     let args = CliOptions::parse();
 
+    if args.output_format == OutputFormat::Lsp && args.compile.writes_to_stdout() {
+        CliOptions::command()
+            .error(ErrorKind::ArgumentConflict, LSP_STDOUT_CONFLICT)
+            .exit();
+    }
+
     // The Tectonic crate comes with a hidden internal "test mode" that forces
     // it to use a specified set of local files, rather than going to the
     // bundle -- this makes it so that we can run tests without having to go
@@ -170,18 +284,25 @@ fn main() {
     // something I'd be relatively OK with since it'd only affect the progam
     // UI, not the processing results).
 
-    let mut status = if args.cli_color.should_enable() {
-        Box::new(TermcolorStatusBackend::new(args.chatter_level)) as Box<dyn StatusBackend>
-    } else {
-        Box::new(PlainStatusBackend::new(args.chatter_level)) as Box<dyn StatusBackend>
-    };
+    let status = args.output_format.status_backend(
+        args.chatter_level,
+        &args.cli_color,
+        args.compile.writes_to_stdout(),
+    );
+    let status = tee_build_log(status, args.build_log.as_deref());
+    let source_dirs = (args.output_format == OutputFormat::Lsp).then(|| args.compile.source_dirs());
 
     // Now that we've got colorized output, pass off to the inner function ...
     // all so that we can print out the word "error:" in red. This code
     // parallels various bits of the `error_chain` crate.
 
-    if let Err(e) = args.compile.execute(config, &mut *status) {
+    let (result, mut status) = run_collecting_lsp(status, source_dirs, |status| {
+        args.compile.execute(config, status)
+    });
+
+    if let Err(e) = result {
+        let code = compile::failure_exit_code(&e);
         status.report_error(&SyncError::new(e).into());
-        process::exit(1)
+        process::exit(code)
     }
 }
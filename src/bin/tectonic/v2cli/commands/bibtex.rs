@@ -0,0 +1,86 @@
+use clap::Parser;
+use std::path::PathBuf;
+use tectonic::{
+    config::PersistentConfig,
+    driver::{PassSetting, ProcessingSessionBuilder},
+    errmsg,
+    errors::Result,
+    tt_note,
+    unstable_opts::UnstableOptions,
+};
+use tectonic_bridge_core::{SecuritySettings, SecurityStance};
+use tectonic_status_base::StatusBackend;
+
+use crate::v2cli::{CommandCustomizations, TectonicCommand};
+
+/// `bibtex`: Run BibTeX on an `.aux` file
+#[derive(Debug, Eq, PartialEq, Parser)]
+pub struct BibtexCommand {
+    /// Use only resource files cached locally
+    #[arg(short = 'C', long)]
+    only_cached: bool,
+
+    /// Never access the network, and fail if a resource file isn't cached locally
+    #[arg(long)]
+    offline: bool,
+
+    /// Include an item after this many crossrefs [default: 2]
+    #[arg(long)]
+    min_crossrefs: Option<u32>,
+
+    /// The `.aux` file to process
+    #[arg(value_hint = clap::ValueHint::FilePath)]
+    aux: PathBuf,
+}
+
+impl TectonicCommand for BibtexCommand {
+    fn customize(&self, _cc: &mut CommandCustomizations) {}
+
+    fn execute(self, config: PersistentConfig, status: &mut dyn StatusBackend) -> Result<i32> {
+        let aux_name = match self.aux.file_name() {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => {
+                return Err(errmsg!(
+                    "can't figure out a basename for input path \"{}\"",
+                    self.aux.display()
+                ));
+            }
+        };
+
+        if !self.aux.is_file() {
+            return Err(errmsg!("no such file \"{}\"", self.aux.display()));
+        }
+
+        let unstables = UnstableOptions {
+            min_crossrefs: self.min_crossrefs,
+            ..UnstableOptions::default()
+        };
+
+        if self.only_cached {
+            tt_note!(status, "using only cached resource files");
+        }
+
+        if self.offline {
+            crate::compile::go_offline(status);
+        }
+
+        // BibTeX has no insecure features, so there is no need for an
+        // `--untrusted` option.
+        let mut sess_builder = ProcessingSessionBuilder::new_with_security(SecuritySettings::new(
+            SecurityStance::DisableInsecures,
+        ));
+
+        // The format name is required, but won't be used since the TeX engine
+        // never runs.
+        sess_builder
+            .unstables(unstables)
+            .format_name("latex")
+            .primary_input_path(&self.aux)
+            .tex_input_name(&aux_name)
+            .keep_logs(true)
+            .bundle(config.default_bundle(self.only_cached)?)
+            .pass(PassSetting::Bibtex);
+
+        crate::compile::run_and_report(sess_builder, status).map(|_| 0)
+    }
+}
@@ -1,16 +1,41 @@
 use clap::Args;
+use flate2::{write::GzEncoder, Compression};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env,
+    fs::{self, File},
+    io::{self, Read, Write},
+    path::{Component, Path, PathBuf},
+    process::{Command, Output, Stdio},
+    sync::{mpsc, Mutex},
+    thread,
+    time::Duration,
+};
 use tectonic::{
-    config::is_config_test_mode_activated,
     config::PersistentConfig,
+    ctry,
     docmodel::{DocumentExt, DocumentSetupOptions},
+    errmsg,
     errors::Result,
-    tt_error, tt_note,
+    tt_error, tt_note, tt_warning, InteractionMode,
 };
 use tectonic_bridge_core::{SecuritySettings, SecurityStance};
-use tectonic_docmodel::workspace::Workspace;
+use tectonic_bundles::{
+    record::{RecordingBundle, UsedFiles},
+    tar::write_bundle,
+};
+use tectonic_docmodel::{
+    document::Document,
+    workspace::{find_document_dirs, Workspace},
+};
 use tectonic_status_base::StatusBackend;
 
-use crate::v2cli::{CommandCustomizations, TectonicCommand};
+use tokio::runtime;
+
+use crate::v2cli::{
+    commands::watch::{serve::PreviewServer, DEPS_FILE_ENV_VAR},
+    CommandCustomizations, TectonicCommand,
+};
 
 /// `build`: Build a document
 #[derive(Debug, Eq, PartialEq, Args, Clone)]
@@ -23,6 +48,10 @@ pub struct BuildCommand {
     #[arg(short = 'C', long)]
     only_cached: bool,
 
+    /// Never access the network, and fail if a resource file isn't cached locally
+    #[arg(long)]
+    offline: bool,
+
     /// Keep the intermediate files generated during processing
     #[arg(short, long)]
     keep_intermediates: bool,
@@ -31,10 +60,29 @@ pub struct BuildCommand {
     #[arg(long)]
     keep_logs: bool,
 
+    /// Keep the intermediate and log files with these extensions, such as `aux,log,bbl`
+    #[arg(long, value_name = "extensions", value_delimiter = ',')]
+    keep: Vec<String>,
+
     /// Print the engine's chatter during processing
     #[arg(long = "print", short)]
     print_stdout: bool,
 
+    /// Abort any engine pass that runs for longer than this many seconds
+    #[arg(long, value_name = "seconds")]
+    time_limit: Option<u64>,
+
+    /// Abort any engine pass during which the process uses more than this
+    /// many mebibytes of memory
+    #[arg(long, value_name = "MiB")]
+    memory_limit: Option<u64>,
+
+    /// The interaction mode in which to start the TeX engine: errorstopmode
+    /// halts at the first error, while batchmode, nonstopmode, and scrollmode
+    /// keep going
+    #[arg(long, value_name = "mode")]
+    interaction: Option<InteractionMode>,
+
     /// Open built document using system handler
     #[arg(long)]
     open: bool,
@@ -43,9 +91,48 @@ pub struct BuildCommand {
     #[arg(long, help = "Specify the target of the build.")]
     target: Option<String>,
 
+    /// Apply this build profile from `Tectonic.toml`
+    #[arg(long)]
+    profile: Option<String>,
+
     /// Use this URL to find resource files instead of the default
     #[arg(long, short)]
     bundle: Option<String>,
+
+    /// Pack the bundle files used by the build into a tarball at this path,
+    /// and make it the document's bundle
+    #[arg(long, value_name = "path")]
+    export_bundle: Option<PathBuf>,
+
+    /// Write the document's sources, and the bundle files that the build
+    /// used, to a self-contained `.tar.gz` archive at this path
+    #[arg(long, value_name = "path")]
+    archive: Option<PathBuf>,
+
+    /// Write the outputs' dependencies on the local files that the build read
+    /// to this path, as Makefile rules
+    #[arg(long, value_name = "path")]
+    makefile_deps: Option<PathBuf>,
+
+    /// Fetch the bundle files listed at this path all at once before building,
+    /// then list the ones that the build used there
+    #[arg(long, value_name = "path")]
+    bundle_deps: Option<PathBuf>,
+
+    /// Serve the built document over HTTP until interrupted
+    #[arg(long)]
+    serve: bool,
+
+    /// The port for `--serve` to listen on [default: chosen automatically]
+    #[arg(long, requires = "serve")]
+    port: Option<u16>,
+
+    /// Build every document in or below the current directory, in parallel
+    #[arg(
+        long,
+        conflicts_with_all = ["export_bundle", "archive", "makefile_deps", "bundle_deps", "serve"]
+    )]
+    all: bool,
 }
 
 impl TectonicCommand for BuildCommand {
@@ -58,7 +145,22 @@ impl TectonicCommand for BuildCommand {
             tt_note!(status, "--bundle {} ignored", url);
             tt_note!(status, "using workspace bundle configuration");
         }
-        let ws = Workspace::open_from_environment()?;
+
+        if self.all {
+            return self.build_all(status);
+        }
+
+        if self.offline {
+            crate::compile::go_offline(status);
+        }
+
+        let mut ws = Workspace::open_from_environment()?;
+
+        if let Some(profile) = &self.profile {
+            ws.first_document_mut().apply_profile(profile)?;
+            tt_note!(status, "using build profile `{}`", profile);
+        }
+
         let doc = ws.first_document();
 
         // Default to allowing insecure since it would be super duper annoying
@@ -75,6 +177,35 @@ impl TectonicCommand for BuildCommand {
             DocumentSetupOptions::new_with_security(SecuritySettings::new(stance));
         setup_options.only_cached(self.only_cached);
 
+        // If we're being run by `-X watch`, it wants to know which files the
+        // build read.
+        let deps_file = env::var_os(DEPS_FILE_ENV_VAR);
+        let collect_deps = deps_file.is_some() || self.makefile_deps.is_some();
+        let mut deps = Vec::new();
+        let mut targets = Vec::new();
+
+        // When exporting a bundle, the format files are regenerated in a
+        // scratch cache, so that the files needed to make them are recorded
+        // too.
+        let used_files = UsedFiles::default();
+        let export_format_dir = match self.export_bundle {
+            Some(_) => Some(ctry!(tempfile::tempdir(); "can't create a temporary directory")),
+            None => None,
+        };
+
+        // When archiving, the main file of each output and the source files
+        // that the builds read are gathered up, along with the bundle files.
+        let record_bundle =
+            self.export_bundle.is_some() || self.archive.is_some() || self.bundle_deps.is_some();
+        let mut archive_mains = BTreeMap::new();
+        let mut archive_sources = BTreeMap::new();
+
+        // The bundle files that the last build used are fetched up front.
+        let prefetch = match &self.bundle_deps {
+            Some(path) => read_bundle_deps(path)?,
+            None => Vec::new(),
+        };
+
         for output_name in doc.output_names() {
             if let Some(out) = self.target.as_ref() {
                 if out != output_name {
@@ -84,33 +215,493 @@ impl TectonicCommand for BuildCommand {
 
             let mut builder = doc.setup_session(output_name, &setup_options, status)?;
 
+            if record_bundle {
+                let bundle = doc.bundle(&setup_options)?;
+                builder.bundle(Box::new(RecordingBundle::new(bundle, used_files.clone())));
+            }
+
+            if let Some(dir) = &export_format_dir {
+                builder.format_cache_path(dir.path());
+            } else {
+                builder.format_cache_path(config.format_cache_path()?);
+            }
+
             builder
-                .format_cache_path(config.format_cache_path()?)
+                .prefetch_bundle_files(&prefetch)
                 .keep_intermediates(self.keep_intermediates)
                 .keep_logs(self.keep_logs)
+                .keep_extensions(&self.keep)
                 .print_stdout(self.print_stdout);
 
-            crate::compile::run_and_report(builder, status)?;
+            if let Some(secs) = self.time_limit {
+                builder.time_limit(Duration::from_secs(secs));
+            }
+
+            if let Some(mib) = self.memory_limit {
+                builder.memory_limit(mib.saturating_mul(1024 * 1024));
+            }
+
+            if let Some(mode) = self.interaction {
+                builder.interaction_mode(mode);
+            }
+
+            let sess = crate::compile::run_and_report(builder, status)?;
+
+            if collect_deps {
+                deps.extend(sess.filesystem_inputs());
+                targets.push(doc.output_main_file(output_name));
+            }
+
+            if self.archive.is_some() {
+                archive_mains.insert(
+                    format!("{output_name}.tex"),
+                    doc.primary_input_text(output_name)?,
+                );
+                archive_sources.extend(sess.filesystem_input_files());
+            }
 
             if self.open {
-                let out_file = doc.output_main_file(output_name);
-
-                if is_config_test_mode_activated() {
-                    tt_note!(status, "not opening `{}` -- test mode", out_file.display());
-                } else {
-                    tt_note!(status, "opening `{}`", out_file.display());
-                    if let Err(e) = open::that(&out_file) {
-                        tt_error!(
-                            status,
-                            "failed to open `{}` with system handler",
-                            out_file.display();
-                            e.into()
-                        )
+                crate::compile::open_output(&doc.output_main_file(output_name), status);
+            }
+        }
+
+        if let Some(dest) = &self.archive {
+            write_archive(
+                ws.first_document(),
+                dest,
+                &archive_mains,
+                &archive_sources,
+                &used_files.borrow(),
+                &setup_options,
+                status,
+            )?;
+        }
+
+        if let Some(dest) = &self.export_bundle {
+            let doc = ws.first_document_mut();
+            export_bundle(doc, dest, &used_files.borrow(), &setup_options, status)?;
+        }
+
+        if let Some(dest) = &self.bundle_deps {
+            let mut text = String::new();
+
+            for name in used_files.borrow().iter() {
+                text.push_str(name);
+                text.push('\n');
+            }
+
+            ctry!(
+                fs::write(dest, text);
+                "couldn't write the bundle dependency list `{}`", dest.display()
+            );
+        }
+
+        if let Some(deps_file) = deps_file {
+            let cwd = env::current_dir()?;
+            let mut text = String::new();
+
+            for path in &deps {
+                text.push_str(&cwd.join(path).to_string_lossy());
+                text.push('\n');
+            }
+
+            ctry!(
+                fs::write(&deps_file, text);
+                "couldn't write the dependency list `{}`", deps_file.to_string_lossy()
+            );
+        }
+
+        if let Some(dest) = &self.makefile_deps {
+            // The build also depends on its settings.
+            deps.push(ws.first_document().src_dir().join("Tectonic.toml"));
+            write_makefile_deps(dest, &targets, &deps)?;
+        }
+
+        if self.serve {
+            serve_document(ws.first_document(), self.port.unwrap_or(0), status)?;
+        }
+
+        Ok(0)
+    }
+}
+
+impl BuildCommand {
+    /// Build every document in or below the current directory.
+    ///
+    /// The engines keep global state, so that only one of them can run at a
+    /// time in a process. Each document is therefore built by a separate
+    /// `tectonic -X build` process, several at once, and their results are
+    /// reported as they finish.
+    fn build_all(&self, status: &mut dyn StatusBackend) -> Result<i32> {
+        let root = env::current_dir()?;
+        let dirs = find_document_dirs(&root)?;
+
+        if dirs.is_empty() {
+            return Err(errmsg!(
+                "could not find `Tectonic.toml` in `{}` or any subdirectory",
+                root.display()
+            ));
+        }
+
+        let exe = env::current_exe()?;
+        let args = self.document_args();
+        let n_jobs = thread::available_parallelism()
+            .map_or(1, |n| n.get())
+            .min(dirs.len());
+        let queue = Mutex::new(dirs.iter());
+        let mut n_failed = 0;
+
+        thread::scope(|scope| {
+            let (tx, rx) = mpsc::channel();
+
+            for _ in 0..n_jobs {
+                let tx = tx.clone();
+                let (exe, args, queue) = (&exe, &args, &queue);
+
+                scope.spawn(move || loop {
+                    let Some(dir) = queue.lock().unwrap().next() else {
+                        break;
+                    };
+
+                    let result = Command::new(exe)
+                        .args(["-X", "build"])
+                        .args(args)
+                        .current_dir(dir)
+                        .env_remove(DEPS_FILE_ENV_VAR)
+                        .stdin(Stdio::null())
+                        .output();
+
+                    if tx.send((dir, result)).is_err() {
+                        break;
                     }
+                });
+            }
+
+            drop(tx);
+
+            for (dir, result) in rx {
+                let name = match dir.strip_prefix(&root) {
+                    Ok(p) if p.as_os_str().is_empty() => ".".to_owned(),
+                    Ok(p) => p.display().to_string(),
+                    Err(_) => dir.display().to_string(),
+                };
+
+                if !report_document_build(&name, result, self.print_stdout, status) {
+                    n_failed += 1;
                 }
             }
+        });
+
+        if n_failed > 0 {
+            return Err(errmsg!(
+                "{} of {} documents failed to build",
+                n_failed,
+                dirs.len()
+            ));
         }
 
+        tt_note!(status, "built {} documents", dirs.len());
         Ok(0)
     }
+
+    /// The arguments that pass this command's options on to the build of
+    /// each document by [`Self::build_all`].
+    fn document_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        let flags = [
+            (self.untrusted, "--untrusted"),
+            (self.only_cached, "--only-cached"),
+            (self.offline, "--offline"),
+            (self.keep_intermediates, "--keep-intermediates"),
+            (self.keep_logs, "--keep-logs"),
+            (self.print_stdout, "--print"),
+            (self.open, "--open"),
+        ];
+
+        for (set, flag) in flags {
+            if set {
+                args.push(flag.to_owned());
+            }
+        }
+
+        if !self.keep.is_empty() {
+            args.push("--keep".to_owned());
+            args.push(self.keep.join(","));
+        }
+
+        if let Some(target) = &self.target {
+            args.push("--target".to_owned());
+            args.push(target.clone());
+        }
+
+        if let Some(profile) = &self.profile {
+            args.push("--profile".to_owned());
+            args.push(profile.clone());
+        }
+
+        if let Some(secs) = self.time_limit {
+            args.push("--time-limit".to_owned());
+            args.push(secs.to_string());
+        }
+
+        if let Some(mib) = self.memory_limit {
+            args.push("--memory-limit".to_owned());
+            args.push(mib.to_string());
+        }
+
+        if let Some(mode) = self.interaction {
+            args.push("--interaction".to_owned());
+            args.push(mode.to_string());
+        }
+
+        args
+    }
+}
+
+/// Report how the build of the document in the directory `name` went, and
+/// return whether it succeeded. The output of failed builds is shown in full.
+fn report_document_build(
+    name: &str,
+    result: io::Result<Output>,
+    print_stdout: bool,
+    status: &mut dyn StatusBackend,
+) -> bool {
+    let output = match result {
+        Ok(o) => o,
+        Err(e) => {
+            tt_error!(status, "couldn't run the build of `{}`", name; e.into());
+            return false;
+        }
+    };
+
+    if output.status.success() {
+        if print_stdout {
+            let _ = io::stdout().write_all(&output.stdout);
+        }
+
+        tt_note!(status, "built `{}`", name);
+        true
+    } else {
+        tt_error!(status, "failed to build `{}`", name);
+        let mut logs = output.stdout;
+        logs.extend_from_slice(&output.stderr);
+        status.dump_error_logs(&logs);
+        false
+    }
+}
+
+/// Read a list of bundle files written by `--bundle-deps`, which is empty if
+/// there hasn't been a build to write it yet.
+fn read_bundle_deps(path: &Path) -> Result<Vec<String>> {
+    let text = match fs::read_to_string(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        r => ctry!(r; "couldn't read the bundle dependency list `{}`", path.display()),
+    };
+
+    Ok(text
+        .lines()
+        .filter(|l| !l.is_empty())
+        .map(str::to_owned)
+        .collect())
+}
+
+/// Write a Makefile-format dependency file at `dest`, stating that the
+/// `targets` depend on the `deps`. Both Make and Ninja understand the format,
+/// as written by C compilers with `-MD`. Relative paths are made absolute.
+fn write_makefile_deps(dest: &Path, targets: &[PathBuf], deps: &[PathBuf]) -> Result<()> {
+    let cwd = env::current_dir()?;
+    let escape = |path: &Path| {
+        let mut text = String::new();
+
+        for c in cwd.join(path).to_string_lossy().chars() {
+            match c {
+                ' ' | '#' => {
+                    text.push('\\');
+                    text.push(c);
+                }
+                '$' => text.push_str("$$"),
+                c => text.push(c),
+            }
+        }
+
+        text
+    };
+
+    let targets: Vec<String> = targets.iter().map(|p| escape(p)).collect();
+    let deps: BTreeSet<String> = deps.iter().map(|p| escape(p)).collect();
+
+    let mut text = targets.join(" ");
+    text.push(':');
+
+    for dep in deps {
+        text.push_str(" \\\n  ");
+        text.push_str(&dep);
+    }
+
+    text.push('\n');
+
+    ctry!(
+        fs::write(dest, text);
+        "couldn't write the dependency file `{}`", dest.display()
+    );
+    Ok(())
+}
+
+/// Serve the built document with the same preview server as `watch`, until
+/// the program is interrupted.
+fn serve_document(doc: &Document, port: u16, status: &mut dyn StatusBackend) -> Result<()> {
+    let rt = ctry!(
+        runtime::Builder::new_multi_thread().enable_all().build();
+        "failed to start the preview server"
+    );
+
+    rt.block_on(async {
+        let (_server, url) = PreviewServer::start_for_document(doc, port).await?;
+        tt_note!(
+            status,
+            "serving a preview of the document at {}; press Ctrl-C to stop",
+            url
+        );
+        std::future::pending::<Result<()>>().await
+    })
+}
+
+/// Write a gzipped tarball at `dest` that holds the main file of each output,
+/// the source files that the builds read, and the named bundle files, all
+/// under the names by which the engines opened them. The document can then be
+/// built from the archive alone, by Tectonic or by other TeX tools.
+fn write_archive(
+    doc: &Document,
+    dest: &Path,
+    mains: &BTreeMap<String, String>,
+    sources: &BTreeMap<String, PathBuf>,
+    bundle_names: &BTreeSet<String>,
+    setup_options: &DocumentSetupOptions,
+    status: &mut dyn StatusBackend,
+) -> Result<()> {
+    let mut files: BTreeMap<&str, Vec<u8>> = BTreeMap::new();
+
+    for (name, path) in sources {
+        // Files opened by absolute paths, or from outside of the search
+        // directories, don't have a place in the archive.
+        if Path::new(name)
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir))
+        {
+            tt_warning!(
+                status,
+                "not archiving `{}`, which is outside of the document",
+                name
+            );
+            continue;
+        }
+
+        let data = ctry!(fs::read(path); "couldn't read `{}`", path.display());
+        files.insert(name, data);
+    }
+
+    for (name, text) in mains {
+        if files.contains_key(name.as_str()) {
+            return Err(errmsg!(
+                "the archive's main file `{}` has the same name as one of the document's files",
+                name
+            ));
+        }
+
+        files.insert(name, text.clone().into_bytes());
+    }
+
+    // Files in the document take precedence over bundle files, just as they
+    // did during the build.
+    let mut bundle = doc.bundle(setup_options)?;
+
+    for name in bundle_names {
+        if files.contains_key(name.as_str()) {
+            continue;
+        }
+
+        let mut data = Vec::new();
+        let mut ih = ctry!(
+            bundle.input_open_name(name, status).must_exist();
+            "couldn't open bundle file `{}`", name
+        );
+        ctry!(ih.read_to_end(&mut data); "couldn't read bundle file `{}`", name);
+        files.insert(name, data);
+    }
+
+    let file = ctry!(File::create(dest); "couldn't create `{}`", dest.display());
+    let mut tar = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+
+    for (name, data) in &files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(0);
+        ctry!(
+            tar.append_data(&mut header, name, &data[..]);
+            "couldn't write `{}` to the archive", name
+        );
+    }
+
+    let mut gz = ctry!(tar.into_inner(); "couldn't write the archive `{}`", dest.display());
+    ctry!(gz.flush(); "couldn't write the archive `{}`", dest.display());
+    ctry!(gz.try_finish(); "couldn't write the archive `{}`", dest.display());
+
+    tt_note!(
+        status,
+        "wrote the document's sources and {} bundle files to `{}`",
+        bundle_names.len(),
+        dest.display()
+    );
+    Ok(())
+}
+
+/// Pack the named files of the document's bundle into a new tar bundle at
+/// `dest`, and switch the document over to it.
+fn export_bundle(
+    doc: &mut Document,
+    dest: &Path,
+    names: &BTreeSet<String>,
+    setup_options: &DocumentSetupOptions,
+    status: &mut dyn StatusBackend,
+) -> Result<()> {
+    let mut bundle = doc.bundle(setup_options)?;
+
+    // Write to a temporary file first, since the document's current bundle
+    // might be the one that we're replacing.
+    let dest_dir = match dest.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    };
+    let mut file = ctry!(
+        tempfile::NamedTempFile::new_in(dest_dir);
+        "can't create a temporary file in `{}`", dest_dir.display()
+    );
+
+    ctry!(
+        write_bundle(&mut *bundle, names.iter().map(|n| n.as_str()), &mut file, status);
+        "failed to export the bundle to `{}`", dest.display()
+    );
+    ctry!(file.persist(dest); "failed to write `{}`", dest.display());
+
+    // Refer to the new bundle relative to the document, if we can.
+    let dest = ctry!(dest.canonicalize(); "failed to find `{}`", dest.display());
+    let loc = match doc
+        .src_dir()
+        .canonicalize()
+        .ok()
+        .and_then(|src_dir| dest.strip_prefix(src_dir).ok().map(Path::to_owned))
+    {
+        Some(rel) => rel.display().to_string(),
+        None => dest.display().to_string(),
+    };
+
+    doc.pin_bundle(loc.clone())?;
+    tt_note!(
+        status,
+        "exported {} bundle files to `{}`, which is now the document's bundle",
+        names.len(),
+        loc
+    );
+    Ok(())
 }
@@ -1,28 +1,56 @@
 use super::{
     create::{BundleCreateCommand, BundleFormat},
     pack::bundlev1::BundleV1,
-    select::{picker::FilePicker, spec::BundleSpec},
+    select::{
+        picker::FilePicker,
+        spec::{BundleSpec, TEXLIVE_INCLUDE_FILES, TEXLIVE_INCLUDE_SOURCE},
+    },
 };
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use std::{
     cmp::Ordering,
     fs::{self, File},
     io::Read,
+    path::PathBuf,
     thread,
     time::Duration,
 };
 use tracing::{error, info, warn};
 
-pub(super) fn select(cli: &BundleCreateCommand) -> Result<()> {
-    let bundle_dir = cli
-        .bundle_spec
+/// Load the bundle specification that `cli` asks for, along with the
+/// directory that its relative paths are resolved against.
+fn load_spec(cli: &BundleCreateCommand) -> Result<(BundleSpec, PathBuf)> {
+    if let Some(texlive) = &cli.from_texlive {
+        let texlive = texlive
+            .canonicalize()
+            .with_context(|| format!("while opening TeX Live installation {texlive:?}"))?;
+
+        // Accept either the root of an installation or its `texmf-dist`.
+        let texmf_dist = if texlive.join("texmf-dist").is_dir() {
+            texlive.join("texmf-dist")
+        } else {
+            texlive.clone()
+        };
+
+        if !texmf_dist.join("tex").is_dir() {
+            bail!("{texlive:?} doesn't look like a TeX Live installation: no `texmf-dist/tex` directory");
+        }
+
+        let name = cli.name.as_deref().unwrap_or("texlive");
+        return Ok((BundleSpec::from_texlive(name, texmf_dist), texlive));
+    }
+
+    // Clap makes sure that we have one or the other.
+    let bundle_spec = cli.bundle_spec.as_ref().unwrap();
+
+    let bundle_dir = bundle_spec
         .canonicalize()
         .unwrap()
         .parent()
         .unwrap()
         .to_path_buf();
 
-    let mut file = File::open(&cli.bundle_spec)?;
+    let mut file = File::open(bundle_spec)?;
     let mut file_str = String::new();
     file.read_to_string(&mut file_str)?;
     let bundle_config: BundleSpec = match toml::from_str(&file_str) {
@@ -33,6 +61,12 @@ pub(super) fn select(cli: &BundleCreateCommand) -> Result<()> {
         }
     };
 
+    Ok((bundle_config, bundle_dir))
+}
+
+pub(super) fn select(cli: &BundleCreateCommand) -> Result<()> {
+    let (bundle_config, bundle_dir) = load_spec(cli)?;
+
     if let Err(e) = bundle_config.validate() {
         error!("failed to validate bundle specification");
         return Err(e);
@@ -65,6 +99,9 @@ pub(super) fn select(cli: &BundleCreateCommand) -> Result<()> {
     for source in sources {
         picker.add_source(cli, &source)?;
     }
+    if cli.from_texlive.is_some() {
+        picker.add_generated_files(TEXLIVE_INCLUDE_SOURCE, TEXLIVE_INCLUDE_FILES)?;
+    }
     picker.finish(true)?;
 
     // Print statistics
@@ -86,7 +123,11 @@ pub(super) fn select(cli: &BundleCreateCommand) -> Result<()> {
         let mut hash = String::new();
         file.read_to_string(&mut hash)?;
         let hash = hash.trim();
-        if hash != bundle_config.bundle.expected_hash {
+        if cli.from_texlive.is_some() {
+            // There's no configuration to check against. Print the hash so
+            // that it can be pinned.
+            info!("final bundle hash is {hash}");
+        } else if hash != bundle_config.bundle.expected_hash {
             warn!("final bundle hash doesn't match bundle configuration:");
             warn!("bundle hash is {hash}");
             warn!("config hash is {}", bundle_config.bundle.expected_hash);
@@ -100,10 +141,7 @@ pub(super) fn select(cli: &BundleCreateCommand) -> Result<()> {
 }
 
 pub(super) fn pack(cli: &BundleCreateCommand) -> Result<()> {
-    let mut file = File::open(&cli.bundle_spec)?;
-    let mut file_str = String::new();
-    file.read_to_string(&mut file_str)?;
-    let bundle_config: BundleSpec = toml::from_str(&file_str)?;
+    let (bundle_config, _) = load_spec(cli)?;
 
     let build_dir = cli.build_dir.join(&bundle_config.bundle.name);
 
@@ -53,7 +53,19 @@ pub struct BundleCreateCommand {
     pub job: BundleJob,
 
     /// Bundle specification TOML file.
-    pub bundle_spec: PathBuf,
+    #[arg(required_unless_present = "from_texlive")]
+    pub bundle_spec: Option<PathBuf>,
+
+    /// Build the bundle from this TeX Live installation instead of from a
+    /// specification file. This may be the root of the installation or its
+    /// `texmf-dist` directory.
+    #[arg(long, value_name = "DIR", conflicts_with = "bundle_spec")]
+    pub from_texlive: Option<PathBuf>,
+
+    /// The name of the bundle built with `--from-texlive`.
+    /// Defaults to `texlive`.
+    #[arg(long, conflicts_with = "bundle_spec")]
+    pub name: Option<String>,
 
     /// Build directory for this bundle.
     /// Will be removed.
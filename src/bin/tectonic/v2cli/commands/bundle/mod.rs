@@ -7,7 +7,7 @@ use tectonic::{
     tt_note,
 };
 use tectonic_bundles::Bundle;
-use tectonic_docmodel::workspace::Workspace;
+use tectonic_docmodel::{document::wildcard_match, workspace::Workspace};
 use tectonic_status_base::StatusBackend;
 
 use crate::v2cli::{CommandCustomizations, TectonicCommand};
@@ -120,7 +120,7 @@ struct BundleSearchCommand {
     #[arg(short = 'C', long)]
     only_cached: bool,
 
-    #[arg(help = "The search term")]
+    #[arg(help = "The search term, or a pattern using `*` and `?` wildcards")]
     term: Option<String>,
 }
 
@@ -135,7 +135,11 @@ impl BundleSearchCommand {
 
         // Is there a better way to do this?
         let filter: Box<dyn Fn(&str) -> bool> = if let Some(t) = self.term {
-            Box::new(move |s: &str| s.contains(&t))
+            if t.contains(['*', '?']) {
+                Box::new(move |s: &str| wildcard_match(&t, s))
+            } else {
+                Box::new(move |s: &str| s.contains(&t))
+            }
         } else {
             Box::new(|_: &str| true)
         };
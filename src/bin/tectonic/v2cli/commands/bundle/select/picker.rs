@@ -478,6 +478,24 @@ impl FilePicker {
         Ok(())
     }
 
+    /// Add files that aren't read from any input to this bundle under
+    /// `source_name`. These are given as paths and contents.
+    pub fn add_generated_files(&mut self, source: &str, files: &[(&str, &str)]) -> Result<()> {
+        info!("adding generated files under `{source}`");
+
+        for (path, content) in files {
+            self.add_file(
+                Path::new(path),
+                source,
+                &mut Cursor::new(content.as_bytes()),
+                &HashMap::new(),
+            )?;
+        }
+
+        *self.stats.added.entry(source.to_owned()).or_default() += files.len();
+        Ok(())
+    }
+
     pub fn finish(&mut self, save_debug_files: bool) -> Result<()> {
         info!("writing auxillary files");
 
@@ -26,8 +26,118 @@ impl BundleSpec {
 
         Ok(())
     }
+
+    /// Make a bundle specification that takes the files of a local TeX Live
+    /// installation, given the path to its `texmf-dist` directory.
+    ///
+    /// This uses the same search order and ignore patterns as the bundles
+    /// in `./bundles`. Only the files that are installed are included, so
+    /// the package set is whatever was installed into the tree.
+    pub fn from_texlive(name: &str, texmf_dist: PathBuf) -> Self {
+        let strings = |v: &[&str]| v.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+
+        let texlive = BundleInput {
+            source: BundleInputSource::Directory { path: texmf_dist },
+            ignore: Some(strings(TEXLIVE_IGNORE)),
+            patch_dir: None,
+            search_order: Some(strings(TEXLIVE_SEARCH_ORDER)),
+        };
+
+        BundleSpec {
+            bundle: BundleConfig {
+                name: name.to_owned(),
+                expected_hash: String::new(),
+                search_order: vec![
+                    BundleSearchOrder::Plain("/".to_owned()),
+                    BundleSearchOrder::Plain(format!("/{TEXLIVE_INCLUDE_SOURCE}//")),
+                    BundleSearchOrder::Input {
+                        input: "texlive".to_owned(),
+                    },
+                ],
+                ignore: Some(strings(BUNDLE_IGNORE)),
+            },
+            inputs: HashMap::from([("texlive".to_owned(), texlive)]),
+        }
+    }
 }
 
+/// The source under which the extra files that Tectonic needs are added to
+/// bundles made with [`BundleSpec::from_texlive`].
+pub const TEXLIVE_INCLUDE_SOURCE: &str = "include";
+
+/// The extra files that Tectonic needs in bundles made with
+/// [`BundleSpec::from_texlive`], as paths and contents.
+pub const TEXLIVE_INCLUDE_FILES: &[(&str, &str)] = &[
+    (
+        "tectonic/tectonic-format-latex.tex",
+        "\\input xelatex.ini\n",
+    ),
+    (
+        "tectonic/tectonic-format-plain.tex",
+        "\\input plain \\dump\n",
+    ),
+];
+
+const BUNDLE_IGNORE: &[&str] = &[
+    r".*/00readme\.txt",
+    r".*/LICENSE\.md",
+    r".*/Makefile",
+    r".*/README",
+    r".*/README.md",
+    r".*/readme\.txt",
+    r".*/ls-R",
+    r".*\.fmt",
+    r".*\.log",
+    r".*\.lua",
+    r".*\.mf",
+    r".*\.pl",
+    r".*\.ps",
+];
+
+const TEXLIVE_IGNORE: &[&str] = &[
+    "xindy/.*",
+    "tex4ht/.*",
+    "tex/uplatex/.*",
+    "tex/uptex/.*",
+    "tex/cslatex/.*",
+    "tex/csplain/.*",
+    "tex/ptex/.*",
+    "tex/platex/.*",
+    "tex/lollipop/.*",
+    "tex/context/.*",
+    "context/.*",
+    "texdoc/.*",
+    "texdoctk/.*",
+    "texconfig/.*",
+    "scripts/.*",
+    "dvips/.*",
+    "asymptote/.*",
+    "makeindex/.*",
+    "luatex-cache/.*",
+    "hb2gf/.*",
+    "chktex/.*",
+    "source/.*",
+    "doc/.*",
+    "tex/luatex/.*",
+    "tex/lualatex/.*",
+    "tex/lambda/.*",
+    "omega/.*",
+];
+
+const TEXLIVE_SEARCH_ORDER: &[&str] = &[
+    "tex/{xelatex,latex,xetex,plain,generic}//",
+    "bibtex/{bib,bst,csf}//",
+    "web2c//",
+    "fonts//",
+    "biber//",
+    "mft//",
+    "dvips//",
+    "makeindex//",
+    "{web,cweb}//",
+    "ttf2pk//",
+    "dvipdfmx/",
+];
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct BundleConfig {
     /// The bundle's name
@@ -0,0 +1,567 @@
+use byte_unit::{Byte, UnitType};
+use clap::{Parser, Subcommand};
+use std::{
+    collections::HashSet,
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+use tectonic::{config::PersistentConfig, errors::Result, tt_note, tt_warning};
+use tectonic_bundles::cache::lock_bundle_data;
+use tectonic_io_base::app_dirs;
+use tectonic_status_base::StatusBackend;
+
+use crate::v2cli::{CommandCustomizations, TectonicCommand};
+
+/// `cache`: Manage the per-user cache of resource files
+#[derive(Debug, Eq, PartialEq, Parser)]
+pub struct CacheCommand {
+    #[command(subcommand)]
+    command: CacheCommands,
+}
+
+#[derive(Debug, Eq, PartialEq, Subcommand)]
+enum CacheCommands {
+    #[command(name = "status")]
+    /// Show what the cache contains and how much space it uses
+    Status(CacheStatusCommand),
+
+    #[command(name = "prune")]
+    /// Delete cached files that are no longer needed
+    Prune(CachePruneCommand),
+}
+
+impl TectonicCommand for CacheCommand {
+    fn customize(&self, cc: &mut CommandCustomizations) {
+        match &self.command {
+            CacheCommands::Status(c) => c.customize(cc),
+            CacheCommands::Prune(c) => c.customize(cc),
+        }
+    }
+
+    fn execute(self, config: PersistentConfig, status: &mut dyn StatusBackend) -> Result<i32> {
+        match self.command {
+            CacheCommands::Status(c) => c.execute(config, status),
+            CacheCommands::Prune(c) => c.execute(config, status),
+        }
+    }
+}
+
+/// A file in the cache.
+#[derive(Debug)]
+struct CachedFile {
+    path: PathBuf,
+    size: u64,
+    last_used: SystemTime,
+}
+
+/// The contents of the per-user cache, grouped by the bundle digest that they
+/// belong to.
+///
+/// The bundle cache lives in `bundles/`, where `hashes/` records the current
+/// digest of each bundle location that has been used, `data/<digest>/` holds
+/// the files of that version of the bundle, and `data/<digest>.index` is its
+/// index. Formats are cached in `formats/` as
+/// `<digest>-<name>-<serial>.fmt`.
+#[derive(Debug, Default)]
+struct CacheContents {
+    bundles_dir: PathBuf,
+    formats_dir: PathBuf,
+
+    /// Digests that some bundle location currently refers to.
+    current: HashSet<String>,
+
+    /// Bundle data files, by digest.
+    data: Vec<(String, Vec<CachedFile>)>,
+
+    /// Format files, with the digest of the bundle that they were made from,
+    /// if they're usable by this version of Tectonic.
+    formats: Vec<(Option<String>, CachedFile)>,
+}
+
+fn size_text(size: u64) -> String {
+    format!(
+        "{:.1}",
+        Byte::from_u64(size).get_appropriate_unit(UnitType::Binary)
+    )
+}
+
+fn cached_file(path: PathBuf) -> Result<CachedFile> {
+    let meta = fs::metadata(&path)?;
+
+    // Access times aren't always tracked, but files are written once and never
+    // modified, so the modification time is a reasonable fallback.
+    let last_used = meta.accessed().or_else(|_| meta.modified())?;
+
+    Ok(CachedFile {
+        path,
+        size: meta.len(),
+        last_used,
+    })
+}
+
+/// Recursively collect the files under `dir`.
+fn collect_files(dir: &Path, files: &mut Vec<CachedFile>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+
+        if entry.file_type()?.is_dir() {
+            collect_files(&entry.path(), files)?;
+        } else {
+            files.push(cached_file(entry.path())?);
+        }
+    }
+
+    Ok(())
+}
+
+/// Recursively remove the empty directories under `dir`, including any that
+/// become empty along the way.
+fn remove_empty_dirs(dir: &Path) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+
+        if entry.file_type()?.is_dir() {
+            remove_empty_dirs(&entry.path())?;
+
+            // This fails if the directory isn't empty, which is fine.
+            let _ = fs::remove_dir(entry.path());
+        }
+    }
+
+    Ok(())
+}
+
+impl CacheContents {
+    fn scan(config: &PersistentConfig) -> Result<Self> {
+        let mut contents = CacheContents {
+            bundles_dir: app_dirs::get_user_cache_dir("bundles")?,
+            formats_dir: config.format_cache_path()?,
+            ..CacheContents::default()
+        };
+
+        let hashes_dir = contents.bundles_dir.join("hashes");
+
+        if hashes_dir.is_dir() {
+            for entry in fs::read_dir(&hashes_dir)? {
+                let text = fs::read_to_string(entry?.path())?;
+                contents.current.insert(text.trim().to_owned());
+            }
+        }
+
+        let data_dir = contents.bundles_dir.join("data");
+
+        if data_dir.is_dir() {
+            for entry in fs::read_dir(&data_dir)? {
+                let entry = entry?;
+                let name = entry.file_name().to_string_lossy().into_owned();
+
+                // Lock files are left alone, since other runs may be
+                // waiting on them.
+                if name.ends_with(".lock") {
+                    continue;
+                }
+
+                let (digest, files) = if entry.file_type()?.is_dir() {
+                    let mut files = Vec::new();
+                    collect_files(&entry.path(), &mut files)?;
+                    (name, files)
+                } else {
+                    // An index file, or a temporary file left over from an
+                    // interrupted download.
+                    let digest = name.split('.').next().unwrap_or_default().to_owned();
+                    (digest, vec![cached_file(entry.path())?])
+                };
+
+                match contents.data.iter_mut().find(|(d, _)| *d == digest) {
+                    Some((_, existing)) => existing.extend(files),
+                    None => contents.data.push((digest, files)),
+                }
+            }
+        }
+
+        contents.data.sort_by(|a, b| a.0.cmp(&b.0));
+
+        if contents.formats_dir.is_dir() {
+            let suffix = format!("-{}.fmt", tectonic_engine_xetex::FORMAT_SERIAL);
+
+            for entry in fs::read_dir(&contents.formats_dir)? {
+                let entry = entry?;
+                let name = entry.file_name().to_string_lossy().into_owned();
+
+                if !name.ends_with(".fmt") {
+                    continue;
+                }
+
+                let digest = if name.ends_with(&suffix) {
+                    name.split('-').next().map(str::to_owned)
+                } else {
+                    None
+                };
+
+                contents.formats.push((digest, cached_file(entry.path())?));
+            }
+        }
+
+        Ok(contents)
+    }
+
+    fn is_current(&self, digest: &Option<String>) -> bool {
+        digest.as_ref().is_some_and(|d| self.current.contains(d))
+    }
+
+    /// Describe the contents of the cache, for `status`.
+    fn report(&self) -> String {
+        let mut text = String::new();
+        let mut total = 0;
+
+        let _ = writeln!(text, "bundle cache: {}", self.bundles_dir.display());
+
+        for (digest, files) in &self.data {
+            let size = files.iter().map(|f| f.size).sum::<u64>();
+            let state = if self.current.contains(digest) {
+                "current"
+            } else {
+                "stale"
+            };
+            let _ = writeln!(
+                text,
+                "  {digest}: {} files, {} ({state})",
+                files.len(),
+                size_text(size)
+            );
+            total += size;
+        }
+
+        let _ = writeln!(text, "format cache: {}", self.formats_dir.display());
+
+        for (digest, file) in &self.formats {
+            let state = if self.is_current(digest) {
+                "current"
+            } else {
+                "stale"
+            };
+            let name = file.path.file_name().unwrap_or_default().to_string_lossy();
+            let _ = writeln!(text, "  {name}: {} ({state})", size_text(file.size));
+            total += file.size;
+        }
+
+        let _ = writeln!(text, "total: {}", size_text(total));
+        text
+    }
+
+    /// Work out which files `prune` should delete to bring the cache down to
+    /// `max_size` bytes, if given.
+    fn files_to_prune(&self, max_size: Option<u64>) -> Vec<&CachedFile> {
+        let mut doomed = Vec::new();
+        let mut kept = Vec::new();
+
+        // Files of bundle versions that nothing refers to any more, and
+        // formats made from them or by other versions of Tectonic, will
+        // never be used again.
+
+        for (digest, files) in self.data.iter() {
+            if self.current.contains(digest) {
+                kept.extend(files);
+            } else {
+                doomed.extend(files);
+            }
+        }
+
+        for (digest, file) in self.formats.iter() {
+            if self.is_current(digest) {
+                kept.push(file);
+            } else {
+                doomed.push(file);
+            }
+        }
+
+        // Then, if we need to, delete the rest of the files in the order in
+        // which they were last used. Bundle indexes are tiny and needed to
+        // use the bundle at all, so they're left alone.
+
+        if let Some(max_size) = max_size {
+            let mut size = kept.iter().map(|f| f.size).sum::<u64>();
+
+            kept.sort_by_key(|f| f.last_used);
+
+            for file in kept {
+                if size <= max_size {
+                    break;
+                }
+
+                if file.path.extension().is_some_and(|e| e == "index") {
+                    continue;
+                }
+
+                size -= file.size;
+                doomed.push(file);
+            }
+        }
+
+        doomed
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Parser)]
+struct CacheStatusCommand {}
+
+impl CacheStatusCommand {
+    fn customize(&self, cc: &mut CommandCustomizations) {
+        cc.always_stderr = true;
+    }
+
+    fn execute(self, config: PersistentConfig, _status: &mut dyn StatusBackend) -> Result<i32> {
+        let contents = CacheContents::scan(&config)?;
+        print!("{}", contents.report());
+        Ok(0)
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Parser)]
+struct CachePruneCommand {
+    /// After deleting stale files, delete the least recently used files until
+    /// the cache is no bigger than this (e.g. `500MiB`)
+    #[arg(long)]
+    max_size: Option<Byte>,
+
+    /// Report what would be deleted, but don't delete anything
+    #[arg(long)]
+    dry_run: bool,
+}
+
+impl CachePruneCommand {
+    fn customize(&self, _cc: &mut CommandCustomizations) {}
+
+    fn execute(self, config: PersistentConfig, status: &mut dyn StatusBackend) -> Result<i32> {
+        let contents = CacheContents::scan(&config)?;
+        let doomed = contents.files_to_prune(self.max_size.map(|s| s.as_u64()));
+        let freed = doomed.iter().map(|f| f.size).sum::<u64>();
+
+        if self.dry_run {
+            for file in &doomed {
+                println!("{}", file.path.display());
+            }
+
+            tt_note!(
+                status,
+                "would delete {} files, freeing {}",
+                doomed.len(),
+                size_text(freed)
+            );
+            return Ok(0);
+        }
+
+        let doomed_paths: HashSet<&Path> = doomed.iter().map(|f| f.path.as_path()).collect();
+        let mut remove = |file: &CachedFile| {
+            if doomed_paths.contains(file.path.as_path()) {
+                if let Err(e) = fs::remove_file(&file.path) {
+                    tt_warning!(status, "couldn't delete `{}`", file.path.display(); e.into());
+                }
+            }
+        };
+
+        // Runs of tectonic hold the lock on a bundle version while adding
+        // files to it, so hold it too while deleting them.
+        for (digest, files) in &contents.data {
+            if !files
+                .iter()
+                .any(|f| doomed_paths.contains(f.path.as_path()))
+            {
+                continue;
+            }
+
+            let _lock = lock_bundle_data(&contents.bundles_dir, digest)?;
+            files.iter().for_each(&mut remove);
+        }
+
+        for (_, file) in &contents.formats {
+            remove(file);
+        }
+
+        // Clean up directories that are now empty, so that they don't show
+        // up as cached bundle versions.
+        let data_dir = contents.bundles_dir.join("data");
+
+        if data_dir.is_dir() {
+            remove_empty_dirs(&data_dir)?;
+        }
+
+        tt_note!(
+            status,
+            "deleted {} files, freeing {}",
+            doomed.len(),
+            size_text(freed)
+        );
+        Ok(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        env,
+        fs::{File, FileTimes},
+        sync::Mutex,
+        time::Duration,
+    };
+    use tectonic_status_base::NoopStatusBackend;
+
+    /// `TECTONIC_CACHE_DIR` is process-wide, so the tests take turns.
+    static CACHE_DIR_LOCK: Mutex<()> = Mutex::new(());
+
+    /// Run `f` with `TECTONIC_CACHE_DIR` pointing at a new temporary directory.
+    fn with_cache_dir(f: impl FnOnce(&Path)) {
+        let _guard = CACHE_DIR_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let dir = tempfile::tempdir().unwrap();
+        env::set_var("TECTONIC_CACHE_DIR", dir.path());
+        f(dir.path());
+        env::remove_var("TECTONIC_CACHE_DIR");
+    }
+
+    /// Create a cache file of `size` bytes, last used `age` seconds after the
+    /// epoch.
+    fn put(root: &Path, path: &str, size: usize, age: u64) {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, vec![b'x'; size]).unwrap();
+
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(age);
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_times(FileTimes::new().set_accessed(time).set_modified(time))
+            .unwrap();
+    }
+
+    /// Populate a cache in which the bundle `aaa` is current and `bbb` stale.
+    fn populate(root: &Path) {
+        let serial = tectonic_engine_xetex::FORMAT_SERIAL;
+        put(root, "bundles/hashes/some-url", 0, 0);
+        fs::write(root.join("bundles/hashes/some-url"), "aaa\n").unwrap();
+        put(root, "bundles/data/aaa.index", 10, 0);
+        put(root, "bundles/data/aaa.lock", 0, 0);
+        put(root, "bundles/data/aaa/tex/old.sty", 100, 1000);
+        put(root, "bundles/data/aaa/new.sty", 100, 3000);
+        put(root, "bundles/data/bbb/stale.sty", 100, 5000);
+        put(root, &format!("formats/aaa-latex-{serial}.fmt"), 100, 2000);
+        put(root, &format!("formats/bbb-latex-{serial}.fmt"), 100, 5000);
+        put(root, "formats/aaa-latex-0.fmt", 100, 5000);
+    }
+
+    fn scan() -> CacheContents {
+        CacheContents::scan(&PersistentConfig::default()).unwrap()
+    }
+
+    fn names(files: &[&CachedFile]) -> Vec<String> {
+        let mut names: Vec<_> = files
+            .iter()
+            .map(|f| f.path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn status() {
+        with_cache_dir(|root| {
+            populate(root);
+            let report = scan().report();
+            let serial = tectonic_engine_xetex::FORMAT_SERIAL;
+
+            let (s100, s210) = (size_text(100), size_text(210));
+
+            assert!(report.contains(&format!("  aaa: 3 files, {s210} (current)\n")));
+            assert!(report.contains(&format!("  bbb: 1 files, {s100} (stale)\n")));
+            assert!(report.contains(&format!("  aaa-latex-{serial}.fmt: {s100} (current)\n")));
+            assert!(report.contains(&format!("  bbb-latex-{serial}.fmt: {s100} (stale)\n")));
+            assert!(report.contains(&format!("  aaa-latex-0.fmt: {s100} (stale)\n")));
+            assert!(report.ends_with(&format!("total: {}\n", size_text(610))));
+        });
+    }
+
+    #[test]
+    fn stale_files() {
+        with_cache_dir(|root| {
+            populate(root);
+            let serial = tectonic_engine_xetex::FORMAT_SERIAL;
+
+            assert_eq!(
+                names(&scan().files_to_prune(None)),
+                [
+                    "aaa-latex-0.fmt".to_owned(),
+                    format!("bbb-latex-{serial}.fmt"),
+                    "stale.sty".to_owned(),
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn max_size() {
+        with_cache_dir(|root| {
+            populate(root);
+            let serial = tectonic_engine_xetex::FORMAT_SERIAL;
+            let contents = scan();
+
+            // 310 bytes are kept after deleting the stale files, so getting
+            // down to 250 only takes the least recently used one.
+            let doomed = contents.files_to_prune(Some(250));
+            assert_eq!(doomed.len(), 4);
+            assert!(names(&doomed).contains(&"old.sty".to_owned()));
+
+            // Then in order of use, but never the index.
+            let doomed = contents.files_to_prune(Some(0));
+            assert!(doomed
+                .iter()
+                .all(|f| f.path.extension().unwrap_or_default() != "index"));
+            assert_eq!(
+                doomed[3..]
+                    .iter()
+                    .map(|f| f.path.file_name().unwrap().to_string_lossy().into_owned())
+                    .collect::<Vec<_>>(),
+                [
+                    "old.sty".to_owned(),
+                    format!("aaa-latex-{serial}.fmt"),
+                    "new.sty".to_owned(),
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn prune() {
+        with_cache_dir(|root| {
+            populate(root);
+
+            let cmd = CachePruneCommand {
+                max_size: None,
+                dry_run: true,
+            };
+            cmd.execute(
+                PersistentConfig::default(),
+                &mut NoopStatusBackend::default(),
+            )
+            .unwrap();
+            assert!(root.join("bundles/data/bbb/stale.sty").exists());
+
+            let cmd = CachePruneCommand {
+                max_size: None,
+                dry_run: false,
+            };
+            cmd.execute(
+                PersistentConfig::default(),
+                &mut NoopStatusBackend::default(),
+            )
+            .unwrap();
+            assert!(!root.join("bundles/data/bbb").exists());
+            assert!(root.join("bundles/data/bbb.lock").exists());
+            assert!(!root.join("formats/aaa-latex-0.fmt").exists());
+            assert!(root.join("bundles/data/aaa/tex/old.sty").exists());
+            assert!(root.join("bundles/data/aaa.index").exists());
+        });
+    }
+}
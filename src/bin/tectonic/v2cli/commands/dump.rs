@@ -26,6 +26,10 @@ pub struct DumpCommand {
     #[arg(short = 'C', long)]
     only_cached: bool,
 
+    /// Never access the network, and fail if a resource file isn't cached locally
+    #[arg(long)]
+    offline: bool,
+
     /// Use the specified output profile for the partial build
     #[arg(short = 'p', long)]
     profile: Option<String>,
@@ -46,6 +50,10 @@ impl TectonicCommand for DumpCommand {
     }
 
     fn execute(self, config: PersistentConfig, status: &mut dyn StatusBackend) -> Result<i32> {
+        if self.offline {
+            crate::compile::go_offline(status);
+        }
+
         let ws = Workspace::open_from_environment()?;
         let doc = ws.first_document();
 
@@ -0,0 +1,108 @@
+use std::io::Write;
+
+use clap::Parser;
+use tectonic::{
+    config::PersistentConfig,
+    ctry,
+    docmodel::{DocumentExt, DocumentSetupOptions},
+    driver::PassSetting,
+    errors::Result,
+    tt_error,
+};
+use tectonic_bridge_core::{SecuritySettings, SecurityStance};
+use tectonic_docmodel::workspace::Workspace;
+use tectonic_status_base::StatusBackend;
+
+use crate::v2cli::{CommandCustomizations, TectonicCommand};
+
+/// `dump-text`: Run a partial build and print the document's plain text
+#[derive(Debug, Eq, PartialEq, Parser)]
+pub struct DumpTextCommand {
+    /// Document is untrusted -- disable all known-insecure features
+    #[arg(long)]
+    untrusted: bool,
+
+    /// Use only resource files cached locally
+    #[arg(short = 'C', long)]
+    only_cached: bool,
+
+    /// Never access the network, and fail if a resource file isn't cached locally
+    #[arg(long)]
+    offline: bool,
+
+    /// Use the specified output profile for the partial build
+    #[arg(short = 'p', long)]
+    profile: Option<String>,
+}
+
+impl TectonicCommand for DumpTextCommand {
+    fn customize(&self, cc: &mut CommandCustomizations) {
+        cc.always_stderr = true;
+        cc.minimal_chatter = true;
+    }
+
+    fn execute(self, config: PersistentConfig, status: &mut dyn StatusBackend) -> Result<i32> {
+        if self.offline {
+            crate::compile::go_offline(status);
+        }
+
+        let ws = Workspace::open_from_environment()?;
+        let doc = ws.first_document();
+
+        // See `dump` for why insecure features are allowed by default.
+        let stance = if self.untrusted {
+            SecurityStance::DisableInsecures
+        } else {
+            SecurityStance::MaybeAllowInsecures
+        };
+
+        let mut setup_options =
+            DocumentSetupOptions::new_with_security(SecuritySettings::new(stance));
+        setup_options.only_cached(self.only_cached);
+
+        // If output profile is unspecified, just grab one at (pseudo-)random.
+        let output_name = self
+            .profile
+            .as_ref()
+            .unwrap_or_else(|| doc.outputs.keys().next().unwrap());
+
+        let mut builder = doc.setup_session(output_name, &setup_options, status)?;
+
+        builder
+            .format_cache_path(config.format_cache_path()?)
+            .actual_text(true)
+            .pass(PassSetting::Tex);
+
+        let sess = crate::compile::run_and_report(builder, status)?;
+        let files = sess.into_file_data();
+
+        let Some((name, info)) = files
+            .iter()
+            .find(|(name, _)| name.ends_with(".xdv") || name.ends_with(".spx"))
+        else {
+            tt_error!(
+                status,
+                "the TeX engine did not produce any XDV or SPX output"
+            );
+            return Ok(1);
+        };
+
+        let pages = ctry!(
+            tectonic_xdv::extract_text(&info.data[..]);
+            "failed to parse `{}`", name
+        );
+
+        // Pages are separated with form feeds, like `pdftotext` does.
+        let mut stdout = std::io::stdout().lock();
+
+        for (i, page) in pages.iter().enumerate() {
+            if i > 0 {
+                ctry!(stdout.write_all(b"\x0c"); "error writing document text");
+            }
+
+            ctry!(stdout.write_all(page.as_bytes()); "error writing document text");
+        }
+
+        Ok(0)
+    }
+}
@@ -0,0 +1,103 @@
+use clap::{Parser, Subcommand};
+use tectonic::{config::PersistentConfig, errmsg, errors::Result, tt_note};
+use tectonic_status_base::StatusBackend;
+use tectonic_xetex_layout::manager::{FontManager, SystemFont};
+
+use crate::v2cli::{CommandCustomizations, TectonicCommand};
+
+/// `fonts`: Inspect the fonts installed on this computer
+#[derive(Debug, Eq, PartialEq, Parser)]
+pub struct FontsCommand {
+    #[command(subcommand)]
+    command: FontsCommands,
+}
+
+#[derive(Debug, Eq, PartialEq, Subcommand)]
+enum FontsCommands {
+    #[command(name = "list")]
+    /// List the installed fonts that documents can load by name
+    List(FontsListCommand),
+
+    #[command(name = "refresh")]
+    /// Bring the system's font cache up to date
+    Refresh(FontsRefreshCommand),
+}
+
+impl TectonicCommand for FontsCommand {
+    fn customize(&self, cc: &mut CommandCustomizations) {
+        match &self.command {
+            FontsCommands::List(c) => c.customize(cc),
+            FontsCommands::Refresh(c) => c.customize(cc),
+        }
+    }
+
+    fn execute(self, config: PersistentConfig, status: &mut dyn StatusBackend) -> Result<i32> {
+        match self.command {
+            FontsCommands::List(c) => c.execute(config, status),
+            FontsCommands::Refresh(c) => c.execute(config, status),
+        }
+    }
+}
+
+/// Describe a font on one line, for `list`.
+fn font_line(font: &SystemFont) -> String {
+    format!(
+        "{}\t{}\t{}\t{}",
+        font.family_name, font.style_name, font.ps_name, font.desc
+    )
+}
+
+#[derive(Debug, Eq, PartialEq, Parser)]
+struct FontsListCommand {
+    /// Only list the fonts in this family
+    #[arg(long)]
+    family: Option<String>,
+}
+
+impl FontsListCommand {
+    fn customize(&self, cc: &mut CommandCustomizations) {
+        cc.always_stderr = true;
+    }
+
+    fn execute(self, _config: PersistentConfig, status: &mut dyn StatusBackend) -> Result<i32> {
+        let fonts = FontManager::with_font_manager(|mgr| mgr.system_fonts());
+        let mut n_listed = 0;
+
+        for font in &fonts {
+            if self
+                .family
+                .as_ref()
+                .is_some_and(|f| !f.eq_ignore_ascii_case(&font.family_name))
+            {
+                continue;
+            }
+
+            println!("{}", font_line(font));
+            n_listed += 1;
+        }
+
+        tt_note!(status, "listed {} of {} fonts", n_listed, fonts.len());
+        Ok(0)
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Parser)]
+struct FontsRefreshCommand {}
+
+impl FontsRefreshCommand {
+    fn customize(&self, _cc: &mut CommandCustomizations) {}
+
+    fn execute(self, _config: PersistentConfig, status: &mut dyn StatusBackend) -> Result<i32> {
+        if !FontManager::refresh_system_fonts() {
+            return Err(errmsg!("failed to refresh the system's font cache"));
+        }
+
+        let n_fonts = FontManager::with_font_manager(|mgr| mgr.system_fonts().len());
+        tt_note!(
+            status,
+            "the system's font cache is up to date, with {} fonts",
+            n_fonts
+        );
+        Ok(0)
+    }
+}
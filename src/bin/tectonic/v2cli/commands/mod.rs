@@ -1,6 +1,11 @@
+pub mod bibtex;
 pub mod build;
 pub mod bundle;
+pub mod cache;
 pub mod dump;
+pub mod dump_text;
+pub mod fonts;
 pub mod new;
+pub mod self_cmd;
 pub mod show;
 pub mod watch;
@@ -1,10 +1,15 @@
 use clap::Parser;
-use std::env;
-use std::path::PathBuf;
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+use std::{env, fs};
 use tectonic::{
-    config::PersistentConfig, ctry, docmodel::WorkspaceCreatorExt, errors::Result, tt_note,
+    config::PersistentConfig, ctry, docmodel::WorkspaceCreatorExt, errmsg, errors::Result, tt_note,
+    tt_warning,
 };
-use tectonic_docmodel::workspace::WorkspaceCreator;
+use tectonic_docmodel::{document::InputFile, workspace::WorkspaceCreator};
+use tectonic_io_base::app_dirs;
 use tectonic_status_base::StatusBackend;
 
 use crate::v2cli::{CommandCustomizations, TectonicCommand};
@@ -15,6 +20,10 @@ pub struct InitCommand {
     /// Use this URL to find resource files instead of the default
     #[arg(long, short)]
     bundle: Option<String>,
+
+    /// The main file of an existing project [default: detected automatically]
+    #[arg(long, value_hint = clap::ValueHint::FilePath)]
+    main: Option<PathBuf>,
 }
 
 impl TectonicCommand for InitCommand {
@@ -22,21 +31,297 @@ impl TectonicCommand for InitCommand {
 
     fn execute(self, config: PersistentConfig, status: &mut dyn StatusBackend) -> Result<i32> {
         let path = env::current_dir()?;
+
+        let main = match self.main {
+            Some(main) => Some(main),
+            None => find_main_file(&path, status)?,
+        };
+
+        let main = match main {
+            Some(main) => main,
+            None => {
+                tt_note!(
+                    status,
+                    "creating new document in this directory ({})",
+                    path.display()
+                );
+
+                let wc = WorkspaceCreator::new(path);
+                ctry!(
+                    wc.create_defaulted(&config, self.bundle);
+                    "failed to create the new Tectonic workspace"
+                );
+                return Ok(0);
+            }
+        };
+
+        let main_text = ctry!(
+            fs::read_to_string(path.join(&main));
+            "couldn't read the main file `{}`", main.display()
+        );
+
         tt_note!(
             status,
-            "creating new document in this directory ({})",
+            "creating a document for the existing project in this directory ({})",
             path.display()
         );
+        tt_note!(status, "using `{}` as the main file", main.display());
+
+        // The sources stay where they are, so the project directory is added to
+        // the search path, along with the directories of any bibliography
+        // databases that we find elsewhere.
+        let mut extra_paths = vec![PathBuf::from(".")];
+
+        for database in find_bib_databases(&path, &main_text) {
+            if path.join(&database).is_file() {
+                tt_note!(status, "found bibliography database `{}`", database);
+                continue;
+            }
+
+            let file_name = Path::new(&database).file_name().unwrap_or_default();
+
+            match find_file(&path, Path::new(""), file_name) {
+                Some(found) => {
+                    tt_note!(
+                        status,
+                        "found bibliography database `{}` as `{}`",
+                        database,
+                        found.display()
+                    );
+
+                    if let Some(dir) = found.parent() {
+                        if !extra_paths.iter().any(|p| p == dir) {
+                            extra_paths.push(dir.to_owned());
+                        }
+                    }
+                }
+                None => {
+                    tt_warning!(status, "couldn't find bibliography database `{}`", database);
+                }
+            }
+        }
 
         let wc = WorkspaceCreator::new(path);
         ctry!(
-            wc.create_defaulted(&config, self.bundle);
+            wc.create_for_existing_defaulted(
+                &config,
+                self.bundle,
+                extra_paths,
+                vec![InputFile::File(main.to_string_lossy().into_owned())],
+            );
             "failed to create the new Tectonic workspace"
         );
+
+        // Untrusted builds ignore `extra_paths`, which is where all of this
+        // document's sources are found.
+        tt_note!(
+            status,
+            "the sources are found through `extra_paths`, so `tectonic -X build --untrusted` won't be able to build this document"
+        );
         Ok(0)
     }
 }
 
+/// Remove the comments from some TeX source.
+fn strip_comments(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+
+    for line in text.lines() {
+        let mut escaped = false;
+
+        for c in line.chars() {
+            if c == '%' && !escaped {
+                break;
+            }
+
+            escaped = c == '\\' && !escaped;
+            stripped.push(c);
+        }
+
+        stripped.push('\n');
+    }
+
+    stripped
+}
+
+/// Get the mandatory arguments of every use of the control sequence `\name`
+/// in some TeX source, skipping any optional arguments.
+fn command_args(text: &str, name: &str) -> Vec<String> {
+    let pattern = format!("\\{name}");
+    let mut args = Vec::new();
+    let mut rest = text;
+
+    while let Some(idx) = rest.find(&pattern) {
+        rest = &rest[idx + pattern.len()..];
+
+        // Make sure that we didn't just match a prefix of some other control
+        // sequence, like `\bibliographystyle`.
+        if rest.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            continue;
+        }
+
+        let mut after = rest.trim_start();
+
+        if after.starts_with('[') {
+            match after.find(']') {
+                Some(end) => after = after[end + 1..].trim_start(),
+                None => continue,
+            }
+        }
+
+        if let Some(after) = after.strip_prefix('{') {
+            if let Some(end) = after.find('}') {
+                args.push(after[..end].trim().to_owned());
+            }
+        }
+    }
+
+    args
+}
+
+/// Look for the main file of an existing TeX project in `dir`: a `.tex` file
+/// that uses `\documentclass`.
+///
+/// Returns `None` if the directory doesn't contain any TeX files at all, in
+/// which case we should create a new document from scratch.
+fn find_main_file(dir: &Path, status: &mut dyn StatusBackend) -> Result<Option<PathBuf>> {
+    let mut tex_files = Vec::new();
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+
+        if path.is_file() && path.extension().is_some_and(|e| e == "tex") {
+            tex_files.push(path);
+        }
+    }
+
+    if tex_files.is_empty() {
+        return Ok(None);
+    }
+
+    tex_files.sort();
+
+    let mut candidates = Vec::new();
+
+    for path in tex_files {
+        // Files that aren't UTF-8 are very unlikely to be the main file.
+        let text = fs::read_to_string(&path).unwrap_or_default();
+
+        if !command_args(&strip_comments(&text), "documentclass").is_empty() {
+            candidates.push(PathBuf::from(path.file_name().unwrap()));
+        }
+    }
+
+    // If there are several candidates, prefer the conventional names.
+    let dir_name = dir
+        .file_name()
+        .map(|n| format!("{}.tex", n.to_string_lossy()))
+        .unwrap_or_default();
+
+    let preferred = candidates
+        .iter()
+        .position(|c| c == Path::new("main.tex"))
+        .or_else(|| candidates.iter().position(|c| *c == Path::new(&dir_name)));
+
+    match (candidates.len(), preferred) {
+        (0, _) => Err(errmsg!(
+            "couldn't find a main TeX file in this directory; use `--main` to specify one"
+        )),
+        (1, _) => Ok(candidates.pop()),
+        (_, Some(idx)) => Ok(Some(candidates.swap_remove(idx))),
+        (_, None) => {
+            let names = candidates
+                .iter()
+                .map(|c| format!("`{}`", c.display()))
+                .collect::<Vec<_>>()
+                .join(", ");
+            tt_warning!(
+                status,
+                "several files could be the main file ({}); use `--main` to choose a different one",
+                names
+            );
+            Ok(Some(candidates.swap_remove(0)))
+        }
+    }
+}
+
+/// Find the bibliography databases used by some TeX source, following
+/// `\input` and `\include` into other files in `dir`.
+fn find_bib_databases(dir: &Path, main_text: &str) -> Vec<String> {
+    let mut databases = Vec::new();
+    let mut seen = HashSet::new();
+    let mut pending = vec![strip_comments(main_text)];
+
+    while let Some(text) = pending.pop() {
+        for arg in command_args(&text, "bibliography") {
+            for name in arg.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+                let name = if name.ends_with(".bib") {
+                    name.to_owned()
+                } else {
+                    format!("{name}.bib")
+                };
+
+                if !databases.contains(&name) {
+                    databases.push(name);
+                }
+            }
+        }
+
+        for name in command_args(&text, "addbibresource") {
+            if !databases.contains(&name) {
+                databases.push(name);
+            }
+        }
+
+        for cmd in ["input", "include"] {
+            for name in command_args(&text, cmd) {
+                let name = if name.ends_with(".tex") {
+                    name
+                } else {
+                    format!("{name}.tex")
+                };
+
+                if seen.insert(name.clone()) {
+                    if let Ok(text) = fs::read_to_string(dir.join(&name)) {
+                        pending.push(strip_comments(&text));
+                    }
+                }
+            }
+        }
+    }
+
+    databases
+}
+
+/// Search `root` for a file with the given name, skipping hidden directories
+/// and the build directory. Returns its path relative to `root`.
+fn find_file(root: &Path, rel_dir: &Path, file_name: &OsStr) -> Option<PathBuf> {
+    let mut entries = fs::read_dir(root.join(rel_dir))
+        .ok()?
+        .filter_map(|e| e.ok())
+        .collect::<Vec<_>>();
+    entries.sort_by_key(|e| e.file_name());
+
+    for entry in entries {
+        let name = entry.file_name();
+        let rel_path = rel_dir.join(&name);
+
+        if entry.file_type().is_ok_and(|t| t.is_dir()) {
+            if name.to_string_lossy().starts_with('.') || rel_path == Path::new("build") {
+                continue;
+            }
+
+            if let Some(found) = find_file(root, &rel_path, file_name) {
+                return Some(found);
+            }
+        } else if name == file_name {
+            return Some(rel_path);
+        }
+    }
+
+    None
+}
+
 /// `new`: Create a new document project
 #[derive(Debug, Eq, PartialEq, Parser)]
 pub struct NewCommand {
@@ -47,6 +332,95 @@ pub struct NewCommand {
     /// Use this URL to find resource files instead of the default
     #[arg(long, short)]
     bundle: Option<String>,
+
+    /// Copy the files of this template (a directory, a Git repository URL, or
+    /// the name of a template in the user's templates directory) instead of
+    /// creating the default skeleton
+    #[arg(long, short)]
+    template: Option<String>,
+}
+
+/// Where the files of a template come from.
+#[derive(Debug, Eq, PartialEq)]
+enum TemplateSource {
+    /// The built-in skeleton.
+    Default,
+
+    /// A local directory.
+    Dir(PathBuf),
+
+    /// A Git repository URL.
+    Git(String),
+}
+
+/// Check whether a template argument looks like something that `git clone`
+/// can fetch: a URL, or the `user@host:path` form used for SSH.
+fn is_git_url(template: &str) -> bool {
+    template.contains("://")
+        || template
+            .split_once(':')
+            .is_some_and(|(host, _)| host.contains('@') && !host.contains('/'))
+}
+
+/// Work out where a template comes from. Named templates are looked up in the
+/// `templates` directory of the user's Tectonic configuration directory,
+/// `user_templates`.
+fn find_template(template: &str, user_templates: Option<&Path>) -> Result<TemplateSource> {
+    if template == "default" {
+        return Ok(TemplateSource::Default);
+    }
+
+    if Path::new(template).is_dir() {
+        return Ok(TemplateSource::Dir(PathBuf::from(template)));
+    }
+
+    if is_git_url(template) {
+        return Ok(TemplateSource::Git(template.to_owned()));
+    }
+
+    let mut components = Path::new(template).components();
+    let is_name = matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(_)), None)
+    );
+
+    if is_name {
+        if let Some(dir) = user_templates.map(|d| d.join(template)) {
+            if dir.is_dir() {
+                return Ok(TemplateSource::Dir(dir));
+            }
+        }
+    }
+
+    let searched = match user_templates {
+        Some(d) => format!(" or a template in `{}`", d.display()),
+        None => String::new(),
+    };
+
+    Err(errmsg!(
+        "the template `{}` is not a directory, a Git repository URL{}",
+        template,
+        searched
+    ))
+}
+
+/// Fetch a template from a Git repository into `dest`.
+fn clone_template(url: &str, dest: &Path, status: &mut dyn StatusBackend) -> Result<()> {
+    tt_note!(status, "fetching template from `{}`", url);
+
+    let exit = ctry!(
+        Command::new("git")
+            .args(["clone", "--quiet", "--depth", "1", "--", url])
+            .arg(dest)
+            .status();
+        "failed to run `git` to fetch the template"
+    );
+
+    if !exit.success() {
+        return Err(errmsg!("failed to fetch the template from `{}`", url));
+    }
+
+    Ok(())
 }
 
 impl TectonicCommand for NewCommand {
@@ -60,10 +434,86 @@ impl TectonicCommand for NewCommand {
         );
 
         let wc = WorkspaceCreator::new(self.path);
+
+        let source = match self.template.as_deref() {
+            None => TemplateSource::Default,
+            Some(t) => {
+                let user_templates = app_dirs::get_user_config()
+                    .ok()
+                    .map(|d| d.join("templates"));
+                find_template(t, user_templates.as_deref())?
+            }
+        };
+
+        // The temporary directory has to outlive the creation of the
+        // workspace, if we need one.
+        let clone_dir;
+
+        let template_dir = match source {
+            TemplateSource::Default => {
+                ctry!(
+                    wc.create_defaulted(&config, self.bundle);
+                    "failed to create the new Tectonic workspace"
+                );
+                return Ok(0);
+            }
+
+            TemplateSource::Dir(dir) => dir,
+
+            TemplateSource::Git(url) => {
+                clone_dir = ctry!(
+                    tempfile::Builder::new().prefix("tectonic_template").tempdir();
+                    "can't create temporary directory for the template"
+                );
+                let dest = clone_dir.path().join("template");
+                clone_template(&url, &dest, status)?;
+                dest
+            }
+        };
+
         ctry!(
-            wc.create_defaulted(&config, self.bundle);
+            wc.create_from_template_defaulted(&config, self.bundle, &template_dir);
             "failed to create the new Tectonic workspace"
         );
         Ok(0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn template_sources() {
+        let dir = tempfile::tempdir().unwrap();
+        let user_templates = dir.path().join("templates");
+        fs::create_dir_all(user_templates.join("article")).unwrap();
+        let local = dir.path().join("local");
+        fs::create_dir_all(&local).unwrap();
+        let find = |t: &str| find_template(t, Some(&user_templates));
+
+        assert_eq!(find("default").unwrap(), TemplateSource::Default);
+        assert_eq!(
+            find(local.to_str().unwrap()).unwrap(),
+            TemplateSource::Dir(local.clone())
+        );
+        assert_eq!(
+            find("article").unwrap(),
+            TemplateSource::Dir(user_templates.join("article"))
+        );
+
+        for url in [
+            "https://github.com/example/template.git",
+            "file:///srv/template",
+            "git@github.com:example/template.git",
+        ] {
+            assert_eq!(find(url).unwrap(), TemplateSource::Git(url.to_owned()));
+        }
+
+        // Anything else is an error, rather than something for `git` to try.
+        assert!(find("no-such-template").is_err());
+        assert!(find("../article").is_err());
+        assert!(find("some/dir").is_err());
+        assert!(find_template("article", None).is_err());
+    }
+}
@@ -0,0 +1,117 @@
+// Copyright 2025 the Tectonic Project
+// Licensed under the MIT License.
+
+//! The `self` command: integrate the `nextonic` command with the system.
+
+use clap::{CommandFactory, Parser, Subcommand};
+use std::path::PathBuf;
+use tectonic::{config::PersistentConfig, ctry, errors::Result, tt_note};
+use tectonic_status_base::StatusBackend;
+
+use crate::v2cli::{CommandCustomizations, TectonicCommand, V2CliOptions};
+
+/// The name that completions and manual pages are generated for. The current
+/// v1 & v2 cli mixture makes it a bit difficult to offer clean ones for the
+/// `tectonic` command, so for now we only target the `nextonic` command.
+const BIN_NAME: &str = "nextonic";
+
+/// `self`: Commands relating to this installation of Tectonic
+#[derive(Debug, Eq, PartialEq, Parser)]
+pub struct SelfCommand {
+    #[command(subcommand)]
+    command: SelfCommands,
+}
+
+#[derive(Debug, Eq, PartialEq, Subcommand)]
+enum SelfCommands {
+    #[command(name = "completions")]
+    /// Print shell completions code for some given shell
+    Completions(CompletionsCommand),
+
+    #[command(name = "manpage")]
+    /// Generate manual pages for the V2 command-line interface
+    Manpage(ManpageCommand),
+}
+
+impl TectonicCommand for SelfCommand {
+    fn customize(&self, cc: &mut CommandCustomizations) {
+        match &self.command {
+            SelfCommands::Completions(c) => c.customize(cc),
+            SelfCommands::Manpage(c) => c.customize(cc),
+        }
+    }
+
+    fn execute(self, config: PersistentConfig, status: &mut dyn StatusBackend) -> Result<i32> {
+        match self.command {
+            SelfCommands::Completions(c) => c.execute(config, status),
+            SelfCommands::Manpage(c) => c.execute(config, status),
+        }
+    }
+}
+
+/// Shell completions, which are also available as `show shell-completions`.
+#[derive(Debug, Eq, PartialEq, Parser)]
+pub struct CompletionsCommand {
+    /// Target shell for the generated completion code
+    shell: clap_complete::Shell,
+}
+
+impl CompletionsCommand {
+    pub fn customize(&self, cc: &mut CommandCustomizations) {
+        cc.always_stderr = true;
+    }
+
+    /// Generates shell completions at runtime
+    pub fn execute(
+        self,
+        _config: PersistentConfig,
+        _status: &mut dyn StatusBackend,
+    ) -> Result<i32> {
+        clap_complete::generate(
+            self.shell,
+            &mut V2CliOptions::command(),
+            BIN_NAME,
+            &mut std::io::stdout(),
+        );
+        Ok(0)
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Parser)]
+struct ManpageCommand {
+    /// Write a page for every subcommand into this directory, rather than
+    /// printing the main page
+    #[arg(long, short)]
+    out_dir: Option<PathBuf>,
+}
+
+impl ManpageCommand {
+    fn customize(&self, cc: &mut CommandCustomizations) {
+        cc.always_stderr = true;
+    }
+
+    fn execute(self, _config: PersistentConfig, status: &mut dyn StatusBackend) -> Result<i32> {
+        let cmd = V2CliOptions::command()
+            .name(BIN_NAME)
+            .version(env!("CARGO_PKG_VERSION"));
+
+        if let Some(out_dir) = self.out_dir {
+            ctry!(
+                std::fs::create_dir_all(&out_dir);
+                "couldn't create the directory `{}`", out_dir.display()
+            );
+            ctry!(
+                clap_mangen::generate_to(cmd, &out_dir);
+                "couldn't write manual pages into `{}`", out_dir.display()
+            );
+            tt_note!(status, "wrote manual pages into `{}`", out_dir.display());
+        } else {
+            ctry!(
+                clap_mangen::Man::new(cmd).render(&mut std::io::stdout());
+                "couldn't write the manual page"
+            );
+        }
+
+        Ok(0)
+    }
+}
@@ -1,9 +1,17 @@
-use clap::{CommandFactory, Parser};
-use tectonic::{config::PersistentConfig, errors::Result};
+use clap::Parser;
+use serde::Serialize;
+use std::{env, fs, path::PathBuf};
+use tectonic::{config::PersistentConfig, ctry, errors::Result};
+use tectonic_bridge_core::{SecuritySettings, SecurityStance};
+use tectonic_bundles::fetch::RetryPolicy;
+use tectonic_docmodel::workspace::Workspace;
+use tectonic_geturl::{ca_certs_path, PROXY_ENV_VARS};
 use tectonic_io_base::app_dirs;
 use tectonic_status_base::StatusBackend;
 
-use crate::v2cli::{CommandCustomizations, TectonicCommand, V2CliOptions};
+use crate::v2cli::{CommandCustomizations, TectonicCommand};
+
+use super::self_cmd::CompletionsCommand;
 
 /// `show`: Show various useful pieces of information.
 #[derive(Debug, Eq, PartialEq, Parser)]
@@ -14,18 +22,23 @@ pub struct ShowCommand {
 
 #[derive(Debug, Eq, PartialEq, Parser)]
 enum ShowCommands {
+    #[command(name = "environment")]
+    /// Print a report about the environment that documents are built in
+    Environment(ShowEnvironmentCommand),
+
     #[command(name = "user-cache-dir")]
     /// Print the location of the default per-user cache directory
     UserCacheDir(ShowUserCacheDirCommand),
 
     #[command(name = "shell-completions")]
     /// Print shell completions code for some given shell
-    ShellCompletions(ShowShellCompletionsCommand),
+    ShellCompletions(CompletionsCommand),
 }
 
 impl TectonicCommand for ShowCommand {
     fn customize(&self, cc: &mut CommandCustomizations) {
         match &self.command {
+            ShowCommands::Environment(c) => c.customize(cc),
             ShowCommands::UserCacheDir(c) => c.customize(cc),
             ShowCommands::ShellCompletions(c) => c.customize(cc),
         }
@@ -33,6 +46,7 @@ impl TectonicCommand for ShowCommand {
 
     fn execute(self, config: PersistentConfig, status: &mut dyn StatusBackend) -> Result<i32> {
         match self.command {
+            ShowCommands::Environment(c) => c.execute(config, status),
             ShowCommands::UserCacheDir(c) => c.execute(config, status),
             ShowCommands::ShellCompletions(c) => c.execute(config, status),
         }
@@ -40,41 +54,214 @@ impl TectonicCommand for ShowCommand {
 }
 
 #[derive(Debug, Eq, PartialEq, Parser)]
-struct ShowUserCacheDirCommand {}
+struct ShowEnvironmentCommand {
+    /// Print the report as JSON
+    #[arg(long)]
+    json: bool,
+}
 
-impl ShowUserCacheDirCommand {
+/// Information about the environment that documents are built in, for bug
+/// reports and debugging.
+#[derive(Debug, Serialize)]
+struct EnvironmentReport {
+    tectonic_version: &'static str,
+    bundle: BundleReport,
+    cache: CacheReport,
+    network: NetworkReport,
+    security: SecurityReport,
+    engines: EnginesReport,
+}
+
+#[derive(Debug, Serialize)]
+struct BundleReport {
+    /// The bundle that a build in the current directory would use.
+    location: String,
+
+    /// The `Tectonic.toml` file that the location comes from, if any.
+    workspace: Option<PathBuf>,
+
+    /// The digest of the bundle, if it's been cached.
+    cached_digest: Option<String>,
+
+    /// How many times a failed download is retried.
+    download_retries: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct CacheReport {
+    bundles: PathBuf,
+    formats: PathBuf,
+
+    /// The value of `TECTONIC_CACHE_DIR`, if it's set.
+    cache_dir_override: Option<PathBuf>,
+}
+
+#[derive(Debug, Serialize)]
+struct NetworkReport {
+    /// The names of the proxy environment variables that are set. Their
+    /// values aren't reported, since they may contain credentials.
+    proxy_vars: Vec<&'static str>,
+
+    /// The value of `TECTONIC_CA_CERTS`, if it's set.
+    ca_certs: Option<PathBuf>,
+}
+
+#[derive(Debug, Serialize)]
+struct SecurityReport {
+    /// Whether `TECTONIC_UNTRUSTED_MODE` is set.
+    untrusted_mode: bool,
+
+    /// Whether known-insecure features, like shell-escape, can be enabled.
+    insecures_allowed: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct EnginesReport {
+    xetex: &'static str,
+    format_serial: u32,
+    bibtex: &'static str,
+}
+
+impl ShowEnvironmentCommand {
     fn customize(&self, cc: &mut CommandCustomizations) {
         cc.always_stderr = true;
     }
 
-    fn execute(self, _config: PersistentConfig, _status: &mut dyn StatusBackend) -> Result<i32> {
-        println!("{}", app_dirs::get_user_cache_dir("bundles")?.display());
+    fn execute(self, config: PersistentConfig, _status: &mut dyn StatusBackend) -> Result<i32> {
+        let (location, workspace) = match Workspace::open_from_environment() {
+            Ok(ws) => (
+                ws.first_document().bundle_loc.clone(),
+                Some(ws.first_document().src_dir().join("Tectonic.toml")),
+            ),
+            Err(_) => (config.default_bundle_loc().to_owned(), None),
+        };
+
+        let bundles_dir = app_dirs::get_user_cache_dir("bundles")?;
+
+        // This is where the bundle cache records the digest of each network
+        // bundle that it has seen. Other kinds of bundles aren't cached.
+        let cached_digest = fs::read_to_string(
+            bundles_dir
+                .join("hashes")
+                .join(app_dirs::app_dirs2::sanitized(&location)),
+        )
+        .ok()
+        .map(|text| text.trim().to_owned());
+
+        let report = EnvironmentReport {
+            tectonic_version: env!("CARGO_PKG_VERSION"),
+            bundle: BundleReport {
+                location,
+                workspace,
+                cached_digest,
+                download_retries: RetryPolicy::from_env().retries,
+            },
+            cache: CacheReport {
+                bundles: bundles_dir,
+                formats: config.format_cache_path()?,
+                cache_dir_override: env::var_os("TECTONIC_CACHE_DIR").map(PathBuf::from),
+            },
+            network: NetworkReport {
+                proxy_vars: PROXY_ENV_VARS
+                    .iter()
+                    .copied()
+                    .filter(|v| env::var_os(v).is_some())
+                    .collect(),
+                ca_certs: ca_certs_path(),
+            },
+            security: SecurityReport {
+                untrusted_mode: env::var_os("TECTONIC_UNTRUSTED_MODE").is_some(),
+                insecures_allowed: SecuritySettings::new(SecurityStance::MaybeAllowInsecures)
+                    .allow_shell_escape(),
+            },
+            engines: EnginesReport {
+                xetex: tectonic_engine_xetex::XETEX_VERSION,
+                format_serial: tectonic_engine_xetex::FORMAT_SERIAL,
+                bibtex: tectonic_engine_bibtex::BIBTEX_VERSION,
+            },
+        };
+
+        if self.json {
+            let json = ctry!(
+                serde_json::to_string_pretty(&report);
+                "failed to serialize the environment report"
+            );
+            println!("{json}");
+            return Ok(0);
+        }
+
+        println!("tectonic version: {}", report.tectonic_version);
+
+        println!("bundle:");
+        println!("  location: {}", report.bundle.location);
+        match &report.bundle.workspace {
+            Some(p) => println!("  from: {}", p.display()),
+            None => println!("  from: user configuration"),
+        }
+        println!(
+            "  cached digest: {}",
+            report.bundle.cached_digest.as_deref().unwrap_or("(none)")
+        );
+        println!("  download retries: {}", report.bundle.download_retries);
+
+        println!("cache:");
+        println!("  bundles: {}", report.cache.bundles.display());
+        println!("  formats: {}", report.cache.formats.display());
+        if let Some(p) = &report.cache.cache_dir_override {
+            println!("  TECTONIC_CACHE_DIR: {}", p.display());
+        }
+
+        println!("network:");
+        if report.network.proxy_vars.is_empty() {
+            println!("  proxy variables: (none)");
+        } else {
+            println!(
+                "  proxy variables: {}",
+                report.network.proxy_vars.join(", ")
+            );
+        }
+        if let Some(p) = &report.network.ca_certs {
+            println!("  TECTONIC_CA_CERTS: {}", p.display());
+        }
+
+        println!("security:");
+        println!(
+            "  TECTONIC_UNTRUSTED_MODE: {}",
+            if report.security.untrusted_mode {
+                "set"
+            } else {
+                "not set"
+            }
+        );
+        println!(
+            "  insecure features: {}",
+            if report.security.insecures_allowed {
+                "allowed if requested"
+            } else {
+                "always disabled"
+            }
+        );
+
+        println!("engines:");
+        println!(
+            "  XeTeX: {} (format serial {})",
+            report.engines.xetex, report.engines.format_serial
+        );
+        println!("  BibTeX: {}", report.engines.bibtex);
         Ok(0)
     }
 }
 
 #[derive(Debug, Eq, PartialEq, Parser)]
-struct ShowShellCompletionsCommand {
-    /// Target shell for the generated completion code
-    shell: clap_complete::Shell,
-}
+struct ShowUserCacheDirCommand {}
 
-impl ShowShellCompletionsCommand {
+impl ShowUserCacheDirCommand {
     fn customize(&self, cc: &mut CommandCustomizations) {
         cc.always_stderr = true;
     }
 
-    /// Generates shell completions at runtime
     fn execute(self, _config: PersistentConfig, _status: &mut dyn StatusBackend) -> Result<i32> {
-        // The current v1 & v2 cli mixture makes it a bit difficult to offer
-        // clean completions for the `tectonic` command, so for now we only
-        // target the `nextonic` command exclusively for the v2 cli.
-        clap_complete::generate(
-            self.shell,
-            &mut V2CliOptions::command(),
-            "nextonic",
-            &mut std::io::stdout(),
-        );
+        println!("{}", app_dirs::get_user_cache_dir("bundles")?.display());
         Ok(0)
     }
 }
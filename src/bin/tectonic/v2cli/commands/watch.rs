@@ -1,13 +1,20 @@
 use clap::Parser;
+use std::collections::HashSet;
+use std::sync::{Mutex, OnceLock};
 use std::time::Duration;
-use std::{env, path::PathBuf, sync::Arc};
-use tectonic::{config::PersistentConfig, errors::Result, tt_error};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tectonic::{config::PersistentConfig, ctry, errors::Result, tt_error, tt_note};
+use tectonic_docmodel::workspace::Workspace;
 use tectonic_status_base::StatusBackend;
 use tokio::runtime;
 use watchexec::command::Program;
 use watchexec::{
     command::{Command, Shell},
-    Id, Watchexec,
+    Config, Id, WatchedPath, Watchexec,
 };
 use watchexec_filterer_globset::GlobsetFilterer;
 use watchexec_signals::Signal;
@@ -16,6 +23,10 @@ use watchexec_supervisor::ProcessEnd;
 
 use crate::v2cli::{CommandCustomizations, TectonicCommand};
 
+use self::serve::PreviewServer;
+
+pub(crate) mod serve;
+
 /// Obtain the executable name without a prefix if the executable is available in the PATH, e.g.
 /// most cases. Otherwise, use the full path e.g. in development.
 fn get_trimmed_exe_name() -> PathBuf {
@@ -32,12 +43,102 @@ fn get_trimmed_exe_name() -> PathBuf {
     exe_name
 }
 
+/// The environment variable through which `watch` asks `build` to write out
+/// the files that it read, one absolute path per line.
+pub(crate) const DEPS_FILE_ENV_VAR: &str = "TECTONIC_WATCH_DEPS_FILE";
+
+/// State shared between the watcher's action handler and the tasks that wait
+/// for builds to finish.
+#[derive(Debug)]
+struct WatchState {
+    /// Where the builds write the files that they read.
+    deps_file: PathBuf,
+
+    /// The files that the last build read, or nothing if we don't know them
+    /// yet.
+    deps: Mutex<HashSet<PathBuf>>,
+
+    /// The (canonicalized) directory being watched.
+    current_dir: PathBuf,
+
+    /// The watcher's configuration, once it exists.
+    config: OnceLock<Arc<Config>>,
+
+    /// The preview server, if we're running one.
+    preview: Option<PreviewServer>,
+}
+
+/// Normalize a path so that paths reported by the build and by the file
+/// watcher can be compared. The file might have just been deleted, so only its
+/// directory is canonicalized.
+fn normalize_path(path: &Path) -> PathBuf {
+    match (path.parent(), path.file_name()) {
+        (Some(dir), Some(name)) => dir
+            .canonicalize()
+            .map(|dir| dir.join(name))
+            .unwrap_or_else(|_| path.to_owned()),
+        _ => path.to_owned(),
+    }
+}
+
+impl WatchState {
+    /// Load the dependency list written by the last build, if there is one,
+    /// and watch the current directory plus the directories of any
+    /// dependencies outside of it.
+    ///
+    /// If the last build failed, the list from the build before it is kept.
+    fn load_dependencies(&self) {
+        let files: HashSet<PathBuf> = fs::read_to_string(&self.deps_file)
+            .unwrap_or_default()
+            .lines()
+            .map(|line| normalize_path(Path::new(line)))
+            .collect();
+
+        let mut pathset = vec![WatchedPath::recursive(&self.current_dir)];
+        let mut dirs = HashSet::new();
+
+        for file in &files {
+            if let Some(dir) = file.parent() {
+                if !dir.starts_with(&self.current_dir) && dirs.insert(dir.to_owned()) {
+                    pathset.push(WatchedPath::non_recursive(dir));
+                }
+            }
+        }
+
+        *self.deps.lock().unwrap() = files;
+
+        if let Some(config) = self.config.get() {
+            config.pathset(pathset);
+        }
+    }
+
+    /// Decide whether a change to any of these paths should trigger a
+    /// rebuild. Until we know what the build reads, any change does.
+    fn is_relevant_change(&self, paths: &[&Path]) -> bool {
+        let deps = self.deps.lock().unwrap();
+
+        deps.is_empty()
+            || paths.iter().any(|path| {
+                path.file_name() == Some("Tectonic.toml".as_ref())
+                    || deps.contains(&normalize_path(path))
+            })
+    }
+}
+
 /// `watch`: Watch input files and execute commands on change
 #[derive(Debug, Eq, PartialEq, Parser)]
 pub struct WatchCommand {
     /// Tectonic commands to execute on build [default: build]
     #[arg(long = "exec", short = 'x')]
     execute: Vec<String>,
+
+    /// Serve the built document over HTTP, reloading it in the browser after each build
+    #[arg(long)]
+    serve: bool,
+
+    /// The port for `--serve` to listen on [default: chosen automatically]
+    #[arg(long, requires = "serve")]
+    port: Option<u16>,
 }
 
 impl WatchCommand {
@@ -95,7 +196,33 @@ impl WatchCommand {
             cmds.push((Id::default(), Arc::new(cmd)));
         }
 
-        let current_dir = env::current_dir()?;
+        let current_dir = env::current_dir()?.canonicalize()?;
+
+        // The builds tell us which files they read through this file, so that
+        // we only rebuild when something relevant changes.
+        let deps_dir = ctry!(
+            tempfile::Builder::new().prefix("tectonic_watch").tempdir();
+            "can't create temporary directory for build dependencies"
+        );
+
+        let preview = if self.serve {
+            let ws = Workspace::open_from_environment()?;
+            let (server, url) =
+                PreviewServer::start_for_document(ws.first_document(), self.port.unwrap_or(0))
+                    .await?;
+            tt_note!(status, "serving a preview of the document at {}", url);
+            Some(server)
+        } else {
+            None
+        };
+
+        let state = Arc::new(WatchState {
+            deps_file: deps_dir.path().join("deps.txt"),
+            deps: Mutex::default(),
+            current_dir: current_dir.clone(),
+            config: OnceLock::new(),
+            preview,
+        });
 
         let filter = GlobsetFilterer::new(
             &current_dir,
@@ -109,14 +236,20 @@ impl WatchCommand {
         .await
         .unwrap();
 
-        async fn end_task(end: Ticket, job: Job) {
+        async fn end_task(end: Ticket, job: Job, state: Arc<WatchState>) {
             end.await;
-            job.run(|ctx| match ctx.current {
+            state.load_dependencies();
+
+            job.run(move |ctx| match ctx.current {
                 CommandState::Finished {
                     status: ProcessEnd::Success,
                     ..
                 } => {
-                    println!("[Finished Running. Exit Status: 0]")
+                    println!("[Finished Running. Exit Status: 0]");
+
+                    if let Some(preview) = &state.preview {
+                        preview.reload();
+                    }
                 }
                 CommandState::Finished {
                     status: ProcessEnd::ExitError(err),
@@ -130,8 +263,10 @@ impl WatchCommand {
         }
 
         let cmds = Arc::new(cmds);
+        let handler_state = Arc::clone(&state);
         let exec_handler = Watchexec::new_async(move |mut action| {
             let cmds = Arc::clone(&cmds);
+            let state = Arc::clone(&handler_state);
             Box::new(async move {
                 // When we spawn a job it doesn't immediately become available. So we chain it
                 // with existing jobs.
@@ -140,7 +275,9 @@ impl WatchCommand {
                 if action.get_job(cmds[0].0).is_none() {
                     for (id, cmd) in &*cmds {
                         let job = action.get_or_create_job(*id, || Arc::clone(cmd));
-                        job.set_spawn_hook(|_, ctx| {
+                        let deps_file = state.deps_file.clone();
+                        job.set_spawn_hook(move |command, ctx| {
+                            command.command_mut().env(DEPS_FILE_ENV_VAR, &deps_file);
                             println!("[Running `{}`]", ctx.command);
                         });
                         new_job = Some((*id, job));
@@ -164,12 +301,12 @@ impl WatchCommand {
                         return action;
                     }
 
-                    let paths = event.paths().collect::<Vec<_>>();
-                    if !paths.is_empty() {
+                    let paths = event.paths().map(|(path, _)| path).collect::<Vec<_>>();
+                    if !paths.is_empty() && state.is_relevant_change(&paths) {
                         for (_, job) in action.list_jobs().chain(new_job) {
                             job.start().await;
                             let end = job.to_wait();
-                            tokio::spawn(end_task(end, job));
+                            tokio::spawn(end_task(end, job, Arc::clone(&state)));
                         }
                         return action;
                     }
@@ -192,6 +329,7 @@ impl WatchCommand {
                     .config
                     .pathset([current_dir])
                     .filterer(Arc::new(filter));
+                let _ = state.config.set(Arc::clone(&exec_handler.config));
                 exec_handler.main().await.unwrap().unwrap();
                 Ok(0)
             }
@@ -0,0 +1,380 @@
+//! A small HTTP server for previewing the document built by `build` or
+//! `watch`.
+//!
+//! The server hosts the contents of the build directory. Its root page shows
+//! the main output file in a frame and listens for server-sent events on
+//! [`EVENTS_PATH`], reloading the frame whenever a build succeeds.
+//!
+//! The server only listens on the loopback interface. To keep other web pages
+//! that the browser visits from reading it through DNS rebinding, requests
+//! must also name it as `localhost` or `127.0.0.1` in their `Host` header.
+
+use std::path::{Component, Path, PathBuf};
+use tectonic::{ctry, errors::Result};
+use tectonic_docmodel::document::Document;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    sync::broadcast,
+};
+
+/// The path of the event stream that tells the browser to reload.
+const EVENTS_PATH: &str = "/_tectonic/events";
+
+/// The page served at `/`. `{{MAIN}}` is replaced with the URL of the main
+/// output file.
+const INDEX_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Tectonic preview</title>
+<style>html, body, iframe { margin: 0; border: 0; width: 100%; height: 100%; }</style>
+</head>
+<body>
+<iframe id="main" src="{{MAIN}}"></iframe>
+<script>
+new EventSource("/_tectonic/events").onmessage = function () {
+  var frame = document.getElementById("main");
+  frame.src = frame.src;
+};
+</script>
+</body>
+</html>
+"#;
+
+/// A running preview server.
+#[derive(Debug)]
+pub struct PreviewServer {
+    reloads: broadcast::Sender<()>,
+}
+
+impl PreviewServer {
+    /// Start serving the build directory of `doc` on the given port, or on
+    /// a port chosen by the system if `port` is zero.
+    ///
+    /// If the document has several output profiles, the main file of one of
+    /// them is shown, chosen at (pseudo-)random like `dump` does.
+    pub async fn start_for_document(doc: &Document, port: u16) -> Result<(Self, String)> {
+        let output_name = doc.output_names().next().unwrap();
+        let main_file = doc.output_main_file(output_name);
+        let main_file = main_file
+            .strip_prefix(doc.build_dir())
+            .unwrap_or(&main_file)
+            .to_owned();
+
+        Ok(ctry!(
+            Self::start(doc.build_dir().to_owned(), main_file, port).await;
+            "failed to start the preview server"
+        ))
+    }
+
+    /// Start serving `root` on the given port of the loopback interface, or
+    /// on a port chosen by the system if `port` is zero. `main_file` is the
+    /// path of the main output file, relative to `root`.
+    ///
+    /// Returns the server and the URL of its root page.
+    pub async fn start(
+        root: PathBuf,
+        main_file: PathBuf,
+        port: u16,
+    ) -> std::io::Result<(Self, String)> {
+        std::fs::create_dir_all(&root)?;
+        let root = root.canonicalize()?;
+        let listener = TcpListener::bind(("127.0.0.1", port)).await?;
+        let port = listener.local_addr()?.port();
+        let url = format!("http://127.0.0.1:{port}/");
+        let (reloads, _) = broadcast::channel(16);
+
+        let main_url = main_file
+            .components()
+            .map(|c| percent_encode(&c.as_os_str().to_string_lossy()))
+            .collect::<Vec<_>>()
+            .join("/");
+        let index = INDEX_TEMPLATE.replace("{{MAIN}}", &format!("/{main_url}"));
+
+        let accept_reloads = reloads.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let root = root.clone();
+                let index = index.clone();
+                let reloads = accept_reloads.subscribe();
+
+                tokio::spawn(async move {
+                    // Errors here are just clients going away.
+                    let _ = handle_connection(stream, &root, port, &index, reloads).await;
+                });
+            }
+        });
+
+        Ok((PreviewServer { reloads }, url))
+    }
+
+    /// Tell any connected browsers to reload the document.
+    pub fn reload(&self) {
+        // This only fails if nobody is listening.
+        let _ = self.reloads.send(());
+    }
+}
+
+async fn handle_connection(
+    stream: TcpStream,
+    root: &Path,
+    port: u16,
+    index: &str,
+    mut reloads: broadcast::Receiver<()>,
+) -> std::io::Result<()> {
+    let mut stream = BufReader::new(stream);
+
+    let mut request_line = String::new();
+    stream.read_line(&mut request_line).await?;
+
+    // The only header that we care about is `Host`.
+    let mut host = None;
+
+    loop {
+        let mut line = String::new();
+        if stream.read_line(&mut line).await? == 0 || line.trim_end().is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("host") {
+                host = Some(value.trim().to_owned());
+            }
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let target = parts.next().unwrap_or_default();
+    let path = target.split(['?', '#']).next().unwrap_or_default();
+    let stream = stream.get_mut();
+
+    if !host.is_some_and(|h| is_allowed_host(&h, port)) {
+        return respond(stream, "403 Forbidden", "text/plain", b"forbidden").await;
+    }
+
+    if method != "GET" && method != "HEAD" {
+        return respond(stream, "405 Method Not Allowed", "text/plain", b"").await;
+    }
+
+    if path == EVENTS_PATH {
+        stream
+            .write_all(
+                b"HTTP/1.1 200 OK\r\n\
+                  Content-Type: text/event-stream\r\n\
+                  Cache-Control: no-cache\r\n\
+                  Connection: keep-alive\r\n\r\n",
+            )
+            .await?;
+        stream.flush().await?;
+
+        loop {
+            match reloads.recv().await {
+                Ok(()) | Err(broadcast::error::RecvError::Lagged(_)) => {
+                    stream.write_all(b"data: reload\n\n").await?;
+                    stream.flush().await?;
+                }
+                Err(broadcast::error::RecvError::Closed) => return Ok(()),
+            }
+        }
+    }
+
+    if path == "/" {
+        return respond(
+            stream,
+            "200 OK",
+            "text/html; charset=utf-8",
+            index.as_bytes(),
+        )
+        .await;
+    }
+
+    let file_path = match resolve(root, path) {
+        Some(p) => p,
+        None => return respond(stream, "404 Not Found", "text/plain", b"not found").await,
+    };
+
+    match tokio::fs::read(&file_path).await {
+        Ok(data) => {
+            let body = if method == "HEAD" { &[][..] } else { &data[..] };
+            respond(stream, "200 OK", content_type(&file_path), body).await
+        }
+        Err(_) => respond(stream, "404 Not Found", "text/plain", b"not found").await,
+    }
+}
+
+async fn respond(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &[u8],
+) -> std::io::Result<()> {
+    let head = format!(
+        "HTTP/1.1 {status}\r\n\
+         Content-Type: {content_type}\r\n\
+         Content-Length: {}\r\n\
+         Cache-Control: no-store\r\n\
+         Connection: close\r\n\r\n",
+        body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body).await?;
+    stream.flush().await
+}
+
+/// Check the `Host` header of a request, which must name the loopback
+/// interface on our port.
+fn is_allowed_host(host: &str, port: u16) -> bool {
+    match host.rsplit_once(':') {
+        Some((name, p)) => {
+            p.parse() == Ok(port) && (name == "127.0.0.1" || name.eq_ignore_ascii_case("localhost"))
+        }
+        None => false,
+    }
+}
+
+/// Map a request path onto an existing file under `root`, which must be
+/// canonicalized, refusing anything that would escape it, including through
+/// symbolic links.
+fn resolve(root: &Path, path: &str) -> Option<PathBuf> {
+    let relative = PathBuf::from(percent_decode(path.trim_start_matches('/'))?);
+
+    if !relative
+        .components()
+        .all(|c| matches!(c, Component::Normal(_)))
+    {
+        return None;
+    }
+
+    let mut full = root.join(relative).canonicalize().ok()?;
+
+    if full.is_dir() {
+        full = full.join("index.html").canonicalize().ok()?;
+    }
+
+    if !full.starts_with(root) {
+        return None;
+    }
+
+    Some(full)
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("css") => "text/css",
+        Some("js") => "text/javascript",
+        Some("pdf") => "application/pdf",
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("svg") => "image/svg+xml",
+        Some("otf") => "font/otf",
+        Some("ttf") => "font/ttf",
+        Some("woff") => "font/woff",
+        Some("woff2") => "font/woff2",
+        _ => "application/octet-stream",
+    }
+}
+
+fn percent_encode(text: &str) -> String {
+    let mut encoded = String::new();
+
+    for b in text.bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{b:02X}"));
+        }
+    }
+
+    encoded
+}
+
+fn percent_decode(text: &str) -> Option<String> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    String::from_utf8(decoded).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn hosts() {
+        assert!(is_allowed_host("127.0.0.1:8000", 8000));
+        assert!(is_allowed_host("LocalHost:8000", 8000));
+        assert!(!is_allowed_host("127.0.0.1:8001", 8000));
+        assert!(!is_allowed_host("127.0.0.1", 8000));
+        assert!(!is_allowed_host("evil.example.com:8000", 8000));
+        assert!(!is_allowed_host("localhost.evil.example.com:8000", 8000));
+    }
+
+    #[test]
+    fn percent_coding() {
+        assert_eq!(percent_encode("a b/ü.pdf"), "a%20b%2F%C3%BC.pdf");
+        assert_eq!(percent_decode("a%20b%2F%C3%BC.pdf").unwrap(), "a b/ü.pdf");
+        assert_eq!(
+            percent_decode("plain-name.html").unwrap(),
+            "plain-name.html"
+        );
+        assert_eq!(percent_decode("%2e%2E").unwrap(), "..");
+        assert!(percent_decode("bad%2").is_none());
+        assert!(percent_decode("bad%zz").is_none());
+        assert!(percent_decode("%FF").is_none());
+    }
+
+    #[test]
+    fn resolving() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("build");
+        fs::create_dir_all(root.join("html")).unwrap();
+        fs::write(root.join("doc.pdf"), "pdf").unwrap();
+        fs::write(root.join("html/index.html"), "html").unwrap();
+        fs::write(dir.path().join("secret.txt"), "secret").unwrap();
+        let root = root.canonicalize().unwrap();
+
+        assert_eq!(resolve(&root, "/doc.pdf"), Some(root.join("doc.pdf")));
+        assert_eq!(
+            resolve(&root, "/html/"),
+            Some(root.join("html").join("index.html"))
+        );
+        assert_eq!(resolve(&root, "/missing.pdf"), None);
+        assert_eq!(resolve(&root, "/../secret.txt"), None);
+        assert_eq!(resolve(&root, "/%2e%2e/secret.txt"), None);
+        assert_eq!(resolve(&root, "/html/%2E%2E/doc.pdf"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resolving_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("build");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("doc.pdf"), "pdf").unwrap();
+        fs::write(dir.path().join("secret.txt"), "secret").unwrap();
+        std::os::unix::fs::symlink(dir.path().join("secret.txt"), root.join("leak.txt")).unwrap();
+        std::os::unix::fs::symlink(dir.path(), root.join("up")).unwrap();
+        std::os::unix::fs::symlink(root.join("doc.pdf"), root.join("alias.pdf")).unwrap();
+        let root = root.canonicalize().unwrap();
+
+        assert_eq!(resolve(&root, "/leak.txt"), None);
+        assert_eq!(resolve(&root, "/up/secret.txt"), None);
+        assert_eq!(resolve(&root, "/alias.pdf"), Some(root.join("doc.pdf")));
+    }
+}
@@ -4,23 +4,28 @@
 //! The "v2cli" command-line interface -- a "multitool" interface resembling
 //! Cargo, as compared to the classic "rustc-like" CLI.
 
-use clap::{Parser, Subcommand};
+use clap::{error::ErrorKind, CommandFactory, Parser, Subcommand};
 use std::{env, ffi::OsString, fs, path::Path, path::PathBuf, process};
 use tectonic::{
     config::PersistentConfig,
     errors::{Result, SyncError},
-    status::{termcolor::TermcolorStatusBackend, ChatterLevel, StatusBackend},
+    status::{ChatterLevel, StatusBackend},
     tt_note,
 };
+use tectonic_docmodel::workspace::Workspace;
 use tectonic_errors::prelude::anyhow;
-use tectonic_status_base::plain::PlainStatusBackend;
 use tracing::level_filters::LevelFilter;
 
 use self::commands::{
+    bibtex::BibtexCommand,
     build::BuildCommand,
     bundle::BundleCommand,
+    cache::CacheCommand,
     dump::DumpCommand,
+    dump_text::DumpTextCommand,
+    fonts::FontsCommand,
     new::{InitCommand, NewCommand},
+    self_cmd::SelfCommand,
     show::ShowCommand,
     watch::WatchCommand,
 };
@@ -43,6 +48,14 @@ struct V2CliOptions {
     #[arg(long = "color", default_value = "auto")]
     cli_color: crate::CliColor,
 
+    /// How to format status messages
+    #[arg(long = "output-format", default_value = "human")]
+    output_format: crate::OutputFormat,
+
+    /// Also write all status messages to this file, replacing it
+    #[arg(long, value_name = "path")]
+    build_log: Option<PathBuf>,
+
     /// The command to run
     #[command(subcommand)]
     command: Commands,
@@ -79,6 +92,14 @@ pub fn v2_main(effective_args: &[OsString]) {
 
     let args = V2CliOptions::parse_from(effective_args);
 
+    if let Commands::Compile(o) = &args.command {
+        if args.output_format == crate::OutputFormat::Lsp && o.writes_to_stdout() {
+            V2CliOptions::command()
+                .error(ErrorKind::ArgumentConflict, crate::LSP_STDOUT_CONFLICT)
+                .exit();
+        }
+    }
+
     tracing_subscriber::fmt()
         .with_max_level(LevelFilter::INFO)
         .with_target(false)
@@ -92,12 +113,18 @@ pub fn v2_main(effective_args: &[OsString]) {
 
     let mut customizations = CommandCustomizations::default();
     match &args.command {
+        Commands::Bibtex(o) => o.customize(&mut customizations),
         Commands::Build(o) => o.customize(&mut customizations),
         Commands::Bundle(o) => o.customize(&mut customizations),
-        Commands::Compile(_) => {} // avoid namespacing/etc issues
+        Commands::Cache(o) => o.customize(&mut customizations),
+        // Compile isn't a TectonicCommand, to avoid namespacing/etc issues
+        Commands::Compile(o) => customizations.always_stderr = o.writes_to_stdout(),
         Commands::Dump(o) => o.customize(&mut customizations),
+        Commands::DumpText(o) => o.customize(&mut customizations),
+        Commands::Fonts(o) => o.customize(&mut customizations),
         Commands::New(o) => o.customize(&mut customizations),
         Commands::Init(o) => o.customize(&mut customizations),
+        Commands::SelfCmd(o) => o.customize(&mut customizations),
         Commands::Show(o) => o.customize(&mut customizations),
         Commands::Watch(o) => o.customize(&mut customizations),
         Commands::External(_) => {}
@@ -110,15 +137,12 @@ pub fn v2_main(effective_args: &[OsString]) {
         args.chatter_level
     };
 
-    let mut status = if args.cli_color.should_enable() {
-        let mut sb = TermcolorStatusBackend::new(chatter_level);
-        sb.always_stderr(customizations.always_stderr);
-        Box::new(sb) as Box<dyn StatusBackend>
-    } else {
-        let mut sb = PlainStatusBackend::new(chatter_level);
-        sb.always_stderr(customizations.always_stderr);
-        Box::new(sb) as Box<dyn StatusBackend>
-    };
+    let status = args.output_format.status_backend(
+        chatter_level,
+        &args.cli_color,
+        customizations.always_stderr,
+    );
+    let mut status = crate::tee_build_log(status, args.build_log.as_deref());
 
     // For now ...
 
@@ -127,25 +151,38 @@ pub fn v2_main(effective_args: &[OsString]) {
         "\"version 2\" Tectonic command-line interface activated"
     );
 
+    let source_dirs =
+        (args.output_format == crate::OutputFormat::Lsp).then(|| match &args.command {
+            Commands::Compile(o) => o.source_dirs(),
+            _ => workspace_source_dirs(),
+        });
+
     // Now that we've got colorized output, pass off to the inner function.
 
-    let r = match args.command {
-        Commands::Build(o) => o.execute(config, &mut *status),
-        Commands::Bundle(o) => o.execute(config, &mut *status),
-        Commands::Compile(o) => o.execute(config, &mut *status),
-        Commands::Dump(o) => o.execute(config, &mut *status),
-        Commands::New(o) => o.execute(config, &mut *status),
-        Commands::Init(o) => o.execute(config, &mut *status),
-        Commands::Show(o) => o.execute(config, &mut *status),
-        Commands::Watch(o) => o.execute(config, &mut *status),
-        Commands::External(all_args) => do_external(all_args),
-    };
+    let (r, mut status) =
+        crate::run_collecting_lsp(status, source_dirs, |status| match args.command {
+            Commands::Bibtex(o) => o.execute(config, status),
+            Commands::Build(o) => o.execute(config, status),
+            Commands::Bundle(o) => o.execute(config, status),
+            Commands::Cache(o) => o.execute(config, status),
+            Commands::Compile(o) => o.execute(config, status),
+            Commands::Dump(o) => o.execute(config, status),
+            Commands::DumpText(o) => o.execute(config, status),
+            Commands::Fonts(o) => o.execute(config, status),
+            Commands::New(o) => o.execute(config, status),
+            Commands::Init(o) => o.execute(config, status),
+            Commands::SelfCmd(o) => o.execute(config, status),
+            Commands::Show(o) => o.execute(config, status),
+            Commands::Watch(o) => o.execute(config, status),
+            Commands::External(all_args) => do_external(all_args),
+        });
 
     process::exit(match r {
         Ok(c) => c,
         Err(e) => {
+            let code = crate::compile::failure_exit_code(&e);
             status.report_error(&SyncError::new(e).into());
-            1
+            code
         }
     })
 }
@@ -158,6 +195,10 @@ trait TectonicCommand {
 #[allow(clippy::large_enum_variant)]
 #[derive(Debug, Subcommand)]
 enum Commands {
+    #[command(name = "bibtex")]
+    /// Run BibTeX on an `.aux` file
+    Bibtex(BibtexCommand),
+
     #[command(name = "build")]
     /// Build a document
     Build(BuildCommand),
@@ -166,6 +207,10 @@ enum Commands {
     /// Commands relating to this document’s TeX file bundle
     Bundle(BundleCommand),
 
+    #[command(name = "cache")]
+    /// Manage the per-user cache of resource files
+    Cache(CacheCommand),
+
     #[command(name = "compile")]
     /// Run a standalone (La)TeX compilation
     Compile(crate::compile::CompileOptions),
@@ -174,6 +219,14 @@ enum Commands {
     /// Run a partial compilation and output an intermediate file
     Dump(DumpCommand),
 
+    #[command(name = "dump-text")]
+    /// Run a partial compilation and print the document's plain text
+    DumpText(DumpTextCommand),
+
+    #[command(name = "fonts")]
+    /// Inspect the fonts installed on this computer
+    Fonts(FontsCommand),
+
     #[command(name = "new")]
     /// Create a new document project
     New(NewCommand),
@@ -182,6 +235,10 @@ enum Commands {
     /// Initializes a new document in the current directory
     Init(InitCommand),
 
+    #[command(name = "self")]
+    /// Integrate Tectonic with your shell and system
+    SelfCmd(SelfCommand),
+
     #[command(name = "show")]
     /// Display various useful pieces of information
     Show(ShowCommand),
@@ -240,6 +297,20 @@ fn search_directories() -> Vec<PathBuf> {
     dirs
 }
 
+/// The directories that relative file names in the engine's messages should
+/// be resolved against for commands that work on the current workspace: the
+/// document's TeX source directory and its root, or just the current
+/// directory if there's no workspace.
+fn workspace_source_dirs() -> Vec<PathBuf> {
+    match Workspace::open_from_environment() {
+        Ok(ws) => {
+            let src_dir = ws.first_document().src_dir();
+            vec![src_dir.join("src"), src_dir.to_owned()]
+        }
+        Err(_) => vec![env::current_dir().unwrap_or_default()],
+    }
+}
+
 /// Run an external command by executing a subprocess.
 fn do_external(all_args: Vec<String>) -> Result<i32> {
     let (cmd, args) = all_args.split_first().unwrap();
@@ -16,6 +16,7 @@ use std::{
     sync::atomic::{AtomicBool, Ordering},
 };
 use tectonic_bundles::{detect_bundle, Bundle};
+use tectonic_geturl::auth::{add_credentials, Credentials};
 use tectonic_io_base::app_dirs;
 
 use crate::errors::{ErrorKind, Result};
@@ -63,12 +64,66 @@ pub fn maybe_return_test_bundle(bundle: Option<String>) -> Result<Box<dyn Bundle
 #[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 pub struct PersistentConfig {
     default_bundles: Vec<BundleInfo>,
+
+    /// Credentials for servers that host bundles behind authentication.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    bundle_credentials: Vec<CredentialsInfo>,
 }
 
 /// Information about a default bundle
 #[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 pub struct BundleInfo {
     url: String,
+
+    /// The hex-encoded Ed25519 public key that the bundle's index must be
+    /// signed with, if any.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    index_key: Option<String>,
+}
+
+/// Credentials for a server that hosts bundles
+///
+/// Either `token` or `username` must be given. The credentials are only sent
+/// to `host`, and only over HTTPS.
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+#[cfg_attr(not(feature = "serialization"), allow(dead_code))]
+pub struct CredentialsInfo {
+    /// The host that these credentials are for, optionally with a port.
+    host: String,
+
+    /// A bearer token.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    token: Option<String>,
+
+    /// A username for HTTP basic authentication.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    username: Option<String>,
+
+    /// A password for HTTP basic authentication.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    password: Option<String>,
+}
+
+// Without serialization, there are never any credentials.
+#[cfg_attr(not(feature = "serialization"), allow(dead_code))]
+impl CredentialsInfo {
+    fn credentials(&self) -> Result<Credentials> {
+        match (&self.token, &self.username) {
+            (Some(token), None) => Ok(Credentials::Bearer(token.clone())),
+            (None, Some(username)) => Ok(Credentials::Basic {
+                username: username.clone(),
+                password: self.password.clone().unwrap_or_default(),
+            }),
+            _ => Err(ErrorKind::Msg(format!(
+                "the credentials for `{}` must give exactly one of `token` or `username`",
+                self.host
+            ))
+            .into()),
+        }
+    }
 }
 
 impl PersistentConfig {
@@ -82,6 +137,9 @@ impl PersistentConfig {
     /// automatically created, filling in the default configuration. If it is
     /// false, the default configuration is returned and the filesystem is not
     /// modified.
+    ///
+    /// Any bundle credentials in the file are registered with
+    /// `tectonic_geturl`, so that they're used for all later downloads.
     pub fn open(auto_create_config_file: bool) -> Result<PersistentConfig> {
         use std::{
             fs::File,
@@ -117,6 +175,7 @@ impl PersistentConfig {
             }
         };
 
+        config.register_credentials()?;
         Ok(config)
     }
 
@@ -132,6 +191,17 @@ impl PersistentConfig {
         Ok(PersistentConfig::default())
     }
 
+    /// Register this configuration's bundle credentials with
+    /// `tectonic_geturl`.
+    #[cfg_attr(not(feature = "serialization"), allow(dead_code))]
+    fn register_credentials(&self) -> Result<()> {
+        for info in &self.bundle_credentials {
+            add_credentials(&info.host, info.credentials()?);
+        }
+
+        Ok(())
+    }
+
     /// Get the default bundle URL for this configuration
     pub fn default_bundle_loc(&self) -> &str {
         &self.default_bundles[0].url
@@ -151,11 +221,10 @@ impl PersistentConfig {
             .into());
         }
 
-        Ok(
-            detect_bundle(self.default_bundles[0].url.to_owned(), only_cached, None)
-                .unwrap()
-                .unwrap(),
-        )
+        let info = &self.default_bundles[0];
+        let index_key = info.index_key.as_deref().map(str::parse).transpose()?;
+
+        Ok(detect_bundle(info.url.to_owned(), only_cached, None, index_key)?.unwrap())
     }
 
     /// Get the cache directory to use for format files
@@ -173,7 +242,11 @@ impl Default for PersistentConfig {
         let url = tectonic_bundles::get_fallback_bundle_url(tectonic_engine_xetex::FORMAT_SERIAL);
 
         PersistentConfig {
-            default_bundles: vec![BundleInfo { url }],
+            default_bundles: vec![BundleInfo {
+                url,
+                index_key: None,
+            }],
+            bundle_credentials: Vec::new(),
         }
     }
 }
@@ -7,18 +7,25 @@
 //! `tectonic_docmodel` crate with the actual document-processing capabilities
 //! provided by the processing engines.
 
-use std::{fmt::Write as FmtWrite, fs, io, path::PathBuf};
+use std::{
+    fmt::Write as FmtWrite,
+    fs, io,
+    path::{Path, PathBuf},
+};
 use tectonic_bridge_core::SecuritySettings;
-use tectonic_bundles::{detect_bundle, Bundle};
+use tectonic_bundles::{detect_bundle, overlay::OverlayBundle, verify::IndexKey, Bundle};
 use tectonic_docmodel::{
-    document::{BuildTargetType, Document, InputFile},
+    document::{BuildTargetType, Document, HookStage, InputFile},
     workspace::{Workspace, WorkspaceCreator},
 };
 use tectonic_geturl::{DefaultBackend, GetUrlBackend};
 
 use crate::{
     config, ctry,
-    driver::{OutputFormat, PassSetting, ProcessingSessionBuilder},
+    driver::{
+        self, BibtexOptions, EncryptionOptions, ImageOptions, OutputFormat, PassSetting,
+        PdfMetadata, ProcessingSessionBuilder,
+    },
     errors::{ErrorKind, Result},
     status::StatusBackend,
     test_util, tt_note,
@@ -72,9 +79,13 @@ pub trait DocumentExt {
     /// Get the bundle used by this document.
     ///
     /// This parses [`Document::bundle_loc`] and turns it into the appropriate
-    /// bundle backend.
+    /// bundle backend, layering any [`Document::bundle_overlays`] on top of it.
     fn bundle(&self, setup_options: &DocumentSetupOptions) -> Result<Box<dyn Bundle>>;
 
+    /// Get the TeX source that is processed to build one of the outputs. This
+    /// defines the output's variables and then includes its inputs.
+    fn primary_input_text(&self, output_profile: &str) -> Result<String>;
+
     /// Set up a [`ProcessingSessionBuilder`] for one of the outputs.
     ///
     /// The *output_profile* argument gives the name of the document’s output profile to
@@ -89,40 +100,78 @@ pub trait DocumentExt {
 
 impl DocumentExt for Document {
     fn bundle(&self, setup_options: &DocumentSetupOptions) -> Result<Box<dyn Bundle>> {
-        // Load test bundle
-        if config::is_config_test_mode_activated() {
+        // Load test bundle, unless all of the bundles are local ones, like
+        // those made by `-X build --export-bundle`, which can be used for real.
+        let all_local = self
+            .bundle_overlays
+            .iter()
+            .chain(Some(&self.bundle_loc))
+            .all(|loc| !loc.contains("://"));
+
+        if config::is_config_test_mode_activated() && !all_local {
             let bundle = test_util::TestBundle::default();
             return Ok(Box::new(bundle));
         }
 
-        let d = detect_bundle(self.bundle_loc.clone(), setup_options.only_cached, None)?;
+        let mut bundles = Vec::new();
+
+        let index_key = ctry!(
+            self.bundle_key.as_deref().map(str::parse::<IndexKey>).transpose();
+            "invalid `bundle_key` in `Tectonic.toml`"
+        );
+
+        let n_overlays = self.bundle_overlays.len();
+
+        for (i, loc) in self
+            .bundle_overlays
+            .iter()
+            .chain(Some(&self.bundle_loc))
+            .enumerate()
+        {
+            // Relative paths to local bundles, such as directories of support
+            // files, are relative to the document.
+            let loc = if !loc.contains("://") && Path::new(loc).is_relative() {
+                self.src_dir().join(loc).display().to_string()
+            } else {
+                loc.clone()
+            };
+
+            // The key only applies to the base bundle, which comes last.
+            let key = if i == n_overlays { index_key } else { None };
 
-        match d {
-            Some(b) => Ok(b),
-            None => Err(io::Error::new(io::ErrorKind::InvalidInput, "Could not get bundle").into()),
+            match detect_bundle(loc.clone(), setup_options.only_cached, None, key)? {
+                Some(b) => bundles.push(b),
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("Could not get bundle `{loc}`"),
+                    )
+                    .into())
+                }
+            }
+        }
+
+        if bundles.len() == 1 {
+            Ok(bundles.pop().unwrap())
+        } else {
+            Ok(Box::new(OverlayBundle::new(bundles)))
         }
     }
 
-    fn setup_session(
-        &self,
-        output_profile: &str,
-        setup_options: &DocumentSetupOptions,
-        status: &mut dyn StatusBackend,
-    ) -> Result<ProcessingSessionBuilder> {
+    fn primary_input_text(&self, output_profile: &str) -> Result<String> {
         let profile = self.outputs.get(output_profile).ok_or_else(|| {
             ErrorKind::Msg(format!(
                 "unrecognized output profile name \"{output_profile}\""
             ))
         })?;
 
-        let output_format = match profile.target_type {
-            BuildTargetType::Html => OutputFormat::Html,
-            BuildTargetType::Pdf => OutputFormat::Pdf,
-        };
-
         let mut input_buffer = String::new();
 
-        for input in &profile.inputs {
+        for (name, value) in &profile.variables {
+            writeln!(input_buffer, "\\def\\{name}{{{value}}}")?;
+        }
+
+        for input in &self.expand_inputs(&profile.inputs)? {
             match input {
                 InputFile::Inline(s) => {
                     writeln!(input_buffer, "{s}")?;
@@ -133,6 +182,23 @@ impl DocumentExt for Document {
             };
         }
 
+        Ok(input_buffer)
+    }
+
+    fn setup_session(
+        &self,
+        output_profile: &str,
+        setup_options: &DocumentSetupOptions,
+        status: &mut dyn StatusBackend,
+    ) -> Result<ProcessingSessionBuilder> {
+        let input_buffer = self.primary_input_text(output_profile)?;
+        let profile = &self.outputs[output_profile];
+
+        let output_format = match profile.target_type {
+            BuildTargetType::Html => OutputFormat::Html,
+            BuildTargetType::Pdf => OutputFormat::Pdf,
+        };
+
         let mut sess_builder =
             ProcessingSessionBuilder::new_with_security(setup_options.security.clone());
 
@@ -155,7 +221,78 @@ impl DocumentExt for Document {
             .pass(PassSetting::Default)
             .primary_input_buffer(input_buffer.as_bytes())
             .tex_input_name(output_profile)
-            .synctex(profile.synctex);
+            .synctex(profile.synctex)
+            .system_fonts(profile.system_fonts)
+            .font_features(&profile.font_features)
+            .pdfa(profile.pdfa)
+            .html_mathml(profile.mathml)
+            .html_optimize_assets(profile.optimize_assets)
+            .html_split_level(profile.split_level);
+
+        for path in &profile.attachments {
+            sess_builder.pdf_attachment(self.src_dir().join(path));
+        }
+
+        for (name, path) in &profile.html_templates {
+            sess_builder.html_template(name, self.src_dir().join(path));
+        }
+
+        if output_format == OutputFormat::Pdf {
+            let info = &self.info;
+
+            sess_builder.pdf_metadata(PdfMetadata {
+                title: info.title.clone(),
+                author: info.author.clone(),
+                subject: info.subject.clone(),
+                keywords: if info.keywords.is_empty() {
+                    None
+                } else {
+                    Some(info.keywords.join(", "))
+                },
+                language: info.language.clone(),
+            });
+        }
+
+        let images = &profile.images;
+
+        sess_builder.image_options(ImageOptions {
+            max_dpi: images.max_dpi,
+            jpeg_quality: images.jpeg_quality,
+            grayscale: images.grayscale,
+        });
+
+        sess_builder.pdf_encryption(profile.encryption.as_ref().map(|enc| EncryptionOptions {
+            owner_password: enc.owner_password.clone(),
+            user_password: enc.user_password.clone(),
+            allow_printing: enc.allow_printing,
+            allow_copying: enc.allow_copying,
+        }));
+
+        let bibtex = &profile.bibtex;
+
+        sess_builder.bibtex_options(BibtexOptions {
+            style: bibtex.style.clone(),
+            min_crossrefs: bibtex.min_crossrefs,
+            warnings_as_errors: bibtex.warnings_as_errors,
+            search_paths: bibtex
+                .search_paths
+                .iter()
+                .map(|x| self.src_dir().join(x))
+                .collect(),
+        });
+
+        for hook in &self.hooks {
+            sess_builder.tool_hook(driver::ToolHook {
+                stage: match hook.stage {
+                    HookStage::Pre => driver::HookStage::Pre,
+                    HookStage::Post => driver::HookStage::Post,
+                },
+                command: hook.command.clone(),
+                inputs: hook.inputs.clone(),
+                outputs: hook.outputs.clone(),
+                stdout: hook.stdout.clone(),
+            });
+        }
 
         if profile.shell_escape {
             // For now, this is the only option we allow.
@@ -164,6 +301,10 @@ impl DocumentExt for Document {
             } else {
                 sess_builder.shell_escape_with_temp_dir();
             }
+
+            if let Some(allowed) = &self.shell_escape_allow {
+                sess_builder.shell_escape_allowlist(allowed.iter().cloned());
+            }
         }
 
         if setup_options.only_cached {
@@ -199,6 +340,57 @@ pub trait WorkspaceCreatorExt {
         config: &config::PersistentConfig,
         bundle: Option<String>,
     ) -> Result<Workspace>;
+
+    /// Create the new workspace from a template directory, with a good default
+    /// for the bundle location.
+    ///
+    /// This method is a thin wrapper on
+    /// [`WorkspaceCreator::create_from_template`]. The bundle location is only
+    /// used if the template doesn't provide its own `Tectonic.toml`.
+    fn create_from_template_defaulted(
+        self,
+        config: &config::PersistentConfig,
+        bundle: Option<String>,
+        template_dir: &Path,
+    ) -> Result<Workspace>;
+
+    /// Create a workspace around existing TeX sources, with a good default
+    /// for the bundle location.
+    ///
+    /// This method is a thin wrapper on
+    /// [`WorkspaceCreator::create_for_existing`].
+    fn create_for_existing_defaulted(
+        self,
+        config: &config::PersistentConfig,
+        bundle: Option<String>,
+        extra_paths: Vec<PathBuf>,
+        inputs: Vec<InputFile>,
+    ) -> Result<Workspace>;
+}
+
+/// Determine the bundle location to use for a new workspace.
+fn default_bundle_loc_for_new(
+    config: &config::PersistentConfig,
+    bundle: Option<String>,
+) -> Result<String> {
+    if config::is_test_bundle_wanted(bundle.clone()) {
+        Ok("test-bundle://".to_owned())
+    } else {
+        let loc = bundle.unwrap_or(config.default_bundle_loc().to_owned());
+
+        // A local bundle is recorded with its absolute path, since the new
+        // document will live in a different directory.
+        if Path::new(&loc).exists() {
+            let path = ctry!(
+                fs::canonicalize(&loc);
+                "couldn't find the absolute path of the bundle `{}`", loc
+            );
+            return Ok(path.display().to_string());
+        }
+
+        let mut gub = DefaultBackend::default();
+        Ok(gub.resolve_url(&loc)?)
+    }
 }
 
 impl WorkspaceCreatorExt for WorkspaceCreator {
@@ -207,14 +399,28 @@ impl WorkspaceCreatorExt for WorkspaceCreator {
         config: &config::PersistentConfig,
         bundle: Option<String>,
     ) -> Result<Workspace> {
-        let bundle_loc = if config::is_test_bundle_wanted(bundle.clone()) {
-            "test-bundle://".to_owned()
-        } else {
-            let loc = bundle.unwrap_or(config.default_bundle_loc().to_owned());
-            let mut gub = DefaultBackend::default();
-            gub.resolve_url(&loc)?
-        };
-
+        let bundle_loc = default_bundle_loc_for_new(config, bundle)?;
         Ok(self.create(bundle_loc, Vec::new())?)
     }
+
+    fn create_from_template_defaulted(
+        self,
+        config: &config::PersistentConfig,
+        bundle: Option<String>,
+        template_dir: &Path,
+    ) -> Result<Workspace> {
+        let bundle_loc = default_bundle_loc_for_new(config, bundle)?;
+        Ok(self.create_from_template(template_dir, bundle_loc, Vec::new())?)
+    }
+
+    fn create_for_existing_defaulted(
+        self,
+        config: &config::PersistentConfig,
+        bundle: Option<String>,
+        extra_paths: Vec<PathBuf>,
+        inputs: Vec<InputFile>,
+    ) -> Result<Workspace> {
+        let bundle_loc = default_bundle_loc_for_new(config, bundle)?;
+        Ok(self.create_for_existing(bundle_loc, extra_paths, inputs)?)
+    }
 }
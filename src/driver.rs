@@ -16,17 +16,19 @@
 use byte_unit::{Byte, UnitType};
 use quick_xml::{events::Event, NsReader};
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
     fs::File,
     io::{Cursor, Read, Write},
-    path::{Path, PathBuf},
-    process::Command,
+    path::{Component, Path, PathBuf},
+    process::{Command, Stdio},
     rc::Rc,
     result::Result as StdResult,
     str::FromStr,
     time::{Duration, SystemTime},
 };
-use tectonic_bridge_core::{CoreBridgeLauncher, DriverHooks, SecuritySettings, SystemRequestError};
+use tectonic_bridge_core::{
+    CancelFlag, CoreBridgeLauncher, DriverHooks, FileAccess, SecuritySettings, SystemRequestError,
+};
 use tectonic_bundles::Bundle;
 use tectonic_engine_spx2html::AssetSpecification;
 use tectonic_io_base::{
@@ -38,17 +40,25 @@ use tectonic_io_base::{
 use which::which;
 
 use crate::{
-    ctry, errmsg,
-    errors::{ChainErrCompatExt, ErrorKind, Result},
+    ctry,
+    digest::{self, Digest},
+    engines::{
+        bibtex::parse_blg_diagnostics,
+        makeindex::IndexStyle,
+        tex::{parse_log_diagnostics, parse_log_trace},
+    },
+    errmsg,
+    errors::{ChainErrCompatExt, ErrorKind, Result, SyncError},
     io::{
         format_cache::FormatCache,
         memory::{MemoryFileCollection, MemoryIo},
-        InputOrigin,
+        normalize_tex_path, InputOrigin, ZipIo,
     },
-    status::StatusBackend,
+    status::{MessageKind, NoopStatusBackend, Progress, SourceDiagnostic, StatusBackend},
     tt_error, tt_note, tt_warning,
     unstable_opts::UnstableOptions,
-    BibtexEngine, Spx2HtmlEngine, TexEngine, TexOutcome, XdvipdfmxEngine,
+    BibtexEngine, InteractionMode, MakeindexEngine, MemoryParameter, Spx2HtmlEngine, TexEngine,
+    TexOutcome, XdvipdfmxEngine,
 };
 
 /// Different patterns with which files may have been accessed by the
@@ -145,6 +155,162 @@ impl FromStr for OutputFormat {
     }
 }
 
+impl OutputFormat {
+    /// The file extension of the main output of this format.
+    ///
+    /// HTML output has many files, so this is the extension of just the main
+    /// page.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Aux => "aux",
+            OutputFormat::Html => "html",
+            OutputFormat::Xdv => "xdv",
+            OutputFormat::Pdf => "pdf",
+            OutputFormat::Format => "fmt",
+        }
+    }
+}
+
+/// Metadata to record in PDF outputs.
+///
+/// These are defaults: if the document sets the same metadata itself, for
+/// instance with `hyperref`, its values are used instead.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct PdfMetadata {
+    /// The document title.
+    pub title: Option<String>,
+
+    /// The name of the person or people who wrote the document.
+    pub author: Option<String>,
+
+    /// The subject of the document.
+    pub subject: Option<String>,
+
+    /// Keywords associated with the document.
+    pub keywords: Option<String>,
+
+    /// The natural language of the document, as a language tag like `en-US`.
+    pub language: Option<String>,
+}
+
+/// Settings for the BibTeX passes.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct BibtexOptions {
+    /// The name of a style to use instead of the one chosen by the document,
+    /// with or without its `.bst` extension.
+    pub style: Option<String>,
+
+    /// The BibTeX `min_crossrefs` parameter. If unset, the `min-crossrefs`
+    /// unstable option is used, or else BibTeX's default.
+    pub min_crossrefs: Option<u32>,
+
+    /// Whether BibTeX warnings and errors should make processing fail.
+    pub warnings_as_errors: bool,
+
+    /// Extra directories to search for database files, before the usual
+    /// locations. These are ignored if the security settings don't allow
+    /// extra search paths.
+    pub search_paths: Vec<PathBuf>,
+}
+
+/// Settings for the raster images embedded in PDF outputs.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ImageOptions {
+    /// The maximum resolution of images, in dots per inch. See
+    /// `XdvipdfmxEngine::image_max_dpi`.
+    pub max_dpi: Option<u32>,
+
+    /// The quality at which JPEG images are re-encoded. See
+    /// `XdvipdfmxEngine::jpeg_quality`.
+    pub jpeg_quality: Option<u8>,
+
+    /// Whether color images should be converted to grayscale.
+    pub grayscale: bool,
+}
+
+/// Settings for encrypting PDF outputs.
+///
+/// See `XdvipdfmxEngine::encryption` for how these are applied.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EncryptionOptions {
+    /// The password that lifts the restrictions on the document. It may not
+    /// be empty.
+    pub owner_password: String,
+
+    /// The password needed to open the document. If it's empty, anyone can
+    /// open the document, subject to the restrictions.
+    pub user_password: String,
+
+    /// Whether the document may be printed without the owner password.
+    pub allow_printing: bool,
+
+    /// Whether text and graphics may be copied out of the document without
+    /// the owner password.
+    pub allow_copying: bool,
+}
+
+/// Settings for the outline (bookmarks) of PDF outputs.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct OutlineOptions {
+    /// If set, LaTeX documents that don't load `hyperref` get an outline entry
+    /// for each table of contents entry down to this sectioning level. As with
+    /// LaTeX's `tocdepth`, chapters are level 0, sections level 1, and so on;
+    /// parts are always included.
+    pub depth: Option<u8>,
+
+    /// The number of outline levels that are expanded when the PDF is opened.
+    /// See `XdvipdfmxEngine::outline_open_depth`.
+    pub open_level: Option<u8>,
+}
+
+/// When a [`ToolHook`] is run.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum HookStage {
+    /// Before the first TeX pass.
+    Pre,
+
+    /// After the first TeX pass. The TeX engine is always rerun afterwards, so
+    /// that it can use the tool's outputs.
+    Post,
+}
+
+/// An external program that is run as part of the processing pipeline.
+///
+/// The program runs in a temporary directory, like the other external tools
+/// that the driver uses, such as `biber`. Its inputs are copied into that
+/// directory first, and its outputs are brought back afterwards, so that they
+/// can be read by the engines and saved with the other output files.
+///
+/// The temporary directory doesn't isolate the program in any way: it can read
+/// and write anything that the user running Tectonic can. This is bad for
+/// reproducibility, and running arbitrary programs is no more secure than
+/// shell-escape, so sessions with hooks can only be created if the security
+/// settings allow shell-escape.
+///
+/// This is the driver's counterpart of
+/// [`tectonic_docmodel::document::ToolHook`], which it can't use directly
+/// because the `tectonic_docmodel` dependency is optional.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ToolHook {
+    /// When the program is run.
+    pub stage: HookStage,
+
+    /// The program, followed by its arguments.
+    pub command: Vec<String>,
+
+    /// Files that the program needs. Arguments that name files produced by
+    /// the session are also treated as inputs.
+    pub inputs: Vec<String>,
+
+    /// The files or directories that the program creates. If empty, all of the
+    /// files that it creates at the top level of its working directory are
+    /// used.
+    pub outputs: Vec<String>,
+
+    /// If set, the name of a file to save the program's standard output to.
+    pub stdout: Option<String>,
+}
+
 /// The different types of "passes" that [`ProcessingSession`] knows how to run. See
 /// [`ProcessingSession::run`] for more details.
 #[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
@@ -156,6 +322,9 @@ pub enum PassSetting {
     Tex,
     /// Like the default pass, but runs BibTeX once first, before doing anything else.
     BibtexFirst,
+    /// Just run BibTeX once, on the `.aux` file named by the TeX input name. The TeX engine is
+    /// not run at all, so no format file is needed.
+    Bibtex,
 }
 
 impl FromStr for PassSetting {
@@ -181,6 +350,9 @@ enum PrimaryInputMode {
     /// A path on the filesystem.
     Path(PathBuf),
 
+    /// The main TeX file of a ZIP archive on the filesystem.
+    Zip(PathBuf),
+
     /// An in-memory buffer.
     Buffer(Vec<u8>),
 }
@@ -202,6 +374,90 @@ enum OutputDestination {
     Nowhere,
 }
 
+/// Find where the preamble of a LaTeX document ends: at a line reading
+/// `%endofdump`, as with the `mylatexformat` package, or else at
+/// `\begin{document}`. Returns None if neither can be found.
+fn find_preamble_end(text: &[u8]) -> Option<usize> {
+    let mut offset = 0;
+
+    for line in text.split_inclusive(|b| *b == b'\n') {
+        if line.trim_ascii() == b"%endofdump" {
+            return Some(offset);
+        }
+
+        // Ignore anything in a comment, taking care that `\%` doesn't start
+        // one.
+
+        let mut code_len = 0;
+
+        while code_len < line.len() {
+            match line[code_len] {
+                b'%' => break,
+                b'\\' => code_len += 2,
+                _ => code_len += 1,
+            }
+        }
+
+        let code = &line[..code_len.min(line.len())];
+
+        if let Some(pos) = code.windows(16).position(|w| w == b"\\begin{document}") {
+            return Some(offset + pos);
+        }
+
+        offset += line.len();
+    }
+
+    None
+}
+
+/// The primary input of a document whose preamble has been compiled into a
+/// format file: the original input with the preamble blanked out. The line
+/// breaks are kept so that the engine reports the right line numbers.
+struct PrecompiledPreambleIo {
+    body: Vec<u8>,
+    origin: InputOrigin,
+    abspath: Option<PathBuf>,
+}
+
+impl PrecompiledPreambleIo {
+    fn new(
+        mut text: Vec<u8>,
+        preamble_len: usize,
+        origin: InputOrigin,
+        abspath: Option<PathBuf>,
+    ) -> Self {
+        for b in &mut text[..preamble_len] {
+            if *b != b'\n' && *b != b'\r' {
+                *b = b' ';
+            }
+        }
+
+        PrecompiledPreambleIo {
+            body: text,
+            origin,
+            abspath,
+        }
+    }
+}
+
+impl IoProvider for PrecompiledPreambleIo {
+    fn input_open_primary(&mut self, status: &mut dyn StatusBackend) -> OpenResult<InputHandle> {
+        match self.input_open_primary_with_abspath(status) {
+            OpenResult::Ok((ih, _path)) => OpenResult::Ok(ih),
+            OpenResult::Err(e) => OpenResult::Err(e),
+            OpenResult::NotAvailable => OpenResult::NotAvailable,
+        }
+    }
+
+    fn input_open_primary_with_abspath(
+        &mut self,
+        _status: &mut dyn StatusBackend,
+    ) -> OpenResult<(InputHandle, Option<PathBuf>)> {
+        let handle = InputHandle::new("", Cursor::new(self.body.clone()), self.origin);
+        OpenResult::Ok((handle, self.abspath.clone()))
+    }
+}
+
 /// The subset of the driver state that is captured when running a C/C++ engine.
 ///
 /// The main purpose of this type is to implement the [`DriverHooks`] trait,
@@ -226,12 +482,24 @@ struct BridgeState {
     /// Memory buffering for files written during processing.
     mem: MemoryIo,
 
+    /// Input files provided in memory by the caller. These take precedence
+    /// over files on disk, but aren't reported as outputs.
+    memory_inputs: MemoryIo,
+
+    /// The ZIP archive that the primary input came from, if any. Its files
+    /// take precedence over those on the filesystem.
+    archive: Option<ZipIo>,
+
     /// The main filesystem backing for input files in the project.
     filesystem: FilesystemIo,
 
     /// Extra paths we search through for files.
     extra_search_paths: Vec<FilesystemIo>,
 
+    /// Caller-provided sources of input files, searched after the
+    /// filesystem.
+    input_providers: Vec<Box<dyn IoProvider>>,
+
     /// Additional filesystem backing used if "shell escape" functionality is
     /// activated. If None, we take that to mean that shell-escape is
     /// disallowed. We have to use a persistent filesystem directory for this
@@ -239,6 +507,10 @@ struct BridgeState {
     /// that assume continuity from one to the next.
     shell_escape_work: Option<FilesystemIo>,
 
+    /// If set, the only programs that shell-escape commands may run. Such
+    /// commands are run without a shell; see [`RestrictedCommand`].
+    shell_escape_allowlist: Option<Vec<String>>,
+
     /// I/O for saving any generated format files.
     format_cache: FormatCache,
 
@@ -265,6 +537,15 @@ impl BridgeState {
         )));
     }
 
+    /// Enter "format mode" to generate a format file with a document's
+    /// preamble preloaded. The primary input is the preamble, which is followed
+    /// by a `\dump` that can't have been redefined.
+    fn enter_preamble_format_mode(&mut self, preamble: &[u8]) {
+        let mut text = preamble.to_owned();
+        text.extend_from_slice(b"\n\\primitive\\dump\n");
+        self.format_primary = Some(BufferedPrimaryIo::from_buffer(text));
+    }
+
     /// Leave "format mode".
     fn leave_format_mode(&mut self) {
         self.format_primary = None;
@@ -309,7 +590,7 @@ impl BridgeState {
             for name in &read_files {
                 // If a relative parent is found in the file to open, this fn
                 // does not properly handle that. Thus, throw an error.
-                if name.contains("../") {
+                if has_parent_dir(name) {
                     return Err(errmsg!(
                         "relative parent paths are not supported for the \
                         external tool. Got path `{}`.",
@@ -355,7 +636,10 @@ impl BridgeState {
 
         // Now we can actually run the command.
 
-        let output = cmd.current_dir(tempdir.path()).output()?;
+        let output = ctry!(
+            cmd.current_dir(tempdir.path()).output();
+            "failed to run the external tool `{}`", tool.argv[0]
+        );
 
         if let Some(0) = output.status.code() {
         } else {
@@ -374,37 +658,67 @@ impl BridgeState {
             };
         }
 
+        if let Some(name) = &tool.stdout {
+            if has_parent_dir(name) || Path::new(name).is_absolute() {
+                return Err(errmsg!(
+                    "external tool output must be saved to a relative path without `..`. \
+                    Got path `{}`.",
+                    name
+                ));
+            }
+
+            self.import_tool_output(name, output.stdout);
+        }
+
         // Search for any files that the tool created, and import them into the
         // memory layer.
 
-        for entry in std::fs::read_dir(tempdir.path())? {
-            let entry = entry?;
+        if tool.outputs.is_empty() {
+            for entry in std::fs::read_dir(tempdir.path())? {
+                let entry = entry?;
 
-            if !entry.file_type()?.is_file() {
-                continue;
+                if !entry.file_type()?.is_file() {
+                    continue;
+                }
+
+                if let Some(basename) = entry.file_name().to_str() {
+                    if !self.mem.files.borrow().contains_key(basename) {
+                        let data = read_tool_file(&entry.path())?;
+                        self.import_tool_output(basename, data);
+                    }
+                }
             }
+        } else {
+            let mut created = Vec::new();
 
-            if let Some(basename) = entry.file_name().to_str() {
-                if !self.mem.files.borrow().contains_key(basename) {
-                    let path = entry.path();
-                    let mut data = Vec::new();
+            for name in &tool.outputs {
+                if has_parent_dir(name) || Path::new(name).is_absolute() {
+                    return Err(errmsg!(
+                        "external tool outputs must be relative paths inside its \
+                        working directory. Got path `{}`.",
+                        name
+                    ));
+                }
 
-                    let mut f = ctry!(
-                        File::open(&path);
-                        "failed to open tool-created file `{}`", path.display()
-                    );
-                    ctry!(
-                        f.read_to_end(&mut data);
-                        "failed to read tool-created file `{}`", path.display()
-                    );
+                let path = tempdir.path().join(name);
 
-                    self.mem.create_entry(basename, data);
-                    self.events.insert(
-                        basename.to_owned(),
-                        FileSummary::new(AccessPattern::Written, InputOrigin::NotInput),
-                    );
+                if path.is_dir() {
+                    collect_tool_files(&path, name, &mut created)?;
+                } else if path.is_file() {
+                    created.push((name.to_owned(), path));
+                } else {
+                    return Err(errmsg!(
+                        "the external tool `{}` did not create `{}`",
+                        tool.argv[0],
+                        name
+                    ));
                 }
             }
+
+            for (name, path) in created {
+                let data = read_tool_file(&path)?;
+                self.import_tool_output(&name, data);
+            }
         }
 
         // Mark the input files as having been read, and we're done.
@@ -420,6 +734,16 @@ impl BridgeState {
         Ok(())
     }
 
+    /// Save a file created outside of the engines, such as by an external
+    /// tool, in the memory layer.
+    fn import_tool_output(&mut self, name: &str, data: Vec<u8>) {
+        self.mem.create_entry(name, data);
+        self.events.insert(
+            name.to_owned(),
+            FileSummary::new(AccessPattern::Written, InputOrigin::NotInput),
+        );
+    }
+
     // Get the names of all intermediate files which are generated from
     // previous passes.
     fn get_intermediate_file_names(&self) -> Vec<String> {
@@ -428,6 +752,305 @@ impl BridgeState {
     }
 }
 
+/// Append the lines of the `.aux` file `name` that matter to BibTeX to
+/// `lines`, recursing into the files that it includes with `\@input`. Files
+/// that were already visited are skipped, so that a cycle can't send us
+/// around forever.
+fn collect_bibtex_aux_lines(
+    files: &MemoryFileCollection,
+    name: &str,
+    seen: &mut HashSet<String>,
+    lines: &mut Vec<Vec<u8>>,
+) {
+    const COMMANDS: &[&[u8]] = &[b"\\citation", b"\\bibdata", b"\\bibstyle"];
+    const INPUT: &[u8] = b"\\@input{";
+
+    if !seen.insert(name.to_owned()) {
+        return;
+    }
+
+    let Some(file) = files.get(name) else {
+        return;
+    };
+
+    for line in file.data.split(|&c| c == b'\n') {
+        if COMMANDS.iter().any(|cmd| line.starts_with(cmd)) {
+            lines.push(line.to_vec());
+        } else if let Some(rest) = line.strip_prefix(INPUT) {
+            let sub = rest.split(|&c| c == b'}').next().unwrap_or_default();
+
+            if let Ok(sub) = std::str::from_utf8(sub) {
+                collect_bibtex_aux_lines(files, sub, seen, lines);
+            }
+        }
+    }
+}
+
+/// The glossaries that the `glossaries` package declares in an `.aux` file,
+/// so that they can be sorted with `makeindex`.
+#[derive(Debug, Default)]
+struct GlossaryAux {
+    /// The name of the `makeindex` style file, from `\@istfilename`.
+    style_file: Option<String>,
+
+    /// Whether entries are sorted letter by letter, from `\@glsorder`.
+    letter_ordering: bool,
+
+    /// The extensions of the transcript, output and input files of each
+    /// glossary, from `\@newglossary`.
+    glossaries: Vec<[String; 3]>,
+}
+
+impl GlossaryAux {
+    fn parse(aux: &[u8]) -> GlossaryAux {
+        let mut info = GlossaryAux::default();
+
+        for line in String::from_utf8_lossy(aux).lines() {
+            let args = |cmd: &str| {
+                line.strip_prefix(cmd)
+                    .and_then(|rest| rest.strip_prefix('{'))
+                    .and_then(|rest| rest.strip_suffix('}'))
+                    .map(|rest| rest.split("}{").map(str::to_owned).collect::<Vec<_>>())
+            };
+
+            if let Some(args) = args("\\@istfilename") {
+                info.style_file = args.into_iter().next();
+            } else if let Some(args) = args("\\@glsorder") {
+                info.letter_ordering = args.first().is_some_and(|o| o == "letter");
+            } else if let Some(args) = args("\\@newglossary") {
+                if let [_, log, out, input] = &args[..] {
+                    info.glossaries
+                        .push([log.clone(), out.clone(), input.clone()]);
+                }
+            }
+        }
+
+        info
+    }
+}
+
+/// Check whether a path used by an external tool refers to a parent
+/// directory anywhere along the way.
+fn has_parent_dir(name: &str) -> bool {
+    Path::new(name)
+        .components()
+        .any(|c| c == Component::ParentDir)
+}
+
+/// Might the shell-escape argument *arg* refer to a path outside of the
+/// working directory?
+///
+/// Besides the argument as a whole, this checks the values of options, as in
+/// `--output=/etc/x`. Short options can carry a value right after the option
+/// letter, as in `-o../x`, and can be grouped, as in `-vo../x`, so an argument
+/// starting with a single `-` is also checked from each point where such a
+/// value could start. That rejects some harmless arguments like `-Isub/dir`,
+/// which can be written as `-I sub/dir` instead.
+fn escapes_work_dir(arg: &str) -> bool {
+    let mut candidates = vec![arg];
+    candidates.extend(arg.match_indices('=').map(|(i, _)| &arg[i + 1..]));
+
+    if let Some(rest) = arg.strip_prefix('-').filter(|r| !r.starts_with('-')) {
+        let letters = rest
+            .find(|c: char| !c.is_ascii_alphanumeric())
+            .unwrap_or(rest.len());
+        candidates.extend((1..=letters).map(|i| &rest[i..]));
+    }
+
+    candidates
+        .iter()
+        .any(|c| Path::new(c).is_absolute() || has_parent_dir(c))
+}
+
+/// Environment variables that are passed on to restricted shell-escape
+/// commands. The rest of the environment is withheld from them.
+const SHELL_ESCAPE_ENV_VARS: &[&str] = &[
+    "PATH",
+    "HOME",
+    "LANG",
+    "LC_ALL",
+    "LC_CTYPE",
+    "TMPDIR",
+    "TEMP",
+    "TMP",
+    "PATHEXT",
+    "SYSTEMROOT",
+    "USERPROFILE",
+];
+
+/// A shell-escape command that has been checked against an allowlist of
+/// programs, and split up so that it can be run without a shell.
+#[derive(Debug, Eq, PartialEq)]
+struct RestrictedCommand {
+    /// The program, followed by its arguments.
+    argv: Vec<String>,
+
+    /// The file that the command's standard output is redirected to, if any.
+    stdout: Option<String>,
+}
+
+impl RestrictedCommand {
+    /// Split up a shell-escape command and check it against the *allowed*
+    /// programs.
+    ///
+    /// Words may be quoted with single or double quotes, and the standard
+    /// output may be redirected to a file with a final `> file`, but no other
+    /// shell syntax is understood. The program must be named exactly as in the
+    /// allowlist, and no argument may be an absolute path or lead out of the
+    /// working directory with `..`, including paths given as the values of
+    /// options; see [`escapes_work_dir`].
+    fn parse(command: &str, allowed: &[String]) -> StdResult<Self, String> {
+        let mut words = Vec::new();
+        let mut word: Option<String> = None;
+        let mut quote = None;
+        let mut redirect_at = None;
+
+        for c in command.chars() {
+            match (quote, c) {
+                (Some(q), c) if c == q => quote = None,
+                (Some(_), c) => word.get_or_insert_with(String::new).push(c),
+                (None, '\'' | '"') => {
+                    quote = Some(c);
+                    word.get_or_insert_with(String::new);
+                }
+                (None, c) if c.is_whitespace() => words.extend(word.take()),
+                (None, '>') if redirect_at.is_none() => {
+                    words.extend(word.take());
+                    redirect_at = Some(words.len());
+                }
+                (None, '|' | '&' | ';' | '<' | '>' | '(' | ')' | '$' | '`') => {
+                    return Err(format!("shell syntax like `{c}` isn't supported"));
+                }
+                (None, c) => word.get_or_insert_with(String::new).push(c),
+            }
+        }
+
+        if quote.is_some() {
+            return Err("it has an unterminated quotation".to_owned());
+        }
+
+        words.extend(word.take());
+
+        let stdout = match redirect_at {
+            None => None,
+            Some(i) if i > 0 && words.len() == i + 1 => words.pop(),
+            Some(_) => return Err("an output redirection must come last".to_owned()),
+        };
+
+        let Some(program) = words.first() else {
+            return Err("it is empty".to_owned());
+        };
+
+        if !allowed.contains(program) {
+            return Err(format!("`{program}` is not an allowed program"));
+        }
+
+        for arg in words[1..].iter().chain(&stdout) {
+            if escapes_work_dir(arg) {
+                return Err(format!(
+                    "`{arg}` refers to a path outside of the working directory"
+                ));
+            }
+        }
+
+        Ok(RestrictedCommand {
+            argv: words,
+            stdout,
+        })
+    }
+}
+
+/// Make sure that the security settings allow the file at *path* to be
+/// written.
+fn check_write_access(security: &SecuritySettings, path: &Path) -> Result<()> {
+    if !security.allow_file_access(FileAccess::Write, path) {
+        return Err(errmsg!(
+            "forbidden to write `{}` by the security settings",
+            path.display()
+        ));
+    }
+
+    Ok(())
+}
+
+/// Describe a list of file names for a message, such as `"a.aux" and "a.toc"`.
+fn describe_file_list(names: &[String]) -> String {
+    let quoted: Vec<String> = names.iter().map(|n| format!("\"{n}\"")).collect();
+
+    match quoted.as_slice() {
+        [] => String::new(),
+        [one] => one.clone(),
+        [first, second] => format!("{first} and {second}"),
+        [rest @ .., last] => format!("{}, and {}", rest.join(", "), last),
+    }
+}
+
+/// Does the file *name* have one of the extensions that should be kept?
+fn is_kept(exts: &HashSet<String>, name: &str) -> bool {
+    exts.iter().any(|ext| {
+        name.strip_suffix(ext.as_str())
+            .is_some_and(|stem| stem.ends_with('.'))
+    })
+}
+
+/// Create the file *name* in the shell-escape working directory *root*,
+/// making sure that symbolic links don't put it outside of the directory.
+fn create_work_file(root: &Path, name: &str) -> std::io::Result<File> {
+    let path = root.join(name);
+    let parent = path.parent().unwrap_or(root);
+
+    if !parent.canonicalize()?.starts_with(root.canonicalize()?)
+        || path
+            .symlink_metadata()
+            .is_ok_and(|m| m.file_type().is_symlink())
+    {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            "the path leads outside of the working directory",
+        ));
+    }
+
+    File::create(path)
+}
+
+/// Read a file created by an external tool.
+fn read_tool_file(path: &Path) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+
+    let mut f = ctry!(
+        File::open(path);
+        "failed to open tool-created file `{}`", path.display()
+    );
+    ctry!(
+        f.read_to_end(&mut data);
+        "failed to read tool-created file `{}`", path.display()
+    );
+
+    Ok(data)
+}
+
+/// Recursively collect the files in a directory created by an external tool,
+/// along with their names relative to the tool's working directory. `name` is
+/// the name of the directory itself.
+fn collect_tool_files(dir: &Path, name: &str, files: &mut Vec<(String, PathBuf)>) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+
+        let entry_name = match entry.file_name().into_string() {
+            Ok(n) => format!("{}/{}", name.trim_end_matches('/'), n),
+            Err(_) => continue,
+        };
+
+        if entry.file_type()?.is_dir() {
+            collect_tool_files(&entry.path(), &entry_name, files)?;
+        } else {
+            files.push((entry_name, entry.path()));
+        }
+    }
+
+    Ok(())
+}
+
 macro_rules! bridgestate_ioprovider_try {
     ($provider:expr, $($inner:tt)+) => {
         let r = $provider.$($inner)+;
@@ -457,6 +1080,12 @@ macro_rules! bridgestate_ioprovider_cascade {
         bridgestate_ioprovider_try!($self.mem, $($inner)+);
 
         if use_fs {
+            bridgestate_ioprovider_try!($self.memory_inputs, $($inner)+);
+
+            if let Some(ref mut p) = $self.archive {
+                bridgestate_ioprovider_try!(p, $($inner)+);
+            }
+
             bridgestate_ioprovider_try!($self.filesystem, $($inner)+);
 
             // With this ordering, we are preventing files created by
@@ -472,6 +1101,10 @@ macro_rules! bridgestate_ioprovider_cascade {
             for fsio in $self.extra_search_paths.iter_mut() {
                 bridgestate_ioprovider_try!(fsio, $($inner)+);
             }
+
+            for p in $self.input_providers.iter_mut() {
+                bridgestate_ioprovider_try!(p, $($inner)+);
+            }
         }
 
         bridgestate_ioprovider_try!($self.bundle.as_ioprovider_mut(), $($inner)+);
@@ -576,6 +1209,12 @@ impl IoProvider for BridgeState {
                         AccessPattern::Written => AccessPattern::WrittenThenRead,
                         c => c, // identity mapping makes sense for remaining options
                     };
+
+                    // Read digests are reset between passes, and this pass
+                    // saw the file as empty.
+                    if summ.read_digest.is_none() {
+                        summ.read_digest = Some(DigestData::of_nothing());
+                    }
                 } else {
                     // Unlike other cases, here we need to fill in the read_digest. `None`
                     // is not an appropriate value since, if the file is written and then
@@ -714,16 +1353,48 @@ impl DriverHooks for BridgeState {
                 })?;
             }
 
-            // Now we can actually run the command.
+            // Now we can actually run the command. If there's an allowlist,
+            // the command is run without a shell, and with only a few
+            // environment variables that programs commonly need.
 
-            tt_note!(status, "running shell command: `{}`", command);
+            let mut cmd = match &self.shell_escape_allowlist {
+                None => {
+                    tt_note!(status, "running shell command: `{}`", command);
+                    let mut cmd = Command::new(SHELL[0]);
+                    cmd.args(&SHELL[1..]).arg(command);
+                    cmd
+                }
 
-            match Command::new(SHELL[0])
-                .args(&SHELL[1..])
-                .arg(command)
-                .current_dir(work.root())
-                .status()
-            {
+                Some(allowed) => {
+                    let restricted = RestrictedCommand::parse(command, allowed).map_err(|e| {
+                        tt_error!(status, "refusing to run shell command `{}`: {}", command, e);
+                        SystemRequestError::NotAllowed
+                    })?;
+
+                    tt_note!(status, "running restricted shell command: `{}`", command);
+                    let mut cmd = Command::new(&restricted.argv[0]);
+                    cmd.args(&restricted.argv[1..])
+                        .env_clear()
+                        .stdin(Stdio::null());
+
+                    for var in SHELL_ESCAPE_ENV_VARS {
+                        if let Some(value) = std::env::var_os(var) {
+                            cmd.env(var, value);
+                        }
+                    }
+
+                    if let Some(name) = &restricted.stdout {
+                        cmd.stdout(create_work_file(work.root(), name).map_err(|e| {
+                            tt_error!(status, "failed to create file `{}`", name; e.into());
+                            SystemRequestError::Failed
+                        })?);
+                    }
+
+                    cmd
+                }
+            };
+
+            match cmd.current_dir(work.root()).status() {
                 Ok(s) => match s.code() {
                     Some(0) => Ok(()),
                     Some(n) => {
@@ -786,24 +1457,98 @@ enum ShellEscapeMode {
 struct ExternalToolPass {
     argv: Vec<String>,
     extra_requires: HashSet<String>,
+
+    /// The files or directories to bring back from the tool's working
+    /// directory. If empty, every new top-level file is.
+    outputs: Vec<String>,
+
+    /// Where to save the tool's standard output, if anywhere.
+    stdout: Option<String>,
 }
 
-/// A builder-style interface for creating a [`ProcessingSession`].
+impl From<&ToolHook> for ExternalToolPass {
+    fn from(hook: &ToolHook) -> Self {
+        ExternalToolPass {
+            argv: hook.command.clone(),
+            extra_requires: hook.inputs.iter().cloned().collect(),
+            outputs: hook.outputs.clone(),
+            stdout: hook.stdout.clone(),
+        }
+    }
+}
+
+/// The name under which the TeX code that generates outline entries is
+/// provided to the engine.
+const OUTLINE_TEX_NAME: &str = "tectonic-outline.tex";
+
+/// Wraps the primary input provider so that TeX reads some extra code before
+/// the input itself.
 ///
-/// This uses standard builder patterns. The `Default` implementation defaults
-/// to restrictive security settings that disable all known-insecure features
-/// that could be abused by untrusted inputs. Use
-/// [`ProcessingSessionBuilder::new_with_security()`] in order to have the
-/// option to enable potentially-insecure features such as shell-escape.
-#[derive(Default)]
-pub struct ProcessingSessionBuilder {
-    security: SecuritySettings,
-    primary_input: PrimaryInputMode,
-    tex_input_name: Option<String>,
-    output_dest: OutputDestination,
-    filesystem_root: Option<PathBuf>,
-    format_name: Option<String>,
-    format_cache_path: Option<PathBuf>,
+/// The code is inserted at the beginning of the first line, so that line
+/// numbers in the input are unaffected.
+struct ProloguePrimaryIo {
+    inner: Box<dyn IoProvider>,
+    prologue: Vec<u8>,
+}
+
+impl IoProvider for ProloguePrimaryIo {
+    fn input_open_primary(&mut self, status: &mut dyn StatusBackend) -> OpenResult<InputHandle> {
+        match self.input_open_primary_with_abspath(status) {
+            OpenResult::Ok((ih, _path)) => OpenResult::Ok(ih),
+            OpenResult::Err(e) => OpenResult::Err(e),
+            OpenResult::NotAvailable => OpenResult::NotAvailable,
+        }
+    }
+
+    fn input_open_primary_with_abspath(
+        &mut self,
+        status: &mut dyn StatusBackend,
+    ) -> OpenResult<(InputHandle, Option<PathBuf>)> {
+        let (mut ih, path) = match self.inner.input_open_primary_with_abspath(status) {
+            OpenResult::Ok(r) => r,
+            OpenResult::Err(e) => return OpenResult::Err(e),
+            OpenResult::NotAvailable => return OpenResult::NotAvailable,
+        };
+
+        let mut data = Vec::new();
+
+        if let Err(e) = ih.read_to_end(&mut data) {
+            return OpenResult::Err(e.into());
+        }
+
+        // Keep a byte order mark at the very start.
+        let start = if data.starts_with(b"\xef\xbb\xbf") {
+            3
+        } else {
+            0
+        };
+        data.splice(start..start, self.prologue.iter().copied());
+
+        let name = ih.name().to_owned();
+        let origin = ih.origin();
+        OpenResult::Ok((InputHandle::new(name, Cursor::new(data), origin), path))
+    }
+}
+
+/// A builder-style interface for creating a [`ProcessingSession`].
+///
+/// This uses standard builder patterns. The `Default` implementation defaults
+/// to restrictive security settings that disable all known-insecure features
+/// that could be abused by untrusted inputs. Use
+/// [`ProcessingSessionBuilder::new_with_security()`] in order to have the
+/// option to enable potentially-insecure features such as shell-escape.
+#[derive(Default)]
+pub struct ProcessingSessionBuilder {
+    security: SecuritySettings,
+    primary_input: PrimaryInputMode,
+    memory_inputs: HashMap<String, Vec<u8>>,
+    input_providers: Vec<Box<dyn IoProvider>>,
+    tex_input_name: Option<String>,
+    job_name: Option<String>,
+    output_dest: OutputDestination,
+    filesystem_root: Option<PathBuf>,
+    format_name: Option<String>,
+    format_cache_path: Option<PathBuf>,
     output_format: OutputFormat,
     makefile_output_path: Option<PathBuf>,
     hidden_input_paths: HashSet<PathBuf>,
@@ -811,16 +1556,40 @@ pub struct ProcessingSessionBuilder {
     reruns: Option<usize>,
     print_stdout: bool,
     bundle: Option<Box<dyn Bundle>>,
+    bundle_prefetch: Vec<String>,
     keep_intermediates: bool,
     keep_logs: bool,
+    keep_extensions: HashSet<String>,
     synctex: bool,
+    actual_text: bool,
+    system_fonts_disabled: bool,
+    font_features: Vec<String>,
     build_date: Option<SystemTime>,
+    reproducible: bool,
+    time_limit: Option<Duration>,
+    memory_limit: Option<u64>,
+    cancel_flag: Option<CancelFlag>,
+    tex_memory_parameters: Vec<(MemoryParameter, u32)>,
+    interaction_mode: Option<InteractionMode>,
+    pdf_metadata: PdfMetadata,
+    pdfa: bool,
+    pdf_encryption: Option<EncryptionOptions>,
+    pdf_attachments: Vec<PathBuf>,
+    image_options: ImageOptions,
+    outline_options: OutlineOptions,
+    bibtex_options: BibtexOptions,
+    tool_hooks: Vec<ToolHook>,
     unstables: UnstableOptions,
     shell_escape_mode: ShellEscapeMode,
+    shell_escape_allowlist: Option<Vec<String>>,
     html_assets_spec_path: Option<String>,
     html_precomputed_assets: Option<AssetSpecification>,
     html_do_not_emit_files: bool,
     html_do_not_emit_assets: bool,
+    html_mathml: bool,
+    html_templates: Vec<(String, PathBuf)>,
+    html_optimize_assets: bool,
+    html_split_level: Option<u8>,
 }
 
 impl ProcessingSessionBuilder {
@@ -840,6 +1609,19 @@ impl ProcessingSessionBuilder {
         self
     }
 
+    /// Sets the primary input to be the main TeX file of a ZIP archive, such
+    /// as an arXiv source archive.
+    ///
+    /// The main file is found with [`ZipIo::find_main_file`]. The other files
+    /// in the archive are available to the engines, looked up relative to the
+    /// main file's directory, and take precedence over files on disk. As with
+    /// [`Self::primary_input_path`], the directory containing the archive is
+    /// the default filesystem root and output directory.
+    pub fn primary_input_zip<P: AsRef<Path>>(&mut self, p: P) -> &mut Self {
+        self.primary_input = PrimaryInputMode::Zip(p.as_ref().to_owned());
+        self
+    }
+
     /// Sets the primary input to be a caller-specified buffer.
     ///
     /// If neither this nor a primary input path is specified, we will default
@@ -849,6 +1631,33 @@ impl ProcessingSessionBuilder {
         self
     }
 
+    /// Provides an input file held in memory, such as a generated snippet of
+    /// TeX or the contents of a `.bib` file.
+    ///
+    /// The engines can open the file as *name*, and it takes precedence over
+    /// any file of that name on disk. It isn't included in the results of
+    /// [`ProcessingSession::into_file_data`], so that, combined with
+    /// [`Self::primary_input_buffer`] and [`Self::do_not_write_output_files`],
+    /// a document can be processed entirely in memory.
+    pub fn memory_input(&mut self, name: &str, data: &[u8]) -> &mut Self {
+        self.memory_inputs
+            .insert(normalize_tex_path(name).into_owned(), data.to_owned());
+        self
+    }
+
+    /// Adds a source of input files, which the engines search after the
+    /// filesystem and before the bundle. Sources are searched in the order
+    /// that they're added.
+    ///
+    /// This is how files can come from somewhere that Tectonic doesn't know
+    /// about. For instance, a service built on an async runtime can fetch
+    /// files with its own HTTP client through a
+    /// [`tectonic_io_base::async_input::AsyncInputIo`].
+    pub fn input_provider(&mut self, p: Box<dyn IoProvider>) -> &mut Self {
+        self.input_providers.push(p);
+        self
+    }
+
     /// Sets the name of the main input file.
     ///
     /// This value will be used to infer the names of the output files; for example, if
@@ -860,6 +1669,16 @@ impl ProcessingSessionBuilder {
         self
     }
 
+    /// Sets the TeX job name, which is the value of `\jobname` and the base
+    /// name of the output files, instead of inferring it from `tex_input_name`.
+    ///
+    /// For example, if the job name is set to `"paper-draft"`, the pdf output
+    /// file will be `"paper-draft.pdf"`. The name can't contain a directory.
+    pub fn job_name(&mut self, name: &str) -> &mut Self {
+        self.job_name = Some(name.to_owned());
+        self
+    }
+
     /// Set the directory that serves as the root for finding files on disk.
     ///
     /// If unspecified, and there is a primary input file, the directory
@@ -960,7 +1779,24 @@ impl ProcessingSessionBuilder {
         self
     }
 
-    /// If set to `true`, various intermediate files will be written out to the filesystem.
+    /// Fetch these files from the bundle when the session is created, all at
+    /// once if the bundle can, rather than one by one as the engines ask for
+    /// them. Good candidates are the bundle files that a previous build of the
+    /// same document used, which can make a build with an empty bundle cache
+    /// much faster.
+    pub fn prefetch_bundle_files<I, S>(&mut self, names: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.bundle_prefetch
+            .extend(names.into_iter().map(|n| n.as_ref().to_owned()));
+        self
+    }
+
+    /// If set to `true`, various intermediate files will be written out to the
+    /// filesystem. These include the XDV (or SPX) file produced by the TeX
+    /// engine, which can then be handed to other post-processors.
     pub fn keep_intermediates(&mut self, k: bool) -> &mut Self {
         self.keep_intermediates = k;
         self
@@ -972,12 +1808,110 @@ impl ProcessingSessionBuilder {
         self
     }
 
+    /// Write out the intermediate and log files whose names end with one of
+    /// these extensions, such as `"aux"` or `"synctex.gz"`, to the
+    /// filesystem. This is a more selective alternative to
+    /// [`Self::keep_intermediates`] and [`Self::keep_logs`]. Use `"xdv"` to
+    /// keep the engine's XDV output alongside the PDF.
+    pub fn keep_extensions<I, S>(&mut self, exts: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.keep_extensions.extend(
+            exts.into_iter()
+                .map(|e| e.as_ref().trim_start_matches('.').to_owned()),
+        );
+        self
+    }
+
     /// If set to `true`, tex files will be compiled using synctex information.
     pub fn synctex(&mut self, s: bool) -> &mut Self {
         self.synctex = s;
         self
     }
 
+    /// If set to `true`, the TeX engine will record the Unicode text of
+    /// native-font output in the XDV file, so that it can be recovered with
+    /// [`tectonic_xdv::extract_text`].
+    pub fn actual_text(&mut self, a: bool) -> &mut Self {
+        self.actual_text = a;
+        self
+    }
+
+    /// Set whether the TeX engine may load fonts installed on the host system
+    /// by name. The default is true. See `TexEngine::system_fonts`.
+    pub fn system_fonts(&mut self, enabled: bool) -> &mut Self {
+        self.system_fonts_disabled = !enabled;
+        self
+    }
+
+    /// Sets OpenType features to apply to the document's fonts, such as
+    /// `+smcp` or `language=DEU`. See `TexEngine::font_features`.
+    pub fn font_features<I, S>(&mut self, features: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.font_features = features.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the metadata to record in PDF outputs.
+    pub fn pdf_metadata(&mut self, m: PdfMetadata) -> &mut Self {
+        self.pdf_metadata = m;
+        self
+    }
+
+    /// If set to `true`, PDF outputs will conform to the PDF/A-2b archival
+    /// standard. See `XdvipdfmxEngine::pdfa`.
+    pub fn pdfa(&mut self, enabled: bool) -> &mut Self {
+        self.pdfa = enabled;
+        self
+    }
+
+    /// Sets how PDF outputs will be encrypted. If `None`, the default, they
+    /// aren't encrypted unless the document asks for that itself.
+    pub fn pdf_encryption(&mut self, e: Option<EncryptionOptions>) -> &mut Self {
+        self.pdf_encryption = e;
+        self
+    }
+
+    /// Attach the file at `path` to PDF outputs, under its file name. See
+    /// `XdvipdfmxEngine::attachment`.
+    pub fn pdf_attachment(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        self.pdf_attachments.push(path.into());
+        self
+    }
+
+    /// Sets the options for the raster images embedded in PDF outputs.
+    pub fn image_options(&mut self, o: ImageOptions) -> &mut Self {
+        self.image_options = o;
+        self
+    }
+
+    /// Sets the options for the outline (bookmarks) of PDF outputs.
+    pub fn outline_options(&mut self, o: OutlineOptions) -> &mut Self {
+        self.outline_options = o;
+        self
+    }
+
+    /// Sets the options for running BibTeX.
+    pub fn bibtex_options(&mut self, o: BibtexOptions) -> &mut Self {
+        self.bibtex_options = o;
+        self
+    }
+
+    /// Adds an external program to run as part of the processing. Hooks for
+    /// the same stage are run in the order in which they're added.
+    ///
+    /// Creating the session will fail if the security settings don't allow
+    /// shell-escape.
+    pub fn tool_hook(&mut self, hook: ToolHook) -> &mut Self {
+        self.tool_hooks.push(hook);
+        self
+    }
+
     /// Sets the date and time of the processing session.
     /// See `TexEngine::build_date` for mor information.
     pub fn build_date(&mut self, date: SystemTime) -> &mut Self {
@@ -989,8 +1923,12 @@ impl ProcessingSessionBuilder {
     /// If `SOURCE_DATE_EPOCH` is set, it's used as the build date.
     /// If `force_deterministic` is set, we fall back to UNIX_EPOCH.
     /// Otherwise, we use the current system time.
+    ///
+    /// In the first two cases, the date is pinned as with
+    /// [`Self::pinned_build_date`].
     pub fn build_date_from_env(&mut self, force_deterministic: bool) -> &mut Self {
         let build_date_str = std::env::var("SOURCE_DATE_EPOCH").ok();
+        let reproducible = force_deterministic || build_date_str.is_some();
         let build_date = match (force_deterministic, build_date_str) {
             (_, Some(s)) => {
                 let epoch = s
@@ -1004,7 +1942,26 @@ impl ProcessingSessionBuilder {
             (true, None) => SystemTime::UNIX_EPOCH,
             (false, None) => SystemTime::now(),
         };
-        self.build_date(build_date)
+
+        if reproducible {
+            self.pinned_build_date(build_date)
+        } else {
+            self.build_date(build_date)
+        }
+    }
+
+    /// Pins the date and time of the processing session to `date`, for
+    /// callers that want reproducible outputs but don't get the date from
+    /// `SOURCE_DATE_EPOCH`.
+    ///
+    /// The session then makes sure that its outputs depend only on its inputs
+    /// and the build date: the TeX engine's clock is pinned as described in
+    /// `TexEngine::pinned_build_date`, the modification times of input files
+    /// are reported as the build date, and the tags of font subsets in PDF
+    /// outputs are numbered instead of being random.
+    pub fn pinned_build_date(&mut self, date: SystemTime) -> &mut Self {
+        self.reproducible = true;
+        self.build_date(date)
     }
 
     /// Loads unstable options into the processing session
@@ -1046,6 +2003,62 @@ impl ProcessingSessionBuilder {
         self
     }
 
+    /// Only allow shell-escape commands that run one of the named programs.
+    /// This doesn't activate shell-escape by itself. Allowed commands are run
+    /// without a shell, in the shell-escape working directory, with most of
+    /// the environment withheld; their arguments can't be paths leading out
+    /// of the working directory. Any other command fails. The default is to
+    /// run shell-escape commands with the system shell, unrestricted.
+    pub fn shell_escape_allowlist<I: IntoIterator<Item = String>>(
+        &mut self,
+        programs: I,
+    ) -> &mut Self {
+        self.shell_escape_allowlist = Some(programs.into_iter().collect());
+        self
+    }
+
+    /// Limit how long each engine pass may run. A pass that runs for longer
+    /// is aborted, failing the session with an error.
+    pub fn time_limit(&mut self, limit: Duration) -> &mut Self {
+        self.time_limit = Some(limit);
+        self
+    }
+
+    /// Limit the resident memory of the process, in bytes, during each engine
+    /// pass. A pass during which it grows beyond the limit is aborted, failing
+    /// the session with an error. The limit isn't enforced on platforms where
+    /// the memory usage can't be determined, such as Windows.
+    pub fn memory_limit(&mut self, bytes: u64) -> &mut Self {
+        self.memory_limit = Some(bytes);
+        self
+    }
+
+    /// Watch a flag that cancels the processing when it's raised, such as
+    /// from a Ctrl-C handler. The running engine stops at its next
+    /// opportunity, no output files are written, not even the logs, and the
+    /// session fails with [`ErrorKind::Cancelled`].
+    pub fn cancel_flag(&mut self, flag: CancelFlag) -> &mut Self {
+        self.cancel_flag = Some(flag);
+        self
+    }
+
+    /// Raise the size of one of the TeX engine’s internal tables, for
+    /// documents that overflow it. See `TexEngine::memory_parameter` for
+    /// details.
+    pub fn tex_memory_parameter(&mut self, param: MemoryParameter, value: u32) -> &mut Self {
+        self.tex_memory_parameters.push((param, value));
+        self
+    }
+
+    /// Set the interaction mode in which the TeX engine starts, which decides
+    /// whether it halts at the first error. This overrides the
+    /// `continue-on-errors` unstable option. See `TexEngine::interaction_mode`
+    /// for details.
+    pub fn interaction_mode(&mut self, mode: InteractionMode) -> &mut Self {
+        self.interaction_mode = Some(mode);
+        self
+    }
+
     /// When using HTML mode, emit an asset specification file instead of actual
     /// asset files.
     ///
@@ -1107,6 +2120,39 @@ impl ProcessingSessionBuilder {
         self
     }
 
+    /// Set whether HTML outputs should express math as MathML. See
+    /// `Spx2HtmlEngine::mathml`.
+    pub fn html_mathml(&mut self, enabled: bool) -> &mut Self {
+        self.html_mathml = enabled;
+        self
+    }
+
+    /// Use the Tera template in the file `path` for HTML outputs, in place of
+    /// any template named `name` that the document provides. See
+    /// `Spx2HtmlEngine::template`.
+    pub fn html_template(
+        &mut self,
+        name: impl Into<String>,
+        path: impl Into<PathBuf>,
+    ) -> &mut Self {
+        self.html_templates.push((name.into(), path.into()));
+        self
+    }
+
+    /// Set whether HTML assets should be optimized for publication. See
+    /// `Spx2HtmlEngine::optimize_assets`.
+    pub fn html_optimize_assets(&mut self, enabled: bool) -> &mut Self {
+        self.html_optimize_assets = enabled;
+        self
+    }
+
+    /// Set the heading level at which HTML output is split into several pages,
+    /// if any. See `Spx2HtmlEngine::split_level`.
+    pub fn html_split_level(&mut self, level: Option<u8>) -> &mut Self {
+        self.html_split_level = level;
+        self
+    }
+
     /// Creates a `ProcessingSession`.
     pub fn create(self, status: &mut dyn StatusBackend) -> Result<ProcessingSession> {
         // First, work on the "bridge state", which gathers the subset of our
@@ -1115,7 +2161,25 @@ impl ProcessingSessionBuilder {
 
         let mut bundle = self.bundle.expect("a bundle must be specified");
 
+        let mut bibtex_options = self.bibtex_options;
+
+        if !bibtex_options.search_paths.is_empty() && !self.security.allow_extra_search_paths() {
+            tt_warning!(status, "BibTeX search path(s) ignored due to security");
+            bibtex_options.search_paths.clear();
+        }
+
+        if !self.tool_hooks.is_empty() && !self.security.allow_shell_escape() {
+            return Err(errmsg!(
+                "the document runs external tools, but this is forbidden by the security settings"
+            ));
+        }
+
+        if self.tool_hooks.iter().any(|h| h.command.is_empty()) {
+            return Err(errmsg!("external tool hooks must name a program to run"));
+        }
+
         let mut filesystem_root = self.filesystem_root.unwrap_or_default();
+        let mut archive = None;
 
         let (pio, primary_input_path, default_output_path) = match self.primary_input {
             PrimaryInputMode::Path(p) => {
@@ -1137,6 +2201,37 @@ impl ProcessingSessionBuilder {
                 (pio, Some(p), parent)
             }
 
+            PrimaryInputMode::Zip(p) => {
+                // Like a path, except that the primary input is read out of
+                // the archive, which then serves up the rest of its files.
+                let parent = match p.parent() {
+                    Some(parent) => parent.to_owned(),
+                    None => {
+                        return Err(errmsg!(
+                            "can't figure out a parent directory for input path \"{}\"",
+                            p.display()
+                        ));
+                    }
+                };
+
+                let mut zio = ctry!(
+                    ZipIo::open(&p);
+                    "failed to open the ZIP archive \"{}\"", p.display()
+                );
+                let main = ctry!(
+                    zio.find_main_file();
+                    "failed to find the main file of \"{}\"", p.display()
+                );
+                let text = ctry!(zio.read_file(&main); "failed to read \"{}\"", main);
+                zio.set_root(main.rsplit_once('/').map_or("", |(dir, _)| dir));
+                tt_note!(status, "using \"{}\" as the main file of the archive", main);
+
+                filesystem_root.clone_from(&parent);
+                archive = Some(zio);
+                let pio: Box<dyn IoProvider> = Box::new(BufferedPrimaryIo::from_buffer(text));
+                (pio, Some(p), parent)
+            }
+
             PrimaryInputMode::Stdin => {
                 // If the main input file is stdin, we don't set a filesystem
                 // root, which means we'll default to the current working
@@ -1162,6 +2257,12 @@ impl ProcessingSessionBuilder {
             .unwrap_or_else(|| filesystem_root.clone());
         let format_cache = FormatCache::new(bundle.get_digest()?, format_cache_path);
 
+        if !self.bundle_prefetch.is_empty() {
+            if let Err(e) = bundle.prefetch(&self.bundle_prefetch, status) {
+                tt_warning!(status, "couldn't prefetch files from the bundle"; e);
+            }
+        }
+
         let genuine_stdout = if self.print_stdout {
             Some(GenuineStdoutIo::new())
         } else {
@@ -1188,12 +2289,34 @@ impl ProcessingSessionBuilder {
 
         let mem = MemoryIo::new(true);
 
+        let mut memory_inputs = MemoryIo::new(false);
+
+        for (name, data) in self.memory_inputs {
+            memory_inputs.create_entry(&name, data);
+        }
+
+        let pio: Box<dyn IoProvider> = if let Some(depth) = self.outline_options.depth {
+            let text = include_str!("tectonic-outline.tex").replace("@DEPTH@", &depth.to_string());
+            memory_inputs.create_entry(OUTLINE_TEX_NAME, text.into_bytes());
+
+            Box::new(ProloguePrimaryIo {
+                inner: pio,
+                prologue: format!("\\input {OUTLINE_TEX_NAME} ").into_bytes(),
+            })
+        } else {
+            pio
+        };
+
         let bs = BridgeState {
             primary_input: pio,
             mem,
+            memory_inputs,
+            archive,
             filesystem,
             extra_search_paths,
+            input_providers: self.input_providers,
             shell_escape_work: None,
+            shell_escape_allowlist: self.shell_escape_allowlist,
             format_cache,
             bundle,
             genuine_stdout,
@@ -1212,8 +2335,17 @@ impl ProcessingSessionBuilder {
         let tex_input_name = self
             .tex_input_name
             .expect("tex_input_name must be specified");
-        let mut aux_path = PathBuf::from(tex_input_name.clone());
-        aux_path.set_extension("aux");
+        let aux_path = match &self.job_name {
+            Some(name) => {
+                if name.is_empty() || name.contains(['/', '\\']) {
+                    return Err(errmsg!("invalid job name \"{}\"", name));
+                }
+
+                PathBuf::from(format!("{name}.aux"))
+            }
+
+            None => PathBuf::from(&tex_input_name).with_extension("aux"),
+        };
         let mut xdv_path = aux_path.clone();
         xdv_path.set_extension(if self.output_format == OutputFormat::Html {
             "spx"
@@ -1247,6 +2379,7 @@ impl ProcessingSessionBuilder {
             pass: self.pass,
             primary_input_path,
             primary_input_tex_path: tex_input_name,
+            job_name: self.job_name,
             format_name: self.format_name.unwrap(),
             tex_aux_path: aux_path.display().to_string(),
             tex_xdv_path: xdv_path.display().to_string(),
@@ -1257,14 +2390,36 @@ impl ProcessingSessionBuilder {
             tex_rerun_specification: self.reruns,
             keep_intermediates: self.keep_intermediates,
             keep_logs: self.keep_logs,
+            keep_extensions: self.keep_extensions,
             synctex_enabled: self.synctex,
+            actual_text_enabled: self.actual_text,
+            system_fonts_enabled: !self.system_fonts_disabled,
+            font_features: self.font_features,
             build_date: self.build_date.unwrap_or(SystemTime::UNIX_EPOCH),
+            reproducible: self.reproducible,
+            time_limit: self.time_limit,
+            memory_limit: self.memory_limit,
+            cancel_flag: self.cancel_flag,
+            tex_memory_parameters: self.tex_memory_parameters,
+            interaction_mode: self.interaction_mode,
+            pdf_metadata: self.pdf_metadata,
+            pdfa: self.pdfa,
+            pdf_encryption: self.pdf_encryption,
+            pdf_attachments: self.pdf_attachments,
+            image_options: self.image_options,
+            outline_options: self.outline_options,
+            bibtex_options,
+            tool_hooks: self.tool_hooks,
             unstables: self.unstables,
             shell_escape_mode,
             html_assets_spec_path: self.html_assets_spec_path,
             html_precomputed_assets: self.html_precomputed_assets,
             html_emit_files: !self.html_do_not_emit_files,
             html_emit_assets: !self.html_do_not_emit_assets,
+            html_mathml: self.html_mathml,
+            html_templates: self.html_templates,
+            html_optimize_assets: self.html_optimize_assets,
+            html_split_level: self.html_split_level,
         })
     }
 }
@@ -1272,8 +2427,116 @@ impl ProcessingSessionBuilder {
 #[derive(Debug, Clone)]
 enum RerunReason {
     Biber,
+    Hook(String),
     Bibtex,
-    FileChange(String),
+    Makeindex,
+    Glossaries,
+    FileChange(Vec<String>),
+}
+
+/// The digests of the contents of a set of files, by name.
+type AuxFileState = BTreeMap<String, DigestData>;
+
+/// The warnings issued during a series of TeX passes. Reruns tend to repeat
+/// the same warnings (underfull boxes, undefined citations, ...) every time,
+/// so we collect them here and report each one only once, at the end.
+#[derive(Debug, Default)]
+struct WarningSummary {
+    /// The number of passes so far.
+    passes: usize,
+
+    /// The distinct warnings in the order that they were first issued, with
+    /// the number of passes that issued them and the last pass that did.
+    warnings: Vec<(String, usize, usize)>,
+
+    /// Where each warning is in `warnings`.
+    index: HashMap<String, usize>,
+}
+
+impl WarningSummary {
+    fn start_pass(&mut self) {
+        self.passes += 1;
+    }
+
+    fn add(&mut self, message: String) {
+        if let Some(&i) = self.index.get(&message) {
+            let (_, count, last) = &mut self.warnings[i];
+
+            if *last != self.passes {
+                *count += 1;
+                *last = self.passes;
+            }
+        } else {
+            self.index.insert(message.clone(), self.warnings.len());
+            self.warnings.push((message, 1, self.passes));
+        }
+    }
+
+    /// Report the warnings collected so far, and forget about them.
+    fn report(&mut self, status: &mut dyn StatusBackend) {
+        let passes = self.passes;
+        self.index.clear();
+
+        for (message, count, _) in self.warnings.drain(..) {
+            if passes > 1 {
+                tt_warning!(
+                    status,
+                    "{} (in {} of {} TeX passes)",
+                    message,
+                    count,
+                    passes
+                );
+            } else {
+                tt_warning!(status, "{}", message);
+            }
+        }
+    }
+}
+
+/// A status backend that holds back the warnings of a TeX pass for a
+/// [`WarningSummary`], and passes everything else on.
+struct WarningCollector<'a> {
+    inner: &'a mut dyn StatusBackend,
+    summary: &'a mut WarningSummary,
+}
+
+impl StatusBackend for WarningCollector<'_> {
+    fn report(
+        &mut self,
+        kind: MessageKind,
+        args: std::fmt::Arguments,
+        err: Option<&tectonic_errors::Error>,
+    ) {
+        if kind == MessageKind::Warning && err.is_none() {
+            self.summary.add(args.to_string());
+        } else {
+            self.inner.report(kind, args, err);
+        }
+    }
+
+    fn report_error(&mut self, err: &tectonic_errors::Error) {
+        self.inner.report_error(err);
+    }
+
+    fn note_highlighted(&mut self, before: &str, highlighted: &str, after: &str) {
+        self.inner.note_highlighted(before, highlighted, after);
+    }
+
+    fn report_diagnostic(&mut self, diag: &SourceDiagnostic) {
+        self.inner.report_diagnostic(diag);
+    }
+
+    fn report_progress(&mut self, progress: &Progress) {
+        self.inner.report_progress(progress);
+    }
+
+    fn finish_progress(&mut self) {
+        self.inner.finish_progress();
+    }
+
+    fn dump_error_logs(&mut self, output: &[u8]) {
+        self.inner.dump_error_logs(output);
+    }
 }
 
 /// The ProcessingSession struct runs the whole show when we're actually
@@ -1295,6 +2558,9 @@ pub struct ProcessingSession {
     /// if the latter is None. (Name, "texput.tex").
     primary_input_tex_path: String,
 
+    /// The TeX job name, if it isn't inferred from `primary_input_tex_path`.
+    job_name: Option<String>,
+
     /// This is the name of the format file to use. TeX has to open it by name
     /// internally, so it has to be String compatible.
     format_name: String,
@@ -1321,11 +2587,56 @@ pub struct ProcessingSession {
     tex_rerun_specification: Option<usize>,
     keep_intermediates: bool,
     keep_logs: bool,
+    keep_extensions: HashSet<String>,
     synctex_enabled: bool,
+    actual_text_enabled: bool,
+    system_fonts_enabled: bool,
+    font_features: Vec<String>,
 
     /// See `TexEngine::with_date` and `XdvipdfmxEngine::with_date`.
     build_date: SystemTime,
 
+    /// Whether the outputs should depend only on the inputs and the build
+    /// date. See [`ProcessingSessionBuilder::build_date_from_env`].
+    reproducible: bool,
+
+    /// How long each engine pass may run.
+    time_limit: Option<Duration>,
+
+    /// How much resident memory the process may use during each engine pass,
+    /// in bytes.
+    memory_limit: Option<u64>,
+
+    /// The flag that cancels the processing, if there is one.
+    cancel_flag: Option<CancelFlag>,
+
+    /// Sizes of the TeX engine's internal tables that differ from the
+    /// defaults.
+    tex_memory_parameters: Vec<(MemoryParameter, u32)>,
+
+    /// The interaction mode in which the TeX engine starts, if set.
+    interaction_mode: Option<InteractionMode>,
+
+    pdf_metadata: PdfMetadata,
+
+    /// Whether PDF outputs should conform to PDF/A-2b.
+    pdfa: bool,
+
+    /// How PDF outputs should be encrypted, if at all.
+    pdf_encryption: Option<EncryptionOptions>,
+
+    /// Files to attach to PDF outputs.
+    pdf_attachments: Vec<PathBuf>,
+
+    image_options: ImageOptions,
+
+    outline_options: OutlineOptions,
+
+    bibtex_options: BibtexOptions,
+
+    /// External programs to run along with the engines.
+    tool_hooks: Vec<ToolHook>,
+
     unstables: UnstableOptions,
 
     /// How to handle shell-escape. The `Defaulted` option will never
@@ -1336,45 +2647,80 @@ pub struct ProcessingSession {
     html_precomputed_assets: Option<AssetSpecification>,
     html_emit_files: bool,
     html_emit_assets: bool,
+    html_mathml: bool,
+    html_templates: Vec<(String, PathBuf)>,
+    html_optimize_assets: bool,
+    html_split_level: Option<u8>,
 }
 
 const DEFAULT_MAX_TEX_PASSES: usize = 6;
 const ALWAYS_INTERMEDIATE_EXTENSIONS: &[&str] = &[
-    ".snm", ".toc", // generated by Beamer
+    ".snm",
+    ".toc", // generated by Beamer
+    TRACE_EXTENSION,
 ];
 
+/// The extension of the file that the trace in the TeX log is saved to.
+const TRACE_EXTENSION: &str = ".trace.jsonl";
+
 impl ProcessingSession {
-    /// Assess whether we need to rerun an engine. This is the case if there
-    /// was a file that the engine read and then rewrote, and the rewritten
-    /// version is different than the version that it read in.
-    fn is_rerun_needed(&self, status: &mut dyn StatusBackend) -> Option<RerunReason> {
-        // TODO: we should probably wire up diagnostics since I expect this
-        // stuff could get finicky and we're going to want to be able to
-        // figure out why rerun detection is breaking.
+    /// Get the state of the files generated during processing that the TeX
+    /// engine read during its last pass: their digests as they were when it
+    /// read them, and as they are now.
+    fn aux_file_states(&self) -> (AuxFileState, AuxFileState) {
+        let files = self.bs.mem.files.borrow();
+        let mut read = AuxFileState::new();
+        let mut current = AuxFileState::new();
 
         for (name, info) in &self.bs.events {
-            if info.access_pattern == AccessPattern::ReadThenWritten {
-                let file_changed = match (&info.read_digest, &info.write_digest) {
-                    (Some(d1), Some(d2)) => d1 != d2,
-                    (&None, &Some(_)) => true,
-                    (_, _) => {
-                        // Other cases shouldn't happen.
-                        tt_warning!(
-                            status,
-                            "internal consistency problem when checking if {} changed",
-                            name
-                        );
-                        true
-                    }
-                };
-
-                if file_changed {
-                    return Some(RerunReason::FileChange(name.clone()));
-                }
+            if let (Some(digest), Some(file)) = (info.read_digest, files.get(name)) {
+                let mut dc = digest::create();
+                dc.update(&file.data);
+                read.insert(name.clone(), digest);
+                current.insert(name.clone(), dc.into());
             }
         }
 
-        None
+        (read, current)
+    }
+
+    /// Assess whether we need to rerun the TeX engine. This is the case if any
+    /// of the generated files that it read during its last pass has changed
+    /// since, whether the engine itself rewrote it or another tool did. If none
+    /// has, the files have reached a fixed point, and another pass would give
+    /// the same results.
+    ///
+    /// The states of the files read by each pass are accumulated in *seen*. If
+    /// the files return to the state read by an earlier pass, they will cycle
+    /// forever, so that there is no point in rerunning.
+    fn is_rerun_needed(
+        &self,
+        seen: &mut Vec<AuxFileState>,
+        status: &mut dyn StatusBackend,
+    ) -> Option<RerunReason> {
+        let (read, current) = self.aux_file_states();
+        let changed: Vec<String> = current
+            .iter()
+            .filter(|(name, digest)| read.get(*name) != Some(digest))
+            .map(|(name, _)| name.clone())
+            .collect();
+
+        seen.push(read);
+
+        if changed.is_empty() {
+            return None;
+        }
+
+        if seen.contains(&current) {
+            tt_warning!(
+                status,
+                "TeX rerun seems needed, but the contents of {} are cycling; stopping",
+                describe_file_list(&changed)
+            );
+            return None;
+        }
+
+        Some(RerunReason::FileChange(changed))
     }
 
     #[allow(dead_code)]
@@ -1408,11 +2754,66 @@ impl ProcessingSession {
     ///
     /// - if a `.fmt` file does not yet exist, generate one and cache it
     /// - run the TeX engine once
-    /// - run BibTeX, if it seems to be required
+    /// - run BibTeX, if it seems to be required, or if the citations in the
+    ///   `.aux` file have changed since it last ran
     /// - repeat the last two steps as often as needed
     /// - write the output files to disk, including a Makefile if it was requested.
+    ///
+    /// To run the steps yourself, see [`Self::run_format_pass`] and its
+    /// siblings.
     pub fn run(&mut self, status: &mut dyn StatusBackend) -> Result<()> {
-        // Pre-invocation setup that requires cleanup even if the processing errors out.
+        self.with_shell_escape_work(status, Self::run_inner)
+    }
+
+    /// Generate the format file and cache it, if it isn't available yet.
+    /// Returns whether a format file was generated.
+    ///
+    /// This and the other `run_*_pass` methods run a single step of the
+    /// processing, so that programs embedding Tectonic can string them together
+    /// as they see fit, rather than using [`Self::run`]. They work on the files
+    /// in memory, and don't write anything to disk; use
+    /// [`Self::get_file_data`] or [`Self::into_file_data`] to get at the
+    /// results.
+    pub fn run_format_pass(&mut self, status: &mut dyn StatusBackend) -> Result<bool> {
+        match self.bs.input_open_format(&self.format_name, status) {
+            OpenResult::Ok(_) => Ok(false),
+            OpenResult::NotAvailable => {
+                tt_note!(status, "generating format \"{}\"", self.format_name);
+                self.make_format_pass(status)?;
+                Ok(true)
+            }
+            OpenResult::Err(e) => {
+                Err(e).chain_err(|| format!("could not open format file {}", self.format_name))
+            }
+        }
+    }
+
+    /// Run the TeX engine once. The format file must be available already,
+    /// which [`Self::run_format_pass`] ensures.
+    pub fn run_tex_pass(&mut self, status: &mut dyn StatusBackend) -> Result<()> {
+        self.with_shell_escape_work(status, |s, status| s.single_tex_pass(status).map(|_| ()))
+    }
+
+    /// Run BibTeX on the `.aux` files written by the TeX engine.
+    pub fn run_bibtex_pass(&mut self, status: &mut dyn StatusBackend) -> Result<()> {
+        self.bibtex_pass(status)?;
+        Ok(())
+    }
+
+    /// Convert the XDV file written by the TeX engine to PDF with xdvipdfmx.
+    pub fn run_xdvipdfmx_pass(&mut self, status: &mut dyn StatusBackend) -> Result<()> {
+        self.xdvipdfmx_pass(status)?;
+        Ok(())
+    }
+
+    /// Run `f` with the working directory for shell-escape commands set up,
+    /// cleaning it up afterwards whether or not `f` succeeds.
+    fn with_shell_escape_work<T>(
+        &mut self,
+        status: &mut dyn StatusBackend,
+        f: impl FnOnce(&mut Self, &mut dyn StatusBackend) -> Result<T>,
+    ) -> Result<T> {
+        // Pre-invocation setup that requires cleanup even if the processing errors out.
 
         let (shell_escape_work, clean_up_shell_escape) = match self.shell_escape_mode {
             ShellEscapeMode::Disabled => (None, false),
@@ -1441,7 +2842,7 @@ impl ProcessingSession {
         self.bs.shell_escape_work = shell_escape_work;
 
         // Go-time!
-        let result = self.run_inner(status);
+        let result = f(self, status);
 
         // Do that cleanup.
 
@@ -1455,54 +2856,74 @@ impl ProcessingSession {
             }
         }
 
-        // Propagate the actual result.
+        // Propagate the actual result, reporting a failure that was caused
+        // by a cancellation as such.
+
+        if result.is_err() && self.is_cancelled() {
+            return Err(ErrorKind::Cancelled.into());
+        }
+
         result
     }
 
+    fn is_cancelled(&self) -> bool {
+        self.cancel_flag.as_ref().is_some_and(|f| f.is_cancelled())
+    }
+
+    /// Fail if the processing has been cancelled, for use between steps that
+    /// aren't run through a `CoreBridgeLauncher`.
+    fn check_cancelled(&self) -> Result<()> {
+        if self.is_cancelled() {
+            return Err(ErrorKind::Cancelled.into());
+        }
+
+        Ok(())
+    }
+
     /// The bulk of the `run` implementation. We need to wrap it to manage the
     /// lifecycle of resources like the shell-escape temporary directory, if
     /// needed.
     fn run_inner(&mut self, status: &mut dyn StatusBackend) -> Result<()> {
         // Do we need to generate the format file?
 
-        let generate_format = if self.output_format == OutputFormat::Format {
-            false
-        } else {
-            match self.bs.input_open_format(&self.format_name, status) {
-                OpenResult::Ok(_) => false,
-                OpenResult::NotAvailable => true,
-                OpenResult::Err(e) => {
-                    return Err(e)
-                        .chain_err(|| format!("could not open format file {}", self.format_name));
-                }
-            }
-        };
+        if self.output_format != OutputFormat::Format && self.pass != PassSetting::Bibtex {
+            self.run_format_pass(status)?;
+        }
 
-        if generate_format {
-            tt_note!(status, "generating format \"{}\"", self.format_name);
-            self.make_format_pass(status)?;
+        if self.output_format != OutputFormat::Format {
+            self.hook_pass(HookStage::Pre, status)?;
+
+            // This comes after the hooks, in case they generate the input.
+            if self.unstables.precompiled_preamble && self.pass != PassSetting::Bibtex {
+                self.preamble_format_pass(status)?;
+            }
         }
 
         // Do the meat of the work.
 
         let result = match self.pass {
-            PassSetting::Tex => match self.tex_pass(None, status) {
-                Ok(Some(warnings)) => {
-                    tt_warning!(status, "{}", warnings);
-                    Ok(0)
-                }
-                Ok(None) => Ok(0),
-                Err(e) => Err(e),
-            },
+            PassSetting::Tex => self.single_tex_pass(status),
             PassSetting::Default => self.default_pass(false, status),
             PassSetting::BibtexFirst => self.default_pass(true, status),
+            PassSetting::Bibtex => self.bibtex_pass(status),
         };
 
         if let Err(e) = result {
-            self.write_files(None, status, true)?;
+            // The logs of a cancelled run would be cut off, so they're not
+            // worth keeping.
+            if !self.is_cancelled() {
+                self.write_files(None, status, true)?;
+            }
+
             return Err(e);
         };
 
+        // Nothing is written to disk until all of the engines have finished,
+        // so if the processing is cancelled now, no partial outputs are left
+        // behind.
+
+        self.check_cancelled()?;
+
         // Write output files and the first line of our Makefile output.
 
         let mut mf_dest_maybe = match self.makefile_output_path {
@@ -1514,6 +2935,7 @@ impl ProcessingSession {
                     );
                     None
                 } else {
+                    check_write_access(&self.security, p)?;
                     Some(File::create(p)?)
                 }
             }
@@ -1609,6 +3031,7 @@ impl ProcessingSession {
                     continue;
                 }
             } else if !self.keep_intermediates
+                && !is_kept(&self.keep_extensions, sname)
                 && (summ.access_pattern != AccessPattern::Written
                     || ALWAYS_INTERMEDIATE_EXTENSIONS
                         .iter()
@@ -1620,11 +3043,13 @@ impl ProcessingSession {
 
             let is_logfile = sname.ends_with(".log") || sname.ends_with(".blg");
 
-            if is_logfile && !self.keep_logs {
+            if is_logfile && !self.keep_logs && !is_kept(&self.keep_extensions, sname) {
                 continue;
             }
 
-            if !is_logfile && only_logs {
+            // The trace is kept like an intermediate, but it's most useful
+            // when the processing failed.
+            if !is_logfile && only_logs && !sname.ends_with(TRACE_EXTENSION) {
                 continue;
             }
 
@@ -1638,6 +3063,9 @@ impl ProcessingSession {
             }
 
             let real_path = root.join(name);
+
+            check_write_access(&self.security, &real_path)?;
+
             let byte_len = Byte::from_u128(file.data.len() as u128).unwrap();
             status.note_highlighted(
                 "Writing ",
@@ -1671,27 +3099,57 @@ impl ProcessingSession {
     /// The "default" pass really runs a bunch of sub-passes. It is a "Do What
     /// I Mean" operation.
     fn default_pass(&mut self, bibtex_first: bool, status: &mut dyn StatusBackend) -> Result<i32> {
+        // The warnings of the TeX passes are normally reported once the last
+        // one is done, but if something fails along the way, they might help
+        // to explain why.
+        let mut summary = WarningSummary::default();
+        let result = self.default_passes(bibtex_first, &mut summary, status);
+        summary.report(status);
+        result
+    }
+
+    fn default_passes(
+        &mut self,
+        bibtex_first: bool,
+        summary: &mut WarningSummary,
+        status: &mut dyn StatusBackend,
+    ) -> Result<i32> {
         // If `bibtex_first` is true, we start by running bibtex, and run
         // proceed with the standard rerun logic. Otherwise, we run TeX,
         // auto-detect whether we need to run bibtex, possibly run it, and
         // then go ahead.
 
         let mut warnings = None;
+        let mut bibtex_aux = None;
+        let mut last_idx = None;
+        let mut last_glossaries = None;
+        let mut aux_states = Vec::new();
         let mut rerun_result = if bibtex_first {
             self.bibtex_pass(status)?;
+            bibtex_aux = Some(self.bibtex_aux_lines());
             Some(RerunReason::Bibtex)
         } else {
-            warnings = self.tex_pass(None, status)?;
+            warnings = self.summarized_tex_pass(None, summary, status)?;
             let maybe_biber = self.check_biber_requirement(status)?;
+            let indexed = self.makeindex_pass(&mut last_idx, status)?;
+            let sorted = self.glossaries_pass(&mut last_glossaries, status)?;
+            let hooked = self.hook_pass(HookStage::Post, status)?;
 
             if let Some(biber) = maybe_biber {
                 self.bs.external_tool_pass(&biber, status)?;
                 Some(RerunReason::Biber)
             } else if self.is_bibtex_needed() {
                 self.bibtex_pass(status)?;
+                bibtex_aux = Some(self.bibtex_aux_lines());
                 Some(RerunReason::Bibtex)
+            } else if indexed {
+                Some(RerunReason::Makeindex)
+            } else if sorted {
+                Some(RerunReason::Glossaries)
+            } else if let Some(program) = hooked {
+                Some(RerunReason::Hook(program))
             } else {
-                self.is_rerun_needed(status)
+                self.is_rerun_needed(&mut aux_states, status)
             }
         };
 
@@ -1708,8 +3166,13 @@ impl ProcessingSession {
             } else {
                 match rerun_result {
                     Some(RerunReason::Biber) => "biber was run".to_owned(),
+                    Some(RerunReason::Hook(ref s)) => format!("{s} was run"),
                     Some(RerunReason::Bibtex) => "bibtex was run".to_owned(),
-                    Some(RerunReason::FileChange(ref s)) => format!("\"{s}\" changed"),
+                    Some(RerunReason::Makeindex) => "makeindex was run".to_owned(),
+                    Some(RerunReason::Glossaries) => "the glossaries were sorted".to_owned(),
+                    Some(RerunReason::FileChange(ref names)) => {
+                        format!("{} changed", describe_file_list(names))
+                    }
                     None => break,
                 }
             };
@@ -1724,10 +3187,33 @@ impl ProcessingSession {
                 summ.read_digest = None;
             }
 
-            warnings = self.tex_pass(Some(&rerun_explanation), status)?;
+            warnings = self.summarized_tex_pass(Some(&rerun_explanation), summary, status)?;
 
             if !reruns_fixed {
-                rerun_result = self.is_rerun_needed(status);
+                rerun_result = self.is_rerun_needed(&mut aux_states, status);
+
+                // If the citations have changed since BibTeX last ran (say,
+                // because they depend on something that the previous pass
+                // worked out), its output is stale.
+                if let Some(last_lines) = &bibtex_aux {
+                    let lines = self.bibtex_aux_lines();
+
+                    if &lines != last_lines {
+                        self.bibtex_pass(status)?;
+                        bibtex_aux = Some(lines);
+                        rerun_result = Some(RerunReason::Bibtex);
+                    }
+                }
+
+                // Likewise if the index entries have changed.
+                if self.makeindex_pass(&mut last_idx, status)? {
+                    rerun_result = Some(RerunReason::Makeindex);
+                }
+
+                // And the glossary entries.
+                if self.glossaries_pass(&mut last_glossaries, status)? {
+                    rerun_result = Some(RerunReason::Glossaries);
+                }
 
                 if rerun_result.is_some() && i == DEFAULT_MAX_TEX_PASSES - 1 {
                     tt_warning!(
@@ -1740,6 +3226,8 @@ impl ProcessingSession {
             }
         }
 
+        summary.report(status);
+
         // The last tex pass generated warnings.
         if let Some(warnings) = warnings {
             tt_warning!(status, "{}", warnings);
@@ -1772,6 +3260,263 @@ impl ProcessingSession {
             .unwrap_or(false)
     }
 
+    /// Get the lines of the `.aux` files that determine what BibTeX does, so
+    /// that we can tell whether it needs to be run again. Like BibTeX, we
+    /// follow `\@input` commands into the auxiliary files of included
+    /// chapters.
+    fn bibtex_aux_lines(&self) -> Vec<Vec<u8>> {
+        let files = self.bs.mem.files.borrow();
+        let mut lines = Vec::new();
+        let mut seen = HashSet::new();
+        collect_bibtex_aux_lines(&files, &self.tex_aux_path, &mut seen, &mut lines);
+        lines
+    }
+
+    /// Run `makeindex` on the `.idx` file written by the last TeX pass, if
+    /// there is one and its contents differ from those that `makeindex` last
+    /// saw in `last_idx`. Returns whether the index was processed.
+    ///
+    /// The builtin implementation is used unless an external program has
+    /// been given with `-Z makeindex`, which is only run if the security
+    /// settings allow shell-escape. Failing to run an external `makeindex` is
+    /// not fatal, since plenty of documents say `\makeindex` without ever
+    /// printing an index.
+    fn makeindex_pass(
+        &mut self,
+        last_idx: &mut Option<Vec<u8>>,
+        status: &mut dyn StatusBackend,
+    ) -> Result<bool> {
+        let idx_path = Path::new(&self.tex_aux_path)
+            .with_extension("idx")
+            .display()
+            .to_string();
+        let ind_path = Path::new(&idx_path)
+            .with_extension("ind")
+            .display()
+            .to_string();
+        let ilg_path = Path::new(&idx_path)
+            .with_extension("ilg")
+            .display()
+            .to_string();
+
+        let data = match self.bs.mem.files.borrow().get(&idx_path) {
+            Some(file) if !file.data.is_empty() => file.data.clone(),
+            _ => return Ok(false),
+        };
+
+        if last_idx.as_ref() == Some(&data) {
+            return Ok(false);
+        }
+
+        let program = match self.unstables.makeindex {
+            Some(_) if !self.security.allow_shell_escape() => {
+                tt_warning!(
+                    status,
+                    "using the builtin makeindex, since the security settings \
+                     forbid running external programs"
+                );
+                None
+            }
+            Some(ref path) => Some(path.display().to_string()),
+            None => None,
+        };
+
+        let Some(program) = program else {
+            status.note_highlighted("Running ", "makeindex", " (builtin) ...");
+            let output = MakeindexEngine::default().process(&idx_path, &ind_path, &data);
+
+            if output.rejected > 0 {
+                tt_warning!(
+                    status,
+                    "makeindex could not understand {} index entries; see `{}`",
+                    output.rejected,
+                    ilg_path
+                );
+            }
+
+            self.bs.import_tool_output(&ind_path, output.ind);
+            self.bs.import_tool_output(&ilg_path, output.ilg);
+            *last_idx = Some(data);
+            return Ok(true);
+        };
+
+        *last_idx = Some(data);
+
+        // The outputs are named explicitly, so that those of an earlier pass
+        // get replaced.
+        let tool = ExternalToolPass {
+            argv: vec![program, idx_path],
+            extra_requires: HashSet::new(),
+            outputs: vec![ind_path, ilg_path],
+            stdout: None,
+        };
+
+        if let Err(e) = self.bs.external_tool_pass(&tool, status) {
+            tt_warning!(status, "failed to process the index with makeindex"; SyncError::new(e).into());
+            return Ok(false);
+        }
+
+        Ok(true)
+    }
+
+    /// Sort the glossaries of a document that uses the `glossaries` package,
+    /// if the entries written by the last TeX pass, or the style file, differ
+    /// from those last seen in `last_state`. Returns whether they were
+    /// sorted.
+    ///
+    /// The glossaries are found from the `\@newglossary` lines of the `.aux`
+    /// file. The builtin `makeindex` is used unless an external
+    /// `makeglossaries` program has been given with `-Z makeglossaries`, which
+    /// is only run if the security settings allow shell-escape. Glossaries
+    /// meant for `xindy` can only be sorted by the external program.
+    fn glossaries_pass(
+        &mut self,
+        last_state: &mut Option<Vec<Vec<u8>>>,
+        status: &mut dyn StatusBackend,
+    ) -> Result<bool> {
+        let stem = Path::new(&self.tex_aux_path).with_extension("");
+        let stem = stem.display().to_string();
+
+        let (info, style, jobs, state) = {
+            let files = self.bs.mem.files.borrow();
+
+            let info = match files.get(&self.tex_aux_path) {
+                Some(aux) => GlossaryAux::parse(&aux.data),
+                None => return Ok(false),
+            };
+
+            let style = info
+                .style_file
+                .as_ref()
+                .and_then(|name| files.get(name))
+                .map(|file| file.data.clone());
+
+            // The input, output and transcript files of each glossary with
+            // entries, and the entries.
+            let mut jobs = Vec::new();
+            let mut state = vec![style.clone().unwrap_or_default()];
+
+            for [log, out, input] in &info.glossaries {
+                let input = format!("{stem}.{input}");
+
+                if let Some(file) = files.get(&input).filter(|f| !f.data.is_empty()) {
+                    state.push(file.data.clone());
+                    jobs.push((input, format!("{stem}.{out}"), format!("{stem}.{log}")));
+                }
+            }
+
+            (info, style, jobs, state)
+        };
+
+        if jobs.is_empty() || last_state.as_ref() == Some(&state) {
+            return Ok(false);
+        }
+
+        *last_state = Some(state);
+
+        let program = match self.unstables.makeglossaries {
+            Some(_) if !self.security.allow_shell_escape() => {
+                tt_warning!(
+                    status,
+                    "using the builtin makeindex for the glossaries, since the security \
+                     settings forbid running external programs"
+                );
+                None
+            }
+            Some(ref path) => Some(path.display().to_string()),
+            None => None,
+        };
+
+        if let Some(program) = program {
+            let mut extra_requires: HashSet<_> =
+                jobs.iter().map(|(input, _, _)| input.clone()).collect();
+            extra_requires.insert(self.tex_aux_path.clone());
+            extra_requires.extend(info.style_file.clone());
+
+            // The outputs are named explicitly, so that those of an earlier
+            // pass get replaced.
+            let tool = ExternalToolPass {
+                argv: vec![program, stem],
+                extra_requires,
+                outputs: jobs
+                    .into_iter()
+                    .flat_map(|(_, out, log)| [out, log])
+                    .collect(),
+                stdout: None,
+            };
+
+            if let Err(e) = self.bs.external_tool_pass(&tool, status) {
+                tt_warning!(status, "failed to sort the glossaries with makeglossaries"; SyncError::new(e).into());
+                return Ok(false);
+            }
+
+            return Ok(true);
+        }
+
+        let style = match (&info.style_file, style) {
+            (Some(name), _) if name.ends_with(".xdy") => {
+                tt_warning!(
+                    status,
+                    "the glossaries are meant to be sorted with xindy, which needs \
+                     an external makeglossaries program (see `-Z makeglossaries`)"
+                );
+                return Ok(false);
+            }
+            (_, Some(style)) => IndexStyle::parse(&String::from_utf8_lossy(&style)),
+            (name, None) => {
+                tt_warning!(
+                    status,
+                    "can't sort the glossaries, since their style file `{}` wasn't written",
+                    name.as_deref().unwrap_or_default()
+                );
+                return Ok(false);
+            }
+        };
+
+        status.note_highlighted("Running ", "makeindex", " (builtin) on the glossaries ...");
+
+        for (input, out, log) in jobs {
+            let data = self.bs.mem.files.borrow()[&input].data.clone();
+            let output = MakeindexEngine::default()
+                .style(style.clone())
+                .letter_ordering(info.letter_ordering)
+                .process(&input, &out, &data);
+
+            if output.rejected > 0 {
+                tt_warning!(
+                    status,
+                    "makeindex could not understand {} glossary entries; see `{}`",
+                    output.rejected,
+                    log
+                );
+            }
+
+            self.bs.import_tool_output(&out, output.ind);
+            self.bs.import_tool_output(&log, output.ilg);
+        }
+
+        Ok(true)
+    }
+
+    /// Run the tool hooks for one stage of the processing. Returns the name of
+    /// the last program run, if any were.
+    fn hook_pass(
+        &mut self,
+        stage: HookStage,
+        status: &mut dyn StatusBackend,
+    ) -> Result<Option<String>> {
+        let mut last = None;
+
+        for hook in self.tool_hooks.iter().filter(|h| h.stage == stage) {
+            self.check_cancelled()?;
+            self.bs
+                .external_tool_pass(&ExternalToolPass::from(hook), status)?;
+            last = Some(hook.command[0].clone());
+        }
+
+        Ok(last)
+    }
+
     /// Use the TeX engine to generate a format file.
     #[allow(clippy::manual_split_once)] // requires Rust 1.52 (note that we don't actually define our MSRV)
     fn make_format_pass(&mut self, status: &mut dyn StatusBackend) -> Result<i32> {
@@ -1792,7 +3537,15 @@ impl ProcessingSession {
                 .enter_format_mode(&format!("tectonic-format-{stem}.tex"));
             let mut launcher =
                 CoreBridgeLauncher::new_with_security(&mut self.bs, status, self.security.clone());
-            let r = TexEngine::default()
+            launcher
+                .with_time_limit(self.time_limit)
+                .with_memory_limit(self.memory_limit)
+                .with_cancel_flag(self.cancel_flag.clone());
+            let mut engine = TexEngine::default();
+            for &(param, value) in &self.tex_memory_parameters {
+                engine.memory_parameter(param, value);
+            }
+            let r = engine
                 .halt_on_error_mode(true)
                 .initex_mode(true)
                 .shell_escape(self.shell_escape_mode != ShellEscapeMode::Disabled)
@@ -1831,6 +3584,8 @@ impl ProcessingSession {
             }
 
             // Note that we intentionally pass 'stem', not 'name'.
+            let path = self.bs.format_cache.path_for_format(stem)?;
+            check_write_access(&self.security, &path)?;
             ctry!(self.bs.format_cache.write_format(stem, &file.data, status); "cannot write format file {}", sname);
         }
 
@@ -1840,6 +3595,152 @@ impl ProcessingSession {
         Ok(0)
     }
 
+    /// Set things up to process the document with its preamble preloaded
+    /// from a format file, generating the format if it isn't in the cache.
+    ///
+    /// The format is generated with the filesystem hidden from the engine,
+    /// like the ordinary formats, so that it depends only on the bundle and
+    /// the text of the preamble; those, and the engine's format version, are
+    /// what the cached file is named by. If the preamble needs anything else,
+    /// the document is just processed as usual.
+    fn preamble_format_pass(&mut self, status: &mut dyn StatusBackend) -> Result<()> {
+        let (text, origin, abspath) = match self
+            .bs
+            .primary_input
+            .input_open_primary_with_abspath(status)
+        {
+            OpenResult::Ok((mut ih, abspath)) => {
+                let mut text = Vec::new();
+                ih.read_to_end(&mut text)?;
+                (text, ih.origin(), abspath)
+            }
+            // The TeX pass will report the problem.
+            _ => return Ok(()),
+        };
+
+        let preamble_len = match find_preamble_end(&text) {
+            Some(n) if !text[..n].trim_ascii().is_empty() => n,
+            _ => return Ok(()),
+        };
+
+        let mut dc = digest::create();
+        dc.update(&text[..preamble_len]);
+        let stem = self.format_name.split('.').next().unwrap_or_default();
+        let preamble_format = format!("{stem}-preamble-{}", DigestData::from(dc));
+
+        match self.bs.input_open_format(&preamble_format, status) {
+            OpenResult::Ok(_) => {}
+            OpenResult::NotAvailable => {
+                tt_note!(status, "precompiling the preamble of the document");
+
+                let result =
+                    self.make_preamble_format_pass(&text[..preamble_len], &preamble_format);
+                self.bs.mem.files.borrow_mut().clear();
+
+                if let Err(e) = result {
+                    tt_note!(status, "the preamble can't be precompiled, so it will be processed as usual"; SyncError::new(e).into());
+                    return Ok(());
+                }
+            }
+            OpenResult::Err(e) => {
+                return Err(e)
+                    .chain_err(|| format!("could not open format file {preamble_format}"));
+            }
+        }
+
+        self.format_name = preamble_format;
+        self.bs.primary_input = Box::new(PrecompiledPreambleIo::new(
+            text,
+            preamble_len,
+            origin,
+            abspath,
+        ));
+        Ok(())
+    }
+
+    /// Use the TeX engine to generate a format file that extends the current
+    /// one with a document's preamble.
+    fn make_preamble_format_pass(&mut self, preamble: &[u8], name: &str) -> Result<()> {
+        // Problems are only to be expected, and are dealt with by processing
+        // the preamble as usual, so there's no point in reporting them.
+        let mut status = NoopStatusBackend::default();
+
+        let result = {
+            self.bs.enter_preamble_format_mode(preamble);
+            let mut launcher = CoreBridgeLauncher::new_with_security(
+                &mut self.bs,
+                &mut status,
+                self.security.clone(),
+            );
+            launcher
+                .with_time_limit(self.time_limit)
+                .with_memory_limit(self.memory_limit)
+                .with_cancel_flag(self.cancel_flag.clone());
+            let mut engine = TexEngine::default();
+            for &(param, value) in &self.tex_memory_parameters {
+                engine.memory_parameter(param, value);
+            }
+            let r = engine
+                .halt_on_error_mode(true)
+                .initex_mode(true)
+                .initex_loads_format(true)
+                .system_fonts(self.system_fonts_enabled)
+                .shell_escape(self.shell_escape_mode != ShellEscapeMode::Disabled)
+                .process(&mut launcher, &self.format_name, "texput");
+            self.bs.leave_format_mode();
+            r
+        };
+
+        match result {
+            Ok(TexOutcome::Spotless) | Ok(TexOutcome::Warnings) => {}
+            Ok(TexOutcome::Errors) => {
+                return Err(errmsg!("errors were issued by the TeX engine"));
+            }
+            Err(e) => return Err(e.into()),
+        }
+
+        let files = self.bs.mem.files.borrow();
+        let Some(file) = files
+            .iter()
+            .find_map(|(name, file)| name.ends_with(".fmt").then_some(file))
+        else {
+            return Err(errmsg!("the TeX engine did not generate a format file"));
+        };
+
+        let path = self.bs.format_cache.path_for_format(name)?;
+        check_write_access(&self.security, &path)?;
+        ctry!(self.bs.format_cache.write_format(name, &file.data, &mut status); "cannot write format file {}", name);
+        Ok(())
+    }
+
+    /// Run one pass of the TeX engine on its own, reporting whether it issued
+    /// warnings.
+    fn single_tex_pass(&mut self, status: &mut dyn StatusBackend) -> Result<i32> {
+        if let Some(warnings) = self.tex_pass(None, status)? {
+            tt_warning!(status, "{}", warnings);
+        }
+
+        Ok(0)
+    }
+
+    /// Run one pass of the TeX engine, holding back its warnings for
+    /// `summary`.
+    fn summarized_tex_pass(
+        &mut self,
+        rerun_explanation: Option<&str>,
+        summary: &mut WarningSummary,
+        status: &mut dyn StatusBackend,
+    ) -> Result<Option<&'static str>> {
+        summary.start_pass();
+
+        let mut collector = WarningCollector {
+            inner: status,
+            summary,
+        };
+
+        self.tex_pass(rerun_explanation, &mut collector)
+    }
+
     /// Run one pass of the TeX engine.
     fn tex_pass(
         &mut self,
@@ -1855,26 +3756,51 @@ impl ProcessingSession {
 
             let mut launcher =
                 CoreBridgeLauncher::new_with_security(&mut self.bs, status, self.security.clone());
+            launcher
+                .with_time_limit(self.time_limit)
+                .with_memory_limit(self.memory_limit)
+                .with_cancel_flag(self.cancel_flag.clone());
 
             // In deterministic mode, we stub a few aspects of the environment.
-            // They default to a "realistic" view, but we override them with static values:
+            // They default to a "realistic" view, but we override them with static values.
+            // File modification times are also stubbed in reproducible builds.
             if self.unstables.deterministic_mode {
                 launcher.with_expose_absolute_paths(false);
+            }
+
+            if self.unstables.deterministic_mode || self.reproducible {
                 launcher.with_mtime_override(Some(
                     self.build_date
                         .duration_since(SystemTime::UNIX_EPOCH)
                         .map(|x| x.as_secs() as i64)
-                        .expect("invalid build date in deterministic mode"),
+                        .expect("invalid build date in reproducible mode"),
                 ));
             }
 
-            TexEngine::default()
-                .halt_on_error_mode(!self.unstables.continue_on_errors)
+            let mut engine = TexEngine::default();
+            for &(param, value) in &self.tex_memory_parameters {
+                engine.memory_parameter(param, value);
+            }
+            engine.halt_on_error_mode(!self.unstables.continue_on_errors);
+            if self.reproducible {
+                engine.pinned_build_date(self.build_date);
+            } else {
+                engine.build_date(self.build_date);
+            }
+            if let Some(name) = &self.job_name {
+                engine.job_name(name);
+            }
+            if let Some(mode) = self.interaction_mode {
+                engine.interaction_mode(mode);
+            }
+            engine
                 .initex_mode(self.output_format == OutputFormat::Format)
                 .synctex(self.synctex_enabled)
                 .semantic_pagination(self.output_format == OutputFormat::Html)
+                .actual_text(self.actual_text_enabled)
+                .system_fonts(self.system_fonts_enabled)
+                .font_features(&self.font_features)
                 .shell_escape(self.shell_escape_mode != ShellEscapeMode::Disabled)
-                .build_date(self.build_date)
                 .process(
                     &mut launcher,
                     &self.format_name,
@@ -1882,6 +3808,12 @@ impl ProcessingSession {
                 )
         };
 
+        if matches!(result, Ok(TexOutcome::Errors) | Err(_)) {
+            self.report_tex_diagnostics(status);
+        }
+
+        self.save_tex_trace();
+
         let warnings = match result {
             Ok(TexOutcome::Spotless) => None,
             Ok(TexOutcome::Warnings) =>
@@ -1905,38 +3837,191 @@ impl ProcessingSession {
         Ok(warnings)
     }
 
+    /// Report the errors in the log of the TeX engine through the status
+    /// backend, with the places in the input where they were found.
+    fn report_tex_diagnostics(&mut self, status: &mut dyn StatusBackend) {
+        let log_path = Path::new(&self.tex_aux_path).with_extension("log");
+
+        if let Some(log) = self.get_file_data(&log_path.display().to_string()) {
+            for mut diag in parse_log_diagnostics(&log) {
+                diag.source_line = self.read_source_line(&diag, status);
+                status.report_diagnostic(&diag);
+            }
+        }
+    }
+
+    /// Save the trace in the log of the TeX engine, if the document turned
+    /// on any of the `\tracing...` parameters, as JSON Lines. It's kept apart
+    /// from the rest of the log so that it can be searched and filtered.
+    fn save_tex_trace(&mut self) {
+        let log_path = Path::new(&self.tex_aux_path).with_extension("log");
+
+        let Some(log) = self.get_file_data(&log_path.display().to_string()) else {
+            return;
+        };
+
+        let trace = parse_log_trace(&log);
+
+        if trace.is_empty() {
+            return;
+        }
+
+        let mut data = Vec::new();
+
+        for entry in &trace {
+            data.extend_from_slice(entry.to_json().as_bytes());
+            data.push(b'\n');
+        }
+
+        let trace_path = Path::new(&self.tex_aux_path).with_extension(&TRACE_EXTENSION[1..]);
+        self.bs
+            .import_tool_output(&trace_path.display().to_string(), data);
+    }
+
+    /// Report the problems in a log written by BibTeX through the status
+    /// backend. Unlike those of the TeX engine, they haven't been reported as
+    /// messages yet, so that's done here too.
+    fn report_bibtex_diagnostics(&mut self, blg: &str, status: &mut dyn StatusBackend) {
+        let Some(log) = self.get_file_data(blg) else {
+            return;
+        };
+
+        for mut diag in parse_blg_diagnostics(&log) {
+            let (Some(file), Some(line)) = (diag.file.as_ref(), diag.line) else {
+                continue;
+            };
+
+            status.report(
+                diag.kind,
+                format_args!("{}:{}: {}", file, line, diag.message),
+                None,
+            );
+            diag.source_line = self.read_source_line(&diag, status);
+            status.report_diagnostic(&diag);
+        }
+    }
+
+    /// Read the line of an input file that a diagnostic is about, so that it
+    /// can be shown.
+    fn read_source_line(
+        &mut self,
+        diag: &SourceDiagnostic,
+        status: &mut dyn StatusBackend,
+    ) -> Option<String> {
+        let name = diag.file.as_ref()?;
+        let index = diag.line?.checked_sub(1)? as usize;
+
+        // The engines print the names of files as they opened them, which for
+        // the primary input is its TeX name.
+        let is_primary = name.strip_prefix("./").unwrap_or(name)
+            == self
+                .primary_input_tex_path
+                .strip_prefix("./")
+                .unwrap_or(&self.primary_input_tex_path);
+
+        let ih = if is_primary {
+            self.bs.primary_input.input_open_primary(status)
+        } else {
+            self.bs.input_open_name(name, status)
+        };
+
+        let OpenResult::Ok(mut ih) = ih else {
+            return None;
+        };
+
+        let mut data = Vec::new();
+        ih.read_to_end(&mut data).ok()?;
+        let text = String::from_utf8_lossy(&data);
+        text.lines().nth(index).map(|l| l.to_owned())
+    }
+
     // Run Bibtex process for one .aux file.
     fn bibtex_pass_for_one_aux_file(
         &mut self,
         status: &mut dyn StatusBackend,
         aux_file: &String,
     ) -> Result<i32> {
+        let options = &self.bibtex_options;
+        let mut engine = BibtexEngine::new();
+
+        if let Some(n) = options.min_crossrefs {
+            engine.min_crossrefs(n);
+        }
+
+        for path in &options.search_paths {
+            engine.bib_search_path(path);
+        }
+
+        if let Some(style) = &options.style {
+            let name = if style.ends_with(".bst") {
+                style.clone()
+            } else {
+                format!("{style}.bst")
+            };
+
+            let mut ih = ctry!(
+                self.bs.input_open_name(&name, status).must_exist();
+                "can't open the BibTeX style file `{}`", name
+            );
+            let mut data = Vec::new();
+            ctry!(
+                ih.read_to_end(&mut data);
+                "failed to read the BibTeX style file `{}`", name
+            );
+            engine.bst_data(data);
+        }
+
         let result = {
             status.note_highlighted("Running ", "BibTeX", &format!(" on {aux_file} ..."));
             let mut launcher =
                 CoreBridgeLauncher::new_with_security(&mut self.bs, status, self.security.clone());
-            let mut engine = BibtexEngine::new();
+            launcher
+                .with_time_limit(self.time_limit)
+                .with_memory_limit(self.memory_limit)
+                .with_cancel_flag(self.cancel_flag.clone());
             engine.process(&mut launcher, aux_file, &self.unstables)
         };
 
-        match result {
-            Ok(TexOutcome::Spotless) => {}
-            Ok(TexOutcome::Warnings) => {
-                tt_note!(
-                    status,
-                    "warnings were issued by BibTeX; use --print and/or --keep-logs for details."
-                );
+        let run = match result {
+            Ok(run) => run,
+            Err(e) => {
+                return Err(e.chain_err(|| ErrorKind::EngineError("BibTeX")));
             }
-            Ok(TexOutcome::Errors) => {
+        };
+
+        if run.outcome != TexOutcome::Spotless {
+            if let Some(blg) = &run.blg_file {
+                self.report_bibtex_diagnostics(blg, status);
+            }
+        }
+
+        // Point at the log that BibTeX actually wrote, if it got that far.
+        let details = match &run.blg_file {
+            Some(blg) if self.keep_logs || is_kept(&self.keep_extensions, blg) => {
+                format!("see `{blg}` for details.")
+            }
+            Some(blg) => format!("use --print, or --keep-logs to save `{blg}`, for details."),
+            None => "use --print for details.".to_owned(),
+        };
+
+        match run.outcome {
+            TexOutcome::Spotless => {}
+            TexOutcome::Warnings | TexOutcome::Errors if self.bibtex_options.warnings_as_errors => {
+                return Err(errmsg!(
+                    "BibTeX issued warnings or errors, which are configured to be fatal; {}",
+                    details
+                ));
+            }
+            TexOutcome::Warnings => {
+                tt_note!(status, "warnings were issued by BibTeX; {}", details);
+            }
+            TexOutcome::Errors => {
                 tt_warning!(
                     status,
-                    "errors were issued by BibTeX, but were ignored; \
-                     use --print and/or --keep-logs for details."
+                    "errors were issued by BibTeX, but were ignored; {}",
+                    details
                 );
             }
-            Err(e) => {
-                return Err(e.chain_err(|| ErrorKind::EngineError("BibTeX")));
-            }
         }
 
         Ok(0)
@@ -1965,22 +4050,99 @@ impl ProcessingSession {
 
             let mut launcher =
                 CoreBridgeLauncher::new_with_security(&mut self.bs, status, self.security.clone());
+            launcher
+                .with_time_limit(self.time_limit)
+                .with_memory_limit(self.memory_limit)
+                .with_cancel_flag(self.cancel_flag.clone());
             let mut engine = XdvipdfmxEngine::default();
 
-            engine.build_date(self.build_date);
+            engine
+                .build_date(self.build_date)
+                .enable_deterministic_tags(self.reproducible)
+                .pdfa(self.pdfa);
 
             if let Some(ref ps) = self.unstables.paper_size {
                 engine.paper_spec(ps.clone());
             }
 
+            if let Some((major, minor)) = self.unstables.pdf_version {
+                engine.pdf_version(major, minor);
+            }
+
+            if let Some(level) = self.unstables.pdf_compression_level {
+                engine.compression_level(level);
+            }
+
+            if self.unstables.no_pdf_object_streams {
+                engine.enable_object_streams(false);
+            }
+
+            let images = &self.image_options;
+
+            if let Some(dpi) = images.max_dpi {
+                engine.image_max_dpi(dpi);
+            }
+
+            if let Some(quality) = images.jpeg_quality {
+                engine.jpeg_quality(quality);
+            }
+
+            engine.grayscale_images(images.grayscale);
+
+            if let Some(level) = self.outline_options.open_level {
+                engine.outline_open_depth(level);
+            }
+
+            let meta = &self.pdf_metadata;
+
+            for (key, value) in [
+                ("Title", &meta.title),
+                ("Author", &meta.author),
+                ("Subject", &meta.subject),
+                ("Keywords", &meta.keywords),
+            ] {
+                if let Some(value) = value {
+                    engine.document_info(key, value);
+                }
+            }
+
+            if let Some(ref lang) = meta.language {
+                engine.language(lang);
+            }
+
+            if let Some(ref enc) = self.pdf_encryption {
+                engine
+                    .encryption(&enc.owner_password, &enc.user_password)
+                    .allow_printing(enc.allow_printing)
+                    .allow_copying(enc.allow_copying);
+            }
+
+            for path in &self.pdf_attachments {
+                let name = match path.file_name() {
+                    Some(name) => name.to_string_lossy(),
+                    None => return Err(errmsg!("invalid PDF attachment `{}`", path.display())),
+                };
+                let data = ctry!(
+                    std::fs::read(path);
+                    "unable to read PDF attachment `{}`", path.display()
+                );
+                engine.attachment(&name, data);
+            }
+
             engine.process(&mut launcher, &self.tex_xdv_path, &self.tex_pdf_path)?;
         }
 
-        self.bs.mem.files.borrow_mut().remove(&self.tex_xdv_path);
+        if !self.keeps_xdv() {
+            self.bs.mem.files.borrow_mut().remove(&self.tex_xdv_path);
+        }
         Ok(0)
     }
 
     fn spx2html_pass(&mut self, status: &mut dyn StatusBackend) -> Result<i32> {
+        // This engine writes its outputs directly to disk and can't be
+        // interrupted, so this is the last chance to cancel.
+        self.check_cancelled()?;
+
         {
             let mut engine = Spx2HtmlEngine::default();
 
@@ -2000,14 +4162,34 @@ impl ProcessingSession {
                 engine.precomputed_assets(a.clone());
             }
 
+            engine.mathml(self.html_mathml);
+            engine.optimize_assets(self.html_optimize_assets);
+            engine.split_level(self.html_split_level);
+
+            for (name, path) in &self.html_templates {
+                let contents = ctry!(
+                    std::fs::read_to_string(path);
+                    "unable to read HTML template `{}`", path.display()
+                );
+                engine.template(name, contents);
+            }
+
             status.note_highlighted("Running ", "spx2html", " ...");
             engine.process_to_filesystem(&mut self.bs, status, &self.tex_xdv_path)?;
         }
 
-        self.bs.mem.files.borrow_mut().remove(&self.tex_xdv_path);
+        if !self.keeps_xdv() {
+            self.bs.mem.files.borrow_mut().remove(&self.tex_xdv_path);
+        }
         Ok(0)
     }
 
+    /// Whether the XDV or SPX file should be written out along with the final
+    /// output, rather than discarded once it's been converted.
+    fn keeps_xdv(&self) -> bool {
+        self.keep_intermediates || is_kept(&self.keep_extensions, &self.tex_xdv_path)
+    }
+
     /// Get what was printed to standard output, if anything.
     pub fn get_stdout_content(&self) -> Vec<u8> {
         self.bs
@@ -2019,6 +4201,60 @@ impl ProcessingSession {
             .unwrap_or_default()
     }
 
+    /// Get the contents of a file that the session created in memory, such as
+    /// an intermediate file like `mydoc.aux`, whether or not it was written
+    /// out to the filesystem. Returns `None` if there's no such file.
+    pub fn get_file_data(&self, name: &str) -> Option<Vec<u8>> {
+        if name == self.bs.mem.stdout_key() {
+            return None;
+        }
+
+        self.bs
+            .mem
+            .files
+            .borrow()
+            .get(name)
+            .map(|mfi| mfi.data.clone())
+    }
+
+    /// Get the paths of the files that the engines read from the filesystem,
+    /// as opposed to the bundle or memory. These are the files that a build
+    /// depends on. Files that were written back to disk are left out, since
+    /// they're outputs as much as inputs.
+    pub fn filesystem_inputs(&self) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = self.primary_input_path.iter().cloned().collect();
+        paths.extend(self.filesystem_input_files().into_iter().map(|(_, p)| p));
+        paths
+    }
+
+    /// Like [`Self::filesystem_inputs`], but pairing the path of each file
+    /// with the name that the engines opened it by, and leaving out the
+    /// primary input.
+    pub fn filesystem_input_files(&self) -> Vec<(String, PathBuf)> {
+        let mut files = Vec::new();
+
+        for (name, info) in &self.bs.events {
+            if info.input_origin != InputOrigin::Filesystem || info.got_written_to_disk {
+                continue;
+            }
+
+            // The events don't record which filesystem provider opened the
+            // file, so look for it in the source directories in the order
+            // that the I/O stack searches them.
+            let mut roots = std::iter::once(self.bs.filesystem.root())
+                .chain(self.bs.extra_search_paths.iter().map(|fs| fs.root()));
+
+            if let Some(path) = roots.find_map(|root| {
+                let path = root.join(name);
+                path.is_file().then_some(path)
+            }) {
+                files.push((name.clone(), path));
+            }
+        }
+
+        files
+    }
+
     /// Consume this session and return the current set of files in memory.
     ///
     /// This convenience function tries to help with the annoyances of getting
@@ -2039,9 +4275,10 @@ impl ProcessingSession {
     ) -> Result<Option<ExternalToolPass>> {
         // Is there a `.run.xml` file?
 
-        let mut run_xml_path = PathBuf::from(&self.primary_input_tex_path);
-        run_xml_path.set_extension("run.xml");
-        let run_xml_path = run_xml_path.display().to_string();
+        let run_xml_path = Path::new(&self.tex_aux_path)
+            .with_extension("run.xml")
+            .display()
+            .to_string();
 
         let mem_files = &*self.bs.mem.files.borrow();
         let run_xml_entry = match mem_files.get(&run_xml_path) {
@@ -2064,7 +4301,10 @@ impl ProcessingSession {
             }
             // when `TECTONIC_TEST_FAKE_BIBER` is empty, proceed to discover
             // the biber binary as follows.
-            _ => vec!["biber".to_owned()],
+            _ => match self.unstables.biber {
+                Some(ref path) => vec![path.display().to_string()],
+                None => vec!["biber".to_owned()],
+            },
         };
 
         // Moreover, we allow an override of the biber executable, to cope with
@@ -2081,7 +4321,13 @@ impl ProcessingSession {
         };
 
         let mut use_tectonic_biber_override = false;
-        for binary_name in ["./tectonic-biber", "tectonic-biber"] {
+        let binary_names = if self.unstables.biber.is_some() {
+            // An explicitly requested executable takes precedence.
+            &[][..]
+        } else {
+            &["./tectonic-biber", "tectonic-biber"][..]
+        };
+        for binary_name in binary_names {
             if let Some(biber_path) = find_by(binary_name) {
                 argv = vec![biber_path];
                 use_tectonic_biber_override = true;
@@ -2261,7 +4507,61 @@ impl ProcessingSession {
             Some(ExternalToolPass {
                 argv,
                 extra_requires,
+                outputs: Vec::new(),
+                stdout: None,
             })
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(command: &str) -> StdResult<RestrictedCommand, String> {
+        RestrictedCommand::parse(command, &["tool".to_owned()])
+    }
+
+    #[test]
+    fn restricted_command() {
+        assert_eq!(
+            parse("tool -o out.txt 'a b' --level=2 -Isub > log").unwrap(),
+            RestrictedCommand {
+                argv: vec![
+                    "tool".to_owned(),
+                    "-o".to_owned(),
+                    "out.txt".to_owned(),
+                    "a b".to_owned(),
+                    "--level=2".to_owned(),
+                    "-Isub".to_owned(),
+                ],
+                stdout: Some("log".to_owned()),
+            }
+        );
+
+        assert!(parse("other x").is_err());
+        assert!(parse("tool x | sh").is_err());
+        assert!(parse("tool > log x").is_err());
+    }
+
+    #[test]
+    fn restricted_command_paths() {
+        assert!(parse("tool sub/dir/file.txt --out=sub/x -I sub/dir").is_ok());
+
+        for command in [
+            "tool /etc/passwd",
+            "tool ../x",
+            "tool sub/../../x",
+            "tool x > ../log",
+            "tool --output=/etc/x",
+            "tool --output=../x",
+            "tool --define=a=../x",
+            "tool -o../x",
+            "tool -I/abs",
+            "tool -vo/abs",
+            "tool -o=../x",
+        ] {
+            assert!(parse(command).is_err(), "`{command}` should be refused");
+        }
+    }
+}
@@ -3,11 +3,34 @@
 
 //! Engine for invoking `bibtex`.
 
+use std::{
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+};
 use tectonic_bridge_core::CoreBridgeLauncher;
 use tectonic_engine_bibtex::{BibtexEngine as RealBibtexEngine, BibtexOutcome};
+use tectonic_io_base::{InputHandle, InputOrigin};
 
 use super::tex::TexOutcome;
-use crate::{errors::Result, unstable_opts::UnstableOptions};
+use crate::{
+    errors::Result,
+    status::{MessageKind, SourceDiagnostic},
+    unstable_opts::UnstableOptions,
+};
+
+/// The results of a BibTeX run.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BibtexRun {
+    /// The overall outcome of the run.
+    pub outcome: TexOutcome,
+
+    /// The name of the `.bbl` output file, if it was opened.
+    pub bbl_file: Option<String>,
+
+    /// The name of the `.blg` log file, if it was opened.
+    pub blg_file: Option<String>,
+}
 
 /// A struct for invoking the `bibtex` engine.
 ///
@@ -15,7 +38,11 @@ use crate::{errors::Result, unstable_opts::UnstableOptions};
 /// apply any settings that you wish, and eventually run the
 /// [`process()`](Self::process) method.
 #[derive(Default)]
-pub struct BibtexEngine {}
+pub struct BibtexEngine {
+    min_crossrefs: Option<u32>,
+    bst_data: Option<Vec<u8>>,
+    bib_search_paths: Vec<PathBuf>,
+}
 
 impl BibtexEngine {
     /// Create a new, default engine for running `bibtex`.
@@ -23,6 +50,30 @@ impl BibtexEngine {
         Default::default()
     }
 
+    /// Set the BibTeX `min_crossrefs` parameter.
+    ///
+    /// This takes precedence over the `min-crossrefs` unstable option.
+    pub fn min_crossrefs(&mut self, value: u32) -> &mut Self {
+        self.min_crossrefs = Some(value);
+        self
+    }
+
+    /// Supply the contents of the style file to use, instead of the one named
+    /// by the `.aux` file.
+    pub fn bst_data(&mut self, data: Vec<u8>) -> &mut Self {
+        self.bst_data = Some(data);
+        self
+    }
+
+    /// Add a directory to search for database files.
+    ///
+    /// These directories are searched in the order that they're added, before
+    /// the database files are looked up through the usual I/O stack.
+    pub fn bib_search_path<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
+        self.bib_search_paths.push(path.as_ref().to_owned());
+        self
+    }
+
     /// Process a document using the current engine configuration.
     ///
     /// The *launcher* parameter gives overarching environmental context in
@@ -33,24 +84,170 @@ impl BibtexEngine {
     ///
     /// The *unstables* parameter controls unstable options that may change the behavior of
     /// `bibtex`.
+    ///
+    /// The result gives the names of the files that BibTeX wrote along with
+    /// its outcome, so that callers don't need to work them out from *aux*.
     pub fn process(
         &mut self,
         launcher: &mut CoreBridgeLauncher,
         aux: &str,
         unstables: &UnstableOptions,
-    ) -> Result<TexOutcome> {
+    ) -> Result<BibtexRun> {
         let mut real_engine = RealBibtexEngine::default();
 
-        if let Some(x) = unstables.min_crossrefs {
+        if let Some(x) = self.min_crossrefs.or(unstables.min_crossrefs) {
             real_engine.min_crossrefs(x);
         }
 
-        let real_outcome = real_engine.process(launcher, aux)?;
+        if let Some(data) = self.bst_data.take() {
+            real_engine.bst_data(data);
+        }
+
+        if !self.bib_search_paths.is_empty() {
+            let dirs = self.bib_search_paths.clone();
+
+            real_engine.bib_resolver(move |name| {
+                let name = format!("{name}.bib");
 
-        match real_outcome {
-            BibtexOutcome::Spotless => Ok(TexOutcome::Spotless),
-            BibtexOutcome::Warnings => Ok(TexOutcome::Warnings),
-            BibtexOutcome::Errors => Ok(TexOutcome::Errors),
+                dirs.iter().find_map(|dir| {
+                    let f = File::open(dir.join(&name)).ok()?;
+                    Some(InputHandle::new(
+                        name.clone(),
+                        BufReader::new(f),
+                        InputOrigin::Filesystem,
+                    ))
+                })
+            });
         }
+
+        let report = real_engine.process(launcher, aux)?;
+
+        let outcome = match report.outcome {
+            BibtexOutcome::Spotless => TexOutcome::Spotless,
+            BibtexOutcome::Warnings => TexOutcome::Warnings,
+            BibtexOutcome::Errors => TexOutcome::Errors,
+        };
+
+        Ok(BibtexRun {
+            outcome,
+            bbl_file: report.bbl_file,
+            blg_file: report.blg_file,
+        })
+    }
+}
+
+/// Find the problems with a known location in a log written by BibTeX.
+///
+/// BibTeX gives the location of a problem as `---line <number> of file
+/// <name>` for errors, and `--line ...` for warnings, either at the end of the
+/// message or on the line after it. Warnings start with `Warning--`. If the
+/// problem is in a line that BibTeX was parsing, the location is followed by
+/// two lines starting with ` : `, which split the input line at the point
+/// where the problem was found.
+pub fn parse_blg_diagnostics(log: &[u8]) -> Vec<SourceDiagnostic> {
+    let log = String::from_utf8_lossy(log);
+    let lines: Vec<&str> = log.lines().collect();
+    let mut diagnostics = Vec::new();
+
+    for (i, line) in lines.iter().enumerate() {
+        let Some(pos) = line.rfind("--line ") else {
+            continue;
+        };
+
+        let Some((number, file)) = line[pos + 7..].split_once(" of file ") else {
+            continue;
+        };
+
+        let Ok(number) = number.parse() else {
+            continue;
+        };
+
+        // The message is on the line before if this one has only the
+        // location, or tells where in the style file an error happened.
+        let mut message = line[..pos].trim_end_matches('-');
+
+        if message.is_empty() || message == "while executing" {
+            message = match i.checked_sub(1) {
+                Some(j) => lines[j],
+                None => continue,
+            };
+        }
+
+        let (kind, message) = match message.strip_prefix("Warning--") {
+            Some(m) => (MessageKind::Warning, m),
+            None => (MessageKind::Error, message),
+        };
+
+        let context = lines[i + 1..]
+            .iter()
+            .take(2)
+            .take_while(|l| l.starts_with(" : "))
+            .map(|l| l.to_string())
+            .collect();
+
+        diagnostics.push(SourceDiagnostic {
+            kind,
+            file: Some(file.to_owned()),
+            line: Some(number),
+            message: message.to_owned(),
+            context,
+            source_line: None,
+        });
+    }
+
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blg_diagnostics() {
+        let log = b"The top-level auxiliary file: main.aux\n\
+            The style file: plain.bst\n\
+            Database file #1: refs.bib\n\
+            I was expecting a `,' or a `}'---line 17 of file refs.bib\n\
+            \x20:   title = {Brown's Book} \n\
+            \x20:                          extra {text, here},\n\
+            I'm skipping whatever remains of this entry\n\
+            Warning--entry type for \"jz\" isn't style-file defined\n\
+            --line 2 of file refs.bib\n\
+            Warning--I didn't find a database entry for \"A\"\n\
+            Warning--empty citation key--line 4 of file main.aux\n\
+            (There was 1 error message)\n";
+
+        assert_eq!(
+            parse_blg_diagnostics(log),
+            vec![
+                SourceDiagnostic {
+                    kind: MessageKind::Error,
+                    file: Some("refs.bib".to_owned()),
+                    line: Some(17),
+                    message: "I was expecting a `,' or a `}'".to_owned(),
+                    context: vec![
+                        " :   title = {Brown's Book} ".to_owned(),
+                        " :                          extra {text, here},".to_owned(),
+                    ],
+                    source_line: None,
+                },
+                SourceDiagnostic {
+                    kind: MessageKind::Warning,
+                    file: Some("refs.bib".to_owned()),
+                    line: Some(2),
+                    message: "entry type for \"jz\" isn't style-file defined".to_owned(),
+                    context: Vec::new(),
+                    source_line: None,
+                },
+                SourceDiagnostic {
+                    kind: MessageKind::Warning,
+                    file: Some("main.aux".to_owned()),
+                    line: Some(4),
+                    message: "empty citation key".to_owned(),
+                    context: Vec::new(),
+                    source_line: None,
+                },
+            ]
+        );
     }
 }
@@ -0,0 +1,896 @@
+// Copyright 2025 the Tectonic Project
+// Licensed under the MIT License.
+
+//! A builtin implementation of `makeindex`.
+//!
+//! This turns the `\indexentry` commands that LaTeX writes into an `.idx`
+//! file into a sorted `theindex` environment, formatted like the default
+//! style of the classic `makeindex` program. It understands subentries
+//! (`!`), separate sort keys (`@`), page encapsulators (`|`), explicit page
+//! ranges (`|(` and `|)`) and the `"` quote character. Style files can
+//! change these characters and the text that the index is written with, as
+//! the `glossaries` package does, but not how pages are numbered.
+
+use std::{cmp::Ordering, fmt::Write};
+
+/// The parameters of an index style, as set by a `makeindex` style file. The
+/// defaults are those of `makeindex` itself.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct IndexStyle {
+    keyword: String,
+    actual: char,
+    encap: char,
+    level: char,
+    quote: char,
+    preamble: String,
+    postamble: String,
+    group_skip: String,
+    headings_flag: i32,
+    heading_prefix: String,
+    heading_suffix: String,
+    symhead_positive: String,
+    symhead_negative: String,
+    numhead_positive: String,
+    numhead_negative: String,
+    item: [String; 3],
+    item_01: String,
+    item_x1: String,
+    item_12: String,
+    item_x2: String,
+    delim: [String; 3],
+    delim_n: String,
+    delim_r: String,
+    delim_t: String,
+    encap_prefix: String,
+    encap_infix: String,
+    encap_suffix: String,
+}
+
+impl Default for IndexStyle {
+    fn default() -> Self {
+        IndexStyle {
+            keyword: "\\indexentry".to_owned(),
+            actual: '@',
+            encap: '|',
+            level: '!',
+            quote: '"',
+            preamble: "\\begin{theindex}\n".to_owned(),
+            postamble: "\n\n\\end{theindex}\n".to_owned(),
+            group_skip: "\n\n  \\indexspace\n".to_owned(),
+            headings_flag: 0,
+            heading_prefix: String::new(),
+            heading_suffix: String::new(),
+            symhead_positive: "Symbols".to_owned(),
+            symhead_negative: "symbols".to_owned(),
+            numhead_positive: "Numbers".to_owned(),
+            numhead_negative: "numbers".to_owned(),
+            item: [
+                "\n  \\item ".to_owned(),
+                "\n    \\subitem ".to_owned(),
+                "\n      \\subsubitem ".to_owned(),
+            ],
+            item_01: "\n    \\subitem ".to_owned(),
+            item_x1: "\n    \\subitem ".to_owned(),
+            item_12: "\n      \\subsubitem ".to_owned(),
+            item_x2: "\n      \\subsubitem ".to_owned(),
+            delim: [", ".to_owned(), ", ".to_owned(), ", ".to_owned()],
+            delim_n: ", ".to_owned(),
+            delim_r: "--".to_owned(),
+            delim_t: String::new(),
+            encap_prefix: "\\".to_owned(),
+            encap_infix: "{".to_owned(),
+            encap_suffix: "}".to_owned(),
+        }
+    }
+}
+
+impl IndexStyle {
+    /// Parse the contents of a `makeindex` style file. Parameters that aren't
+    /// understood, and lines that can't be parsed, are ignored.
+    pub fn parse(text: &str) -> IndexStyle {
+        let mut style = IndexStyle::default();
+        let mut rest = text;
+
+        while let Some((key, value, tail)) = next_style_param(rest) {
+            rest = tail;
+
+            match (key, value) {
+                ("actual", StyleValue::Char(c)) => style.actual = c,
+                ("encap", StyleValue::Char(c)) => style.encap = c,
+                ("level", StyleValue::Char(c)) => style.level = c,
+                ("quote", StyleValue::Char(c)) => style.quote = c,
+                ("headings_flag", StyleValue::Number(n)) => style.headings_flag = n,
+                (key, StyleValue::String(s)) => {
+                    if let Some(slot) = style.string_param(key) {
+                        *slot = s;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        style
+    }
+
+    fn string_param(&mut self, key: &str) -> Option<&mut String> {
+        Some(match key {
+            "keyword" => &mut self.keyword,
+            "preamble" => &mut self.preamble,
+            "postamble" => &mut self.postamble,
+            "group_skip" => &mut self.group_skip,
+            "heading_prefix" => &mut self.heading_prefix,
+            "heading_suffix" => &mut self.heading_suffix,
+            "symhead_positive" => &mut self.symhead_positive,
+            "symhead_negative" => &mut self.symhead_negative,
+            "numhead_positive" => &mut self.numhead_positive,
+            "numhead_negative" => &mut self.numhead_negative,
+            "item_0" => &mut self.item[0],
+            "item_1" => &mut self.item[1],
+            "item_2" => &mut self.item[2],
+            "item_01" => &mut self.item_01,
+            "item_x1" => &mut self.item_x1,
+            "item_12" => &mut self.item_12,
+            "item_x2" => &mut self.item_x2,
+            "delim_0" => &mut self.delim[0],
+            "delim_1" => &mut self.delim[1],
+            "delim_2" => &mut self.delim[2],
+            "delim_n" => &mut self.delim_n,
+            "delim_r" => &mut self.delim_r,
+            "delim_t" => &mut self.delim_t,
+            "encap_prefix" => &mut self.encap_prefix,
+            "encap_infix" => &mut self.encap_infix,
+            "encap_suffix" => &mut self.encap_suffix,
+            _ => return None,
+        })
+    }
+}
+
+/// The value of a style file parameter.
+enum StyleValue {
+    Char(char),
+    String(String),
+    Number(i32),
+}
+
+/// Parse the next `key value` pair of a style file, skipping whitespace and
+/// `%` comments. Returns the key, the value, and the rest of the text.
+fn next_style_param(mut text: &str) -> Option<(&str, StyleValue, &str)> {
+    loop {
+        text = text.trim_start();
+
+        if let Some(comment) = text.strip_prefix('%') {
+            text = comment.split_once('\n').map_or("", |(_, rest)| rest);
+            continue;
+        }
+
+        if text.is_empty() {
+            return None;
+        }
+
+        let key_len = text.find(|c: char| c.is_whitespace()).unwrap_or(text.len());
+        let (key, rest) = text.split_at(key_len);
+        let rest = rest.trim_start();
+        let mut chars = rest.chars();
+
+        let parsed = match chars.next() {
+            Some('\'') => {
+                let c = match chars.next() {
+                    Some('\\') => unescape(chars.next()?),
+                    c => c?,
+                };
+                chars
+                    .next()
+                    .filter(|&q| q == '\'')
+                    .map(|_| (StyleValue::Char(c), chars.as_str()))
+            }
+            Some('"') => {
+                let mut value = String::new();
+                let mut closed = false;
+
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => {
+                            closed = true;
+                            break;
+                        }
+                        '\\' => value.push(unescape(chars.next()?)),
+                        c => value.push(c),
+                    }
+                }
+
+                closed.then_some((StyleValue::String(value), chars.as_str()))
+            }
+            _ => {
+                let num_len = rest.find(|c: char| c.is_whitespace()).unwrap_or(rest.len());
+                rest[..num_len]
+                    .parse()
+                    .ok()
+                    .map(|n| (StyleValue::Number(n), &rest[num_len..]))
+            }
+        };
+
+        match parsed {
+            Some((value, rest)) => return Some((key, value, rest)),
+            // Skip the rest of a line that we can't understand.
+            None => text = rest.split_once('\n').map_or("", |(_, rest)| rest),
+        }
+    }
+}
+
+/// Interpret a character that follows a backslash in a style file string.
+fn unescape(c: char) -> char {
+    match c {
+        'n' => '\n',
+        't' => '\t',
+        c => c,
+    }
+}
+
+/// The results of indexing an `.idx` file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MakeindexOutput {
+    /// The contents of the `.ind` file.
+    pub ind: Vec<u8>,
+
+    /// The contents of the `.ilg` transcript.
+    pub ilg: Vec<u8>,
+
+    /// The number of `\indexentry` commands that couldn't be understood.
+    pub rejected: usize,
+}
+
+/// A struct for running the builtin `makeindex`.
+#[derive(Debug, Default)]
+pub struct MakeindexEngine {
+    style: IndexStyle,
+    letter_ordering: bool,
+}
+
+impl MakeindexEngine {
+    /// Set the style in which the index is read and written.
+    ///
+    /// The default is that of `makeindex` without a style file.
+    pub fn style(&mut self, style: IndexStyle) -> &mut Self {
+        self.style = style;
+        self
+    }
+
+    /// Set whether entries are sorted letter by letter, ignoring spaces, as
+    /// with the `-l` option of `makeindex`.
+    ///
+    /// The default is false.
+    pub fn letter_ordering(&mut self, letter_ordering: bool) -> &mut Self {
+        self.letter_ordering = letter_ordering;
+        self
+    }
+
+    /// Process the contents of the `.idx` file *idx_name*.
+    ///
+    /// The names are only used in the transcript.
+    pub fn process(&mut self, idx_name: &str, ind_name: &str, idx: &[u8]) -> MakeindexOutput {
+        let text = String::from_utf8_lossy(idx);
+        let mut ilg = String::from("This is the Tectonic builtin makeindex.\n");
+        let mut entries = Vec::new();
+        let mut rejected = 0;
+
+        for (lineno, line) in text.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            match parse_index_entry(line, entries.len(), &self.style) {
+                Some(mut entry) => {
+                    if self.letter_ordering {
+                        for level in &mut entry.levels {
+                            level.key.retain(|c| c != ' ');
+                        }
+                    }
+
+                    entries.push(entry);
+                }
+                None => {
+                    rejected += 1;
+                    let _ = writeln!(
+                        ilg,
+                        "!! Input index error (file = {idx_name}, line = {}):\n   -- Can't understand `{line}'.",
+                        lineno + 1
+                    );
+                }
+            }
+        }
+
+        let _ = writeln!(
+            ilg,
+            "Scanning input file {idx_name}...done ({} entries accepted, {rejected} rejected).",
+            entries.len()
+        );
+
+        entries.sort_by(Entry::compare);
+        let ind = write_index(&entries, &self.style);
+        let _ = writeln!(
+            ilg,
+            "Generating output file {ind_name}...done ({} lines written).",
+            ind.lines().count()
+        );
+
+        MakeindexOutput {
+            ind: ind.into_bytes(),
+            ilg: ilg.into_bytes(),
+            rejected,
+        }
+    }
+}
+
+/// One level of an index entry: the text to print, and the key to sort it by.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Level {
+    key: String,
+    text: String,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum PageKind {
+    Single,
+    RangeOpen,
+    RangeClose,
+}
+
+#[derive(Clone, Debug)]
+struct Entry {
+    levels: Vec<Level>,
+    page: String,
+    kind: PageKind,
+    encap: Option<String>,
+    order: usize,
+}
+
+impl Entry {
+    fn compare(a: &Entry, b: &Entry) -> Ordering {
+        for (la, lb) in a.levels.iter().zip(&b.levels) {
+            let ord = compare_keys(&la.key, &lb.key).then_with(|| la.text.cmp(&lb.text));
+
+            if ord != Ordering::Equal {
+                return ord;
+            }
+        }
+
+        a.levels
+            .len()
+            .cmp(&b.levels.len())
+            .then_with(|| page_key(&a.page).cmp(&page_key(&b.page)))
+            .then_with(|| a.order.cmp(&b.order))
+    }
+}
+
+/// Parse one `\indexentry{<entry>}{<page>}` line, or its equivalent with
+/// the keyword of the style.
+fn parse_index_entry(line: &str, order: usize, style: &IndexStyle) -> Option<Entry> {
+    let rest = line.strip_prefix(style.keyword.as_str())?;
+    let (entry, rest) = braced_arg(rest, style.quote)?;
+    let (page, rest) = braced_arg(rest, style.quote)?;
+
+    if !rest.trim().is_empty() || page.trim().is_empty() {
+        return None;
+    }
+
+    let (levels, encap) = parse_entry(entry, style)?;
+    let (kind, encap) = match encap {
+        None => (PageKind::Single, None),
+        Some(e) => {
+            let (kind, cmd) = if let Some(cmd) = e.strip_prefix('(') {
+                (PageKind::RangeOpen, cmd)
+            } else if let Some(cmd) = e.strip_prefix(')') {
+                (PageKind::RangeClose, cmd)
+            } else {
+                (PageKind::Single, e.as_str())
+            };
+            (kind, Some(cmd.to_owned()).filter(|c| !c.is_empty()))
+        }
+    };
+
+    Some(Entry {
+        levels,
+        page: page.trim().to_owned(),
+        kind,
+        encap,
+        order,
+    })
+}
+
+/// Split a braced argument off the start of `text`, honoring nested braces
+/// and the quote character.
+fn braced_arg(text: &str, quote: char) -> Option<(&str, &str)> {
+    let text = text.trim_start().strip_prefix('{')?;
+    let mut depth = 0;
+    let mut quoted = false;
+    let mut prev = '\0';
+
+    for (i, c) in text.char_indices() {
+        if quoted {
+            quoted = false;
+        } else if c == quote && prev != '\\' {
+            quoted = true;
+        } else if c == '{' {
+            depth += 1;
+        } else if c == '}' {
+            if depth == 0 {
+                return Some((&text[..i], &text[i + 1..]));
+            }
+            depth -= 1;
+        }
+
+        prev = c;
+    }
+
+    None
+}
+
+/// Split an entry into its levels and its page encapsulator. The special
+/// characters only count outside of braces, and lose their meaning after the
+/// quote character, which is dropped, unless it follows a backslash.
+fn parse_entry(entry: &str, style: &IndexStyle) -> Option<(Vec<Level>, Option<String>)> {
+    let mut levels = Vec::new();
+    let mut key = None;
+    let mut cur = String::new();
+    let mut depth = 0;
+    let mut chars = entry.chars();
+    let mut encap = None;
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c == style.quote && !cur.ends_with('\\') => {
+                if let Some(next) = chars.next() {
+                    cur.push(next);
+                }
+            }
+            '{' => {
+                depth += 1;
+                cur.push(c);
+            }
+            '}' => {
+                depth -= 1;
+                cur.push(c);
+            }
+            c if c == style.actual && depth == 0 && key.is_none() => {
+                key = Some(std::mem::take(&mut cur))
+            }
+            c if c == style.level && depth == 0 => levels.push(finish_level(key.take(), &mut cur)?),
+            c if c == style.encap && depth == 0 => {
+                encap = Some(unquote(chars.as_str(), style.quote));
+                break;
+            }
+            _ => cur.push(c),
+        }
+    }
+
+    levels.push(finish_level(key, &mut cur)?);
+
+    if levels.len() > 3 {
+        return None;
+    }
+
+    Some((levels, encap))
+}
+
+/// Drop the quote characters from a page encapsulator, keeping the
+/// characters that they quote, unless they follow a backslash.
+fn unquote(text: &str, quote: char) -> String {
+    let mut out = String::new();
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        if c == quote && !out.ends_with('\\') {
+            if let Some(next) = chars.next() {
+                out.push(next);
+            }
+        } else {
+            out.push(c);
+        }
+    }
+
+    out
+}
+
+fn finish_level(key: Option<String>, cur: &mut String) -> Option<Level> {
+    let text = std::mem::take(cur).trim().to_owned();
+    let key = key
+        .map(|k| k.trim().to_owned())
+        .unwrap_or_else(|| text.clone());
+
+    if key.is_empty() {
+        return None;
+    }
+
+    Some(Level { key, text })
+}
+
+/// The groups into which the index is divided: symbols, then numbers, then
+/// one for each letter.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+enum Group {
+    Symbols,
+    Numbers,
+    Letter(char),
+}
+
+fn group_of(key: &str) -> Group {
+    match key.chars().next() {
+        Some(c) if c.is_alphabetic() => Group::Letter(c.to_lowercase().next().unwrap_or(c)),
+        Some(c) if c.is_ascii_digit() => Group::Numbers,
+        _ => Group::Symbols,
+    }
+}
+
+/// Compare sort keys the way makeindex does by default: by group, with whole
+/// numbers in numerical order, and otherwise ignoring case, with lowercase
+/// before uppercase in case of a tie.
+fn compare_keys(a: &str, b: &str) -> Ordering {
+    let ord = group_of(a).cmp(&group_of(b));
+
+    if ord != Ordering::Equal {
+        return ord;
+    }
+
+    if let (Ok(na), Ok(nb)) = (a.parse::<u64>(), b.parse::<u64>()) {
+        let ord = na.cmp(&nb);
+
+        if ord != Ordering::Equal {
+            return ord;
+        }
+    }
+
+    a.to_lowercase()
+        .cmp(&b.to_lowercase())
+        .then_with(|| b.cmp(a))
+}
+
+/// The sort key of a page number: lowercase roman numerals come first, then
+/// arabic numbers, then anything else in string order.
+fn page_key(page: &str) -> (u8, u64, &str) {
+    if let Some(n) = roman_value(page) {
+        (0, n, page)
+    } else if let Ok(n) = page.parse::<u64>() {
+        (1, n, page)
+    } else {
+        (2, 0, page)
+    }
+}
+
+fn roman_value(page: &str) -> Option<u64> {
+    if page.is_empty() {
+        return None;
+    }
+
+    let mut total = 0;
+    let mut prev = 0;
+
+    for c in page.chars().rev() {
+        let v = match c {
+            'i' => 1,
+            'v' => 5,
+            'x' => 10,
+            'l' => 50,
+            'c' => 100,
+            'd' => 500,
+            'm' => 1000,
+            _ => return None,
+        };
+
+        if v < prev {
+            total -= v;
+        } else {
+            total += v;
+            prev = v;
+        }
+    }
+
+    u64::try_from(total).ok()
+}
+
+fn format_page(page: &str, encap: &Option<String>, style: &IndexStyle) -> String {
+    match encap {
+        Some(cmd) => format!(
+            "{}{cmd}{}{page}{}",
+            style.encap_prefix, style.encap_infix, style.encap_suffix
+        ),
+        None => page.to_owned(),
+    }
+}
+
+/// Format the page list of entries that all have the same levels, which are
+/// sorted by page. Explicit ranges are kept, and runs of three or more
+/// consecutive pages with the same encapsulator become implicit ones.
+fn format_pages(entries: &[&Entry], style: &IndexStyle) -> String {
+    let mut parts = Vec::new();
+    let mut i = 0;
+
+    while i < entries.len() {
+        let e = entries[i];
+
+        if e.kind == PageKind::RangeOpen {
+            if let Some(j) = (i + 1..entries.len())
+                .find(|&j| entries[j].kind == PageKind::RangeClose && entries[j].encap == e.encap)
+            {
+                let end = &entries[j].page;
+
+                if *end == e.page {
+                    parts.push(format_page(&e.page, &e.encap, style));
+                } else {
+                    parts.push(format!(
+                        "{}{}{}",
+                        format_page(&e.page, &e.encap, style),
+                        style.delim_r,
+                        format_page(end, &e.encap, style)
+                    ));
+                }
+
+                i = j + 1;
+                continue;
+            }
+        }
+
+        let mut last = e.page.parse::<u64>().ok();
+        let mut end = i;
+        let mut distinct = 1;
+
+        if e.kind == PageKind::Single {
+            while let (Some(prev), Some(next)) = (last, entries.get(end + 1)) {
+                if next.kind != PageKind::Single || next.encap != e.encap {
+                    break;
+                }
+
+                match next.page.parse::<u64>() {
+                    Ok(n) if n == prev => {}
+                    Ok(n) if n == prev + 1 => distinct += 1,
+                    _ => break,
+                }
+
+                last = Some(next.page.parse().unwrap_or(prev));
+                end += 1;
+            }
+        }
+
+        if distinct >= 3 {
+            parts.push(format!(
+                "{}{}{}",
+                format_page(&e.page, &e.encap, style),
+                style.delim_r,
+                format_page(&entries[end].page, &e.encap, style)
+            ));
+        } else {
+            for (k, p) in entries[i..=end].iter().enumerate() {
+                let dup = k > 0 && p.page == entries[i + k - 1].page;
+
+                if !dup {
+                    parts.push(format_page(&p.page, &p.encap, style));
+                }
+            }
+        }
+
+        i = end + 1;
+    }
+
+    parts.dedup();
+    parts.join(&style.delim_n)
+}
+
+/// The heading of a group, for styles that ask for them.
+fn group_heading(group: Group, style: &IndexStyle) -> String {
+    let positive = style.headings_flag > 0;
+
+    match group {
+        Group::Symbols if positive => style.symhead_positive.clone(),
+        Group::Symbols => style.symhead_negative.clone(),
+        Group::Numbers if positive => style.numhead_positive.clone(),
+        Group::Numbers => style.numhead_negative.clone(),
+        Group::Letter(c) if positive => c.to_uppercase().collect(),
+        Group::Letter(c) => c.to_string(),
+    }
+}
+
+/// Write out the sorted entries, by default as a `theindex` environment.
+fn write_index(entries: &[Entry], style: &IndexStyle) -> String {
+    let mut out = style.preamble.clone();
+    let mut prev_levels: &[Level] = &[];
+    let mut prev_group = None;
+    // The depth of the last item written, and whether it had pages.
+    let mut prev_item = None;
+    let mut i = 0;
+
+    while i < entries.len() {
+        let levels = &entries[i].levels;
+        let mut j = i + 1;
+
+        while j < entries.len() && entries[j].levels == *levels {
+            j += 1;
+        }
+
+        let group = group_of(&levels[0].key);
+
+        if prev_group != Some(group) {
+            if prev_group.is_some() {
+                out.push_str(&style.group_skip);
+            }
+
+            if style.headings_flag != 0 {
+                out.push_str(&style.heading_prefix);
+                out.push_str(&group_heading(group, style));
+                out.push_str(&style.heading_suffix);
+            }
+        }
+
+        prev_group = Some(group);
+
+        let first_new = levels
+            .iter()
+            .zip(prev_levels)
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        for (depth, level) in levels.iter().enumerate().skip(first_new) {
+            let item = match (depth, prev_item) {
+                (1, Some((0, true))) => &style.item_01,
+                (1, Some((0, false))) => &style.item_x1,
+                (2, Some((1, true))) => &style.item_12,
+                (2, Some((1, false))) => &style.item_x2,
+                _ => &style.item[depth],
+            };
+
+            out.push_str(item);
+            out.push_str(&level.text);
+            prev_item = Some((depth, depth == levels.len() - 1));
+        }
+
+        let group_entries: Vec<&Entry> = entries[i..j].iter().collect();
+        out.push_str(&style.delim[levels.len() - 1]);
+        out.push_str(&format_pages(&group_entries, style));
+        out.push_str(&style.delim_t);
+
+        prev_levels = levels;
+        i = j;
+    }
+
+    out.push_str(&style.postamble);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index(idx: &str) -> String {
+        let out = MakeindexEngine::default().process("t.idx", "t.ind", idx.as_bytes());
+        String::from_utf8(out.ind).unwrap()
+    }
+
+    #[test]
+    fn sorting_and_groups() {
+        assert_eq!(
+            index(
+                "\\indexentry{beta}{2}\n\\indexentry{Alpha}{1}\n\\indexentry{alpha}{3}\n\
+                 \\indexentry{$x$}{4}\n\\indexentry{bravo}{5}\n"
+            ),
+            "\\begin{theindex}\n\n  \\item $x$, 4\n\n  \\indexspace\n\n  \\item alpha, 3\
+             \n  \\item Alpha, 1\n\n  \\indexspace\n\n  \\item beta, 2\n  \\item bravo, 5\
+             \n\n\\end{theindex}\n"
+        );
+    }
+
+    #[test]
+    fn subentries_and_keys() {
+        assert_eq!(
+            index(
+                "\\indexentry{fruit!pear}{2}\n\\indexentry{fruit!apple}{7}\n\
+                 \\indexentry{zeta@$\\zeta$}{1}\n\\indexentry{fruit}{3}\n"
+            ),
+            "\\begin{theindex}\n\n  \\item fruit, 3\n    \\subitem apple, 7\
+             \n    \\subitem pear, 2\n\n  \\indexspace\n\n  \\item $\\zeta$, 1\
+             \n\n\\end{theindex}\n"
+        );
+    }
+
+    #[test]
+    fn page_lists() {
+        assert_eq!(
+            index(
+                "\\indexentry{a}{3}\n\\indexentry{a}{1}\n\\indexentry{a}{2}\n\
+                 \\indexentry{a}{2}\n\\indexentry{a}{iv}\n\\indexentry{a}{9}\n\
+                 \\indexentry{a|textbf}{10}\n\\indexentry{b|(}{4}\n\\indexentry{b|)}{8}\n\
+                 \\indexentry{c|see{a}}{1}\n"
+            ),
+            "\\begin{theindex}\n\n  \\item a, iv, 1--3, 9, \\textbf{10}\n\n  \\indexspace\n\
+             \n  \\item b, 4--8\n\n  \\indexspace\n\n  \\item c, \\see{a}{1}\n\n\\end{theindex}\n"
+        );
+    }
+
+    #[test]
+    fn quoting() {
+        assert_eq!(
+            index("\\indexentry{a\"!b}{1}\n\\indexentry{\\\"o}{2}\n"),
+            "\\begin{theindex}\n\n  \\item \\\"o, 2\n\n  \\indexspace\n\n  \\item a!b, 1\
+             \n\n\\end{theindex}\n"
+        );
+    }
+
+    #[test]
+    fn glossaries_style() {
+        // As written by the `glossaries` package.
+        const IST: &str = r#"% makeindex style file created by the glossaries package
+actual '?'
+encap '|'
+level '!'
+quote '"'
+keyword "\\glossaryentry"
+preamble "\\glossarysection[\\glossarytoctitle]{\\glossarytitle}\\glossarypreamble\n\\begin{theglossary}\\glossaryheader\n"
+postamble "\%\n\\end{theglossary}\\glossarypostamble\n"
+group_skip "\\glsgroupskip\n"
+item_0 "\%\n"
+item_1 "\%\n"
+item_2 "\%\n"
+item_01 "\%\n"
+item_x1 "\\relax \\glsresetentrylist\n"
+item_12 "\%\n"
+item_x2 "\\relax \\glsresetentrylist\n"
+delim_0 "\{\\glossaryentrynumbers\{\\relax "
+delim_1 "\{\\glossaryentrynumbers\{\\relax "
+delim_2 "\{\\glossaryentrynumbers\{\\relax "
+delim_t "\}\}"
+delim_n "\\delimN "
+delim_r "\\delimR "
+headings_flag 1
+heading_prefix "\\glsgroupheading\{"
+heading_suffix "\}\\relax \\glsresetentrylist "
+symhead_positive "glssymbols"
+numhead_positive "glsnumbers"
+page_compositor "."
+"#;
+
+        let glo = "\\glossaryentry{zebra?\\glossentry{zebra}|setentrycounter[]{page}\"\\glsnumberformat}{2}\n\
+                   \\glossaryentry{ant?\\glossentry{ant}|setentrycounter[]{page}\"\\glsnumberformat}{1}\n\
+                   \\glossaryentry{ant?\\glossentry{ant}|setentrycounter[]{page}\"\\glsnumberformat}{3}\n";
+
+        let out = MakeindexEngine::default()
+            .style(IndexStyle::parse(IST))
+            .process("t.glo", "t.gls", glo.as_bytes());
+        assert_eq!(out.rejected, 0);
+        assert_eq!(
+            String::from_utf8(out.ind).unwrap(),
+            "\\glossarysection[\\glossarytoctitle]{\\glossarytitle}\\glossarypreamble\n\
+             \\begin{theglossary}\\glossaryheader\n\
+             \\glsgroupheading{A}\\relax \\glsresetentrylist %\n\
+             \\glossentry{ant}{\\glossaryentrynumbers{\\relax \
+             \\setentrycounter[]{page}\\glsnumberformat{1}\\delimN \
+             \\setentrycounter[]{page}\\glsnumberformat{3}}}\
+             \\glsgroupskip\n\
+             \\glsgroupheading{Z}\\relax \\glsresetentrylist %\n\
+             \\glossentry{zebra}{\\glossaryentrynumbers{\\relax \
+             \\setentrycounter[]{page}\\glsnumberformat{2}}}%\n\
+             \\end{theglossary}\\glossarypostamble\n"
+        );
+    }
+
+    #[test]
+    fn letter_ordering() {
+        let idx = "\\indexentry{sea lion}{1}\n\\indexentry{seal}{2}\n";
+        assert!(index(idx).contains("sea lion, 1\n  \\item seal, 2"));
+
+        let out = MakeindexEngine::default().letter_ordering(true).process(
+            "t.idx",
+            "t.ind",
+            idx.as_bytes(),
+        );
+        assert!(String::from_utf8(out.ind)
+            .unwrap()
+            .contains("seal, 2\n  \\item sea lion, 1"));
+    }
+
+    #[test]
+    fn rejected() {
+        let out =
+            MakeindexEngine::default().process("t.idx", "t.ind", b"\\indexentry{a}\nnonsense\n");
+        assert_eq!(out.rejected, 2);
+        assert_eq!(out.ind, b"\\begin{theindex}\n\n\n\\end{theindex}\n");
+    }
+}
@@ -4,9 +4,12 @@
 
 //! Engine for invoking `XeTeX`.
 
-use crate::errors::DefinitelySame;
+use crate::{
+    errors::DefinitelySame,
+    status::{MessageKind, SourceDiagnostic},
+};
 
-pub use tectonic_engine_xetex::{TexEngine, TexOutcome};
+pub use tectonic_engine_xetex::{InteractionMode, MemoryParameter, TexEngine, TexOutcome};
 
 // Sigh, have to do this manually because of the Result/PartialEq conflict in errors.rs
 impl DefinitelySame for TexOutcome {
@@ -14,3 +17,492 @@ impl DefinitelySame for TexOutcome {
         self == other
     }
 }
+
+/// The width at which the engine wraps the lines of its log.
+const MAX_PRINT_LINE: usize = 79;
+
+/// Find the errors in a log written by the TeX engine.
+///
+/// Each error starts with a line beginning with `! `, followed by the context
+/// of the error: pairs of lines showing what TeX had read, and was still to
+/// read, of each level of its input. The last pair shows the input line, as
+/// `l.<number> ...`. Then comes the help text, which ends with an empty line.
+/// The file containing the input line is found by following the parentheses
+/// that the engine prints around the name of each file that it reads.
+pub fn parse_log_diagnostics(log: &[u8]) -> Vec<SourceDiagnostic> {
+    let log = String::from_utf8_lossy(log);
+    let lines: Vec<&str> = log.lines().collect();
+    let mut files = FileTracker::default();
+    let mut diagnostics = Vec::new();
+    let mut i = 0;
+
+    while i < lines.len() {
+        let Some(message) = lines[i].strip_prefix("! ") else {
+            files.scan(lines[i]);
+            i += 1;
+            continue;
+        };
+
+        let mut message = message.to_owned();
+        let mut wrapped = lines[i].chars().count() == MAX_PRINT_LINE;
+        i += 1;
+
+        while wrapped && i < lines.len() {
+            message.push_str(lines[i]);
+            wrapped = lines[i].chars().count() == MAX_PRINT_LINE;
+            i += 1;
+        }
+
+        let mut diag = SourceDiagnostic {
+            kind: MessageKind::Error,
+            file: None,
+            line: None,
+            message: message.trim_end().to_owned(),
+            context: Vec::new(),
+            source_line: None,
+        };
+
+        while let Some(&first) = lines.get(i) {
+            if first.is_empty() {
+                break;
+            }
+
+            i += 1;
+            diag.context.push(first.trim_end().to_owned());
+
+            // This marks levels left out because of `\errorcontextlines`.
+            if first == "..." {
+                continue;
+            }
+
+            if let Some(&second) = lines.get(i) {
+                i += 1;
+
+                if !second.trim().is_empty() {
+                    diag.context.push(second.trim_end().to_owned());
+                }
+            }
+
+            if let Some(number) = input_line_number(first) {
+                diag.file = files.current();
+                diag.line = Some(number);
+                break;
+            }
+
+            // Terminal input, which is also the bottom level.
+            if first.starts_with("<*>") {
+                break;
+            }
+        }
+
+        while i < lines.len() && !lines[i].is_empty() {
+            i += 1;
+        }
+
+        diagnostics.push(diag);
+    }
+
+    diagnostics
+}
+
+/// The kinds of entries in the trace that the TeX engine writes to its log
+/// when the `\tracing...` parameters are set.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TraceKind {
+    /// The expansion of a macro, from `\tracingmacros`.
+    Macro,
+
+    /// A command being executed, from `\tracingcommands`.
+    Command,
+
+    /// A change to a register or definition, from `\tracingassigns`.
+    Assignment,
+
+    /// A group being entered or left, from `\tracinggroups`.
+    Group,
+
+    /// A conditional being evaluated, from `\tracingifs`.
+    Conditional,
+}
+
+impl TraceKind {
+    fn name(self) -> &'static str {
+        match self {
+            TraceKind::Macro => "macro",
+            TraceKind::Command => "command",
+            TraceKind::Assignment => "assignment",
+            TraceKind::Group => "group",
+            TraceKind::Conditional => "conditional",
+        }
+    }
+}
+
+/// One entry of the trace in a TeX log.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TraceEntry {
+    /// What kind of entry this is.
+    pub kind: TraceKind,
+
+    /// The file that the engine was reading, if known.
+    pub file: Option<String>,
+
+    /// The text of the entry, without the braces around those that have them.
+    /// For a macro, this is its name, parameters, and replacement text.
+    pub text: String,
+
+    /// For a macro, the arguments that it was given.
+    pub args: Vec<String>,
+}
+
+impl TraceEntry {
+    /// Express this entry as a single-line JSON object.
+    pub fn to_json(&self) -> String {
+        let mut json = format!(
+            "{{\"kind\":{},\"file\":{},\"text\":{}",
+            json_string(self.kind.name()),
+            self.file.as_deref().map_or("null".to_owned(), json_string),
+            json_string(&self.text),
+        );
+
+        if self.kind == TraceKind::Macro {
+            let args: Vec<String> = self.args.iter().map(|a| json_string(a)).collect();
+            json.push_str(&format!(",\"args\":[{}]", args.join(",")));
+        }
+
+        json.push('}');
+        json
+    }
+}
+
+/// Find the trace that the TeX engine wrote to its log.
+///
+/// Macro expansions are lines of the form `\name<parameters>->replacement`,
+/// followed by a line `#<n><-<argument>` for each argument. The other kinds of
+/// entries are lines in braces, such as `{vertical mode: \par}` or `{entering
+/// simple group (level 1) at line 3}`. Entries can be wrapped over several
+/// lines. The contexts of errors look like macro expansions too, so they're
+/// skipped.
+pub fn parse_log_trace(log: &[u8]) -> Vec<TraceEntry> {
+    let log = String::from_utf8_lossy(log);
+    let lines: Vec<&str> = log.lines().collect();
+    let mut files = FileTracker::default();
+    let mut entries: Vec<TraceEntry> = Vec::new();
+    let mut i = 0;
+
+    // Join a line with the ones that it was wrapped onto, returning the text
+    // and the index of the line after them.
+    let unwrap = |mut i: usize| {
+        let mut text = lines[i].to_owned();
+        let mut wrapped = lines[i].chars().count() == MAX_PRINT_LINE;
+        i += 1;
+
+        while wrapped && i < lines.len() {
+            text.push_str(lines[i]);
+            wrapped = lines[i].chars().count() == MAX_PRINT_LINE;
+            i += 1;
+        }
+
+        (text, i)
+    };
+
+    while i < lines.len() {
+        let line = lines[i];
+
+        if line.starts_with("! ") {
+            while i < lines.len()
+                && input_line_number(lines[i]).is_none()
+                && !lines[i].starts_with("<*>")
+            {
+                i += 1;
+            }
+
+            while i < lines.len() && !lines[i].is_empty() {
+                i += 1;
+            }
+
+            continue;
+        }
+
+        if line.starts_with('{') {
+            let (text, next) = unwrap(i);
+
+            if let Some(inner) = text.strip_prefix('{').and_then(|t| t.strip_suffix('}')) {
+                entries.push(TraceEntry {
+                    kind: brace_entry_kind(inner),
+                    file: files.current(),
+                    text: inner.to_owned(),
+                    args: Vec::new(),
+                });
+                i = next;
+                continue;
+            }
+        } else if is_macro_expansion(line) {
+            let (text, next) = unwrap(i);
+            entries.push(TraceEntry {
+                kind: TraceKind::Macro,
+                file: files.current(),
+                text,
+                args: Vec::new(),
+            });
+            i = next;
+            continue;
+        } else if macro_argument(line).is_some() {
+            if let Some(entry) = entries.last_mut().filter(|e| e.kind == TraceKind::Macro) {
+                let (text, next) = unwrap(i);
+                entry.args.extend(macro_argument(&text).map(str::to_owned));
+                i = next;
+                continue;
+            }
+        }
+
+        files.scan(line);
+        i += 1;
+    }
+
+    entries
+}
+
+/// Does this log line start the expansion of a macro, as `\name ...->`?
+fn is_macro_expansion(line: &str) -> bool {
+    let Some(rest) = line.strip_prefix('\\') else {
+        return false;
+    };
+
+    let Some((head, _)) = rest.split_once("->") else {
+        return false;
+    };
+
+    // The name is either letters or a single other character, and is followed
+    // by the parameter text, which doesn't contain braces.
+    !head.is_empty() && !head.contains(['{', '}'])
+}
+
+/// Get the argument from a log line of the form `#<n><-<argument>`.
+fn macro_argument(line: &str) -> Option<&str> {
+    let rest = line.strip_prefix('#')?;
+    let digit = rest.chars().next().filter(|c| c.is_ascii_digit())?;
+    rest[digit.len_utf8()..].strip_prefix("<-")
+}
+
+/// Classify an entry of the trace that's written in braces.
+fn brace_entry_kind(text: &str) -> TraceKind {
+    let text = text.strip_prefix("globally ").unwrap_or(text);
+
+    if ["changing ", "into ", "reassigning "]
+        .iter()
+        .any(|p| text.starts_with(p))
+    {
+        TraceKind::Assignment
+    } else if text.starts_with("entering ") || text.starts_with("leaving ") {
+        TraceKind::Group
+    } else if text == "true"
+        || text == "false"
+        || (text.starts_with("\\if") || text.starts_with("\\unless") || text.starts_with("\\fi"))
+            && text.contains("(level ")
+    {
+        TraceKind::Conditional
+    } else {
+        TraceKind::Command
+    }
+}
+
+/// Quote a string for JSON.
+fn json_string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+
+    quoted.push('"');
+    quoted
+}
+
+/// Get the number from a context line of the form `l.<number> ...`.
+fn input_line_number(line: &str) -> Option<u32> {
+    let rest = line.strip_prefix("l.")?;
+    let end = rest.find(|c: char| !c.is_ascii_digit())?;
+    rest[..end].parse().ok()
+}
+
+/// Follows which file the engine was reading, from the parentheses in its log.
+#[derive(Debug, Default)]
+struct FileTracker {
+    /// For each open parenthesis, the name of the file that it opened, if any.
+    stack: Vec<Option<String>>,
+
+    /// A name that was cut off at the end of the previous line.
+    partial: Option<String>,
+}
+
+impl FileTracker {
+    fn current(&self) -> Option<String> {
+        self.stack.iter().rev().flatten().next().cloned()
+    }
+
+    fn scan(&mut self, line: &str) {
+        let mut name = self.partial.take();
+
+        for c in line.chars() {
+            match (&mut name, c) {
+                (Some(n), c) if !c.is_whitespace() && c != '(' && c != ')' => {
+                    n.push(c);
+                    continue;
+                }
+                (Some(_), _) => self.open(name.take().unwrap()),
+                _ => {}
+            }
+
+            match c {
+                '(' => name = Some(String::new()),
+                ')' => {
+                    self.stack.pop();
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(n) = name {
+            if line.chars().count() == MAX_PRINT_LINE {
+                self.partial = Some(n);
+            } else {
+                self.open(n);
+            }
+        }
+    }
+
+    fn open(&mut self, name: String) {
+        // Parentheses also appear in ordinary text, so only take names that
+        // look like those of files.
+        let is_file = name.contains('.') || name.contains('/');
+        self.stack.push(is_file.then_some(name));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn undefined_control_sequence() {
+        let log =
+            b"This is XeTeX, Version 3.141592653-2.6-0.999996 (preloaded format=plain 2024.2.1)\n\
+            (./doc.tex (./chapter.tex [1]\n\
+            ! Undefined control sequence.\n\
+            l.5 Some \\foo\n\
+            \x20            bar\n\
+            The control sequence at the end of the top line\n\
+            and I'll forget about whatever was undefined.\n\
+            \n\
+            ) [2] )\n";
+
+        assert_eq!(
+            parse_log_diagnostics(log),
+            vec![SourceDiagnostic {
+                kind: MessageKind::Error,
+                file: Some("./chapter.tex".to_owned()),
+                line: Some(5),
+                message: "Undefined control sequence.".to_owned(),
+                context: vec!["l.5 Some \\foo".to_owned(), "             bar".to_owned()],
+                source_line: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn macro_context_and_closed_files() {
+        let log = b"(./doc.tex (./defs.tex) (see the manual)\n\
+            ! Missing $ inserted.\n\
+            <inserted text> \n\
+            \x20               $\n\
+            \\mac #1->x^#1\n\
+            \x20            \n\
+            l.12 \\mac{2}\n\
+            \x20            \n\
+            I've inserted a begin-math/end-math symbol (see the manual).\n\
+            \n\
+            ! Emergency stop.\n\
+            <*> doc.tex\n\
+            \x20          \n\
+            \n";
+
+        let diags = parse_log_diagnostics(log);
+        assert_eq!(diags.len(), 2);
+        assert_eq!(diags[0].file.as_deref(), Some("./doc.tex"));
+        assert_eq!(diags[0].line, Some(12));
+        assert_eq!(
+            diags[0].context,
+            [
+                "<inserted text>",
+                "                $",
+                "\\mac #1->x^#1",
+                "l.12 \\mac{2}"
+            ]
+        );
+        assert_eq!(diags[1].message, "Emergency stop.");
+        assert_eq!(diags[1].file, None);
+        assert_eq!(diags[1].line, None);
+        assert_eq!(diags[1].context, ["<*> doc.tex"]);
+    }
+
+    #[test]
+    fn trace() {
+        let log = b"(./doc.tex\n\
+            {vertical mode: \\greet}\n\
+            \n\
+            \\greet #1->Hello, #1!\n\
+            #1<-world\n\
+            {entering simple group (level 1) at line 3}\n\
+            {changing \\count255=0}\n\
+            {into \\count255=1}\n\
+            {\\ifnum: (level 1) entered on line 4}\n\
+            {true}\n\
+            ! Missing $ inserted.\n\
+            \\mac #1->x^#1\n\
+            l.12 \\mac{2}\n\
+            \n\
+            )\n";
+
+        let trace = parse_log_trace(log);
+        let kinds: Vec<_> = trace.iter().map(|e| e.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                TraceKind::Command,
+                TraceKind::Macro,
+                TraceKind::Group,
+                TraceKind::Assignment,
+                TraceKind::Assignment,
+                TraceKind::Conditional,
+                TraceKind::Conditional,
+            ]
+        );
+        assert_eq!(trace[1].args, ["world"]);
+        assert_eq!(
+            trace[1].to_json(),
+            r#"{"kind":"macro","file":"./doc.tex","text":"\\greet #1->Hello, #1!","args":["world"]}"#
+        );
+        assert_eq!(
+            trace[2].to_json(),
+            r#"{"kind":"group","file":"./doc.tex","text":"entering simple group (level 1) at line 3"}"#
+        );
+    }
+
+    #[test]
+    fn wrapped_file_name() {
+        let first = format!("({}", "a".repeat(MAX_PRINT_LINE - 1));
+        let log = format!("{first}\naa/b.tex\n! Oops.\nl.1 x\n    \n\n");
+        let diags = parse_log_diagnostics(log.as_bytes());
+        assert_eq!(
+            diags[0].file,
+            Some(format!("{}aa/b.tex", "a".repeat(MAX_PRINT_LINE - 1)))
+        );
+    }
+}
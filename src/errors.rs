@@ -100,6 +100,11 @@ error_chain! {
             description("some engine had an unrecoverable error")
             display("the {} engine had an unrecoverable error", engine)
         }
+
+        Cancelled {
+            description("the processing was cancelled")
+            display("the processing was cancelled")
+        }
     }
 }
 
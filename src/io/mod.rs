@@ -7,6 +7,7 @@ use tectonic_status_base::StatusBackend;
 
 pub mod format_cache;
 pub mod memory;
+pub mod zip;
 
 // Convenience re-exports.
 
@@ -22,6 +23,7 @@ pub use tectonic_io_base::{
 // Internal Reexports
 
 pub use self::memory::MemoryIo;
+pub use self::zip::ZipIo;
 
 // Helper for testing. FIXME: I want this to be conditionally compiled with
 // #[cfg(test)] but things break if I do that.
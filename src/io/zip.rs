@@ -0,0 +1,229 @@
+// Copyright 2024 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Input files served out of a ZIP archive.
+//!
+//! This lets a document be compiled straight from an archive of its sources,
+//! such as an arXiv submission, without unpacking it first.
+
+use std::{
+    fs::File,
+    io::{Cursor, Read, Seek},
+    path::Path,
+};
+use tectonic_errors::{anyhow::bail, atry, Result};
+use zip::{result::ZipError, ZipArchive};
+
+use super::{normalize_tex_path, InputHandle, InputOrigin, IoProvider, OpenResult};
+use crate::status::StatusBackend;
+
+/// An I/O provider that serves input files out of a ZIP archive.
+///
+/// Names are looked up relative to a root directory inside the archive, which
+/// is the top level unless changed with [`ZipIo::set_root`]. The provider
+/// never creates output files.
+pub struct ZipIo<R: Read + Seek = File> {
+    zip: ZipArchive<R>,
+    root: String,
+}
+
+impl ZipIo<File> {
+    /// Open a ZIP archive on the filesystem.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<ZipIo<File>> {
+        Self::new(File::open(path)?)
+    }
+}
+
+impl<R: Read + Seek> ZipIo<R> {
+    /// Create a provider for a generic readable and seekable stream.
+    pub fn new(reader: R) -> Result<ZipIo<R>> {
+        Ok(ZipIo {
+            zip: ZipArchive::new(reader)?,
+            root: String::new(),
+        })
+    }
+
+    /// Look up files relative to the directory *dir* inside the archive. This
+    /// should be the directory containing the main file, since that's what
+    /// the paths in its `\input` commands are relative to.
+    pub fn set_root(&mut self, dir: &str) {
+        self.root = dir.trim_end_matches('/').to_owned();
+
+        if !self.root.is_empty() {
+            self.root.push('/');
+        }
+    }
+
+    /// Read the whole of the file named *name*, relative to the top of the
+    /// archive.
+    pub fn read_file(&mut self, name: &str) -> Result<Vec<u8>> {
+        let mut item = self.zip.by_name(name)?;
+
+        if item.size() >= u32::MAX as u64 {
+            bail!("ZIP archive item `{}` is too large", name);
+        }
+
+        // The size comes from the archive's headers, which might be lying,
+        // so don't trust it with more than a modest preallocation.
+        let mut buf = Vec::with_capacity(item.size().min(1 << 20) as usize);
+        atry!(
+            item.read_to_end(&mut buf);
+            ["couldn't read `{}` from the ZIP archive", name]
+        );
+        Ok(buf)
+    }
+
+    /// Figure out which file in the archive is the main TeX file.
+    ///
+    /// If the archive holds only one `.tex` file, that's it. Otherwise, it's
+    /// the one that starts a document with `\documentclass` (or the LaTeX 2.09
+    /// `\documentstyle`), preferring files closer to the top of the archive.
+    /// It's an error if that doesn't narrow things down to a single file.
+    pub fn find_main_file(&mut self) -> Result<String> {
+        let tex_files: Vec<String> = self
+            .zip
+            .file_names()
+            .filter(|n| {
+                n.to_ascii_lowercase().ends_with(".tex")
+                    && !n.starts_with("__MACOSX/")
+                    && !n.ends_with('/')
+            })
+            .map(|n| n.to_owned())
+            .collect();
+
+        if let [only] = &tex_files[..] {
+            return Ok(only.clone());
+        }
+
+        let mut candidates = Vec::new();
+
+        for name in tex_files {
+            let text = self.read_file(&name)?;
+
+            if starts_document(&String::from_utf8_lossy(&text)) {
+                candidates.push(name);
+            }
+        }
+
+        let depth = |n: &String| n.matches('/').count();
+
+        if let Some(min_depth) = candidates.iter().map(depth).min() {
+            candidates.retain(|n| depth(n) == min_depth);
+        }
+
+        match &candidates[..] {
+            [main] => Ok(main.clone()),
+            [] => bail!("couldn't find a main TeX file in the ZIP archive"),
+            _ => bail!(
+                "couldn't tell which of these files in the ZIP archive is the main one: {}",
+                candidates.join(", ")
+            ),
+        }
+    }
+}
+
+/// Does this TeX source start a document? Commented-out lines don't count.
+fn starts_document(text: &str) -> bool {
+    text.lines().any(|line| {
+        let line = line.trim_start();
+        line.starts_with("\\documentclass") || line.starts_with("\\documentstyle")
+    })
+}
+
+impl<R: Read + Seek> IoProvider for ZipIo<R> {
+    fn input_open_name(
+        &mut self,
+        name: &str,
+        _status: &mut dyn StatusBackend,
+    ) -> OpenResult<InputHandle> {
+        let name = normalize_tex_path(name);
+
+        // Absolute paths and paths leading out of the root aren't in the
+        // archive.
+        if name.starts_with('/') || name.split('/').any(|c| c == "..") {
+            return OpenResult::NotAvailable;
+        }
+
+        let full_name = format!("{}{}", self.root, name);
+
+        match self.read_file(&full_name) {
+            Ok(buf) => OpenResult::Ok(InputHandle::new_read_only(
+                name,
+                Cursor::new(buf),
+                InputOrigin::Other,
+            )),
+            Err(e) => match e.downcast_ref::<ZipError>() {
+                Some(ZipError::FileNotFound) => OpenResult::NotAvailable,
+                _ => OpenResult::Err(e),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::status::NoopStatusBackend;
+    use std::io::Write;
+    use zip::{write::SimpleFileOptions, ZipWriter};
+
+    fn archive(files: &[(&str, &str)]) -> ZipIo<Cursor<Vec<u8>>> {
+        let mut w = ZipWriter::new(Cursor::new(Vec::new()));
+
+        for (name, text) in files {
+            w.start_file(*name, SimpleFileOptions::default()).unwrap();
+            w.write_all(text.as_bytes()).unwrap();
+        }
+
+        ZipIo::new(w.finish().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn main_file() {
+        let doc = "% \\documentclass{old}\n  \\documentclass{article}\n";
+
+        assert_eq!(
+            archive(&[("paper/ms.tex", "hi"), ("paper/fig.pdf", "")])
+                .find_main_file()
+                .unwrap(),
+            "paper/ms.tex"
+        );
+        assert_eq!(
+            archive(&[("intro.tex", "hi"), ("main.tex", doc)])
+                .find_main_file()
+                .unwrap(),
+            "main.tex"
+        );
+        assert_eq!(
+            archive(&[("old/main.tex", doc), ("main.tex", doc)])
+                .find_main_file()
+                .unwrap(),
+            "main.tex"
+        );
+        assert!(archive(&[("a.tex", doc), ("b.tex", doc)])
+            .find_main_file()
+            .is_err());
+        assert!(archive(&[("a.tex", "% \\documentclass"), ("b.tex", "")])
+            .find_main_file()
+            .is_err());
+    }
+
+    #[test]
+    fn lookups() {
+        let mut zio = archive(&[("paper/ms.tex", "main"), ("paper/sec/a.tex", "a")]);
+        zio.set_root("paper");
+
+        let mut text = String::new();
+        zio.input_open_name("./sec/a.tex", &mut NoopStatusBackend {})
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        assert_eq!(text, "a");
+
+        for name in ["sec/b.tex", "../paper/ms.tex", "/paper/ms.tex"] {
+            assert!(zio
+                .input_open_name(name, &mut NoopStatusBackend {})
+                .is_not_available());
+        }
+    }
+}
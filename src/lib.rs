@@ -74,8 +74,10 @@
 //! println!("Output PDF size is {} bytes", pdf_data.len());
 //! ```
 //!
-//! The [`driver`] module provides a high-level interface for driving the
-//! engines in more realistic circumstances.
+//! A [`LatexJob`] does the same with more input files and more kinds of output,
+//! and returns the log and other files created along the way. The [`driver`]
+//! module provides a high-level interface for driving the engines in more
+//! realistic circumstances.
 
 pub mod config;
 pub mod digest;
@@ -88,14 +90,17 @@ pub mod io;
 pub mod status;
 pub mod unstable_opts;
 
+use std::collections::HashMap;
+
 // Note: this module is intentionally *not* gated by #[cfg(test)] -- see its
 // docstring for details.
 #[doc(hidden)]
 pub mod test_util;
 
-pub use crate::engines::bibtex::BibtexEngine;
+pub use crate::engines::bibtex::{BibtexEngine, BibtexRun};
+pub use crate::engines::makeindex::{MakeindexEngine, MakeindexOutput};
 pub use crate::engines::spx2html::Spx2HtmlEngine;
-pub use crate::engines::tex::{TexEngine, TexOutcome};
+pub use crate::engines::tex::{InteractionMode, MemoryParameter, TexEngine, TexOutcome};
 pub use crate::engines::xdvipdfmx::XdvipdfmxEngine;
 pub use crate::errors::{Error, ErrorKind, Result};
 
@@ -131,9 +136,11 @@ pub use tectonic_status_base::{tt_error, tt_note, tt_warning};
 /// document are discarded. The XeTeX engine is run multiple times if needed
 /// to get the output file to converge.
 ///
-/// For more sophisticated uses, use the [`driver`] module, which provides a
-/// high-level interface for driving the typesetting engines with much more
-/// control over their behavior.
+/// To supply more input files, get the log and other files created along the
+/// way, or produce HTML or XDV output, use a [`LatexJob`]. For more
+/// sophisticated uses, use the [`driver`] module, which provides a high-level
+/// interface for driving the typesetting engines with much more control over
+/// their behavior.
 ///
 /// Note that the current engine implementations use lots of global state, so
 /// they are not thread-safe. This crate uses a global mutex to serialize
@@ -143,43 +150,199 @@ pub use tectonic_status_base::{tt_error, tt_note, tt_warning};
 /// extensive work on the underlying C/C++ code.
 pub fn latex_to_pdf<T: AsRef<str>>(latex: T) -> Result<Vec<u8>> {
     let mut status = status::NoopStatusBackend::default();
+    let mut outputs = LatexJob::new(latex).run(&mut status)?.outputs;
+
+    match outputs.remove("texput.pdf") {
+        Some(data) => Ok(data),
+        None => Err(errmsg!(
+            "LaTeX didn't report failure, but no PDF was created (??)"
+        )),
+    }
+}
+
+/// A LaTeX document to be compiled in memory.
+///
+/// This extends [`latex_to_pdf`] for uses like web services, which have a
+/// document's files in hand and want all of the results back:
+///
+/// ```
+/// let latex = r#"
+/// \documentclass{article}
+/// \begin{document}
+/// \input{body}
+/// \end{document}
+/// "#;
+///
+/// # tectonic::test_util::activate_test_mode_augmented(env!("CARGO_MANIFEST_DIR"));
+/// let mut status = tectonic::status::NoopStatusBackend::default();
+/// let results = tectonic::LatexJob::new(latex)
+///     .input("body.tex", b"Hello, world!")
+///     .run(&mut status)
+///     .expect("processing failed");
+/// println!("Output PDF size is {} bytes", results.outputs["texput.pdf"].len());
+/// println!("The log is {} bytes", results.log.len());
+/// ```
+///
+/// The main input file is named `texput.tex`, so the output files are named
+/// `texput.pdf` and so on. Processing works as it does for [`latex_to_pdf`]:
+/// nothing is written to disk, except that HTML output is generated in a
+/// temporary directory and read back in.
+#[derive(Clone, Debug)]
+pub struct LatexJob {
+    latex: Vec<u8>,
+    inputs: Vec<(String, Vec<u8>)>,
+    output_format: driver::OutputFormat,
+}
+
+/// The files produced by a [`LatexJob`].
+#[derive(Clone, Debug, Default)]
+pub struct LatexResults {
+    /// The output files, by name. For PDF or XDV output, this is just the
+    /// document, `texput.pdf` or `texput.xdv`. For HTML output, it's the HTML
+    /// files and their supporting assets, named by their paths relative to
+    /// the output directory, like `fonts/font.woff2`.
+    pub outputs: HashMap<String, Vec<u8>>,
 
-    let auto_create_config_file = false;
-    let config = ctry!(config::PersistentConfig::open(auto_create_config_file);
-                       "failed to open the default configuration file");
+    /// The log of the TeX engine, `texput.log`.
+    pub log: Vec<u8>,
 
-    let only_cached = false;
-    let bundle = ctry!(config.default_bundle(only_cached);
-                       "failed to load the default resource bundle");
+    /// The other files created during processing, such as `texput.aux` and
+    /// `texput.toc`, by name.
+    pub intermediates: HashMap<String, Vec<u8>>,
+}
 
-    let format_cache_path = ctry!(config.format_cache_path();
-                                  "failed to set up the format cache");
+impl LatexJob {
+    /// Set up to compile the LaTeX text `latex`, producing a PDF.
+    pub fn new<T: AsRef<str>>(latex: T) -> Self {
+        LatexJob {
+            latex: latex.as_ref().as_bytes().to_owned(),
+            inputs: Vec::new(),
+            output_format: driver::OutputFormat::Pdf,
+        }
+    }
+
+    /// Provide another input file, which the document can load as `name`
+    /// with `\input`, `\includegraphics`, and the like.
+    ///
+    /// These files take precedence over any files of the same name in the
+    /// current directory or the bundle.
+    pub fn input(&mut self, name: &str, data: &[u8]) -> &mut Self {
+        self.inputs.push((name.to_owned(), data.to_owned()));
+        self
+    }
+
+    /// Set the kind of output to produce. This can be
+    /// [`driver::OutputFormat::Pdf`] (the default),
+    /// [`driver::OutputFormat::Xdv`], or [`driver::OutputFormat::Html`].
+    pub fn output_format(&mut self, format: driver::OutputFormat) -> &mut Self {
+        self.output_format = format;
+        self
+    }
+
+    /// Compile the document.
+    ///
+    /// Messages about the processing are reported to `status`. If the TeX
+    /// engine fails, its log is passed to
+    /// [`status::StatusBackend::dump_error_logs`], since it's likely to be the
+    /// best explanation of what went wrong.
+    pub fn run(&self, status: &mut dyn status::StatusBackend) -> Result<LatexResults> {
+        let output_ext = match self.output_format {
+            driver::OutputFormat::Pdf => "pdf",
+            driver::OutputFormat::Xdv => "xdv",
+            driver::OutputFormat::Html => "html",
+            _ => return Err(errmsg!("only PDF, XDV, and HTML outputs are supported")),
+        };
+
+        let auto_create_config_file = false;
+        let config = ctry!(config::PersistentConfig::open(auto_create_config_file);
+                           "failed to open the default configuration file");
+
+        let only_cached = false;
+        let bundle = ctry!(config.default_bundle(only_cached);
+                           "failed to load the default resource bundle");
+
+        let format_cache_path = ctry!(config.format_cache_path();
+                                      "failed to set up the format cache");
 
-    let mut files = {
-        // Looking forward to non-lexical lifetimes!
         let mut sb = driver::ProcessingSessionBuilder::default();
         sb.bundle(bundle)
-            .primary_input_buffer(latex.as_ref().as_bytes())
+            .primary_input_buffer(&self.latex)
             .tex_input_name("texput.tex")
             .format_name("latex")
             .format_cache_path(format_cache_path)
             .keep_logs(false)
             .keep_intermediates(false)
             .print_stdout(false)
-            .output_format(driver::OutputFormat::Pdf)
-            .do_not_write_output_files();
+            .output_format(self.output_format);
+
+        for (name, data) in &self.inputs {
+            sb.memory_input(name, data);
+        }
+
+        // The HTML engine can only write its outputs to disk.
+        let html_dir = if self.output_format == driver::OutputFormat::Html {
+            let dir = ctry!(tempfile::Builder::new().tempdir();
+                            "can't create temporary directory for HTML output");
+            sb.output_dir(dir.path());
+            Some(dir)
+        } else {
+            sb.do_not_write_output_files();
+            None
+        };
 
         let mut sess =
-            ctry!(sb.create(&mut status); "failed to initialize the LaTeX processing session");
-        ctry!(sess.run(&mut status); "the LaTeX engine failed");
-        sess.into_file_data()
-    };
+            ctry!(sb.create(status); "failed to initialize the LaTeX processing session");
 
-    match files.remove("texput.pdf") {
-        Some(file) => Ok(file.data),
-        None => Err(errmsg!(
-            "LaTeX didn't report failure, but no PDF was created (??)"
-        )),
+        if let Err(e) = sess.run(status) {
+            if let Some(log) = sess.get_file_data("texput.log") {
+                status.dump_error_logs(&log);
+            }
+
+            return Err(e.chain_err(|| "the LaTeX engine failed"));
+        }
+
+        let mut results = LatexResults::default();
+
+        for (name, file) in sess.into_file_data() {
+            if name.is_empty() {
+                // This is where the engine's standard output goes.
+                continue;
+            }
+
+            if name == "texput.log" {
+                results.log = file.data;
+            } else if name == format!("texput.{output_ext}") {
+                results.outputs.insert(name, file.data);
+            } else {
+                results.intermediates.insert(name, file.data);
+            }
+        }
+
+        if let Some(dir) = html_dir {
+            for entry in walkdir::WalkDir::new(dir.path()) {
+                let entry = ctry!(entry; "failed to read HTML output directory");
+
+                if !entry.file_type().is_file() {
+                    continue;
+                }
+
+                let rel = entry.path().strip_prefix(dir.path()).unwrap();
+                let name = rel
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+
+                // Other outputs of the session are in memory already.
+                if !results.intermediates.contains_key(&name) {
+                    let data = ctry!(std::fs::read(entry.path());
+                                     "failed to read HTML output `{}`", name);
+                    results.outputs.insert(name, data);
+                }
+            }
+        }
+
+        Ok(results)
     }
 }
 
@@ -5,13 +5,26 @@
 // TODO: make this module a feature that can be disabled if the user doesn't want to
 //! Status backend that emits colorized errors to the terminal.
 
-use std::fmt::Arguments;
-use std::io::Write;
+use std::fmt::{Arguments, Write as _};
+use std::io::{self, IsTerminal, Write};
+use std::time::{Duration, Instant};
 use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
 
 use tectonic_errors::Error;
 
-use super::{ChatterLevel, MessageKind, StatusBackend};
+use super::{
+    snippet::{SnippetPart, SourceSnippet},
+    ChatterLevel, MessageKind, Progress, SourceDiagnostic, StatusBackend,
+};
+
+/// How often the progress line is redrawn, at most.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The maximum width of the progress line, in characters.
+const PROGRESS_MAX_WIDTH: usize = 78;
+
+/// The width of the progress bar, in characters.
+const PROGRESS_BAR_WIDTH: usize = 20;
 
 /// Status backend based on `termcolor` that emits compile errors and note with terminal colors.
 pub struct TermcolorStatusBackend {
@@ -23,6 +36,10 @@ pub struct TermcolorStatusBackend {
     highlight_spec: ColorSpec,
     warning_spec: ColorSpec,
     error_spec: ColorSpec,
+    gutter_spec: ColorSpec,
+    show_progress: bool,
+    progress_width: usize,
+    progress_drawn: Option<Instant>,
 }
 
 impl TermcolorStatusBackend {
@@ -40,6 +57,9 @@ impl TermcolorStatusBackend {
         let mut error_spec = ColorSpec::new();
         error_spec.set_fg(Some(Color::Red)).set_bold(true);
 
+        let mut gutter_spec = ColorSpec::new();
+        gutter_spec.set_fg(Some(Color::Blue)).set_bold(true);
+
         TermcolorStatusBackend {
             chatter,
             always_stderr: false,
@@ -49,6 +69,10 @@ impl TermcolorStatusBackend {
             highlight_spec,
             warning_spec,
             error_spec,
+            gutter_spec,
+            show_progress: io::stderr().is_terminal(),
+            progress_width: 0,
+            progress_drawn: None,
         }
     }
 
@@ -58,6 +82,17 @@ impl TermcolorStatusBackend {
         self
     }
 
+    /// Erase the progress line, if one is showing, so that a message can be
+    /// printed in its place.
+    fn clear_progress(&mut self) {
+        if self.progress_width > 0 {
+            write!(self.stderr, "\r{:1$}\r", "", self.progress_width)
+                .and_then(|_| self.stderr.flush())
+                .expect("write to stderr failed");
+            self.progress_width = 0;
+        }
+    }
+
     fn styled<F>(&mut self, kind: MessageKind, f: F)
     where
         F: FnOnce(&mut StandardStream),
@@ -66,6 +101,8 @@ impl TermcolorStatusBackend {
             return;
         }
 
+        self.clear_progress();
+
         let (spec, stream) = match kind {
             MessageKind::Note => {
                 if self.always_stderr {
@@ -91,6 +128,8 @@ impl TermcolorStatusBackend {
             return;
         }
 
+        self.clear_progress();
+
         let stream = match kind {
             MessageKind::Note => {
                 if self.always_stderr {
@@ -131,6 +170,8 @@ impl TermcolorStatusBackend {
     /// Write the result of `fmt_args!` as a colorized note.
     pub fn note_styled(&mut self, args: Arguments) {
         if self.chatter > ChatterLevel::Minimal {
+            self.clear_progress();
+
             if self.always_stderr {
                 writeln!(self.stderr, "{args}").expect("write to stderr failed");
             } else {
@@ -195,6 +236,8 @@ impl StatusBackend for TermcolorStatusBackend {
 
     fn note_highlighted(&mut self, before: &str, highlighted: &str, after: &str) {
         if self.chatter > ChatterLevel::Minimal {
+            self.clear_progress();
+
             let stream = if self.always_stderr {
                 &mut self.stderr
             } else {
@@ -211,6 +254,67 @@ impl StatusBackend for TermcolorStatusBackend {
         }
     }
 
+    fn report_diagnostic(&mut self, diag: &SourceDiagnostic) {
+        let Some(snippet) = SourceSnippet::from_diagnostic(diag) else {
+            return;
+        };
+
+        let gutter_spec = self.gutter_spec.clone();
+        let caret_spec = match diag.kind {
+            MessageKind::Note => self.note_spec.clone(),
+            MessageKind::Warning => self.warning_spec.clone(),
+            MessageKind::Error => self.error_spec.clone(),
+        };
+
+        self.with_stream(diag.kind, |s| {
+            snippet.render(|part, text| {
+                match part {
+                    SnippetPart::Gutter => s.set_color(&gutter_spec),
+                    SnippetPart::Text => s.reset(),
+                    SnippetPart::Caret => s.set_color(&caret_spec),
+                }
+                .expect("failed to set color");
+                write!(s, "{text}").expect("failed to write to standard stream");
+            });
+            s.reset().expect("failed to clear color");
+        });
+    }
+
+    fn report_progress(&mut self, progress: &Progress) {
+        if !self.show_progress || self.chatter <= ChatterLevel::Minimal {
+            return;
+        }
+
+        // Redrawing for every file that TeX opens would slow things down, so
+        // we do it only so often.
+        let now = Instant::now();
+
+        if self.progress_width > 0
+            && self
+                .progress_drawn
+                .is_some_and(|t| now.duration_since(t) < PROGRESS_INTERVAL)
+        {
+            return;
+        }
+
+        let line = format_progress(progress);
+        let width = line.chars().count();
+        write!(
+            self.stderr,
+            "\r{line}{:1$}",
+            "",
+            self.progress_width.saturating_sub(width)
+        )
+        .and_then(|_| self.stderr.flush())
+        .expect("write to stderr failed");
+        self.progress_width = width.max(self.progress_width);
+        self.progress_drawn = Some(now);
+    }
+
+    fn finish_progress(&mut self) {
+        self.clear_progress();
+    }
+
     fn dump_error_logs(&mut self, output: &[u8]) {
         tt_error_styled!(
             self,
@@ -227,3 +331,69 @@ impl StatusBackend for TermcolorStatusBackend {
         );
     }
 }
+
+/// Format a progress report as a line of text, such as:
+///
+/// ```text
+/// writing PDF [########------------]  40% page 4
+/// ```
+fn format_progress(progress: &Progress) -> String {
+    let mut line = progress.phase.clone();
+
+    if let Some(percent) = progress.percent {
+        let percent = percent.clamp(0., 100.);
+        let filled = (percent / 100. * PROGRESS_BAR_WIDTH as f32).round() as usize;
+        write!(
+            line,
+            " [{}{}] {percent:3.0}%",
+            "#".repeat(filled),
+            "-".repeat(PROGRESS_BAR_WIDTH - filled)
+        )
+        .unwrap();
+    }
+
+    if let Some(page) = progress.page {
+        write!(line, " page {page}").unwrap();
+    }
+
+    // If the file name is too long, we show its end, which is the most
+    // informative part.
+    if let Some(file) = progress.file.as_ref() {
+        let room = PROGRESS_MAX_WIDTH.saturating_sub(line.chars().count() + 1);
+        let n_chars = file.chars().count();
+
+        if n_chars <= room {
+            write!(line, " {file}").unwrap();
+        } else if room > 1 {
+            let tail: String = file.chars().skip(n_chars + 1 - room).collect();
+            write!(line, " …{tail}").unwrap();
+        }
+    }
+
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn progress_lines() {
+        let mut progress = Progress::new("writing PDF");
+        assert_eq!(format_progress(&progress), "writing PDF");
+
+        progress.percent = Some(40.);
+        progress.page = Some(4);
+        assert_eq!(
+            format_progress(&progress),
+            "writing PDF [########------------]  40% page 4"
+        );
+
+        let mut progress = Progress::new("running TeX");
+        progress.file = Some(format!("{}/main.tex", "x".repeat(100)));
+        let line = format_progress(&progress);
+        assert_eq!(line.chars().count(), PROGRESS_MAX_WIDTH);
+        assert!(line.starts_with("running TeX …xxx"));
+        assert!(line.ends_with("x/main.tex"));
+    }
+}
@@ -15,10 +15,24 @@ use std::str::FromStr;
 const HELPMSG: &str = r#"Available unstable options:
 
     -Z help                     List all unstable options
+    -Z biber=<path>             Use the biber program at <path> for biblatex documents, rather than
+                                    looking for `tectonic-biber` or `biber` in the search path
     -Z continue-on-errors       Keep compiling even when severe errors occur
+    -Z makeglossaries=<path>    Use the makeglossaries program at <path> to sort the glossaries of
+                                    the glossaries package, rather than the builtin makeindex (needs
+                                    shell-escape to be allowed)
+    -Z makeindex=<path>         Use the makeindex program at <path> to process document indexes,
+                                    rather than the builtin one (needs shell-escape to be allowed)
     -Z min-crossrefs=<num>      Equivalent to bibtex's -min-crossrefs flag - "include after <num>
                                     crossrefs" [default: 2]
+    -Z no-pdf-object-streams    Don't collect PDF objects into compressed object streams
     -Z paper-size=<spec>        Change the initial paper size [default: letter]
+    -Z pdf-compression-level=<num>
+                                Compress PDF streams at level <num>, from 0 (none) to 9 [default: 9]
+    -Z pdf-version=<version>    Target version <version> of the PDF format, from 1.3 to 1.7 or 2.0
+                                    [default: 1.5]
+    -Z precompiled-preamble     Compile the document's preamble into a format file that is cached and
+                                    reused for as long as the preamble stays the same
     -Z search-path=<path>       Also look in <path> for files (unless --untrusted has been specified),
                                     like TEXINPUTS. Can be specified multiple times.
     -Z shell-escape             Enable \write18 (unless --untrusted has been specified)
@@ -36,10 +50,17 @@ const HELPMSG: &str = r#"Available unstable options:
 #[doc(hidden)]
 #[derive(Debug, Clone)]
 pub enum UnstableArg {
+    Biber(PathBuf),
     ContinueOnErrors,
     Help,
+    Makeglossaries(PathBuf),
+    Makeindex(PathBuf),
     MinCrossrefs(u32),
+    NoPdfObjectStreams,
     PaperSize(String),
+    PdfCompressionLevel(u8),
+    PdfVersion(u8, u8),
+    PrecompiledPreamble,
     SearchPath(PathBuf),
     ShellEscapeEnabled,
     ShellEscapeCwd(String),
@@ -78,16 +99,42 @@ impl FromStr for UnstableArg {
         match arg {
             "help" => Ok(UnstableArg::Help),
 
+            "biber" => require_value("path").map(|s| UnstableArg::Biber(s.into())),
+
             "continue-on-errors" => Ok(UnstableArg::ContinueOnErrors),
 
+            "makeglossaries" => {
+                require_value("path").map(|s| UnstableArg::Makeglossaries(s.into()))
+            }
+
+            "makeindex" => require_value("path").map(|s| UnstableArg::Makeindex(s.into())),
+
             "min-crossrefs" => require_value("num")
                 .and_then(|s| {
                     FromStr::from_str(s).map_err(|e| format!("-Z min-crossrefs: {e}").into())
                 })
                 .map(UnstableArg::MinCrossrefs),
 
+            "no-pdf-object-streams" => require_no_value(value, UnstableArg::NoPdfObjectStreams),
+
             "paper-size" => require_value("spec").map(|s| UnstableArg::PaperSize(s.to_string())),
 
+            "pdf-compression-level" => require_value("num")
+                .and_then(|s| {
+                    FromStr::from_str(s)
+                        .map_err(|e| format!("-Z pdf-compression-level: {e}").into())
+                })
+                .map(UnstableArg::PdfCompressionLevel),
+
+            "pdf-version" => require_value("version").and_then(|s| {
+                s.split_once('.')
+                    .and_then(|(major, minor)| Some((major.parse().ok()?, minor.parse().ok()?)))
+                    .map(|(major, minor)| UnstableArg::PdfVersion(major, minor))
+                    .ok_or_else(|| format!("-Z pdf-version: invalid version `{s}`").into())
+            }),
+
+            "precompiled-preamble" => require_no_value(value, UnstableArg::PrecompiledPreamble),
+
             "search-path" => require_value("path").map(|s| UnstableArg::SearchPath(s.into())),
 
             "shell-escape" => require_no_value(value, UnstableArg::ShellEscapeEnabled),
@@ -116,6 +163,32 @@ pub struct UnstableOptions {
     /// Set the paper size used by the output document.
     pub paper_size: Option<String>,
 
+    /// The version of the PDF format targeted by the output document, as
+    /// major and minor version numbers.
+    pub pdf_version: Option<(u8, u8)>,
+
+    /// The level of compression applied to streams in the output PDF, from 0
+    /// to 9.
+    pub pdf_compression_level: Option<u8>,
+
+    /// Don't collect objects into compressed object streams in the output PDF.
+    pub no_pdf_object_streams: bool,
+
+    /// Compile the preamble of the document, everything before
+    /// `\begin{document}`, into a format file, and use it instead of
+    /// processing the preamble. The format files are cached along with the
+    /// ordinary ones, so that later runs only need to generate one if the
+    /// preamble has changed.
+    ///
+    /// The preamble can't depend on files outside of the bundle, or load
+    /// fonts that XeTeX can't save in a format file. If it does, it's
+    /// processed as usual. Everything it does has to be saved in the format,
+    /// so it shouldn't open files for writing or depend on the job name
+    /// either. Such code can be moved after a line reading `%endofdump`, as
+    /// with the `mylatexformat` package: only what comes before that line is
+    /// precompiled.
+    pub precompiled_preamble: bool,
+
     /// Allow using shell commands during document compilation. All shell escapes will be executed
     /// within a custom temporary directory that lives for the duration of the compilation session.
     /// [`Self::shell_escape_cwd`] will take precedence over this flag.
@@ -131,6 +204,21 @@ pub struct UnstableOptions {
     /// compilation is complete. This overrides [`Self::shell_escape`].
     pub shell_escape_cwd: Option<String>,
 
+    /// The `biber` program to run for biblatex documents. If unset, the
+    /// driver looks for `tectonic-biber` and then `biber` in the search path.
+    pub biber: Option<PathBuf>,
+
+    /// An external `makeglossaries` program to sort the glossaries of
+    /// documents that use the `glossaries` package. If unset, or if the
+    /// security settings forbid running external programs, the builtin
+    /// `makeindex` is used.
+    pub makeglossaries: Option<PathBuf>,
+
+    /// An external `makeindex` program to run on index files written by the
+    /// document. If unset, or if the security settings forbid running
+    /// external programs, the builtin implementation is used.
+    pub makeindex: Option<PathBuf>,
+
     /// Ensure a deterministic build environment.
     ///
     /// The most significant user-facing difference is a static document build
@@ -156,9 +244,16 @@ impl UnstableOptions {
             use UnstableArg::*;
             match u {
                 Help => print_unstable_help_and_exit(),
+                Biber(p) => opts.biber = Some(p),
                 ContinueOnErrors => opts.continue_on_errors = true,
+                Makeglossaries(p) => opts.makeglossaries = Some(p),
+                Makeindex(p) => opts.makeindex = Some(p),
                 MinCrossrefs(num) => opts.min_crossrefs = Some(num),
+                NoPdfObjectStreams => opts.no_pdf_object_streams = true,
                 PaperSize(size) => opts.paper_size = Some(size),
+                PdfCompressionLevel(level) => opts.pdf_compression_level = Some(level),
+                PdfVersion(major, minor) => opts.pdf_version = Some((major, minor)),
+                PrecompiledPreamble => opts.precompiled_preamble = true,
                 ShellEscapeEnabled => opts.shell_escape = true,
                 SearchPath(p) => opts.extra_search_paths.push(p),
                 ShellEscapeCwd(p) => {
@@ -3,12 +3,24 @@
 
 //! Bibtex test suite - compare running bibtex against many different test files
 
+use std::cell::RefCell;
 use std::collections::HashSet;
+use std::io::Cursor;
 use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
 
-use tectonic::io::{FilesystemIo, IoProvider, IoStack, MemoryIo};
+use tectonic::io::{
+    memory::MemoryFileCollection, FilesystemIo, InputHandle, InputOrigin, IoProvider, IoStack,
+    MemoryIo,
+};
 use tectonic::{errors::Result, BibtexEngine};
 use tectonic_bridge_core::{CoreBridgeLauncher, MinimalDriver};
+use tectonic_engine_bibtex::{
+    BibComment, BibFileStats, BibtexEngine as RealBibtexEngine, BibtexOutcome, BibtexProgress,
+    BibtexReport, DuplicateEntryPolicy, EmptyCitationPolicy, EntryFields, ExecSnapshot,
+    InjectedEntry, StackValue,
+};
 use tectonic_engine_xetex::TexOutcome;
 use tectonic_status_base::NoopStatusBackend;
 
@@ -20,6 +32,7 @@ struct TestCase {
     parts: &'static [&'static str],
     test_bbl: bool,
     expected_result: Result<TexOutcome>,
+    configure: Option<fn(&mut RealBibtexEngine)>,
 }
 
 impl TestCase {
@@ -29,6 +42,7 @@ impl TestCase {
             parts,
             test_bbl: true,
             expected_result: Ok(TexOutcome::Spotless),
+            configure: None,
         }
     }
 
@@ -42,6 +56,12 @@ impl TestCase {
         self
     }
 
+    /// Run the underlying engine directly, configured by the given function.
+    fn configure(mut self, configure: fn(&mut RealBibtexEngine)) -> Self {
+        self.configure = Some(configure);
+        self
+    }
+
     fn test_dir(&self) -> PathBuf {
         let mut p = test_path(&["bibtex"]);
         for sub in &self.parts[..self.parts.len() - 1] {
@@ -69,7 +89,27 @@ impl TestCase {
         let mut status = NoopStatusBackend::default();
         let mut launcher = CoreBridgeLauncher::new(&mut hooks, &mut status);
 
-        let res = BibtexEngine::new().process(&mut launcher, &auxname, &Default::default());
+        let res = match self.configure {
+            Some(configure) => {
+                let mut engine = RealBibtexEngine::default();
+                configure(&mut engine);
+                engine
+                    .process(&mut launcher, &auxname)
+                    .map(|report| match report.outcome {
+                        BibtexOutcome::Spotless => TexOutcome::Spotless,
+                        BibtexOutcome::Warnings => TexOutcome::Warnings,
+                        BibtexOutcome::Errors => TexOutcome::Errors,
+                    })
+                    .map_err(Into::into)
+            }
+            None => BibtexEngine::new()
+                .process(&mut launcher, &auxname, &Default::default())
+                .map(|run| {
+                    let blg = format!("{}.blg", self.parts[self.parts.len() - 1]);
+                    assert_eq!(run.blg_file, Some(blg));
+                    run.outcome
+                }),
+        };
 
         // Check that outputs match expectations.
 
@@ -90,6 +130,31 @@ impl TestCase {
     }
 }
 
+/// Run a configured engine directly on an aux file in one of the test
+/// directories, for tests that look at its report rather than its outputs.
+/// The files that it wrote are returned along with the report.
+fn run_engine(
+    dir: &[&str],
+    engine: &mut RealBibtexEngine,
+    auxname: &str,
+) -> (BibtexReport, Rc<RefCell<MemoryFileCollection>>) {
+    util::set_test_root();
+
+    let mut p = test_path(&["bibtex"]);
+    p.extend(dir);
+
+    let mut mem = MemoryIo::new(true);
+    let files = mem.files.clone();
+    let mut assets = FilesystemIo::new(&p, false, false, HashSet::new());
+    let io = IoStack::new(vec![&mut mem, &mut assets]);
+    let mut hooks = MinimalDriver::new(io);
+    let mut status = NoopStatusBackend::default();
+    let mut launcher = CoreBridgeLauncher::new(&mut hooks, &mut status);
+
+    let report = engine.process(&mut launcher, auxname).unwrap();
+    (report, files)
+}
+
 #[test]
 fn test_single_entry() {
     TestCase::new(&["cites", "single_entry"]).go()
@@ -175,6 +240,41 @@ fn test_mismatched_style() {
         .go();
 }
 
+#[test]
+fn test_mismatched_field() {
+    TestCase::new(&["mismatched_braces", "field"])
+        .expect(Ok(TexOutcome::Errors))
+        .test_bbl(false)
+        .go();
+}
+
+#[test]
+fn test_mismatched_field_lenient() {
+    TestCase::new(&["mismatched_braces", "lenient"])
+        .configure(|engine| {
+            engine.lenient(true);
+        })
+        .expect(Ok(TexOutcome::Errors))
+        .go();
+}
+
+#[test]
+fn test_malformed_fields() {
+    TestCase::new(&["malformed", "strict"])
+        .expect(Ok(TexOutcome::Errors))
+        .go();
+}
+
+#[test]
+fn test_malformed_fields_lenient() {
+    TestCase::new(&["malformed", "lenient"])
+        .configure(|engine| {
+            engine.lenient(true);
+        })
+        .expect(Ok(TexOutcome::Errors))
+        .go();
+}
+
 #[test]
 fn test_duplicated_data() {
     TestCase::new(&["duplicated", "data"])
@@ -191,6 +291,43 @@ fn test_duplicated_style() {
         .go();
 }
 
+#[test]
+fn test_duplicated_entries_default() {
+    TestCase::new(&["duplicated", "entries_error"])
+        .expect(Ok(TexOutcome::Errors))
+        .go();
+}
+
+#[test]
+fn test_duplicated_entries_error() {
+    TestCase::new(&["duplicated", "entries_error"])
+        .configure(|engine| {
+            engine.duplicate_entries(DuplicateEntryPolicy::Error);
+        })
+        .expect(Ok(TexOutcome::Errors))
+        .go();
+}
+
+#[test]
+fn test_duplicated_entries_first_wins() {
+    TestCase::new(&["duplicated", "entries_first"])
+        .configure(|engine| {
+            engine.duplicate_entries(DuplicateEntryPolicy::FirstWins);
+        })
+        .expect(Ok(TexOutcome::Warnings))
+        .go();
+}
+
+#[test]
+fn test_duplicated_entries_last_wins() {
+    TestCase::new(&["duplicated", "entries_last"])
+        .configure(|engine| {
+            engine.duplicate_entries(DuplicateEntryPolicy::LastWins);
+        })
+        .expect(Ok(TexOutcome::Warnings))
+        .go();
+}
+
 #[test]
 fn test_bad_crossref() {
     TestCase::new(&["crossref", "bad"])
@@ -205,6 +342,16 @@ fn test_min_crossref() {
         .go();
 }
 
+#[test]
+fn test_include_crossref_parents() {
+    TestCase::new(&["crossref", "parents"])
+        .configure(|engine| {
+            engine.include_crossref_parents(true);
+        })
+        .expect(Ok(TexOutcome::Warnings))
+        .go();
+}
+
 #[test]
 fn test_single_preamble() {
     TestCase::new(&["preamble", "single"])
@@ -258,3 +405,397 @@ fn test_missing_bst() {
         .test_bbl(false)
         .go();
 }
+
+#[test]
+fn test_empty_citation_warn() {
+    TestCase::new(&["aux_files", "empty_cite_warn"])
+        .configure(|engine| {
+            engine.empty_citations(EmptyCitationPolicy::Warn);
+        })
+        .expect(Ok(TexOutcome::Warnings))
+        .go();
+}
+
+#[test]
+fn test_empty_citation_ignore() {
+    TestCase::new(&["aux_files", "empty_cite_ignore"])
+        .configure(|engine| {
+            engine.empty_citations(EmptyCitationPolicy::Ignore);
+        })
+        .go();
+}
+
+#[test]
+fn test_empty_citation_error() {
+    TestCase::new(&["aux_files", "empty_cite_error"])
+        .configure(|engine| {
+            engine.empty_citations(EmptyCitationPolicy::Error);
+        })
+        .expect(Ok(TexOutcome::Errors))
+        .go();
+}
+
+#[test]
+fn test_memory_bst() {
+    TestCase::new(&["aux_files", "memory_bst"])
+        .configure(|engine| {
+            engine.bst_data(&include_bytes!("bibtex/plain.bst")[..]);
+        })
+        .go();
+}
+
+#[test]
+fn test_fallback_database() {
+    TestCase::new(&["cites", "fallback"])
+        .configure(|engine| {
+            engine.fallback_database("fallback_master");
+        })
+        .go();
+}
+
+#[test]
+fn test_memory_bib() {
+    TestCase::new(&["cites", "memory"])
+        .configure(|engine| {
+            engine.bib_data(
+                "memory_1",
+                &b"@book{Memory01,
+    title = \"A book in memory\",
+    author = \"Nobody Sr.\",
+    publisher = \"Nobody\",
+    year = 2024,
+}
+"[..],
+            );
+
+            let data = b"@misc{Memory02,
+    title = \"A handle\",
+    author = \"Nobody Jr.\",
+}
+";
+            engine
+                .bib_input(
+                    "memory_2.bib",
+                    InputHandle::new_read_only(
+                        "memory_2.bib",
+                        Cursor::new(data.to_vec()),
+                        InputOrigin::Other,
+                    ),
+                )
+                .unwrap();
+        })
+        .go();
+}
+
+#[test]
+fn test_injected_entry() {
+    TestCase::new(&["cites", "injected"])
+        .configure(|engine| {
+            engine
+                .inject_entry(InjectedEntry {
+                    entry_type: "misc".to_owned(),
+                    key: "Injected01".to_owned(),
+                    fields: vec![
+                        ("title".to_owned(), "Some {Software}".to_owned()),
+                        ("author".to_owned(), "Nobody Jr.".to_owned()),
+                        ("year".to_owned(), "2024".to_owned()),
+                    ],
+                })
+                .unwrap();
+        })
+        .go();
+}
+
+#[test]
+fn test_injected_entry_validation() {
+    let entry = |entry_type: &str, key: &str, field: (&str, &str)| InjectedEntry {
+        entry_type: entry_type.to_owned(),
+        key: key.to_owned(),
+        fields: vec![(field.0.to_owned(), field.1.to_owned())],
+    };
+    let mut engine = RealBibtexEngine::default();
+
+    assert!(engine
+        .inject_entry(entry("misc", "O'Brien:2024", ("title", "{A} {{B}}")))
+        .is_ok());
+
+    for bad in [
+        entry("", "key", ("title", "x")),
+        entry("mi sc", "key", ("title", "x")),
+        entry("misc{", "key", ("title", "x")),
+        entry("1misc", "key", ("title", "x")),
+        entry("misc", "", ("title", "x")),
+        entry("misc", "a key", ("title", "x")),
+        entry("misc", "a,key", ("title", "x")),
+        entry("misc", "a}key", ("title", "x")),
+        entry("misc", "key", ("ti=tle", "x")),
+        entry("misc", "key", ("title", "{x")),
+        entry("misc", "key", ("title", "x}")),
+        entry("misc", "key", ("title", "}x{")),
+    ] {
+        assert!(engine.inject_entry(bad.clone()).is_err(), "{bad:?}");
+    }
+}
+
+#[test]
+fn test_bib_resolver() {
+    TestCase::new(&["cites", "resolver"])
+        .configure(|engine| {
+            engine.bib_resolver(|name| {
+                let data = b"@misc{Virtual01,
+    title = \"Resolved\",
+    author = \"Nobody Jr.\",
+}
+";
+                (name == "virtual").then(|| {
+                    InputHandle::new_read_only(
+                        "virtual.bib",
+                        Cursor::new(data.to_vec()),
+                        InputOrigin::Other,
+                    )
+                })
+            });
+        })
+        .go();
+}
+
+#[test]
+fn test_bib_file_stats() {
+    let (report, _) = run_engine(
+        &["cites"],
+        &mut RealBibtexEngine::default(),
+        "multi_file.aux",
+    );
+
+    assert_eq!(report.outcome, BibtexOutcome::Spotless);
+    assert_eq!(
+        report.bib_files,
+        vec![
+            BibFileStats {
+                name: "multi_file_1.bib".to_owned(),
+                entries_scanned: 1,
+                entries_used: 1,
+                macros_defined: 0,
+                parse_errors: 0,
+            },
+            BibFileStats {
+                name: "multi_file_2.bib".to_owned(),
+                entries_scanned: 1,
+                entries_used: 1,
+                macros_defined: 0,
+                parse_errors: 0,
+            },
+        ]
+    );
+    assert_eq!(report.bbl_file.as_deref(), Some("multi_file.bbl"));
+    assert_eq!(report.blg_file.as_deref(), Some("multi_file.blg"));
+}
+
+#[test]
+fn test_bib_file_stats_last_wins() {
+    let mut engine = RealBibtexEngine::default();
+    engine.duplicate_entries(DuplicateEntryPolicy::LastWins);
+    let (report, _) = run_engine(&["duplicated"], &mut engine, "entries_last.aux");
+
+    assert_eq!(report.outcome, BibtexOutcome::Warnings);
+    assert_eq!(
+        report.bib_files,
+        vec![
+            BibFileStats {
+                name: "entries_1.bib".to_owned(),
+                entries_scanned: 2,
+                entries_used: 2,
+                macros_defined: 0,
+                parse_errors: 0,
+            },
+            BibFileStats {
+                name: "entries_2.bib".to_owned(),
+                entries_scanned: 1,
+                entries_used: 0,
+                macros_defined: 0,
+                parse_errors: 0,
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_field_report() {
+    let mut engine = RealBibtexEngine::default();
+    engine.bib_data(
+        "multi_file_2",
+        &b"@book{Nobody01,
+    title = \"A book\",
+    author = \"Nobody Sr.\",
+    publisher = \"Nobody\",
+    DOI = \"10.0000/nobody\",
+}
+"[..],
+    );
+    let (report, _) = run_engine(&["cites"], &mut engine, "multi_file.aux");
+
+    assert_eq!(
+        report.style_fields.first().map(String::as_str),
+        Some("crossref")
+    );
+    assert!(report.style_fields.iter().any(|f| f == "title"));
+    assert!(!report.style_fields.iter().any(|f| f == "doi"));
+
+    let fields = |list: &[&str]| list.iter().map(|&f| f.to_owned()).collect::<Vec<_>>();
+    assert_eq!(
+        report.entry_fields,
+        vec![
+            EntryFields {
+                key: "Nobody02".to_owned(),
+                fields: fields(&["title", "author", "publisher", "year"]),
+            },
+            EntryFields {
+                key: "Nobody01".to_owned(),
+                fields: fields(&["title", "author", "publisher", "doi"]),
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_inspect_execution() {
+    let snapshots = Arc::new(Mutex::new(Vec::new()));
+    let seen = snapshots.clone();
+    let mut engine = RealBibtexEngine::default();
+    engine.inspect_execution(move |snapshot| seen.lock().unwrap().push(snapshot.clone()));
+    run_engine(&["cites"], &mut engine, "multi_file.aux");
+
+    let snapshots = snapshots.lock().unwrap();
+    let find = |function: &str, entry: Option<&str>| -> &ExecSnapshot {
+        snapshots
+            .iter()
+            .find(|s| s.function == function && s.entry.as_deref() == entry)
+            .unwrap()
+    };
+    let var = |vars: &[(String, StackValue)], name: &str| {
+        vars.iter().find(|(n, _)| n == name).unwrap().1.clone()
+    };
+
+    let consts = find("init.state.consts", None);
+    assert_eq!(consts.command, "execute");
+    assert!(consts.stack.is_empty());
+    assert!(consts.entry_vars.is_empty());
+    assert_eq!(var(&consts.globals, "after.block"), StackValue::Integer(3));
+    assert_eq!(var(&consts.globals, "entry.max$"), StackValue::Integer(250));
+
+    let presort = find("presort", Some("Nobody01"));
+    assert_eq!(presort.command, "iterate");
+    assert_eq!(
+        var(&presort.entry_vars, "sort.key$"),
+        StackValue::String("sr  nobody    2024    book".to_owned())
+    );
+}
+
+#[test]
+fn test_preserve_comments() {
+    let mut engine = RealBibtexEngine::default();
+    engine.preserve_comments(true);
+    let (report, _) = run_engine(&["cites"], &mut engine, "comments.aux");
+
+    let comment = |line, text: &str, comment_command| BibComment {
+        file: "comments.bib".to_owned(),
+        line,
+        text: text.to_owned(),
+        comment_command,
+    };
+    assert_eq!(
+        report.comments,
+        vec![
+            comment(
+                1,
+                "This file collects the references for chapter one.\nKeep it sorted by key.",
+                false,
+            ),
+            comment(4, "{Remember to check the year of Comment01}", true),
+            comment(13, "The end.", false),
+        ]
+    );
+}
+
+#[test]
+fn test_progress() {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let observed = events.clone();
+
+    let mut engine = RealBibtexEngine::default();
+    engine.progress(move |progress| {
+        let event = match progress {
+            BibtexProgress::StyleCommand { command } => command.to_owned(),
+            BibtexProgress::DatabaseOpened { name } => format!("open {name}"),
+            BibtexProgress::EntriesRead { name, count } => format!("{count} in {name}"),
+            _ => return,
+        };
+        observed.lock().unwrap().push(event);
+    });
+    run_engine(&["cites"], &mut engine, "multi_file.aux");
+
+    let events = events.lock().unwrap();
+    let read = events.iter().position(|e| e == "read").unwrap();
+    assert_eq!(
+        events[read..read + 3],
+        ["read", "open multi_file_1.bib", "open multi_file_2.bib"]
+    );
+    assert_eq!(events.first().map(String::as_str), Some("entry"));
+}
+
+#[test]
+fn test_warm_start() {
+    util::set_test_root();
+
+    let mut p = test_path(&["bibtex", "cites"]);
+    let mut engine = RealBibtexEngine::default();
+    engine.warm_start(true);
+
+    for name in ["single_entry", "multi_file", "single_entry"] {
+        let (report, files) = run_engine(&["cites"], &mut engine, &format!("{name}.aux"));
+        assert_eq!(report.outcome, BibtexOutcome::Spotless);
+
+        p.push(name);
+        let files = files.borrow();
+        Expected::<tectonic::errors::Error>::new()
+            .file(ExpectedFile::read_with_extension(&mut p, "bbl").collection(&files))
+            .file(ExpectedFile::read_with_extension(&mut p, "blg").collection(&files))
+            .finish();
+        p.pop();
+    }
+}
+
+#[test]
+fn test_concurrent_runs() {
+    let opened = Arc::new(Mutex::new(Vec::new()));
+
+    std::thread::scope(|scope| {
+        let runs: Vec<_> = ["single_entry.aux", "multi_file.aux"]
+            .into_iter()
+            .map(|aux| {
+                // Engines are set up on this thread, callbacks and all, and
+                // then sent off to run on others.
+                let mut engine = RealBibtexEngine::default();
+                let opened = opened.clone();
+                engine.progress(move |progress| {
+                    if let BibtexProgress::DatabaseOpened { name } = progress {
+                        opened.lock().unwrap().push(name.to_owned());
+                    }
+                });
+
+                scope.spawn(move || run_engine(&["cites"], &mut engine, aux).0.outcome)
+            })
+            .collect();
+
+        for run in runs {
+            assert_eq!(run.join().unwrap(), BibtexOutcome::Spotless);
+        }
+    });
+
+    let mut opened = opened.lock().unwrap().clone();
+    opened.sort();
+    assert_eq!(
+        opened,
+        ["multi_file_1.bib", "multi_file_2.bib", "single_entry.bib"]
+    );
+}
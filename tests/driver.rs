@@ -8,15 +8,886 @@
 //! ProcessingSessionBuilder will need to learn how to tell `xdvipdfmx` to
 //! enable the reproducibility options used in the `tex-outputs` test rig.
 
+use flate2::read::GzDecoder;
+use std::{
+    fmt::Arguments,
+    io::Read,
+    path::Path,
+    time::{Duration, SystemTime},
+};
 use tectonic::{
-    config::PersistentConfig, driver::ProcessingSessionBuilder,
-    status::termcolor::TermcolorStatusBackend, status::ChatterLevel, test_util::TestBundle,
+    config::PersistentConfig,
+    driver::{
+        EncryptionOptions, ImageOptions, OutlineOptions, OutputFormat, PdfMetadata,
+        ProcessingSessionBuilder,
+    },
+    errors::ErrorKind,
+    status::termcolor::TermcolorStatusBackend,
+    status::{
+        snippet::SourceSnippet, ChatterLevel, MessageKind, NoopStatusBackend, Progress,
+        SourceDiagnostic, StatusBackend,
+    },
+    test_util::TestBundle,
+    unstable_opts::UnstableOptions,
+    LatexJob, MemoryParameter,
 };
+use tectonic_bridge_core::{CancelFlag, FileAccess, SecuritySettings, SecurityStance};
+use tectonic_errors::Error;
 
 mod util;
 
 // Keep these alphabetized.
 
+#[test]
+fn cancel_flag() {
+    util::set_test_root();
+
+    /// Cancels the processing once the PDF starts being written.
+    struct CancellingBackend(CancelFlag);
+
+    impl StatusBackend for CancellingBackend {
+        fn report(&mut self, _kind: MessageKind, _args: Arguments, _err: Option<&Error>) {}
+
+        fn report_progress(&mut self, progress: &Progress) {
+            if progress.phase == "writing PDF" {
+                self.0.cancel();
+            }
+        }
+
+        fn dump_error_logs(&mut self, _output: &[u8]) {}
+    }
+
+    let flag = CancelFlag::new();
+    let mut status = CancellingBackend(flag.clone());
+    let outdir = tempfile::tempdir().unwrap();
+
+    let mut pbuilder = ProcessingSessionBuilder::default();
+    pbuilder
+        .primary_input_buffer(b"One.\\vfill\\eject Two.\\bye")
+        .tex_input_name("texput.tex")
+        .format_name("plain")
+        .format_cache_path(util::test_path(&[]))
+        .output_dir(outdir.path())
+        .cancel_flag(flag.clone())
+        .bundle(Box::new(TestBundle::default()));
+
+    let mut session = pbuilder
+        .create(&mut status)
+        .expect("couldn't create processing session");
+
+    let err = session.run(&mut status).unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::Cancelled), "{err}");
+    assert!(flag.is_cancelled());
+
+    // Neither the partial PDF nor anything else was written.
+    assert_eq!(std::fs::read_dir(outdir.path()).unwrap().count(), 0);
+}
+
+#[test]
+fn file_access_rules() {
+    util::set_test_root();
+
+    let project = tempfile::tempdir().unwrap();
+    let elsewhere = tempfile::tempdir().unwrap();
+    std::fs::write(project.path().join("chapter.tex"), b"Chapter").unwrap();
+    std::fs::write(elsewhere.path().join("secret.tex"), b"Secret").unwrap();
+
+    let mut security = SecuritySettings::new(SecurityStance::DisableInsecures);
+    security
+        .allow_directory(FileAccess::Read, project.path())
+        .allow_directory(FileAccess::Write, elsewhere.path())
+        .allow_directory(FileAccess::Write, util::test_path(&[]))
+        .deny_directory(FileAccess::Write, project.path());
+
+    let run = |input: &Path, output_dir: &Path| {
+        let mut status = NoopStatusBackend::default();
+
+        let mut pbuilder = ProcessingSessionBuilder::new_with_security(security.clone());
+        pbuilder
+            .primary_input_buffer(format!("\\input {} \\bye", input.display()).as_bytes())
+            .tex_input_name("texput.tex")
+            .format_name("plain")
+            .format_cache_path(util::test_path(&[]))
+            .output_dir(output_dir)
+            .bundle(Box::new(TestBundle::default()));
+
+        let mut session = pbuilder
+            .create(&mut status)
+            .expect("couldn't create processing session");
+
+        session.run(&mut status)
+    };
+
+    // Files outside of the allowed directory can't be read.
+    assert!(run(&elsewhere.path().join("secret.tex"), elsewhere.path()).is_err());
+
+    run(&project.path().join("chapter.tex"), elsewhere.path())
+        .expect("failed to execute processing session");
+    assert!(elsewhere.path().join("texput.pdf").exists());
+
+    // Nor can outputs be written to a denied directory.
+    assert!(run(&project.path().join("chapter.tex"), project.path()).is_err());
+    assert!(!project.path().join("texput.pdf").exists());
+}
+
+#[test]
+fn get_file_data() {
+    util::set_test_root();
+
+    let mut status = TermcolorStatusBackend::new(ChatterLevel::Minimal);
+
+    let mut pbuilder = ProcessingSessionBuilder::default();
+    pbuilder
+        .primary_input_buffer(b"\\message{Hello} \\bye")
+        .tex_input_name("texput.tex")
+        .format_name("plain")
+        .format_cache_path(util::test_path(&[]))
+        .do_not_write_output_files()
+        .bundle(Box::new(TestBundle::default()));
+
+    let mut session = pbuilder
+        .create(&mut status)
+        .expect("couldn't create processing session");
+
+    session
+        .run(&mut status)
+        .expect("failed to execute processing session");
+
+    let log = session
+        .get_file_data("texput.log")
+        .expect("no `texput.log` in the session");
+    assert!(String::from_utf8_lossy(&log).contains("Hello"));
+    assert!(session.get_file_data("texput.bbl").is_none());
+}
+
+#[test]
+fn image_options() {
+    util::set_test_root();
+
+    fn build(options: ImageOptions) -> String {
+        let mut status = TermcolorStatusBackend::new(ChatterLevel::Minimal);
+
+        // redbox.png is 12x12 RGB pixels, so at half an inch it's 24 DPI.
+        let mut pbuilder = ProcessingSessionBuilder::default();
+        pbuilder
+            .primary_input_buffer(b"\\XeTeXpicfile redbox.png width 0.5in \\bye")
+            .tex_input_name("texput.tex")
+            .format_name("plain")
+            .format_cache_path(util::test_path(&[]))
+            .image_options(options)
+            .do_not_write_output_files()
+            .bundle(Box::new(TestBundle::default()));
+
+        let mut session = pbuilder
+            .create(&mut status)
+            .expect("couldn't create processing session");
+
+        session
+            .run(&mut status)
+            .expect("failed to execute processing session");
+
+        // Image dictionaries are never compressed, so they can be checked
+        // directly.
+        let files = session.into_file_data();
+        String::from_utf8_lossy(&files["texput.pdf"].data).into_owned()
+    }
+
+    let pdf = build(ImageOptions::default());
+    assert!(pdf.contains("/Width 12"), "{pdf}");
+    assert!(pdf.contains("/DeviceRGB"), "{pdf}");
+
+    let pdf = build(ImageOptions {
+        max_dpi: Some(12),
+        grayscale: true,
+        ..Default::default()
+    });
+    assert!(pdf.contains("/Width 6"), "{pdf}");
+    assert!(pdf.contains("/Height 6"), "{pdf}");
+    assert!(pdf.contains("/DeviceGray"), "{pdf}");
+    assert!(!pdf.contains("/DeviceRGB"), "{pdf}");
+}
+
+#[test]
+fn latex_job() {
+    // This uses the fake LaTeX format of the test bundle.
+    tectonic::test_util::activate_test_mode_augmented(env!("CARGO_MANIFEST_DIR"));
+
+    let mut status = NoopStatusBackend::default();
+    let results =
+        LatexJob::new("\\documentclass{article}\\begin{document}\\input body \\end{document}")
+            .input(
+                "body.tex",
+                b"\\message{Hello from the body}\
+          \\immediate\\openout1=texput.toc \\immediate\\write1{Contents}\\immediate\\closeout1\
+          Hello",
+            )
+            .output_format(OutputFormat::Xdv)
+            .run(&mut status)
+            .expect("failed to run the LaTeX job");
+
+    let outputs: Vec<_> = results.outputs.keys().collect();
+    assert_eq!(outputs, ["texput.xdv"]);
+    assert!(String::from_utf8_lossy(&results.log).contains("Hello from the body"));
+    assert_eq!(results.intermediates["texput.toc"], b"Contents\n");
+    assert!(!results.intermediates.contains_key("body.tex"));
+}
+
+#[test]
+fn memory_inputs() {
+    util::set_test_root();
+
+    let mut status = TermcolorStatusBackend::new(ChatterLevel::Minimal);
+
+    let mut pbuilder = ProcessingSessionBuilder::default();
+    pbuilder
+        .primary_input_buffer(b"\\input snippet \\bye")
+        .memory_input("./snippet.tex", b"Seeded content.")
+        .tex_input_name("texput.tex")
+        .format_name("plain")
+        .format_cache_path(util::test_path(&[]))
+        .do_not_write_output_files()
+        .bundle(Box::new(TestBundle::default()));
+
+    let mut session = pbuilder
+        .create(&mut status)
+        .expect("couldn't create processing session");
+
+    session
+        .run(&mut status)
+        .expect("failed to execute processing session");
+
+    let files = session.into_file_data();
+    assert!(files.contains_key("texput.pdf"));
+    assert!(!files.contains_key("snippet.tex"));
+}
+
+#[cfg(unix)]
+#[test]
+fn memory_limit() {
+    util::set_test_root();
+
+    let mut status = TermcolorStatusBackend::new(ChatterLevel::Minimal);
+
+    // The process surely uses more than one byte by the time that the limit is
+    // first checked.
+    let mut pbuilder = ProcessingSessionBuilder::default();
+    pbuilder
+        .primary_input_buffer(
+            b"\\count1=0 \\loop\\advance\\count1 by 1 \\ifnum\\count1<100000 \\repeat \\bye",
+        )
+        .tex_input_name("texput.tex")
+        .format_name("plain")
+        .format_cache_path(util::test_path(&[]))
+        .memory_limit(1)
+        .do_not_write_output_files()
+        .bundle(Box::new(TestBundle::default()));
+
+    let mut session = pbuilder
+        .create(&mut status)
+        .expect("couldn't create processing session");
+
+    assert!(session.run(&mut status).is_err());
+}
+
+#[test]
+fn outline_options() {
+    util::set_test_root();
+
+    fn build(options: OutlineOptions) -> String {
+        let mut status = TermcolorStatusBackend::new(ChatterLevel::Minimal);
+
+        let mut pbuilder = ProcessingSessionBuilder::default();
+        pbuilder
+            .primary_input_buffer(
+                b"\\special{pdf:outline 1 <</Title (One)>>}\\special{pdf:outline 2 <</Title (Two)>>}Hello \\bye",
+            )
+            .tex_input_name("texput.tex")
+            .format_name("plain")
+            .format_cache_path(util::test_path(&[]))
+            .outline_options(options)
+            .unstables(UnstableOptions {
+                no_pdf_object_streams: true,
+                ..Default::default()
+            })
+            .do_not_write_output_files()
+            .bundle(Box::new(TestBundle::default()));
+
+        let mut session = pbuilder
+            .create(&mut status)
+            .expect("couldn't create processing session");
+
+        session
+            .run(&mut status)
+            .expect("failed to execute processing session");
+
+        let files = session.into_file_data();
+        String::from_utf8_lossy(&files["texput.pdf"].data).into_owned()
+    }
+
+    // By default, only the top-level entry is visible.
+    let pdf = build(OutlineOptions::default());
+    assert!(pdf.contains("/Count -1/Parent"), "{pdf}");
+
+    // The generated outline code is a no-op in plain TeX, and must leave the
+    // input intact.
+    let pdf = build(OutlineOptions {
+        depth: Some(2),
+        open_level: Some(1),
+    });
+    assert!(pdf.contains("/Count 1/Parent"), "{pdf}");
+    assert!(pdf.contains("/Title(Two)"), "{pdf}");
+}
+
+#[test]
+fn pass_by_pass() {
+    util::set_test_root();
+
+    let mut status = TermcolorStatusBackend::new(ChatterLevel::Minimal);
+    let outdir = tempfile::tempdir().unwrap();
+
+    let mut pbuilder = ProcessingSessionBuilder::default();
+    pbuilder
+        .primary_input_buffer(b"Hello \\bye")
+        .tex_input_name("texput.tex")
+        .format_name("plain")
+        .format_cache_path(util::test_path(&[]))
+        .output_dir(outdir.path())
+        .bundle(Box::new(TestBundle::default()));
+
+    let mut session = pbuilder
+        .create(&mut status)
+        .expect("couldn't create processing session");
+
+    session
+        .run_format_pass(&mut status)
+        .expect("failed to get the format");
+    session
+        .run_tex_pass(&mut status)
+        .expect("failed to run TeX");
+    assert!(session.get_file_data("texput.xdv").is_some());
+    assert!(session.get_file_data("texput.pdf").is_none());
+
+    session
+        .run_xdvipdfmx_pass(&mut status)
+        .expect("failed to run xdvipdfmx");
+    let pdf = session
+        .get_file_data("texput.pdf")
+        .expect("no `texput.pdf` in the session");
+    assert!(pdf.starts_with(b"%PDF"));
+
+    // Nothing is written out by the individual passes.
+    assert_eq!(std::fs::read_dir(outdir.path()).unwrap().count(), 0);
+}
+
+#[test]
+fn pdf_encryption() {
+    util::set_test_root();
+
+    let mut status = TermcolorStatusBackend::new(ChatterLevel::Minimal);
+
+    let mut pbuilder = ProcessingSessionBuilder::default();
+    pbuilder
+        .primary_input_buffer(b"Hello \\bye")
+        .tex_input_name("texput.tex")
+        .format_name("plain")
+        .format_cache_path(util::test_path(&[]))
+        .pdf_encryption(Some(EncryptionOptions {
+            owner_password: "editor".to_owned(),
+            user_password: String::new(),
+            allow_printing: false,
+            allow_copying: true,
+        }))
+        .do_not_write_output_files()
+        .bundle(Box::new(TestBundle::default()));
+
+    let mut session = pbuilder
+        .create(&mut status)
+        .expect("couldn't create processing session");
+
+    session
+        .run(&mut status)
+        .expect("failed to execute processing session");
+
+    // The encryption dictionary is never compressed or encrypted. Its
+    // permissions deny printing (bits 3 and 12) and nothing else.
+    let files = session.into_file_data();
+    let pdf = String::from_utf8_lossy(&files["texput.pdf"].data);
+    assert!(pdf.contains("/Encrypt"), "{pdf}");
+    assert!(pdf.contains("/P -2056"), "{pdf}");
+}
+
+#[test]
+fn pdfa() {
+    util::set_test_root();
+
+    let mut status = TermcolorStatusBackend::new(ChatterLevel::Minimal);
+
+    // PDF/A output can't be encrypted, so the request is ignored.
+    let mut pbuilder = ProcessingSessionBuilder::default();
+    pbuilder
+        .primary_input_buffer(
+            b"\\special{pdf:encrypt userpw (u) ownerpw (o) length 128 perm 2052}Hello \\bye",
+        )
+        .tex_input_name("texput.tex")
+        .format_name("plain")
+        .format_cache_path(util::test_path(&[]))
+        .pdf_metadata(PdfMetadata {
+            title: Some("Fish & Chips".to_owned()),
+            ..Default::default()
+        })
+        .pdfa(true)
+        .do_not_write_output_files()
+        .bundle(Box::new(TestBundle::default()));
+
+    let mut session = pbuilder
+        .create(&mut status)
+        .expect("couldn't create processing session");
+
+    session
+        .run(&mut status)
+        .expect("failed to execute processing session");
+
+    // The metadata stream is never compressed, so it can be checked directly.
+    let files = session.into_file_data();
+    let pdf = String::from_utf8_lossy(&files["texput.pdf"].data);
+    assert!(pdf.contains("<pdfaid:part>2</pdfaid:part>"), "{pdf}");
+    assert!(
+        pdf.contains("<pdfaid:conformance>B</pdfaid:conformance>"),
+        "{pdf}"
+    );
+    assert!(pdf.contains(">Fish &amp; Chips</rdf:li>"), "{pdf}");
+    assert!(!pdf.contains("/Encrypt"), "{pdf}");
+}
+
+#[test]
+fn pdfa_unembedded_font() {
+    util::set_test_root();
+
+    /// Collects the errors reported to it.
+    #[derive(Default)]
+    struct ErrorsBackend(Vec<String>);
+
+    impl StatusBackend for ErrorsBackend {
+        fn report(&mut self, kind: MessageKind, args: Arguments, _err: Option<&Error>) {
+            if kind == MessageKind::Error {
+                self.0.push(args.to_string());
+            }
+        }
+
+        fn dump_error_logs(&mut self, _output: &[u8]) {}
+    }
+
+    let mut status = ErrorsBackend::default();
+
+    // Times-Roman is one of the standard fonts that xdvipdfmx doesn't embed.
+    let mut pbuilder = ProcessingSessionBuilder::default();
+    pbuilder
+        .primary_input_buffer(
+            b"\\special{pdf:mapline cmss10 builtin Times-Roman}\\font\\x=cmss10 \\x Hello \\bye",
+        )
+        .tex_input_name("texput.tex")
+        .format_name("plain")
+        .format_cache_path(util::test_path(&[]))
+        .pdfa(true)
+        .do_not_write_output_files()
+        .bundle(Box::new(TestBundle::default()));
+
+    let mut session = pbuilder
+        .create(&mut status)
+        .expect("couldn't create processing session");
+
+    assert!(session.run(&mut status).is_err());
+    assert!(
+        status
+            .0
+            .iter()
+            .any(|e| e.contains("font \"Times-Roman\" is not embedded")),
+        "{:?}",
+        status.0
+    );
+}
+
+#[test]
+fn pinned_build_date() {
+    util::set_test_root();
+
+    let mut status = TermcolorStatusBackend::new(ChatterLevel::Minimal);
+
+    // 2016-02-24 09:01:32 UTC.
+    let date = SystemTime::UNIX_EPOCH + Duration::from_secs(1_456_304_492);
+
+    let mut pbuilder = ProcessingSessionBuilder::default();
+    pbuilder
+        .primary_input_buffer(
+            b"\\immediate\\write-1{CLOCK=\\the\\year/\\the\\month/\\the\\day\\space\\the\\time\\space\
+              \\the\\elapsedtime\\space\\the\\randomseed}\\bye",
+        )
+        .tex_input_name("texput.tex")
+        .format_name("plain")
+        .format_cache_path(util::test_path(&[]))
+        .pinned_build_date(date)
+        .do_not_write_output_files()
+        .bundle(Box::new(TestBundle::default()));
+
+    let mut session = pbuilder
+        .create(&mut status)
+        .expect("couldn't create processing session");
+
+    session
+        .run(&mut status)
+        .expect("failed to execute processing session");
+
+    let log = session
+        .get_file_data("texput.log")
+        .expect("no `texput.log` in the session");
+    let log = String::from_utf8_lossy(&log);
+    assert!(log.contains("CLOCK=2016/2/24 541 0 304492"), "{log}");
+}
+
+#[test]
+fn precompiled_preamble() {
+    util::set_test_root();
+
+    let cache = tempfile::tempdir().unwrap();
+
+    let run = |input: &[u8]| {
+        let mut status = NoopStatusBackend::default();
+
+        let mut pbuilder = ProcessingSessionBuilder::default();
+        pbuilder
+            .primary_input_buffer(input)
+            .memory_input("./snippet.tex", b"\\def\\greeting{Salutations}")
+            .tex_input_name("texput.tex")
+            .format_name("latex")
+            .format_cache_path(cache.path())
+            .unstables(UnstableOptions {
+                precompiled_preamble: true,
+                ..Default::default()
+            })
+            .do_not_write_output_files()
+            .bundle(Box::new(TestBundle::default()));
+
+        let mut session = pbuilder
+            .create(&mut status)
+            .expect("couldn't create processing session");
+
+        session
+            .run(&mut status)
+            .expect("failed to execute processing session");
+
+        let log = session
+            .get_file_data("texput.log")
+            .expect("no `texput.log` in the session");
+        String::from_utf8_lossy(&log).into_owned()
+    };
+
+    let n_preamble_formats = || {
+        std::fs::read_dir(cache.path())
+            .unwrap()
+            .filter(|e| {
+                e.as_ref()
+                    .unwrap()
+                    .file_name()
+                    .to_string_lossy()
+                    .contains("-latex-preamble-")
+            })
+            .count()
+    };
+
+    // The main pass gets the definitions made in the preamble from the format
+    // file, without processing the preamble itself.
+    let log = run(
+        b"\\documentclass{article}\n\\message{In the preamble}\n\\def\\greeting{Hello}\n\
+                    \\begin{document}\\message{\\greeting}\\end{document}\n",
+    );
+    assert!(log.contains("Hello"), "{log}");
+    assert!(!log.contains("In the preamble"), "{log}");
+    assert_eq!(n_preamble_formats(), 1);
+
+    // The format is reused as long as the preamble stays the same.
+    let log = run(
+        b"\\documentclass{article}\n\\message{In the preamble}\n\\def\\greeting{Hello}\n\
+                    \\begin{document}\\message{\\greeting\\space again}\\end{document}\n",
+    );
+    assert!(log.contains("Hello again"), "{log}");
+    assert_eq!(n_preamble_formats(), 1);
+
+    let log = run(b"\\documentclass{article}\n\\def\\greeting{Howdy}\n\
+                    \\begin{document}\\message{\\greeting}\\end{document}\n");
+    assert!(log.contains("Howdy"), "{log}");
+    assert_eq!(n_preamble_formats(), 2);
+
+    // Preambles that use files from outside of the bundle are processed as
+    // usual.
+    let log = run(b"\\documentclass{article}\n\\input snippet\n\
+                    \\begin{document}\\message{\\greeting}\\end{document}\n");
+    assert!(log.contains("Salutations"), "{log}");
+    assert_eq!(n_preamble_formats(), 2);
+}
+
+#[test]
+fn progress_reports() {
+    util::set_test_root();
+
+    /// Collects the progress reported to it, with `None` marking the end of
+    /// a task.
+    #[derive(Default)]
+    struct ProgressBackend(Vec<Option<Progress>>);
+
+    impl StatusBackend for ProgressBackend {
+        fn report(&mut self, _kind: MessageKind, _args: Arguments, _err: Option<&Error>) {}
+
+        fn report_progress(&mut self, progress: &Progress) {
+            self.0.push(Some(progress.clone()));
+        }
+
+        fn finish_progress(&mut self) {
+            self.0.push(None);
+        }
+
+        fn dump_error_logs(&mut self, _output: &[u8]) {}
+    }
+
+    let mut status = ProgressBackend::default();
+
+    let mut pbuilder = ProcessingSessionBuilder::default();
+    pbuilder
+        .primary_input_buffer(b"One.\\vfill\\eject Two.\\bye")
+        .tex_input_name("texput.tex")
+        .format_name("plain")
+        .format_cache_path(util::test_path(&[]))
+        .do_not_write_output_files()
+        .bundle(Box::new(TestBundle::default()));
+
+    let mut session = pbuilder
+        .create(&mut status)
+        .expect("couldn't create processing session");
+
+    session
+        .run(&mut status)
+        .expect("failed to execute processing session");
+
+    let reports: Vec<_> = status.0.iter().flatten().collect();
+    let tex: Vec<_> = reports
+        .iter()
+        .filter(|p| p.phase == "running TeX")
+        .collect();
+    assert!(tex
+        .iter()
+        .any(|p| p.file.as_ref().is_some_and(|f| f.ends_with("texput.tex"))));
+    assert_eq!(tex.last().unwrap().page, Some(2));
+
+    let pdf_pages: Vec<_> = reports
+        .iter()
+        .filter(|p| p.phase == "writing PDF")
+        .filter_map(|p| p.page.map(|n| (n, p.percent)))
+        .collect();
+    assert_eq!(pdf_pages.first(), Some(&(1, Some(50.))));
+    assert_eq!(pdf_pages.last(), Some(&(2, Some(100.))));
+
+    // Each task is finished before the next begins.
+    let mut in_task = false;
+
+    for report in &status.0 {
+        match report {
+            Some(p) if p.file.is_none() && p.page.is_none() => {
+                assert!(!in_task, "{p:?}");
+                in_task = true;
+            }
+            Some(_) => assert!(in_task),
+            None => in_task = false,
+        }
+    }
+
+    assert!(!in_task);
+}
+
+#[test]
+fn structure_tags() {
+    util::set_test_root();
+
+    let mut status = TermcolorStatusBackend::new(ChatterLevel::Minimal);
+
+    let mut pbuilder = ProcessingSessionBuilder::default();
+    pbuilder
+        .primary_input_buffer(
+            b"\\special{pdf:bstruct /Figure <</Alt (A red box)>>}\\XeTeXpicfile redbox.png\\special{pdf:estruct}\\bye",
+        )
+        .tex_input_name("texput.tex")
+        .format_name("plain")
+        .format_cache_path(util::test_path(&[]))
+        .unstables(UnstableOptions {
+            no_pdf_object_streams: true,
+            ..Default::default()
+        })
+        .do_not_write_output_files()
+        .bundle(Box::new(TestBundle::default()));
+
+    let mut session = pbuilder
+        .create(&mut status)
+        .expect("couldn't create processing session");
+
+    session
+        .run(&mut status)
+        .expect("failed to execute processing session");
+
+    // Without object streams, the structure dictionaries are left
+    // uncompressed.
+    let files = session.into_file_data();
+    let pdf = String::from_utf8_lossy(&files["texput.pdf"].data);
+    assert!(pdf.contains("/StructTreeRoot"), "{pdf}");
+    assert!(pdf.contains("/MarkInfo<</Marked true>>"), "{pdf}");
+    assert!(pdf.contains("/Alt(A red box)"), "{pdf}");
+    assert!(pdf.contains("/S/Figure"), "{pdf}");
+    assert!(pdf.contains("/StructParents 0"), "{pdf}");
+}
+
+#[test]
+fn synctex_memory_inputs() {
+    util::set_test_root();
+
+    let mut status = TermcolorStatusBackend::new(ChatterLevel::Minimal);
+
+    let mut pbuilder = ProcessingSessionBuilder::default();
+    pbuilder
+        .primary_input_buffer(b"\\input snippet \\bye")
+        .memory_input("snippet.tex", b"Seeded content.")
+        .tex_input_name("texput.tex")
+        .format_name("plain")
+        .format_cache_path(util::test_path(&[]))
+        .synctex(true)
+        .do_not_write_output_files()
+        .bundle(Box::new(TestBundle::default()));
+
+    let mut session = pbuilder
+        .create(&mut status)
+        .expect("couldn't create processing session");
+
+    session
+        .run(&mut status)
+        .expect("failed to execute processing session");
+
+    let files = session.into_file_data();
+    let mut synctex = String::new();
+    GzDecoder::new(&files["texput.synctex.gz"].data[..])
+        .read_to_string(&mut synctex)
+        .unwrap();
+
+    // Files without filesystem paths are recorded by the names that they
+    // were opened with.
+    assert!(synctex.contains("Input:1:texput.tex\n"), "{synctex}");
+    assert!(synctex.contains(":snippet.tex\n"), "{synctex}");
+}
+
+#[test]
+fn system_fonts() {
+    util::set_test_root();
+
+    let mut status = NoopStatusBackend::default();
+
+    let mut pbuilder = ProcessingSessionBuilder::default();
+    pbuilder
+        .primary_input_buffer(b"\\XeTeXtracingfonts=1 \\font\\x=\"Some System Font\" \\bye")
+        .tex_input_name("texput.tex")
+        .format_name("plain")
+        .format_cache_path(util::test_path(&[]))
+        .system_fonts(false)
+        .do_not_write_output_files()
+        .bundle(Box::new(TestBundle::default()));
+
+    let mut session = pbuilder
+        .create(&mut status)
+        .expect("couldn't create processing session");
+
+    // The font can't be found, since the host system isn't searched.
+    assert!(session.run(&mut status).is_err());
+
+    let log = session
+        .get_file_data("texput.log")
+        .expect("no `texput.log` in the session");
+    assert!(String::from_utf8_lossy(&log).contains("-> system fonts are disabled"));
+}
+
+#[test]
+fn tex_diagnostics() {
+    util::set_test_root();
+
+    /// Collects the diagnostics reported to it.
+    #[derive(Default)]
+    struct DiagnosticsBackend(Vec<SourceDiagnostic>);
+
+    impl StatusBackend for DiagnosticsBackend {
+        fn report(&mut self, _kind: MessageKind, _args: Arguments, _err: Option<&Error>) {}
+
+        fn report_diagnostic(&mut self, diag: &SourceDiagnostic) {
+            self.0.push(diag.clone());
+        }
+
+        fn dump_error_logs(&mut self, _output: &[u8]) {}
+    }
+
+    let mut status = DiagnosticsBackend::default();
+
+    let mut pbuilder = ProcessingSessionBuilder::default();
+    pbuilder
+        .primary_input_buffer(b"Hello\n\\foo world\n\\bye\n")
+        .tex_input_name("texput.tex")
+        .format_name("plain")
+        .format_cache_path(util::test_path(&[]))
+        .do_not_write_output_files()
+        .bundle(Box::new(TestBundle::default()));
+
+    let mut session = pbuilder
+        .create(&mut status)
+        .expect("couldn't create processing session");
+
+    assert!(session.run(&mut status).is_err());
+    assert_eq!(status.0.len(), 1);
+
+    let diag = &status.0[0];
+    assert_eq!(diag.kind, MessageKind::Error);
+    assert!(diag.file.as_deref().unwrap().ends_with("texput.tex"));
+    assert_eq!(diag.line, Some(2));
+    assert_eq!(diag.message, "Undefined control sequence.");
+    assert_eq!(diag.context[0], "l.2 \\foo");
+    assert_eq!(diag.source_line.as_deref(), Some("\\foo world"));
+
+    let snippet = SourceSnippet::from_diagnostic(diag).unwrap();
+    assert_eq!(snippet.caret, Some((0, 4)));
+}
+
+#[test]
+fn tex_memory_parameter() {
+    util::set_test_root();
+
+    // Each level of this recursion leaves tokens on TeX's input stack, so it
+    // nests deeper than the default stack size allows.
+    let input: &[u8] = b"\\newcount\\n \\n=6000 \\def\\a{\\ifnum\\n>0 \\advance\\n by -1 \\a\\relax\\fi}\\a x\\bye";
+
+    for (stack_size, ok) in [(None, false), (Some(10_000), true)] {
+        let mut status = TermcolorStatusBackend::new(ChatterLevel::Minimal);
+
+        let mut pbuilder = ProcessingSessionBuilder::default();
+        pbuilder
+            .primary_input_buffer(input)
+            .tex_input_name("texput.tex")
+            .format_name("plain")
+            .format_cache_path(util::test_path(&[]))
+            .do_not_write_output_files()
+            .bundle(Box::new(TestBundle::default()));
+
+        if let Some(value) = stack_size {
+            pbuilder.tex_memory_parameter(MemoryParameter::StackSize, value);
+        }
+
+        let mut session = pbuilder
+            .create(&mut status)
+            .expect("couldn't create processing session");
+
+        assert_eq!(session.run(&mut status).is_ok(), ok);
+    }
+}
+
 #[test]
 fn the_letter_a() {
     util::set_test_root();
@@ -50,3 +921,27 @@ fn the_letter_a() {
         .run(&mut status)
         .expect("failed to execute processing session");
 }
+
+#[test]
+fn time_limit() {
+    util::set_test_root();
+
+    let mut status = TermcolorStatusBackend::new(ChatterLevel::Minimal);
+
+    // This document never finishes.
+    let mut pbuilder = ProcessingSessionBuilder::default();
+    pbuilder
+        .primary_input_buffer(b"\\def\\a{\\a}\\a")
+        .tex_input_name("texput.tex")
+        .format_name("plain")
+        .format_cache_path(util::test_path(&[]))
+        .time_limit(Duration::from_secs(1))
+        .do_not_write_output_files()
+        .bundle(Box::new(TestBundle::default()));
+
+    let mut session = pbuilder
+        .create(&mut status)
+        .expect("couldn't create processing session");
+
+    assert!(session.run(&mut status).is_err());
+}
@@ -13,6 +13,7 @@ use std::{
     str, thread,
     time::{Duration, Instant},
 };
+use tectonic_docmodel::document::{Document, InputFile};
 use tempfile::TempDir;
 
 #[path = "util/mod.rs"]
@@ -408,6 +409,39 @@ fn biber_tectonic_override() {
     success_or_panic(&output);
 }
 
+/// Test `-Z biber=<path>`: the given executable is used instead of looking
+/// for `tectonic-biber` or `biber`.
+#[cfg(unix)]
+#[test]
+fn biber_unstable_path() {
+    let fmt_arg = get_plain_format_arg();
+    let biber_arg = format!(
+        "-Zbiber={}",
+        util::test_path(&["executable", "tectonic-biber"]).display()
+    );
+    let tempdir = setup_and_copy_files(&["subdirectory/empty.bib"]);
+    let mut command = prep_tectonic(tempdir.path(), &[&fmt_arg, &biber_arg, "-"]);
+    command.env("TECTONIC_TEST_FAKE_BIBER", "");
+
+    command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    println!("running {command:?}");
+    let mut child = command.spawn().expect("tectonic failed to start");
+
+    write!(
+        child.stdin.as_mut().unwrap(),
+        "{BIBER_TRIGGER_TEX}{BIBER_VALIDATE_TEX}"
+    )
+    .expect("failed to send data to tectonic subprocess");
+
+    let output = child
+        .wait_with_output()
+        .expect("failed to wait on tectonic subprocess");
+    success_or_panic(&output);
+}
+
 /// #844: biber input with absolute path blows away the file
 ///
 /// We need to create a separate temporary directory to see if the abspath input
@@ -452,12 +486,286 @@ fn bibtex_multiple_aux_files() {
     success_or_panic(&output);
 }
 
+/// BibTeX should be rerun if the citations change after it has run once.
+#[test]
+fn bibtex_rerun_on_new_citations() {
+    let fmt_arg = get_plain_format_arg();
+    let tempdir = setup_and_copy_files(&["bibtex_rerun.tex", "refs.bib", "catchkey.bst"]);
+    let output = run_tectonic(
+        tempdir.path(),
+        &[&fmt_arg, "--keep-intermediates", "bibtex_rerun.tex"],
+    );
+    success_or_panic(&output);
+
+    let mut bbl = String::new();
+    File::open(tempdir.path().join("bibtex_rerun.bbl"))
+        .expect("`bibtex_rerun.bbl` not found")
+        .read_to_string(&mut bbl)
+        .expect("Cannot read `bibtex_rerun.bbl`");
+
+    assert!(bbl.contains("\\sawrefB"));
+}
+
+/// Index files written by the document should be run through makeindex.
+#[cfg(unix)]
+#[test]
+fn makeindex_pass() {
+    let fmt_arg = get_plain_format_arg();
+    let makeindex_arg = format!(
+        "-Zmakeindex={}",
+        util::test_path(&["executable", "fake-makeindex"]).display()
+    );
+    let tempdir = setup_and_copy_files(&["makeindex.tex"]);
+    let output = run_tectonic(
+        tempdir.path(),
+        &[
+            &fmt_arg,
+            &makeindex_arg,
+            "-Zshell-escape",
+            "--keep-intermediates",
+            "makeindex.tex",
+        ],
+    );
+    success_or_panic(&output);
+
+    let mut ind = String::new();
+    File::open(tempdir.path().join("makeindex.ind"))
+        .expect("`makeindex.ind` not found")
+        .read_to_string(&mut ind)
+        .expect("Cannot read `makeindex.ind`");
+
+    assert!(ind.contains("\\indexed"));
+}
+
+/// An external makeindex is only run if shell-escape is allowed; otherwise
+/// the builtin one is used.
+#[cfg(unix)]
+#[test]
+fn makeindex_external_needs_shell_escape() {
+    let fmt_arg = get_plain_format_arg();
+    let makeindex_arg = format!(
+        "-Zmakeindex={}",
+        util::test_path(&["executable", "fake-makeindex"]).display()
+    );
+    let tempdir = setup_and_copy_files(&["makeindex_builtin.tex"]);
+    let output = run_tectonic(
+        tempdir.path(),
+        &[
+            &fmt_arg,
+            &makeindex_arg,
+            "--keep-intermediates",
+            "makeindex_builtin.tex",
+        ],
+    );
+    success_or_panic(&output);
+
+    let ilg = std::fs::read_to_string(tempdir.path().join("makeindex_builtin.ilg"))
+        .expect("`makeindex_builtin.ilg` not found");
+    assert!(ilg.contains("builtin makeindex"));
+}
+
+/// Without an external program, the builtin makeindex processes the index.
+#[test]
+fn makeindex_builtin() {
+    let fmt_arg = get_plain_format_arg();
+    let tempdir = setup_and_copy_files(&["makeindex_builtin.tex"]);
+    let output = run_tectonic(
+        tempdir.path(),
+        &[&fmt_arg, "--keep-intermediates", "makeindex_builtin.tex"],
+    );
+    success_or_panic(&output);
+
+    let ind = std::fs::read_to_string(tempdir.path().join("makeindex_builtin.ind"))
+        .expect("`makeindex_builtin.ind` not found");
+    assert!(ind.contains("\\item alpha, 1\n\n  \\indexspace\n\n  \\item beta, 2"));
+}
+
+/// Glossaries declared in the `.aux` file are sorted with the builtin
+/// makeindex and the style file written by the document.
+#[test]
+fn glossaries_builtin() {
+    let fmt_arg = get_plain_format_arg();
+    let tempdir = setup_and_copy_files(&["glossaries_builtin.tex"]);
+    let output = run_tectonic(
+        tempdir.path(),
+        &[&fmt_arg, "--keep-intermediates", "glossaries_builtin.tex"],
+    );
+    success_or_panic(&output);
+
+    let gls = std::fs::read_to_string(tempdir.path().join("glossaries_builtin.gls"))
+        .expect("`glossaries_builtin.gls` not found");
+    assert!(gls.starts_with("\\begingls\n"));
+    assert!(gls.contains("\\gls alpha :: 1\n\\gls beta :: 2"));
+    assert!(gls.ends_with("\\endgls\n"));
+    assert!(tempdir.path().join("glossaries_builtin.glg").exists());
+}
+
+/// `-X bibtex` runs just BibTeX on an `.aux` file.
+#[test]
+fn v2_bibtex() {
+    let tempdir = setup_and_copy_files(&["bibtex_only.aux", "refs.bib", "catchkey.bst"]);
+    let output = run_tectonic(tempdir.path(), &["-X", "bibtex", "bibtex_only.aux"]);
+    success_or_panic(&output);
+
+    let mut bbl = String::new();
+    File::open(tempdir.path().join("bibtex_only.bbl"))
+        .expect("`bibtex_only.bbl` not found")
+        .read_to_string(&mut bbl)
+        .expect("Cannot read `bibtex_only.bbl`");
+
+    assert_eq!(bbl, "\\sawrefB\n");
+    assert!(tempdir.path().join("bibtex_only.blg").exists());
+}
+
+/// `--build-log` writes all of the status messages to a file, whatever the
+/// chatter level.
+#[test]
+fn build_log() {
+    let fmt_arg = get_plain_format_arg();
+    let tempdir = setup_and_copy_files(&[]);
+    let output = run_tectonic_with_stdin(
+        tempdir.path(),
+        &[
+            "--chatter",
+            "minimal",
+            "--build-log",
+            "build.log",
+            &fmt_arg,
+            "-",
+        ],
+        "Hello\\bye",
+    );
+    success_or_panic(&output);
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("Running TeX"));
+
+    let log = fs::read_to_string(tempdir.path().join("build.log")).expect("no build log");
+    assert!(log.contains("note: Running TeX ...\n"), "{log}");
+    assert!(log.contains("note: Writing `"), "{log}");
+    assert!(log.contains("texput.pdf"), "{log}");
+}
+
+#[test]
+fn warnings_summarized() {
+    let fmt_arg = get_plain_format_arg();
+    let tempdir = setup_and_copy_files(&[]);
+
+    // Both passes find the same overfull box.
+    let output = run_tectonic_with_stdin(
+        tempdir.path(),
+        &[&fmt_arg, "--reruns", "1", "-"],
+        "\\hbox to 1pt{Hello world}\\bye",
+    );
+    success_or_panic(&output);
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(stderr.matches("Overfull \\hbox").count(), 1, "{stderr}");
+    assert!(stderr.contains("(in 2 of 2 TeX passes)"), "{stderr}");
+}
+
 #[test]
 fn help_flag() {
     let output = run_tectonic(&PathBuf::from("."), &["-h"]);
     success_or_panic(&output);
 }
 
+#[test]
+fn interaction_mode() {
+    let fmt_arg = get_plain_format_arg();
+    let doc = "\\undefined Text after the error.\\bye";
+
+    // The build halts at the first error by default, and in errorstopmode.
+    for args in [&[][..], &["--interaction", "errorstopmode"][..]] {
+        let tempdir = setup_and_copy_files(&[]);
+        let mut all_args = vec![fmt_arg.as_str(), "-"];
+        all_args.extend_from_slice(args);
+        let output = run_tectonic_with_stdin(tempdir.path(), &all_args, doc);
+        error_or_panic(&output);
+    }
+
+    let tempdir = setup_and_copy_files(&[]);
+    let output = run_tectonic_with_stdin(
+        tempdir.path(),
+        &[&fmt_arg, "-", "--interaction", "nonstopmode"],
+        doc,
+    );
+    success_or_panic(&output);
+    check_file(&tempdir, "texput.pdf");
+}
+
+#[test]
+fn jobname_option() {
+    let fmt_arg = get_plain_format_arg();
+    let tempdir = setup_and_copy_files(&[]);
+
+    // The same input can be built under several names.
+    for name in ["draft", "final"] {
+        let output = run_tectonic_with_stdin(
+            tempdir.path(),
+            &[&fmt_arg, "-", "--jobname", name, "--keep-logs"],
+            "\\immediate\\write-1{JOB=\\jobname}Hello.\\bye",
+        );
+        success_or_panic(&output);
+        check_file(&tempdir, &format!("{name}.pdf"));
+
+        let log = fs::read_to_string(tempdir.path().join(format!("{name}.log"))).unwrap();
+        assert!(log.contains(&format!("JOB={name}")));
+    }
+
+    assert!(!tempdir.path().join("texput.pdf").exists());
+}
+
+#[test]
+fn keep_extensions() {
+    let fmt_arg = get_plain_format_arg();
+    let tempdir = setup_and_copy_files(&[]);
+    let output = run_tectonic_with_stdin(
+        tempdir.path(),
+        &[&fmt_arg, "-", "--keep", "toc,log"],
+        "\\immediate\\openout1=texput.toc \\immediate\\write1{toc}\\immediate\\closeout1
+\\immediate\\openout1=texput.snm \\immediate\\write1{snm}\\immediate\\closeout1
+\\bye",
+    );
+    success_or_panic(&output);
+
+    // `.toc` and `.snm` files are always treated as intermediates.
+    assert!(tempdir.path().join("texput.toc").exists());
+    assert!(tempdir.path().join("texput.log").exists());
+    assert!(!tempdir.path().join("texput.snm").exists());
+}
+
+/// The trace in the TeX log is saved separately, as an intermediate file.
+#[test]
+fn keep_trace() {
+    let fmt_arg = get_plain_format_arg();
+    let tempdir = setup_and_copy_files(&[]);
+    let output = run_tectonic_with_stdin(
+        tempdir.path(),
+        &[&fmt_arg, "-", "--keep-intermediates"],
+        "\\tracingmacros=1 \\def\\greet#1{Hello, #1!}\\greet{world}\\bye",
+    );
+    success_or_panic(&output);
+
+    let trace = fs::read_to_string(tempdir.path().join("texput.trace.jsonl")).unwrap();
+    assert!(trace.contains(r#""kind":"macro""#));
+    assert!(trace.contains(r#""args":["world"]"#));
+}
+
+/// The XDV file can be kept along with the PDF made from it.
+#[test]
+fn keep_xdv() {
+    let fmt_arg = get_plain_format_arg();
+    let tempdir = setup_and_copy_files(&[]);
+    let output = run_tectonic_with_stdin(
+        tempdir.path(),
+        &[&fmt_arg, "-", "--keep", "xdv"],
+        "Hello.\\bye",
+    );
+    success_or_panic(&output);
+
+    check_file(&tempdir, "texput.pdf");
+    check_file(&tempdir, "texput.xdv");
+}
+
 #[test]
 fn keep_logs_on_error() {
     // No input files here, but output files are created.
@@ -560,6 +868,115 @@ fn outdir_option_is_file() {
     success_or_panic(&output);
 }
 
+#[test]
+fn output_format_json() {
+    let fmt_arg = get_plain_format_arg();
+    let tempdir = setup_and_copy_files(&[]);
+    fs::write(
+        tempdir.path().join("main.tex"),
+        "Some text.\n\\undefined\n\\bye\n",
+    )
+    .unwrap();
+
+    let output = run_tectonic(
+        tempdir.path(),
+        &[&fmt_arg, "main.tex", "--output-format=json"],
+    );
+    error_or_panic(&output);
+
+    // Everything goes to stderr, one JSON object per line.
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    for line in stderr.lines() {
+        assert!(line.starts_with("{\"severity\":\""), "{line}");
+        assert!(line.ends_with("]}"), "{line}");
+    }
+
+    assert!(
+        stderr.lines().any(|line| line
+            .starts_with("{\"severity\":\"error\",\"message\":\"Undefined control sequence.")
+            && line.contains("main.tex\",\"line\":2,")),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn output_format_lsp() {
+    let fmt_arg = get_plain_format_arg();
+    let tempdir = setup_and_copy_files(&[]);
+    fs::write(
+        tempdir.path().join("main.tex"),
+        "Some text.\n\\undefined\n\\bye\n",
+    )
+    .unwrap();
+
+    let output = run_tectonic(
+        tempdir.path(),
+        &[&fmt_arg, "main.tex", "--output-format=lsp"],
+    );
+    error_or_panic(&output);
+
+    // The diagnostics for main.tex are the only thing on stdout, with the
+    // range covering `\undefined` on the second line.
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<_> = stdout.lines().collect();
+    assert_eq!(lines.len(), 1, "{stdout}");
+    assert!(lines[0].starts_with("{\"uri\":\"file://"), "{stdout}");
+    assert!(
+        lines[0].contains("/main.tex\",\"diagnostics\":["),
+        "{stdout}"
+    );
+    assert!(
+        lines[0].contains(
+            "{\"range\":{\"start\":{\"line\":1,\"character\":0},\
+             \"end\":{\"line\":1,\"character\":10}},\"severity\":1,"
+        ),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn pdf_output_options() {
+    let fmt_arg = get_plain_format_arg();
+    let tempdir = setup_and_copy_files(&[]);
+
+    let output = run_tectonic_with_stdin(
+        tempdir.path(),
+        &[
+            &fmt_arg,
+            "-",
+            "-Zpdf-version=1.4",
+            "-Zpdf-compression-level=0",
+        ],
+        "Hello.\\bye",
+    );
+    success_or_panic(&output);
+
+    let pdf = fs::read(tempdir.path().join("texput.pdf")).unwrap();
+    assert!(pdf.starts_with(b"%PDF-1.4\n"));
+    assert!(find_bytes(&pdf, b"/FlateDecode").is_none());
+
+    // Object streams are used by default from PDF 1.5.
+    for (args, objstm) in [(&[][..], true), (&["-Zno-pdf-object-streams"][..], false)] {
+        let mut all_args = vec![fmt_arg.as_str(), "-"];
+        all_args.extend_from_slice(args);
+        let output = run_tectonic_with_stdin(tempdir.path(), &all_args, "Hello.\\bye");
+        success_or_panic(&output);
+
+        let pdf = fs::read(tempdir.path().join("texput.pdf")).unwrap();
+        assert!(pdf.starts_with(b"%PDF-1.5\n"));
+        assert_eq!(find_bytes(&pdf, b"/ObjStm").is_some(), objstm);
+    }
+
+    let output = run_tectonic_with_stdin(
+        tempdir.path(),
+        &[&fmt_arg, "-", "-Zpdf-version=1.9"],
+        "Hello.\\bye",
+    );
+    error_or_panic(&output);
+}
+
 #[test] // GitHub #31
 fn relative_include() {
     let fmt_arg = get_plain_format_arg();
@@ -576,6 +993,56 @@ fn relative_include() {
     check_file(&tempdir, "subdirectory/relative_include.pdf");
 }
 
+/// Run a plain TeX document from the standard input, returning the combined
+/// output of the run.
+fn run_rerun_document(doc: &str) -> String {
+    let fmt_arg = get_plain_format_arg();
+    let tempdir = setup_and_copy_files(&[]);
+    let output = run_tectonic_with_stdin(tempdir.path(), &[&fmt_arg, "-"], doc);
+    success_or_panic(&output);
+
+    let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+    text.push_str(&String::from_utf8_lossy(&output.stderr));
+    text
+}
+
+/// TeX isn't rerun endlessly if the files that it reads flip back and forth.
+#[test]
+fn rerun_cycle() {
+    let output = run_rerun_document(
+        "\\def\\x{A}
+\\newread\\state \\openin\\state=texput.aux
+\\ifeof\\state \\else \\closein\\state \\input texput.aux \\fi
+\\immediate\\openout1=texput.aux
+\\if A\\x \\immediate\\write1{\\noexpand\\def\\noexpand\\x{B}}%
+\\else \\immediate\\write1{\\noexpand\\def\\noexpand\\x{A}}\\fi
+\\immediate\\closeout1
+x\\bye",
+    );
+
+    assert_eq!(output.matches("Rerunning").count(), 2, "{output}");
+    assert!(output.contains("are cycling"), "{output}");
+}
+
+/// TeX isn't rerun once the files that it reads stop changing.
+#[test]
+fn rerun_fixed_point() {
+    let output = run_rerun_document(
+        "\\newread\\state \\openin\\state=texput.aux
+\\ifeof\\state \\else \\closein\\state \\input texput.aux \\fi
+\\immediate\\openout1=texput.aux
+\\immediate\\write1{\\noexpand\\def\\noexpand\\x{done}}
+\\immediate\\closeout1
+x\\bye",
+    );
+
+    assert_eq!(output.matches("Rerunning").count(), 1, "{output}");
+    assert!(
+        output.contains("because \"texput.aux\" changed"),
+        "{output}"
+    );
+}
+
 // Regression #36
 #[test]
 fn space_in_filename() {
@@ -599,23 +1066,83 @@ fn stdin_content() {
     success_or_panic(&output);
 }
 
-/// Test various web bundle overrides for the v1 CLI & `-X compile`
+#[cfg(feature = "serialization")]
 #[test]
-fn bundle_overrides() {
-    let filename = "subdirectory/content/1.tex";
-    let fmt_arg: &str = &get_plain_format_arg();
-    let tempdir = setup_and_copy_files(&[filename]);
-    let temppath = tempdir.path().to_owned();
-
-    let arg_bad_bundle = ["--bundle", "bad-bundle"];
-    let arg_good_bundle = ["--bundle", "test-bundle://"];
-
-    // test with a bad bundle
-    let output = run_tectonic(
-        &temppath,
-        &[&arg_bad_bundle[..], &[fmt_arg, filename]].concat(),
+fn v2_compile_stdin_to_stdout() {
+    // The document goes from standard input to standard output, and no files
+    // are created.
+    let fmt_arg = get_plain_format_arg();
+    let tempdir = setup_and_copy_files(&[]);
+    let output = run_tectonic_with_stdin(
+        tempdir.path(),
+        &["-X", "compile", &fmt_arg, "-", "--output", "-"],
+        "Standard input content.\\bye",
     );
-    error_or_panic(&output);
+    success_or_panic(&output);
+    assert!(output.stdout.starts_with(b"%PDF-"));
+    assert_eq!(fs::read_dir(tempdir.path()).unwrap().count(), 0);
+
+    let output = run_tectonic_with_stdin(
+        tempdir.path(),
+        &["-X", "compile", &fmt_arg, "-", "--output", "doc.pdf"],
+        "Standard input content.\\bye",
+    );
+    success_or_panic(&output);
+    check_file(&tempdir, "doc.pdf");
+    assert!(!tempdir.path().join("texput.pdf").exists());
+}
+
+#[test]
+fn zip_input() {
+    let fmt_arg = get_plain_format_arg();
+    let tempdir = setup_and_copy_files(&[]);
+
+    // Files in the archive are found relative to the main file's directory.
+    let mut zip = zip::ZipWriter::new(File::create(tempdir.path().join("paper.zip")).unwrap());
+    let options = zip::write::SimpleFileOptions::default();
+    zip.start_file("paper/main.tex", options).unwrap();
+    zip.write_all(b"\\input sec/body.inc \\bye").unwrap();
+    zip.start_file("paper/sec/body.inc", options).unwrap();
+    zip.write_all(b"Archived content.").unwrap();
+    zip.finish().unwrap();
+
+    let output = run_tectonic(tempdir.path(), &[&fmt_arg, "paper.zip"]);
+    success_or_panic(&output);
+    check_file(&tempdir, "paper.pdf");
+}
+
+#[test]
+fn compile_open() {
+    let fmt_arg = get_plain_format_arg();
+    let tempdir = setup_and_copy_files(&[]);
+    let output = run_tectonic_with_stdin(
+        tempdir.path(),
+        &[&fmt_arg, "--open", "-"],
+        "Standard input content.\\bye",
+    );
+    success_or_panic(&output);
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("not opening `texput.pdf` -- test mode"));
+}
+
+/// Test various web bundle overrides for the v1 CLI & `-X compile`
+#[test]
+fn bundle_overrides() {
+    let filename = "subdirectory/content/1.tex";
+    let fmt_arg: &str = &get_plain_format_arg();
+    let tempdir = setup_and_copy_files(&[filename]);
+    let temppath = tempdir.path().to_owned();
+
+    let arg_bad_bundle = ["--bundle", "bad-bundle"];
+    let arg_good_bundle = ["--bundle", "test-bundle://"];
+
+    // test with a bad bundle
+    let output = run_tectonic(
+        &temppath,
+        &[&arg_bad_bundle[..], &[fmt_arg, filename]].concat(),
+    );
+    error_or_panic(&output);
 
     // test with a good bundle (override)
     let mut valid_args: Vec<Vec<&str>> = vec![
@@ -705,6 +1232,52 @@ fn v2_build_basic() {
     success_or_panic(&output);
 }
 
+/// The V2 CLI takes `--build-log` too.
+#[cfg(feature = "serialization")]
+#[test]
+fn v2_build_log() {
+    let (_tempdir, temppath) = setup_v2();
+    let output = run_tectonic(&temppath, &["-X", "--build-log", "build.log", "build"]);
+    success_or_panic(&output);
+
+    let log = fs::read_to_string(temppath.join("build.log")).expect("no build log");
+    assert!(log.contains("note: Running TeX ...\n"), "{log}");
+}
+
+/// `--bundle-deps` lists the bundle files that a build used, and prefetches
+/// them in the next build.
+#[cfg(feature = "serialization")]
+#[test]
+fn v2_build_bundle_deps() {
+    let (_tempdir, temppath) = setup_v2();
+    let args = ["-X", "build", "--bundle-deps", "bundle.deps"];
+
+    // The list doesn't exist before the first build, which writes it.
+    let output = run_tectonic(&temppath, &args);
+    success_or_panic(&output);
+
+    let deps = fs::read_to_string(temppath.join("bundle.deps")).unwrap();
+    assert!(!deps.is_empty());
+
+    // The next build prefetches the files, ignoring any that the bundle
+    // doesn't have, and lists the files that it used in turn. The first build
+    // may have generated the format file too, which needs more files.
+    fs::write(
+        temppath.join("bundle.deps"),
+        format!("{deps}nonexistent.sty\n"),
+    )
+    .unwrap();
+    let output = run_tectonic(&temppath, &args);
+    success_or_panic(&output);
+
+    let new_deps = fs::read_to_string(temppath.join("bundle.deps")).unwrap();
+    assert!(!new_deps.is_empty());
+    assert!(
+        new_deps.lines().all(|n| deps.lines().any(|d| d == n)),
+        "{new_deps}"
+    );
+}
+
 #[test]
 #[cfg(all(feature = "serialization", not(windows)))] // `echo` may not be available
 fn v2_build_open() {
@@ -794,6 +1367,403 @@ fn v2_build_multiple_outputs() {
     success_or_panic(&output);
 }
 
+#[test]
+fn v2_build_profile() {
+    let (_tempdir, temppath) = setup_v2();
+
+    {
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(temppath.join("Tectonic.toml"))
+            .unwrap();
+        writeln!(file, "[profile.draft.variables]\nmode = 'draft'").unwrap();
+    }
+
+    fs::write(
+        temppath.join("src").join("index.tex"),
+        "\\def\\expected{draft}\\ifx\\mode\\expected Draft.\\else\\wrongvalue\\fi\n",
+    )
+    .unwrap();
+
+    let output = run_tectonic(&temppath, &["-X", "build", "--profile", "draft"]);
+    success_or_panic(&output);
+
+    // Without the profile, `\mode` isn't defined.
+    let output = run_tectonic(&temppath, &["-X", "build"]);
+    error_or_panic(&output);
+
+    let output = run_tectonic(&temppath, &["-X", "build", "--profile", "nonesuch"]);
+    error_or_panic(&output);
+}
+
+/// The `[output.bibtex]` settings should be passed on to BibTeX.
+#[test]
+fn v2_build_bibtex_settings() {
+    let (_tempdir, temppath) = setup_v2();
+
+    {
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(temppath.join("Tectonic.toml"))
+            .unwrap();
+        writeln!(
+            file,
+            "[output.bibtex]\nstyle = 'catchkey'\nsearch_paths = ['refs']"
+        )
+        .unwrap();
+    }
+
+    fs::create_dir(temppath.join("refs")).unwrap();
+    fs::copy(
+        util::test_path(&["executable", "refs.bib"]),
+        temppath.join("refs").join("refs.bib"),
+    )
+    .unwrap();
+    fs::copy(
+        util::test_path(&["executable", "catchkey.bst"]),
+        temppath.join("src").join("catchkey.bst"),
+    )
+    .unwrap();
+
+    // The style named here doesn't exist, so BibTeX fails unless the
+    // configured style is used instead.
+    fs::write(
+        temppath.join("src").join("index.tex"),
+        "\\newwrite\\w\n\
+         \\immediate\\openout\\w=default.aux\\relax\n\
+         \\immediate\\write\\w{\\string\\bibdata{refs}}\n\
+         \\immediate\\write\\w{\\string\\citation{refA}}\n\
+         \\immediate\\write\\w{\\string\\bibstyle{nonexistent}}\n\
+         \\immediate\\closeout\\w\n\
+         Hello.\n",
+    )
+    .unwrap();
+
+    let output = run_tectonic(&temppath, &["-X", "build", "--keep-intermediates"]);
+    success_or_panic(&output);
+
+    let bbl = fs::read_to_string(temppath.join("build").join("default").join("default.bbl"))
+        .expect("`default.bbl` not found");
+    assert!(bbl.contains("\\sawrefA"));
+}
+
+/// A "pre" hook's output should be available to the TeX engine.
+#[cfg(unix)]
+#[test]
+fn v2_build_hooks() {
+    let (_tempdir, temppath) = setup_v2();
+
+    {
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(temppath.join("Tectonic.toml"))
+            .unwrap();
+        writeln!(
+            file,
+            "[[hook]]\nstage = 'pre'\ncommand = ['echo', '\\def\\greeting{{hello}}']\nstdout = 'hooked.tex'"
+        )
+        .unwrap();
+    }
+
+    fs::write(
+        temppath.join("src").join("index.tex"),
+        "\\input hooked.tex\n\\def\\expected{hello}\\ifx\\greeting\\expected Hello.\\else\\wrongvalue\\fi\n",
+    )
+    .unwrap();
+
+    let output = run_tectonic(&temppath, &["-X", "build"]);
+    success_or_panic(&output);
+    assert!(!temppath
+        .join("build")
+        .join("default")
+        .join("hooked.tex")
+        .exists());
+
+    // Hooks can't run in untrusted mode.
+    let output = run_tectonic(&temppath, &["-X", "build", "--untrusted"]);
+    error_or_panic(&output);
+}
+
+/// An HTML output should be written to its output directory, using the
+/// built-in template if the document doesn't have one.
+#[test]
+fn v2_build_html() {
+    let (_tempdir, temppath) = setup_v2();
+
+    {
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(temppath.join("Tectonic.toml"))
+            .unwrap();
+        writeln!(file, "[profile.web]\ntype = 'html'").unwrap();
+    }
+
+    let output = run_tectonic(&temppath, &["-X", "build", "--profile", "web"]);
+    success_or_panic(&output);
+
+    let outdir = temppath.join("build").join("default");
+    let index = fs::read_to_string(outdir.join("index.html")).unwrap();
+    assert!(index.starts_with("<!DOCTYPE html>"));
+    assert!(index.contains("tdux-fonts.css"));
+    assert!(outdir.join("tdux-fonts.css").exists());
+    assert!(!outdir.join("default.pdf").exists());
+}
+
+#[test]
+fn v2_build_export_bundle() {
+    let (_tempdir, temppath) = setup_v2();
+
+    let output = run_tectonic(&temppath, &["-X", "build", "--export-bundle", "vendor.tar"]);
+    success_or_panic(&output);
+    assert!(temppath.join("vendor.tar").is_file());
+
+    let toml = fs::read_to_string(temppath.join("Tectonic.toml")).unwrap();
+    assert!(toml.contains("bundle = \"vendor.tar\""));
+
+    // This build reads its support files, and the files needed to make the
+    // format, from the exported bundle ...
+    let output = run_tectonic(&temppath, &["-X", "build"]);
+    success_or_panic(&output);
+
+    // ... so without it, the build fails.
+    fs::remove_file(temppath.join("vendor.tar")).unwrap();
+    let output = run_tectonic(&temppath, &["-X", "build"]);
+    error_or_panic(&output);
+}
+
+#[test]
+fn v2_build_all() {
+    let (tempdir, temppath) = setup_v2();
+
+    // Make a second document alongside the first.
+    let other = tempdir.path().join("other");
+    fs::create_dir_all(other.join("src")).unwrap();
+    fs::copy(temppath.join("Tectonic.toml"), other.join("Tectonic.toml")).unwrap();
+
+    for name in ["_preamble.tex", "index.tex", "_postamble.tex"] {
+        fs::copy(
+            temppath.join("src").join(name),
+            other.join("src").join(name),
+        )
+        .unwrap();
+    }
+
+    let output = run_tectonic(tempdir.path(), &["-X", "build", "--all"]);
+    success_or_panic(&output);
+
+    for dir in [&temppath, &other] {
+        assert!(dir.join("build/default/default.pdf").exists());
+    }
+
+    // A failure in one document is reported without stopping the others.
+    fs::write(other.join("src/index.tex"), "\\undefinedcontrolsequence\n").unwrap();
+    fs::remove_file(temppath.join("build/default/default.pdf")).unwrap();
+
+    let output = run_tectonic(tempdir.path(), &["-X", "build", "--all"]);
+    error_or_panic(&output);
+    assert!(temppath.join("build/default/default.pdf").exists());
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("failed to build `other`"), "{stderr}");
+    assert!(
+        stderr.contains("1 of 2 documents failed to build"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn v2_build_archive() {
+    let (_tempdir, temppath) = setup_v2();
+
+    let output = run_tectonic(&temppath, &["-X", "build", "--archive", "sources.tar.gz"]);
+    success_or_panic(&output);
+
+    let file = File::open(temppath.join("sources.tar.gz")).unwrap();
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(file));
+    let names: Vec<String> = archive
+        .entries()
+        .unwrap()
+        .map(|e| e.unwrap().path().unwrap().display().to_string())
+        .collect();
+
+    // The main file is generated from the document's settings, and the
+    // source files are read from `src`.
+    assert!(names.iter().any(|n| n == "default.tex"), "{names:?}");
+    assert!(names.iter().any(|n| n == "index.tex"), "{names:?}");
+}
+
+#[test]
+fn v2_build_makefile_deps() {
+    let (_tempdir, temppath) = setup_v2();
+
+    let output = run_tectonic(&temppath, &["-X", "build", "--makefile-deps", "deps.d"]);
+    success_or_panic(&output);
+
+    let deps = fs::read_to_string(temppath.join("deps.d")).unwrap();
+    let (targets, inputs) = deps.split_once(':').unwrap();
+    assert!(targets.ends_with("default.pdf"), "{deps}");
+    assert!(inputs.contains("index.tex"), "{deps}");
+    assert!(inputs.contains("Tectonic.toml"), "{deps}");
+}
+
+#[test]
+fn v2_build_variables() {
+    let (_tempdir, temppath) = setup_v2();
+
+    {
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(temppath.join("Tectonic.toml"))
+            .unwrap();
+        writeln!(file, "[output.variables]\ngreeting = 'hello'").unwrap();
+    }
+
+    // This fails with an undefined control sequence unless `\greeting` is
+    // defined as expected.
+    fs::write(
+        temppath.join("src").join("index.tex"),
+        "\\def\\expected{hello}\\ifx\\greeting\\expected Hello.\\else\\wrongvalue\\fi\n",
+    )
+    .unwrap();
+
+    let output = run_tectonic(&temppath, &["-X", "build"]);
+    success_or_panic(&output);
+}
+
+/// Read a PDF file, followed by the decompressed contents of all of its
+/// Flate-encoded streams, so that tests can look for objects that xdvipdfmx
+/// puts in compressed object streams.
+fn read_inflated_pdf(path: &Path) -> Vec<u8> {
+    let pdf = fs::read(path).unwrap();
+    let mut text = pdf.clone();
+    let mut rest = &pdf[..];
+
+    while let Some(start) = find_bytes(rest, b"stream\n") {
+        rest = &rest[start + 7..];
+        let end = find_bytes(rest, b"endstream").unwrap_or(rest.len());
+        let mut inflated = Vec::new();
+
+        if flate2::read::ZlibDecoder::new(&rest[..end])
+            .read_to_end(&mut inflated)
+            .is_ok()
+        {
+            text.extend_from_slice(&inflated);
+        }
+
+        rest = &rest[end..];
+    }
+
+    text
+}
+
+fn find_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+#[test]
+fn v2_build_document_info() {
+    let (_tempdir, temppath) = setup_v2();
+
+    {
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(temppath.join("Tectonic.toml"))
+            .unwrap();
+        writeln!(
+            file,
+            "[doc.info]\ntitle = 'Tectonic Test Title'\nauthor = 'A. U. Thor'\nlanguage = 'en-US'"
+        )
+        .unwrap();
+    }
+
+    let output = run_tectonic(&temppath, &["-X", "build"]);
+    success_or_panic(&output);
+
+    let pdf = read_inflated_pdf(&temppath.join("build").join("default").join("default.pdf"));
+
+    for expected in [
+        &b"/Title(Tectonic Test Title)"[..],
+        b"/Author(A. U. Thor)",
+        b"/Lang(en-US)",
+    ] {
+        assert!(
+            find_bytes(&pdf, expected).is_some(),
+            "didn't find `{}` in the PDF",
+            String::from_utf8_lossy(expected)
+        );
+    }
+}
+
+#[test]
+fn v2_build_pdfa() {
+    let (_tempdir, temppath) = setup_v2();
+
+    {
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(temppath.join("Tectonic.toml"))
+            .unwrap();
+        writeln!(
+            file,
+            "pdfa = true\n\n[doc.info]\ntitle = 'Tectonic Test Title'"
+        )
+        .unwrap();
+    }
+
+    let output = run_tectonic(&temppath, &["-X", "build"]);
+    success_or_panic(&output);
+
+    let pdf = read_inflated_pdf(&temppath.join("build").join("default").join("default.pdf"));
+
+    for expected in [
+        &b"/OutputIntents"[..],
+        b"/S/GTS_PDFA1",
+        b"/DestOutputProfile",
+        b"/Type/Metadata",
+        b"<pdfaid:part>2</pdfaid:part>",
+        b"<rdf:li xml:lang=\"x-default\">Tectonic Test Title</rdf:li>",
+    ] {
+        assert!(
+            find_bytes(&pdf, expected).is_some(),
+            "didn't find `{}` in the PDF",
+            String::from_utf8_lossy(expected)
+        );
+    }
+}
+
+#[test]
+fn v2_build_attachments() {
+    let (_tempdir, temppath) = setup_v2();
+
+    fs::write(temppath.join("results.csv"), "trial,value\n1,42\n").unwrap();
+
+    {
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(temppath.join("Tectonic.toml"))
+            .unwrap();
+        writeln!(file, "attachments = ['results.csv']").unwrap();
+    }
+
+    let output = run_tectonic(&temppath, &["-X", "build"]);
+    success_or_panic(&output);
+
+    let pdf = read_inflated_pdf(&temppath.join("build").join("default").join("default.pdf"));
+
+    for expected in [
+        &b"/EmbeddedFiles"[..],
+        b"/Type/EmbeddedFile",
+        b"/UF(results.csv)",
+        b"trial,value\n1,42\n",
+    ] {
+        assert!(
+            find_bytes(&pdf, expected).is_some(),
+            "didn't find `{}` in the PDF",
+            String::from_utf8_lossy(expected)
+        );
+    }
+}
+
 #[test]
 #[cfg(feature = "serialization")]
 fn v2_dump_basic() {
@@ -863,6 +1833,190 @@ fn v2_dump_suffix() {
     assert!(saw_first && saw_second);
 }
 
+#[test]
+#[cfg(feature = "serialization")]
+fn v2_dump_text() {
+    let (_tempdir, mut temppath) = setup_v2();
+
+    temppath.push("src");
+    temppath.push("index.tex");
+
+    {
+        let mut file = File::create(&temppath).unwrap();
+        writeln!(
+            file,
+            r#"\font\x="[lmroman12-regular]" \x
+Hello, world!\par
+\vfill\eject
+Second page."#
+        )
+        .unwrap();
+    }
+
+    temppath.pop();
+    temppath.pop();
+
+    let output = run_tectonic(&temppath, &["-X", "dump-text"]);
+    success_or_panic(&output);
+
+    let t = std::str::from_utf8(&output.stdout[..]).unwrap();
+    let pages: Vec<_> = t.split('\x0c').collect();
+    assert_eq!(pages.len(), 2);
+    assert!(pages[0].starts_with("Hello, world!"));
+    assert!(pages[1].starts_with("Second page."));
+}
+
+#[test]
+fn v2_fonts_list() {
+    let tempdir = setup_and_copy_files(&[]);
+
+    let output = run_tectonic(tempdir.path(), &["-X", "fonts", "list"]);
+    success_or_panic(&output);
+
+    // Family, style, PostScript name, and location.
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let n_fonts = stdout.lines().count();
+
+    for line in stdout.lines() {
+        assert_eq!(line.split('\t').count(), 4, "{line}");
+    }
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(&format!("listed {n_fonts} of {n_fonts} fonts")),
+        "{stderr}"
+    );
+
+    let output = run_tectonic(
+        tempdir.path(),
+        &["-X", "fonts", "list", "--family", "No Such Family"],
+    );
+    success_or_panic(&output);
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn v2_init_existing() {
+    util::set_test_root();
+
+    let tempdir = setup_and_copy_files(&[]);
+    let temppath = tempdir.path().to_owned();
+
+    fs::write(
+        temppath.join("paper.tex"),
+        "% \\documentclass{report}\n\\documentclass{article}\n\\begin{document}\n\\input{body}\n\\end{document}\n",
+    )
+    .unwrap();
+    fs::write(
+        temppath.join("body.tex"),
+        "Hello.\n\\bibliographystyle{plain}\n\\bibliography{refs, missing}\n",
+    )
+    .unwrap();
+    fs::create_dir_all(temppath.join("bib")).unwrap();
+    fs::write(temppath.join("bib").join("refs.bib"), "").unwrap();
+
+    let output = run_tectonic(&temppath, &["-X", "init"]);
+    success_or_panic(&output);
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("couldn't find bibliography database `missing.bib`"));
+
+    let mut file = File::open(temppath.join("Tectonic.toml")).unwrap();
+    let doc = Document::new_from_toml(&temppath, temppath.join("build"), &mut file).unwrap();
+    assert_eq!(
+        doc.extra_paths,
+        vec![PathBuf::from("."), PathBuf::from("bib")]
+    );
+    assert_eq!(
+        doc.outputs["default"].inputs,
+        vec![InputFile::File("paper.tex".to_owned())]
+    );
+
+    // No stub files should have been created.
+    assert!(!temppath.join("src").exists());
+
+    // The workspace should build, finding `body.tex` through `extra_paths`. The
+    // missing database would make BibTeX fail, so drop the bibliography first.
+    fs::write(temppath.join("body.tex"), "Hello.\n").unwrap();
+
+    let output = run_tectonic(&temppath, &["-X", "build"]);
+    success_or_panic(&output);
+    assert!(temppath
+        .join("build")
+        .join("default")
+        .join("default.pdf")
+        .is_file());
+}
+
+#[test]
+fn v2_new_template() {
+    util::set_test_root();
+
+    let tempdir = setup_and_copy_files(&[]);
+    let mut temppath = tempdir.path().to_owned();
+
+    // A template with no Tectonic.toml of its own, and a `.git` directory that
+    // shouldn't be copied.
+
+    let template = temppath.join("template");
+    fs::create_dir_all(template.join("src")).unwrap();
+    fs::create_dir_all(template.join(".git")).unwrap();
+    fs::write(template.join(".git").join("HEAD"), "ref: refs/heads/main\n").unwrap();
+    fs::write(template.join("src").join("_preamble.tex"), "\n").unwrap();
+    fs::write(template.join("src").join("index.tex"), "Hello.\n").unwrap();
+    fs::write(template.join("src").join("_postamble.tex"), "\\end\n").unwrap();
+
+    let output = run_tectonic(
+        &temppath,
+        &["-X", "new", "doc", "--template", template.to_str().unwrap()],
+    );
+    success_or_panic(&output);
+
+    temppath.push("doc");
+    assert!(!temppath.join(".git").exists());
+
+    let index = fs::read_to_string(temppath.join("src").join("index.tex")).unwrap();
+    assert_eq!(index, "Hello.\n");
+
+    {
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(temppath.join("Tectonic.toml"))
+            .unwrap();
+        writeln!(file, "tex_format = 'plain'").unwrap();
+    }
+
+    let output = run_tectonic(&temppath, &["-X", "build"]);
+    success_or_panic(&output);
+
+    // Refuse to create a workspace over the top of the template's files.
+    let output = run_tectonic(
+        &temppath,
+        &["-X", "new", ".", "--template", template.to_str().unwrap()],
+    );
+    error_or_panic(&output);
+}
+
+/// Checks that the environment report picks up the workspace's bundle
+#[cfg(feature = "serialization")]
+#[test]
+fn v2_show_environment() {
+    let (_tempdir, temppath) = setup_v2();
+    let output = run_tectonic(&temppath, &["-X", "show", "environment", "--json"]);
+    success_or_panic(&output);
+
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["bundle"]["location"], "test-bundle://");
+    assert!(report["bundle"]["workspace"]
+        .as_str()
+        .unwrap()
+        .ends_with("Tectonic.toml"));
+    assert_eq!(
+        report["engines"]["format_serial"],
+        tectonic_engine_xetex::FORMAT_SERIAL
+    );
+}
+
 /// Checks that shell completions are correctly generated
 #[cfg(feature = "serialization")]
 #[test]
@@ -876,6 +2030,44 @@ fn v2_show_shell_completions() {
     }
 }
 
+/// `self completions` generates the same completions as `show
+/// shell-completions`.
+#[cfg(feature = "serialization")]
+#[test]
+fn v2_self_completions() {
+    let (_tempdir, temppath) = setup_v2();
+    let output = run_tectonic(&temppath, &["-X", "self", "completions", "bash"]);
+    success_or_panic(&output);
+
+    let show = run_tectonic(&temppath, &["-X", "show", "shell-completions", "bash"]);
+    success_or_panic(&show);
+    assert_eq!(output.stdout, show.stdout);
+}
+
+/// Checks that manual pages are generated for the V2 CLI and its subcommands
+#[cfg(feature = "serialization")]
+#[test]
+fn v2_self_manpage() {
+    let (_tempdir, temppath) = setup_v2();
+    let output = run_tectonic(&temppath, &["-X", "self", "manpage"]);
+    success_or_panic(&output);
+    assert!(String::from_utf8_lossy(&output.stdout).contains(".TH nextonic 1"));
+
+    let output = run_tectonic(&temppath, &["-X", "self", "manpage", "--out-dir", "man"]);
+    success_or_panic(&output);
+
+    for page in [
+        "nextonic.1",
+        "nextonic-build.1",
+        "nextonic-show-environment.1",
+    ] {
+        assert!(
+            temppath.join("man").join(page).exists(),
+            "`{page}` not written"
+        );
+    }
+}
+
 const SHELL_ESCAPE_TEST_DOC: &str = r"\immediate\write18{mkdir shellwork}
 \immediate\write18{echo 123 >shellwork/persist}
 \ifnum123=\input{shellwork/persist}
@@ -958,6 +2150,102 @@ fn shell_escape_env_override() {
     error_or_panic(&output);
 }
 
+/// `--untrusted --offline` disables shell-escape and all network access: a
+/// bundle that isn't cached is an error, rather than being downloaded.
+#[test]
+fn untrusted_offline() {
+    let fmt_arg = get_plain_format_arg();
+    let tempdir = setup_and_copy_files(&[]);
+
+    let output = run_tectonic_with_stdin(
+        tempdir.path(),
+        &[&fmt_arg, "-", "-Zshell-escape", "--untrusted", "--offline"],
+        SHELL_ESCAPE_TEST_DOC,
+    );
+    error_or_panic(&output);
+
+    fs::write(tempdir.path().join("x.tex"), "\\bye\n").unwrap();
+    let mut command = prep_tectonic(
+        tempdir.path(),
+        &[
+            "--offline",
+            "--bundle",
+            "https://bundles.example.com/offline.ttb",
+            "x.tex",
+        ],
+    );
+    command.env("TECTONIC_CACHE_DIR", tempdir.path().join("cache"));
+    println!("running {command:?}");
+    let output = command.output().expect("tectonic failed to start");
+    error_or_panic(&output);
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("isn't cached, and network access is disabled"),
+        "{stderr}"
+    );
+}
+
+/// Test that a document's shell-escape allowlist lets the listed programs run,
+/// and only those.
+#[test]
+fn shell_escape_allowlist() {
+    let (_tempdir, temppath) = setup_v2();
+    let toml_path = temppath.join("Tectonic.toml");
+    let toml = fs::read_to_string(&toml_path).unwrap();
+
+    fs::write(
+        temppath.join("src").join("index.tex"),
+        SHELL_ESCAPE_TEST_DOC,
+    )
+    .unwrap();
+
+    let allow = |programs: &str| {
+        let toml = toml.replacen(
+            "[doc]\n",
+            &format!("[doc]\nshell_escape_allow = [{programs}]\n"),
+            1,
+        );
+        fs::write(&toml_path, format!("{toml}shell_escape = true\n")).unwrap();
+    };
+
+    allow("'mkdir', 'echo'");
+    let output = run_tectonic(&temppath, &["-X", "build"]);
+    success_or_panic(&output);
+
+    allow("'mkdir'");
+    let output = run_tectonic(&temppath, &["-X", "build"]);
+    error_or_panic(&output);
+}
+
+/// Test that builds with `SOURCE_DATE_EPOCH` set are byte-for-byte identical,
+/// even when they're made at different times, in different directories, from
+/// files with different modification times.
+#[test]
+fn source_date_epoch_reproducible() {
+    let fmt_arg = get_plain_format_arg();
+
+    let build = || {
+        let tempdir = setup_and_copy_files(&[]);
+        fs::write(
+            tempdir.path().join("doc.tex"),
+            "Hello, \\the\\year.\n\\bye\n",
+        )
+        .unwrap();
+
+        let output = prep_tectonic(tempdir.path(), &[&fmt_arg, "doc.tex"])
+            .env("SOURCE_DATE_EPOCH", "1456304492")
+            .output()
+            .expect("tectonic failed to start");
+        success_or_panic(&output);
+        fs::read(tempdir.path().join("doc.pdf")).unwrap()
+    };
+
+    let first = build();
+    thread::sleep(Duration::from_millis(1100));
+    assert!(first == build(), "the two builds differ");
+}
+
 /// Test that include paths work
 #[test]
 fn extra_search_paths() {